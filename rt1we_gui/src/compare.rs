@@ -0,0 +1,160 @@
+//! A/B comparison: pin a render as "A", and compare the following renders ("B") with it.
+//!
+//! A can also follow the renders, to compare each render with the previous one while
+//! changing the samples, the depth or the materials.
+use eframe::egui;
+use rt1we_renderer::compare::{heatmap, psnr};
+use rt1we_renderer::image::ImageRGBA;
+
+/// How the pinned and current images are compared.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompareMode {
+    /// A left of a vertical split, B right of it.
+    Wipe,
+    /// A heatmap of the differences.
+    Difference,
+}
+
+/// What the viewer should show.
+pub enum ComparisonView<'a> {
+    /// The current image only.
+    Current,
+    /// The pinned image left of `split`, the current image right of it.
+    Wipe { pinned: &'a ImageRGBA, split: f32 },
+    /// An image replacing the current one.
+    Replaced(ImageRGBA),
+}
+
+/// The pinned image and the comparison settings.
+pub struct Comparison {
+    pinned: Option<ImageRGBA>,
+    /// Describes how the pinned image was rendered.
+    label: String,
+    enabled: bool,
+    /// Pin each finished render when the next one finishes.
+    follow: bool,
+    /// The latest finished render, and its description.
+    latest: Option<(ImageRGBA, String)>,
+    mode: CompareMode,
+    /// Position of the wipe split, as a fraction of the image width.
+    split: f32,
+    /// Difference between the images, when they have the same resolution.
+    psnr: Option<f64>,
+    max_error: Option<u8>,
+}
+
+impl Default for Comparison {
+    fn default() -> Self {
+        Comparison {
+            pinned: None,
+            label: String::new(),
+            enabled: false,
+            follow: false,
+            latest: None,
+            mode: CompareMode::Wipe,
+            split: 0.5,
+            psnr: None,
+            max_error: None,
+        }
+    }
+}
+
+impl Comparison {
+    /// Show the comparison controls.
+    ///
+    /// # Arguments
+    /// - `current` - The displayed image, with a description of how it was rendered.
+    ///
+    /// # Returns
+    /// Whether the viewer should be updated.
+    pub fn show(&mut self, ui: &mut egui::Ui, current: Option<(&ImageRGBA, String)>) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            if let Some((image, label)) = current {
+                if ui
+                    .button("Pin as A")
+                    .on_hover_text("Compare the next renders with this one")
+                    .clicked()
+                {
+                    self.pinned = Some(image.clone());
+                    self.label = label;
+                    self.enabled = true;
+                    changed = true;
+                }
+            }
+            if self.pinned.is_some() && ui.button("Unpin").clicked() {
+                self.pinned = None;
+                self.enabled = false;
+                self.follow = false;
+                changed = true;
+            }
+        });
+        let follow = ui
+            .checkbox(&mut self.follow, "Compare with the previous render")
+            .on_hover_text("A is the render before the latest one, pinned as each render finishes");
+        if follow.changed() && self.follow {
+            self.enabled = true;
+            changed = true;
+        }
+        if self.pinned.is_none() {
+            return changed;
+        }
+
+        ui.label(format!("A: {}", self.label));
+        changed |= ui.checkbox(&mut self.enabled, "Compare with A").changed();
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.horizontal(|ui| {
+                changed |= ui.radio_value(&mut self.mode, CompareMode::Wipe, "Wipe").changed();
+                changed |=
+                    ui.radio_value(&mut self.mode, CompareMode::Difference, "Difference").changed();
+            });
+            if self.mode == CompareMode::Wipe {
+                let slider = egui::Slider::new(&mut self.split, 0.0..=1.0).show_value(false);
+                changed |= ui.add(slider).on_hover_text("A on the left, B on the right").changed();
+            }
+            match (self.psnr, self.max_error) {
+                (Some(psnr), Some(max_error)) => {
+                    ui.label(format!("PSNR: {psnr:.2} dB, largest difference: {max_error}"));
+                }
+                _ => {
+                    ui.label("A and B have different resolutions");
+                }
+            }
+        });
+        changed
+    }
+
+    /// Record a finished render, which becomes A when the next one finishes, if A follows the
+    /// renders.
+    ///
+    /// # Arguments
+    /// - `image` - The displayed image of the render.
+    /// - `label` - Describes how it was rendered.
+    pub fn finished(&mut self, image: &ImageRGBA, label: String) {
+        let previous = self.latest.replace((image.clone(), label));
+        if let (true, Some((image, label))) = (self.follow, previous) {
+            self.pinned = Some(image);
+            self.label = label;
+        }
+    }
+
+    /// What to show in the viewer, for the current image.
+    pub fn view(&mut self, current: &ImageRGBA) -> ComparisonView<'_> {
+        let Some(pinned) = &self.pinned else {
+            return ComparisonView::Current;
+        };
+        self.psnr = psnr(pinned, current).ok();
+        let difference = heatmap(pinned, current).ok();
+        self.max_error = difference.as_ref().map(|(_, max_error)| *max_error);
+
+        match (self.enabled, self.mode, difference) {
+            (false, _, _) => ComparisonView::Current,
+            (true, CompareMode::Wipe, _) => ComparisonView::Wipe { pinned, split: self.split },
+            (true, CompareMode::Difference, Some((heatmap, _))) => {
+                ComparisonView::Replaced(heatmap)
+            }
+            // Images of different resolutions have no per-pixel difference.
+            (true, CompareMode::Difference, None) => ComparisonView::Current,
+        }
+    }
+}
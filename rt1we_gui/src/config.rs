@@ -0,0 +1,80 @@
+//! Settings kept between sessions.
+//!
+//! They are saved by eframe on exit, along with the window size and position, and restored
+//! on startup.
+use rt1we_renderer::geometry::Point;
+use rt1we_renderer::view::OrbitView;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::settings::RenderSettings;
+
+/// Camera position, see [`OrbitView`].
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct CameraConfig {
+    pub target: [f32; 3],
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl From<OrbitView> for CameraConfig {
+    fn from(view: OrbitView) -> Self {
+        let t = view.target;
+        CameraConfig {
+            target: [t.x, t.y, t.z],
+            distance: view.distance,
+            yaw: view.yaw,
+            pitch: view.pitch,
+        }
+    }
+}
+
+impl From<CameraConfig> for OrbitView {
+    /// The camera, with the distance and pitch brought back in their range by a no-op move.
+    fn from(camera: CameraConfig) -> Self {
+        let [x, y, z] = camera.target;
+        let mut view = OrbitView {
+            target: Point::new(x, y, z),
+            distance: camera.distance,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+        };
+        view.orbit(0.0, 0.0);
+        view.dolly(1.0);
+        view
+    }
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        OrbitView::default().into()
+    }
+}
+
+/// Everything saved between sessions. Missing entries take their default value, so that
+/// files written by older versions still load.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub settings: RenderSettings,
+    pub camera: CameraConfig,
+    /// Directory of the last saved image.
+    pub output_dir: Option<PathBuf>,
+    /// Scene files opened recently, the latest first.
+    pub recent_scenes: Vec<PathBuf>,
+}
+
+impl Config {
+    /// The settings of the previous session, or the defaults on first launch.
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        let mut config: Config =
+            storage.and_then(|s| eframe::get_value(s, eframe::APP_KEY)).unwrap_or_default();
+        config.settings.clamp();
+        config
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }
+}
@@ -0,0 +1,87 @@
+//! Pixel inspector: the values of a clicked pixel, and the path of a ray through it.
+use eframe::egui;
+use rt1we_renderer::inspect::{trace_pixel, PixelTrace};
+use rt1we_renderer::render::Scene;
+use rt1we_renderer::view::OrbitView;
+
+use crate::postprocess::Frame;
+
+/// Shows the values of the selected pixel in a window.
+#[derive(Default)]
+pub struct PixelInspector {
+    /// Selected pixel, from the bottom left as in rendered images.
+    pixel: Option<(usize, usize)>,
+    /// Resolution of the image the pixel was selected in.
+    resolution: (usize, usize),
+    trace: Option<PixelTrace>,
+    /// Show the bounces of the traced ray.
+    verbose: bool,
+}
+
+impl PixelInspector {
+    /// Select a pixel and trace the ray through its center.
+    ///
+    /// # Arguments
+    /// - `x`, `y` - Pixel coordinates, from the top left of the displayed image.
+    pub fn select(
+        &mut self, x: usize, y: usize, frame: &Frame, scene: &Scene, view: &OrbitView,
+        max_depth: usize,
+    ) {
+        let (width, height) = (frame.image.width, frame.image.height);
+        let (i, j) = (x, height - 1 - y);
+        self.pixel = Some((i, j));
+        self.resolution = (width, height);
+        self.trace = Some(trace_pixel(scene, view, width, height, i, j, max_depth));
+    }
+
+    /// Show the inspector window, when a pixel is selected.
+    pub fn show(
+        &mut self, ctx: &egui::Context, frame: Option<&Frame>, scene: &Scene, view: &OrbitView,
+        max_depth: usize,
+    ) {
+        let Some((i, j)) = self.pixel else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Pixel inspector").open(&mut open).show(ctx, |ui| {
+            ui.label(format!("Pixel ({i}, {j}) of {}x{}", self.resolution.0, self.resolution.1));
+
+            // The displayed image may have been replaced by one of another resolution.
+            let frame = frame.filter(|f| (f.image.width, f.image.height) == self.resolution);
+            if let Some(frame) = frame {
+                let (r, g, b) = frame.hdr.at(i, j);
+                ui.label(format!("Linear RGB : ({r:.4}, {g:.4}, {b:.4})"));
+                let (r, g, b, _) = frame.image.at(i, j);
+                ui.label(format!("Displayed  : ({r}, {g}, {b})"));
+            }
+            if let Some(trace) = &self.trace {
+                match (trace.depth(), trace.object_id()) {
+                    (Some(depth), Some(id)) => {
+                        ui.label(format!("Depth      : {depth:.4}"));
+                        ui.label(format!("Object     : #{id}"));
+                    }
+                    _ => {
+                        ui.label("Depth      : sky");
+                    }
+                }
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Trace again").clicked() {
+                    let (width, height) = self.resolution;
+                    self.trace = Some(trace_pixel(scene, view, width, height, i, j, max_depth));
+                }
+                ui.checkbox(&mut self.verbose, "Show bounces");
+            });
+            if let Some(trace) = self.trace.as_ref().filter(|_| self.verbose) {
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    ui.monospace(trace.to_string());
+                });
+            }
+        });
+        if !open {
+            self.pixel = None;
+            self.trace = None;
+        }
+    }
+}
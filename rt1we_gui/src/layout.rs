@@ -0,0 +1,277 @@
+//! Layout view: the outlines of the objects, drawn instead of rendered, so that laying out a
+//! scene follows every edit.
+//!
+//! Clicking an object selects it. The selected object has a gizmo: drag its arrows to move
+//! the object along the axes, and its square to scale it.
+use eframe::egui;
+use egui::{Color32, PointerButton, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+use rt1we_renderer::geometry::{Color, Vec3};
+use rt1we_renderer::raster::Projection;
+use rt1we_renderer::render::{MaterialParams, Scene, Sphere};
+use rt1we_renderer::view::OrbitView;
+
+use crate::viewer::{navigation, Navigation};
+
+/// Number of segments of the sphere outlines.
+const SEGMENTS: usize = 64;
+/// Fraction of the area taken by the image frame, the rest shows what lies beyond it.
+const FRAME_FILL: f32 = 0.8;
+const SKY: Color32 = Color32::from_rgb(128, 179, 255);
+/// Length of the gizmo arrows, relative to the depth of the object center.
+const ARROW_LENGTH: f32 = 0.15;
+/// Distance from a handle within which a drag grabs it, in points.
+const HANDLE_RADIUS: f32 = 8.0;
+/// Objects are not scaled down below this radius.
+const MIN_RADIUS: f32 = 0.01;
+const AXIS_COLORS: [Color32; 3] = [Color32::RED, Color32::GREEN, Color32::BLUE];
+
+/// Part of the gizmo of the selected object.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Handle {
+    /// Moves the object along the x, y or z axis.
+    Axis(usize),
+    Scale,
+}
+
+/// The gizmo of the selected object, on screen.
+struct Gizmo {
+    center: Pos2,
+    /// Length of the arrows, in scene units.
+    length: f32,
+    handles: Vec<(Handle, Pos2)>,
+}
+
+/// What happened in the layout view during a frame.
+pub struct LayoutResponse {
+    /// The camera moves, when there were any.
+    pub navigation: Option<Navigation>,
+    /// Whether a gizmo moved or scaled an object.
+    pub changed: bool,
+}
+
+/// Draws the scene, and edits it with gizmos.
+#[derive(Default)]
+pub struct LayoutView {
+    /// The handle being dragged.
+    dragged: Option<Handle>,
+}
+
+fn axis(k: usize) -> Vec3 {
+    let mut v = Vec3::new(0.0, 0.0, 0.0);
+    match k {
+        0 => v.x = 1.0,
+        1 => v.y = 1.0,
+        _ => v.z = 1.0,
+    }
+    v
+}
+
+/// Color of the objects made of a material.
+fn material_color(material: Option<MaterialParams>) -> Color32 {
+    let rgb = |c: Color| {
+        let channel = |v: f32| (v.clamp(0.0, 1.0).sqrt() * 255.0) as u8;
+        Color32::from_rgb(channel(c.x), channel(c.y), channel(c.z))
+    };
+    match material {
+        Some(MaterialParams::Lambertian { albedo }) => rgb(albedo.average()),
+        Some(MaterialParams::Metal { albedo, .. }) => rgb(albedo),
+        Some(MaterialParams::Microfacet { diffuse, f0, .. }) => rgb(diffuse + f0),
+        Some(MaterialParams::Principled(params)) => rgb(params.base_color),
+        Some(MaterialParams::Subsurface { albedo, .. }) => rgb(albedo),
+        Some(MaterialParams::Dielectric { .. }) => Color32::from_white_alpha(60),
+        Some(MaterialParams::DiffuseLight { color, .. }) => rgb(color),
+        Some(MaterialParams::Isotropic { albedo }) => rgb(albedo.average()).gamma_multiply(0.5),
+        None => Color32::GRAY,
+    }
+}
+
+/// The gizmo of a sphere, `None` when its center is behind the camera.
+///
+/// # Arguments
+/// - `to_screen` - Converts image coordinates to screen positions.
+fn gizmo(
+    projection: &Projection, sphere: &Sphere, to_screen: impl Fn((f32, f32)) -> Pos2,
+) -> Option<Gizmo> {
+    let (x, y, depth) = projection.project(&sphere.center)?;
+    let length = ARROW_LENGTH * depth;
+    let mut handles: Vec<(Handle, Pos2)> = (0..3)
+        .filter_map(|k| {
+            let (x, y, _) = projection.project(&(sphere.center + length * axis(k)))?;
+            Some((Handle::Axis(k), to_screen((x, y))))
+        })
+        .collect();
+    // The scale handle sits on the right of the outline.
+    let outline = projection.sphere_outline(sphere, SEGMENTS).unwrap_or_default();
+    if let Some(right) = outline.into_iter().map(&to_screen).max_by(|a, b| a.x.total_cmp(&b.x)) {
+        handles.push((Handle::Scale, right));
+    }
+    Some(Gizmo { center: to_screen((x, y)), length, handles })
+}
+
+impl Gizmo {
+    /// The handle within reach of a screen position, the closest one first.
+    fn handle_at(&self, pos: Pos2) -> Option<Handle> {
+        self.handles
+            .iter()
+            .filter(|(_, p)| p.distance(pos) <= HANDLE_RADIUS)
+            .min_by(|a, b| a.1.distance(pos).total_cmp(&b.1.distance(pos)))
+            .map(|(handle, _)| *handle)
+    }
+
+    /// Move or scale a sphere, following a drag of one of the handles.
+    ///
+    /// # Arguments
+    /// - `pos` - Position of the pointer, after the move.
+    /// - `delta` - Pointer move since the previous frame.
+    fn drag(&self, sphere: &mut Sphere, handle: Handle, pos: Pos2, delta: Vec2) {
+        let Some(&(_, tip)) = self.handles.iter().find(|(h, _)| *h == handle) else {
+            return;
+        };
+        match handle {
+            Handle::Axis(k) => {
+                // The part of the move along the arrow, as seen on screen.
+                let arrow = tip - self.center;
+                if arrow.length_sq() > 1.0 {
+                    sphere.center += (self.length * delta.dot(arrow) / arrow.length_sq()) * axis(k);
+                }
+            }
+            Handle::Scale => {
+                let before = (pos - delta).distance(self.center);
+                if before > 1.0 {
+                    let radius = sphere.radius.abs() * pos.distance(self.center) / before;
+                    sphere.radius = radius.max(MIN_RADIUS).copysign(sphere.radius);
+                }
+            }
+        }
+    }
+}
+
+impl LayoutView {
+    /// Draw the scene seen from the camera in all the available space, and handle the clicks
+    /// and the gizmo drags.
+    ///
+    /// # Arguments
+    /// - `view` - The camera.
+    /// - `aspect_ratio` - Width over height of the rendered images, framed in the area.
+    /// - `selected` - Index of the selected object, changed by clicks.
+    pub fn show(
+        &mut self, ui: &mut egui::Ui, scene: &mut Scene, view: &OrbitView, aspect_ratio: f32,
+        selected: &mut Option<usize>,
+    ) -> LayoutResponse {
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+        let size = egui::vec2(rect.height() * aspect_ratio, rect.height())
+            .min(egui::vec2(rect.width(), rect.width() / aspect_ratio))
+            * FRAME_FILL;
+        let frame = Rect::from_center_size(rect.center(), size);
+        // Image coordinates have their origin at the bottom left of the frame.
+        let to_screen = |(x, y): (f32, f32)| {
+            Pos2::new(frame.left() + x * frame.width(), frame.bottom() - y * frame.height())
+        };
+        let projection = Projection::new(view, scene.lens(), aspect_ratio);
+        *selected = selected.filter(|&i| i < scene.world().len());
+
+        let mut changed = false;
+        if let Some(index) = *selected {
+            // Only spheres have gizmos.
+            let sphere = scene.world_mut().objects_mut()[index].as_sphere_mut();
+            if let Some((sphere, gizmo)) =
+                sphere.and_then(|s| gizmo(&projection, s, to_screen).map(|g| (s, g)))
+            {
+                if response.drag_started_by(PointerButton::Primary) {
+                    let origin = ui.input(|i| i.pointer.press_origin());
+                    self.dragged = origin.and_then(|pos| gizmo.handle_at(pos));
+                }
+                let pos = response.interact_pointer_pos();
+                if let (Some(handle), Some(pos)) = (self.dragged, pos) {
+                    if response.dragged_by(PointerButton::Primary) {
+                        gizmo.drag(sphere, handle, pos, response.drag_delta());
+                        changed = response.drag_delta() != Vec2::ZERO;
+                    }
+                }
+            }
+        }
+        if !response.dragged() {
+            self.dragged = None;
+        }
+
+        if let Some(pos) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+            let x = (pos.x - frame.left()) / frame.width();
+            let y = (frame.bottom() - pos.y) / frame.height();
+            // The closest objects are drawn last.
+            *selected = projection
+                .silhouettes(scene.world(), SEGMENTS)
+                .into_iter()
+                .rev()
+                .find(|s| s.contains(x, y))
+                .map(|s| s.object_id);
+        }
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, SKY);
+        for silhouette in projection.silhouettes(scene.world(), SEGMENTS) {
+            let object = &scene.world().objects()[silhouette.object_id];
+            let mut outline: Vec<Pos2> = silhouette.outline.into_iter().map(to_screen).collect();
+            // egui fills clockwise polygons.
+            let area: f32 = outline
+                .iter()
+                .zip(outline.iter().cycle().skip(1))
+                .map(|(a, b)| a.x * b.y - b.x * a.y)
+                .sum();
+            if area < 0.0 {
+                outline.reverse();
+            }
+            let stroke = if *selected == Some(silhouette.object_id) {
+                Stroke::new(2.0, Color32::YELLOW)
+            } else {
+                Stroke::new(1.0, Color32::from_black_alpha(160))
+            };
+            let fill = material_color(scene.material(object.material_id()));
+            painter.add(Shape::convex_polygon(outline, fill, stroke));
+        }
+        // What lies outside of the frame is dimmed.
+        let dim = Color32::from_black_alpha(120);
+        for outside in [
+            Rect::from_min_max(rect.min, Pos2::new(rect.right(), frame.top())),
+            Rect::from_min_max(Pos2::new(rect.left(), frame.bottom()), rect.max),
+            Rect::from_min_max(Pos2::new(rect.left(), frame.top()), frame.left_bottom()),
+            Rect::from_min_max(frame.right_top(), Pos2::new(rect.right(), frame.bottom())),
+        ] {
+            painter.rect_filled(outside, 0.0, dim);
+        }
+        painter.rect_stroke(frame, 0.0, Stroke::new(1.0, Color32::WHITE));
+
+        let sphere = selected.and_then(|index| scene.world().objects()[index].as_sphere().copied());
+        if let Some(gizmo) = sphere.and_then(|s| gizmo(&projection, &s, to_screen)) {
+            self.paint_gizmo(&painter, &gizmo);
+        }
+
+        let mut navigation = navigation(ui, &response);
+        if self.dragged.is_some() {
+            // The drag belongs to the gizmo, the camera only pans and dollies.
+            navigation = navigation
+                .map(|n| Navigation { orbit: Vec2::ZERO, ..n })
+                .filter(|n| n.pan != Vec2::ZERO || n.dolly != 0.0);
+        }
+        LayoutResponse { navigation, changed }
+    }
+
+    fn paint_gizmo(&self, painter: &egui::Painter, gizmo: &Gizmo) {
+        for &(handle, pos) in &gizmo.handles {
+            let color = match handle {
+                _ if self.dragged == Some(handle) => Color32::YELLOW,
+                Handle::Axis(k) => AXIS_COLORS[k],
+                Handle::Scale => Color32::WHITE,
+            };
+            match handle {
+                Handle::Axis(_) => {
+                    painter.line_segment([gizmo.center, pos], Stroke::new(2.0, color));
+                    painter.circle_filled(pos, HANDLE_RADIUS / 2.0, color);
+                }
+                Handle::Scale => {
+                    let square = Rect::from_center_size(pos, Vec2::splat(HANDLE_RADIUS));
+                    painter.rect_filled(square, 0.0, color);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Lens editing: projection, field of view, aperture and focus distance of the camera.
+use eframe::egui;
+use rt1we_renderer::render::Scene;
+use rt1we_renderer::view::CameraProjection;
+
+/// Show the lens of the camera of `scene`, with its editable parameters.
+///
+/// # Arguments
+/// - `target_distance` - Distance from the camera to its target, where the lens focuses by
+///   default.
+///
+/// # Returns
+/// Whether the lens changed.
+pub fn show(ui: &mut egui::Ui, scene: &mut Scene, target_distance: f32) -> bool {
+    let mut lens = *scene.lens();
+    let mut changed = false;
+
+    egui::ComboBox::from_label("Projection").selected_text(lens.projection.name()).show_ui(
+        ui,
+        |ui| {
+            for projection in CameraProjection::ALL {
+                let option =
+                    ui.selectable_value(&mut lens.projection, projection, projection.name());
+                changed |= option.changed();
+            }
+        },
+    );
+
+    let slider = egui::Slider::new(&mut lens.vfov, 10.0..=150.0).suffix("°").text("Field of view");
+    changed |= ui.add(slider).on_hover_text("Vertical field of view").changed();
+    let slider = egui::Slider::new(&mut lens.aperture, 0.0..=1.0).text("Aperture");
+    changed |= ui.add(slider).on_hover_text("Lens diameter, 0 keeps everything in focus").changed();
+
+    let mut on_target = lens.focus_distance.is_none();
+    if ui.checkbox(&mut on_target, "Focus on the target").changed() {
+        lens.focus_distance = if on_target { None } else { Some(target_distance) };
+        changed = true;
+    }
+    if let Some(distance) = &mut lens.focus_distance {
+        let slider =
+            egui::Slider::new(distance, 0.05..=100.0).logarithmic(true).text("Focus distance");
+        changed |= ui.add(slider).changed();
+    }
+
+    if changed {
+        scene.set_lens(&lens);
+    }
+    changed
+}
@@ -1,9 +1,54 @@
 extern crate rt1we_renderer;
+mod compare;
+mod config;
+mod inspector;
+mod layout;
+mod lens_editor;
+mod material_editor;
+mod postprocess;
+mod queue;
+mod scene_editor;
+mod settings;
+mod timeline;
+mod viewer;
+mod worker;
 
 use eframe::egui;
-use rt1we_renderer::render::render;
-use std::thread;
-use std::time::Duration;
+use rt1we_renderer::image::flipv;
+use rt1we_renderer::imageio::{imwrite, imwrite_hdr};
+use rt1we_renderer::render::Scene;
+use rt1we_renderer::scene_io::load_scene;
+use rt1we_renderer::scenes::ScenePreset;
+use rt1we_renderer::tonemap::ToneMapSettings;
+use rt1we_renderer::view::OrbitView;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::compare::{Comparison, ComparisonView};
+use crate::config::Config;
+use crate::inspector::PixelInspector;
+use crate::layout::LayoutView;
+use crate::postprocess::{Frame, PostSettings};
+use crate::queue::RenderQueue;
+use crate::scene_editor::SceneEditor;
+use crate::settings::RenderSettings;
+use crate::timeline::{Timeline, TimelineAction};
+use crate::viewer::{ImageViewer, Navigation, ViewerResponse};
+use crate::worker::{JobUpdate, RenderJob, RenderRequest};
+
+/// Camera rotation per dragged point, in degrees.
+const ORBIT_SPEED: f32 = 0.4;
+/// Camera move per dragged point, relative to the distance to the target.
+const PAN_SPEED: f32 = 0.005;
+/// Dolly factor exponent per scrolled point.
+const DOLLY_SPEED: f32 = 0.002;
+/// Quality of the renders while the camera moves or the scene is edited.
+const INTERACTIVE_SAMPLES: usize = 4;
+const INTERACTIVE_MAX_DEPTH: usize = 4;
+/// Number of scene files kept in the recent scenes menu.
+const MAX_RECENT_SCENES: usize = 8;
+/// Time without changes after which the full quality render starts.
+const SETTLE_DELAY: Duration = Duration::from_millis(300);
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
@@ -12,52 +57,557 @@ fn main() -> Result<(), eframe::Error> {
         ..Default::default()
     };
     eframe::run_native(
-        "My egui App",
+        "rt1we-gui",
         options,
         Box::new(|cc| {
             // This gives us image support:
             egui_extras::install_image_loaders(&cc.egui_ctx);
 
-            Box::<MyApp>::default()
+            Box::new(MyApp::new(cc))
         }),
     )
 }
 
 struct MyApp {
-    width: u32,
-    height: u32,
-    max_depth: u32,
-    samples_per_pixel: u32,
+    settings: RenderSettings,
+    tonemap: ToneMapSettings,
+    scene: Scene,
+    scene_editor: SceneEditor,
+    /// Scene files opened recently, the latest first.
+    recent_scenes: Vec<PathBuf>,
+    /// The scene file that failed to load, and why.
+    scene_errors: Option<(PathBuf, String)>,
+    view: OrbitView,
+    /// Time of the last camera move or scene edit, while the low quality render is shown.
+    last_change: Option<Instant>,
+    job: Option<RenderJob>,
+    queue: RenderQueue,
+    timeline: Timeline,
+    /// The latest image.
+    frame: Option<Frame>,
+    /// Samples per pixel of the image, when its render was cancelled.
+    cancelled_at: Option<usize>,
+    viewer: ImageViewer,
+    inspector: PixelInspector,
+    comparison: Comparison,
+    /// Save the linear values rather than the displayed image.
+    save_hdr: bool,
+    /// Show the outlines of the objects instead of rendering them.
+    layout_view: bool,
+    layout: LayoutView,
+    /// Directory of the last saved image.
+    output_dir: Option<PathBuf>,
+    error: Option<String>,
 }
 
 impl Default for MyApp {
     fn default() -> Self {
-        Self { width: 160, height: 120, max_depth: 50, samples_per_pixel: 100 }
+        Self {
+            settings: RenderSettings::default(),
+            tonemap: ToneMapSettings::default(),
+            scene: Scene::demo(),
+            scene_editor: SceneEditor::default(),
+            recent_scenes: Vec::new(),
+            scene_errors: None,
+            view: OrbitView::default(),
+            last_change: None,
+            job: None,
+            queue: RenderQueue::default(),
+            timeline: Timeline::default(),
+            frame: None,
+            cancelled_at: None,
+            viewer: ImageViewer::default(),
+            inspector: PixelInspector::default(),
+            comparison: Comparison::default(),
+            save_hdr: false,
+            layout_view: false,
+            layout: LayoutView::default(),
+            output_dir: None,
+            error: None,
+        }
+    }
+}
+
+impl MyApp {
+    /// The app with the settings of the previous session.
+    fn new(cc: &eframe::CreationContext) -> Self {
+        let config = Config::load(cc.storage);
+        MyApp {
+            settings: config.settings,
+            view: config.camera.into(),
+            output_dir: config.output_dir,
+            recent_scenes: config.recent_scenes,
+            ..Default::default()
+        }
+    }
+
+    fn render_request(&self) -> RenderRequest {
+        let settings = &self.settings;
+        RenderRequest {
+            scene: self.scene.clone(),
+            width: settings.width as usize,
+            height: settings.height as usize,
+            max_depth: settings.max_depth as usize,
+            samples_per_pixel: settings.samples_per_pixel as usize,
+            view: self.view,
+            threads: settings.threads as usize,
+            integrator: settings.integrator,
+        }
+    }
+
+    fn post(&self) -> PostSettings {
+        PostSettings { tonemap: self.tonemap, denoise: self.settings.denoise }
+    }
+
+    fn start_render(&mut self, ctx: &egui::Context) {
+        self.last_change = None;
+        self.spawn(self.render_request(), ctx);
+    }
+
+    /// Replace the running render, if any.
+    fn spawn(&mut self, request: RenderRequest, ctx: &egui::Context) {
+        self.error = None;
+        self.cancelled_at = None;
+        self.job = Some(RenderJob::spawn(request, ctx.clone()));
+    }
+
+    /// Move the camera, and restart the render at low quality.
+    fn navigate(&mut self, navigation: Navigation, ctx: &egui::Context) {
+        self.view.orbit(-navigation.orbit.x * ORBIT_SPEED, navigation.orbit.y * ORBIT_SPEED);
+        self.view.pan(-navigation.pan.x * PAN_SPEED, navigation.pan.y * PAN_SPEED);
+        self.view.dolly((-navigation.dolly * DOLLY_SPEED).exp());
+        self.start_preview(ctx);
+    }
+
+    /// Restart the render at low quality, the full quality render follows once the changes
+    /// stop.
+    fn start_preview(&mut self, ctx: &egui::Context) {
+        // The layout view follows the changes without rendering.
+        if self.layout_view {
+            return;
+        }
+        let settings = &self.settings;
+        let (width, height) = settings.preview_scale.apply(settings.width, settings.height);
+        let request = RenderRequest {
+            width: width as usize,
+            height: height as usize,
+            samples_per_pixel: INTERACTIVE_SAMPLES,
+            max_depth: INTERACTIVE_MAX_DEPTH.min(settings.max_depth as usize),
+            ..self.render_request()
+        };
+        self.spawn(request, ctx);
+        self.last_change = Some(Instant::now());
+    }
+
+    /// Start the full quality render once the changes stopped.
+    fn settle(&mut self, ctx: &egui::Context) {
+        let Some(last) = self.last_change else {
+            return;
+        };
+        let elapsed = last.elapsed();
+        if elapsed >= SETTLE_DELAY {
+            self.last_change = None;
+            self.start_render(ctx);
+        } else {
+            ctx.request_repaint_after(SETTLE_DELAY - elapsed);
+        }
+    }
+
+    /// Ask where to save the latest image, and write it.
+    fn save_image(&mut self) {
+        let Some(frame) = &self.frame else {
+            return;
+        };
+        let mut dialog = rfd::FileDialog::new();
+        if let Some(dir) = &self.output_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog
+            .add_filter("PNG", &["png"])
+            .add_filter("PPM", &["ppm"])
+            .add_filter("OpenEXR", &["exr"])
+            .set_file_name(frame.file_name(self.save_hdr))
+            .save_file()
+        else {
+            return;
+        };
+
+        let fpath = path.to_string_lossy();
+        // Rendered images have their origin at the bottom left, files at the top left.
+        let result = if self.save_hdr {
+            imwrite_hdr(&fpath, &frame.hdr.flipv())
+        } else {
+            imwrite(&fpath, &flipv(&frame.image))
+        };
+        self.error = result.err().map(|e| e.to_string());
+        self.output_dir = path.parent().map(|p| p.to_path_buf());
+    }
+
+    fn show_frame(&mut self, ctx: &egui::Context, frame: Frame) {
+        self.frame = Some(frame);
+        self.refresh_view(ctx);
+    }
+
+    /// Show the latest image in the viewer, compared with the pinned one when requested.
+    fn refresh_view(&mut self, ctx: &egui::Context) {
+        let Some(frame) = &self.frame else {
+            return;
+        };
+        match self.comparison.view(&frame.image) {
+            ComparisonView::Current => {
+                self.viewer.set_image(ctx, &frame.image);
+                self.viewer.set_wipe(ctx, None);
+            }
+            ComparisonView::Wipe { pinned, split } => {
+                self.viewer.set_image(ctx, &frame.image);
+                self.viewer.set_wipe(ctx, Some((pinned, split)));
+            }
+            ComparisonView::Replaced(image) => {
+                self.viewer.set_image(ctx, &image);
+                self.viewer.set_wipe(ctx, None);
+            }
+        }
+    }
+
+    /// Replace the scene by the one in a scene file, and preview it.
+    fn open_scene(&mut self, path: PathBuf, ctx: &egui::Context) {
+        match load_scene(&path.to_string_lossy()) {
+            Ok(scene) => {
+                self.recent_scenes.retain(|p| *p != path);
+                self.recent_scenes.insert(0, path);
+                self.recent_scenes.truncate(MAX_RECENT_SCENES);
+                self.set_scene(scene, ctx);
+            }
+            Err(e) => self.scene_errors = Some((path, e.to_string())),
+        }
+    }
+
+    /// Replace the scene, seen from its own camera, and preview it.
+    fn set_scene(&mut self, scene: Scene, ctx: &egui::Context) {
+        self.view = *scene.view();
+        self.scene = scene;
+        self.scene_editor = SceneEditor::default();
+        self.scene_errors = None;
+        self.start_preview(ctx);
+    }
+
+    /// Show why the last scene file failed to load.
+    fn show_scene_errors(&mut self, ctx: &egui::Context) {
+        let Some((path, errors)) = &self.scene_errors else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Scene errors").open(&mut open).show(ctx, |ui| {
+            ui.label(format!("{} could not be loaded:", path.display()));
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                for line in errors.lines() {
+                    ui.colored_label(egui::Color32::RED, line);
+                }
+            });
+        });
+        if !open {
+            self.scene_errors = None;
+        }
+    }
+
+    /// Ask where to write the frames of the animation, and start rendering them.
+    fn render_sequence(&mut self, ctx: &egui::Context) {
+        let mut dialog = rfd::FileDialog::new();
+        if let Some(dir) = &self.output_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(dir) = dialog.pick_folder() {
+            self.timeline.start_sequence(self.render_request(), &dir, ctx);
+            self.output_dir = Some(dir);
+        }
+    }
+
+    /// Collect the image of the running render, if it is done or cancelled.
+    fn poll_render(&mut self, ctx: &egui::Context) {
+        let post = self.post();
+        let Some(job) = self.job.as_mut() else {
+            return;
+        };
+        match job.poll() {
+            Ok(JobUpdate::Pending) => {}
+            Ok(JobUpdate::Refined(hdr)) => {
+                let frame = Frame::new(hdr, job.samples(), job.max_depth(), false, &post);
+                self.show_frame(ctx, frame);
+            }
+            Ok(JobUpdate::Done(hdr)) => {
+                let frame = Frame::new(hdr, job.samples(), job.max_depth(), true, &post);
+                // Previews are not worth comparing.
+                if self.last_change.is_none() {
+                    self.comparison.finished(&frame.image, frame.describe());
+                }
+                self.show_frame(ctx, frame);
+                self.job = None;
+            }
+            Ok(JobUpdate::Cancelled(hdr)) => {
+                self.cancelled_at = Some(job.samples());
+                let frame = Frame::new(hdr, job.samples(), job.max_depth(), false, &post);
+                self.show_frame(ctx, frame);
+                self.job = None;
+            }
+            Err(msg) => {
+                self.error = Some(msg);
+                self.job = None;
+            }
+        }
     }
 }
 
 impl eframe::App for MyApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        let config = Config {
+            settings: self.settings.clone(),
+            camera: self.view.into(),
+            output_dir: self.output_dir.clone(),
+            recent_scenes: self.recent_scenes.clone(),
+        };
+        config.save(storage);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
+        self.poll_render(ctx);
+
+        let dropped = ctx.input(|i| i.raw.dropped_files.iter().find_map(|f| f.path.clone()));
+        if let Some(path) = dropped {
+            self.open_scene(path, ctx);
+        }
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop"));
+            let screen = ctx.screen_rect();
+            let painter = ctx.layer_painter(layer);
+            painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(160));
+            painter.text(
+                screen.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop the scene file to open it",
+                egui::FontId::proportional(24.0),
+                egui::Color32::WHITE,
+            );
+        }
+        self.show_scene_errors(ctx);
+        let post = self.post();
+        self.queue.poll(ctx, &post);
+        self.timeline.poll(ctx, &post);
+        if let Some(view) = self.timeline.play(ctx) {
+            self.view = view;
+            self.start_preview(ctx);
+        }
+
+        let action = egui::TopBottomPanel::bottom("timeline")
+            .show(ctx, |ui| self.timeline.show(ui, &self.view))
+            .inner;
+        match action {
+            TimelineAction::None => {}
+            TimelineAction::Seek(view) => {
+                self.view = view;
+                self.start_preview(ctx);
+            }
+            TimelineAction::RenderSequence => self.render_sequence(ctx),
+        }
+        if !self.queue.is_empty() {
+            let shown = egui::TopBottomPanel::bottom("queue")
+                .show(ctx, |ui| {
+                    ui.heading("Render queue");
+                    self.queue.show(ui).cloned()
+                })
+                .inner;
+            if let Some(mut frame) = shown {
+                frame.update(&post);
+                self.cancelled_at = None;
+                self.show_frame(ctx, frame);
+            }
+        }
+
+        let scene_changed = egui::SidePanel::right("scene")
+            .show(ctx, |ui| {
+                ui.heading("Scene");
+                let mut reopened = None;
+                ui.add_enabled_ui(!self.recent_scenes.is_empty(), |ui| {
+                    ui.menu_button("Open recent", |ui| {
+                        for path in &self.recent_scenes {
+                            let name = path.file_name().unwrap_or_default().to_string_lossy();
+                            let button = ui.button(name).on_hover_text(path.display().to_string());
+                            if button.clicked() {
+                                reopened = Some(path.clone());
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                })
+                .response
+                .on_hover_text("Drop a scene file on the window to open it");
+                if let Some(path) = reopened {
+                    self.open_scene(path, ctx);
+                }
+                let mut preset = None;
+                ui.menu_button("Presets", |ui| {
+                    for p in ScenePreset::ALL {
+                        if ui.button(p.name()).clicked() {
+                            preset = Some(p);
+                            ui.close_menu();
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Replace the scene by a built-in one");
+                if let Some(preset) = preset {
+                    self.set_scene(preset.build(), ctx);
+                }
+                let objects_changed = self.scene_editor.show(ui, &mut self.scene);
+                ui.separator();
+                ui.heading("Materials");
+                let materials_changed = material_editor::show(ui, &mut self.scene);
+                objects_changed || materials_changed
+            })
+            .inner;
+        if scene_changed {
+            self.start_preview(ctx);
+        }
+
+        egui::SidePanel::left("settings").show(ctx, |ui| {
             ui.heading("rt1we-gui");
 
-            ui.add(egui::Slider::new(&mut self.width, 0..=4000).text("Width"));
-            ui.add(egui::Slider::new(&mut self.height, 0..=4000).text("Height"));
-            ui.add(egui::Slider::new(&mut self.max_depth, 0..=200).text("Height"));
-            ui.add(egui::Slider::new(&mut self.samples_per_pixel, 0..=1000).text("Height"));
+            self.settings.show(ui);
+            if ui.button("Reset camera").clicked() {
+                self.view = OrbitView::default();
+                if self.job.is_some() || self.viewer.has_image() {
+                    self.start_render(ctx);
+                }
+            }
+            egui::CollapsingHeader::new("Lens").show(ui, |ui| {
+                if lens_editor::show(ui, &mut self.scene, self.view.distance) {
+                    self.start_preview(ctx);
+                }
+            });
 
             ui.separator();
 
-            if ui.button("Render one frame").clicked() {
-                let img = render(
-                    self.width as usize,
-                    self.height as usize,
-                    self.max_depth as usize,
-                    self.samples_per_pixel as usize,
-                    &rt1we_renderer::geometry::Vec3::new(0.0, 0.0, 0.0),
-                );
+            let rendering = self.job.is_some();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!rendering, egui::Button::new("Render one frame")).clicked() {
+                    self.start_render(ctx);
+                }
+                if ui.button("Add to queue").clicked() {
+                    self.queue.push(self.render_request());
+                }
+            });
+
+            if let Some(job) = &mut self.job {
+                ui.horizontal(|ui| {
+                    if job.is_paused() {
+                        if ui.button("Resume").clicked() {
+                            job.resume();
+                        }
+                    } else {
+                        ui.spinner();
+                        if ui.button("Pause").clicked() {
+                            job.pause();
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        job.cancel();
+                    }
+                });
+                ui.add(egui::ProgressBar::new(job.progress()).show_percentage());
+                ui.label(format!("Samples/px: {}/{}", job.samples(), job.samples_per_pixel()));
+                ui.label(format!("Elapsed: {:.1?}", job.elapsed()));
+                if let Some(throughput) = job.throughput() {
+                    ui.label(format!("Throughput: {:.2} Mrays/s", throughput / 1e6))
+                        .on_hover_text("Camera rays traced per second, bounces excluded");
+                }
+            } else if let Some(im) = self.frame.as_ref().map(|f| &f.image) {
+                match self.cancelled_at {
+                    Some(samples) => ui.label(format!(
+                        "Cancelled a {}x{} image at {} samples/px",
+                        im.width, im.height, samples
+                    )),
+                    None => ui.label(format!("Rendered a {}x{} image", im.width, im.height)),
+                };
+            }
+
+            ui.horizontal(|ui| {
+                if ui.add_enabled(self.frame.is_some(), egui::Button::new("Save as…")).clicked() {
+                    self.save_image();
+                }
+                ui.checkbox(&mut self.save_hdr, "Raw HDR")
+                    .on_hover_text("Save the linear values, before tone mapping and denoising");
+            });
+            if let Some(msg) = &self.error {
+                ui.colored_label(egui::Color32::RED, msg);
             }
-            // ui.label(format!("Hello '{}', age {}", self.name, self.age));
+
+            if self.viewer.has_image() {
+                ui.separator();
+                self.viewer.controls(ui);
+            }
+
+            ui.separator();
+            egui::CollapsingHeader::new("A/B comparison").show(ui, |ui| {
+                let current = self.frame.as_ref().map(|f| (&f.image, f.describe()));
+                if self.comparison.show(ui, current) {
+                    self.refresh_view(ctx);
+                }
+            });
+            ui.separator();
+            egui::CollapsingHeader::new("Post-processing").show(ui, |ui| {
+                let mut post = self.post();
+                postprocess::show(ui, &mut post, self.frame.as_ref().map(|f| &f.histogram));
+                self.tonemap = post.tonemap;
+                self.settings.denoise = post.denoise;
+            });
         });
+
+        // Post-processing changes apply to the current image, without rendering it again.
+        let post = self.post();
+        if self.frame.as_mut().is_some_and(|f| f.update(&post)) {
+            self.refresh_view(ctx);
+        }
+
+        let response = egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                let layout_view = self.layout_view;
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.layout_view, false, "Rendered");
+                    ui.selectable_value(&mut self.layout_view, true, "Layout")
+                        .on_hover_text("Draw the objects without rendering them, to place them");
+                });
+                if layout_view != self.layout_view && !self.layout_view {
+                    self.start_render(ctx);
+                }
+                if self.layout_view {
+                    let aspect_ratio = self.settings.width as f32 / self.settings.height as f32;
+                    let mut selected = self.scene_editor.selected();
+                    let response = self.layout.show(
+                        ui,
+                        &mut self.scene,
+                        &self.view,
+                        aspect_ratio,
+                        &mut selected,
+                    );
+                    self.scene_editor.select(selected);
+                    if response.changed {
+                        self.start_preview(ctx);
+                    }
+                    ViewerResponse { navigation: response.navigation, clicked: None }
+                } else {
+                    self.viewer.show(ui)
+                }
+            })
+            .inner;
+        let max_depth = self.settings.max_depth as usize;
+        if let (Some((x, y)), Some(frame)) = (response.clicked, &self.frame) {
+            self.inspector.select(x, y, frame, &self.scene, &self.view, max_depth);
+        }
+        self.inspector.show(ctx, self.frame.as_ref(), &self.scene, &self.view, max_depth);
+
+        match response.navigation {
+            Some(navigation) => self.navigate(navigation, ctx),
+            None if !scene_changed => self.settle(ctx),
+            None => {}
+        }
     }
 }
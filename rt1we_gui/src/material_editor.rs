@@ -0,0 +1,218 @@
+//! Material editing: colors, roughness, index of refraction, thin films and light intensity of
+//! the scene materials.
+use eframe::egui;
+use rt1we_renderer::geometry::Color;
+use rt1we_renderer::principled::PrincipledParams;
+use rt1we_renderer::render::{MaterialParams, Scene};
+use rt1we_renderer::texture::TextureParams;
+use rt1we_renderer::thin_film::ThinFilm;
+
+/// Show the materials of `scene`, with their editable parameters.
+///
+/// # Returns
+/// Whether a material changed.
+pub fn show(ui: &mut egui::Ui, scene: &mut Scene) -> bool {
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Add");
+        let added = [
+            ("Lambertian", MaterialParams::Lambertian { albedo: Color::new(0.5, 0.5, 0.5).into() }),
+            (
+                "Metal",
+                MaterialParams::Metal { albedo: Color::new(0.8, 0.8, 0.8), fuzz: 0.0, film: None },
+            ),
+            (
+                "Microfacet",
+                MaterialParams::Microfacet {
+                    diffuse: Color::new(0.5, 0.5, 0.5),
+                    f0: Color::new(0.04, 0.04, 0.04),
+                    roughness: 0.3,
+                },
+            ),
+            ("Principled", MaterialParams::Principled(PrincipledParams::default())),
+            (
+                "Subsurface",
+                MaterialParams::Subsurface {
+                    albedo: Color::new(0.9, 0.8, 0.6),
+                    mean_free_path: Color::new(0.1, 0.05, 0.02),
+                },
+            ),
+            (
+                "Dielectric",
+                MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.0, film: None },
+            ),
+            ("Light", MaterialParams::DiffuseLight { color: Color::WHITE, intensity: 4.0 }),
+            ("Isotropic", MaterialParams::Isotropic { albedo: Color::WHITE.into() }),
+        ];
+        for (label, params) in added {
+            if ui.button(label).clicked() {
+                scene.add_material(&params);
+                changed = true;
+            }
+        }
+    });
+
+    egui::ScrollArea::vertical().id_source("materials").show(ui, |ui| {
+        let ids: Vec<_> = scene.materials().ids().collect();
+        for id in ids {
+            let Some(mut params) = scene.material(id) else {
+                continue;
+            };
+            let name = scene.materials().name(id).unwrap_or("?");
+            let header = format!("{name} ({})", params.name());
+            egui::CollapsingHeader::new(header).id_source(id).show(ui, |ui| {
+                if edit_params(ui, &mut params) {
+                    scene.set_material(id, &params);
+                    changed = true;
+                }
+            });
+        }
+    });
+    changed
+}
+
+/// Editable fields of a material.
+fn edit_params(ui: &mut egui::Ui, params: &mut MaterialParams) -> bool {
+    let mut changed = false;
+    match params {
+        MaterialParams::Lambertian { albedo } => {
+            changed |= edit_texture(ui, "Albedo", albedo);
+        }
+        MaterialParams::Metal { albedo, fuzz, film } => {
+            changed |= edit_color(ui, "Albedo", albedo);
+            // Edited as the roughness, the square root of the fuzz, more even to the eye.
+            let mut roughness = fuzz.sqrt();
+            let slider = egui::Slider::new(&mut roughness, 0.0..=1.0).text("Roughness");
+            if ui.add(slider).changed() {
+                *fuzz = roughness * roughness;
+                changed = true;
+            }
+            changed |= edit_film(ui, film);
+        }
+        MaterialParams::Microfacet { diffuse, f0, roughness } => {
+            changed |= edit_color(ui, "Diffuse", diffuse);
+            changed |= edit_color(ui, "F0", f0);
+            let slider = egui::Slider::new(roughness, 0.0..=1.0).text("Roughness");
+            changed |= ui.add(slider).changed();
+        }
+        MaterialParams::Principled(principled) => {
+            changed |= edit_color(ui, "Base color", &mut principled.base_color);
+            let sliders = [
+                (&mut principled.metallic, "Metallic"),
+                (&mut principled.roughness, "Roughness"),
+                (&mut principled.specular, "Specular"),
+                (&mut principled.clearcoat, "Clearcoat"),
+                (&mut principled.transmission, "Transmission"),
+            ];
+            for (value, label) in sliders {
+                changed |= ui.add(egui::Slider::new(value, 0.0..=1.0).text(label)).changed();
+            }
+        }
+        MaterialParams::Subsurface { albedo, mean_free_path } => {
+            changed |= edit_color(ui, "Albedo", albedo);
+            ui.horizontal(|ui| {
+                ui.label("Mean free path");
+                for channel in [&mut mean_free_path.x, &mut mean_free_path.y, &mut mean_free_path.z]
+                {
+                    let drag = egui::DragValue::new(channel).speed(0.001).clamp_range(0.001..=10.0);
+                    changed |= ui.add(drag).changed();
+                }
+            });
+        }
+        MaterialParams::Dielectric { refraction_index, dispersion, film } => {
+            let slider = egui::Slider::new(refraction_index, 1.0..=3.0).text("IOR");
+            changed |= ui.add(slider).changed();
+            let slider = egui::Slider::new(dispersion, 0.0..=0.05).text("Dispersion");
+            changed |= ui.add(slider).changed();
+            changed |= edit_film(ui, film);
+        }
+        MaterialParams::Isotropic { albedo } => {
+            changed |= edit_texture(ui, "Albedo", albedo);
+        }
+        MaterialParams::DiffuseLight { color, intensity } => {
+            changed |= edit_color(ui, "Color", color);
+            let drag = egui::DragValue::new(intensity).speed(0.1).clamp_range(0.0..=f32::MAX);
+            ui.horizontal(|ui| {
+                ui.label("Intensity");
+                changed |= ui.add(drag).changed();
+            });
+        }
+    }
+    changed
+}
+
+/// Texture kind selector, and the fields of the selected kind.
+fn edit_texture(ui: &mut egui::Ui, label: &str, texture: &mut TextureParams) -> bool {
+    let mut changed = false;
+    // Switching kind starts from the average color.
+    let color = texture.average();
+    let kinds = [
+        TextureParams::Solid(color),
+        TextureParams::Checker { odd: Color::BLACK, even: color, scale: 1.0 },
+        TextureParams::Noise { color, scale: 4.0 },
+    ];
+    ui.horizontal(|ui| {
+        ui.label(label);
+        egui::ComboBox::from_id_source(label).selected_text(texture.name()).show_ui(ui, |ui| {
+            for kind in kinds {
+                let selected = kind.name() == texture.name();
+                if ui.selectable_label(selected, kind.name()).clicked() && !selected {
+                    *texture = kind;
+                    changed = true;
+                }
+            }
+        });
+    });
+    match texture {
+        TextureParams::Solid(color) => {
+            changed |= edit_color(ui, "Color", color);
+        }
+        TextureParams::Checker { odd, even, scale } => {
+            changed |= edit_color(ui, "Odd", odd);
+            changed |= edit_color(ui, "Even", even);
+            let slider = egui::Slider::new(scale, 0.01..=10.0).logarithmic(true).text("Scale");
+            changed |= ui.add(slider).changed();
+        }
+        TextureParams::Noise { color, scale } => {
+            changed |= edit_color(ui, "Color", color);
+            let slider = egui::Slider::new(scale, 0.1..=50.0).logarithmic(true).text("Scale");
+            changed |= ui.add(slider).changed();
+        }
+        TextureParams::Image(image) => {
+            ui.label(format!("{}x{} image", image.width(), image.height()));
+        }
+    }
+    changed
+}
+
+/// Color picker for a linear color.
+/// Edit an optional thin film coating, added as a soap film.
+fn edit_film(ui: &mut egui::Ui, film: &mut Option<ThinFilm>) -> bool {
+    let mut coated = film.is_some();
+    let mut changed = ui.checkbox(&mut coated, "Thin film").changed();
+    if changed {
+        *film = coated.then(ThinFilm::default);
+    }
+    if let Some(film) = film {
+        let slider = egui::Slider::new(&mut film.thickness, 0.0..=1500.0).text("Thickness (nm)");
+        changed |= ui.add(slider).changed();
+        let slider = egui::Slider::new(&mut film.refraction_index, 1.0..=3.0).text("Film IOR");
+        changed |= ui.add(slider).changed();
+    }
+    changed
+}
+
+fn edit_color(ui: &mut egui::Ui, label: &str, color: &mut Color) -> bool {
+    let mut rgb = [color.x, color.y, color.z];
+    let changed = ui
+        .horizontal(|ui| {
+            ui.label(label);
+            egui::color_picker::color_edit_button_rgb(ui, &mut rgb).changed()
+        })
+        .inner;
+    if changed {
+        *color = Color::new(rgb[0], rgb[1], rgb[2]);
+    }
+    changed
+}
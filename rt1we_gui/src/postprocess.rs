@@ -0,0 +1,181 @@
+//! Post-processing of the rendered images: tone mapping, denoising and luminance histogram.
+//!
+//! The linear values of the latest render are kept, so that changing the settings only
+//! post-processes them again, without tracing any ray.
+use eframe::egui;
+use rt1we_renderer::denoise::{denoise_hdr, HdrGuides, WaveletSettings};
+use rt1we_renderer::histogram::Histogram;
+use rt1we_renderer::image::{ImageRGBA, ImageRGBF32};
+use rt1we_renderer::tonemap::{tonemap, ToneMapOperator, ToneMapSettings};
+
+const HISTOGRAM_BINS: usize = 64;
+/// Luminance range of the histogram, in stops.
+const HISTOGRAM_RANGE: (f32, f32) = (-12.0, 4.0);
+const HISTOGRAM_HEIGHT: f32 = 80.0;
+const MAX_EXPOSURE: f32 = 8.0;
+
+/// How the accumulated values are turned into the displayed image.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct PostSettings {
+    pub tonemap: ToneMapSettings,
+    /// Denoise the complete renders, before tone mapping.
+    pub denoise: bool,
+}
+
+/// A rendered image, as accumulated and as displayed.
+#[derive(Clone)]
+pub struct Frame {
+    /// Linear values, before post-processing.
+    pub hdr: ImageRGBF32,
+    /// Post-processed with `post`.
+    pub image: ImageRGBA,
+    pub histogram: Histogram,
+    /// Samples per pixel accumulated in the image.
+    pub samples: usize,
+    /// Maximum number of bounces of the rays.
+    pub max_depth: usize,
+    /// The render is over, partial renders are never denoised.
+    complete: bool,
+    post: PostSettings,
+}
+
+impl Frame {
+    /// Post-process a rendered image.
+    ///
+    /// # Arguments
+    /// - `hdr` - Linear values of the render.
+    /// - `samples` - Samples per pixel accumulated in `hdr`.
+    /// - `max_depth` - Maximum number of bounces of the rays.
+    /// - `complete` - Whether the render is over.
+    /// - `post` - Post-processing settings.
+    pub fn new(
+        hdr: ImageRGBF32, samples: usize, max_depth: usize, complete: bool, post: &PostSettings,
+    ) -> Self {
+        let (min_ev, max_ev) = HISTOGRAM_RANGE;
+        let histogram = Histogram::of(&hdr, HISTOGRAM_BINS, min_ev, max_ev);
+        let image = develop(&hdr, complete, post);
+        Frame { hdr, image, histogram, samples, max_depth, complete, post: *post }
+    }
+
+    /// Post-process the image again if the settings changed.
+    ///
+    /// # Returns
+    /// Whether the image changed.
+    pub fn update(&mut self, post: &PostSettings) -> bool {
+        if self.post == *post {
+            return false;
+        }
+        // Toggling denoising on a partial render does not change it.
+        let denoise_only = self.post.tonemap == post.tonemap;
+        self.post = *post;
+        if denoise_only && !self.complete {
+            return false;
+        }
+        self.image = develop(&self.hdr, self.complete, post);
+        true
+    }
+
+    /// Describe how the image was rendered.
+    pub fn describe(&self) -> String {
+        let denoised = if self.complete && self.post.denoise { ", denoised" } else { "" };
+        format!("{}x{}, {} samples/px{denoised}", self.image.width, self.image.height, self.samples)
+    }
+
+    /// File name to save the image as, with the settings it was rendered with.
+    ///
+    /// # Arguments
+    /// - `raw` - Name the linear values, saved as OpenEXR, rather than the displayed image.
+    pub fn file_name(&self, raw: bool) -> String {
+        let (width, height) = (self.image.width, self.image.height);
+        let name = format!("render_{width}x{height}_{}spp_depth{}", self.samples, self.max_depth);
+        if raw {
+            format!("{name}.exr")
+        } else if self.complete && self.post.denoise {
+            format!("{name}_denoised.png")
+        } else {
+            format!("{name}.png")
+        }
+    }
+}
+
+fn develop(hdr: &ImageRGBF32, complete: bool, post: &PostSettings) -> ImageRGBA {
+    if complete && post.denoise {
+        let denoised = denoise_hdr(hdr, &HdrGuides::default(), &WaveletSettings::default());
+        tonemap(&denoised.expect("there are no guides"), &post.tonemap)
+    } else {
+        tonemap(hdr, &post.tonemap)
+    }
+}
+
+/// Show the post-processing controls, and the histogram of the image when there is one.
+pub fn show(ui: &mut egui::Ui, post: &mut PostSettings, histogram: Option<&Histogram>) {
+    let settings = &mut post.tonemap;
+
+    if let Some(histogram) = histogram {
+        show_histogram(ui, histogram, settings);
+    }
+
+    egui::Grid::new("tonemap").num_columns(2).show(ui, |ui| {
+        ui.label("Operator");
+        egui::ComboBox::from_id_source("tonemap_operator")
+            .selected_text(settings.operator.name())
+            .show_ui(ui, |ui| {
+                for operator in ToneMapOperator::ALL {
+                    ui.selectable_value(&mut settings.operator, operator, operator.name());
+                }
+            });
+        ui.end_row();
+
+        ui.label("Exposure");
+        ui.add(
+            egui::Slider::new(&mut settings.exposure, -MAX_EXPOSURE..=MAX_EXPOSURE)
+                .suffix(" EV")
+                .step_by(0.1),
+        );
+        ui.end_row();
+
+        ui.label("Gamma");
+        ui.add(egui::Slider::new(&mut settings.gamma, 1.0..=3.0).step_by(0.05));
+        ui.end_row();
+    });
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut post.denoise, "Denoise")
+            .on_hover_text("Applied to complete renders, after tone mapping");
+        if ui.button("Reset").clicked() {
+            post.tonemap = ToneMapSettings::default();
+        }
+    });
+}
+
+/// Draw the histogram, with the luminance exposed to 1 marked by a line.
+fn show_histogram(ui: &mut egui::Ui, histogram: &Histogram, settings: &ToneMapSettings) {
+    let size = egui::vec2(ui.available_width(), HISTOGRAM_HEIGHT);
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+    let range = histogram.max_ev - histogram.min_ev;
+    let x_of = |ev: f32| rect.left() + (ev - histogram.min_ev) / range * rect.width();
+    let white_ev = -settings.exposure;
+    let peak = histogram.peak().max(1) as f32;
+    for (k, &count) in histogram.bins.iter().enumerate() {
+        let (ev0, ev1) = histogram.bin_range(k);
+        let height = count as f32 / peak * rect.height();
+        let bar = egui::Rect::from_x_y_ranges(
+            x_of(ev0)..=x_of(ev1),
+            rect.bottom() - height..=rect.bottom(),
+        );
+        let clipped = settings.operator == ToneMapOperator::Clamp && ev0 >= white_ev;
+        let color = if clipped { egui::Color32::LIGHT_RED } else { egui::Color32::GRAY };
+        painter.rect_filled(bar, 0.0, color);
+    }
+
+    let x = x_of(white_ev);
+    painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, egui::Color32::YELLOW));
+
+    response.on_hover_text(format!(
+        "Luminance of the pixels, from 2^{} to 2^{}. The line marks the luminance exposed to \
+         1, {} pixels are black.",
+        histogram.min_ev, histogram.max_ev, histogram.black
+    ));
+}
@@ -0,0 +1,148 @@
+//! Render queue: renders enqueued with their own scene and settings, run one after the other.
+use eframe::egui;
+use rt1we_renderer::image::{resize, ImageRGBA};
+
+use crate::postprocess::{Frame, PostSettings};
+use crate::viewer::color_image;
+use crate::worker::{JobUpdate, RenderJob, RenderRequest};
+
+/// Width of the thumbnails of the finished renders, in pixels.
+const THUMBNAIL_WIDTH: usize = 96;
+
+enum JobState {
+    Waiting,
+    Running(RenderJob),
+    Done { frame: Frame, thumbnail: egui::TextureHandle },
+    Failed(String),
+    Cancelled,
+}
+
+struct QueuedJob {
+    name: String,
+    request: RenderRequest,
+    state: JobState,
+}
+
+/// Renders waiting, running or done, in the order they were enqueued.
+#[derive(Default)]
+pub struct RenderQueue {
+    jobs: Vec<QueuedJob>,
+    /// Number of jobs enqueued so far, to name them.
+    count: usize,
+}
+
+impl RenderQueue {
+    /// Add a render at the end of the queue.
+    pub fn push(&mut self, request: RenderRequest) {
+        self.count += 1;
+        let name = format!(
+            "#{} {}x{}, {} samples/px",
+            self.count, request.width, request.height, request.samples_per_pixel
+        );
+        self.jobs.push(QueuedJob { name, request, state: JobState::Waiting });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Collect the result of the running render, and start the next one once it is over.
+    ///
+    /// # Arguments
+    /// - `ctx` - The UI context, repainted whenever a render progresses.
+    /// - `post` - Post-processing of the finished renders.
+    pub fn poll(&mut self, ctx: &egui::Context, post: &PostSettings) {
+        for job in &mut self.jobs {
+            let JobState::Running(running) = &mut job.state else {
+                continue;
+            };
+            match running.poll() {
+                Ok(JobUpdate::Pending) | Ok(JobUpdate::Refined(_)) => return,
+                Ok(JobUpdate::Done(hdr)) => {
+                    let frame = Frame::new(hdr, running.samples(), running.max_depth(), true, post);
+                    let thumbnail = ctx.load_texture(
+                        format!("thumbnail {}", job.name),
+                        color_image(&thumbnail(&frame.image)),
+                        egui::TextureOptions::LINEAR,
+                    );
+                    job.state = JobState::Done { frame, thumbnail };
+                }
+                Ok(JobUpdate::Cancelled(_)) => job.state = JobState::Cancelled,
+                Err(msg) => job.state = JobState::Failed(msg),
+            }
+        }
+
+        if let Some(job) = self.jobs.iter_mut().find(|j| matches!(j.state, JobState::Waiting)) {
+            job.state = JobState::Running(RenderJob::spawn(job.request.clone(), ctx.clone()));
+        }
+    }
+
+    /// Show the queued renders, with the progress of the running one and thumbnails of the
+    /// finished ones.
+    ///
+    /// # Returns
+    /// The finished render whose thumbnail was clicked, if any.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<&Frame> {
+        let mut clicked = None;
+        let mut removed = None;
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                for (k, job) in self.jobs.iter().enumerate() {
+                    ui.group(|ui| {
+                        ui.vertical(|ui| {
+                            ui.set_width(THUMBNAIL_WIDTH as f32);
+                            ui.label(&job.name);
+                            match &job.state {
+                                JobState::Waiting => {
+                                    ui.label("Waiting");
+                                }
+                                JobState::Running(running) => {
+                                    let progress = egui::ProgressBar::new(running.progress());
+                                    ui.add(progress.show_percentage());
+                                }
+                                JobState::Done { thumbnail, .. } => {
+                                    let button = egui::ImageButton::new(
+                                        egui::load::SizedTexture::from_handle(thumbnail),
+                                    );
+                                    if ui.add(button).on_hover_text("Show this render").clicked() {
+                                        clicked = Some(k);
+                                    }
+                                }
+                                JobState::Failed(msg) => {
+                                    ui.colored_label(egui::Color32::RED, msg);
+                                }
+                                JobState::Cancelled => {
+                                    ui.label("Cancelled");
+                                }
+                            }
+                            let label = if let JobState::Running(_) = job.state {
+                                "Cancel"
+                            } else {
+                                "Remove"
+                            };
+                            if ui.small_button(label).clicked() {
+                                removed = Some(k);
+                            }
+                        });
+                    });
+                }
+            });
+        });
+
+        // Dropping a running job cancels it, the next one then starts.
+        if let Some(k) = removed {
+            self.jobs.remove(k);
+            return None;
+        }
+        clicked.and_then(|k| match &self.jobs[k].state {
+            JobState::Done { frame, .. } => Some(frame),
+            _ => None,
+        })
+    }
+}
+
+/// A small copy of an image, keeping its aspect ratio.
+fn thumbnail(im: &ImageRGBA) -> ImageRGBA {
+    let height = (im.height * THUMBNAIL_WIDTH / im.width.max(1)).max(1);
+    resize(im, THUMBNAIL_WIDTH, height)
+}
@@ -0,0 +1,360 @@
+//! Scene editing: the list of objects, and the properties of the selected one.
+use eframe::egui;
+use rt1we_renderer::geometry::{Color, Point, Vec3};
+use rt1we_renderer::instance::Transform;
+use rt1we_renderer::rect::Cuboid;
+use rt1we_renderer::registry::MaterialId;
+use rt1we_renderer::render::{MaterialParams, Object, Scene, Sphere};
+use std::sync::Arc;
+
+/// Position change per dragged point.
+const DRAG_SPEED: f64 = 0.01;
+
+/// Edits the objects of a scene.
+#[derive(Default)]
+pub struct SceneEditor {
+    /// Index of the selected object.
+    selected: Option<usize>,
+}
+
+impl SceneEditor {
+    /// Index of the selected object.
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    pub fn select(&mut self, index: Option<usize>) {
+        self.selected = index;
+    }
+
+    /// Show the editor.
+    ///
+    /// # Returns
+    /// Whether the scene changed.
+    pub fn show(&mut self, ui: &mut egui::Ui, scene: &mut Scene) -> bool {
+        let mut changed = false;
+        let count = scene.world().len();
+        self.selected = self.selected.filter(|&i| i < count);
+
+        ui.horizontal(|ui| {
+            if ui.button("Add").clicked() {
+                let material_id = match scene.materials().ids().next() {
+                    Some(id) => id,
+                    None => scene.add_material(&MaterialParams::Lambertian {
+                        albedo: Color::new(0.5, 0.5, 0.5).into(),
+                    }),
+                };
+                let sphere = Sphere::new(Point::new(0.0, 0.0, -1.0), 0.5, material_id);
+                scene.add(sphere).expect("the material is one of the scene");
+                self.selected = Some(count);
+                changed = true;
+            }
+            if let Some(index) = self.selected {
+                if ui.button("Duplicate").clicked() {
+                    let copy = scene.world().objects()[index].clone();
+                    scene.add(copy).expect("the copy has a material of the scene");
+                    self.selected = Some(count);
+                    changed = true;
+                }
+                if ui.button("Remove").clicked() {
+                    scene.world_mut().remove(index);
+                    self.selected = None;
+                    changed = true;
+                }
+            }
+        });
+
+        egui::ScrollArea::vertical().id_source("objects").max_height(200.0).show(ui, |ui| {
+            for (i, object) in scene.world().objects().iter().enumerate() {
+                let material = scene.materials().name(object.material_id()).unwrap_or("?");
+                let label = format!("#{i} {} ({material})", object.name());
+                if ui.selectable_label(self.selected == Some(i), label).clicked() {
+                    self.selected = Some(i);
+                }
+            }
+        });
+
+        if let Some(index) = self.selected {
+            ui.separator();
+            changed |= Self::object_properties(ui, scene, index);
+        }
+        changed
+    }
+
+    /// Editable fields of the object at `index`.
+    fn object_properties(ui: &mut egui::Ui, scene: &mut Scene, index: usize) -> bool {
+        let registry = scene.materials();
+        let materials: Vec<(MaterialId, String)> = registry
+            .ids()
+            .map(|id| {
+                let kind = scene.material_name(id).unwrap_or("?");
+                (id, format!("{} ({kind})", registry.name(id).unwrap_or("?")))
+            })
+            .collect();
+        let object = &mut scene.world_mut().objects_mut()[index];
+        let mut changed = false;
+
+        egui::Grid::new("object").num_columns(2).show(ui, |ui| {
+            match object {
+                Object::Sphere(sphere) => {
+                    ui.label("Center");
+                    changed |= vec3_fields(ui, &mut sphere.center);
+                    ui.end_row();
+
+                    ui.label("Radius");
+                    let radius = egui::DragValue::new(&mut sphere.radius).speed(DRAG_SPEED);
+                    changed |= ui.add(radius).changed();
+                    ui.end_row();
+                }
+                Object::MovingSphere(sphere) => {
+                    for (label, center, time) in [
+                        ("Start", &mut sphere.center0, &mut sphere.time0),
+                        ("End", &mut sphere.center1, &mut sphere.time1),
+                    ] {
+                        ui.label(label);
+                        changed |= vec3_fields(ui, center);
+                        ui.end_row();
+
+                        ui.label(format!("{label} time"));
+                        changed |= ui.add(egui::DragValue::new(time).speed(DRAG_SPEED)).changed();
+                        ui.end_row();
+                    }
+
+                    ui.label("Radius");
+                    let radius = egui::DragValue::new(&mut sphere.radius).speed(DRAG_SPEED);
+                    changed |= ui.add(radius).changed();
+                    ui.end_row();
+                }
+                Object::Plane(plane) => {
+                    ui.label("Point");
+                    changed |= vec3_fields(ui, &mut plane.center);
+                    ui.end_row();
+
+                    // The normal stays a unit vector, a null one is not applied.
+                    ui.label("Normal");
+                    let mut normal = plane.normal;
+                    if vec3_fields(ui, &mut normal) && normal.len() > 0.0 {
+                        plane.normal = normal.normed();
+                        changed = true;
+                    }
+                    ui.end_row();
+                }
+                Object::Triangle(triangle) => {
+                    for (k, vertex) in triangle.vertices.iter_mut().enumerate() {
+                        ui.label(format!("Vertex {k}"));
+                        changed |= vec3_fields(ui, vertex);
+                        ui.end_row();
+                    }
+                }
+                Object::Mesh(mesh) => {
+                    ui.label("Triangles");
+                    ui.label(mesh.len().to_string());
+                    ui.end_row();
+                }
+                Object::XyRect(rect) => {
+                    changed |= range_row(ui, "x", &mut rect.x0, &mut rect.x1);
+                    changed |= range_row(ui, "y", &mut rect.y0, &mut rect.y1);
+                    changed |= value_row(ui, "z", &mut rect.k);
+                }
+                Object::XzRect(rect) => {
+                    changed |= range_row(ui, "x", &mut rect.x0, &mut rect.x1);
+                    changed |= range_row(ui, "z", &mut rect.z0, &mut rect.z1);
+                    changed |= value_row(ui, "y", &mut rect.k);
+                }
+                Object::YzRect(rect) => {
+                    changed |= range_row(ui, "y", &mut rect.y0, &mut rect.y1);
+                    changed |= range_row(ui, "z", &mut rect.z0, &mut rect.z1);
+                    changed |= value_row(ui, "x", &mut rect.k);
+                }
+                Object::Cuboid(cuboid) => {
+                    let (mut min, mut max) = (cuboid.min, cuboid.max);
+                    ui.label("Min");
+                    let mut corners_changed = vec3_fields(ui, &mut min);
+                    ui.end_row();
+
+                    ui.label("Max");
+                    corners_changed |= vec3_fields(ui, &mut max);
+                    ui.end_row();
+
+                    // Corners dragged past each other swap.
+                    if corners_changed {
+                        *cuboid = Cuboid::new(min, max, cuboid.material_id);
+                        changed = true;
+                    }
+                }
+                Object::Cylinder(cylinder) => {
+                    changed |= axis_rows(ui, &mut cylinder.base, &mut cylinder.top);
+                    changed |= radius_row(ui, "Radius", &mut cylinder.radius, 0.001);
+                }
+                Object::Cone(cone) => {
+                    changed |= axis_rows(ui, &mut cone.base, &mut cone.top);
+                    changed |= radius_row(ui, "Base radius", &mut cone.base_radius, 0.0);
+                    changed |= radius_row(ui, "Top radius", &mut cone.top_radius, 0.0);
+                }
+                Object::Capsule(capsule) => {
+                    changed |= axis_rows(ui, &mut capsule.base, &mut capsule.top);
+                    changed |= radius_row(ui, "Radius", &mut capsule.radius, 0.001);
+                }
+                Object::Torus(torus) => {
+                    ui.label("Center");
+                    changed |= vec3_fields(ui, &mut torus.center);
+                    ui.end_row();
+
+                    // The axis stays a unit vector, a null one is not applied.
+                    ui.label("Axis");
+                    let mut axis = torus.axis;
+                    if vec3_fields(ui, &mut axis) && axis.len() > 0.0 {
+                        torus.axis = axis.normed();
+                        changed = true;
+                    }
+                    ui.end_row();
+
+                    changed |= radius_row(ui, "Major radius", &mut torus.major_radius, 0.001);
+                    changed |= radius_row(ui, "Minor radius", &mut torus.minor_radius, 0.001);
+                }
+                Object::Sdf(object) => {
+                    ui.label("Shape");
+                    ui.label(object.sdf.name());
+                    ui.end_row();
+                }
+                Object::Heightfield(heightfield) => {
+                    ui.label("Heights");
+                    ui.label(format!("{} × {}", heightfield.columns(), heightfield.rows()));
+                    ui.end_row();
+
+                    ui.label("Min");
+                    let mut min = heightfield.min;
+                    if vec3_fields(ui, &mut min) {
+                        Arc::make_mut(heightfield).min = min;
+                        changed = true;
+                    }
+                    ui.end_row();
+
+                    // The size stays positive, other sizes are not applied.
+                    ui.label("Size");
+                    let mut size = heightfield.size;
+                    if vec3_fields(ui, &mut size) && size.x > 0.0 && size.y > 0.0 && size.z > 0.0 {
+                        Arc::make_mut(heightfield).size = size;
+                        changed = true;
+                    }
+                    ui.end_row();
+                }
+                Object::Instance(instance) => {
+                    ui.label("Triangles");
+                    ui.label(instance.mesh().indices().len().to_string());
+                    ui.end_row();
+
+                    let transform = instance.transform();
+                    let (mut translation, mut rotation, mut scale) =
+                        (transform.translation(), transform.rotation(), transform.scale());
+                    ui.label("Translation");
+                    let mut edited = vec3_fields(ui, &mut translation);
+                    ui.end_row();
+                    ui.label("Rotation");
+                    edited |= vec3_fields(ui, &mut rotation);
+                    ui.end_row();
+                    // A null scale is not applied, it would flatten the mesh.
+                    ui.label("Scale");
+                    edited |= vec3_fields(ui, &mut scale);
+                    ui.end_row();
+                    if edited && scale.x != 0.0 && scale.y != 0.0 && scale.z != 0.0 {
+                        instance.set_transform(Transform::new(translation, rotation, scale));
+                        changed = true;
+                    }
+                }
+                Object::Medium(medium) => {
+                    ui.label("Boundary");
+                    ui.label(medium.boundary.name());
+                    ui.end_row();
+
+                    ui.label("Density");
+                    let density = egui::DragValue::new(&mut medium.density)
+                        .speed(DRAG_SPEED)
+                        .clamp_range(0.001..=f32::MAX);
+                    changed |= ui.add(density).changed();
+                    ui.end_row();
+                }
+            }
+
+            ui.label("Material");
+            let material_id = object.material_id_mut();
+            let selected = materials.iter().find(|(id, _)| id == material_id);
+            let selected = selected.map_or("?", |(_, name)| name.as_str());
+            egui::ComboBox::from_id_source("material").selected_text(selected).show_ui(ui, |ui| {
+                for (id, name) in &materials {
+                    changed |= ui.selectable_value(material_id, *id, name).changed();
+                }
+            });
+            ui.end_row();
+        });
+        changed
+    }
+}
+
+/// One drag field per coordinate of `v`.
+///
+/// # Returns
+/// Whether `v` changed.
+fn vec3_fields(ui: &mut egui::Ui, v: &mut Vec3) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        for (axis, value) in [("x", &mut v.x), ("y", &mut v.y), ("z", &mut v.z)] {
+            let field = egui::DragValue::new(value).speed(DRAG_SPEED).prefix(format!("{axis}: "));
+            changed |= ui.add(field).changed();
+        }
+    });
+    changed
+}
+
+/// A grid row with two drag fields, the bounds of a range along `axis`.
+///
+/// # Returns
+/// Whether a bound changed.
+fn range_row(ui: &mut egui::Ui, axis: &str, lo: &mut f32, hi: &mut f32) -> bool {
+    let mut changed = false;
+    ui.label(format!("{axis} range"));
+    ui.horizontal(|ui| {
+        changed |= ui.add(egui::DragValue::new(lo).speed(DRAG_SPEED)).changed();
+        changed |= ui.add(egui::DragValue::new(hi).speed(DRAG_SPEED)).changed();
+    });
+    ui.end_row();
+    changed
+}
+
+/// A grid row with a single drag field, a coordinate along `axis`.
+///
+/// # Returns
+/// Whether the value changed.
+fn value_row(ui: &mut egui::Ui, axis: &str, value: &mut f32) -> bool {
+    ui.label(axis);
+    let changed = ui.add(egui::DragValue::new(value).speed(DRAG_SPEED)).changed();
+    ui.end_row();
+    changed
+}
+
+/// Grid rows with the ends of the axis of a cylinder, a cone or a capsule.
+///
+/// # Returns
+/// Whether an end changed.
+fn axis_rows(ui: &mut egui::Ui, base: &mut Point, top: &mut Point) -> bool {
+    ui.label("Base");
+    let mut changed = vec3_fields(ui, base);
+    ui.end_row();
+
+    ui.label("Top");
+    changed |= vec3_fields(ui, top);
+    ui.end_row();
+    changed
+}
+
+/// A grid row with a drag field for a radius, kept above `min`.
+///
+/// # Returns
+/// Whether the radius changed.
+fn radius_row(ui: &mut egui::Ui, label: &str, radius: &mut f32, min: f32) -> bool {
+    ui.label(label);
+    let field = egui::DragValue::new(radius).speed(DRAG_SPEED).clamp_range(min..=f32::MAX);
+    let changed = ui.add(field).changed();
+    ui.end_row();
+    changed
+}
@@ -0,0 +1,361 @@
+//! Render settings: quality presets, resolution, samples, preview scale, threads and
+//! integrator.
+use eframe::egui;
+use rt1we_renderer::preset::QualityPreset;
+use rt1we_renderer::progressive::available_threads;
+use rt1we_renderer::wavefront::Integrator;
+use serde::{Deserialize, Serialize};
+
+/// Resolution the quality presets are scaled from.
+const BASE_RESOLUTION: (u32, u32) = (160, 120);
+const MAX_RESOLUTION: u32 = 8192;
+const MAX_SAMPLES: u32 = 100_000;
+const MAX_DEPTH: u32 = 100;
+
+/// Common output resolutions, by aspect ratio then size.
+const RESOLUTIONS: [(&str, u32, u32); 8] = [
+    ("512×512 (1:1)", 512, 512),
+    ("160×120 (4:3)", 160, 120),
+    ("320×240 (4:3)", 320, 240),
+    ("640×480 (4:3)", 640, 480),
+    ("320×180 (16:9)", 320, 180),
+    ("640×360 (16:9)", 640, 360),
+    ("1280×720 (16:9)", 1280, 720),
+    ("1920×1080 (16:9)", 1920, 1080),
+];
+
+/// Resolution of the low quality renders shown while the camera moves or the scene is edited,
+/// relative to the output resolution.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PreviewScale {
+    Quarter,
+    Half,
+    Full,
+}
+
+impl PreviewScale {
+    const ALL: [PreviewScale; 3] = [PreviewScale::Quarter, PreviewScale::Half, PreviewScale::Full];
+
+    fn name(&self) -> &'static str {
+        match self {
+            PreviewScale::Quarter => "1/4",
+            PreviewScale::Half => "1/2",
+            PreviewScale::Full => "1/1",
+        }
+    }
+
+    /// Scale a resolution, keeping at least two pixels in each direction.
+    pub fn apply(&self, width: u32, height: u32) -> (u32, u32) {
+        let divisor = match self {
+            PreviewScale::Quarter => 4,
+            PreviewScale::Half => 2,
+            PreviewScale::Full => 1,
+        };
+        ((width / divisor).max(2), (height / divisor).max(2))
+    }
+}
+
+/// Settings of the renders, edited in the settings panel.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub max_depth: u32,
+    pub samples_per_pixel: u32,
+    /// Set by the quality presets, and edited with the post-processing settings.
+    pub denoise: bool,
+    pub preview_scale: PreviewScale,
+    /// Number of threads rendering, not part of the presets.
+    pub threads: u32,
+    /// How the rays are scheduled, not part of the presets either.
+    pub integrator: Integrator,
+    /// The preset the settings come from, `None` once edited.
+    #[serde(skip)]
+    preset: Option<QualityPreset>,
+    /// Width over height, kept when either changes.
+    locked_aspect: Option<f32>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: BASE_RESOLUTION.0,
+            height: BASE_RESOLUTION.1,
+            max_depth: 50,
+            samples_per_pixel: 100,
+            denoise: false,
+            preview_scale: PreviewScale::Half,
+            threads: available_threads() as u32,
+            integrator: Integrator::default(),
+            preset: None,
+            locked_aspect: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    fn apply_preset(&mut self, preset: QualityPreset) {
+        let quality = preset.settings();
+        let (w, h) =
+            quality.scale_resolution(BASE_RESOLUTION.0 as usize, BASE_RESOLUTION.1 as usize);
+        self.set_resolution(w as u32, h as u32);
+        self.max_depth = quality.max_depth as u32;
+        self.samples_per_pixel = quality.samples_per_pixel as u32;
+        self.denoise = quality.denoise;
+    }
+
+    /// Bring the settings back in the ranges of the controls, as after loading them.
+    pub fn clamp(&mut self) {
+        self.width = self.width.clamp(2, MAX_RESOLUTION);
+        self.height = self.height.clamp(2, MAX_RESOLUTION);
+        self.samples_per_pixel = self.samples_per_pixel.clamp(1, MAX_SAMPLES);
+        self.max_depth = self.max_depth.clamp(1, MAX_DEPTH);
+        // The settings may come from a machine with more cores.
+        self.threads = self.threads.clamp(1, available_threads() as u32);
+        self.locked_aspect = self.locked_aspect.filter(|a| a.is_finite() && *a > 0.0);
+    }
+
+    /// Change the resolution, and the locked aspect ratio with it.
+    fn set_resolution(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        if self.locked_aspect.is_some() {
+            self.locked_aspect = Some(width as f32 / height as f32);
+        }
+    }
+
+    /// Change the width, and the height with it when the aspect ratio is locked.
+    fn set_width(&mut self, width: u32) {
+        self.width = width;
+        if let Some(aspect) = self.locked_aspect {
+            self.height = ((width as f32 / aspect).round() as u32).clamp(2, MAX_RESOLUTION);
+        }
+    }
+
+    /// Change the height, and the width with it when the aspect ratio is locked.
+    fn set_height(&mut self, height: u32) {
+        self.height = height;
+        if let Some(aspect) = self.locked_aspect {
+            self.width = ((height as f32 * aspect).round() as u32).clamp(2, MAX_RESOLUTION);
+        }
+    }
+
+    /// Keep the current aspect ratio when the width or the height changes, or stop keeping it.
+    fn lock_aspect(&mut self, locked: bool) {
+        self.locked_aspect = locked.then_some(self.width as f32 / self.height as f32);
+    }
+
+    /// Show the settings controls.
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let previous = self.preset;
+        egui::ComboBox::from_label("Preset")
+            .selected_text(self.preset.map_or("custom", |p| p.name()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.preset, None, "custom");
+                for preset in QualityPreset::ALL {
+                    ui.selectable_value(&mut self.preset, Some(preset), preset.name());
+                }
+            });
+        if self.preset != previous {
+            if let Some(preset) = self.preset {
+                self.apply_preset(preset);
+            }
+        }
+
+        let mut edited = false;
+        let current = RESOLUTIONS.iter().find(|r| (r.1, r.2) == (self.width, self.height));
+        egui::ComboBox::from_label("Resolution")
+            .selected_text(current.map_or("custom", |r| r.0))
+            .show_ui(ui, |ui| {
+                for (name, w, h) in RESOLUTIONS {
+                    if ui.selectable_label(current.is_some_and(|r| r.0 == name), name).clicked() {
+                        self.set_resolution(w, h);
+                        edited = true;
+                    }
+                }
+            });
+
+        egui::Grid::new("settings").num_columns(2).show(ui, |ui| {
+            let range = 2..=MAX_RESOLUTION;
+            ui.label("Width");
+            let mut width = self.width;
+            if ui.add(egui::DragValue::new(&mut width).clamp_range(range.clone())).changed() {
+                self.set_width(width);
+                edited = true;
+            }
+            ui.end_row();
+
+            ui.label("Height");
+            let mut height = self.height;
+            if ui.add(egui::DragValue::new(&mut height).clamp_range(range)).changed() {
+                self.set_height(height);
+                edited = true;
+            }
+            ui.end_row();
+
+            ui.label("");
+            let mut locked = self.locked_aspect.is_some();
+            if ui.checkbox(&mut locked, "Lock aspect ratio").changed() {
+                self.lock_aspect(locked);
+            }
+            ui.end_row();
+
+            ui.label("Samples/px");
+            let samples = egui::DragValue::new(&mut self.samples_per_pixel)
+                .clamp_range(1..=MAX_SAMPLES)
+                .speed(1.0);
+            edited |= ui.add(samples).changed();
+            ui.end_row();
+
+            ui.label("Max depth");
+            let depth = egui::DragValue::new(&mut self.max_depth).clamp_range(1..=MAX_DEPTH);
+            edited |= ui.add(depth).changed();
+            ui.end_row();
+
+            ui.label("Preview scale");
+            egui::ComboBox::from_id_source("preview_scale")
+                .selected_text(self.preview_scale.name())
+                .show_ui(ui, |ui| {
+                    for scale in PreviewScale::ALL {
+                        ui.selectable_value(&mut self.preview_scale, scale, scale.name());
+                    }
+                })
+                .response
+                .on_hover_text("Resolution of the renders while the camera moves");
+            ui.end_row();
+
+            ui.label("Threads");
+            let threads =
+                egui::DragValue::new(&mut self.threads).clamp_range(1..=available_threads());
+            ui.add(threads).on_hover_text("Fewer threads keep the machine usable while rendering");
+            ui.end_row();
+
+            ui.label("Integrator");
+            egui::ComboBox::from_id_source("integrator")
+                .selected_text(self.integrator.name())
+                .show_ui(ui, |ui| {
+                    for integrator in Integrator::ALL {
+                        ui.selectable_value(&mut self.integrator, integrator, integrator.name());
+                    }
+                })
+                .response
+                .on_hover_text("Wavefront traces the rays of a row one bounce at a time");
+            ui.end_row();
+        });
+
+        if edited {
+            self.preset = None;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::config::Config;
+    use crate::settings::{PreviewScale, RenderSettings, MAX_DEPTH, MAX_RESOLUTION};
+    use eframe::Storage;
+    use rt1we_renderer::preset::QualityPreset;
+    use rt1we_renderer::progressive::available_threads;
+    use std::collections::HashMap;
+
+    /// Storage in memory, in place of the file eframe writes.
+    #[derive(Default)]
+    struct MemoryStorage(HashMap<String, String>);
+
+    impl Storage for MemoryStorage {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_string(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn test_clamp() {
+        let mut settings = RenderSettings {
+            width: 0,
+            height: 100_000,
+            samples_per_pixel: 0,
+            max_depth: 1000,
+            threads: 0,
+            locked_aspect: Some(f32::NAN),
+            ..Default::default()
+        };
+        settings.clamp();
+        assert_eq!((settings.width, settings.height), (2, MAX_RESOLUTION));
+        assert_eq!((settings.samples_per_pixel, settings.max_depth), (1, MAX_DEPTH));
+        assert_eq!(settings.threads, 1);
+        assert_eq!(settings.locked_aspect, None);
+
+        // The settings of a machine with more cores use all of them on this one.
+        settings.threads = available_threads() as u32 + 1;
+        settings.clamp();
+        assert_eq!(settings.threads, available_threads() as u32);
+    }
+
+    #[test]
+    fn test_locked_aspect() {
+        let mut settings = RenderSettings::default();
+        settings.set_width(320);
+        assert_eq!((settings.width, settings.height), (320, 120));
+
+        settings.lock_aspect(true);
+        settings.set_width(640);
+        assert_eq!((settings.width, settings.height), (640, 240));
+        settings.set_height(90);
+        assert_eq!((settings.width, settings.height), (240, 90));
+        // The resolution stays in the range of the controls.
+        settings.set_width(2);
+        assert_eq!((settings.width, settings.height), (2, 2));
+
+        // A new resolution, from a preset or the list, is the new aspect ratio.
+        settings.set_resolution(400, 100);
+        settings.set_height(50);
+        assert_eq!((settings.width, settings.height), (200, 50));
+        settings.apply_preset(QualityPreset::Preview);
+        let (width, height) = (settings.width, settings.height);
+        assert_eq!(settings.locked_aspect, Some(width as f32 / height as f32));
+
+        settings.lock_aspect(false);
+        settings.set_width(100);
+        assert_eq!((settings.width, settings.height), (100, height));
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let mut config = Config::default();
+        config.settings.set_resolution(640, 360);
+        config.settings.lock_aspect(true);
+        config.settings.samples_per_pixel = 16;
+        config.settings.denoise = true;
+        config.settings.preview_scale = PreviewScale::Quarter;
+        config.camera.yaw = 30.0;
+        config.output_dir = Some("renders".into());
+        config.recent_scenes = vec!["cornell.json".into(), "demo.json".into()];
+
+        let mut storage = MemoryStorage::default();
+        config.save(&mut storage);
+        let loaded = Config::load(Some(&storage));
+        let settings = &loaded.settings;
+        assert_eq!((settings.width, settings.height), (640, 360));
+        assert_eq!(settings.locked_aspect, Some(640.0 / 360.0));
+        assert_eq!((settings.samples_per_pixel, settings.denoise), (16, true));
+        assert_eq!(settings.preview_scale, PreviewScale::Quarter);
+        assert_eq!(loaded.camera.yaw, 30.0);
+        assert_eq!(loaded.output_dir, config.output_dir);
+        assert_eq!(loaded.recent_scenes, config.recent_scenes);
+
+        // Entries which are missing, or out of range, are brought back to usable values.
+        storage.set_string(eframe::APP_KEY, "(settings: (width: 0, max_depth: 500))".to_string());
+        let loaded = Config::load(Some(&storage));
+        assert_eq!((loaded.settings.width, loaded.settings.height), (2, 120));
+        assert_eq!(loaded.settings.max_depth, MAX_DEPTH);
+        assert!(loaded.recent_scenes.is_empty());
+        assert_eq!(Config::load(None).settings.samples_per_pixel, 100);
+    }
+}
@@ -0,0 +1,278 @@
+//! Timeline: camera keyframes, preview playback and image sequence rendering.
+use eframe::egui;
+use rt1we_renderer::animation::{AnimationSettings, CameraTrack, ViewKeyframe};
+use rt1we_renderer::image::flipv;
+use rt1we_renderer::imageio::imwrite;
+use rt1we_renderer::output::{expand_template, prepare_output, OverwritePolicy, TemplateContext};
+use rt1we_renderer::view::OrbitView;
+use std::path::Path;
+
+use crate::postprocess::{Frame, PostSettings};
+use crate::worker::{JobUpdate, RenderJob, RenderRequest};
+
+/// File names of the rendered frames, in the chosen directory.
+const SEQUENCE_TEMPLATE: &str = "frame-{frame}.png";
+const TRACK_HEIGHT: f32 = 24.0;
+/// Distance from a keyframe marker within which a click selects it, in points.
+const MARKER_RADIUS: f32 = 6.0;
+
+/// What the timeline asks of the app.
+pub enum TimelineAction {
+    None,
+    /// Move the camera, and preview the new view.
+    Seek(OrbitView),
+    /// Render the animation, see [`Timeline::start_sequence`].
+    RenderSequence,
+}
+
+/// The frames of an animation, rendered one after the other and written as they finish.
+struct SequenceRender {
+    request: RenderRequest,
+    views: Vec<OrbitView>,
+    outputs: Vec<String>,
+    /// Index of the frame being rendered.
+    frame: usize,
+    job: RenderJob,
+}
+
+/// Camera keyframes along a timeline.
+pub struct Timeline {
+    track: CameraTrack,
+    /// Current time, in seconds.
+    time: f32,
+    /// Length of the timeline, in seconds.
+    length: f32,
+    fps: f32,
+    playing: bool,
+    selected: Option<usize>,
+    sequence: Option<SequenceRender>,
+    /// Outcome of the last sequence render.
+    status: Option<Result<String, String>>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline {
+            track: CameraTrack::default(),
+            time: 0.0,
+            length: 4.0,
+            fps: 24.0,
+            playing: false,
+            selected: None,
+            sequence: None,
+            status: None,
+        }
+    }
+}
+
+impl Timeline {
+    fn animation(&self) -> AnimationSettings {
+        AnimationSettings::new(self.fps, self.track.duration())
+    }
+
+    fn seek(&mut self, time: f32) -> TimelineAction {
+        self.time = time.clamp(0.0, self.length);
+        self.track.at(self.time).map_or(TimelineAction::None, TimelineAction::Seek)
+    }
+
+    /// Advance the playback.
+    ///
+    /// # Returns
+    /// The view to preview, when playback reached a new frame.
+    pub fn play(&mut self, ctx: &egui::Context) -> Option<OrbitView> {
+        if !self.playing {
+            return None;
+        }
+        let frame = (self.time * self.fps) as usize;
+        self.time += ctx.input(|i| i.stable_dt);
+        if self.time >= self.track.duration() {
+            self.time = self.track.duration();
+            self.playing = false;
+        }
+        ctx.request_repaint();
+        if (self.time * self.fps) as usize == frame && self.playing {
+            return None;
+        }
+        self.track.at(self.time)
+    }
+
+    /// Show the timeline controls.
+    ///
+    /// # Arguments
+    /// - `view` - The current camera, for new keyframes.
+    pub fn show(&mut self, ui: &mut egui::Ui, view: &OrbitView) -> TimelineAction {
+        let mut action = TimelineAction::None;
+        ui.horizontal(|ui| {
+            let can_play = self.track.keyframes().len() >= 2;
+            let label = if self.playing { "Pause" } else { "Play" };
+            if ui.add_enabled(can_play, egui::Button::new(label)).clicked() {
+                self.playing = !self.playing;
+                if self.time >= self.track.duration() {
+                    self.time = 0.0;
+                }
+            }
+            if ui.button("Start").clicked() {
+                action = self.seek(0.0);
+            }
+            let slider = egui::Slider::new(&mut self.time, 0.0..=self.length).suffix(" s");
+            if ui.add(slider).changed() {
+                action = self.seek(self.time);
+            }
+            ui.label("Length");
+            // Keyframes stay on the timeline.
+            let min_length = self.track.duration().max(0.1);
+            let length = egui::DragValue::new(&mut self.length).clamp_range(min_length..=600.0);
+            ui.add(length.suffix(" s"));
+            ui.label("FPS");
+            ui.add(egui::DragValue::new(&mut self.fps).clamp_range(1.0..=120.0));
+        });
+
+        if let Some(time) = self.show_track(ui) {
+            action = self.seek(time);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Add keyframe").on_hover_text("Keep the current camera here").clicked() {
+                self.track.insert(ViewKeyframe { time: self.time, view: *view });
+            }
+            if let Some(k) = self.selected {
+                if ui.button("Remove keyframe").clicked() {
+                    self.track.remove(k);
+                    self.selected = None;
+                }
+            }
+
+            let ready = self.track.keyframes().len() >= 2 && self.sequence.is_none();
+            let frames = self.animation().frame_count();
+            let render = egui::Button::new(format!("Render sequence ({frames} frames)…"));
+            if ui.add_enabled(ready, render).clicked() {
+                action = TimelineAction::RenderSequence;
+            }
+            if let Some(sequence) = &self.sequence {
+                ui.spinner();
+                ui.label(format!("Frame {}/{}", sequence.frame + 1, sequence.views.len()));
+                if ui.button("Cancel").clicked() {
+                    self.sequence = None;
+                    self.status = Some(Err("sequence render cancelled".to_string()));
+                }
+            }
+            match &self.status {
+                Some(Ok(msg)) => {
+                    ui.label(msg);
+                }
+                Some(Err(msg)) => {
+                    ui.colored_label(egui::Color32::RED, msg);
+                }
+                None => {}
+            }
+        });
+        action
+    }
+
+    /// Draw the keyframes and the current time, handle clicks and drags.
+    ///
+    /// # Returns
+    /// The time clicked or dragged to, if any.
+    fn show_track(&mut self, ui: &mut egui::Ui) -> Option<f32> {
+        let size = egui::vec2(ui.available_width(), TRACK_HEIGHT);
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+        let x_of = |t: f32| rect.left() + t / self.length * rect.width();
+        for (k, keyframe) in self.track.keyframes().iter().enumerate() {
+            let center = egui::pos2(x_of(keyframe.time), rect.center().y);
+            let color = if self.selected == Some(k) {
+                egui::Color32::YELLOW
+            } else {
+                ui.visuals().text_color()
+            };
+            painter.circle_filled(center, MARKER_RADIUS - 2.0, color);
+        }
+        let x = x_of(self.time);
+        painter.vline(x, rect.y_range(), egui::Stroke::new(2.0, egui::Color32::LIGHT_BLUE));
+
+        let pos = response.interact_pointer_pos()?;
+        if !(response.clicked() || response.dragged()) {
+            return None;
+        }
+        if response.clicked() {
+            self.selected = self
+                .track
+                .keyframes()
+                .iter()
+                .position(|k| (x_of(k.time) - pos.x).abs() <= MARKER_RADIUS);
+            if let Some(k) = self.selected {
+                return Some(self.track.keyframes()[k].time);
+            }
+        }
+        Some((pos.x - rect.left()) / rect.width() * self.length)
+    }
+
+    /// Start rendering the animation, one file per frame.
+    ///
+    /// # Arguments
+    /// - `request` - Settings of the frames, the camera of each frame comes from the timeline.
+    /// - `dir` - Directory the frames are written to, existing frames are replaced.
+    /// - `ctx` - The UI context, repainted whenever the render progresses.
+    pub fn start_sequence(&mut self, request: RenderRequest, dir: &Path, ctx: &egui::Context) {
+        let views = self.track.sample(&self.animation());
+        let template = dir.join(SEQUENCE_TEMPLATE).to_string_lossy().into_owned();
+        let context = TemplateContext::now(0, None);
+        let outputs: Result<Vec<String>, _> = (0..views.len())
+            .map(|frame| expand_template(&template, &TemplateContext { frame, ..context }))
+            .collect();
+        let outputs = match outputs {
+            Ok(outputs) => outputs,
+            Err(e) => {
+                self.status = Some(Err(e.to_string()));
+                return;
+            }
+        };
+
+        let job =
+            RenderJob::spawn(RenderRequest { view: views[0], ..request.clone() }, ctx.clone());
+        self.status = None;
+        self.sequence = Some(SequenceRender { request, views, outputs, frame: 0, job });
+    }
+
+    /// Write the frames of the sequence render as they finish, and start the next ones.
+    ///
+    /// # Arguments
+    /// - `ctx` - The UI context.
+    /// - `post` - Post-processing of the written frames.
+    pub fn poll(&mut self, ctx: &egui::Context, post: &PostSettings) {
+        let Some(sequence) = &mut self.sequence else {
+            return;
+        };
+        let result = match sequence.job.poll() {
+            Ok(JobUpdate::Pending) | Ok(JobUpdate::Refined(_)) => return,
+            Ok(JobUpdate::Done(hdr)) => {
+                let frame =
+                    Frame::new(hdr, sequence.job.samples(), sequence.job.max_depth(), true, post);
+                let output = &sequence.outputs[sequence.frame];
+                prepare_output(output, OverwritePolicy::Overwrite)
+                    .and_then(|path| imwrite(&path.to_string_lossy(), &flipv(&frame.image)))
+                    .map_err(|e| e.to_string())
+            }
+            Ok(JobUpdate::Cancelled(_)) => Err("sequence render cancelled".to_string()),
+            Err(msg) => Err(msg),
+        };
+
+        if let Err(msg) = result {
+            self.status = Some(Err(msg));
+            self.sequence = None;
+            return;
+        }
+        sequence.frame += 1;
+        if sequence.frame == sequence.views.len() {
+            let msg = format!("Rendered {} frames", sequence.views.len());
+            self.status = Some(Ok(msg));
+            self.sequence = None;
+            return;
+        }
+        let request =
+            RenderRequest { view: sequence.views[sequence.frame], ..sequence.request.clone() };
+        sequence.job = RenderJob::spawn(request, ctx.clone());
+    }
+}
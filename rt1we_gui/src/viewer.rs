@@ -0,0 +1,207 @@
+//! Rendered image display, with scroll-to-zoom and drag-to-pan.
+//!
+//! In camera mode, the same gestures move the camera instead of the image.
+use eframe::egui;
+use egui::{
+    Color32, ColorImage, PointerButton, Pos2, Rect, Sense, Stroke, TextureHandle, TextureOptions,
+    Vec2,
+};
+use rt1we_renderer::image::ImageRGBA;
+
+/// Zoom factor applied for each scrolled point.
+const ZOOM_SPEED: f32 = 0.002;
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 64.0;
+
+/// Camera moves requested on the image, in screen points.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Navigation {
+    /// Primary button drag.
+    pub orbit: Vec2,
+    /// Middle button drag.
+    pub pan: Vec2,
+    /// Scroll, positive upwards.
+    pub dolly: f32,
+}
+
+/// What happened in the viewer during a frame.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ViewerResponse {
+    /// The camera moves, in camera mode, when there were any.
+    pub navigation: Option<Navigation>,
+    /// The clicked pixel, from the top left of the image.
+    pub clicked: Option<(usize, usize)>,
+}
+
+/// Shows an image in a pannable, zoomable area.
+pub struct ImageViewer {
+    texture: Option<TextureHandle>,
+    /// Another image shown over the left part of the image, up to a fraction of its width.
+    wipe: Option<(TextureHandle, f32)>,
+    /// Screen points per image pixel.
+    zoom: f32,
+    /// Offset of the image center from the area center, in points.
+    offset: Vec2,
+    /// Show one image pixel per screen pixel.
+    pixel_perfect: bool,
+    /// Fit the image in the area on the next frame.
+    fit_requested: bool,
+    /// Gestures move the camera rather than the image.
+    camera_mode: bool,
+}
+
+impl Default for ImageViewer {
+    fn default() -> Self {
+        ImageViewer {
+            texture: None,
+            wipe: None,
+            zoom: 1.0,
+            offset: Vec2::ZERO,
+            pixel_perfect: false,
+            fit_requested: true,
+            camera_mode: true,
+        }
+    }
+}
+
+impl ImageViewer {
+    /// Replace the displayed image, keeping the current view.
+    pub fn set_image(&mut self, ctx: &egui::Context, im: &ImageRGBA) {
+        let image = color_image(im);
+
+        match self.texture.as_mut() {
+            Some(texture) if texture.size() == [im.width, im.height] => {
+                texture.set(image, TextureOptions::NEAREST)
+            }
+            previous => {
+                // Keep the displayed size when the resolution changes, as for preview renders.
+                match previous {
+                    Some(texture) => self.zoom *= texture.size()[0] as f32 / im.width as f32,
+                    None => self.fit_requested = true,
+                }
+                self.texture = Some(ctx.load_texture("render", image, TextureOptions::NEAREST));
+            }
+        }
+    }
+
+    /// Show another image left of a vertical split, `None` to show the image alone.
+    ///
+    /// # Arguments
+    /// - `wipe` - The other image, stretched to the size of the image, and the position of the
+    ///   split as a fraction of the image width.
+    pub fn set_wipe(&mut self, ctx: &egui::Context, wipe: Option<(&ImageRGBA, f32)>) {
+        self.wipe = wipe.map(|(im, split)| {
+            let texture = ctx.load_texture("wipe", color_image(im), TextureOptions::NEAREST);
+            (texture, split)
+        });
+    }
+
+    pub fn has_image(&self) -> bool {
+        self.texture.is_some()
+    }
+
+    /// View controls: camera mode, fit, 1:1 and the zoom readout.
+    pub fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Fit").clicked() {
+                self.pixel_perfect = false;
+                self.fit_requested = true;
+            }
+            if ui.checkbox(&mut self.pixel_perfect, "1:1").changed() && self.pixel_perfect {
+                self.offset = Vec2::ZERO;
+            }
+            ui.label(format!("{:.0}%", self.zoom * 100.0));
+        });
+        ui.checkbox(&mut self.camera_mode, "Move the camera")
+            .on_hover_text("Drag to orbit, middle-drag to pan, scroll to dolly");
+        ui.label("Click a pixel to inspect it");
+    }
+
+    /// Draw the image in all the available space and handle zoom and pan.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> ViewerResponse {
+        let Some(texture) = self.texture.clone() else {
+            ui.centered_and_justified(|ui| ui.label("No image rendered yet"));
+            return ViewerResponse::default();
+        };
+        let image_size = texture.size_vec2();
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+        let navigation = if self.camera_mode { navigation(ui, &response) } else { None };
+
+        if self.fit_requested {
+            self.zoom = (rect.width() / image_size.x).min(rect.height() / image_size.y);
+            self.offset = Vec2::ZERO;
+            self.fit_requested = false;
+        }
+        if self.pixel_perfect {
+            self.zoom = 1.0 / ui.ctx().pixels_per_point();
+        }
+
+        // In camera mode, the gestures belong to the camera.
+        if response.dragged() && !self.camera_mode {
+            self.offset += response.drag_delta();
+        }
+        if let Some(cursor) = response.hover_pos().filter(|_| !self.camera_mode) {
+            let scroll = ui.input(|i| i.scroll_delta.y);
+            if scroll != 0.0 {
+                self.pixel_perfect = false;
+                self.zoom_around(rect.center(), cursor, (scroll * ZOOM_SPEED).exp());
+            }
+        }
+
+        let center = rect.center() + self.offset;
+        let image_rect = Rect::from_center_size(center, image_size * self.zoom);
+        let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+        let painter = ui.painter_at(rect);
+        painter.image(texture.id(), image_rect, uv, Color32::WHITE);
+        if let Some((other, split)) = &self.wipe {
+            let x = image_rect.left() + split * image_rect.width();
+            let left = Rect::from_min_max(image_rect.min, Pos2::new(x, image_rect.bottom()));
+            let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(*split, 1.0));
+            painter.image(other.id(), left, uv, Color32::WHITE);
+            painter.vline(x, image_rect.y_range(), Stroke::new(1.0, Color32::WHITE));
+        }
+
+        let clicked = response
+            .interact_pointer_pos()
+            .filter(|pos| response.clicked() && image_rect.contains(*pos))
+            .map(|pos| {
+                let pixel = (pos - image_rect.min) / self.zoom;
+                let [w, h] = texture.size();
+                ((pixel.x as usize).min(w - 1), (pixel.y as usize).min(h - 1))
+            });
+        ViewerResponse { navigation, clicked }
+    }
+
+    /// Zoom by `factor`, keeping the image point under `cursor` in place.
+    fn zoom_around(&mut self, area_center: Pos2, cursor: Pos2, factor: f32) {
+        let zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let image_point = (cursor - area_center - self.offset) / self.zoom;
+        self.offset = cursor - area_center - image_point * zoom;
+        self.zoom = zoom;
+    }
+}
+
+/// Camera moves from the gestures on an area.
+pub fn navigation(ui: &egui::Ui, response: &egui::Response) -> Option<Navigation> {
+    let mut navigation = Navigation::default();
+    if response.dragged_by(PointerButton::Primary) {
+        navigation.orbit = response.drag_delta();
+    }
+    if response.dragged_by(PointerButton::Middle) {
+        navigation.pan = response.drag_delta();
+    }
+    if response.hovered() {
+        navigation.dolly = ui.input(|i| i.scroll_delta.y);
+    }
+    let moved =
+        navigation.orbit != Vec2::ZERO || navigation.pan != Vec2::ZERO || navigation.dolly != 0.0;
+    moved.then_some(navigation)
+}
+
+/// Convert an image to a texture.
+pub fn color_image(im: &ImageRGBA) -> ColorImage {
+    // Rendered images have their origin at the bottom left, textures at the top left.
+    let row = im.width * 4;
+    let pixels: Vec<u8> = im.pixels.chunks(row).rev().flatten().copied().collect();
+    ColorImage::from_rgba_unmultiplied([im.width, im.height], &pixels)
+}
@@ -0,0 +1,265 @@
+//! Background rendering, so that the UI stays responsive while a frame renders.
+//!
+//! The frame is rendered progressively, and the linear image is sent back after each pass,
+//! post-processing is left to the UI. The render can be paused between passes, and cancelled
+//! at any time.
+use eframe::egui;
+use rt1we_renderer::cancel::CancellationToken;
+use rt1we_renderer::image::ImageRGBF32;
+use rt1we_renderer::progressive::ProgressiveRenderer;
+use rt1we_renderer::render::Scene;
+use rt1we_renderer::view::OrbitView;
+use rt1we_renderer::wavefront::Integrator;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Passes taking less time than this get more samples, to limit the cost of sending images.
+const MIN_PASS_TIME: Duration = Duration::from_millis(100);
+/// Passes taking more time than this get fewer samples, so that pausing stays responsive.
+const MAX_PASS_TIME: Duration = Duration::from_millis(400);
+/// How often a paused render checks for cancellation.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Settings of a single frame render.
+#[derive(Clone)]
+pub struct RenderRequest {
+    pub scene: Scene,
+    pub width: usize,
+    pub height: usize,
+    pub max_depth: usize,
+    pub samples_per_pixel: usize,
+    pub view: OrbitView,
+    /// Number of threads tracing the frame.
+    pub threads: usize,
+    pub integrator: Integrator,
+}
+
+/// Messages sent by the render thread.
+enum WorkerMessage {
+    /// The image after a pass, with the number of samples per pixel so far and the camera
+    /// rays traced per second during the pass.
+    Pass(ImageRGBF32, usize, f64),
+    Done(ImageRGBF32),
+    /// The render was cancelled, with the image as it was.
+    Cancelled(ImageRGBF32),
+    /// The render could not start.
+    Failed(String),
+}
+
+/// Messages sent to the render thread.
+enum Control {
+    Pause,
+    Resume,
+}
+
+/// Result of [`RenderJob::poll`], with linear images to post-process.
+pub enum JobUpdate {
+    /// Nothing new since the last poll.
+    Pending,
+    /// A more refined image.
+    Refined(ImageRGBF32),
+    /// The final image.
+    Done(ImageRGBF32),
+    /// The render stopped early, with the samples accumulated until then.
+    Cancelled(ImageRGBF32),
+}
+
+/// A render running on its own thread.
+pub struct RenderJob {
+    rx: Receiver<WorkerMessage>,
+    control: Sender<Control>,
+    token: CancellationToken,
+    samples: usize,
+    samples_per_pixel: usize,
+    max_depth: usize,
+    throughput: Option<f64>,
+    started: Instant,
+    /// Time spent rendering before the current pause, or until now.
+    active: Duration,
+    paused_at: Option<Instant>,
+}
+
+/// Render loop of the worker thread.
+fn run(
+    r: RenderRequest, tx: Sender<WorkerMessage>, control: Receiver<Control>,
+    token: CancellationToken, ctx: egui::Context,
+) {
+    let send = |message| {
+        let sent = tx.send(message).is_ok();
+        ctx.request_repaint();
+        sent
+    };
+
+    let renderer = ProgressiveRenderer::with_view(r.scene, r.width, r.height, r.max_depth, &r.view)
+        .and_then(|mut renderer| renderer.set_threads(r.threads).map(|_| renderer));
+    let mut renderer = match renderer {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            send(WorkerMessage::Failed(e.to_string()));
+            return;
+        }
+    };
+    renderer.set_integrator(r.integrator);
+    let mut pass = 1;
+    let mut paused = false;
+    while renderer.samples() < r.samples_per_pixel {
+        while let Ok(c) = control.try_recv() {
+            paused = matches!(c, Control::Pause);
+        }
+        if paused {
+            match control.recv_timeout(PAUSE_POLL_INTERVAL) {
+                Ok(c) => paused = matches!(c, Control::Pause),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            if !token.is_cancelled() {
+                continue;
+            }
+        }
+
+        let start = Instant::now();
+        let samples = pass.min(r.samples_per_pixel - renderer.samples());
+        let complete = renderer.step(samples, &token);
+        if !complete || token.is_cancelled() {
+            send(WorkerMessage::Cancelled(renderer.hdr_image()));
+            return;
+        }
+        let elapsed = start.elapsed();
+        let throughput = (r.width * r.height * samples) as f64 / elapsed.as_secs_f64().max(1e-6);
+        if elapsed < MIN_PASS_TIME {
+            pass = (pass * 2).min(64);
+        } else if elapsed > MAX_PASS_TIME {
+            pass = (pass / 2).max(1);
+        }
+
+        if renderer.samples() < r.samples_per_pixel
+            && !send(WorkerMessage::Pass(renderer.hdr_image(), renderer.samples(), throughput))
+        {
+            return;
+        }
+    }
+
+    send(WorkerMessage::Done(renderer.hdr_image()));
+}
+
+impl RenderJob {
+    /// Start rendering on a new thread.
+    ///
+    /// # Arguments
+    /// - `request` - What to render.
+    /// - `ctx` - The UI context, repainted whenever the render progresses.
+    pub fn spawn(request: RenderRequest, ctx: egui::Context) -> Self {
+        let (tx, rx) = channel();
+        let (control_tx, control_rx) = channel();
+        let token = CancellationToken::new();
+        let worker_token = token.clone();
+        let (samples_per_pixel, max_depth) = (request.samples_per_pixel, request.max_depth);
+        thread::spawn(move || run(request, tx, control_rx, worker_token, ctx));
+
+        RenderJob {
+            rx,
+            control: control_tx,
+            token,
+            samples: 0,
+            samples_per_pixel,
+            max_depth,
+            throughput: None,
+            started: Instant::now(),
+            active: Duration::ZERO,
+            paused_at: None,
+        }
+    }
+
+    /// Process the messages of the render thread.
+    ///
+    /// # Returns
+    /// The most recent image, or an error if the render could not start or its thread died.
+    pub fn poll(&mut self) -> Result<JobUpdate, String> {
+        let mut update = JobUpdate::Pending;
+        loop {
+            match self.rx.try_recv() {
+                Ok(WorkerMessage::Pass(hdr, samples, throughput)) => {
+                    self.samples = samples;
+                    self.throughput = Some(throughput);
+                    update = JobUpdate::Refined(hdr);
+                }
+                Ok(WorkerMessage::Done(hdr)) => {
+                    self.samples = self.samples_per_pixel;
+                    return Ok(JobUpdate::Done(hdr));
+                }
+                Ok(WorkerMessage::Cancelled(hdr)) => return Ok(JobUpdate::Cancelled(hdr)),
+                Ok(WorkerMessage::Failed(msg)) => return Err(msg),
+                Err(TryRecvError::Empty) => return Ok(update),
+                Err(TryRecvError::Disconnected) => {
+                    return Err("the render thread stopped unexpectedly".to_string())
+                }
+            }
+        }
+    }
+
+    /// Stop sampling after the current pass, keeping the accumulated samples.
+    pub fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.active += self.started.elapsed();
+            self.paused_at = Some(Instant::now());
+            let _ = self.control.send(Control::Pause);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.paused_at.take().is_some() {
+            self.started = Instant::now();
+            let _ = self.control.send(Control::Resume);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Stop the render, [`RenderJob::poll`] then returns the partial image.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// Number of samples per pixel of the latest image.
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Number of samples per pixel of the final image.
+    pub fn samples_per_pixel(&self) -> usize {
+        self.samples_per_pixel
+    }
+
+    /// Maximum number of bounces of the rays.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Camera rays traced per second during the latest pass, `None` before the first one.
+    pub fn throughput(&self) -> Option<f64> {
+        self.throughput
+    }
+
+    /// Fraction of the samples rendered so far, in `[0;1]`.
+    pub fn progress(&self) -> f32 {
+        self.samples as f32 / self.samples_per_pixel.max(1) as f32
+    }
+
+    /// Time spent rendering, pauses excluded.
+    pub fn elapsed(&self) -> Duration {
+        match self.paused_at {
+            Some(_) => self.active,
+            None => self.active + self.started.elapsed(),
+        }
+    }
+}
+
+impl Drop for RenderJob {
+    /// Abandoned renders stop instead of running in the background.
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
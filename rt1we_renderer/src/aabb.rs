@@ -0,0 +1,142 @@
+//! Axis-aligned bounding boxes.
+use crate::geometry::{Point, Vec3};
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+/// Axis-aligned bounding box, defined by its two extreme corners.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    /// An empty box, which can be grown with [`Aabb::surrounding`].
+    pub const EMPTY: Aabb = Aabb {
+        min: Point { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY },
+        max: Point { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY },
+    };
+
+    /// Create a box from any two opposite corners.
+    pub fn new(a: Point, b: Point) -> Self {
+        Aabb {
+            min: Point::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Point::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// Smallest box containing both boxes.
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    /// Size of the box along each axis.
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn centroid(&self) -> Point {
+        0.5 * (self.min + self.max)
+    }
+
+    /// Index of the longest axis: 0 for x, 1 for y, 2 for z.
+    pub fn longest_axis(&self) -> usize {
+        let e = self.extent();
+        if e.x >= e.y && e.x >= e.z {
+            0
+        } else if e.y >= e.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Check if a ray goes through the box within the distances `ray_t`, using the slab method.
+    pub fn hit(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.clip(r, ray_t).is_some()
+    }
+
+    /// The distances of `ray_t` along which a ray goes through the box, if any.
+    pub fn clip(&self, r: &Ray, mut ray_t: Interval) -> Option<Interval> {
+        for axis in 0..3 {
+            let inv_d = 1.0 / axis_of(&r.dir, axis);
+            let mut t0 = (axis_of(&self.min, axis) - axis_of(&r.orig, axis)) * inv_d;
+            let mut t1 = (axis_of(&self.max, axis) - axis_of(&r.orig, axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > ray_t.min {
+                ray_t.min = t0;
+            }
+            if t1 < ray_t.max {
+                ray_t.max = t1;
+            }
+            if ray_t.max <= ray_t.min {
+                return None;
+            }
+        }
+        Some(ray_t)
+    }
+}
+
+/// Component of a vector along an axis index.
+pub fn axis_of(v: &Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::aabb::Aabb;
+    use crate::geometry::{Point, Vec3};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    #[test]
+    fn test_new_orders_corners() {
+        let b = Aabb::new(Point::new(1.0, -1.0, 2.0), Point::new(-1.0, 1.0, 0.0));
+        assert_eq!(b.min, Point::new(-1.0, -1.0, 0.0));
+        assert_eq!(b.max, Point::new(1.0, 1.0, 2.0));
+        assert_eq!(b.longest_axis(), 0);
+    }
+
+    #[test]
+    fn test_surrounding_box() {
+        let a = Aabb::new(Point::ZERO, Point::new(1.0, 1.0, 1.0));
+        let b = Aabb::new(Point::new(2.0, -1.0, 0.0), Point::new(3.0, 0.0, 4.0));
+
+        let s = Aabb::EMPTY.surrounding(&a).surrounding(&b);
+        assert_eq!(s.min, Point::new(0.0, -1.0, 0.0));
+        assert_eq!(s.max, Point::new(3.0, 1.0, 4.0));
+        assert_eq!(s.longest_axis(), 2);
+    }
+
+    #[test]
+    fn test_ray_box_intersection() {
+        let b = Aabb::new(Point::new(-1.0, -1.0, -3.0), Point::new(1.0, 1.0, -2.0));
+
+        let towards = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+        assert!(b.hit(&towards, Interval::new(0.0, f32::INFINITY)));
+        assert!(!b.hit(&towards, Interval::new(0.0, 1.0)));
+
+        let away = Ray::new(Point::ZERO, Vec3::UNIT_Z);
+        assert!(!b.hit(&away, Interval::new(0.0, f32::INFINITY)));
+
+        let beside = Ray::new(Point::new(2.0, 0.0, 0.0), -Vec3::UNIT_Z);
+        assert!(!b.hit(&beside, Interval::new(0.0, f32::INFINITY)));
+    }
+}
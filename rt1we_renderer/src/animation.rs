@@ -0,0 +1,452 @@
+//! Animation timing functions and data structures.
+//!
+//! Trajectories are authored in seconds, and only turned into frames when sampled with
+//! [`AnimationSettings`], so changing the output frame rate does not require touching the
+//! keyframes.
+//!
+//! Between two keyframes, the [`Easing`] of a trajectory sets how the camera speeds up and slows
+//! down, and its [`Interpolation`] whether it goes straight or follows a curve through all the
+//! keyframes.
+use crate::geometry::{lerp, Point};
+use crate::view::OrbitView;
+use std::fmt;
+use std::str::FromStr;
+
+/// Timing of an animation.
+#[derive(Debug, Copy, Clone)]
+pub struct AnimationSettings {
+    /// Output frames per second.
+    pub fps: f32,
+    /// Output duration, in seconds.
+    pub duration: f32,
+    /// Scene seconds elapsed per output second. Use `< 1.0` for slow motion.
+    pub time_scale: f32,
+}
+
+impl AnimationSettings {
+    pub fn new(fps: f32, duration: f32) -> Self {
+        AnimationSettings { fps, duration, time_scale: 1.0 }
+    }
+
+    /// Number of frames to render. Always at least one, so a zero duration renders a still.
+    pub fn frame_count(&self) -> usize {
+        ((self.duration * self.fps).round() as usize).max(1)
+    }
+
+    /// Scene time, in seconds, at which a frame is rendered.
+    ///
+    /// # Arguments
+    /// - `frame` - Frame index.
+    pub fn frame_time(&self, frame: usize) -> f32 {
+        (frame as f32 / self.fps) * self.time_scale
+    }
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        AnimationSettings::new(24.0, 0.0)
+    }
+}
+
+/// How the speed changes between two keyframes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Easing {
+    /// Constant speed.
+    #[default]
+    Linear,
+    /// Start at rest, and speed up.
+    EaseIn,
+    /// Slow down, to stop at the next keyframe.
+    EaseOut,
+    /// Start and stop at rest: the camera stops at each keyframe.
+    EaseInOut,
+}
+
+impl Easing {
+    pub const ALL: [Easing; 4] =
+        [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::EaseIn => "ease-in",
+            Easing::EaseOut => "ease-out",
+            Easing::EaseInOut => "ease-in-out",
+        }
+    }
+
+    /// Fraction of the way done at a fraction `u` of the time, both in `[0;1]`.
+    pub fn apply(&self, u: f32) -> f32 {
+        match self {
+            Easing::Linear => u,
+            Easing::EaseIn => u * u,
+            Easing::EaseOut => 1.0 - (1.0 - u) * (1.0 - u),
+            Easing::EaseInOut => u * u * (3.0 - 2.0 * u),
+        }
+    }
+}
+
+impl fmt::Display for Easing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Easing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Easing::ALL.into_iter().find(|e| e.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = Easing::ALL.iter().map(|e| e.name()).collect();
+            format!("unknown easing '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+/// Path between the positions of the keyframes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Straight lines, turning sharply at the keyframes.
+    #[default]
+    Linear,
+    /// A Catmull-Rom spline: a smooth curve through all the keyframes.
+    CatmullRom,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 2] = [Interpolation::Linear, Interpolation::CatmullRom];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Interpolation::Linear => "linear",
+            Interpolation::CatmullRom => "catmull-rom",
+        }
+    }
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Interpolation::ALL.into_iter().find(|i| i.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = Interpolation::ALL.iter().map(|i| i.name()).collect();
+            format!("unknown interpolation '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+/// A position at a given time, in seconds.
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe {
+    pub time: f32,
+    pub position: Point,
+}
+
+/// Positions interpolated between keyframes.
+#[derive(Debug, Clone)]
+pub struct Trajectory {
+    keyframes: Vec<Keyframe>,
+    interpolation: Interpolation,
+    easing: Easing,
+}
+
+impl Trajectory {
+    /// Create a trajectory, going straight at constant speed between the keyframes. Keyframes
+    /// are sorted by time.
+    ///
+    /// # Panics
+    /// If `keyframes` is empty.
+    pub fn new(keyframes: &[Keyframe]) -> Self {
+        assert!(!keyframes.is_empty(), "a trajectory needs at least one keyframe");
+        let mut keyframes = keyframes.to_vec();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Trajectory { keyframes, interpolation: Interpolation::Linear, easing: Easing::Linear }
+    }
+
+    /// The same trajectory, following another path between the keyframes.
+    pub fn with_interpolation(self, interpolation: Interpolation) -> Self {
+        Trajectory { interpolation, ..self }
+    }
+
+    /// The same trajectory, with another speed between the keyframes.
+    pub fn with_easing(self, easing: Easing) -> Self {
+        Trajectory { easing, ..self }
+    }
+
+    /// Time of the last keyframe, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.keyframes[self.keyframes.len() - 1].time
+    }
+
+    /// Position at a given time, interpolated between the surrounding keyframes. Times outside
+    /// the keyframe range are clamped.
+    ///
+    /// # Arguments
+    /// - `t` - Time, in seconds.
+    pub fn at(&self, t: f32) -> Point {
+        let (a, b, u) = locate(&self.keyframes, |k| k.time, t);
+        let u = self.easing.apply(u);
+        let position = |k: usize| self.keyframes[k].position;
+        match self.interpolation {
+            Interpolation::Linear => lerp(&position(a), &position(b), u),
+            Interpolation::CatmullRom => {
+                // The keyframes before and after the segment give its tangents.
+                let before = position(a.saturating_sub(1));
+                let after = position((b + 1).min(self.keyframes.len() - 1));
+                catmull_rom(&before, &position(a), &position(b), &after, u)
+            }
+        }
+    }
+
+    /// Sample one position per frame.
+    ///
+    /// # Arguments
+    /// - `settings` - Animation timing.
+    pub fn sample(&self, settings: &AnimationSettings) -> Vec<Point> {
+        (0..settings.frame_count()).map(|i| self.at(settings.frame_time(i))).collect()
+    }
+}
+
+/// A camera view at a given time, in seconds.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ViewKeyframe {
+    pub time: f32,
+    pub view: OrbitView,
+}
+
+/// Camera views interpolated between keyframes, edited interactively.
+#[derive(Debug, Clone, Default)]
+pub struct CameraTrack {
+    keyframes: Vec<ViewKeyframe>,
+}
+
+impl CameraTrack {
+    /// Keyframes, sorted by time.
+    pub fn keyframes(&self) -> &[ViewKeyframe] {
+        &self.keyframes
+    }
+
+    /// Add a keyframe, replacing the one at the same time if any.
+    pub fn insert(&mut self, keyframe: ViewKeyframe) {
+        match self.keyframes.binary_search_by(|k| k.time.total_cmp(&keyframe.time)) {
+            Ok(k) => self.keyframes[k] = keyframe,
+            Err(k) => self.keyframes.insert(k, keyframe),
+        }
+    }
+
+    /// Remove a keyframe.
+    ///
+    /// # Panics
+    /// If `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> ViewKeyframe {
+        self.keyframes.remove(index)
+    }
+
+    /// Time of the last keyframe, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    /// View at a given time, `None` without keyframes. Times outside the keyframe range are
+    /// clamped.
+    ///
+    /// # Arguments
+    /// - `t` - Time, in seconds.
+    pub fn at(&self, t: f32) -> Option<OrbitView> {
+        if self.keyframes.is_empty() {
+            return None;
+        }
+        let (a, b, u) = locate(&self.keyframes, |k| k.time, t);
+        Some(self.keyframes[a].view.lerp(&self.keyframes[b].view, u))
+    }
+
+    /// Sample one view per frame, none without keyframes.
+    ///
+    /// # Arguments
+    /// - `settings` - Animation timing.
+    pub fn sample(&self, settings: &AnimationSettings) -> Vec<OrbitView> {
+        (0..settings.frame_count()).filter_map(|i| self.at(settings.frame_time(i))).collect()
+    }
+}
+
+/// Point of a uniform Catmull-Rom spline, between `p1` at `u = 0` and `p2` at `u = 1`.
+fn catmull_rom(p0: &Point, p1: &Point, p2: &Point, p3: &Point, u: f32) -> Point {
+    let (p0, p1, p2, p3) = (*p0, *p1, *p2, *p3);
+    let (u2, u3) = (u * u, u * u * u);
+    0.5 * (2.0 * p1
+        + (p2 - p0) * u
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * u3)
+}
+
+/// Keyframes surrounding a time, and the interpolation factor between them.
+///
+/// Times outside the keyframe range are clamped to the first or last keyframe.
+///
+/// # Arguments
+/// - `keyframes` - Keyframes sorted by time, at least one.
+/// - `time` - Time of a keyframe.
+/// - `t` - Time, in seconds.
+fn locate<K>(keyframes: &[K], time: impl Fn(&K) -> f32, t: f32) -> (usize, usize, f32) {
+    if t <= time(&keyframes[0]) {
+        return (0, 0, 0.0);
+    }
+
+    for (k, pair) in keyframes.windows(2).enumerate() {
+        let (a, b) = (time(&pair[0]), time(&pair[1]));
+        if t <= b {
+            let span = b - a;
+            if span <= 0.0 {
+                return (k + 1, k + 1, 0.0);
+            }
+            return (k, k + 1, (t - a) / span);
+        }
+    }
+
+    let last = keyframes.len() - 1;
+    (last, last, 0.0)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::animation::{
+        AnimationSettings, CameraTrack, Easing, Interpolation, Keyframe, Trajectory, ViewKeyframe,
+    };
+    use crate::geometry::Point;
+    use crate::view::OrbitView;
+
+    fn make_trajectory() -> Trajectory {
+        Trajectory::new(&[
+            Keyframe { time: 0.0, position: Point::new(0.0, 0.0, 0.0) },
+            Keyframe { time: 1.0, position: Point::new(0.0, 0.0, 1.0) },
+            Keyframe { time: 2.0, position: Point::new(0.0, 1.0, 1.0) },
+        ])
+    }
+
+    #[test]
+    fn test_frame_count_follows_fps_and_duration() {
+        assert_eq!(AnimationSettings::new(24.0, 2.0).frame_count(), 48);
+        assert_eq!(AnimationSettings::new(30.0, 2.0).frame_count(), 60);
+        assert_eq!(AnimationSettings::new(24.0, 0.0).frame_count(), 1);
+    }
+
+    #[test]
+    fn test_frame_time_is_scaled() {
+        let mut settings = AnimationSettings::new(10.0, 1.0);
+        assert_f32_near!(settings.frame_time(5), 0.5);
+
+        settings.time_scale = 0.5;
+        assert_f32_near!(settings.frame_time(5), 0.25);
+    }
+
+    #[test]
+    fn test_trajectory_interpolates_between_keyframes() {
+        let trajectory = make_trajectory();
+
+        assert_eq!(trajectory.duration(), 2.0);
+        assert_eq!(trajectory.at(0.0), Point::new(0.0, 0.0, 0.0));
+        assert_eq!(trajectory.at(0.5), Point::new(0.0, 0.0, 0.5));
+        assert_eq!(trajectory.at(1.0), Point::new(0.0, 0.0, 1.0));
+        assert_eq!(trajectory.at(1.5), Point::new(0.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn test_trajectory_clamps_out_of_range_times() {
+        let trajectory = make_trajectory();
+
+        assert_eq!(trajectory.at(-1.0), Point::new(0.0, 0.0, 0.0));
+        assert_eq!(trajectory.at(10.0), Point::new(0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_keyframes_are_sorted() {
+        let trajectory = Trajectory::new(&[
+            Keyframe { time: 1.0, position: Point::new(1.0, 0.0, 0.0) },
+            Keyframe { time: 0.0, position: Point::new(0.0, 0.0, 0.0) },
+        ]);
+
+        assert_eq!(trajectory.at(0.25), Point::new(0.25, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_easing() {
+        for easing in Easing::ALL {
+            assert_eq!((easing.apply(0.0), easing.apply(1.0)), (0.0, 1.0));
+            assert_eq!(easing.name().parse(), Ok(easing));
+        }
+        assert!(Easing::EaseIn.apply(0.25) < 0.25);
+        assert!(Easing::EaseOut.apply(0.25) > 0.25);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert!("bounce".parse::<Easing>().is_err());
+
+        let trajectory = make_trajectory().with_easing(Easing::EaseInOut);
+        assert_eq!(trajectory.at(1.0), Point::new(0.0, 0.0, 1.0));
+        assert!(trajectory.at(0.25).z < 0.25);
+        assert_eq!(trajectory.at(0.5), Point::new(0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn test_catmull_rom_goes_through_the_keyframes() {
+        let trajectory = make_trajectory().with_interpolation(Interpolation::CatmullRom);
+        assert_eq!(trajectory.at(0.0), Point::new(0.0, 0.0, 0.0));
+        assert_eq!(trajectory.at(1.0), Point::new(0.0, 0.0, 1.0));
+        assert_eq!(trajectory.at(2.0), Point::new(0.0, 1.0, 1.0));
+        // The curve rounds the corner at the second keyframe instead of turning sharply.
+        let before = trajectory.at(0.9);
+        assert!(before.y < 0.0 && before.z < 1.0, "{before:?}");
+        let after = trajectory.at(1.1);
+        assert!(after.z > 1.0, "{after:?}");
+
+        // Between keyframes on a straight line, it is the straight line.
+        let straight = Trajectory::new(&[
+            Keyframe { time: 0.0, position: Point::new(0.0, 0.0, 0.0) },
+            Keyframe { time: 1.0, position: Point::new(1.0, 0.0, 0.0) },
+            Keyframe { time: 2.0, position: Point::new(2.0, 0.0, 0.0) },
+            Keyframe { time: 3.0, position: Point::new(3.0, 0.0, 0.0) },
+        ])
+        .with_interpolation(Interpolation::CatmullRom);
+        assert_f32_near!(straight.at(1.5).x, 1.5);
+        assert_eq!("catmull-rom".parse(), Ok(Interpolation::CatmullRom));
+        assert!("bezier".parse::<Interpolation>().is_err());
+    }
+
+    #[test]
+    fn test_changing_fps_keeps_keyframe_timing() {
+        let trajectory = make_trajectory();
+
+        let at_10fps = trajectory.sample(&AnimationSettings::new(10.0, 2.0));
+        let at_20fps = trajectory.sample(&AnimationSettings::new(20.0, 2.0));
+
+        assert_eq!(at_10fps.len(), 20);
+        assert_eq!(at_20fps.len(), 40);
+        assert_eq!(at_10fps[10], Point::new(0.0, 0.0, 1.0));
+        assert_eq!(at_20fps[20], Point::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_camera_track() {
+        let mut track = CameraTrack::default();
+        assert_eq!(track.at(0.0), None);
+
+        let near = OrbitView { distance: 1.0, ..Default::default() };
+        let far = OrbitView { distance: 3.0, ..Default::default() };
+        track.insert(ViewKeyframe { time: 2.0, view: far });
+        track.insert(ViewKeyframe { time: 0.0, view: far });
+        track.insert(ViewKeyframe { time: 0.0, view: near });
+
+        assert_eq!(track.keyframes().len(), 2);
+        assert_eq!(track.duration(), 2.0);
+        assert_eq!(track.at(-1.0), Some(near));
+        assert_f32_near!(track.at(1.0).unwrap().distance, 2.0);
+        assert_eq!(track.sample(&AnimationSettings::new(2.0, 2.0)).len(), 4);
+
+        track.remove(0);
+        assert_eq!(track.at(0.0), Some(far));
+    }
+}
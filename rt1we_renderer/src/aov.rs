@@ -0,0 +1,263 @@
+//! Auxiliary output variables: what the camera rays hit first, the albedo, normal, depth and
+//! object of each pixel. They are noise-free, which is what denoisers need to keep the edges
+//! of a noisy render sharp, and what compositing needs to separate its objects.
+use crate::error::RtError;
+use crate::geometry::{Color, Vec3};
+use crate::image::{ImageRGBA, ImageRGBF32};
+use crate::interval::Interval;
+use crate::render::{surface_albedo, Camera, HitRecord, Scene};
+use crate::sampler::Sampler;
+use crate::settings::RenderSettings;
+use crate::stereo::Cameras;
+use rand::Rng;
+use rayon::prelude::*;
+use std::path::Path;
+
+/// A buffer of [`Aovs`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AovKind {
+    Albedo,
+    Normal,
+    Depth,
+    ObjectId,
+}
+
+impl AovKind {
+    pub const ALL: [AovKind; 4] =
+        [AovKind::Albedo, AovKind::Normal, AovKind::Depth, AovKind::ObjectId];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AovKind::Albedo => "albedo",
+            AovKind::Normal => "normal",
+            AovKind::Depth => "depth",
+            AovKind::ObjectId => "object-id",
+        }
+    }
+}
+
+/// What the camera rays of a render hit first, row 0 at the bottom as in the rendered images.
+#[derive(Debug, Clone)]
+pub struct Aovs {
+    pub width: usize,
+    pub height: usize,
+    /// Color factor of the surfaces, averaged over the samples of each pixel, the background
+    /// where nothing is hit.
+    pub albedo: ImageRGBF32,
+    /// Outward normals, averaged over the samples of each pixel, zero where nothing is hit.
+    pub normal: ImageRGBF32,
+    /// Distance from the camera through the center of each pixel, infinite where nothing is
+    /// hit.
+    pub depth: Vec<f32>,
+    /// Index in the scene of the object seen through the center of each pixel.
+    pub object_id: Vec<Option<usize>>,
+    /// Distance from the camera to the target of the view, the middle gray of the 8-bit depth.
+    target_distance: f32,
+}
+
+impl Aovs {
+    /// A buffer, as linear values: normals on `[-1;1]`, depths in scene units, and object
+    /// indices plus one, 0 where there is no object.
+    pub fn image_hdr(&self, kind: AovKind) -> ImageRGBF32 {
+        match kind {
+            AovKind::Albedo => self.albedo.clone(),
+            AovKind::Normal => self.normal.clone(),
+            AovKind::Depth => self.gray(|k| self.depth[k]),
+            AovKind::ObjectId => self.gray(|k| self.object_id[k].map_or(0.0, |id| id as f32 + 1.0)),
+        }
+    }
+
+    /// A buffer, as an 8-bit image: albedos clamped, normals brought to `[0;1]` as the guides
+    /// of [`crate::denoise::denoise`] expect, depths as [`crate::settings::RenderMode::Depth`]
+    /// and a color per object.
+    pub fn image(&self, kind: AovKind) -> ImageRGBA {
+        let mut im = ImageRGBA::new(self.width, self.height);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let k = j * self.width + i;
+                let c = match kind {
+                    AovKind::Albedo => rgb(self.albedo.at(i, j)),
+                    AovKind::Normal => (rgb(self.normal.at(i, j)) + Color::WHITE) * 0.5,
+                    AovKind::Depth => {
+                        let gray = self.target_distance / (self.target_distance + self.depth[k]);
+                        Color::new(gray, gray, gray)
+                    }
+                    AovKind::ObjectId => object_color(self.object_id[k]),
+                };
+                im.put(i, j, to_u8(c.x), to_u8(c.y), to_u8(c.z), 255);
+            }
+        }
+        im
+    }
+
+    fn gray(&self, value: impl Fn(usize) -> f32) -> ImageRGBF32 {
+        let mut im = ImageRGBF32::new(self.width, self.height);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let v = value(j * self.width + i);
+                im.put(i, j, v, v, v);
+            }
+        }
+        im
+    }
+}
+
+fn rgb((r, g, b): (f32, f32, f32)) -> Color {
+    Color::new(r, g, b)
+}
+
+fn to_u8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// A color telling neighbouring objects apart, black where there is no object.
+fn object_color(object_id: Option<usize>) -> Color {
+    let Some(id) = object_id else {
+        return Color::BLACK;
+    };
+    let hash = (id as u32).wrapping_add(1).wrapping_mul(0x9E37_79B1);
+    let channel = |shift: u32| ((hash >> shift) & 0xff) as f32 / 255.0;
+    Color::new(channel(24), channel(16), channel(8))
+}
+
+/// Path of a buffer written next to the image at `path`: `out/image.png` gives
+/// `out/image_normal.png` for the normals.
+pub fn aov_path(path: &str, kind: AovKind) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let name = match path.extension().and_then(|s| s.to_str()) {
+        Some(ext) => format!("{stem}_{}.{ext}", kind.name()),
+        None => format!("{stem}_{}", kind.name()),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Render the auxiliary buffers of a render with the same settings.
+///
+/// Albedos and normals are averaged over the same number of jittered rays per pixel as the
+/// render, so that their edges match those of the image. Depths and objects are those seen
+/// through the center of the pixels.
+///
+/// # Returns
+/// The buffers, or an error if the threads of the render cannot be started.
+pub fn render_aovs(scene: &Scene, settings: &RenderSettings) -> Result<Aovs, RtError> {
+    let (width, height, samples) = (settings.width, settings.height, settings.samples());
+    let scene = &settings.scene(scene);
+    let world = scene.bvh();
+    let cams = Cameras::new(scene, scene.view(), settings);
+    let seed = settings.seed.unwrap_or_else(rand::random);
+    let pool = settings.thread_pool()?;
+    let hit = |cam: &Camera, u: f32, v: f32, rng: &mut Sampler| {
+        let ray = cam.get_ray(u, v, rng);
+        let mut rec = HitRecord::new();
+        let hit = world.hit(&ray, Interval::new(0.001, f32::INFINITY), &mut rec);
+        (ray, hit.then_some(rec))
+    };
+
+    let trace = |j: usize| {
+        let mut rng = Sampler::for_row(seed, 0, j);
+        let mut row = Vec::with_capacity(width);
+        for column in 0..width {
+            let (cam, i) = cams.at(column);
+            let view_width = cams.width() as f32;
+            let (mut albedo, mut normal) = (Color::BLACK, Vec3::ZERO);
+            for _ in 0..samples {
+                let u = (i as f32 + rng.gen::<f32>()) / (view_width - 1.0);
+                let v = (j as f32 + rng.gen::<f32>()) / (height as f32 - 1.0);
+                match hit(cam, u, v, &mut rng) {
+                    (ray, Some(rec)) => {
+                        albedo += surface_albedo(scene, &ray, &rec, &mut rng);
+                        normal += if rec.front_face { rec.normal } else { -rec.normal };
+                    }
+                    (ray, None) => albedo += scene.background().color(&ray.dir),
+                }
+            }
+            let u = (i as f32 + 0.5) / (view_width - 1.0);
+            let v = (j as f32 + 0.5) / (height as f32 - 1.0);
+            let (depth, object_id) = match hit(cam, u, v, &mut rng) {
+                (ray, Some(rec)) => (rec.t * ray.dir.len(), Some(rec.object_id)),
+                (_, None) => (f32::INFINITY, None),
+            };
+            row.push((albedo / samples as f32, normal / samples as f32, depth, object_id));
+        }
+        row
+    };
+    let trace_rows = || (0..height).into_par_iter().map(trace).collect::<Vec<_>>();
+    let rows = match &pool {
+        Some(pool) => pool.install(trace_rows),
+        None => trace_rows(),
+    };
+
+    let mut aovs = Aovs {
+        width,
+        height,
+        albedo: ImageRGBF32::new(width, height),
+        normal: ImageRGBF32::new(width, height),
+        depth: Vec::with_capacity(width * height),
+        object_id: Vec::with_capacity(width * height),
+        target_distance: scene.view().distance,
+    };
+    for (j, row) in rows.into_iter().enumerate() {
+        for (i, (albedo, normal, depth, object_id)) in row.into_iter().enumerate() {
+            aovs.albedo.put(i, j, albedo.x, albedo.y, albedo.z);
+            aovs.normal.put(i, j, normal.x, normal.y, normal.z);
+            aovs.depth.push(depth);
+            aovs.object_id.push(object_id);
+        }
+    }
+    Ok(aovs)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::aov::{aov_path, render_aovs, AovKind};
+    use crate::geometry::Point;
+    use crate::render::Scene;
+    use crate::settings::RenderSettings;
+    use crate::view::{Lens, OrbitView};
+
+    #[test]
+    fn test_render_aovs() {
+        // Close on the front of the pink sphere, two units away.
+        let mut scene = Scene::demo();
+        let view = OrbitView::looking_at(&Point::new(1.0, 0.0, 1.0), &Point::new(1.0, 0.0, -1.0));
+        scene.set_view(&view);
+        scene.set_lens(&Lens { vfov: 10.0, ..Default::default() });
+        let settings = RenderSettings::builder().resolution(33, 33).samples_per_pixel(4);
+        let aovs = render_aovs(&scene, &settings.seed(Some(1)).build().unwrap()).unwrap();
+
+        let k = 16 * 33 + 16;
+        assert_eq!(aovs.object_id[k], Some(2));
+        assert_float_absolute_eq!(aovs.depth[k], 1.5, 1e-3);
+        assert_eq!(aovs.albedo.at(16, 16), (0.7, 0.3, 0.3));
+        let (x, y, z) = aovs.normal.at(16, 16);
+        assert_float_absolute_eq!(z, 1.0, 1e-3);
+        assert!(x.abs() < 0.05 && y.abs() < 0.05);
+
+        let (r, g, b, _) = aovs.image(AovKind::Normal).at(16, 16);
+        assert!(r.abs_diff(128) <= 2 && g.abs_diff(128) <= 2 && b == 255, "{r} {g} {b}");
+        assert_eq!(aovs.image(AovKind::Albedo).at(16, 16), (179, 77, 77, 255));
+        // The target is 2 away: 2 / (2 + 1.5).
+        assert_eq!(aovs.image(AovKind::Depth).at(16, 16), (146, 146, 146, 255));
+        assert_eq!(aovs.image_hdr(AovKind::ObjectId).at(16, 16), (3.0, 3.0, 3.0));
+        assert_eq!(aovs.image_hdr(AovKind::Depth).width, 33);
+    }
+
+    #[test]
+    fn test_aovs_of_the_sky() {
+        let settings = RenderSettings::builder().resolution(4, 3).samples_per_pixel(2);
+        let aovs = render_aovs(&Scene::empty(), &settings.build().unwrap()).unwrap();
+        assert!(aovs.object_id.iter().all(Option::is_none));
+        assert!(aovs.depth.iter().all(|d| d.is_infinite()));
+        assert!(aovs.normal.pixels.iter().all(|&v| v == 0.0));
+        assert!(aovs.image(AovKind::ObjectId).pixels.chunks(4).all(|p| p == [0, 0, 0, 255]));
+        assert!(aovs.image_hdr(AovKind::ObjectId).pixels.iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_aov_path() {
+        assert_eq!(aov_path("out/image.png", AovKind::Normal), "out/image_normal.png");
+        assert_eq!(aov_path("frame_00001.exr", AovKind::ObjectId), "frame_00001_object-id.exr");
+        assert_eq!(aov_path("out/image", AovKind::Depth), "out/image_depth");
+    }
+}
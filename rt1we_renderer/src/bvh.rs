@@ -0,0 +1,477 @@
+//! Bounding volume hierarchy, to avoid testing every object for every ray.
+use crate::aabb::{axis_of, Aabb};
+use crate::environment::EnvironmentMap;
+use crate::geometry::Point;
+use crate::interval::Interval;
+use crate::light::{DirectionalLight, PunctualLight};
+use crate::lod::{bounding_sphere, ProxyGroup};
+use crate::ray::Ray;
+use crate::render::{HitRecord, Hittable, HittableList, Object, Sphere};
+
+/// Maximum number of objects stored in a leaf.
+const LEAF_SIZE: usize = 2;
+
+#[derive(Debug, Copy, Clone)]
+enum BvhNode {
+    /// Objects `first..first + count`.
+    Leaf { bbox: Aabb, first: usize, count: usize },
+    /// Indices of the two child nodes.
+    Interior { bbox: Aabb, left: usize, right: usize },
+}
+
+impl BvhNode {
+    fn bbox(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bbox, .. } => bbox,
+            BvhNode::Interior { bbox, .. } => bbox,
+        }
+    }
+}
+
+/// What an object of the hierarchy stands for.
+#[derive(Debug, Copy, Clone)]
+enum Primitive {
+    /// An object, with its index in the list the hierarchy was built from.
+    Object(usize),
+    /// The proxy of the group at this index.
+    Group(usize),
+}
+
+/// Objects replaced by a proxy when seen from far away, see [`crate::lod`].
+#[derive(Clone)]
+struct LodGroup {
+    /// Index of the first member, reported by the hits on the proxy.
+    object_id: usize,
+    members: Bvh,
+}
+
+/// Binary tree of bounding boxes over a set of objects.
+///
+/// Nodes are split at the median of the object centroids, along the longest axis. Unbounded
+/// objects, as planes, stay out of the tree and are tested by every ray.
+#[derive(Clone)]
+pub struct Bvh {
+    objects: Vec<(Primitive, Object)>,
+    unbounded: Vec<(Primitive, Object)>,
+    groups: Vec<LodGroup>,
+    /// Angular size below which groups are replaced by their proxy, in radians.
+    lod_threshold: f32,
+    nodes: Vec<BvhNode>,
+    depth: usize,
+    /// Lights sampled by the diffuse bounces, see [`crate::pdf`].
+    lights: HittableList,
+    /// Distant light sampled by the diffuse bounces too.
+    sun: Option<DirectionalLight>,
+    punctual_lights: Vec<PunctualLight>,
+    environment: Option<EnvironmentMap>,
+}
+
+impl Bvh {
+    /// Build the hierarchy. Objects are copied and reordered, hits still report their index
+    /// in `objects`.
+    ///
+    /// # Arguments
+    /// - `objects` - The objects to accelerate.
+    pub fn new(objects: &[Object]) -> Self {
+        let objects = objects.iter().enumerate().map(|(i, o)| (Primitive::Object(i), o.clone()));
+        Self::from_primitives(objects.collect(), Vec::new(), 0.0)
+    }
+
+    /// Same as [`Bvh::new`], with groups of objects replaced by a proxy when seen from far.
+    ///
+    /// # Arguments
+    /// - `objects` - The objects to accelerate.
+    /// - `groups` - Groups of spheres, the proxies enclose their members. Members out of
+    ///   bounds, already in a previous group, or not spheres, are ignored.
+    /// - `lod_threshold` - Angular size below which the groups are replaced by their proxy,
+    ///   seen from the ray origin, in radians.
+    pub fn with_proxies(objects: &[Object], groups: &[ProxyGroup], lod_threshold: f32) -> Self {
+        let mut grouped = vec![false; objects.len()];
+        let mut primitives = Vec::new();
+        let mut lod_groups = Vec::new();
+        for group in groups {
+            let members: Vec<usize> = group
+                .members
+                .iter()
+                .copied()
+                .filter(|&i| i < objects.len() && objects[i].as_sphere().is_some())
+                .filter(|&i| !std::mem::replace(&mut grouped[i], true))
+                .collect();
+            let spheres: Vec<Sphere> =
+                members.iter().filter_map(|&i| objects[i].as_sphere().copied()).collect();
+            let Some(proxy) = bounding_sphere(&spheres, group.material_id) else {
+                continue;
+            };
+            primitives.push((Primitive::Group(lod_groups.len()), Object::Sphere(proxy)));
+            let primitives = members.iter().map(|&i| (Primitive::Object(i), objects[i].clone()));
+            lod_groups.push(LodGroup {
+                object_id: members[0],
+                members: Self::from_primitives(primitives.collect(), Vec::new(), lod_threshold),
+            });
+        }
+        let ungrouped = objects.iter().enumerate().filter(|(i, _)| !grouped[*i]);
+        primitives.extend(ungrouped.map(|(i, o)| (Primitive::Object(i), o.clone())));
+        Self::from_primitives(primitives, lod_groups, lod_threshold)
+    }
+
+    fn from_primitives(
+        primitives: Vec<(Primitive, Object)>, groups: Vec<LodGroup>, lod_threshold: f32,
+    ) -> Self {
+        let (objects, unbounded) = primitives.into_iter().partition(|(_, o)| {
+            let bbox = o.bounding_box();
+            bbox.min.len().is_finite() && bbox.max.len().is_finite()
+        });
+        let mut bvh = Bvh {
+            objects,
+            unbounded,
+            groups,
+            lod_threshold,
+            nodes: Vec::new(),
+            depth: 0,
+            lights: HittableList::new(),
+            sun: None,
+            punctual_lights: Vec::new(),
+            environment: None,
+        };
+        if !bvh.objects.is_empty() {
+            let count = bvh.objects.len();
+            bvh.build(0, count, 1);
+        }
+        bvh
+    }
+
+    /// The same hierarchy, with `lights` sampled by the diffuse bounces of the rays.
+    pub(crate) fn with_lights(self, lights: HittableList) -> Self {
+        Bvh { lights, ..self }
+    }
+
+    /// Lights sampled by the diffuse bounces.
+    pub(crate) fn lights(&self) -> &HittableList {
+        &self.lights
+    }
+
+    /// The same hierarchy, with a distant light sampled by the diffuse bounces of the rays.
+    pub(crate) fn with_sun(self, sun: Option<DirectionalLight>) -> Self {
+        Bvh { sun, ..self }
+    }
+
+    /// Distant light sampled by the diffuse bounces.
+    pub(crate) fn sun(&self) -> Option<&DirectionalLight> {
+        self.sun.as_ref()
+    }
+
+    /// The same hierarchy, with point and spot lights lit through shadow rays.
+    pub(crate) fn with_punctual_lights(self, punctual_lights: Vec<PunctualLight>) -> Self {
+        Bvh { punctual_lights, ..self }
+    }
+
+    /// The same hierarchy, with a panorama around it sampled by the diffuse bounces of the
+    /// rays.
+    pub(crate) fn with_environment(self, environment: Option<EnvironmentMap>) -> Self {
+        Bvh { environment, ..self }
+    }
+
+    /// Panorama sampled by the diffuse bounces.
+    pub(crate) fn environment(&self) -> Option<&EnvironmentMap> {
+        self.environment.as_ref()
+    }
+
+    /// Point and spot lights lit through shadow rays by the diffuse bounces.
+    pub(crate) fn punctual_lights(&self) -> &[PunctualLight] {
+        &self.punctual_lights
+    }
+
+    /// Whether an object stands between `from` and `to`.
+    pub(crate) fn occluded(&self, from: &Point, to: &Point, time: f32) -> bool {
+        let to_light = *to - *from;
+        let distance = to_light.len();
+        let shadow = Ray { orig: *from, dir: to_light / distance, time, wavelength: None };
+        let mut rec = HitRecord::new();
+        self.hit(&shadow, Interval::new(0.001, distance - 0.001), &mut rec)
+    }
+
+    /// Number of nodes, leaves included.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Number of nodes on the longest path from the root to a leaf.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Box around all the bounded objects.
+    pub fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map(|n| *n.bbox()).unwrap_or(Aabb::EMPTY)
+    }
+
+    /// Approximate memory used by the hierarchy and its objects, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.nodes.len() * std::mem::size_of::<BvhNode>()
+            + (self.objects.len() + self.unbounded.len())
+                * std::mem::size_of::<(Primitive, Object)>()
+            + self.groups.iter().map(|g| g.members.memory_size()).sum::<usize>()
+    }
+
+    fn build(&mut self, first: usize, count: usize, depth: usize) -> usize {
+        self.depth = self.depth.max(depth);
+        let objects = &mut self.objects[first..first + count];
+        let bbox = objects.iter().fold(Aabb::EMPTY, |b, (_, o)| b.surrounding(&o.bounding_box()));
+
+        let index = self.nodes.len();
+        if count <= LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf { bbox, first, count });
+            return index;
+        }
+
+        let centroids = objects.iter().fold(Aabb::EMPTY, |b, (_, o)| {
+            let c = o.bounding_box().centroid();
+            b.surrounding(&Aabb::new(c, c))
+        });
+        let axis = centroids.longest_axis();
+        objects.sort_by(|a, b| {
+            let ca = axis_of(&a.1.bounding_box().centroid(), axis);
+            let cb = axis_of(&b.1.bounding_box().centroid(), axis);
+            ca.total_cmp(&cb)
+        });
+
+        // Reserve the slot, children are filled in once built.
+        self.nodes.push(BvhNode::Leaf { bbox, first, count });
+        let half = count / 2;
+        let left = self.build(first, half, depth + 1);
+        let right = self.build(first + half, count - half, depth + 1);
+        self.nodes[index] = BvhNode::Interior { bbox, left, right };
+        index
+    }
+
+    /// Process a single ray cast, keeping the closest hit.
+    ///
+    /// # Arguments
+    /// - `r` - The ray.
+    /// - `ray_t` - Distances along the ray for which the ray cast is considered a valid hit.
+    /// - `rec` - Keep track of the hit properties.
+    pub fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut temp_rec = HitRecord::new();
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+        for (primitive, object) in &self.unbounded {
+            let ray_t = Interval::new(ray_t.min, closest_so_far);
+            if self.hit_primitive(primitive, object, r, ray_t, &mut temp_rec) {
+                hit_anything = true;
+                closest_so_far = temp_rec.t;
+                *rec = temp_rec;
+            }
+        }
+        if self.nodes.is_empty() {
+            return hit_anything;
+        }
+
+        let mut stack = vec![0usize];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bbox().hit(r, Interval::new(ray_t.min, closest_so_far)) {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { first, count, .. } => {
+                    for (primitive, object) in &self.objects[first..first + count] {
+                        let ray_t = Interval::new(ray_t.min, closest_so_far);
+                        if self.hit_primitive(primitive, object, r, ray_t, &mut temp_rec) {
+                            hit_anything = true;
+                            closest_so_far = temp_rec.t;
+                            *rec = temp_rec;
+                        }
+                    }
+                }
+                BvhNode::Interior { left, right, .. } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        hit_anything
+    }
+
+    /// Hit an object, or the proxy or the members of a group depending on how large the group
+    /// looks from the ray origin.
+    fn hit_primitive(
+        &self, primitive: &Primitive, object: &Object, r: &Ray, ray_t: Interval,
+        rec: &mut HitRecord,
+    ) -> bool {
+        match *primitive {
+            Primitive::Object(id) => {
+                let hit = object.hit(r, ray_t, rec);
+                if hit {
+                    rec.object_id = id;
+                }
+                hit
+            }
+            Primitive::Group(g) => {
+                let group = &self.groups[g];
+                // Proxies are spheres.
+                let Object::Sphere(proxy) = object else {
+                    return false;
+                };
+                let distance = (proxy.center - r.orig).len();
+                if proxy.radius >= self.lod_threshold * distance {
+                    return group.members.hit(r, ray_t, rec);
+                }
+                let hit = object.hit(r, ray_t, rec);
+                if hit {
+                    rec.object_id = group.object_id;
+                }
+                hit
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::bvh::Bvh;
+    use crate::geometry::{Point, Vec3};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, HittableList, Object, Plane, Sphere};
+
+    fn make_spheres(count: usize) -> Vec<Object> {
+        (0..count)
+            .map(|i| {
+                Sphere::new(Point::new(i as f32 * 2.0, (i % 3) as f32, -5.0), 0.5, MaterialId(0))
+                    .into()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_empty_bvh_never_hits() {
+        let bvh = Bvh::new(&[]);
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+
+        assert_eq!(bvh.node_count(), 0);
+        assert_eq!(bvh.depth(), 0);
+        assert!(!bvh.hit(&r, Interval::new(0.0, f32::INFINITY), &mut HitRecord::new()));
+    }
+
+    #[test]
+    fn test_bvh_shape() {
+        let bvh = Bvh::new(&make_spheres(8));
+
+        // 4 leaves of 2 objects, 3 interior nodes
+        assert_eq!(bvh.node_count(), 7);
+        assert_eq!(bvh.depth(), 3);
+        assert_eq!(bvh.bounding_box().min, Point::new(-0.5, -0.5, -5.5));
+        assert_eq!(bvh.bounding_box().max, Point::new(14.5, 2.5, -4.5));
+    }
+
+    #[test]
+    fn test_bvh_hits_match_brute_force() {
+        let spheres = make_spheres(20);
+        let bvh = Bvh::new(&spheres);
+        let mut list = HittableList::new();
+        for s in &spheres {
+            list.add(s.clone());
+        }
+
+        for k in 0..50 {
+            let target = Point::new(k as f32 * 0.8, (k % 4) as f32 * 0.7, -5.0);
+            let r = Ray::new(Point::new(10.0, 1.0, 5.0), target - Point::new(10.0, 1.0, 5.0));
+
+            let mut rec_bvh = HitRecord::new();
+            let mut rec_list = HitRecord::new();
+            let hit_bvh = bvh.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec_bvh);
+            let hit_list = list.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec_list);
+
+            assert_eq!(hit_bvh, hit_list);
+            if hit_bvh {
+                assert_f32_near!(rec_bvh.t, rec_list.t);
+                assert_eq!(rec_bvh.object_id, rec_list.object_id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_planes_stay_out_of_the_tree() {
+        let mut objects = make_spheres(8);
+        objects.push(Plane::new(Point::new(0.0, -1.0, 0.0), Vec3::UNIT_Y, MaterialId(1)).into());
+        let bvh = Bvh::new(&objects);
+
+        assert_eq!(bvh.node_count(), Bvh::new(&make_spheres(8)).node_count());
+        assert_eq!(bvh.bounding_box(), Bvh::new(&make_spheres(8)).bounding_box());
+
+        // Below the spheres, the ray only meets the plane.
+        let r = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, -1.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(bvh.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!(rec.object_id, 8);
+        assert_f32_near!(rec.t, 1.0);
+        // A sphere in front of the plane hides it.
+        let r = Ray::new(Point::new(0.0, 0.0, 5.0), -Vec3::UNIT_Z);
+        assert!(bvh.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!(rec.object_id, 0);
+    }
+
+    mod properties {
+        use crate::bvh::Bvh;
+        use crate::geometry::Point;
+        use crate::interval::Interval;
+        use crate::mesh::Triangle;
+        use crate::ray::Ray;
+        use crate::registry::MaterialId;
+        use crate::render::{HitRecord, HittableList, Object, Plane, Sphere};
+        use proptest::prelude::*;
+
+        fn point() -> impl Strategy<Value = Point> {
+            (-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0)
+                .prop_map(|(x, y, z)| Point::new(x, y, z))
+        }
+
+        fn sphere() -> impl Strategy<Value = Sphere> {
+            (point(), 1e-3f32..3.0, any::<bool>(), 0usize..4).prop_map(|(center, r, hollow, m)| {
+                Sphere::new(center, if hollow { -r } else { r }, MaterialId(m))
+            })
+        }
+
+        /// Mostly spheres, and a few planes and triangles.
+        fn object() -> impl Strategy<Value = Object> {
+            let plane = (point(), point())
+                .prop_filter("no normal", |(_, normal)| normal.len() > 1e-3)
+                .prop_map(|(center, normal)| Plane::new(center, normal, MaterialId(0)).into());
+            let triangle = (point(), point(), point())
+                .prop_map(|(a, b, c)| Triangle::new(a, b, c, MaterialId(0)).into());
+            prop_oneof![8 => sphere().prop_map(Object::from), 1 => plane, 2 => triangle]
+        }
+
+        proptest! {
+            #[test]
+            fn test_bvh_hits_match_brute_force_on_random_scenes(
+                objects in prop::collection::vec(object(), 0..40),
+                rays in prop::collection::vec((point(), point()), 1..20),
+            ) {
+                let bvh = Bvh::new(&objects);
+                let mut list = HittableList::new();
+                for o in &objects {
+                    list.add(o.clone());
+                }
+
+                for (orig, target) in rays {
+                    let dir = target - orig;
+                    prop_assume!(dir.len() > 1e-3);
+                    let r = Ray::new(orig, dir.normed());
+                    let mut rec_bvh = HitRecord::new();
+                    let mut rec_list = HitRecord::new();
+                    let hit_bvh = bvh.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec_bvh);
+                    let hit_list = list.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec_list);
+
+                    prop_assert_eq!(hit_bvh, hit_list);
+                    if hit_bvh {
+                        prop_assert_eq!(rec_bvh.t, rec_list.t);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Image comparison metrics, to check renders against a baseline.
+//!
+//! Metrics work on the 8-bit RGB values as stored, the alpha channel is ignored.
+use crate::error::RtError;
+use crate::image::ImageRGBA;
+
+/// Side of the square windows SSIM is computed on.
+const SSIM_WINDOW: usize = 8;
+
+fn check_sizes(a: &ImageRGBA, b: &ImageRGBA) -> Result<(), RtError> {
+    if (a.width, a.height) != (b.width, b.height) {
+        return Err(RtError::ImageSize {
+            name: "compared".to_string(),
+            size: (b.width, b.height),
+            expected: (a.width, a.height),
+        });
+    }
+    Ok(())
+}
+
+fn rgb_pairs<'a>(a: &'a ImageRGBA, b: &'a ImageRGBA) -> impl Iterator<Item = (&'a [u8], &'a [u8])> {
+    a.pixels.chunks(4).zip(b.pixels.chunks(4)).map(|(pa, pb)| (&pa[..3], &pb[..3]))
+}
+
+/// Peak signal-to-noise ratio, in dB. Identical images give `f64::INFINITY`.
+pub fn psnr(a: &ImageRGBA, b: &ImageRGBA) -> Result<f64, RtError> {
+    check_sizes(a, b)?;
+    let (sum, count) = rgb_pairs(a, b).fold((0.0, 0usize), |(sum, count), (pa, pb)| {
+        let se: f64 = pa.iter().zip(pb).map(|(x, y)| (*x as f64 - *y as f64).powi(2)).sum();
+        (sum + se, count + 3)
+    });
+    if count == 0 || sum == 0.0 {
+        return Ok(f64::INFINITY);
+    }
+    let mse = sum / count as f64;
+    Ok(10.0 * (255.0 * 255.0 / mse).log10())
+}
+
+fn luma(im: &ImageRGBA, i: usize, j: usize) -> f64 {
+    let (r, g, b, _) = im.at(i, j);
+    0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64
+}
+
+/// Structural similarity of the luma, averaged over 8x8 windows, in `[-1;1]`.
+///
+/// # Notes
+/// Windows overlap by half their size. Images smaller than a window use a single window.
+pub fn ssim(a: &ImageRGBA, b: &ImageRGBA) -> Result<f64, RtError> {
+    check_sizes(a, b)?;
+    if a.width == 0 || a.height == 0 {
+        return Ok(1.0);
+    }
+
+    let c1 = (0.01f64 * 255.0).powi(2);
+    let c2 = (0.03f64 * 255.0).powi(2);
+    let starts = |size: usize| -> Vec<usize> {
+        let last = size.saturating_sub(SSIM_WINDOW);
+        let mut v: Vec<usize> = (0..=last).step_by(SSIM_WINDOW / 2).collect();
+        if *v.last().unwrap() != last {
+            v.push(last);
+        }
+        v
+    };
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y0 in starts(a.height) {
+        for x0 in starts(a.width) {
+            let coords: Vec<(usize, usize)> = (y0..(y0 + SSIM_WINDOW).min(a.height))
+                .flat_map(|j| (x0..(x0 + SSIM_WINDOW).min(a.width)).map(move |i| (i, j)))
+                .collect();
+            let n = coords.len() as f64;
+            let xs: Vec<f64> = coords.iter().map(|&(i, j)| luma(a, i, j)).collect();
+            let ys: Vec<f64> = coords.iter().map(|&(i, j)| luma(b, i, j)).collect();
+
+            let mx = xs.iter().sum::<f64>() / n;
+            let my = ys.iter().sum::<f64>() / n;
+            let vx = xs.iter().map(|x| (x - mx).powi(2)).sum::<f64>() / n;
+            let vy = ys.iter().map(|y| (y - my).powi(2)).sum::<f64>() / n;
+            let cov = xs.iter().zip(&ys).map(|(x, y)| (x - mx) * (y - my)).sum::<f64>() / n;
+
+            total += ((2.0 * mx * my + c1) * (2.0 * cov + c2))
+                / ((mx * mx + my * my + c1) * (vx + vy + c2));
+            windows += 1;
+        }
+    }
+
+    Ok(total / windows as f64)
+}
+
+/// Map `t` in `[0;1]` to a black, red, yellow, white color ramp.
+fn heat(t: f32) -> (u8, u8, u8) {
+    let t = t.clamp(0.0, 1.0) * 3.0;
+    let c = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    (c(t), c(t - 1.0), c(t - 2.0))
+}
+
+/// Per-pixel difference image, and the largest channel difference.
+///
+/// Each pixel shows the largest channel difference, scaled by the largest difference in the
+/// image so that small errors stay visible. Identical images give a black heatmap.
+pub fn heatmap(a: &ImageRGBA, b: &ImageRGBA) -> Result<(ImageRGBA, u8), RtError> {
+    check_sizes(a, b)?;
+    let errors: Vec<u8> = rgb_pairs(a, b)
+        .map(|(pa, pb)| pa.iter().zip(pb).map(|(x, y)| x.abs_diff(*y)).max().unwrap())
+        .collect();
+    let max_error = errors.iter().copied().max().unwrap_or(0);
+
+    let mut out = ImageRGBA::new(a.width, a.height);
+    for (k, e) in errors.iter().enumerate() {
+        let t = if max_error == 0 { 0.0 } else { *e as f32 / max_error as f32 };
+        let (r, g, b) = heat(t);
+        out.put(k % a.width, k / a.width, r, g, b, 255);
+    }
+    Ok((out, max_error))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::compare::{heatmap, psnr, ssim};
+    use crate::image::ImageRGBA;
+
+    fn gradient(w: usize, h: usize) -> ImageRGBA {
+        let mut im = ImageRGBA::new(w, h);
+        for j in 0..h {
+            for i in 0..w {
+                let v = ((i + j) * 255 / (w + h)) as u8;
+                im.put(i, j, v, v / 2, 255 - v, 255);
+            }
+        }
+        im
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let im = gradient(20, 12);
+        assert_eq!(psnr(&im, &im).unwrap(), f64::INFINITY);
+        assert_f64_near!(ssim(&im, &im).unwrap(), 1.0);
+
+        let (hm, max_error) = heatmap(&im, &im).unwrap();
+        assert_eq!(max_error, 0);
+        assert_eq!(hm.at(3, 4), (0, 0, 0, 255));
+    }
+
+    #[test]
+    fn test_psnr_of_uniform_error() {
+        let a = ImageRGBA::new(4, 4);
+        let mut b = ImageRGBA::new(4, 4);
+        for p in b.pixels.chunks_mut(4) {
+            p[0] += 5;
+            p[1] += 5;
+            p[2] += 5;
+        }
+        // MSE is 25
+        let expected = 10.0 * (255.0f64 * 255.0 / 25.0).log10();
+        assert_f64_near!(psnr(&a, &b).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_ssim_decreases_with_distortion() {
+        let a = gradient(32, 24);
+        let mut b = gradient(32, 24);
+        for (k, p) in b.pixels.chunks_mut(4).enumerate() {
+            if k % 3 == 0 {
+                p[0] = 255 - p[0];
+            }
+        }
+        let s = ssim(&a, &b).unwrap();
+        assert!(s < 0.99 && s > -1.0);
+    }
+
+    #[test]
+    fn test_heatmap_highlights_largest_error() {
+        let a = ImageRGBA::new(4, 2);
+        let mut b = ImageRGBA::new(4, 2);
+        b.put(1, 1, 50, 10, 10, 255);
+        b.put(2, 0, 30, 10, 10, 255);
+
+        let (hm, max_error) = heatmap(&a, &b).unwrap();
+        assert_eq!(max_error, 40);
+        assert_eq!(hm.at(1, 1), (255, 255, 255, 255));
+        assert_eq!(hm.at(0, 0), (0, 0, 0, 255));
+        assert!(hm.at(2, 0).0 > 0);
+    }
+
+    #[test]
+    fn test_size_mismatch_is_an_error() {
+        let a = ImageRGBA::new(4, 4);
+        let b = ImageRGBA::new(4, 3);
+        let err = psnr(&a, &b).err().unwrap();
+        assert_eq!(err.to_string(), "compared image is 4x3, expected 4x4");
+        assert!(ssim(&a, &b).is_err());
+        assert!(heatmap(&a, &b).is_err());
+    }
+}
@@ -0,0 +1,391 @@
+//! Cylinders, cones and capsules, along any axis: the round parts of machines and furniture.
+//!
+//! Cylinders and cones are closed by flat caps, capsules by half spheres. The `u` surface
+//! coordinate goes around the axis, and `v` along it from the base, but on the flat caps,
+//! mapped as seen along the axis.
+use crate::aabb::Aabb;
+use crate::geometry::{dot, Point, Vec3};
+use crate::interval::Interval;
+use crate::mesh::BOX_PADDING;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::registry::MaterialId;
+use crate::render::{HitRecord, Hittable};
+use std::f32::consts::PI;
+
+/// Cylinder between the centers of its caps.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cylinder {
+    /// Center of the bottom cap.
+    pub base: Point,
+    /// Center of the top cap.
+    pub top: Point,
+    pub radius: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+/// Cone, or cut cone, between the centers of its caps.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cone {
+    /// Center of the bottom cap.
+    pub base: Point,
+    /// Center of the top cap, the apex of a pointed cone.
+    pub top: Point,
+    pub base_radius: f32,
+    /// Radius at the top, 0 for a pointed cone.
+    pub top_radius: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+/// Cylinder ended by half spheres, around the segment between their centers.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Capsule {
+    /// Center of the bottom half sphere.
+    pub base: Point,
+    /// Center of the top half sphere.
+    pub top: Point,
+    pub radius: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+impl Cylinder {
+    pub fn new(base: Point, top: Point, radius: f32, material_id: MaterialId) -> Self {
+        Cylinder { base, top, radius, material_id }
+    }
+}
+
+impl Cone {
+    pub fn new(
+        base: Point, top: Point, base_radius: f32, top_radius: f32, material_id: MaterialId,
+    ) -> Self {
+        Cone { base, top, base_radius, top_radius, material_id }
+    }
+}
+
+impl Capsule {
+    pub fn new(base: Point, top: Point, radius: f32, material_id: MaterialId) -> Self {
+        Capsule { base, top, radius, material_id }
+    }
+}
+
+/// A ray in the frame of the axis of a solid, `z` going along the axis from its base.
+struct LocalRay {
+    frame: Onb,
+    /// Length of the axis.
+    height: f32,
+    orig: Vec3,
+    dir: Vec3,
+}
+
+impl LocalRay {
+    fn new(r: &Ray, base: &Point, top: &Point) -> Self {
+        let axis = *top - *base;
+        let frame = Onb::from_w(&axis);
+        let (orig, dir) = (frame.coordinates(&(r.orig - *base)), frame.coordinates(&r.dir));
+        LocalRay { frame, height: axis.len(), orig, dir }
+    }
+
+    fn at(&self, t: f32) -> Vec3 {
+        self.orig + t * self.dir
+    }
+
+    /// The first hit in `ray_t` of the side of a cut cone, its radius going from `r0` at the
+    /// base to `r1` at the top, and the outward normal there.
+    fn side(&self, r0: f32, r1: f32, ray_t: Interval) -> Option<(f32, Vec3)> {
+        let (o, d) = (&self.orig, &self.dir);
+        let slope = (r1 - r0) / self.height;
+        let radius_at = |z: f32| r0 + slope * z;
+        // Points of the side are as far from the axis as the radius at their height.
+        let radius = radius_at(o.z);
+        let a = d.x * d.x + d.y * d.y - slope * slope * d.z * d.z;
+        let half_b = o.x * d.x + o.y * d.y - slope * d.z * radius;
+        let c = o.x * o.x + o.y * o.y - radius * radius;
+        let roots = if a.abs() <= 1e-8 * d.len_squared() {
+            // Along the side of a cone, or the axis of a cylinder: a single root, if any.
+            let t = -c / (2.0 * half_b);
+            [t, t]
+        } else {
+            let disc = half_b * half_b - a * c;
+            if disc < 0.0 {
+                return None;
+            }
+            let (t0, t1) = ((-half_b - disc.sqrt()) / a, (-half_b + disc.sqrt()) / a);
+            [t0.min(t1), t0.max(t1)]
+        };
+        roots.into_iter().find_map(|t| {
+            let p = self.at(t);
+            let on_side = ray_t.contains(t) && (0.0..=self.height).contains(&p.z);
+            on_side.then(|| (t, Vec3::new(p.x, p.y, -slope * radius_at(p.z)).normed()))
+        })
+    }
+
+    /// The hit in `ray_t` of the disk of `radius` across the axis at `z`, facing the
+    /// direction of the axis times `facing`.
+    fn cap(&self, z: f32, radius: f32, facing: f32, ray_t: Interval) -> Option<(f32, Vec3)> {
+        if radius <= 0.0 || self.dir.z == 0.0 {
+            return None;
+        }
+        let t = (z - self.orig.z) / self.dir.z;
+        let p = self.at(t);
+        let inside = ray_t.contains(t) && p.x * p.x + p.y * p.y <= radius * radius;
+        inside.then_some((t, Vec3::new(0.0, 0.0, facing)))
+    }
+
+    /// The first hit in `ray_t` of the part of the sphere of `radius` around the axis point at
+    /// `z` whose points are `kept`, and the outward normal there.
+    fn ball<F>(&self, z: f32, radius: f32, ray_t: Interval, kept: F) -> Option<(f32, Vec3)>
+    where
+        F: Fn(&Vec3) -> bool,
+    {
+        let center = Vec3::new(0.0, 0.0, z);
+        let oc = self.orig - center;
+        let a = self.dir.len_squared();
+        let half_b = dot(&oc, &self.dir);
+        let disc = half_b * half_b - a * (oc.len_squared() - radius * radius);
+        if disc < 0.0 {
+            return None;
+        }
+        let roots = [(-half_b - disc.sqrt()) / a, (-half_b + disc.sqrt()) / a];
+        roots.into_iter().find_map(|t| {
+            let p = self.at(t);
+            (ray_t.contains(t) && kept(&p)).then(|| (t, (p - center) / radius))
+        })
+    }
+
+    /// Record the closest of `hits`, with its surface coordinates.
+    ///
+    /// # Arguments
+    /// - `uv` - Surface coordinates of a point, in the frame of the axis, and of its normal.
+    ///
+    /// # Returns
+    /// Whether there was a hit.
+    fn record<F>(
+        &self, r: &Ray, hits: &[Option<(f32, Vec3)>], material_id: MaterialId, rec: &mut HitRecord,
+        uv: F,
+    ) -> bool
+    where
+        F: Fn(&Vec3, &Vec3) -> (f32, f32),
+    {
+        let closest = hits.iter().flatten().min_by(|a, b| a.0.total_cmp(&b.0));
+        let Some(&(t, normal)) = closest else {
+            return false;
+        };
+        rec.t = t;
+        rec.p = r.at(t);
+        (rec.u, rec.v) = uv(&self.at(t), &normal);
+        rec.material_id = material_id;
+        rec.set_face_normal(r, &self.frame.local(&normal));
+        true
+    }
+}
+
+/// Angle of a point around the z axis, scaled to `[0;1]`.
+fn around(p: &Vec3) -> f32 {
+    (p.y.atan2(p.x) + PI) / (2.0 * PI)
+}
+
+/// Surface coordinates of a point of a cylinder or a cone of `height`: around the axis and
+/// along it on the side, as seen along the axis on the caps, of `radius`.
+fn capped_uv(p: &Vec3, normal: &Vec3, height: f32, radius: f32) -> (f32, f32) {
+    if normal.x == 0.0 && normal.y == 0.0 {
+        (0.5 + p.x / (2.0 * radius), 0.5 + p.y / (2.0 * radius))
+    } else {
+        (around(p), p.z / height)
+    }
+}
+
+/// Box around a disk of `radius` at `center`, across `axis`, a unit vector.
+fn disk_box(center: &Point, axis: &Vec3, radius: f32) -> Aabb {
+    let extent = |a: f32| radius * (1.0 - a * a).max(0.0).sqrt() + BOX_PADDING;
+    let half = Vec3::new(extent(axis.x), extent(axis.y), extent(axis.z));
+    Aabb::new(*center - half, *center + half)
+}
+
+/// Box around the caps of a cut cone, and so around the cone.
+fn frustum_box(base: &Point, top: &Point, r0: f32, r1: f32) -> Aabb {
+    let axis = (*top - *base).normed();
+    disk_box(base, &axis, r0).surrounding(&disk_box(top, &axis, r1))
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let local = LocalRay::new(r, &self.base, &self.top);
+        let hits = [
+            local.side(self.radius, self.radius, ray_t),
+            local.cap(0.0, self.radius, -1.0, ray_t),
+            local.cap(local.height, self.radius, 1.0, ray_t),
+        ];
+        local.record(r, &hits, self.material_id, rec, |p, n| {
+            capped_uv(p, n, local.height, self.radius)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        frustum_box(&self.base, &self.top, self.radius, self.radius)
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let local = LocalRay::new(r, &self.base, &self.top);
+        let hits = [
+            local.side(self.base_radius, self.top_radius, ray_t),
+            local.cap(0.0, self.base_radius, -1.0, ray_t),
+            local.cap(local.height, self.top_radius, 1.0, ray_t),
+        ];
+        local.record(r, &hits, self.material_id, rec, |p, n| {
+            let radius = if p.z > local.height / 2.0 { self.top_radius } else { self.base_radius };
+            capped_uv(p, n, local.height, radius)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        frustum_box(&self.base, &self.top, self.base_radius, self.top_radius)
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let local = LocalRay::new(r, &self.base, &self.top);
+        let height = local.height;
+        // The half spheres out of the ends of the cylinder.
+        let hits = [
+            local.side(self.radius, self.radius, ray_t),
+            local.ball(0.0, self.radius, ray_t, |p| p.z <= 0.0),
+            local.ball(height, self.radius, ray_t, |p| p.z >= height),
+        ];
+        local.record(r, &hits, self.material_id, rec, |p, _| {
+            (around(p), (p.z + self.radius) / (height + 2.0 * self.radius))
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let ends = Aabb::new(self.base - r, self.base + r);
+        ends.surrounding(&Aabb::new(self.top - r, self.top + r))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::cylinder::{Capsule, Cone, Cylinder};
+    use crate::geometry::{Point, Vec3};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, Hittable};
+
+    fn hit(object: &impl Hittable, orig: Point, dir: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let r = Ray::new(orig, dir);
+        object.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec).then_some(rec)
+    }
+
+    fn near(a: &Vec3, b: &Vec3) -> bool {
+        (*a - *b).len() < 1e-3
+    }
+
+    #[test]
+    fn test_cylinder() {
+        let base = Point::new(0.0, -1.0, -3.0);
+        let cylinder = Cylinder::new(base, Point::new(0.0, 1.0, -3.0), 0.5, MaterialId(2));
+
+        // The side, facing outwards, half way up.
+        let rec = hit(&cylinder, Point::ZERO, -Vec3::UNIT_Z).unwrap();
+        assert_float_absolute_eq!(rec.t, 2.5, 1e-5);
+        assert!(near(&rec.normal, &Vec3::UNIT_Z) && rec.front_face, "{:?}", rec.normal);
+        assert_float_absolute_eq!(rec.v, 0.5, 1e-5);
+        assert_eq!(rec.material_id, MaterialId(2));
+        // The caps, from above and from the inside.
+        let rec = hit(&cylinder, Point::new(0.2, 3.0, -3.0), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 2.0, 1e-5);
+        assert!(near(&rec.normal, &Vec3::UNIT_Y) && rec.front_face);
+        assert!((0.0..=1.0).contains(&rec.u) && (0.0..=1.0).contains(&rec.v));
+        let rec = hit(&cylinder, Point::new(0.0, 0.0, -3.0), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.0, 1e-5);
+        assert!(!rec.front_face);
+        // Past the caps, and beside.
+        assert!(hit(&cylinder, Point::new(0.0, 1.5, 0.0), -Vec3::UNIT_Z).is_none());
+        assert!(hit(&cylinder, Point::new(0.6, 0.0, 0.0), -Vec3::UNIT_Z).is_none());
+        // Along the axis, through both caps.
+        let rec = hit(&cylinder, Point::new(0.1, -3.0, -3.0), Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 2.0, 1e-5);
+
+        let bbox = cylinder.bounding_box();
+        assert!(near(&bbox.min, &Point::new(-0.5, -1.0, -3.5)));
+        assert!(near(&bbox.max, &Point::new(0.5, 1.0, -2.5)));
+    }
+
+    #[test]
+    fn test_tilted_cylinder() {
+        // Along the X = Y diagonal, seen from above its middle.
+        let (base, top) = (Point::new(-1.0, -1.0, 0.0), Point::new(1.0, 1.0, 0.0));
+        let cylinder = Cylinder::new(base, top, 0.5, MaterialId(0));
+        let rec = hit(&cylinder, Point::new(0.0, 0.0, 2.0), -Vec3::UNIT_Z).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.5, 1e-5);
+        assert!(near(&rec.normal, &Vec3::UNIT_Z), "{:?}", rec.normal);
+        // The top cap faces along the axis.
+        let rec = hit(&cylinder, Point::new(3.0, 3.0, 0.0), Vec3::new(-1.0, -1.0, 0.0)).unwrap();
+        assert!(near(&rec.normal, &Vec3::new(1.0, 1.0, 0.0).normed()), "{:?}", rec.normal);
+        // Tighter than the boxes of the spheres around the caps.
+        let bbox = cylinder.bounding_box();
+        assert_float_absolute_eq!(bbox.max.x, 1.0 + 0.5 * 0.5f32.sqrt(), 1e-3);
+        assert_float_absolute_eq!(bbox.max.z, 0.5, 1e-3);
+    }
+
+    #[test]
+    fn test_cone() {
+        // A pointed cone, 2 high on a base of radius 1.
+        let cone = Cone::new(Point::ZERO, Point::new(0.0, 2.0, 0.0), 1.0, 0.0, MaterialId(1));
+        // Half way up, the radius is 0.5, and the side leans back at 45° from the axis.
+        let rec = hit(&cone, Point::new(0.0, 1.0, 2.0), -Vec3::UNIT_Z).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.5, 1e-5);
+        let slanted = Vec3::new(0.0, 0.5, 1.0).normed();
+        assert!(near(&rec.normal, &slanted), "{:?}", rec.normal);
+        assert_float_absolute_eq!(rec.v, 0.5, 1e-5);
+        // Above the apex, the cone is missed, below the base cap is hit.
+        assert!(hit(&cone, Point::new(0.0, 2.1, 2.0), -Vec3::UNIT_Z).is_none());
+        let rec = hit(&cone, Point::new(0.3, -1.0, 0.0), Vec3::UNIT_Y).unwrap();
+        assert!(near(&rec.normal, &-Vec3::UNIT_Y) && rec.front_face);
+        // A ray along the side grazes it.
+        let along = Ray::new(Point::new(0.0, 2.0, 0.0), Vec3::new(0.0, -2.0, 1.0));
+        let mut rec = HitRecord::new();
+        assert!(cone.hit(&along, Interval::new(0.5, f32::INFINITY), &mut rec));
+
+        // A cut cone has a top cap.
+        let cut = Cone { top_radius: 0.5, ..cone };
+        let rec = hit(&cut, Point::new(0.2, 3.0, 0.0), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.0, 1e-5);
+        assert!(near(&rec.normal, &Vec3::UNIT_Y));
+        let bbox = cut.bounding_box();
+        assert!(near(&bbox.min, &Point::new(-1.0, 0.0, -1.0)));
+        assert!(near(&bbox.max, &Point::new(1.0, 2.0, 1.0)));
+    }
+
+    #[test]
+    fn test_capsule() {
+        let capsule =
+            Capsule::new(Point::new(-1.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), 0.5, MaterialId(3));
+        // The side, the rounded ends, and the tip of an end along the axis.
+        let rec = hit(&capsule, Point::new(0.0, 2.0, 0.0), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.5, 1e-5);
+        assert!(near(&rec.normal, &Vec3::UNIT_Y));
+        let rec = hit(&capsule, Point::new(1.3, 2.0, 0.0), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.6, 1e-5);
+        assert!(near(&rec.normal, &Vec3::new(0.6, 0.8, 0.0)), "{:?}", rec.normal);
+        let rec = hit(&capsule, Point::new(3.0, 0.0, 0.0), -Vec3::UNIT_X).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.5, 1e-5);
+        assert_float_absolute_eq!(rec.v, 1.0, 1e-5);
+        // Past the ends, and from the inside.
+        assert!(hit(&capsule, Point::new(1.6, 2.0, 0.0), -Vec3::UNIT_Y).is_none());
+        let rec = hit(&capsule, Point::ZERO, Vec3::UNIT_X).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.5, 1e-5);
+        assert!(!rec.front_face);
+
+        let bbox = capsule.bounding_box();
+        assert_eq!((bbox.min.x, bbox.max.x, bbox.max.y), (-1.5, 1.5, 0.5));
+    }
+}
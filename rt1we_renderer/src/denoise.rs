@@ -0,0 +1,407 @@
+//! Denoising of rendered images.
+//!
+//! A joint bilateral filter: each pixel is replaced by a weighted average of its neighbours,
+//! where the weights fall off with the distance, the color difference and, when available,
+//! the difference in the normal and albedo auxiliary images. The auxiliary images are
+//! noise-free, so they keep edges sharp even when the beauty image is very noisy.
+//!
+//! [`denoise_hdr`] filters the linear radiance before tone mapping instead, with an à-trous
+//! wavelet filter: a few passes of a small kernel whose taps spread twice as far at each
+//! pass, reaching far for the cost of a handful of neighbours.
+use crate::aov::Aovs;
+use crate::error::RtError;
+use crate::image::{ImageRGBA, ImageRGBF32};
+
+/// Parameters of the bilateral filter.
+#[derive(Debug, Copy, Clone)]
+pub struct DenoiseSettings {
+    /// Half-size of the filter window, in pixels.
+    pub radius: usize,
+    /// Spatial falloff, in pixels.
+    pub sigma_spatial: f32,
+    /// Color falloff, on `[0;1]` channel values.
+    pub sigma_color: f32,
+    /// Normal falloff, on `[0;1]` encoded normals.
+    pub sigma_normal: f32,
+    /// Albedo falloff, on `[0;1]` channel values.
+    pub sigma_albedo: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            radius: 3,
+            sigma_spatial: 2.0,
+            sigma_color: 0.25,
+            sigma_normal: 0.1,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+/// Optional noise-free images guiding the filter. They must have the beauty image size.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Guides<'a> {
+    pub normal: Option<&'a ImageRGBA>,
+    pub albedo: Option<&'a ImageRGBA>,
+}
+
+/// Parameters of the à-trous wavelet filter of [`denoise_hdr`].
+#[derive(Debug, Copy, Clone)]
+pub struct WaveletSettings {
+    /// Number of passes, each one spreading its taps twice as far: 5 passes span 125 pixels.
+    pub iterations: usize,
+    /// Color falloff, on the radiance compressed to `[0;1)` by `x / (1 + x)`. Halved at each
+    /// pass, as the noise left decreases.
+    pub sigma_color: f32,
+    /// Normal falloff, on unit normals.
+    pub sigma_normal: f32,
+    /// Albedo falloff, on `[0;1]` channel values.
+    pub sigma_albedo: f32,
+}
+
+impl Default for WaveletSettings {
+    fn default() -> Self {
+        WaveletSettings { iterations: 5, sigma_color: 0.25, sigma_normal: 0.3, sigma_albedo: 0.1 }
+    }
+}
+
+/// Optional noise-free linear images guiding [`denoise_hdr`], such as the raw buffers of
+/// [`crate::aov::Aovs`]. They must have the beauty image size.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct HdrGuides<'a> {
+    /// Normals on `[-1;1]`.
+    pub normal: Option<&'a ImageRGBF32>,
+    pub albedo: Option<&'a ImageRGBF32>,
+}
+
+impl<'a> HdrGuides<'a> {
+    /// The normal and albedo buffers of a render.
+    pub fn of(aovs: &'a Aovs) -> Self {
+        HdrGuides { normal: Some(&aovs.normal), albedo: Some(&aovs.albedo) }
+    }
+}
+
+fn rgb(im: &ImageRGBA, i: usize, j: usize) -> [f32; 3] {
+    let (r, g, b, _) = im.at(i, j);
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]
+}
+
+fn dist2(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+/// Weight of a guide image difference, `1.0` when there is no guide.
+fn guide_weight(
+    guide: Option<&ImageRGBA>, p: (usize, usize), q: (usize, usize), sigma: f32,
+) -> f32 {
+    match guide {
+        Some(im) => {
+            let d2 = dist2(&rgb(im, p.0, p.1), &rgb(im, q.0, q.1));
+            (-d2 / (2.0 * sigma * sigma)).exp()
+        }
+        None => 1.0,
+    }
+}
+
+fn rgb_f32(im: &ImageRGBF32, i: usize, j: usize) -> [f32; 3] {
+    let (r, g, b) = im.at(i, j);
+    [r, g, b]
+}
+
+/// Same as [`guide_weight`], for a linear guide image.
+fn hdr_guide_weight(
+    guide: Option<&ImageRGBF32>, p: (usize, usize), q: (usize, usize), sigma: f32,
+) -> f32 {
+    match guide {
+        Some(im) => {
+            let d2 = dist2(&rgb_f32(im, p.0, p.1), &rgb_f32(im, q.0, q.1));
+            (-d2 / (2.0 * sigma * sigma)).exp()
+        }
+        None => 1.0,
+    }
+}
+
+/// An error if a guide image does not have the size of the beauty image.
+fn check_size(name: &str, size: (usize, usize), beauty: (usize, usize)) -> Result<(), RtError> {
+    if size == beauty {
+        return Ok(());
+    }
+    Err(RtError::ImageSize { name: name.to_string(), size, expected: beauty })
+}
+
+/// Denoise an image.
+///
+/// # Arguments
+/// - `beauty` - The noisy image.
+/// - `guides` - Auxiliary images, improving edge preservation.
+/// - `settings` - Filter parameters.
+///
+/// # Notes
+/// The alpha channel is copied unchanged.
+pub fn denoise(
+    beauty: &ImageRGBA, guides: &Guides, settings: &DenoiseSettings,
+) -> Result<ImageRGBA, RtError> {
+    for (name, guide) in [("normal", guides.normal), ("albedo", guides.albedo)] {
+        if let Some(im) = guide {
+            check_size(name, (im.width, im.height), (beauty.width, beauty.height))?;
+        }
+    }
+
+    let (w, h) = (beauty.width, beauty.height);
+    let r = settings.radius as isize;
+    let two_ss2 = 2.0 * settings.sigma_spatial * settings.sigma_spatial;
+    let two_sc2 = 2.0 * settings.sigma_color * settings.sigma_color;
+    let mut out = ImageRGBA::new(w, h);
+
+    for j in 0..h {
+        for i in 0..w {
+            let center = rgb(beauty, i, j);
+            let mut sum = [0.0f32; 3];
+            let mut total = 0.0f32;
+
+            for dj in -r..=r {
+                for di in -r..=r {
+                    let (qi, qj) = (i as isize + di, j as isize + dj);
+                    if qi < 0 || qj < 0 || qi >= w as isize || qj >= h as isize {
+                        continue;
+                    }
+                    let (qi, qj) = (qi as usize, qj as usize);
+                    let c = rgb(beauty, qi, qj);
+
+                    let weight = (-((di * di + dj * dj) as f32) / two_ss2).exp()
+                        * (-dist2(&center, &c) / two_sc2).exp()
+                        * guide_weight(guides.normal, (i, j), (qi, qj), settings.sigma_normal)
+                        * guide_weight(guides.albedo, (i, j), (qi, qj), settings.sigma_albedo);
+
+                    for k in 0..3 {
+                        sum[k] += weight * c[k];
+                    }
+                    total += weight;
+                }
+            }
+
+            let px = sum.map(|v| ((v / total).clamp(0.0, 1.0) * 255.0).round() as u8);
+            let (_, _, _, a) = beauty.at(i, j);
+            out.put(i, j, px[0], px[1], px[2], a);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Denoise the linear radiance of a render, before tone mapping.
+///
+/// # Arguments
+/// - `beauty` - The noisy radiance.
+/// - `guides` - Auxiliary images, improving edge preservation.
+/// - `settings` - Filter parameters.
+pub fn denoise_hdr(
+    beauty: &ImageRGBF32, guides: &HdrGuides, settings: &WaveletSettings,
+) -> Result<ImageRGBF32, RtError> {
+    for (name, guide) in [("normal", guides.normal), ("albedo", guides.albedo)] {
+        if let Some(im) = guide {
+            check_size(name, (im.width, im.height), (beauty.width, beauty.height))?;
+        }
+    }
+
+    // B3 spline, the kernel of the wavelet.
+    const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+    let compress = |c: [f32; 3]| c.map(|v| v.max(0.0) / (1.0 + v.max(0.0)));
+    let (w, h) = (beauty.width, beauty.height);
+    let mut current = beauty.clone();
+
+    for pass in 0..settings.iterations {
+        let step = 1isize << pass;
+        let sigma_color = settings.sigma_color / (1 << pass) as f32;
+        let two_sc2 = 2.0 * sigma_color * sigma_color;
+        let mut next = ImageRGBF32::new(w, h);
+
+        for j in 0..h {
+            for i in 0..w {
+                let center = compress(rgb_f32(&current, i, j));
+                let mut sum = [0.0f32; 3];
+                let mut total = 0.0f32;
+
+                for (dj, kj) in KERNEL.iter().enumerate() {
+                    for (di, ki) in KERNEL.iter().enumerate() {
+                        let qi = i as isize + (di as isize - 2) * step;
+                        let qj = j as isize + (dj as isize - 2) * step;
+                        if qi < 0 || qj < 0 || qi >= w as isize || qj >= h as isize {
+                            continue;
+                        }
+                        let (qi, qj) = (qi as usize, qj as usize);
+                        let c = rgb_f32(&current, qi, qj);
+
+                        let weight = ki
+                            * kj
+                            * (-dist2(&center, &compress(c)) / two_sc2).exp()
+                            * hdr_guide_weight(
+                                guides.normal,
+                                (i, j),
+                                (qi, qj),
+                                settings.sigma_normal,
+                            )
+                            * hdr_guide_weight(
+                                guides.albedo,
+                                (i, j),
+                                (qi, qj),
+                                settings.sigma_albedo,
+                            );
+
+                        for k in 0..3 {
+                            sum[k] += weight * c[k];
+                        }
+                        total += weight;
+                    }
+                }
+
+                // The center tap always weighs, the total is never zero.
+                next.put(i, j, sum[0] / total, sum[1] / total, sum[2] / total);
+            }
+        }
+        current = next;
+    }
+
+    Ok(current)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::aov::render_aovs;
+    use crate::denoise::{
+        denoise, denoise_hdr, DenoiseSettings, Guides, HdrGuides, WaveletSettings,
+    };
+    use crate::error::RtError;
+    use crate::image::{ImageRGBA, ImageRGBF32};
+    use crate::render::{render_hdr, Scene};
+    use crate::settings::RenderSettings;
+    use rand::{Rng, SeedableRng};
+
+    /// Gray image with uniform noise.
+    fn noisy(w: usize, h: usize, level: u8) -> ImageRGBA {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut im = ImageRGBA::new(w, h);
+        for j in 0..h {
+            for i in 0..w {
+                let v = level.saturating_add(rng.gen_range(0..40)).saturating_sub(20);
+                im.put(i, j, v, v, v, 255);
+            }
+        }
+        im
+    }
+
+    fn variance(im: &ImageRGBA, columns: std::ops::Range<usize>) -> f32 {
+        let values: Vec<f32> = (0..im.height)
+            .flat_map(|j| columns.clone().map(move |i| (i, j)))
+            .map(|(i, j)| im.at(i, j).0 as f32)
+            .collect();
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32
+    }
+
+    #[test]
+    fn test_constant_image_is_unchanged() {
+        let im = ImageRGBA::new(8, 8);
+        let out = denoise(&im, &Guides::default(), &DenoiseSettings::default()).unwrap();
+        assert_eq!(out.pixels, im.pixels);
+    }
+
+    #[test]
+    fn test_noise_is_reduced() {
+        let im = noisy(16, 16, 128);
+        let out = denoise(&im, &Guides::default(), &DenoiseSettings::default()).unwrap();
+        assert!(variance(&out, 0..16) < 0.25 * variance(&im, 0..16));
+    }
+
+    #[test]
+    fn test_normal_guide_preserves_edges() {
+        // Left half dark, right half bright, with a normal discontinuity in between.
+        let mut im = noisy(16, 8, 60);
+        let mut normal = ImageRGBA::new(16, 8);
+        for j in 0..8 {
+            for i in 8..16 {
+                let (v, _, _, _) = im.at(i, j);
+                im.put(i, j, v + 100, v + 100, v + 100, 255);
+                normal.put(i, j, 255, 128, 128, 255);
+            }
+        }
+        let settings = DenoiseSettings { sigma_color: 10.0, ..DenoiseSettings::default() };
+
+        let guided = Guides { normal: Some(&normal), albedo: None };
+        let out = denoise(&im, &guided, &settings).unwrap();
+        assert!(out.at(7, 4).0 < 90);
+        assert!(out.at(8, 4).0 > 130);
+
+        // Without the guide and with a huge color sigma, the edge gets blurred.
+        let blurred = denoise(&im, &Guides::default(), &settings).unwrap();
+        assert!(blurred.at(7, 4).0 > out.at(7, 4).0);
+    }
+
+    #[test]
+    fn test_guide_size_mismatch_is_an_error() {
+        let im = ImageRGBA::new(8, 8);
+        let albedo = ImageRGBA::new(4, 4);
+        let guides = Guides { normal: None, albedo: Some(&albedo) };
+        let err = denoise(&im, &guides, &DenoiseSettings::default()).err().unwrap();
+        assert!(matches!(err, RtError::ImageSize { size: (4, 4), expected: (8, 8), .. }));
+    }
+
+    /// Gray radiance with uniform noise, 0.5 around `level`.
+    fn noisy_hdr(w: usize, h: usize, level: f32) -> ImageRGBF32 {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut im = ImageRGBF32::new(w, h);
+        for j in 0..h {
+            for i in 0..w {
+                let v = level + rng.gen_range(-0.5..0.5);
+                im.put(i, j, v, v, v);
+            }
+        }
+        im
+    }
+
+    fn variance_hdr(im: &ImageRGBF32) -> f32 {
+        let values: Vec<f32> = im.pixels.iter().step_by(3).copied().collect();
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32
+    }
+
+    #[test]
+    fn test_denoise_hdr() {
+        let mut constant = ImageRGBF32::new(8, 8);
+        constant.pixels.fill(4.0);
+        let out = denoise_hdr(&constant, &HdrGuides::default(), &WaveletSettings::default());
+        assert!(out.unwrap().pixels.iter().all(|v| (v - 4.0).abs() < 1e-5));
+
+        // Radiance above 1 is filtered as well, without clipping.
+        let im = noisy_hdr(32, 32, 2.0);
+        let out = denoise_hdr(&im, &HdrGuides::default(), &WaveletSettings::default()).unwrap();
+        assert!(variance_hdr(&out) < 0.1 * variance_hdr(&im));
+        let mean = out.pixels.iter().sum::<f32>() / out.pixels.len() as f32;
+        assert_float_absolute_eq!(mean, 2.0, 0.05);
+    }
+
+    #[test]
+    fn test_denoise_hdr_with_aovs() {
+        // A noisy render gets closer to a converged one, keeping the edges of the spheres.
+        let settings = |spp: usize| {
+            let builder = RenderSettings::builder().resolution(32, 18).max_depth(8).seed(Some(2));
+            builder.samples_per_pixel(spp).build().unwrap()
+        };
+        let scene = Scene::demo();
+        let noisy = render_hdr(&scene, &settings(2)).unwrap();
+        let reference = render_hdr(&scene, &settings(256)).unwrap();
+        let aovs = render_aovs(&scene, &settings(2)).unwrap();
+        let out = denoise_hdr(&noisy, &HdrGuides::of(&aovs), &WaveletSettings::default()).unwrap();
+
+        let error = |im: &ImageRGBF32| {
+            let pairs = im.pixels.iter().zip(&reference.pixels);
+            pairs.map(|(a, b)| (a.min(1.0) - b.min(1.0)).powi(2)).sum::<f32>()
+        };
+        assert!(error(&out) < 0.6 * error(&noisy));
+
+        let small = ImageRGBF32::new(4, 4);
+        let guides = HdrGuides { normal: Some(&small), albedo: None };
+        assert!(denoise_hdr(&noisy, &guides, &WaveletSettings::default()).is_err());
+    }
+}
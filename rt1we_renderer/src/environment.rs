@@ -0,0 +1,275 @@
+//! Environment maps: a panorama around the scene, as the sky and the surroundings photographed
+//! in high dynamic range, lighting the scene from all directions.
+//!
+//! The panorama is an equirectangular image, the longitude across and the latitude down. Its
+//! bright regions, as the sun or windows, are sampled by the diffuse bounces in proportion to
+//! their brightness, so that they light the scene without noise.
+use crate::error::RtError;
+use crate::geometry::{Color, Vec3};
+use crate::image::ImageRGBF32;
+use crate::imageio::imread_hdr;
+use crate::pdf::Pdf;
+use crate::sampler::Sampler;
+use rand::Rng;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Where the light of the pixels goes: each pixel is picked in proportion to its brightness
+/// and to the solid angle it covers.
+#[derive(Debug, PartialEq)]
+struct PixelDistribution {
+    /// Cumulated probability of the rows, from 0 to 1, one more than the rows.
+    rows: Vec<f32>,
+    /// Cumulated probability of the pixels of each row within it, from 0 to 1, one more than
+    /// the pixels of the row, row after row.
+    columns: Vec<f32>,
+    /// Probability of each pixel, row after row.
+    probabilities: Vec<f32>,
+}
+
+/// Running sums of `weights`, divided by their total, starting at 0. Even when all the
+/// weights are zero.
+fn cumulated(weights: &[f32]) -> Vec<f32> {
+    let total: f32 = weights.iter().sum();
+    let mut sums = Vec::with_capacity(weights.len() + 1);
+    sums.push(0.0);
+    let mut sum = 0.0;
+    for (k, w) in weights.iter().enumerate() {
+        sum += if total > 0.0 { w / total } else { 1.0 / weights.len() as f32 };
+        sums.push(if k + 1 == weights.len() { 1.0 } else { sum });
+    }
+    sums
+}
+
+/// Index of the interval of the cumulated probabilities `sums` holding `u`.
+fn interval_of(sums: &[f32], u: f32) -> usize {
+    (sums.partition_point(|&s| s <= u).max(1) - 1).min(sums.len() - 2)
+}
+
+impl PixelDistribution {
+    fn new(image: &ImageRGBF32) -> Self {
+        let (width, height) = (image.width, image.height);
+        let mut weights = vec![0.0; width * height];
+        for j in 0..height {
+            // Rows near the poles cover a smaller part of the sphere.
+            let sin = (PI * (j as f32 + 0.5) / height as f32).sin();
+            for i in 0..width {
+                let (r, g, b) = image.at(i, j);
+                weights[j * width + i] = (r + g + b).max(0.0) / 3.0 * sin;
+            }
+        }
+        let row_weights: Vec<f32> = weights.chunks(width).map(|row| row.iter().sum()).collect();
+        let rows = cumulated(&row_weights);
+        let columns = weights.chunks(width).flat_map(cumulated).collect();
+        let total: f32 = row_weights.iter().sum();
+        let probabilities = if total > 0.0 {
+            weights.iter().map(|w| w / total).collect()
+        } else {
+            vec![1.0 / (width * height) as f32; width * height]
+        };
+        PixelDistribution { rows, columns, probabilities }
+    }
+}
+
+/// A panorama lighting the scene, see the [module](self) documentation.
+///
+/// Copies share the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentMap {
+    /// Linear radiance, from the top left corner as in image files.
+    image: Arc<ImageRGBF32>,
+    distribution: Arc<PixelDistribution>,
+    /// Factor of the radiance of the image.
+    intensity: f32,
+    /// Rotation of the panorama around the vertical axis, in degrees.
+    rotation: f32,
+    /// File the image was loaded from, so that scene files can refer to it.
+    path: Option<String>,
+}
+
+impl EnvironmentMap {
+    /// # Returns
+    /// The environment map, or an error if the image has no pixels.
+    pub fn new(image: ImageRGBF32) -> Result<Self, RtError> {
+        if image.width == 0 || image.height == 0 {
+            return Err(RtError::Texture("an environment map needs at least one pixel".into()));
+        }
+        Ok(EnvironmentMap {
+            distribution: Arc::new(PixelDistribution::new(&image)),
+            image: Arc::new(image),
+            intensity: 1.0,
+            rotation: 0.0,
+            path: None,
+        })
+    }
+
+    /// Load the environment map from an image file, see [`imread_hdr`] for the formats.
+    pub fn load(fpath: &str) -> Result<Self, RtError> {
+        let map = EnvironmentMap::new(imread_hdr(fpath)?)?;
+        Ok(EnvironmentMap { path: Some(fpath.to_string()), ..map })
+    }
+
+    /// The same panorama, its radiance scaled by `intensity`.
+    pub fn with_intensity(self, intensity: f32) -> Self {
+        EnvironmentMap { intensity, ..self }
+    }
+
+    /// The same panorama, turned by `rotation` degrees around the vertical axis.
+    pub fn with_rotation(self, rotation: f32) -> Self {
+        EnvironmentMap { rotation, ..self }
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// The file the image was loaded from, `None` for images built in memory.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height
+    }
+
+    /// `dir` turned around the vertical axis by `degrees`.
+    fn turned(dir: &Vec3, degrees: f32) -> Vec3 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Vec3::new(dir.x * cos + dir.z * sin, dir.y, -dir.x * sin + dir.z * cos)
+    }
+
+    /// Coordinates of `dir` in the image, from 0 to 1, across from the -X axis turning right
+    /// as seen from the inside, through -Z, and down from the zenith.
+    fn image_coordinates(&self, dir: &Vec3) -> (f32, f32) {
+        let d = Self::turned(&dir.normed(), -self.rotation);
+        let phi = d.z.atan2(d.x) + PI;
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    /// Pixel of the image seen along `dir`.
+    fn pixel(&self, dir: &Vec3) -> (usize, usize) {
+        let (x, y) = self.image_coordinates(dir);
+        let i = ((x * self.image.width as f32) as usize).min(self.image.width - 1);
+        let j = ((y * self.image.height as f32) as usize).min(self.image.height - 1);
+        (i, j)
+    }
+
+    /// Light coming from `dir`: the pixel of the image seen in that direction, unfiltered.
+    pub fn radiance(&self, dir: &Vec3) -> Color {
+        let (i, j) = self.pixel(dir);
+        let (r, g, b) = self.image.at(i, j);
+        Color::new(r, g, b) * self.intensity
+    }
+}
+
+impl Pdf for EnvironmentMap {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let d = direction.normed();
+        let sin = (d.x * d.x + d.z * d.z).sqrt();
+        if sin <= 0.0 {
+            return 0.0;
+        }
+        let (i, j) = self.pixel(direction);
+        let probability = self.distribution.probabilities[j * self.image.width + i];
+        // Each pixel spreads its probability evenly over its rectangle of the image, which
+        // covers a solid angle shrinking with the sine of the latitude.
+        let pixels = (self.image.width * self.image.height) as f32;
+        probability * pixels / (2.0 * PI * PI * sin)
+    }
+
+    fn generate(&self, rng: &mut Sampler) -> Vec3 {
+        let (width, height) = (self.image.width, self.image.height);
+        let distribution = &self.distribution;
+        let j = interval_of(&distribution.rows, rng.gen());
+        let i =
+            interval_of(&distribution.columns[j * (width + 1)..(j + 1) * (width + 1)], rng.gen());
+        let x = (i as f32 + rng.gen::<f32>()) / width as f32;
+        let y = (j as f32 + rng.gen::<f32>()) / height as f32;
+        let (phi, theta) = (2.0 * PI * x, PI * y);
+        let d = Vec3::new(-phi.cos() * theta.sin(), theta.cos(), -phi.sin() * theta.sin());
+        Self::turned(&d, self.rotation)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::environment::EnvironmentMap;
+    use crate::geometry::{dot, random_unit_vector, Color, Vec3};
+    use crate::image::ImageRGBF32;
+    use crate::pdf::Pdf;
+    use crate::sampler::Sampler;
+    use std::f32::consts::PI;
+
+    /// A black panorama 16 by 8 pixels, but for a bright pixel, above the horizon.
+    pub(crate) fn bright_spot() -> EnvironmentMap {
+        let mut image = ImageRGBF32::new(16, 8);
+        image.put(4, 2, 100.0, 50.0, 25.0);
+        EnvironmentMap::new(image).unwrap()
+    }
+
+    #[test]
+    fn test_environment_lookup() {
+        assert!(EnvironmentMap::new(ImageRGBF32::new(0, 8)).is_err());
+        // The top rows are above, the bottom rows below.
+        let mut image = ImageRGBF32::new(4, 2);
+        for i in 0..4 {
+            image.put(i, 0, 0.0, 0.0, 1.0);
+            image.put(i, 1, 0.0, 1.0, 0.0);
+        }
+        let map = EnvironmentMap::new(image).unwrap().with_intensity(2.0);
+        assert_eq!(map.radiance(&Vec3::UNIT_Y), Color::new(0.0, 0.0, 2.0));
+        assert_eq!(map.radiance(&Vec3::new(1.0, -0.1, 0.0)), Color::new(0.0, 2.0, 0.0));
+        assert_eq!((map.width(), map.height(), map.path()), (4, 2, None));
+
+        // The bright spot is in the direction it is sampled towards, and turns with the map.
+        let map = bright_spot();
+        let mut rng = Sampler::new(0);
+        let spot = map.generate(&mut rng);
+        assert!(spot.y > 0.3 && spot.y < 0.75, "{spot:?}");
+        assert_eq!(map.radiance(&spot), Color::new(100.0, 50.0, 25.0));
+        let turned = map.clone().with_rotation(90.0);
+        assert_eq!(turned.radiance(&spot), Color::BLACK);
+        let spot = turned.generate(&mut rng);
+        assert_eq!(turned.radiance(&spot), Color::new(100.0, 50.0, 25.0));
+        assert_eq!(turned.rotation(), 90.0);
+    }
+
+    #[test]
+    fn test_environment_sampling() {
+        let mut rng = Sampler::new(0);
+        let mut image = ImageRGBF32::new(16, 8);
+        for j in 0..8 {
+            for i in 0..16 {
+                image.put(i, j, (i * j) as f32, 1.0, 0.0);
+            }
+        }
+        let black = EnvironmentMap::new(ImageRGBF32::new(16, 8)).unwrap();
+        for map in [bright_spot(), EnvironmentMap::new(image).unwrap(), black] {
+            // The directions are sampled with the density of the map.
+            for _ in 0..1000 {
+                let d = map.generate(&mut rng);
+                assert_float_absolute_eq!(d.len(), 1.0, 1e-5);
+                assert!(map.value(&d) > 0.0);
+            }
+            // Which integrates to 1 over the sphere.
+            let n = 200_000;
+            let sum: f32 = (0..n).map(|_| map.value(&random_unit_vector(&mut rng))).sum();
+            assert_float_absolute_eq!(4.0 * PI * sum / n as f32, 1.0, 0.05);
+        }
+
+        // A black map is sampled evenly over the image, as much above as below the horizon.
+        let black = EnvironmentMap::new(ImageRGBF32::new(16, 8)).unwrap();
+        let n = 10_000;
+        let up = (0..n).filter(|_| dot(&black.generate(&mut rng), &Vec3::UNIT_Y) > 0.0).count();
+        assert!((4500..5500).contains(&up), "{up}");
+    }
+}
@@ -0,0 +1,62 @@
+//! Errors of the renderer, for the callers to report instead of crashing.
+use crate::ppmio::PpmError;
+
+/// Error of a file which cannot be read or written, or of a render which cannot start.
+#[derive(Debug, thiserror::Error)]
+pub enum RtError {
+    /// A file cannot be opened, read or written.
+    #[error("{path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A PPM file is malformed.
+    #[error("{path}: {source}")]
+    Ppm {
+        path: String,
+        #[source]
+        source: PpmError,
+    },
+    /// An image file cannot be decoded or encoded.
+    #[error("{path}: {source}")]
+    Image {
+        path: String,
+        #[source]
+        source: image::ImageError,
+    },
+    /// The file extension is not one of an image format.
+    #[error("unsupported image format: {0}")]
+    UnsupportedFormat(String),
+    /// A scene file is not valid JSON, or does not have the expected fields.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A scene description is invalid, one line per problem, or the scene cannot be saved.
+    #[error("{0}")]
+    Scene(String),
+    /// An image cannot be used as a texture.
+    #[error("{0}")]
+    Texture(String),
+    /// An image does not have the size of the image it goes with.
+    #[error("{name} image is {}x{}, expected {}x{}", .size.0, .size.1, .expected.0, .expected.1)]
+    ImageSize { name: String, size: (usize, usize), expected: (usize, usize) },
+    /// A mesh or a heightfield is malformed.
+    #[error("{0}")]
+    Geometry(String),
+    /// Render settings are invalid, one line per invalid setting.
+    #[error("{0}")]
+    Settings(String),
+    /// An output path template is invalid, or the path cannot be written to.
+    #[error("{0}")]
+    Output(String),
+    /// The threads of a render cannot be started.
+    #[error("cannot start the render threads: {0}")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+}
+
+impl RtError {
+    /// Error of an I/O operation on `path`.
+    pub fn io(path: impl Into<String>, source: std::io::Error) -> Self {
+        RtError::Io { path: path.into(), source }
+    }
+}
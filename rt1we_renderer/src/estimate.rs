@@ -0,0 +1,164 @@
+//! Render time and memory estimation, to know what a render costs before starting it.
+use crate::render::{trace_pixels, Scene};
+use crate::settings::RenderSettings;
+use crate::stats::SceneStats;
+use crate::view::OrbitView;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Predicted cost of an animation render.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderEstimate {
+    /// Number of pixels actually traced.
+    pub probes: usize,
+    /// Time spent tracing the probes.
+    pub probe_time: Duration,
+    /// Extrapolated time to render one frame, while the other concurrent frames render.
+    pub frame_time: Duration,
+    pub frames: usize,
+    /// Number of frames rendered concurrently.
+    pub jobs: usize,
+    /// Number of threads shared by the concurrent frames.
+    pub threads: usize,
+    /// Peak memory of the render, in bytes.
+    pub memory: usize,
+}
+
+impl RenderEstimate {
+    /// Extrapolated time to render all the frames.
+    pub fn total_time(&self) -> Duration {
+        self.frame_time * self.frames.div_ceil(self.jobs.max(1)) as u32
+    }
+}
+
+/// Pixel coordinates of a regular grid of about `count` probes over the image.
+///
+/// Probes are spread over the cameras in turn, so that the estimate accounts for how the cost
+/// changes during an animation.
+fn probe_grid(
+    width: usize, height: usize, count: usize, positions: usize,
+) -> Vec<(usize, usize, usize)> {
+    let step = ((width * height) as f32 / count.max(1) as f32).sqrt().max(1.0);
+    let columns = ((width as f32 / step).ceil() as usize).max(1);
+    let rows = ((height as f32 / step).ceil() as usize).max(1);
+
+    let mut probes = Vec::with_capacity(columns * rows);
+    for r in 0..rows {
+        for c in 0..columns {
+            let i = ((c as f32 + 0.5) * step) as usize;
+            let j = ((r as f32 + 0.5) * step) as usize;
+            let k = probes.len() % positions.max(1);
+            probes.push((i.min(width - 1), j.min(height - 1), k));
+        }
+    }
+    probes
+}
+
+/// Estimate the cost of rendering a scene, tracing a sparse grid of pixels.
+///
+/// # Arguments
+/// - `scene` - The scene, its own camera is ignored.
+/// - `settings` - Settings of the render, its threads are shared by the concurrent frames.
+/// - `views` - Camera of each frame.
+/// - `jobs` - Number of frames rendered concurrently.
+/// - `probes` - About how many pixels to trace.
+pub fn estimate(
+    scene: &Scene, settings: &RenderSettings, views: &[OrbitView], jobs: usize, probes: usize,
+) -> RenderEstimate {
+    let (width, height) = (settings.width, settings.height);
+    let grid = probe_grid(width, height, probes, views.len());
+
+    let start = Instant::now();
+    trace_pixels(scene, settings, views, &grid);
+    let probe_time = start.elapsed();
+
+    // The probes are traced on a single thread, the threads split the pixels of the
+    // concurrent frames.
+    let jobs = jobs.max(1).min(views.len().max(1));
+    let threads = settings.thread_count();
+    let per_pixel = probe_time.as_secs_f64() / grid.len() as f64;
+    let frame_time =
+        Duration::from_secs_f64(per_pixel * (width * height * jobs) as f64 / threads as f64);
+
+    // Each concurrent frame holds its own scene, BVH, and the image with its flipped copy.
+    let scene_memory = SceneStats::of(scene).memory;
+    let memory = jobs * (scene_memory + 2 * width * height * 4);
+
+    RenderEstimate {
+        probes: grid.len(),
+        probe_time,
+        frame_time,
+        frames: views.len(),
+        jobs,
+        threads,
+        memory,
+    }
+}
+
+impl fmt::Display for RenderEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Probe pixels   : {} traced in {:?}", self.probes, self.probe_time)?;
+        writeln!(f, "Time per frame : {:.1?}", self.frame_time)?;
+        writeln!(
+            f,
+            "Frames         : {} ({} at a time, on {} threads)",
+            self.frames, self.jobs, self.threads
+        )?;
+        writeln!(f, "Total time     : {:.1?}", self.total_time())?;
+        write!(f, "Memory (est.)  : {:.1} MiB", self.memory as f32 / (1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::estimate::{estimate, probe_grid, RenderEstimate};
+    use crate::geometry::Point;
+    use crate::render::Scene;
+    use crate::settings::RenderSettings;
+    use crate::view::OrbitView;
+
+    #[test]
+    fn test_probe_grid_covers_the_image() {
+        let grid = probe_grid(160, 90, 100, 3);
+        assert!(grid.len() >= 100 && grid.len() <= 150, "{}", grid.len());
+        assert!(grid.iter().all(|&(i, j, k)| i < 160 && j < 90 && k < 3));
+        assert!(grid.iter().any(|&(i, _, _)| i > 140));
+        assert!(grid.iter().any(|&(_, j, _)| j > 75));
+        assert_eq!(grid.iter().filter(|&&(_, _, k)| k == 2).count(), grid.len() / 3);
+    }
+
+    #[test]
+    fn test_probe_grid_on_tiny_images() {
+        assert_eq!(probe_grid(1, 1, 100, 1), vec![(0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let target = Point::new(0.0, 0.0, -1.0);
+        let views = [
+            OrbitView::looking_at(&Point::new(0.0, 0.0, 0.0), &target),
+            OrbitView::looking_at(&Point::new(0.0, 0.5, 0.0), &target),
+        ];
+        let settings = |threads: usize| {
+            let builder = RenderSettings::builder().resolution(32, 18).max_depth(5);
+            builder.samples_per_pixel(2).threads(Some(threads)).build().unwrap()
+        };
+        let est = estimate(&Scene::demo(), &settings(1), &views, 4, 20);
+
+        assert_eq!(est.frames, 2);
+        assert_eq!(est.jobs, 2);
+        assert!(est.probes >= 20);
+        assert!(est.frame_time >= est.probe_time.mul_f64(0.99));
+        assert_eq!(est.total_time(), est.frame_time);
+        assert!(est.memory > 2 * 2 * 32 * 18 * 4);
+        assert!(est.to_string().contains("Frames         : 2 (2 at a time, on 1 threads)"));
+
+        // The threads split the work.
+        let single = estimate(&Scene::demo(), &settings(1), &views, 1, 20);
+        let per_probe =
+            |e: &RenderEstimate| e.frame_time.as_secs_f64() / e.probe_time.as_secs_f64();
+        let parallel = estimate(&Scene::demo(), &settings(4), &views, 1, 20);
+        let ratio = per_probe(&single) / per_probe(&parallel);
+        assert!((ratio - 4.0).abs() < 1e-3, "{ratio}");
+    }
+}
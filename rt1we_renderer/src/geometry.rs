@@ -58,16 +58,12 @@ impl Vec3 {
     }
 
     /// Returns a random vector with values in the `[0;1]` range.
-    pub fn random() -> Vec3 {
-        let mut rng = rand::thread_rng();
-
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         Vec3 { x: rng.gen(), y: rng.gen(), z: rng.gen() }
     }
 
     /// Returns a random vector with values in a given range.
-    pub fn random_range(lo: f32, hi: f32) -> Vec3 {
-        let mut rng = rand::thread_rng();
-
+    pub fn random_range<R: Rng + ?Sized>(lo: f32, hi: f32, rng: &mut R) -> Vec3 {
         Vec3 { x: rng.gen_range(lo..hi), y: rng.gen_range(lo..hi), z: rng.gen_range(lo..hi) }
     }
 
@@ -77,9 +73,9 @@ impl Vec3 {
     }
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
+pub fn random_in_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
     loop {
-        let v = Vec3::random_range(-1.0, 1.0);
+        let v = Vec3::random_range(-1.0, 1.0, rng);
 
         if v.len_squared() < 1.0 {
             break v;
@@ -87,25 +83,56 @@ pub fn random_in_unit_sphere() -> Vec3 {
     }
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    random_in_unit_sphere().normed()
+/// Random point in the unit disk of the z = 0 plane, to sample the lens of a camera.
+///
+/// The square of two random numbers is mapped onto the disk with the concentric mapping of
+/// Shirley and Chiu, which keeps the stratification of low-discrepancy samples, and always
+/// takes two numbers.
+pub fn random_in_unit_disk<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
+    let (a, b) = (2.0 * rng.gen::<f32>() - 1.0, 2.0 * rng.gen::<f32>() - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::ZERO;
+    }
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, std::f32::consts::FRAC_PI_4 * (b / a))
+    } else {
+        (b, std::f32::consts::FRAC_PI_2 - std::f32::consts::FRAC_PI_4 * (a / b))
+    };
+    Vec3::new(r * phi.cos(), r * phi.sin(), 0.0)
+}
+
+pub fn random_unit_vector<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
+    random_in_unit_sphere(rng).normed()
 }
 
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
-    v - &(2.0 * &(dot(&n, &v) * n))
+    v - &(2.0 * &(dot(n, v) * n))
 }
 
-pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f32) -> Vec3 {
+/// Refract a unit vector through a surface.
+///
+/// # Arguments
+/// - `uv` - Incoming unit direction.
+/// - `n` - Unit normal, facing the incoming direction.
+/// - `etai_over_etat` - Ratio of the refractive indices, incoming side over outgoing side.
+///
+/// # Returns
+/// The refracted unit direction, or `None` when the ray cannot get through, under total
+/// internal reflection.
+pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f32) -> Option<Vec3> {
     let cos_theta = dot(&-uv, n).min(1.0);
     let r_out_perp = etai_over_etat * (uv + &(cos_theta * n));
-    let r_out_parallel = -1.0 * (1.0 - r_out_perp.len_squared()).abs().sqrt() * n;
-    r_out_perp + r_out_parallel
+    let parallel_len_squared = 1.0 - r_out_perp.len_squared();
+    if parallel_len_squared < 0.0 {
+        return None;
+    }
+    Some(r_out_perp - parallel_len_squared.sqrt() * n)
 }
 
 // older method
-pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
-    if dot(&in_unit_sphere, &normal) > 0.0 {
+pub fn random_in_hemisphere<R: Rng + ?Sized>(normal: &Vec3, rng: &mut R) -> Vec3 {
+    let in_unit_sphere = random_in_unit_sphere(rng);
+    if dot(&in_unit_sphere, normal) > 0.0 {
         in_unit_sphere
     } else {
         -in_unit_sphere
@@ -134,7 +161,7 @@ impl ops::Add for Vec3 {
 /// let q = Vec3{x:0.5, y:0.5, z:0.5};
 /// let r = &p + &q;
 /// ```
-impl<'a, 'b> ops::Add<&'a Vec3> for &'b Vec3 {
+impl<'a> ops::Add<&'a Vec3> for &Vec3 {
     type Output = Vec3;
     fn add(self, other: &'a Vec3) -> Vec3 {
         Vec3 { x: self.x + other.x, y: self.y + other.y, z: self.z + other.z }
@@ -200,7 +227,7 @@ impl SubAssign for Vec3 {
 /// let q = Vec3{x: 0.5, y: 0.5, z: 0.5};
 /// let r = &p - &q;
 /// ```
-impl<'a, 'b> ops::Sub<&'a Vec3> for &'b Vec3 {
+impl<'a> ops::Sub<&'a Vec3> for &Vec3 {
     type Output = Vec3;
     fn sub(self, other: &'a Vec3) -> Vec3 {
         Vec3 { x: self.x - other.x, y: self.y - other.y, z: self.z - other.z }
@@ -245,7 +272,7 @@ impl ops::Mul<f32> for Vec3 {
 /// let p = Vec3 {x: 1.0, y: 2.0, z: 3.0};
 /// let q = p * 3.5;
 /// ```
-impl<'a> ops::Mul<f32> for &'a Vec3 {
+impl ops::Mul<f32> for &Vec3 {
     type Output = Vec3;
     fn mul(self, s: f32) -> Vec3 {
         Vec3 { x: self.x * s, y: self.y * s, z: self.z * s }
@@ -267,7 +294,7 @@ impl ops::Mul<Vec3> for f32 {
     }
 }
 
-impl<'a> ops::Mul<&'a Vec3> for f32 {
+impl ops::Mul<&Vec3> for f32 {
     type Output = Vec3;
     fn mul(self, v: &Vec3) -> Vec3 {
         Vec3 { x: self * v.x, y: self * v.y, z: self * v.z }
@@ -297,7 +324,7 @@ impl ops::DivAssign<f32> for Vec3 {
     }
 }
 
-impl<'a> ops::Div<f32> for &'a Vec3 {
+impl ops::Div<f32> for &Vec3 {
     type Output = Vec3;
     fn div(self, s: f32) -> Vec3 {
         Vec3 { x: self.x / s, y: self.y / s, z: self.z / s }
@@ -312,7 +339,7 @@ impl<'a> ops::Div<f32> for &'a Vec3 {
 /// let p = Vec3 {x:1.0, y:2.0, z:3.0};
 /// let q = -&p;
 /// ```
-impl<'a> ops::Neg for &'a Vec3 {
+impl ops::Neg for &Vec3 {
     type Output = Vec3;
     fn neg(self) -> Vec3 {
         Vec3 { x: -self.x, y: -self.y, z: -self.z }
@@ -380,10 +407,13 @@ impl Color {
 
 #[cfg(test)]
 pub(crate) mod test {
+    #[allow(clippy::op_ref)]
     mod vec3 {
         use crate::geometry::{
-            lerp, make_color_from_u8, random_in_hemisphere, reflect, refract, Vec3,
+            lerp, make_color_from_u8, random_in_hemisphere, random_in_unit_disk, reflect, refract,
+            Vec3,
         };
+        use crate::sampler::Sampler;
 
         #[test]
         fn test_default_vec3_is_all_zeros() {
@@ -394,18 +424,29 @@ pub(crate) mod test {
 
         #[test]
         fn test_random_vector_has_values_in_0_1_range() {
-            let rand_vec3 = Vec3::random();
+            let rand_vec3 = Vec3::random(&mut Sampler::new(0));
             assert!(rand_vec3.x >= 0.0 && rand_vec3.x <= 1.0);
             assert!(rand_vec3.y >= 0.0 && rand_vec3.y <= 1.0);
             assert!(rand_vec3.z >= 0.0 && rand_vec3.z <= 1.0);
         }
 
+        #[test]
+        fn test_random_in_unit_disk_is_flat() {
+            let mut rng = Sampler::new(0);
+            for _ in 0..100 {
+                let v = random_in_unit_disk(&mut rng);
+                assert!(v.len() < 1.0);
+                assert_eq!(v.z, 0.0);
+            }
+        }
+
         #[test]
         fn test_random_vec_in_hemisphere_always_with_unit_sphere() {
-            let random_vec3 = random_in_hemisphere(&Vec3::UNIT_Y);
+            let mut rng = Sampler::new(0);
+            let random_vec3 = random_in_hemisphere(&Vec3::UNIT_Y, &mut rng);
             assert!(random_vec3.len() <= 1.0);
 
-            let random_vec3 = random_in_hemisphere(&-Vec3::UNIT_Y);
+            let random_vec3 = random_in_hemisphere(&-Vec3::UNIT_Y, &mut rng);
             assert!(random_vec3.len() <= 1.0);
         }
 
@@ -641,7 +682,7 @@ pub(crate) mod test {
             let n = Vec3 { x: -1.0, y: 0.0, z: 0.0 };
             let etai_over_etat = 1.0;
             let expected = Vec3 { x: 0.0, y: 1.0, z: 0.0 };
-            let actual = refract(&uv, &n, etai_over_etat);
+            let actual = refract(&uv, &n, etai_over_etat).unwrap();
             assert_eq!(actual, expected);
         }
 
@@ -650,7 +691,7 @@ pub(crate) mod test {
             let v = Vec3 { x: 1.0, y: -1.0, z: 0.0 }.normed();
             let n = Vec3::UNIT_Y;
 
-            let refracted = refract(&v, &n, 1.0);
+            let refracted = refract(&v, &n, 1.0).unwrap();
             let expected = Vec3 { x: 1.0, y: -1.0, z: 0.0 }.normed();
             assert_eq!(expected, refracted);
         }
@@ -660,7 +701,7 @@ pub(crate) mod test {
             let v = Vec3 { x: 1.0, y: -1.0, z: 0.0 }.normed();
             let n = Vec3::UNIT_Y;
 
-            let refracted = refract(&v, &n, 1.3);
+            let refracted = refract(&v, &n, 1.3).unwrap();
             let expected = Vec3 { x: 0.91923875, y: -0.39370057, z: 0.0 };
             assert_eq!(expected, refracted);
         }
@@ -674,4 +715,53 @@ pub(crate) mod test {
             assert_f32_near!(color.z, 127.0 / 255.0);
         }
     }
+
+    mod properties {
+        use crate::geometry::{dot, reflect, refract, Vec3};
+        use proptest::prelude::*;
+
+        fn vector() -> impl Strategy<Value = Vec3> {
+            (-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0)
+                .prop_map(|(x, y, z)| Vec3::new(x, y, z))
+        }
+
+        fn unit_vector() -> impl Strategy<Value = Vec3> {
+            vector()
+                .prop_filter("too short to normalize", |v| v.len() > 0.01)
+                .prop_map(|v| v.normed())
+        }
+
+        proptest! {
+            #[test]
+            fn test_reflect_preserves_length(v in vector(), n in unit_vector()) {
+                let reflected = reflect(&v, &n);
+                prop_assert!((reflected.len() - v.len()).abs() <= 1e-4 * (1.0 + v.len()));
+                prop_assert!((dot(&reflected, &n) + dot(&v, &n)).abs() <= 1e-4 * (1.0 + v.len()));
+            }
+
+            #[test]
+            fn test_refract_follows_snell_law(
+                uv in unit_vector(), n in unit_vector(), ratio in 0.2f32..3.0,
+            ) {
+                // Incoming against the normal.
+                let uv = if dot(&uv, &n) > 0.0 { -uv } else { uv };
+                let cos_i = -dot(&uv, &n);
+                prop_assume!(cos_i > 1e-3);
+                let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+
+                match refract(&uv, &n, ratio) {
+                    Some(refracted) => {
+                        prop_assert!(ratio * sin_i <= 1.0 + 1e-4);
+                        prop_assert!((refracted.len() - 1.0).abs() <= 1e-3);
+                        // Through the surface, with sin(t) = ratio * sin(i).
+                        let cos_t = -dot(&refracted, &n);
+                        prop_assert!(cos_t >= 0.0);
+                        let sin_t = (1.0 - cos_t * cos_t).max(0.0).sqrt();
+                        prop_assert!((sin_t - ratio * sin_i).abs() <= 1e-2);
+                    }
+                    None => prop_assert!(ratio * sin_i >= 1.0 - 1e-4),
+                }
+            }
+        }
+    }
 }
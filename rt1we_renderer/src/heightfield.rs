@@ -0,0 +1,369 @@
+//! Heightfields, terrains whose height over a grid comes from a grayscale image.
+//!
+//! Each cell of the grid is split into two triangles, shaded with normals interpolated across
+//! them for smooth slopes. Rays walk the cells under them in order, from the first they enter,
+//! and only test the triangles of the cells whose heights they pass through, so that a hit
+//! costs about the number of cells along the ray rather than the number of triangles.
+use crate::aabb::Aabb;
+use crate::error::RtError;
+use crate::geometry::{Point, Vec3};
+use crate::image::ImageRGBA;
+use crate::imageio::imread;
+use crate::interval::Interval;
+use crate::mesh::{Triangle, BOX_PADDING};
+use crate::ray::Ray;
+use crate::registry::MaterialId;
+use crate::render::{HitRecord, Hittable};
+
+/// Terrain over a grid of heights, see the [module](self) documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heightfield {
+    /// Heights from 0 to 1, row by row from the `min` corner, along X then Z.
+    heights: Vec<f32>,
+    columns: usize,
+    rows: usize,
+    /// Lowest and highest height of each cell, row by row.
+    cell_ranges: Vec<(f32, f32)>,
+    /// Lowest and highest height of the terrain.
+    range: (f32, f32),
+    /// Corner of the terrain with the lowest coordinates, where the height is 0.
+    pub min: Point,
+    /// Extent of the terrain along X and Z, and its height where the heights are 1.
+    pub size: Vec3,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+    /// File the heights were loaded from, so that scene files can refer to it.
+    path: Option<String>,
+}
+
+impl Heightfield {
+    /// Create a heightfield from its heights.
+    ///
+    /// # Arguments
+    /// - `heights` - Heights from 0 to 1, `columns` along X per row, rows along Z.
+    /// - `min` - Corner of the terrain with the lowest coordinates.
+    /// - `size` - Extent of the terrain along X and Z, and its height along Y.
+    ///
+    /// # Returns
+    /// The heightfield, or an error if there are not `columns` times `rows` heights, or less
+    /// than 2 per row or per column.
+    pub fn new(
+        heights: Vec<f32>, columns: usize, rows: usize, min: Point, size: Vec3,
+        material_id: MaterialId,
+    ) -> Result<Self, RtError> {
+        if columns < 2 || rows < 2 {
+            let msg = format!("a heightfield needs 2 by 2 heights, not {columns} by {rows}");
+            return Err(RtError::Geometry(msg));
+        }
+        if heights.len() != columns * rows {
+            return Err(RtError::Geometry(format!(
+                "{columns} by {rows} heights expected, there are {}",
+                heights.len()
+            )));
+        }
+        let height = |i, j| heights[j * columns + i];
+        let cell_ranges = (0..rows - 1)
+            .flat_map(|j| (0..columns - 1).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let corners =
+                    [height(i, j), height(i + 1, j), height(i, j + 1), height(i + 1, j + 1)];
+                let lowest = corners.iter().fold(f32::INFINITY, |a, b| a.min(*b));
+                let highest = corners.iter().fold(f32::NEG_INFINITY, |a, b| a.max(*b));
+                (lowest, highest)
+            })
+            .collect::<Vec<_>>();
+        let range = cell_ranges
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), (a, b)| (lo.min(*a), hi.max(*b)));
+        Ok(Heightfield {
+            heights,
+            columns,
+            rows,
+            cell_ranges,
+            range,
+            min,
+            size,
+            material_id,
+            path: None,
+        })
+    }
+
+    /// Create a heightfield from the gray levels of an image, white the highest, a height per
+    /// pixel. The top row of the image lies along the `min` corner.
+    pub fn from_image(
+        image: &ImageRGBA, min: Point, size: Vec3, material_id: MaterialId,
+    ) -> Result<Self, RtError> {
+        let heights = (0..image.height)
+            .flat_map(|j| (0..image.width).map(move |i| image.at(i, j)))
+            .map(|(r, g, b, _)| (r as f32 + g as f32 + b as f32) / (3.0 * 255.0))
+            .collect();
+        Heightfield::new(heights, image.width, image.height, min, size, material_id)
+    }
+
+    /// Load a heightfield from an image file, see [`imread`] for the formats and
+    /// [`Heightfield::from_image`].
+    pub fn load(
+        fpath: &str, min: Point, size: Vec3, material_id: MaterialId,
+    ) -> Result<Self, RtError> {
+        let heightfield = Heightfield::from_image(&imread(fpath)?, min, size, material_id)
+            .map_err(|e| RtError::Geometry(format!("{fpath}: {e}")))?;
+        Ok(Heightfield { path: Some(fpath.to_string()), ..heightfield })
+    }
+
+    /// The file the heights were loaded from, `None` for heightfields built in memory.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Number of heights along X.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Number of heights along Z.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Approximate memory used by the heights of the terrain and its cells, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.heights.len() * std::mem::size_of::<f32>()
+            + self.cell_ranges.len() * std::mem::size_of::<(f32, f32)>()
+    }
+
+    /// Size of a cell along X and Z, the height along Y.
+    fn cell_size(&self) -> Vec3 {
+        let (columns, rows) = ((self.columns - 1) as f32, (self.rows - 1) as f32);
+        Vec3::new(self.size.x / columns, self.size.y, self.size.z / rows)
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point {
+        let cell = self.cell_size();
+        let height = self.heights[j * self.columns + i];
+        self.min + Vec3::new(i as f32 * cell.x, height * cell.y, j as f32 * cell.z)
+    }
+
+    /// Normal of the terrain at the vertex `(i, j)`, from the slopes to its neighbors.
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.columns - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+        let along_x = self.vertex(i1, j) - self.vertex(i0, j);
+        let along_z = self.vertex(i, j1) - self.vertex(i, j0);
+        along_z.cross(&along_x).normed()
+    }
+
+    /// Hit the triangles of the cell `(i, j)`, with interpolated normals.
+    fn hit_cell(&self, i: usize, j: usize, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // Turning counterclockwise seen from above, so that the front faces face up.
+        let corners = [[(i, j), (i, j + 1), (i + 1, j + 1)], [(i, j), (i + 1, j + 1), (i + 1, j)]];
+        let mut closest = ray_t.max;
+        let mut hit = None;
+        for corners in corners {
+            let [a, b, c] = corners.map(|(i, j)| self.vertex(i, j));
+            let triangle = Triangle::new(a, b, c, self.material_id);
+            if triangle.hit(r, Interval::new(ray_t.min, closest), rec) {
+                closest = rec.t;
+                hit = Some(corners);
+            }
+        }
+        let Some(corners) = hit else {
+            return false;
+        };
+
+        // The triangle gives the weights of its second and third corners.
+        let [na, nb, nc] = corners.map(|(i, j)| self.vertex_normal(i, j));
+        let (wb, wc) = (rec.u, rec.v);
+        let normal = ((1.0 - wb - wc) * na + wb * nb + wc * nc).normed();
+        rec.u = (rec.p.x - self.min.x) / self.size.x;
+        rec.v = 1.0 - (rec.p.z - self.min.z) / self.size.z;
+        rec.set_face_normal(r, &normal);
+        true
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let Some(range) = self.bounding_box().clip(r, ray_t) else {
+            return false;
+        };
+        let (columns, rows) = (self.columns - 1, self.rows - 1);
+        let cell = self.cell_size();
+        let o = r.orig - self.min;
+        let d = r.dir;
+
+        // Walk the cells under the ray, from the first it enters.
+        let start = o + range.min * d;
+        let first = |g: f32, cells: usize| (g.floor().max(0.0) as usize).min(cells - 1);
+        let (mut i, mut j) = (first(start.x / cell.x, columns), first(start.z / cell.z, rows));
+        // Distances along the ray to the next cell boundaries, and between two of them.
+        let next = |k: usize, o: f32, d: f32, size: f32| {
+            let boundary = if d > 0.0 { k + 1 } else { k } as f32 * size;
+            if d == 0.0 {
+                f32::INFINITY
+            } else {
+                (boundary - o) / d
+            }
+        };
+        let (mut next_x, mut next_z) = (next(i, o.x, d.x, cell.x), next(j, o.z, d.z, cell.z));
+        let (delta_x, delta_z) = ((cell.x / d.x).abs(), (cell.z / d.z).abs());
+
+        let mut enter = range.min;
+        loop {
+            let exit = next_x.min(next_z).min(range.max);
+            // Cells whose heights the ray stays above or below are skipped.
+            let (y0, y1) = (o.y + enter * d.y, o.y + exit * d.y);
+            let (lowest, highest) = self.cell_ranges[j * columns + i];
+            let passes = y0.min(y1) <= highest * cell.y + BOX_PADDING
+                && y0.max(y1) >= lowest * cell.y - BOX_PADDING;
+            if passes && self.hit_cell(i, j, r, ray_t, rec) {
+                return true;
+            }
+            if exit >= range.max {
+                return false;
+            }
+            if next_x < next_z {
+                if (d.x < 0.0 && i == 0) || (d.x > 0.0 && i + 1 == columns) {
+                    return false;
+                }
+                i = if d.x > 0.0 { i + 1 } else { i - 1 };
+                next_x += delta_x;
+            } else {
+                if (d.z < 0.0 && j == 0) || (d.z > 0.0 && j + 1 == rows) {
+                    return false;
+                }
+                j = if d.z > 0.0 { j + 1 } else { j - 1 };
+                next_z += delta_z;
+            }
+            enter = exit;
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let (lowest, highest) = self.range;
+        let padding = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        let min = self.min + Vec3::new(0.0, lowest * self.size.y, 0.0);
+        let max = self.min + Vec3::new(self.size.x, highest * self.size.y, self.size.z);
+        Aabb::new(min - padding, max + padding)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Point, Vec3};
+    use crate::heightfield::Heightfield;
+    use crate::image::ImageRGBA;
+    use crate::interval::Interval;
+    use crate::mesh::Mesh;
+    use crate::ray::Ray;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, Hittable};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn hit(object: &impl Hittable, orig: Point, dir: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let r = Ray::new(orig, dir);
+        object.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec).then_some(rec)
+    }
+
+    /// A 4 by 3 terrain, 3 by 2 wide, with a peak in the middle of its far edge.
+    fn terrain() -> Heightfield {
+        #[rustfmt::skip]
+        let heights = vec![
+            0.0, 0.5, 1.0, 0.5,
+            0.0, 0.0, 0.5, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+        ];
+        let size = Vec3::new(3.0, 2.0, 2.0);
+        Heightfield::new(heights, 4, 3, Point::ZERO, size, MaterialId(5)).unwrap()
+    }
+
+    #[test]
+    fn test_heightfield() {
+        let terrain = terrain();
+        // From above, onto the flat near edge, then the peak.
+        let rec = hit(&terrain, Point::new(0.5, 3.0, 1.5), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 3.0, 1e-5);
+        assert!((rec.normal - Vec3::UNIT_Y).len() < 1e-5 && rec.front_face);
+        assert_eq!(rec.material_id, MaterialId(5));
+        assert_float_absolute_eq!(rec.u, 0.5 / 3.0, 1e-5);
+        assert_float_absolute_eq!(rec.v, 0.25, 1e-5);
+        let rec = hit(&terrain, Point::new(2.0, 3.0, 0.0), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.0, 1e-5);
+        // Across, low over the flat cells, into the slope of the peak.
+        let rec = hit(&terrain, Point::new(-1.0, 0.5, 0.2), Vec3::UNIT_X).unwrap();
+        assert!(rec.t > 1.0 && rec.t < 3.0, "{}", rec.t);
+        assert!(rec.normal.x < 0.0 && rec.front_face, "{:?}", rec.normal);
+        // Above everything, and beside the terrain.
+        assert!(hit(&terrain, Point::new(-1.0, 2.5, 1.0), Vec3::UNIT_X).is_none());
+        assert!(hit(&terrain, Point::new(4.0, 3.0, 1.0), -Vec3::UNIT_Y).is_none());
+        // From below, onto the back faces.
+        let rec = hit(&terrain, Point::new(0.5, -1.0, 1.5), Vec3::UNIT_Y).unwrap();
+        assert!(!rec.front_face);
+
+        let bbox = terrain.bounding_box();
+        assert!((bbox.max - Point::new(3.0, 2.0, 2.0)).len() < 1e-3);
+        assert!(
+            Heightfield::new(vec![0.0; 5], 3, 2, Point::ZERO, Vec3::UNIT_Y, MaterialId(0)).is_err()
+        );
+        assert!(
+            Heightfield::new(vec![0.0; 3], 3, 1, Point::ZERO, Vec3::UNIT_Y, MaterialId(0)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_heightfield_matches_mesh() {
+        // The same triangles, found by walking the grid or through the hierarchy of a mesh.
+        let mut rng = StdRng::seed_from_u64(7);
+        let (columns, rows) = (9, 7);
+        let heights: Vec<f32> = (0..columns * rows).map(|_| rng.gen()).collect();
+        let (min, size) = (Point::new(-2.0, -1.0, -3.0), Vec3::new(4.0, 1.0, 3.0));
+        let terrain = Heightfield::new(heights, columns, rows, min, size, MaterialId(0)).unwrap();
+        let vertices = (0..rows).flat_map(|j| (0..columns).map(move |i| (i, j)));
+        let vertices = vertices.map(|(i, j)| terrain.vertex(i, j)).collect();
+        let index = |i: usize, j: usize| j * columns + i;
+        let cells = (0..rows - 1).flat_map(|j| (0..columns - 1).map(move |i| (i, j)));
+        let triangles = cells
+            .flat_map(|(i, j)| {
+                [
+                    [index(i, j), index(i, j + 1), index(i + 1, j + 1)],
+                    [index(i, j), index(i + 1, j + 1), index(i + 1, j)],
+                ]
+            })
+            .collect();
+        let mesh = Mesh::new(vertices, triangles, MaterialId(0)).unwrap();
+
+        for _ in 0..500 {
+            let orig = Point::new(
+                rng.gen_range(-4.0..4.0),
+                rng.gen_range(-1.0..2.0),
+                rng.gen_range(-5.0..2.0),
+            );
+            let dir = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let (rec, expected) = (hit(&terrain, orig, dir), hit(&mesh, orig, dir));
+            assert_eq!(rec.is_some(), expected.is_some(), "{orig:?} {dir:?}");
+            if let (Some(rec), Some(expected)) = (rec, expected) {
+                assert_float_absolute_eq!(rec.t, expected.t, 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_image() {
+        let mut image = ImageRGBA::new(3, 2);
+        image.put(1, 0, 255, 255, 255, 255);
+        image.put(2, 1, 255, 0, 0, 255);
+        let size = Vec3::new(2.0, 3.0, 1.0);
+        let terrain = Heightfield::from_image(&image, Point::ZERO, size, MaterialId(0)).unwrap();
+        assert_eq!((terrain.columns(), terrain.rows(), terrain.path()), (3, 2, None));
+        // White is the highest, the top row of the image along the min corner.
+        assert!((terrain.vertex(1, 0) - Point::new(1.0, 3.0, 0.0)).len() < 1e-5);
+        assert!((terrain.vertex(2, 1) - Point::new(2.0, 1.0, 1.0)).len() < 1e-5);
+        assert!(Heightfield::from_image(&ImageRGBA::new(1, 4), Point::ZERO, size, MaterialId(0))
+            .is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! Luminance histograms of linear images, to choose an exposure.
+use crate::image::ImageRGBF32;
+
+/// Relative luminance of a linear RGB color (Rec. 709 weights).
+pub fn luminance(r: f32, g: f32, b: f32) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Number of pixels per luminance range, on a logarithmic scale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Pixel counts, from the darkest bin to the brightest.
+    pub bins: Vec<usize>,
+    /// Luminance at the start of the first bin, in stops (`log2`).
+    pub min_ev: f32,
+    /// Luminance at the end of the last bin, in stops.
+    pub max_ev: f32,
+    /// Number of pixels with no light at all, which are in no bin.
+    pub black: usize,
+}
+
+impl Histogram {
+    /// Count the pixels of an image.
+    ///
+    /// Pixels darker than `min_ev` are counted in the first bin, brighter than `max_ev` in the
+    /// last one.
+    ///
+    /// # Arguments
+    /// - `im` - The image.
+    /// - `bins` - Number of bins, at least 1.
+    /// - `min_ev` - Luminance at the start of the first bin, in stops.
+    /// - `max_ev` - Luminance at the end of the last bin, in stops.
+    pub fn of(im: &ImageRGBF32, bins: usize, min_ev: f32, max_ev: f32) -> Histogram {
+        let mut histogram = Histogram { bins: vec![0; bins.max(1)], min_ev, max_ev, black: 0 };
+        let n = histogram.bins.len();
+        for px in im.pixels.chunks(3) {
+            let l = luminance(px[0], px[1], px[2]);
+            if l <= 0.0 {
+                histogram.black += 1;
+                continue;
+            }
+            let t = (l.log2() - min_ev) / (max_ev - min_ev);
+            let k = (t * n as f32).clamp(0.0, (n - 1) as f32) as usize;
+            histogram.bins[k] += 1;
+        }
+        histogram
+    }
+
+    /// Luminance range of a bin, in stops.
+    pub fn bin_range(&self, k: usize) -> (f32, f32) {
+        let width = (self.max_ev - self.min_ev) / self.bins.len() as f32;
+        (self.min_ev + k as f32 * width, self.min_ev + (k + 1) as f32 * width)
+    }
+
+    /// Count of the fullest bin.
+    pub fn peak(&self) -> usize {
+        self.bins.iter().copied().max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::histogram::{luminance, Histogram};
+    use crate::image::ImageRGBF32;
+
+    #[test]
+    fn test_luminance_of_white_is_one() {
+        assert_float_absolute_eq!(luminance(1.0, 1.0, 1.0), 1.0, 1e-6);
+        assert!(luminance(0.0, 1.0, 0.0) > luminance(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_histogram_bins() {
+        let mut im = ImageRGBF32::new(5, 1);
+        im.put(1, 0, 1.5, 1.5, 1.5);
+        im.put(2, 0, 0.3, 0.3, 0.3);
+        im.put(3, 0, 1000.0, 1000.0, 1000.0);
+        im.put(4, 0, 1e-9, 1e-9, 1e-9);
+
+        // One bin per stop, from -4 to +4.
+        let histogram = Histogram::of(&im, 8, -4.0, 4.0);
+        assert_eq!(histogram.black, 1);
+        assert_eq!(histogram.bins, vec![1, 0, 1, 0, 1, 0, 0, 1]);
+        assert_eq!(histogram.bin_range(4), (0.0, 1.0));
+        assert_eq!(histogram.peak(), 1);
+    }
+}
@@ -1,5 +1,5 @@
 //! Image functions and data structures.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 /// Container for a 2D image with 4 channels.
 pub struct ImageRGBA {
     pub width: usize,
@@ -15,7 +15,7 @@ impl ImageRGBA {
         ImageRGBA { width, height, pixels }
     }
 
-    fn init(pixels: &mut Vec<u8>) {
+    fn init(pixels: &mut [u8]) {
         let l = pixels.len();
         let count = l / 4;
         for i in 0..count {
@@ -27,13 +27,13 @@ impl ImageRGBA {
     }
 
     pub fn at(&self, i: usize, j: usize) -> (u8, u8, u8, u8) {
-        let idx = (j * self.width as usize + i) * 4usize;
+        let idx = (j * self.width + i) * 4usize;
 
         (self.pixels[idx], self.pixels[idx + 1], self.pixels[idx + 2], self.pixels[idx + 3])
     }
 
     pub fn at_u32(&self, i: usize, j: usize) -> u32 {
-        let idx = (j * self.width as usize + i) * 4usize;
+        let idx = (j * self.width + i) * 4usize;
 
         let (r, g, b, a) = (
             self.pixels[idx] as u32,
@@ -46,7 +46,7 @@ impl ImageRGBA {
     }
 
     pub fn put(&mut self, i: usize, j: usize, r: u8, g: u8, b: u8, a: u8) {
-        let idx = (j * self.width as usize + i) * 4;
+        let idx = (j * self.width + i) * 4;
 
         self.pixels[idx] = r;
         self.pixels[idx + 1] = g;
@@ -55,7 +55,7 @@ impl ImageRGBA {
     }
 
     pub fn put_u32(&mut self, i: usize, j: usize, rgba: u32) {
-        let idx = (j * self.width as usize + i) * 4;
+        let idx = (j * self.width + i) * 4;
 
         let r = (rgba >> 24) as u8;
         let g = (rgba >> 16) as u8;
@@ -67,6 +67,59 @@ impl ImageRGBA {
         self.pixels[idx + 2] = b;
         self.pixels[idx + 3] = a;
     }
+
+    /// The `width` columns of the image from column `x`, as for one eye of a stereo render.
+    pub fn columns(&self, x: usize, width: usize) -> ImageRGBA {
+        let pixels = self
+            .pixels
+            .chunks((self.width * 4).max(1))
+            .flat_map(|row| &row[x * 4..(x + width) * 4]);
+        ImageRGBA { width, height: self.height, pixels: pixels.copied().collect() }
+    }
+}
+
+/// Container for a 2D image of linear RGB values, before tone mapping and quantization.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageRGBF32 {
+    pub width: usize,
+    pub height: usize,
+    /// Red, green and blue of each pixel, row after row.
+    pub pixels: Vec<f32>,
+}
+
+impl ImageRGBF32 {
+    /// A black image.
+    pub fn new(width: usize, height: usize) -> ImageRGBF32 {
+        ImageRGBF32 { width, height, pixels: vec![0.0; width * height * 3] }
+    }
+
+    pub fn at(&self, i: usize, j: usize) -> (f32, f32, f32) {
+        let idx = (j * self.width + i) * 3;
+        (self.pixels[idx], self.pixels[idx + 1], self.pixels[idx + 2])
+    }
+
+    pub fn put(&mut self, i: usize, j: usize, r: f32, g: f32, b: f32) {
+        let idx = (j * self.width + i) * 3;
+        self.pixels[idx] = r;
+        self.pixels[idx + 1] = g;
+        self.pixels[idx + 2] = b;
+    }
+
+    /// The `width` columns of the image from column `x`, see [`ImageRGBA::columns`].
+    pub fn columns(&self, x: usize, width: usize) -> ImageRGBF32 {
+        let pixels = self
+            .pixels
+            .chunks((self.width * 3).max(1))
+            .flat_map(|row| &row[x * 3..(x + width) * 3]);
+        ImageRGBF32 { width, height: self.height, pixels: pixels.copied().collect() }
+    }
+
+    /// The image upside down, see [`flipv`].
+    pub fn flipv(&self) -> ImageRGBF32 {
+        let row = self.width * 3;
+        let pixels = self.pixels.chunks(row.max(1)).rev().flatten().copied().collect();
+        ImageRGBF32 { width: self.width, height: self.height, pixels }
+    }
 }
 
 pub fn flipv(im: &ImageRGBA) -> ImageRGBA {
@@ -82,9 +135,50 @@ pub fn flipv(im: &ImageRGBA) -> ImageRGBA {
     out
 }
 
+/// Resize an image, averaging all the source pixels covered by each output pixel.
+///
+/// # Arguments
+/// - `im` - The image to resize.
+/// - `width` - Output width.
+/// - `height` - Output height.
+pub fn resize(im: &ImageRGBA, width: usize, height: usize) -> ImageRGBA {
+    let mut out = ImageRGBA::new(width, height);
+
+    for j in 0..height {
+        let y0 = j * im.height / height;
+        let y1 = ((j + 1) * im.height).div_ceil(height).max(y0 + 1);
+        for i in 0..width {
+            let x0 = i * im.width / width;
+            let x1 = ((i + 1) * im.width).div_ceil(width).max(x0 + 1);
+
+            let mut sum = [0u32; 4];
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let (r, g, b, a) = im.at(x, y);
+                    sum[0] += r as u32;
+                    sum[1] += g as u32;
+                    sum[2] += b as u32;
+                    sum[3] += a as u32;
+                }
+            }
+            let n = ((x1 - x0) * (y1 - y0)) as u32;
+            out.put(
+                i,
+                j,
+                (sum[0] / n) as u8,
+                (sum[1] / n) as u8,
+                (sum[2] / n) as u8,
+                (sum[3] / n) as u8,
+            );
+        }
+    }
+
+    out
+}
+
 #[cfg(test)]
 pub(crate) mod test {
-    use crate::image::{flipv, ImageRGBA};
+    use crate::image::{flipv, resize, ImageRGBA, ImageRGBF32};
 
     #[test]
     fn test_new_image_is_dark_gray() {
@@ -161,4 +255,52 @@ pub(crate) mod test {
         assert_eq!(im_flipped.at_u32(1, 2), 0x000002ff);
         assert_eq!(im_flipped.at_u32(2, 2), 0x000003ff);
     }
+
+    #[test]
+    fn test_rgbf32_flipv() {
+        let mut im = ImageRGBF32::new(2, 3);
+        assert_eq!(im.at(1, 2), (0.0, 0.0, 0.0));
+        im.put(1, 0, 0.5, 1.0, 4.0);
+
+        let im_flipped = im.flipv();
+        assert_eq!(im_flipped.at(1, 2), (0.5, 1.0, 4.0));
+        assert_eq!(im_flipped.at(1, 0), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_columns() {
+        let mut im = ImageRGBA::new(4, 2);
+        im.put_u32(2, 1, 0x102030ff);
+        let right = im.columns(2, 2);
+        assert_eq!((right.width, right.height), (2, 2));
+        assert_eq!(right.at_u32(0, 1), 0x102030ff);
+        assert_eq!(im.columns(0, 2), ImageRGBA::new(2, 2));
+
+        let mut im = ImageRGBF32::new(4, 2);
+        im.put(3, 0, 1.0, 2.0, 3.0);
+        assert_eq!(im.columns(2, 2).at(1, 0), (1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn test_resize_averages_covered_pixels() {
+        let mut im = ImageRGBA::new(4, 2);
+        im.put_u32(0, 0, 0x000000ff);
+        im.put_u32(1, 0, 0x000000ff);
+        im.put_u32(0, 1, 0x646464ff);
+        im.put_u32(1, 1, 0x646464ff);
+        im.put_u32(2, 0, 0xc8c8c8ff);
+        im.put_u32(3, 0, 0xc8c8c8ff);
+        im.put_u32(2, 1, 0xc8c8c8ff);
+        im.put_u32(3, 1, 0xc8c8c8ff);
+
+        let small = resize(&im, 2, 1);
+        assert_eq!(small.width, 2);
+        assert_eq!(small.height, 1);
+        assert_eq!(small.at_u32(0, 0), 0x323232ff);
+        assert_eq!(small.at_u32(1, 0), 0xc8c8c8ff);
+
+        let large = resize(&im, 8, 4);
+        assert_eq!(large.at_u32(0, 0), 0x000000ff);
+        assert_eq!(large.at_u32(7, 3), 0xc8c8c8ff);
+    }
 }
@@ -0,0 +1,273 @@
+//! Read and write images, picking the file format from the file extension.
+//!
+//! PPM files go through [`crate::ppmio`], PNG, JPEG, OpenEXR and Radiance HDR through the
+//! `image` crate. OpenEXR and Radiance HDR files hold linear values: 8-bit pixels are converted
+//! with the same gamma 2.0 the renderer applies before quantization.
+use crate::error::RtError;
+use crate::image::{ImageRGBA, ImageRGBF32};
+use crate::ppmio::{ppmread, ppmwrite, PpmFormat};
+use image::{DynamicImage, ImageBuffer, Rgb32FImage, RgbImage, RgbaImage};
+use std::path::Path;
+
+/// File formats supported by [`imread`] and [`imwrite`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+    Jpeg,
+    Exr,
+    /// Radiance RGBE.
+    Hdr,
+}
+
+impl ImageFormat {
+    /// Guess the format from the file extension, case insensitive.
+    pub fn from_path(fpath: &str) -> Option<ImageFormat> {
+        let ext = Path::new(fpath).extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "exr" => Some(ImageFormat::Exr),
+            "hdr" => Some(ImageFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    /// Whether the format keeps linear values above 1, for later tonemapping.
+    pub fn is_hdr(self) -> bool {
+        matches!(self, ImageFormat::Exr | ImageFormat::Hdr)
+    }
+}
+
+fn format_of(fpath: &str) -> Result<ImageFormat, RtError> {
+    ImageFormat::from_path(fpath).ok_or_else(|| RtError::UnsupportedFormat(fpath.to_string()))
+}
+
+/// An error if `fpath` is not a file, before the image crate reports it less clearly.
+fn check_is_file(fpath: &str) -> Result<(), RtError> {
+    if Path::new(fpath).is_file() {
+        Ok(())
+    } else {
+        let error = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        Err(RtError::io(fpath, error))
+    }
+}
+
+fn image_error(fpath: &str) -> impl FnOnce(image::ImageError) -> RtError + '_ {
+    move |source| RtError::Image { path: fpath.to_string(), source }
+}
+
+/// Read an image.
+///
+/// # Arguments
+/// - `fpath` - File path of the file to read. The extension gives the format.
+pub fn imread(fpath: &str) -> Result<ImageRGBA, RtError> {
+    let format = format_of(fpath)?;
+    check_is_file(fpath)?;
+    if format == ImageFormat::Ppm {
+        return ppmread(fpath);
+    }
+
+    let decoded = image::open(fpath).map_err(image_error(fpath))?;
+    let rgba = match format {
+        ImageFormat::Exr | ImageFormat::Hdr => {
+            let linear = decoded.to_rgba32f();
+            let encoded: Vec<u8> = linear.as_raw().chunks(4).flat_map(encode_gamma).collect();
+            RgbaImage::from_raw(linear.width(), linear.height(), encoded).unwrap()
+        }
+        _ => decoded.to_rgba8(),
+    };
+
+    Ok(ImageRGBA {
+        width: rgba.width() as usize,
+        height: rgba.height() as usize,
+        pixels: rgba.into_raw(),
+    })
+}
+
+/// Read a linear image, as for textures.
+///
+/// OpenEXR and Radiance HDR files keep their values, 8-bit pixels of other formats are decoded
+/// from gamma 2.0.
+///
+/// # Arguments
+/// - `fpath` - File path of the file to read. The extension gives the format.
+pub fn imread_hdr(fpath: &str) -> Result<ImageRGBF32, RtError> {
+    if !format_of(fpath)?.is_hdr() {
+        let im = imread(fpath)?;
+        let pixels = im.pixels.chunks(4).flat_map(decode_gamma).collect();
+        return Ok(ImageRGBF32 { width: im.width, height: im.height, pixels });
+    }
+    check_is_file(fpath)?;
+
+    let decoded = image::open(fpath).map_err(image_error(fpath))?;
+    let linear = decoded.to_rgb32f();
+    Ok(ImageRGBF32 {
+        width: linear.width() as usize,
+        height: linear.height() as usize,
+        pixels: linear.into_raw(),
+    })
+}
+
+/// Write an image.
+///
+/// # Arguments
+/// - `fpath` - The file path to write to. The extension gives the format.
+/// - `im` - The image data to write.
+///
+/// # Notes
+/// The alpha channel is dropped for PPM, JPEG, OpenEXR and Radiance HDR.
+/// PPM files are written in the binary format.
+pub fn imwrite(fpath: &str, im: &ImageRGBA) -> Result<(), RtError> {
+    let format = format_of(fpath)?;
+    let (w, h) = (im.width as u32, im.height as u32);
+
+    let result = match format {
+        ImageFormat::Ppm => return ppmwrite(fpath, im, PpmFormat::Binary),
+        ImageFormat::Png => RgbaImage::from_raw(w, h, im.pixels.clone()).unwrap().save(fpath),
+        ImageFormat::Jpeg => {
+            let rgb = im.pixels.chunks(4).flat_map(|px| [px[0], px[1], px[2]]).collect();
+            RgbImage::from_raw(w, h, rgb).unwrap().save(fpath)
+        }
+        ImageFormat::Exr | ImageFormat::Hdr => {
+            let linear: Vec<f32> = im.pixels.chunks(4).flat_map(decode_gamma).collect();
+            let buffer: Rgb32FImage = ImageBuffer::from_raw(w, h, linear).unwrap();
+            DynamicImage::ImageRgb32F(buffer).save(fpath)
+        }
+    };
+
+    result.map_err(image_error(fpath))
+}
+
+/// Write a linear image.
+///
+/// OpenEXR and Radiance HDR files keep the values as they are, other formats get gamma 2.0
+/// and are clamped to 8 bits.
+///
+/// # Arguments
+/// - `fpath` - The file path to write to. The extension gives the format.
+/// - `im` - The image data to write.
+pub fn imwrite_hdr(fpath: &str, im: &ImageRGBF32) -> Result<(), RtError> {
+    if !format_of(fpath)?.is_hdr() {
+        let pixels =
+            im.pixels.chunks(3).flat_map(|px| encode_gamma(&[px[0], px[1], px[2], 1.0])).collect();
+        return imwrite(fpath, &ImageRGBA { width: im.width, height: im.height, pixels });
+    }
+
+    let buffer: Rgb32FImage =
+        ImageBuffer::from_raw(im.width as u32, im.height as u32, im.pixels.clone()).unwrap();
+    DynamicImage::ImageRgb32F(buffer).save(fpath).map_err(image_error(fpath))
+}
+
+/// Linear RGB from an 8-bit gamma 2.0 pixel.
+fn decode_gamma(px: &[u8]) -> [f32; 3] {
+    let f = |v: u8| (v as f32 / 255.0).powi(2);
+    [f(px[0]), f(px[1]), f(px[2])]
+}
+
+/// 8-bit gamma 2.0 pixel from linear RGBA.
+fn encode_gamma(px: &[f32]) -> [u8; 4] {
+    let f = |v: f32| (v.clamp(0.0, 1.0).sqrt() * 255.0).round() as u8;
+    [f(px[0]), f(px[1]), f(px[2]), f(px[3])]
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::error::RtError;
+    use crate::image::{ImageRGBA, ImageRGBF32};
+    use crate::imageio::{imread, imread_hdr, imwrite, imwrite_hdr, ImageFormat};
+
+    fn make_image() -> ImageRGBA {
+        let mut im = ImageRGBA::new(4, 3);
+        im.put_u32(0, 0, 0xff0000ff);
+        im.put_u32(3, 2, 0x00ff80ff);
+        im
+    }
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(ImageFormat::from_path("a/b.ppm"), Some(ImageFormat::Ppm));
+        assert_eq!(ImageFormat::from_path("b.PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path("b.jpeg"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_path("b.exr"), Some(ImageFormat::Exr));
+        assert_eq!(ImageFormat::from_path("b.hdr"), Some(ImageFormat::Hdr));
+        assert!(ImageFormat::Hdr.is_hdr() && !ImageFormat::Png.is_hdr());
+        assert_eq!(ImageFormat::from_path("b.gif"), None);
+        assert_eq!(ImageFormat::from_path("noext"), None);
+    }
+
+    #[test]
+    fn test_lossless_roundtrips() {
+        let dir = tempfile::tempdir().unwrap();
+        let im = make_image();
+
+        for name in ["im.ppm", "im.png", "im.exr"] {
+            let fpath = dir.path().join(name);
+            let fpath = fpath.to_str().unwrap();
+            imwrite(fpath, &im).unwrap();
+
+            let im_r = imread(fpath).unwrap();
+            assert_eq!((im_r.width, im_r.height), (4, 3));
+            assert_eq!(im_r.pixels, im.pixels, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_jpeg_roundtrip_is_close() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("im.jpg");
+        let fpath = fpath.to_str().unwrap();
+        let im = ImageRGBA::new(8, 8);
+
+        imwrite(fpath, &im).unwrap();
+        let im_r = imread(fpath).unwrap();
+        for (a, b) in im.pixels.iter().zip(im_r.pixels.iter()) {
+            assert!((*a as i32 - *b as i32).abs() <= 2);
+        }
+    }
+
+    #[test]
+    fn test_hdr_exr_keeps_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut im = ImageRGBF32::new(3, 2);
+        im.put(2, 1, 4.0, 0.25, 0.0);
+
+        let fpath = dir.path().join("im.exr");
+        let fpath = fpath.to_str().unwrap();
+        imwrite_hdr(fpath, &im).unwrap();
+        let decoded = image::open(fpath).unwrap().to_rgb32f();
+        assert_eq!(decoded.get_pixel(2, 1).0, [4.0, 0.25, 0.0]);
+        assert_eq!(imread_hdr(fpath).unwrap().at(2, 1), (4.0, 0.25, 0.0));
+
+        // RGBE shares one exponent per pixel, the small channels lose some precision.
+        let fpath = dir.path().join("im.hdr");
+        let fpath = fpath.to_str().unwrap();
+        imwrite_hdr(fpath, &im).unwrap();
+        let (r, g, b) = imread_hdr(fpath).unwrap().at(2, 1);
+        assert_eq!((r, b), (4.0, 0.0));
+        assert!((g - 0.25).abs() < 0.02, "{g}");
+
+        let fpath = dir.path().join("im.png");
+        let fpath = fpath.to_str().unwrap();
+        imwrite_hdr(fpath, &im).unwrap();
+        assert_eq!(imread(fpath).unwrap().at(2, 1), (255, 128, 0, 255));
+        let (r, g, _) = imread_hdr(fpath).unwrap().at(2, 1);
+        assert_eq!(r, 1.0);
+        assert!((g - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(imread("missing.png"), Err(RtError::Io { .. })));
+        assert!(matches!(imread_hdr("missing.exr"), Err(RtError::Io { .. })));
+        assert!(matches!(imread("image.gif"), Err(RtError::UnsupportedFormat(_))));
+        let err = imwrite("image.gif", &make_image()).err().unwrap();
+        assert_eq!(err.to_string(), "unsupported image format: image.gif");
+
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("broken.png");
+        std::fs::write(&fpath, b"not a png").unwrap();
+        assert!(matches!(imread(fpath.to_str().unwrap()), Err(RtError::Image { .. })));
+    }
+}
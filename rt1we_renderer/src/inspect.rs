@@ -0,0 +1,154 @@
+//! Pixel inspection: follow the path of a single ray through the scene.
+use crate::geometry::{Color, Vec3};
+use crate::render::{Bounce, Scene};
+use crate::sampler::Sampler;
+use crate::view::{Lens, OrbitView};
+use std::fmt;
+
+/// The path of a ray through the center of a pixel.
+#[derive(Debug, Clone)]
+pub struct PixelTrace {
+    /// Each step of the path, starting from the camera.
+    pub bounces: Vec<Bounce>,
+    /// Linear color the ray brings back.
+    pub color: Color,
+}
+
+impl PixelTrace {
+    /// Distance from the camera to the first hit, `None` when the ray hits the sky.
+    pub fn depth(&self) -> Option<f32> {
+        match self.bounces.first() {
+            Some(Bounce::Hit { distance, .. }) => Some(*distance),
+            _ => None,
+        }
+    }
+
+    /// Object seen in the pixel, `None` when the ray hits the sky.
+    pub fn object_id(&self) -> Option<usize> {
+        match self.bounces.first() {
+            Some(Bounce::Hit { object_id, .. }) => Some(*object_id),
+            _ => None,
+        }
+    }
+}
+
+/// Trace the ray through the center of a pixel.
+///
+/// Materials scatter rays randomly: tracing the same pixel again may give another path.
+///
+/// # Arguments
+/// - `scene` - The scene to trace.
+/// - `view` - The camera.
+/// - `width` - Image width
+/// - `height` - Image height
+/// - `i` - Pixel column, from the left.
+/// - `j` - Pixel row, from the bottom.
+/// - `max_depth` - Maximum number of ray bounces after a hit.
+pub fn trace_pixel(
+    scene: &Scene, view: &OrbitView, width: usize, height: usize, i: usize, j: usize,
+    max_depth: usize,
+) -> PixelTrace {
+    // A pinhole camera, so that the traced ray goes through the pixel center.
+    let lens = scene.lens();
+    let pinhole = Lens { vfov: lens.vfov, projection: lens.projection, ..Default::default() };
+    let camera = view.camera(width, height, &pinhole);
+    let u = (i as f32 + 0.5) / (width as f32 - 1.0);
+    let v = (j as f32 + 0.5) / (height as f32 - 1.0);
+    let mut rng = Sampler::from_entropy();
+    let ray = camera.get_ray(u, v, &mut rng);
+
+    let mut bounces = Vec::new();
+    let color = scene.trace(&scene.bvh(), &ray, max_depth, &mut rng, &mut |b| bounces.push(b));
+    PixelTrace { bounces, color }
+}
+
+fn fmt_vec(v: &Vec3) -> String {
+    format!("({:.3}, {:.3}, {:.3})", v.x, v.y, v.z)
+}
+
+impl fmt::Display for PixelTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (k, bounce) in self.bounces.iter().enumerate() {
+            match bounce {
+                Bounce::Hit {
+                    depth,
+                    point,
+                    normal,
+                    distance,
+                    object_id,
+                    material_id,
+                    front_face,
+                    emitted,
+                    direct,
+                    attenuation,
+                    scattered,
+                } => {
+                    let face = if *front_face { "front" } else { "back" };
+                    writeln!(
+                        f,
+                        "#{k} [depth={depth}] hit object {object_id} (material {material_id}) \
+                         at {}, distance {distance:.3}, {face} face",
+                        fmt_vec(point)
+                    )?;
+                    writeln!(
+                        f,
+                        "     normal {}, attenuation {}",
+                        fmt_vec(normal),
+                        fmt_vec(attenuation)
+                    )?;
+                    if *emitted != Color::BLACK {
+                        writeln!(f, "     emitted {}", fmt_vec(emitted))?;
+                    }
+                    if *direct != Color::BLACK {
+                        writeln!(f, "     direct light {}", fmt_vec(direct))?;
+                    }
+                    match scattered {
+                        Some(dir) => writeln!(f, "     scattered towards {}", fmt_vec(dir))?,
+                        None => writeln!(f, "     absorbed")?,
+                    }
+                }
+                Bounce::Sky { depth, color } => {
+                    writeln!(f, "#{k} [depth={depth}] sky {}", fmt_vec(color))?
+                }
+                Bounce::DepthLimit => writeln!(f, "#{k} depth limit reached")?,
+                Bounce::Roulette => writeln!(f, "#{k} stopped by the Russian roulette")?,
+            }
+        }
+        write!(f, "Color: {}", fmt_vec(&self.color))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::inspect::trace_pixel;
+    use crate::render::{Bounce, Scene};
+    use crate::view::OrbitView;
+
+    #[test]
+    fn test_center_pixel_sees_the_glass_sphere() {
+        let trace = trace_pixel(&Scene::demo(), &OrbitView::default(), 101, 101, 50, 50, 5);
+
+        assert_eq!(trace.object_id(), Some(0));
+        let depth = trace.depth().unwrap();
+        assert!((depth - 0.5).abs() < 0.01, "{depth}");
+        assert!(trace.bounces.len() <= 6);
+        assert!(trace.to_string().starts_with("#0 [depth=5] hit object 0 (material 4)"));
+    }
+
+    #[test]
+    fn test_sky_pixel() {
+        let trace = trace_pixel(&Scene::demo(), &OrbitView::default(), 16, 16, 8, 15, 5);
+
+        assert_eq!(trace.depth(), None);
+        assert!(matches!(trace.bounces[..], [Bounce::Sky { depth: 5, .. }]));
+        assert!(trace.color.x > 0.0);
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let trace = trace_pixel(&Scene::demo(), &OrbitView::default(), 101, 101, 50, 50, 0);
+
+        assert_eq!(trace.bounces, vec![Bounce::DepthLimit]);
+        assert_eq!(trace.to_string(), "#0 depth limit reached\nColor: (0.000, 0.000, 0.000)");
+    }
+}
@@ -0,0 +1,274 @@
+//! Instances of meshes: copies of a mesh moved, turned and scaled, all sharing its triangles
+//! and its hierarchy.
+//!
+//! A forest of ten thousand trees stores the triangles of a single tree, each tree only its
+//! transform. Rays are moved into the space of the mesh to hit it, and the hits moved back.
+use crate::aabb::Aabb;
+use crate::geometry::{Point, Vec3};
+use crate::interval::Interval;
+use crate::mesh::Mesh;
+use crate::ray::Ray;
+use crate::registry::MaterialId;
+use crate::render::{HitRecord, Hittable};
+use crate::trig::deg2rad;
+use std::sync::Arc;
+
+/// Rows of a 3 by 3 matrix.
+type Matrix = [Vec3; 3];
+
+fn mul(m: &Matrix, v: &Vec3) -> Vec3 {
+    Vec3::new(m[0].dot(v), m[1].dot(v), m[2].dot(v))
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    [
+        Vec3::new(m[0].x, m[1].x, m[2].x),
+        Vec3::new(m[0].y, m[1].y, m[2].y),
+        Vec3::new(m[0].z, m[1].z, m[2].z),
+    ]
+}
+
+fn mul_matrices(a: &Matrix, b: &Matrix) -> Matrix {
+    let columns = transpose(b);
+    a.map(|row| mul(&columns, &row))
+}
+
+/// Scaling, then rotation, then translation of an object.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    translation: Vec3,
+    /// Angles in degrees around the X, then the Y, then the Z axis.
+    rotation: Vec3,
+    scale: Vec3,
+    /// Rotation times scaling.
+    linear: Matrix,
+    inverse: Matrix,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::new(Vec3::ZERO, Vec3::ZERO, Vec3::new(1.0, 1.0, 1.0))
+    }
+}
+
+impl Transform {
+    /// # Arguments
+    /// - `translation` - Where the origin goes.
+    /// - `rotation` - Angles in degrees around the X, then the Y, then the Z axis.
+    /// - `scale` - Scale factors along each axis, none of them zero.
+    pub fn new(translation: Vec3, rotation: Vec3, scale: Vec3) -> Self {
+        let (sx, cx) = deg2rad(rotation.x).sin_cos();
+        let (sy, cy) = deg2rad(rotation.y).sin_cos();
+        let (sz, cz) = deg2rad(rotation.z).sin_cos();
+        let around_x = [Vec3::UNIT_X, Vec3::new(0.0, cx, -sx), Vec3::new(0.0, sx, cx)];
+        let around_y = [Vec3::new(cy, 0.0, sy), Vec3::UNIT_Y, Vec3::new(-sy, 0.0, cy)];
+        let around_z = [Vec3::new(cz, -sz, 0.0), Vec3::new(sz, cz, 0.0), Vec3::UNIT_Z];
+        let rotation_matrix = mul_matrices(&around_z, &mul_matrices(&around_y, &around_x));
+        let linear = rotation_matrix.map(|row| row * scale);
+        // The inverse of a rotation is its transpose.
+        let inverse_scale = Vec3::new(1.0 / scale.x, 1.0 / scale.y, 1.0 / scale.z);
+        let inverse = transpose(&rotation_matrix);
+        let inverse = [
+            inverse[0] * inverse_scale.x,
+            inverse[1] * inverse_scale.y,
+            inverse[2] * inverse_scale.z,
+        ];
+        Transform { translation, rotation, scale, linear, inverse }
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        self.translation
+    }
+
+    /// Angles in degrees around the X, then the Y, then the Z axis.
+    pub fn rotation(&self) -> Vec3 {
+        self.rotation
+    }
+
+    pub fn scale(&self) -> Vec3 {
+        self.scale
+    }
+
+    pub fn point(&self, p: &Point) -> Point {
+        mul(&self.linear, p) + self.translation
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        mul(&self.linear, v)
+    }
+
+    /// The normal of a transformed surface, from its normal before the transform, a unit
+    /// vector.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        mul(&transpose(&self.inverse), n).normed()
+    }
+
+    /// The ray before the transform, whose points are at the same distances along it.
+    fn inverse_ray(&self, r: &Ray) -> Ray {
+        Ray {
+            orig: mul(&self.inverse, &(r.orig - self.translation)),
+            dir: mul(&self.inverse, &r.dir),
+            time: r.time,
+            wavelength: r.wavelength,
+        }
+    }
+}
+
+/// A mesh moved by a transform, sharing its triangles with the other instances of the mesh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    mesh: Arc<Mesh>,
+    transform: Transform,
+    bbox: Aabb,
+    /// Handle of the material of the instance, which replaces the one of the mesh.
+    pub material_id: MaterialId,
+}
+
+impl Instance {
+    pub fn new(mesh: Arc<Mesh>, transform: Transform, material_id: MaterialId) -> Self {
+        let bbox = transformed_box(&mesh.bounding_box(), &transform);
+        Instance { mesh, transform, bbox, material_id }
+    }
+
+    pub fn mesh(&self) -> &Arc<Mesh> {
+        &self.mesh
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        *self = Instance::new(self.mesh.clone(), transform, self.material_id);
+    }
+}
+
+/// Box around the transformed corners of `bbox`.
+fn transformed_box(bbox: &Aabb, transform: &Transform) -> Aabb {
+    let corner = |k: usize| {
+        let pick = |bit: usize, lo: f32, hi: f32| if k & bit == 0 { lo } else { hi };
+        transform.point(&Point::new(
+            pick(1, bbox.min.x, bbox.max.x),
+            pick(2, bbox.min.y, bbox.max.y),
+            pick(4, bbox.min.z, bbox.max.z),
+        ))
+    };
+    (1..8).fold(Aabb::new(corner(0), corner(0)), |bbox, k| {
+        bbox.surrounding(&Aabb::new(corner(k), corner(k)))
+    })
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.mesh.hit(&self.transform.inverse_ray(r), ray_t, rec) {
+            return false;
+        }
+        // Distances along both rays are the same, and transforming the normal keeps the side
+        // it faces.
+        rec.p = r.at(rec.t);
+        rec.normal = self.transform.normal(&rec.normal);
+        rec.material_id = self.material_id;
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Point, Vec3};
+    use crate::instance::{Instance, Transform};
+    use crate::interval::Interval;
+    use crate::mesh::Mesh;
+    use crate::ray::Ray;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, Hittable};
+    use std::sync::Arc;
+
+    fn near(a: &Vec3, b: &Vec3) -> bool {
+        (*a - *b).len() < 1e-4
+    }
+
+    /// A unit square in the XY plane, facing +Z, from the origin.
+    pub(crate) fn square() -> Mesh {
+        let vertices = vec![
+            Point::ZERO,
+            Point::new(1.0, 0.0, 0.0),
+            Point::new(1.0, 1.0, 0.0),
+            Point::new(0.0, 1.0, 0.0),
+        ];
+        Mesh::new(vertices, vec![[0, 1, 2], [0, 2, 3]], MaterialId(0)).unwrap()
+    }
+
+    #[test]
+    fn test_transform() {
+        let transform = Transform::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(0.0, 90.0, 0.0),
+            Vec3::new(2.0, 1.0, 1.0),
+        );
+        // Scaled, turned from X towards -Z, then moved.
+        assert!(near(&transform.point(&Point::new(1.0, 0.0, 0.0)), &Point::new(1.0, 2.0, 1.0)));
+        assert!(near(&transform.vector(&Vec3::UNIT_Y), &Vec3::UNIT_Y));
+        let local = transform.inverse_ray(&Ray::new(Point::new(1.0, 2.0, 1.0), Vec3::UNIT_X));
+        assert!(near(&local.orig, &Point::new(1.0, 0.0, 0.0)));
+        assert!(near(&local.dir, &Vec3::new(0.0, 0.0, 1.0)));
+
+        // Rotations apply around X, then Y, then Z.
+        let transform =
+            Transform::new(Vec3::ZERO, Vec3::new(90.0, 0.0, 90.0), Vec3::new(1.0, 1.0, 1.0));
+        assert!(near(&transform.vector(&Vec3::UNIT_Y), &Vec3::UNIT_Z));
+        assert!(near(&transform.vector(&Vec3::UNIT_X), &Vec3::UNIT_Y));
+
+        // Normals stay orthogonal to stretched surfaces.
+        let stretch = Transform::new(Vec3::ZERO, Vec3::ZERO, Vec3::new(4.0, 1.0, 1.0));
+        let normal = Vec3::new(1.0, 1.0, 0.0).normed();
+        let tangent = stretch.vector(&Vec3::new(1.0, -1.0, 0.0));
+        assert_float_absolute_eq!(stretch.normal(&normal).dot(&tangent), 0.0, 1e-6);
+        assert_eq!(
+            Transform::default().point(&Point::new(1.0, 2.0, 3.0)),
+            Point::new(1.0, 2.0, 3.0)
+        );
+    }
+
+    #[test]
+    fn test_instance() {
+        let mesh = Arc::new(square());
+        // Twice as large, facing +X, 5 along +X.
+        let transform = Transform::new(
+            Vec3::new(5.0, 0.0, 0.0),
+            Vec3::new(0.0, 90.0, 0.0),
+            Vec3::new(2.0, 2.0, 2.0),
+        );
+        let instance = Instance::new(mesh.clone(), transform, MaterialId(3));
+
+        let mut rec = HitRecord::new();
+        let r = Ray::new(Point::new(10.0, 1.0, -1.0), -Vec3::UNIT_X);
+        assert!(instance.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_float_absolute_eq!(rec.t, 5.0, 1e-5);
+        assert!(near(&rec.p, &Point::new(5.0, 1.0, -1.0)));
+        assert!(near(&rec.normal, &Vec3::UNIT_X) && rec.front_face, "{:?}", rec.normal);
+        assert_eq!(rec.material_id, MaterialId(3));
+        // Out of the stretched square, and from behind it.
+        let r = Ray::new(Point::new(10.0, 1.0, -2.5), -Vec3::UNIT_X);
+        assert!(!instance.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        let r = Ray::new(Point::new(0.0, 1.0, -1.0), Vec3::UNIT_X);
+        assert!(instance.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert!(near(&rec.normal, &-Vec3::UNIT_X) && !rec.front_face);
+
+        let bbox = instance.bounding_box();
+        assert!((bbox.min.x - 5.0).abs() < 1e-3 && (bbox.max.x - 5.0).abs() < 1e-3);
+        assert!((bbox.max.y - 2.0).abs() < 1e-3 && (bbox.min.z + 2.0).abs() < 1e-3);
+
+        // The triangles are shared.
+        let copy = Instance::new(mesh.clone(), Transform::default(), MaterialId(0));
+        assert!(Arc::ptr_eq(copy.mesh(), instance.mesh()));
+        assert_eq!(Arc::strong_count(&mesh), 3);
+
+        let mut moved = instance.clone();
+        moved.set_transform(Transform::default());
+        assert!((moved.bounding_box().max - Point::new(1.0, 1.0, 0.0)).len() < 1e-3);
+    }
+}
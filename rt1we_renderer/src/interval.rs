@@ -0,0 +1,58 @@
+//! Intervals of real numbers, such as the distances along a ray where hits are accepted.
+
+/// The closed interval from `min` to `max`, empty when `min > max`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Interval {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Interval {
+    /// Contains no number.
+    pub const EMPTY: Interval = Interval { min: f32::INFINITY, max: f32::NEG_INFINITY };
+    /// Contains every number.
+    pub const UNIVERSE: Interval = Interval { min: f32::NEG_INFINITY, max: f32::INFINITY };
+
+    pub fn new(min: f32, max: f32) -> Self {
+        Interval { min, max }
+    }
+
+    /// Length of the interval, negative when it is empty.
+    pub fn size(&self) -> f32 {
+        self.max - self.min
+    }
+
+    /// Whether `x` is in the interval, its bounds included.
+    pub fn contains(&self, x: f32) -> bool {
+        self.min <= x && x <= self.max
+    }
+
+    /// Whether `x` is strictly inside the interval, its bounds excluded.
+    pub fn surrounds(&self, x: f32) -> bool {
+        self.min < x && x < self.max
+    }
+
+    /// The interval padded by `delta / 2` on each side.
+    pub fn expand(&self, delta: f32) -> Interval {
+        let padding = delta / 2.0;
+        Interval { min: self.min - padding, max: self.max + padding }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::interval::Interval;
+
+    #[test]
+    fn test_bounds() {
+        let i = Interval::new(1.0, 3.0);
+        assert!(i.contains(1.0) && i.contains(2.0) && i.contains(3.0));
+        assert!(!i.contains(0.5) && !i.contains(3.5) && !i.contains(f32::NAN));
+        assert!(i.surrounds(2.0) && !i.surrounds(1.0) && !i.surrounds(3.0));
+        assert_eq!(i.size(), 2.0);
+        assert_eq!(i.expand(1.0), Interval::new(0.5, 3.5));
+
+        assert!(!Interval::EMPTY.contains(0.0) && Interval::EMPTY.size() < 0.0);
+        assert!(Interval::UNIVERSE.contains(f32::MAX) && Interval::UNIVERSE.surrounds(0.0));
+    }
+}
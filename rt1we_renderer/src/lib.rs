@@ -1,9 +1,62 @@
+#[cfg(test)]
 #[macro_use]
 extern crate assert_float_eq;
 
+pub mod aabb;
+pub mod animation;
+pub mod aov;
+pub mod bvh;
+pub mod cancel;
+pub mod compare;
+pub mod cylinder;
+pub mod denoise;
+pub mod environment;
+pub mod error;
+pub mod estimate;
 pub mod geometry;
+pub mod heightfield;
+pub mod histogram;
 pub mod image;
+pub mod imageio;
+pub mod inspect;
+pub mod instance;
+pub mod interval;
+pub mod light;
+pub mod lod;
+pub mod medium;
+pub mod mesh;
+pub mod microfacet;
+pub mod mipmap;
+pub mod onb;
+pub mod output;
+pub mod pdf;
+pub mod perlin;
+pub mod poly;
 pub mod ppmio;
+pub mod preset;
+pub mod principled;
+pub mod progressive;
+pub mod raster;
 pub mod ray;
+pub mod rect;
+pub mod registry;
 pub mod render;
+pub mod sampler;
+pub mod scene_io;
+pub mod scenes;
+pub mod sdf;
+pub mod settings;
+pub mod sky;
+pub mod spectrum;
+pub mod stats;
+pub mod stereo;
+pub mod subsurface;
+pub mod texture;
+pub mod texture_cache;
+pub mod thin_film;
+pub mod tiles;
+pub mod tonemap;
+pub mod torus;
 pub mod trig;
+pub mod view;
+pub mod wavefront;
@@ -0,0 +1,255 @@
+//! Lights apart from the objects of the scene: the sun, so far away that its light comes from
+//! the same small disk of the sky everywhere, and the point and spot lights, tiny bulbs which
+//! the rays never hit, lighting the scene through shadow rays.
+use crate::geometry::{dot, Color, Point, Vec3};
+use crate::onb::Onb;
+use crate::pdf::Pdf;
+use crate::sampler::Sampler;
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// A distant light, as the sun: a disk of the sky, seen under the same angle from everywhere.
+///
+/// Its shadows are as soft as its disk is wide.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DirectionalLight {
+    /// Direction towards the light.
+    pub direction: Vec3,
+    /// Angular radius of the disk, in degrees, from 0 excluded to 90: 0.27 for the sun.
+    pub angular_radius: f32,
+    pub color: Color,
+    /// Light received by a surface facing it, scaling its color.
+    pub intensity: f32,
+}
+
+impl Default for DirectionalLight {
+    /// A white sun, high in the sky.
+    fn default() -> Self {
+        DirectionalLight {
+            direction: Vec3::new(1.0, 2.0, 1.0),
+            angular_radius: 0.27,
+            color: Color::WHITE,
+            intensity: 3.0,
+        }
+    }
+}
+
+impl DirectionalLight {
+    /// Cosine of the angular radius, the smallest cosine of a direction towards the disk.
+    fn cos_max(&self) -> f32 {
+        self.angular_radius.to_radians().cos()
+    }
+
+    /// Solid angle of the disk.
+    pub fn solid_angle(&self) -> f32 {
+        2.0 * PI * (1.0 - self.cos_max())
+    }
+
+    /// Light coming from `dir`: the color of the light spread over its disk, black outside it.
+    pub fn radiance(&self, dir: &Vec3) -> Color {
+        if dot(&dir.normed(), &self.direction.normed()) < self.cos_max() {
+            return Color::BLACK;
+        }
+        self.color * (self.intensity / self.solid_angle())
+    }
+
+    /// Directions towards the disk, for the bounces to sample the light.
+    pub(crate) fn pdf(&self) -> ConePdf {
+        ConePdf { uvw: Onb::from_w(&self.direction), cos_max: self.cos_max() }
+    }
+}
+
+/// Directions evenly spread in a cone.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct ConePdf {
+    /// Basis around the axis of the cone.
+    uvw: Onb,
+    /// Cosine of the half angle of the cone.
+    cos_max: f32,
+}
+
+impl Pdf for ConePdf {
+    fn value(&self, direction: &Vec3) -> f32 {
+        if dot(&direction.normed(), &self.uvw.w) < self.cos_max {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - self.cos_max))
+    }
+
+    fn generate(&self, rng: &mut Sampler) -> Vec3 {
+        let (r1, r2) = (rng.gen::<f32>(), rng.gen::<f32>());
+        let z = 1.0 - r1 * (1.0 - self.cos_max);
+        let phi = 2.0 * PI * r2;
+        let sin = (1.0 - z * z).max(0.0).sqrt();
+        self.uvw.local(&Vec3::new(phi.cos() * sin, phi.sin() * sin, z))
+    }
+}
+
+/// A light bulb of no size, shining evenly in all directions.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PointLight {
+    pub position: Point,
+    pub color: Color,
+    /// Light received by a surface facing it, at a distance of 1, scaling its color.
+    pub intensity: f32,
+}
+
+impl Default for PointLight {
+    /// A white bulb, above the origin.
+    fn default() -> Self {
+        PointLight { position: Point::new(0.0, 2.0, 0.0), color: Color::WHITE, intensity: 5.0 }
+    }
+}
+
+/// A point light shining in a cone only, as a stage light.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpotLight {
+    pub position: Point,
+    /// Axis of the cone, away from the light.
+    pub direction: Vec3,
+    /// Half angle of the cone, in degrees, from 0 excluded to 90.
+    pub angle: f32,
+    /// Share of the cone, from its edge, over which the light fades out, from 0 for a sharp
+    /// edge to 1.
+    pub blend: f32,
+    pub color: Color,
+    /// Light received by a surface facing it on the axis, at a distance of 1, scaling its
+    /// color.
+    pub intensity: f32,
+}
+
+impl Default for SpotLight {
+    /// A white spot, above the origin, pointing down.
+    fn default() -> Self {
+        SpotLight {
+            position: Point::new(0.0, 2.0, 0.0),
+            direction: -Vec3::UNIT_Y,
+            angle: 30.0,
+            blend: 0.2,
+            color: Color::WHITE,
+            intensity: 5.0,
+        }
+    }
+}
+
+impl SpotLight {
+    /// Share of the light sent along `dir`, from 1 inside the cone to 0 outside it.
+    fn falloff(&self, dir: &Vec3) -> f32 {
+        let cosine = dot(&dir.normed(), &self.direction.normed());
+        let outer = self.angle.to_radians().cos();
+        let inner = (self.angle * (1.0 - self.blend.clamp(0.0, 1.0))).to_radians().cos();
+        if cosine >= inner {
+            return 1.0;
+        }
+        let t = ((cosine - outer) / (inner - outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+/// A light of no size, lit with shadow rays by the diffuse bounces: see
+/// [`crate::render::Scene::punctual_lights`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PunctualLight {
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl From<PointLight> for PunctualLight {
+    fn from(light: PointLight) -> Self {
+        PunctualLight::Point(light)
+    }
+}
+
+impl From<SpotLight> for PunctualLight {
+    fn from(light: SpotLight) -> Self {
+        PunctualLight::Spot(light)
+    }
+}
+
+impl PunctualLight {
+    /// Kind of light, for display.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PunctualLight::Point(_) => "point",
+            PunctualLight::Spot(_) => "spot",
+        }
+    }
+
+    pub fn position(&self) -> Point {
+        match self {
+            PunctualLight::Point(light) => light.position,
+            PunctualLight::Spot(light) => light.position,
+        }
+    }
+
+    /// Light sent along `dir`, away from the light, per unit of solid angle.
+    pub fn intensity(&self, dir: &Vec3) -> Color {
+        match self {
+            PunctualLight::Point(light) => light.color * light.intensity,
+            PunctualLight::Spot(light) => light.color * (light.intensity * light.falloff(dir)),
+        }
+    }
+
+    /// Light received at `p`, on a surface facing the light, when nothing is in between.
+    pub fn irradiance(&self, p: &Point) -> Color {
+        let to = *p - self.position();
+        self.intensity(&to) / dot(&to, &to)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{dot, random_unit_vector, Color, Point, Vec3};
+    use crate::light::{DirectionalLight, PointLight, PunctualLight, SpotLight};
+    use crate::pdf::Pdf;
+    use crate::sampler::Sampler;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_directional_light() {
+        let sun = DirectionalLight {
+            direction: Vec3::UNIT_Y,
+            angular_radius: 30.0,
+            ..Default::default()
+        };
+        // The disk of the light brings `intensity` to a surface facing it, roughly.
+        let radiance = sun.radiance(&Vec3::new(0.0, 2.0, 0.1));
+        assert_float_absolute_eq!(radiance.y * sun.solid_angle(), sun.intensity, 1e-4);
+        assert_eq!(sun.radiance(&Vec3::new(1.0, 1.0, 0.0)), Color::BLACK);
+
+        let (pdf, mut rng) = (sun.pdf(), Sampler::new(0));
+        let cos_max = 30f32.to_radians().cos();
+        for _ in 0..1000 {
+            let d = pdf.generate(&mut rng);
+            assert_float_absolute_eq!(d.len(), 1.0, 1e-5);
+            assert!(dot(&d, &Vec3::UNIT_Y) >= cos_max - 1e-5);
+            assert_float_absolute_eq!(pdf.value(&d), 1.0 / sun.solid_angle(), 1e-3);
+        }
+        // The density integrates to 1 over the sphere.
+        let n = 100_000;
+        let sum: f32 = (0..n).map(|_| pdf.value(&random_unit_vector(&mut rng))).sum();
+        assert_float_absolute_eq!(4.0 * PI * sum / n as f32, 1.0, 0.05);
+    }
+
+    #[test]
+    fn test_punctual_lights() {
+        // The light of a bulb falls off with the square of the distance.
+        let bulb = PunctualLight::from(PointLight { intensity: 4.0, ..Default::default() });
+        assert_eq!(bulb.name(), "point");
+        assert_eq!(bulb.irradiance(&Point::new(0.0, 1.0, 0.0)), Color::new(4.0, 4.0, 4.0));
+        assert_eq!(bulb.irradiance(&Point::new(0.0, 4.0, 0.0)), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(bulb.irradiance(&Point::new(2.0, 2.0, 0.0)), Color::new(1.0, 1.0, 1.0));
+
+        // A spot only lights its cone, fading out towards its edge.
+        let spot = PunctualLight::from(SpotLight { angle: 45.0, blend: 0.5, ..Default::default() });
+        assert_eq!(spot.name(), "spot");
+        assert_eq!(spot.position(), Point::new(0.0, 2.0, 0.0));
+        let on_axis = spot.intensity(&-Vec3::UNIT_Y);
+        assert_eq!(on_axis, Color::new(5.0, 5.0, 5.0));
+        assert_eq!(spot.intensity(&Vec3::new(0.2, -1.0, 0.0)), on_axis);
+        let edge = spot.intensity(&Vec3::new(0.8, -1.0, 0.0)).x;
+        assert!(0.0 < edge && edge < 5.0, "{edge}");
+        assert_eq!(spot.intensity(&Vec3::new(1.1, -1.0, 0.0)), Color::BLACK);
+        assert_eq!(spot.intensity(&Vec3::UNIT_Y), Color::BLACK);
+    }
+}
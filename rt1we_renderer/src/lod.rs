@@ -0,0 +1,151 @@
+//! Level of detail: groups of objects replaced by a single proxy sphere when they are seen
+//! from far away.
+//!
+//! A cluster of small spheres covering a few pixels costs as many intersection tests as a
+//! close one, for a result that a single sphere with their average color matches. Rays
+//! reaching a group from far enough hit its proxy instead of its members.
+use crate::geometry::{Color, Point};
+use crate::registry::MaterialId;
+use crate::render::{MaterialParams, Sphere};
+
+/// Default angular size below which a group is replaced by its proxy, in radians: about a
+/// pixel of a 1000 pixels high image with a 90° field of view.
+pub const DEFAULT_LOD_THRESHOLD: f32 = 0.0015;
+
+/// Objects replaced by a proxy sphere when seen from far away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyGroup {
+    /// Indices of the member objects in the scene.
+    pub members: Vec<usize>,
+    /// Material of the proxy.
+    pub material_id: MaterialId,
+}
+
+/// A sphere enclosing all the given spheres, centered on their centers average. Not the
+/// smallest one, but close for clusters of similar spheres.
+///
+/// # Returns
+/// `None` when there are no spheres.
+pub fn bounding_sphere(spheres: &[Sphere], material_id: MaterialId) -> Option<Sphere> {
+    if spheres.is_empty() {
+        return None;
+    }
+    let sum = spheres.iter().fold(Point::ZERO, |sum, s| sum + s.center);
+    let center = sum / spheres.len() as f32;
+    let radius =
+        spheres.iter().map(|s| (s.center - center).len() + s.radius.abs()).fold(0.0, f32::max);
+    Some(Sphere::new(center, radius, material_id))
+}
+
+/// A diffuse material averaging the color of some materials.
+///
+/// Lambertian, metal, isotropic and subsurface materials count with their average albedo,
+/// microfacet ones with their diffuse and glossy colors added, principled ones with their base
+/// color, dielectrics as white, lights with their color.
+pub fn averaged_material(materials: &[MaterialParams]) -> MaterialParams {
+    let colors = materials.iter().map(|m| match m {
+        MaterialParams::Lambertian { albedo } => albedo.average(),
+        MaterialParams::Metal { albedo, .. } => *albedo,
+        MaterialParams::Microfacet { diffuse, f0, .. } => *diffuse + *f0,
+        MaterialParams::Principled(params) => params.base_color,
+        MaterialParams::Subsurface { albedo, .. } => *albedo,
+        MaterialParams::Dielectric { .. } => Color::WHITE,
+        MaterialParams::DiffuseLight { color, .. } => *color,
+        MaterialParams::Isotropic { albedo } => albedo.average(),
+    });
+    let sum = colors.fold(Color::BLACK, |sum, c| sum + c);
+    MaterialParams::Lambertian { albedo: (sum / materials.len().max(1) as f32).into() }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Color, Point, Vec3};
+    use crate::interval::Interval;
+    use crate::lod::{averaged_material, bounding_sphere};
+    use crate::ray::Ray;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, MaterialParams, Plane, Scene, Sphere};
+    use crate::texture::TextureParams;
+
+    #[test]
+    fn test_bounding_sphere_encloses_the_spheres() {
+        let spheres = [
+            Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, MaterialId(0)),
+            Sphere::new(Point::new(4.0, 0.0, 0.0), -0.5, MaterialId(1)),
+        ];
+        let bound = bounding_sphere(&spheres, MaterialId(7)).unwrap();
+        assert_eq!(bound.center, Point::new(2.0, 0.0, 0.0));
+        assert_eq!(bound.radius, 3.0);
+        assert_eq!(bound.material_id, MaterialId(7));
+        assert!(bounding_sphere(&[], MaterialId(0)).is_none());
+    }
+
+    #[test]
+    fn test_averaged_material() {
+        let materials = [
+            MaterialParams::Lambertian { albedo: Color::new(1.0, 0.0, 0.0).into() },
+            MaterialParams::Metal { albedo: Color::new(0.0, 0.5, 0.0), fuzz: 0.1, film: None },
+            MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.0, film: None },
+        ];
+        let MaterialParams::Lambertian { albedo: TextureParams::Solid(albedo) } =
+            averaged_material(&materials)
+        else {
+            panic!("the average is diffuse");
+        };
+        assert_f32_near!(albedo.x, 2.0 / 3.0);
+        assert_f32_near!(albedo.y, 0.5);
+        assert_f32_near!(albedo.z, 1.0 / 3.0);
+    }
+
+    /// A cluster of two spheres with a gap between them, along the x axis at z = -10.
+    fn cluster_scene() -> Scene {
+        let mut scene = Scene::empty();
+        let material =
+            scene.add_material(&MaterialParams::Lambertian { albedo: Color::WHITE.into() });
+        scene.world_mut().add(Sphere::new(Point::new(-1.0, 0.0, -10.0), 0.5, material));
+        scene.world_mut().add(Sphere::new(Point::new(1.0, 0.0, -10.0), 0.5, material));
+        scene.add_proxy(&[0, 1]).unwrap();
+        scene
+    }
+
+    #[test]
+    fn test_far_rays_hit_the_proxy() {
+        let mut scene = cluster_scene();
+        // Through the gap between the members.
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+
+        scene.set_lod_threshold(0.0);
+        assert!(!scene.bvh().hit(&r, Interval::new(0.001, f32::INFINITY), &mut HitRecord::new()));
+
+        // The group, 1.5 units wide at 10 units, is below the threshold.
+        scene.set_lod_threshold(0.2);
+        let mut rec = HitRecord::new();
+        assert!(scene.bvh().hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!(rec.material_id, scene.proxies()[0].material_id);
+        assert_eq!(rec.object_id, 0);
+        assert_f32_near!(rec.t, 8.5);
+    }
+
+    #[test]
+    fn test_close_rays_hit_the_members() {
+        let mut scene = cluster_scene();
+        scene.set_lod_threshold(0.2);
+        let orig = Point::new(0.0, 0.0, -7.0);
+        let r = Ray::new(orig, Point::new(1.0, 0.0, -10.0) - orig);
+
+        let mut rec = HitRecord::new();
+        assert!(scene.bvh().hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!(rec.object_id, 1);
+        assert_eq!(rec.material_id, MaterialId(0));
+    }
+
+    #[test]
+    fn test_invalid_proxies() {
+        let mut scene = cluster_scene();
+        assert!(scene.add_proxy(&[]).is_err());
+        assert!(scene.add_proxy(&[1, 2]).is_err());
+        scene.world_mut().add(Plane::new(Point::ZERO, Vec3::UNIT_Y, MaterialId(0)));
+        assert!(scene.add_proxy(&[1, 2]).is_err());
+        assert_eq!(scene.proxies().len(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+//! Participating media: smoke and fog, scattering the rays inside a volume instead of on a
+//! surface.
+use crate::aabb::Aabb;
+use crate::geometry::Vec3;
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::registry::MaterialId;
+use crate::render::{HitRecord, Hittable, Object};
+use crate::sampler::Sampler;
+use rand::Rng;
+
+/// A volume of constant density, inside the boundary of another object.
+///
+/// A ray crossing the volume is hit at a random distance, more likely to be short the denser
+/// the volume, or goes through. The hit is scattered by the material of the volume, which is
+/// usually isotropic. The boundary must be convex: a ray crosses it at most once each way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstantMedium {
+    /// Object enclosing the volume, its material is not used.
+    pub boundary: Box<Object>,
+    /// Probability that a ray is hit per unit of distance travelled in the volume.
+    pub density: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: impl Into<Object>, density: f32, material_id: MaterialId) -> Self {
+        ConstantMedium { boundary: Box::new(boundary.into()), density, material_id }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // Where the ray line enters and leaves the volume, even behind its origin, so that
+        // rays starting inside the volume are handled as the others.
+        let mut enter = HitRecord::new();
+        if !self.boundary.hit(r, Interval::UNIVERSE, &mut enter) {
+            return false;
+        }
+        let mut leave = HitRecord::new();
+        if !self.boundary.hit(r, Interval::new(enter.t + 0.0001, f32::INFINITY), &mut leave) {
+            return false;
+        }
+        let (t_enter, t_leave) = (enter.t.max(ray_t.min).max(0.0), leave.t.min(ray_t.max));
+        if t_enter >= t_leave {
+            return false;
+        }
+
+        let ray_length = r.dir.len();
+        let distance_inside = (t_leave - t_enter) * ray_length;
+        // Drawn from the ray, so that hits need no sampler and a ray always goes as far.
+        let mut rng = Sampler::for_ray(r, enter.t);
+        let hit_distance = -rng.gen::<f32>().ln() / self.density;
+        if hit_distance > distance_inside {
+            return false;
+        }
+
+        rec.t = t_enter + hit_distance / ray_length;
+        rec.p = r.at(rec.t);
+        // A volume has no surface, any normal will do.
+        rec.normal = Vec3::UNIT_X;
+        rec.front_face = true;
+        (rec.u, rec.v) = (0.0, 0.0);
+        rec.material_id = self.material_id;
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Point, Vec3};
+    use crate::interval::Interval;
+    use crate::medium::ConstantMedium;
+    use crate::ray::Ray;
+    use crate::rect::Cuboid;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, Hittable, Sphere};
+
+    /// Share of `count` rays along `r` hitting `medium`.
+    ///
+    /// The same ray always goes as far in a medium: the rays are cast at different times.
+    fn hit_rate(medium: &ConstantMedium, r: &Ray, count: usize) -> f32 {
+        let mut rec = HitRecord::new();
+        let hits = (0..count)
+            .filter(|&k| {
+                let r = Ray { time: k as f32, ..*r };
+                medium.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec)
+            })
+            .count();
+        hits as f32 / count as f32
+    }
+
+    #[test]
+    fn test_constant_medium() {
+        // A slab of fog 2 units thick.
+        let slab =
+            Cuboid::new(Point::new(-5.0, -5.0, -3.0), Point::new(5.0, 5.0, -1.0), MaterialId(0));
+        let medium = ConstantMedium::new(slab, 0.5, MaterialId(3));
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+        let mut rec = HitRecord::new();
+        for time in 0..100 {
+            let r = Ray { time: time as f32, ..r };
+            if medium.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec) {
+                assert!((1.0..=3.0).contains(&rec.t), "{}", rec.t);
+                assert_eq!((rec.p, rec.material_id), (r.at(rec.t), MaterialId(3)));
+            }
+        }
+        // The same ray always gets the same answer.
+        let hit = medium.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec);
+        assert!(
+            (0..10).all(|_| medium.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec) == hit)
+        );
+        // The rays go through with a probability of exp(-density * thickness).
+        let rate = hit_rate(&medium, &r, 10_000);
+        assert!((rate - (1.0 - (-1.0f32).exp())).abs() < 0.03, "{rate}");
+        assert_eq!(hit_rate(&ConstantMedium::new(slab, 1e6, MaterialId(0)), &r, 100), 1.0);
+
+        // Beside the volume, or stopped before it.
+        let r = Ray::new(Point::new(6.0, 0.0, 0.0), -Vec3::UNIT_Z);
+        assert_eq!(hit_rate(&medium, &r, 100), 0.0);
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+        assert!(!ConstantMedium::new(slab, 1e6, MaterialId(0)).hit(
+            &r,
+            Interval::new(0.001, 0.5),
+            &mut rec
+        ));
+    }
+
+    #[test]
+    fn test_rays_starting_inside_the_medium() {
+        let medium =
+            ConstantMedium::new(Sphere::new(Point::ZERO, 2.0, MaterialId(0)), 1e6, MaterialId(0));
+        let r = Ray::new(Point::new(0.5, 0.0, 0.0), Vec3::UNIT_Y);
+        let mut rec = HitRecord::new();
+        // A dense medium scatters the ray right away, ahead of its origin.
+        assert!(medium.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert!(rec.t >= 0.001 && rec.t < 0.01, "{}", rec.t);
+
+        let thin = ConstantMedium { density: 1e-6, ..medium.clone() };
+        assert_eq!(hit_rate(&thin, &r, 100), 0.0);
+        assert_eq!(medium.bounding_box().max, Point::new(2.0, 2.0, 2.0));
+    }
+}
@@ -0,0 +1,267 @@
+//! Polygonal geometry: single triangles, and meshes of triangles sharing their vertices.
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::error::RtError;
+use crate::geometry::{Point, Vec3};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::registry::MaterialId;
+use crate::render::{HitRecord, Hittable, Object};
+use std::fmt;
+
+/// Thickness given to the boxes of triangles aligned with an axis, which would be missed by
+/// every ray otherwise.
+pub(crate) const BOX_PADDING: f32 = 1e-4;
+
+/// Triangle object description, seen from both sides.
+///
+/// The front side is the one from which the vertices turn counterclockwise.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Triangle {
+    pub vertices: [Point; 3],
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+impl Triangle {
+    pub fn new(a: Point, b: Point, c: Point, material_id: MaterialId) -> Self {
+        Triangle { vertices: [a, b, c], material_id }
+    }
+}
+
+impl Hittable for Triangle {
+    /// Möller–Trumbore intersection: solves for the distance along the ray and the
+    /// barycentric coordinates of the hit at once.
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let [a, b, c] = self.vertices;
+        let (edge1, edge2) = (b - a, c - a);
+        let p = r.dir.cross(&edge2);
+        let det = edge1.dot(&p);
+        // The ray is parallel to the triangle, or the triangle is degenerate.
+        if det.abs() <= f32::EPSILON * edge1.len_squared().max(edge2.len_squared()) {
+            return false;
+        }
+        let inv_det = 1.0 / det;
+
+        let s = r.orig - a;
+        let u = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return false;
+        }
+        let q = s.cross(&edge1);
+        let v = r.dir.dot(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return false;
+        }
+        let t = edge2.dot(&q) * inv_det;
+        if !ray_t.contains(t) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = r.at(t);
+        (rec.u, rec.v) = (u, v);
+        rec.material_id = self.material_id;
+        rec.set_face_normal(r, &edge1.cross(&edge2).normed());
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.vertices;
+        let bbox = Aabb::new(a, b).surrounding(&Aabb::new(c, c));
+        let padding = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        Aabb::new(bbox.min - padding, bbox.max + padding)
+    }
+}
+
+/// Triangles sharing a vertex buffer, with a single material.
+///
+/// The mesh keeps its own hierarchy over its triangles, so it is hit as fast as if its
+/// triangles were objects of the scene.
+#[derive(Clone)]
+pub struct Mesh {
+    vertices: Vec<Point>,
+    /// Indices in `vertices` of the corners of each triangle.
+    indices: Vec<[usize; 3]>,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    /// Create a mesh from its buffers.
+    ///
+    /// # Arguments
+    /// - `vertices` - Positions of the vertices.
+    /// - `indices` - Indices in `vertices` of the three corners of each triangle.
+    ///
+    /// # Returns
+    /// The mesh, or an error if an index is out of the vertex buffer.
+    pub fn new(
+        vertices: Vec<Point>, indices: Vec<[usize; 3]>, material_id: MaterialId,
+    ) -> Result<Self, RtError> {
+        if let Some(k) = indices.iter().position(|t| t.iter().any(|&i| i >= vertices.len())) {
+            return Err(RtError::Geometry(format!(
+                "triangle {k} refers to a missing vertex, the mesh has {} vertices",
+                vertices.len()
+            )));
+        }
+        let triangles: Vec<Object> = indices
+            .iter()
+            .map(|t| {
+                Triangle::new(vertices[t[0]], vertices[t[1]], vertices[t[2]], material_id).into()
+            })
+            .collect();
+        let bvh = Bvh::new(&triangles);
+        Ok(Mesh { vertices, indices, material_id, bvh })
+    }
+
+    pub fn vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[[usize; 3]] {
+        &self.indices
+    }
+
+    /// Number of triangles.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Approximate memory used by the buffers and the hierarchy of the mesh, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.vertices.len() * std::mem::size_of::<Point>()
+            + self.indices.len() * std::mem::size_of::<[usize; 3]>()
+            + self.bvh.memory_size()
+    }
+
+    /// The triangle at `index`, with the material of the mesh.
+    pub fn triangle(&self, index: usize) -> Triangle {
+        let [a, b, c] = self.indices[index].map(|i| self.vertices[i]);
+        Triangle::new(a, b, c, self.material_id)
+    }
+}
+
+impl fmt::Debug for Mesh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mesh")
+            .field("vertices", &self.vertices)
+            .field("indices", &self.indices)
+            .field("material_id", &self.material_id)
+            .finish()
+    }
+}
+
+impl PartialEq for Mesh {
+    fn eq(&self, other: &Self) -> bool {
+        self.vertices == other.vertices
+            && self.indices == other.indices
+            && self.material_id == other.material_id
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // The hierarchy reports the triangle as the object hit, the caller reports the mesh.
+        let hit = self.bvh.hit(r, ray_t, rec);
+        if hit {
+            rec.material_id = self.material_id;
+        }
+        hit
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::error::RtError;
+    use crate::geometry::{Point, Vec3};
+    use crate::interval::Interval;
+    use crate::mesh::{Mesh, Triangle};
+    use crate::ray::Ray;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, Hittable, HittableList};
+
+    /// Unit square in the z = -2 plane, facing +z, made of two triangles.
+    fn square(material_id: MaterialId) -> Mesh {
+        let vertices = vec![
+            Point::new(0.0, 0.0, -2.0),
+            Point::new(1.0, 0.0, -2.0),
+            Point::new(1.0, 1.0, -2.0),
+            Point::new(0.0, 1.0, -2.0),
+        ];
+        Mesh::new(vertices, vec![[0, 1, 2], [0, 2, 3]], material_id).unwrap()
+    }
+
+    #[test]
+    fn test_triangle_hit() {
+        let triangle = Triangle::new(
+            Point::new(-1.0, -1.0, -2.0),
+            Point::new(1.0, -1.0, -2.0),
+            Point::new(0.0, 1.0, -2.0),
+            MaterialId(4),
+        );
+        let mut rec = HitRecord::new();
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+        assert!(triangle.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.p, rec.normal), (2.0, Point::new(0.0, 0.0, -2.0), Vec3::UNIT_Z));
+        assert_eq!(rec.material_id, MaterialId(4));
+        assert!(rec.front_face);
+
+        // From behind, the normal faces the ray.
+        let r = Ray::new(Point::new(0.0, 0.0, -4.0), Vec3::UNIT_Z);
+        assert!(triangle.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, -Vec3::UNIT_Z);
+
+        // Beside the triangle, out of range, and parallel to it.
+        let r = Ray::new(Point::new(0.9, 0.9, 0.0), -Vec3::UNIT_Z);
+        assert!(!triangle.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+        assert!(!triangle.hit(&r, Interval::new(0.001, 1.0), &mut rec));
+        let r = Ray::new(Point::new(-5.0, 0.0, -2.0), Vec3::UNIT_X);
+        assert!(!triangle.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+    }
+
+    #[test]
+    fn test_axis_aligned_box_is_not_flat() {
+        let triangle = Triangle::new(Point::ZERO, Vec3::UNIT_X, Vec3::UNIT_Y, MaterialId(0));
+        let bbox = triangle.bounding_box();
+        assert!(bbox.extent().z > 0.0);
+        let r = Ray::new(Point::new(0.2, 0.2, 1.0), -Vec3::UNIT_Z);
+        assert!(bbox.hit(&r, Interval::new(0.001, f32::INFINITY)));
+    }
+
+    #[test]
+    fn test_mesh() {
+        let mesh = square(MaterialId(2));
+        assert_eq!(mesh.len(), 2);
+        assert_eq!(mesh.triangle(1).vertices[2], Point::new(0.0, 1.0, -2.0));
+        assert_eq!(mesh.clone(), mesh);
+        let err = Mesh::new(vec![Point::ZERO], vec![[0, 0, 1]], MaterialId(0)).err().unwrap();
+        assert!(matches!(err, RtError::Geometry(_)));
+        assert_eq!(
+            err.to_string(),
+            "triangle 0 refers to a missing vertex, the mesh has 1 vertices"
+        );
+
+        let mut world = HittableList::new();
+        world.add(mesh);
+        let mut rec = HitRecord::new();
+        for (x, y) in [(0.75, 0.25), (0.25, 0.75)] {
+            let r = Ray::new(Point::new(x, y, 0.0), -Vec3::UNIT_Z);
+            assert!(world.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+            assert_eq!((rec.t, rec.material_id, rec.object_id), (2.0, MaterialId(2), 0));
+        }
+        let r = Ray::new(Point::new(1.5, 0.5, 0.0), -Vec3::UNIT_Z);
+        assert!(!world.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+    }
+}
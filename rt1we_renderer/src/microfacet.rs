@@ -0,0 +1,293 @@
+//! Microfacet surfaces: glossy metals and plastics, whose surface is made of tiny mirror facets
+//! with normals following the GGX (Trowbridge-Reitz) distribution.
+//!
+//! Directions are in the local coordinates of the surface, with the normal along z.
+use crate::geometry::{dot, Color, Vec3};
+use crate::onb::Onb;
+use crate::pdf::random_cosine_direction;
+use crate::ray::Ray;
+use crate::render::{HitRecord, Material, MaterialParams, ScatterResult};
+use crate::sampler::Sampler;
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// Smallest width of the distribution, below which it is too peaked to evaluate in `f32`.
+const MIN_ALPHA: f32 = 1e-3;
+
+/// GGX distribution of the normals of the facets.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ggx {
+    /// Width of the distribution, from smooth at 0 to rough at 1.
+    pub alpha: f32,
+}
+
+impl Ggx {
+    /// The distribution of a perceptual `roughness` from 0 to 1, whose square is the width.
+    pub fn from_roughness(roughness: f32) -> Self {
+        Ggx { alpha: (roughness * roughness).max(MIN_ALPHA) }
+    }
+
+    /// Density of the facet normal `h`, per unit of solid angle projected on the surface.
+    pub fn d(&self, h: &Vec3) -> f32 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let denominator = h.z * h.z * (a2 - 1.0) + 1.0;
+        a2 / (PI * denominator * denominator)
+    }
+
+    /// Smith's auxiliary function, the share of the facets hidden from `w` being `lambda / (1 +
+    /// lambda)`.
+    fn lambda(&self, w: &Vec3) -> f32 {
+        let cos2 = w.z * w.z;
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Share of the facets seen from `w`, the others being hidden by their neighbours.
+    pub fn g1(&self, w: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Share of the facets seen from both `wo` and `wi`.
+    pub fn g2(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// A random facet normal seen from `wo`, with a density proportional to the area of the
+    /// facets of that normal seen from `wo`, following Heitz's sampling of the visible normals.
+    ///
+    /// # Arguments
+    /// - `wo` - Unit direction from the surface to the viewer, above it.
+    /// - `u1`, `u2` - Random numbers in `[0;1)`.
+    pub fn sample_visible_normal(&self, wo: &Vec3, u1: f32, u2: f32) -> Vec3 {
+        // Stretched into the configuration of a hemisphere of radius 1.
+        let vh = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).normed();
+        let len_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len_squared > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / len_squared.sqrt()
+        } else {
+            Vec3::UNIT_X
+        };
+        let t2 = vh.cross(&t1);
+        // A point of the unit disk, of the half of it seen from `wo` once projected.
+        let (r, phi) = (u1.sqrt(), 2.0 * PI * u2);
+        let (p1, p2) = (r * phi.cos(), r * phi.sin());
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * p2;
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        // Back to the configuration of the distribution.
+        Vec3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(0.0)).normed()
+    }
+
+    /// Density of the directions `wi` reflected off the facets sampled by
+    /// [`Ggx::sample_visible_normal`], per unit of solid angle.
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        let h = (*wo + *wi).normed();
+        self.g1(wo) * self.d(&h) / (4.0 * wo.z)
+    }
+}
+
+/// Schlick's approximation of the Fresnel reflectance, of `f0` at normal incidence.
+pub(crate) fn fresnel(f0: &Color, cosine: f32) -> Color {
+    let weight = (1.0 - cosine).clamp(0.0, 1.0).powi(5);
+    *f0 + weight * (Color::WHITE - *f0)
+}
+
+/// Glossy reflection off GGX facets, over a diffuse base.
+///
+/// Metals have a black base and reflect their color, plastics have a colored base under a
+/// reflection of about 4% at normal incidence.
+pub(crate) struct Microfacet {
+    diffuse: Color,
+    f0: Color,
+    roughness: f32,
+    ggx: Ggx,
+}
+
+impl Microfacet {
+    pub fn new(diffuse: Color, f0: Color, roughness: f32) -> Self {
+        Microfacet { diffuse, f0, roughness, ggx: Ggx::from_roughness(roughness) }
+    }
+
+    /// Probability to sample the glossy reflection instead of the diffuse base, in proportion
+    /// to the light each of them reflects seen from `wo`.
+    fn glossy_probability(&self, wo: &Vec3) -> f32 {
+        let average = |c: Color| (c.x + c.y + c.z) / 3.0;
+        let fresnel = fresnel(&self.f0, wo.z);
+        let glossy = average(fresnel);
+        let diffuse = average((Color::WHITE - fresnel) * self.diffuse);
+        if glossy + diffuse > 0.0 {
+            glossy / (glossy + diffuse)
+        } else {
+            1.0
+        }
+    }
+
+    /// Reflected light, from `wi` to `wo`, per unit of incoming light and solid angle.
+    fn brdf(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let h = (*wo + *wi).normed();
+        let glossy = fresnel(&self.f0, dot(wi, &h))
+            * (self.ggx.d(&h) * self.ggx.g2(wo, wi) / (4.0 * wo.z * wi.z));
+        // The diffuse base only gets the light the glossy coat lets through, both ways.
+        let coat =
+            (Color::WHITE - fresnel(&self.f0, wo.z)) * (Color::WHITE - fresnel(&self.f0, wi.z));
+        glossy + coat * self.diffuse / PI
+    }
+
+    /// Density of the directions sampled by [`Material::scatter`], per unit of solid angle.
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        let glossy = self.glossy_probability(wo);
+        glossy * self.ggx.pdf(wo, wi) + (1.0 - glossy) * wi.z / PI
+    }
+}
+
+impl Material for Microfacet {
+    fn params(&self) -> MaterialParams {
+        MaterialParams::Microfacet { diffuse: self.diffuse, f0: self.f0, roughness: self.roughness }
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Sampler) -> Option<ScatterResult> {
+        let uvw = Onb::from_w(&rec.normal);
+        let wo = uvw.coordinates(&-r_in.dir.normed());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let wi = if rng.gen::<f32>() < self.glossy_probability(&wo) {
+            let h = self.ggx.sample_visible_normal(&wo, rng.gen(), rng.gen());
+            2.0 * dot(&wo, &h) * h - wo
+        } else {
+            random_cosine_direction(rng)
+        };
+        // Reflected below the surface, the ray is absorbed.
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some(ScatterResult {
+            attenuation: self.brdf(&wo, &wi) * (wi.z / self.pdf(&wo, &wi)),
+            scattered: Ray {
+                orig: rec.p,
+                dir: uvw.local(&wi),
+                time: r_in.time,
+                wavelength: r_in.wavelength,
+            },
+            pdf: None,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{random_unit_vector, Color, Point, Vec3};
+    use crate::microfacet::{Ggx, Microfacet};
+    use crate::ray::Ray;
+    use crate::render::{HitRecord, Material};
+    use crate::sampler::Sampler;
+    use rand::Rng;
+    use std::f32::consts::PI;
+
+    const SAMPLES: usize = 200_000;
+
+    /// Monte Carlo estimate of the integral of `f` over the hemisphere above the surface.
+    fn integrate(f: impl Fn(&Vec3) -> f32, rng: &mut Sampler) -> f32 {
+        let mut above = |_| {
+            let w = random_unit_vector(rng);
+            f(&Vec3::new(w.x, w.y, w.z.abs()))
+        };
+        let sum: f32 = (0..SAMPLES).map(&mut above).sum();
+        2.0 * PI * sum / SAMPLES as f32
+    }
+
+    /// Unit direction at `degrees` from the normal.
+    fn direction(degrees: f32) -> Vec3 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Vec3::new(sin, 0.0, cos)
+    }
+
+    #[test]
+    fn test_ggx_distribution() {
+        let mut rng = Sampler::new(0);
+        // Rough enough for uniform samples to estimate the integrals.
+        for roughness in [0.5, 0.75, 1.0] {
+            let ggx = Ggx::from_roughness(roughness);
+            // The facets cover the surface once projected on it.
+            let projected = integrate(|h| ggx.d(h) * h.z, &mut rng);
+            assert_float_absolute_eq!(projected, 1.0, 0.03);
+            // And the facets seen from a direction, once projected on it.
+            for degrees in [0.0, 45.0, 80.0] {
+                let wo = direction(degrees);
+                let seen =
+                    integrate(|h| ggx.g1(&wo) * ggx.d(h) * h.dot(&wo).max(0.0) / wo.z, &mut rng);
+                assert_float_absolute_eq!(seen, 1.0, 0.03);
+            }
+        }
+        assert!(Ggx::from_roughness(0.5).g1(&direction(0.0)) == 1.0);
+        assert!(Ggx::from_roughness(0.5).g1(&direction(85.0)) < 0.5);
+    }
+
+    #[test]
+    fn test_visible_normals_sampling() {
+        // The average height of the sampled normals is that of their density.
+        let mut rng = Sampler::new(1);
+        let ggx = Ggx::from_roughness(0.7);
+        for degrees in [0.0, 30.0, 70.0] {
+            let wo = direction(degrees);
+            let visible = |h: &Vec3| ggx.g1(&wo) * ggx.d(h) * h.dot(&wo).max(0.0) / wo.z;
+            let expected = integrate(|h| h.z * visible(h), &mut rng);
+            let samples =
+                (0..SAMPLES).map(|_| ggx.sample_visible_normal(&wo, rng.gen(), rng.gen()));
+            let samples: Vec<Vec3> = samples.collect();
+            assert!(samples.iter().all(|h| h.z >= 0.0 && h.dot(&wo) >= 0.0));
+            let mean = samples.iter().map(|h| h.z).sum::<f32>() / SAMPLES as f32;
+            assert_float_absolute_eq!(mean, expected, 0.01);
+        }
+    }
+
+    #[test]
+    fn test_scattering_follows_the_brdf() {
+        // The average attenuation of the scattered rays is the share of the light reflected,
+        // at most all of it.
+        let mut rng = Sampler::new(2);
+        let gold = Microfacet::new(Color::BLACK, Color::new(1.0, 0.78, 0.34), 0.4);
+        let plastic = Microfacet::new(Color::new(0.8, 0.1, 0.1), Color::new(0.04, 0.04, 0.04), 0.5);
+        let white = Microfacet::new(Color::WHITE, Color::new(0.04, 0.04, 0.04), 0.8);
+        for material in [gold, plastic, white] {
+            for degrees in [0.0, 40.0, 75.0] {
+                let wo = direction(degrees);
+                let r_in = Ray::new(Point::ZERO + wo, -wo);
+                let mut rec = HitRecord::new();
+                rec.set_face_normal(&r_in, &Vec3::UNIT_Z);
+                let mut albedo = Color::BLACK;
+                for _ in 0..SAMPLES {
+                    if let Some(scattered) = material.scatter(&r_in, &rec, &mut rng) {
+                        assert!(scattered.scattered.dir.z > 0.0);
+                        albedo += scattered.attenuation / SAMPLES as f32;
+                    }
+                }
+                let expected = integrate(|wi| material.brdf(&wo, wi).y * wi.z, &mut rng);
+                assert_float_absolute_eq!(albedo.y, expected, 0.02);
+                assert!(albedo.x <= 1.0 && albedo.y <= 1.0 && albedo.z <= 1.0, "{albedo:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_smooth_metal_is_a_mirror() {
+        let mut rng = Sampler::new(3);
+        let mirror = Microfacet::new(Color::BLACK, Color::WHITE, 0.0);
+        let wo = direction(30.0);
+        let r_in = Ray::new(Point::ZERO + wo, -wo);
+        let mut rec = HitRecord::new();
+        rec.set_face_normal(&r_in, &Vec3::UNIT_Z);
+        // Up to the long tail of the distribution, even at its smallest width.
+        let reflected = Vec3::new(-wo.x, 0.0, wo.z);
+        let mut far = 0;
+        for _ in 0..1000 {
+            let scattered = mirror.scatter(&r_in, &rec, &mut rng).unwrap();
+            far += usize::from((scattered.scattered.dir - reflected).len() > 0.05);
+            assert_float_absolute_eq!(scattered.attenuation.y, 1.0, 1e-3);
+        }
+        assert!(far < 10, "{far}");
+    }
+}
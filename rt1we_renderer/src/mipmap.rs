@@ -0,0 +1,178 @@
+//! Mip chains: textures prefiltered at every power of two of their size, so that they can be
+//! sampled at the scale they are seen without aliasing.
+//!
+//! Sampling a texture pixel by pixel makes it shimmer where many texels fall in one image
+//! pixel, as on a textured ground seen at a grazing angle. Sampling the level whose texels are
+//! the size of the pixel footprint, blended with the next one, averages them instead.
+use crate::geometry::Color;
+use crate::image::ImageRGBF32;
+
+/// A texture and its downsampled copies, each half the size of the previous one.
+#[derive(Debug, Clone)]
+pub struct MipChain {
+    /// From the full size texture down to a single texel.
+    levels: Vec<ImageRGBF32>,
+}
+
+/// Half size copy of an image, averaging 2x2 blocks of texels.
+fn downsample(im: &ImageRGBF32) -> ImageRGBF32 {
+    let (width, height) = (im.width.div_ceil(2), im.height.div_ceil(2));
+    let mut out = ImageRGBF32::new(width, height);
+    for j in 0..height {
+        for i in 0..width {
+            let mut sum = Color::BLACK;
+            let mut n = 0.0;
+            // Odd sizes leave single texels on the last row and column.
+            for y in 2 * j..(2 * j + 2).min(im.height) {
+                for x in 2 * i..(2 * i + 2).min(im.width) {
+                    let (r, g, b) = im.at(x, y);
+                    sum += Color::new(r, g, b);
+                    n += 1.0;
+                }
+            }
+            let c = sum / n;
+            out.put(i, j, c.x, c.y, c.z);
+        }
+    }
+    out
+}
+
+/// Bilinear sample of an image, repeated in both directions.
+fn bilinear(im: &ImageRGBF32, u: f32, v: f32) -> Color {
+    // Texel centers sit at half integer coordinates.
+    let x = u.rem_euclid(1.0) * im.width as f32 - 0.5;
+    let y = v.rem_euclid(1.0) * im.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let texel = |i: f32, j: f32| {
+        let i = (i as isize).rem_euclid(im.width as isize) as usize;
+        let j = (j as isize).rem_euclid(im.height as isize) as usize;
+        let (r, g, b) = im.at(i, j);
+        Color::new(r, g, b)
+    };
+    let bottom = (1.0 - tx) * texel(x0, y0) + tx * texel(x0 + 1.0, y0);
+    let top = (1.0 - tx) * texel(x0, y0 + 1.0) + tx * texel(x0 + 1.0, y0 + 1.0);
+    (1.0 - ty) * bottom + ty * top
+}
+
+impl MipChain {
+    /// Build the chain of a texture, down to a single texel.
+    pub fn new(texture: ImageRGBF32) -> Self {
+        let mut levels = vec![texture];
+        while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+            levels.push(downsample(last));
+        }
+        MipChain { levels }
+    }
+
+    pub fn levels(&self) -> &[ImageRGBF32] {
+        &self.levels
+    }
+
+    /// Memory taken by the texels of all the levels, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.levels.iter().map(|l| l.pixels.len() * std::mem::size_of::<f32>()).sum()
+    }
+
+    /// Level of detail matching a footprint: 0 for the full size texture, 1 for the half
+    /// size one, fractional in between.
+    ///
+    /// # Arguments
+    /// - `footprint` - Size of the area seen by an image pixel, in texture coordinates.
+    pub fn level_of_detail(&self, footprint: f32) -> f32 {
+        let size = self.levels[0].width.max(self.levels[0].height) as f32;
+        let lod = (footprint * size).log2();
+        if lod.is_nan() {
+            return 0.0;
+        }
+        lod.clamp(0.0, (self.levels.len() - 1) as f32)
+    }
+
+    /// Trilinear sample of the texture: bilinear samples of the two levels around the level
+    /// of detail, blended.
+    ///
+    /// # Arguments
+    /// - `u`, `v` - Texture coordinates, `(0, 0)` at the first texel, repeated outside of
+    ///   `[0;1]`.
+    /// - `footprint` - Size of the area seen by an image pixel, in texture coordinates. See
+    ///   [`footprint`] to estimate it.
+    pub fn sample(&self, u: f32, v: f32, footprint: f32) -> Color {
+        let lod = self.level_of_detail(footprint);
+        let fine = lod.floor() as usize;
+        let t = lod - fine as f32;
+        let c = bilinear(&self.levels[fine], u, v);
+        match self.levels.get(fine + 1) {
+            Some(coarse) if t > 0.0 => (1.0 - t) * c + t * bilinear(coarse, u, v),
+            _ => c,
+        }
+    }
+}
+
+/// Estimate the footprint of an image pixel on a texture, from the distance of the hit.
+///
+/// The estimate ignores the angle between the ray and the surface, it is exact for surfaces
+/// facing the camera and too small at grazing angles.
+///
+/// # Arguments
+/// - `distance` - Distance travelled by the ray from the camera.
+/// - `pixel_angle` - Angle covered by an image pixel, in radians.
+/// - `texture_size` - Size covered by the whole texture in the scene.
+pub fn footprint(distance: f32, pixel_angle: f32, texture_size: f32) -> f32 {
+    distance * pixel_angle / texture_size
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::image::ImageRGBF32;
+    use crate::mipmap::{footprint, MipChain};
+
+    fn checkerboard(size: usize) -> ImageRGBF32 {
+        let mut im = ImageRGBF32::new(size, size);
+        for j in 0..size {
+            for i in 0..size {
+                let v = ((i + j) % 2) as f32;
+                im.put(i, j, v, v, v);
+            }
+        }
+        im
+    }
+
+    #[test]
+    fn test_levels_halve_down_to_one_texel() {
+        let chain = MipChain::new(ImageRGBF32::new(5, 3));
+        let sizes: Vec<_> = chain.levels().iter().map(|l| (l.width, l.height)).collect();
+        assert_eq!(sizes, vec![(5, 3), (3, 2), (2, 1), (1, 1)]);
+        assert_eq!(chain.memory_size(), (15 + 6 + 2 + 1) * 3 * 4);
+    }
+
+    #[test]
+    fn test_levels_keep_the_average() {
+        let chain = MipChain::new(checkerboard(8));
+        assert_eq!(chain.levels().len(), 4);
+        for level in &chain.levels()[1..] {
+            assert!(level.pixels.iter().all(|&v| (v - 0.5).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_sample_filters_by_footprint() {
+        let chain = MipChain::new(checkerboard(8));
+
+        // At the center of a texel, with a footprint smaller than a texel.
+        let c = chain.sample(1.5 / 8.0, 0.5 / 8.0, 0.01);
+        assert_f32_near!(c.x, 1.0);
+        // A footprint of several texels averages them.
+        let c = chain.sample(1.5 / 8.0, 0.5 / 8.0, 0.5);
+        assert_f32_near!(c.x, 0.5);
+        // Half way between the first two levels.
+        assert_f32_near!(chain.level_of_detail(2f32.sqrt() / 8.0), 0.5);
+        let c = chain.sample(1.5 / 8.0, 0.5 / 8.0, 2f32.sqrt() / 8.0);
+        assert_f32_near!(c.x, 0.75);
+    }
+
+    #[test]
+    fn test_footprint_grows_with_distance() {
+        assert_f32_near!(footprint(10.0, 0.001, 2.0), 0.005);
+        assert_eq!(MipChain::new(checkerboard(4)).level_of_detail(0.0), 0.0);
+    }
+}
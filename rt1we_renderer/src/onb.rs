@@ -0,0 +1,55 @@
+//! Orthonormal bases, to express directions relative to a surface normal.
+use crate::geometry::{dot, Vec3};
+
+/// Three unit vectors orthogonal to each other, `w` usually along a normal.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// A basis whose `w` axis goes along `n`, which does not need to be a unit vector.
+    pub fn from_w(n: &Vec3) -> Self {
+        let w = n.normed();
+        let other = if w.x.abs() > 0.9 { Vec3::UNIT_Y } else { Vec3::UNIT_X };
+        let v = w.cross(&other).normed();
+        let u = v.cross(&w);
+        Onb { u, v, w }
+    }
+
+    /// The vector with coordinates `a` in the basis, in world coordinates.
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+
+    /// The coordinates in the basis of `a`, in world coordinates, the inverse of
+    /// [`Onb::local`].
+    pub fn coordinates(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(dot(a, &self.u), dot(a, &self.v), dot(a, &self.w))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{dot, Vec3};
+    use crate::onb::Onb;
+
+    #[test]
+    fn test_onb() {
+        for n in [Vec3::UNIT_X, Vec3::new(0.0, -3.0, 0.0), Vec3::new(1.0, 2.0, -2.0)] {
+            let onb = Onb::from_w(&n);
+            assert_eq!(onb.w, n.normed());
+            for (a, b) in [(onb.u, onb.v), (onb.v, onb.w), (onb.w, onb.u)] {
+                assert_float_absolute_eq!(dot(&a, &b), 0.0, 1e-6);
+                assert_float_absolute_eq!(a.len(), 1.0, 1e-6);
+            }
+            // Right-handed.
+            assert_float_absolute_eq!((onb.u.cross(&onb.v) - onb.w).len(), 0.0, 1e-6);
+            assert_eq!(onb.local(&Vec3::UNIT_Z), onb.w);
+            let a = Vec3::new(0.5, -1.0, 2.0);
+            assert_float_absolute_eq!((onb.local(&onb.coordinates(&a)) - a).len(), 0.0, 1e-5);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Output file naming: filename templates, directory creation and overwrite policies.
+//!
+//! # Template placeholders
+//! - `{frame}` - Frame index, padded to 5 digits.
+//! - `{date}` - Local date when the render started, as `YYYYMMDD`.
+//! - `{time}` - Local time when the render started, as `HHMMSS`.
+//! - `{seed}` - Random seed of the render, `random` when unseeded.
+use crate::error::RtError;
+use chrono::{DateTime, Local};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What to do when an output file already exists.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+    /// Refuse to write the file.
+    Error,
+    /// Replace the existing file.
+    Overwrite,
+    /// Append `-1`, `-2`, ... to the file stem until the name is free.
+    #[default]
+    Increment,
+}
+
+impl fmt::Display for OverwritePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OverwritePolicy::Error => "error",
+            OverwritePolicy::Overwrite => "overwrite",
+            OverwritePolicy::Increment => "increment",
+        })
+    }
+}
+
+impl FromStr for OverwritePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(OverwritePolicy::Error),
+            "overwrite" => Ok(OverwritePolicy::Overwrite),
+            "increment" => Ok(OverwritePolicy::Increment),
+            _ => Err(format!(
+                "unknown overwrite policy '{s}', expected error, overwrite or increment"
+            )),
+        }
+    }
+}
+
+/// Values substituted in filename templates.
+#[derive(Debug, Copy, Clone)]
+pub struct TemplateContext {
+    pub frame: usize,
+    pub started: DateTime<Local>,
+    pub seed: Option<u64>,
+}
+
+impl TemplateContext {
+    /// Context for a render starting now.
+    pub fn now(frame: usize, seed: Option<u64>) -> Self {
+        TemplateContext { frame, started: Local::now(), seed }
+    }
+}
+
+/// Replace the placeholders of a filename template.
+///
+/// # Arguments
+/// - `template` - The template, see the module documentation for the placeholders.
+/// - `ctx` - The values to substitute.
+pub fn expand_template(template: &str, ctx: &TemplateContext) -> Result<String, RtError> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| RtError::Output(format!("unclosed placeholder in '{template}'")))?;
+        let value = match &rest[start + 1..start + end] {
+            "frame" => format!("{:0>5}", ctx.frame),
+            "date" => ctx.started.format("%Y%m%d").to_string(),
+            "time" => ctx.started.format("%H%M%S").to_string(),
+            "seed" => ctx.seed.map_or("random".to_string(), |s| s.to_string()),
+            other => {
+                let msg = format!("unknown placeholder '{{{other}}}' in '{template}'");
+                return Err(RtError::Output(msg));
+            }
+        };
+        out.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Prepare a path for writing: create its directory and apply the overwrite policy.
+///
+/// # Returns
+/// The path to write to, which differs from `path` when the policy picked a new name.
+pub fn prepare_output(path: &str, policy: OverwritePolicy) -> Result<PathBuf, RtError> {
+    let path = Path::new(path);
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| RtError::io(dir.to_string_lossy(), e))?;
+    }
+    if !path.exists() {
+        return Ok(path.to_path_buf());
+    }
+
+    match policy {
+        OverwritePolicy::Overwrite => Ok(path.to_path_buf()),
+        OverwritePolicy::Error => {
+            Err(RtError::Output(format!("{} already exists", path.display())))
+        }
+        OverwritePolicy::Increment => {
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            let ext = path.extension().and_then(|s| s.to_str());
+            (1..)
+                .map(|k| {
+                    let name = match ext {
+                        Some(ext) => format!("{stem}-{k}.{ext}"),
+                        None => format!("{stem}-{k}"),
+                    };
+                    path.with_file_name(name)
+                })
+                .find(|p| !p.exists())
+                .ok_or_else(|| RtError::Output(format!("no free name for {}", path.display())))
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::output::{expand_template, prepare_output, OverwritePolicy, TemplateContext};
+    use chrono::{Local, TimeZone};
+    use std::fs;
+
+    fn context() -> TemplateContext {
+        let started = Local.with_ymd_and_hms(2024, 3, 9, 14, 5, 7).unwrap();
+        TemplateContext { frame: 42, started, seed: Some(7) }
+    }
+
+    #[test]
+    fn test_expand_template() {
+        let ctx = context();
+        assert_eq!(
+            expand_template("out/{date}_{time}/img_{frame}_s{seed}.png", &ctx).unwrap(),
+            "out/20240309_140507/img_00042_s7.png"
+        );
+        assert_eq!(expand_template("plain.ppm", &ctx).unwrap(), "plain.ppm");
+
+        let unseeded = TemplateContext { seed: None, ..ctx };
+        assert_eq!(expand_template("{seed}", &unseeded).unwrap(), "random");
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(expand_template("img_{frame", &context()).is_err());
+        assert!(expand_template("img_{user}.ppm", &context()).is_err());
+    }
+
+    #[test]
+    fn test_prepare_output_creates_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a/b/im.ppm");
+
+        let prepared = prepare_output(path.to_str().unwrap(), OverwritePolicy::Error).unwrap();
+        assert_eq!(prepared, path);
+        assert!(dir.path().join("a/b").is_dir());
+    }
+
+    #[test]
+    fn test_overwrite_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("im.ppm");
+        fs::write(&path, "").unwrap();
+        fs::write(dir.path().join("im-1.ppm"), "").unwrap();
+        let p = path.to_str().unwrap();
+
+        assert!(prepare_output(p, OverwritePolicy::Error).is_err());
+        assert_eq!(prepare_output(p, OverwritePolicy::Overwrite).unwrap(), path);
+        assert_eq!(
+            prepare_output(p, OverwritePolicy::Increment).unwrap(),
+            dir.path().join("im-2.ppm")
+        );
+    }
+
+    #[test]
+    fn test_policy_names() {
+        for policy in
+            [OverwritePolicy::Error, OverwritePolicy::Overwrite, OverwritePolicy::Increment]
+        {
+            assert_eq!(policy.to_string().parse::<OverwritePolicy>(), Ok(policy));
+        }
+        assert!("ask".parse::<OverwritePolicy>().is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Probability density functions over directions, for importance sampling.
+//!
+//! A bounce sends its ray where the light most likely comes from, and divides what it brings
+//! back by the density of that direction, which keeps the estimate unbiased. Diffuse surfaces
+//! sample half of their rays towards the lights of the scene, so that small lights do not
+//! take thousands of samples to show.
+use crate::geometry::{Point, Vec3};
+use crate::onb::Onb;
+use crate::render::Hittable;
+use crate::sampler::Sampler;
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// A distribution of directions.
+pub(crate) trait Pdf {
+    /// Density of `direction`, per unit of solid angle.
+    fn value(&self, direction: &Vec3) -> f32;
+
+    /// A random direction, following the distribution.
+    fn generate(&self, rng: &mut Sampler) -> Vec3;
+}
+
+/// Random unit vector around the Z axis, with a density proportional to the cosine of its
+/// angle to the axis.
+pub(crate) fn random_cosine_direction(rng: &mut Sampler) -> Vec3 {
+    let (r1, r2) = (rng.gen::<f32>(), rng.gen::<f32>());
+    let phi = 2.0 * PI * r1;
+    let (x, y) = (phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt());
+    Vec3::new(x, y, (1.0 - r2).sqrt())
+}
+
+/// Directions around a normal, denser near the normal as the light scattered by a lambertian
+/// surface.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        CosinePdf { uvw: Onb::from_w(normal) }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let cosine = direction.normed().dot(&self.uvw.w);
+        (cosine / PI).max(0.0)
+    }
+
+    fn generate(&self, rng: &mut Sampler) -> Vec3 {
+        self.uvw.local(&random_cosine_direction(rng))
+    }
+}
+
+/// Directions from a point towards an object, as a light.
+pub(crate) struct HittablePdf<'a, H: Hittable> {
+    object: &'a H,
+    origin: Point,
+}
+
+impl<'a, H: Hittable> HittablePdf<'a, H> {
+    pub(crate) fn new(object: &'a H, origin: Point) -> Self {
+        HittablePdf { object, origin }
+    }
+}
+
+impl<H: Hittable> Pdf for HittablePdf<'_, H> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        self.object.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self, rng: &mut Sampler) -> Vec3 {
+        self.object.random(&self.origin, rng)
+    }
+}
+
+/// Even mix of distributions.
+pub(crate) struct MixturePdf<'a> {
+    pdfs: Vec<&'a dyn Pdf>,
+}
+
+impl<'a> MixturePdf<'a> {
+    pub(crate) fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
+        MixturePdf { pdfs: vec![p0, p1] }
+    }
+
+    /// Even mix of `pdfs`, `None` when there are none.
+    pub(crate) fn even(pdfs: Vec<&'a dyn Pdf>) -> Option<Self> {
+        (!pdfs.is_empty()).then_some(MixturePdf { pdfs })
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let sum: f32 = self.pdfs.iter().map(|pdf| pdf.value(direction)).sum();
+        sum / self.pdfs.len() as f32
+    }
+
+    fn generate(&self, rng: &mut Sampler) -> Vec3 {
+        let index = rng.gen_range(0..self.pdfs.len());
+        self.pdfs[index].generate(rng)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{random_unit_vector, Point, Vec3};
+    use crate::pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf};
+    use crate::rect::XzRect;
+    use crate::registry::MaterialId;
+    use crate::render::{HittableList, Sphere};
+    use crate::sampler::Sampler;
+    use std::f32::consts::PI;
+
+    /// Integral of the density over all the directions, estimated with uniform samples.
+    fn integral(pdf: &dyn Pdf) -> f32 {
+        let (count, mut rng) = (20_000, Sampler::new(1));
+        let sum: f32 = (0..count).map(|_| pdf.value(&random_unit_vector(&mut rng))).sum();
+        4.0 * PI * sum / count as f32
+    }
+
+    #[test]
+    fn test_cosine_pdf() {
+        let normal = Vec3::new(0.0, 2.0, 0.0);
+        let (pdf, mut rng) = (CosinePdf::new(&normal), Sampler::new(0));
+        for _ in 0..100 {
+            let d = pdf.generate(&mut rng);
+            assert_float_absolute_eq!(d.len(), 1.0, 1e-5);
+            assert!(d.y >= 0.0);
+            assert_float_absolute_eq!(pdf.value(&d), d.y / PI, 1e-5);
+        }
+        assert_eq!(pdf.value(&-Vec3::UNIT_Y), 0.0);
+        assert_float_absolute_eq!(integral(&pdf), 1.0, 0.05);
+    }
+
+    #[test]
+    fn test_hittable_pdf() {
+        let mut lights = HittableList::new();
+        lights.add(XzRect::new(-2.0, 2.0, -2.0, 2.0, 1.0, MaterialId(0)));
+        lights.add(Sphere::new(Point::new(2.0, 0.0, 0.0), 1.5, MaterialId(0)));
+        let mut rng = Sampler::new(0);
+        for light in lights.objects() {
+            let pdf = HittablePdf::new(light, Point::ZERO);
+            assert_float_absolute_eq!(integral(&pdf), 1.0, 0.1);
+            // The generated directions all reach the light.
+            assert!((0..100).all(|_| pdf.value(&pdf.generate(&mut rng)) > 0.0));
+        }
+        let pdf = HittablePdf::new(&lights, Point::ZERO);
+        assert_float_absolute_eq!(integral(&pdf), 1.0, 0.1);
+        assert_eq!(pdf.value(&-Vec3::UNIT_X), 0.0);
+
+        // From inside a sphere, every direction reaches it.
+        let sphere = Sphere::new(Point::ZERO, 2.0, MaterialId(0));
+        let pdf = HittablePdf::new(&sphere, Point::new(0.5, 0.0, 0.0));
+        assert_float_absolute_eq!(pdf.value(&pdf.generate(&mut rng)), 1.0 / (4.0 * PI), 1e-6);
+    }
+
+    #[test]
+    fn test_mixture_pdf() {
+        let light = XzRect::new(-2.0, 2.0, -2.0, 2.0, 1.0, MaterialId(0));
+        let (to_light, cosine) =
+            (HittablePdf::new(&light, Point::ZERO), CosinePdf::new(&Vec3::UNIT_Y));
+        let pdf = MixturePdf::new(&to_light, &cosine);
+        let d = Vec3::UNIT_Y;
+        assert_eq!(pdf.value(&d), 0.5 * (to_light.value(&d) + cosine.value(&d)));
+        assert_float_absolute_eq!(integral(&pdf), 1.0, 0.05);
+        let mut rng = Sampler::new(0);
+        let towards_light =
+            (0..1000).filter(|_| to_light.value(&pdf.generate(&mut rng)) > 0.0).count();
+        assert!(towards_light > 500, "{towards_light}");
+    }
+}
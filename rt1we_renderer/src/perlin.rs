@@ -0,0 +1,143 @@
+//! Perlin noise: smooth pseudo-random values in space, for procedural textures.
+//!
+//! Random unit vectors sit on the corners of a lattice, hashed from the corner coordinates
+//! with permutation tables. The noise at a point interpolates their dot products with the
+//! offsets to the corners, which keeps the lattice from showing.
+use crate::geometry::{dot, Point, Vec3};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// Number of lattice vectors, and size of the permutation tables.
+const POINT_COUNT: usize = 256;
+
+/// Perlin noise generator.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    vectors: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    /// # Arguments
+    /// - `seed` - Seed of the lattice: the same seed gives the same noise.
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let vectors = (0..POINT_COUNT)
+            .map(|_| {
+                let v = Vec3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                );
+                if v.near_zero() {
+                    Vec3::UNIT_X
+                } else {
+                    v.normed()
+                }
+            })
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        let (perm_x, perm_y, perm_z) = (permutation(), permutation(), permutation());
+        Perlin { vectors, perm_x, perm_y, perm_z }
+    }
+
+    /// Noise at `p`, in `[-1;1]`, smooth and zero on the lattice corners.
+    pub fn noise(&self, p: &Point) -> f32 {
+        let (u, v, w) = (p.x - p.x.floor(), p.y - p.y.floor(), p.z - p.z.floor());
+        let (i, j, k) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+        let hash = |n: i64, perm: &[usize]| perm[(n & (POINT_COUNT as i64 - 1)) as usize];
+
+        let mut corners = [[[Vec3::ZERO; 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = hash(i + di as i64, &self.perm_x)
+                        ^ hash(j + dj as i64, &self.perm_y)
+                        ^ hash(k + dk as i64, &self.perm_z);
+                    *corner = self.vectors[index];
+                }
+            }
+        }
+        trilinear(&corners, u, v, w)
+    }
+
+    /// Sum of `depth` octaves of noise, each at twice the frequency and half the weight of
+    /// the previous one, in absolute value.
+    ///
+    /// Turbulence looks like the veins of marble once fed to a sine.
+    pub fn turbulence(&self, p: &Point, depth: usize) -> f32 {
+        let mut sum = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            sum += weight * self.noise(&p);
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+        sum.abs()
+    }
+}
+
+/// Trilinear interpolation of the gradients on the corners of a unit cube.
+///
+/// # Arguments
+/// - `corners` - Gradients, indexed by the x, y and z offsets of the corner.
+/// - `u`, `v`, `w` - Position in the cube, in `[0;1]`.
+fn trilinear(corners: &[[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
+    // Hermite smoothing hides the cube faces.
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+    let mut sum = 0.0;
+    for (i, plane) in corners.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, corner) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f32, j as f32, k as f32);
+                let offset = Vec3::new(u - fi, v - fj, w - fk);
+                sum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                    * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                    * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                    * dot(corner, &offset);
+            }
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::Point;
+    use crate::perlin::Perlin;
+
+    #[test]
+    fn test_noise() {
+        let perlin = Perlin::new(1);
+        let p = Point::new(1.3, -2.7, 0.4);
+        assert_eq!(perlin.noise(&p), Perlin::new(1).noise(&p));
+        // Zero on the lattice, smooth in between.
+        assert_eq!(perlin.noise(&Point::new(3.0, -1.0, 7.0)), 0.0);
+        let near = Point::new(1.3001, -2.7, 0.4);
+        assert!((perlin.noise(&p) - perlin.noise(&near)).abs() < 1e-3);
+
+        let values: Vec<f32> = (0..1000)
+            .map(|i| perlin.noise(&Point::new(i as f32 * 0.37, i as f32 * 0.11, 0.5)))
+            .collect();
+        assert!(values.iter().all(|v| (-1.0..=1.0).contains(v)));
+        assert!(values.iter().any(|&v| v > 0.1) && values.iter().any(|&v| v < -0.1));
+    }
+
+    #[test]
+    fn test_turbulence() {
+        let perlin = Perlin::new(2);
+        let p = Point::new(0.6, 0.2, -1.9);
+        assert_eq!(perlin.turbulence(&p, 1), perlin.noise(&p).abs());
+        assert!(perlin.turbulence(&p, 7) >= 0.0);
+        assert_ne!(perlin.turbulence(&p, 7), perlin.turbulence(&p, 1));
+    }
+}
@@ -0,0 +1,163 @@
+//! Real roots of polynomials, as the quartic of a ray hitting a torus.
+//!
+//! Closed form solutions of cubics and quartics lose most of their precision when roots are
+//! close or coefficients differ by orders of magnitude. Instead, the roots of the derivative
+//! split a range into parts where the polynomial is monotonic, each holding at most one root,
+//! found by Newton steps kept inside the part by bisection.
+
+/// Coefficients of a polynomial, of the constant term first.
+type Coefficients = [f64];
+
+/// Value of the polynomial of `coeffs` at `x`.
+pub fn eval(coeffs: &Coefficients, x: f64) -> f64 {
+    coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+/// Coefficients of the derivative of the polynomial of `coeffs`.
+fn derivative(coeffs: &Coefficients) -> Vec<f64> {
+    coeffs.iter().enumerate().skip(1).map(|(k, c)| k as f64 * c).collect()
+}
+
+/// Real roots of the polynomial of `coeffs` in `[lo;hi]`, a finite range.
+///
+/// # Arguments
+/// - `coeffs` - Coefficients of the polynomial, of the constant term first.
+///
+/// # Returns
+/// The roots in increasing order, multiple roots once.
+pub fn real_roots(coeffs: &Coefficients, lo: f64, hi: f64) -> Vec<f64> {
+    // Leading zeros lower the degree.
+    let degree = coeffs.iter().rposition(|c| *c != 0.0).unwrap_or(0);
+    let coeffs = &coeffs[..=degree];
+    if degree == 0 || lo > hi {
+        return vec![];
+    }
+    if degree == 1 {
+        let x = -coeffs[0] / coeffs[1];
+        return if (lo..=hi).contains(&x) { vec![x] } else { vec![] };
+    }
+
+    let mut bounds = vec![lo];
+    bounds.extend(real_roots(&derivative(coeffs), lo, hi));
+    bounds.push(hi);
+    let mut roots: Vec<f64> = vec![];
+    let mut push = |x: f64| {
+        if roots.last() != Some(&x) {
+            roots.push(x);
+        }
+    };
+    for pair in bounds.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let (fa, fb) = (eval(coeffs, a), eval(coeffs, b));
+        // Multiple roots, where the polynomial does not change sign, are among the bounds.
+        if is_zero(coeffs, a, fa) {
+            push(a);
+        } else if is_zero(coeffs, b, fb) {
+            push(b);
+        } else if fa.signum() != fb.signum() {
+            push(monotonic_root(coeffs, a, b, fa));
+        }
+    }
+    roots
+}
+
+/// Whether `fx`, the value of the polynomial of `coeffs` at `x`, is zero but for rounding
+/// errors.
+fn is_zero(coeffs: &Coefficients, x: f64, fx: f64) -> bool {
+    let magnitude = coeffs.iter().rev().fold(0.0, |acc, c| acc * x.abs() + c.abs());
+    fx.abs() <= 8.0 * f64::EPSILON * magnitude
+}
+
+/// Real roots of `c4 x⁴ + c3 x³ + c2 x² + c1 x + c0` in `[lo;hi]`, see [`real_roots`].
+///
+/// # Arguments
+/// - `coeffs` - `[c0, c1, c2, c3, c4]`.
+pub fn solve_quartic(coeffs: [f64; 5], lo: f64, hi: f64) -> Vec<f64> {
+    real_roots(&coeffs, lo, hi)
+}
+
+/// The root between `a` and `b` of a polynomial monotonic there, changing sign from `fa` at `a`.
+fn monotonic_root(coeffs: &Coefficients, mut a: f64, mut b: f64, fa: f64) -> f64 {
+    let slope = derivative(coeffs);
+    let mut x = 0.5 * (a + b);
+    for _ in 0..100 {
+        let fx = eval(coeffs, x);
+        if fx == 0.0 {
+            return x;
+        }
+        // Keep the root between a and b.
+        if fx.signum() == fa.signum() {
+            a = x;
+        } else {
+            b = x;
+        }
+        let newton = x - fx / eval(&slope, x);
+        let next = if newton > a && newton < b { newton } else { 0.5 * (a + b) };
+        if (next - x).abs() <= 1e-14 * x.abs().max(1.0) {
+            return next;
+        }
+        x = next;
+    }
+    x
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::poly::{eval, real_roots, solve_quartic};
+
+    /// Coefficients of the polynomial of `roots`, of the constant term first.
+    fn with_roots(roots: &[f64]) -> Vec<f64> {
+        roots.iter().fold(vec![1.0], |coeffs, root| {
+            // Multiply by (x - root).
+            let mut next = vec![0.0; coeffs.len() + 1];
+            for (k, c) in coeffs.iter().enumerate() {
+                next[k + 1] += c;
+                next[k] -= root * c;
+            }
+            next
+        })
+    }
+
+    fn assert_roots(found: &[f64], expected: &[f64], tolerance: f64) {
+        assert_eq!(found.len(), expected.len(), "{found:?}");
+        for (a, b) in found.iter().zip(expected) {
+            assert!((a - b).abs() <= tolerance, "{found:?} {expected:?}");
+        }
+    }
+
+    #[test]
+    fn test_low_degrees() {
+        assert_eq!(eval(&[1.0, 2.0, 3.0], 2.0), 17.0);
+        assert_roots(&real_roots(&[-2.0, 4.0], -10.0, 10.0), &[0.5], 0.0);
+        assert_roots(&real_roots(&with_roots(&[-1.0, 3.0]), -10.0, 10.0), &[-1.0, 3.0], 1e-12);
+        assert!(real_roots(&[1.0, 0.0, 1.0], -10.0, 10.0).is_empty());
+        assert!(real_roots(&[1.0], -10.0, 10.0).is_empty());
+        // A zero leading coefficient lowers the degree.
+        assert_roots(&real_roots(&[-2.0, 4.0, 0.0, 0.0], -10.0, 10.0), &[0.5], 0.0);
+    }
+
+    #[test]
+    fn test_quartic() {
+        let roots = [-2.0, 0.5, 1.0, 7.0];
+        let coeffs: [f64; 5] = with_roots(&roots).try_into().unwrap();
+        assert_roots(&solve_quartic(coeffs, -10.0, 10.0), &roots, 1e-10);
+        // Only those in the range.
+        assert_roots(&solve_quartic(coeffs, 0.0, 5.0), &[0.5, 1.0], 1e-10);
+        // Two real roots, two complex ones.
+        let coeffs = [2.0, 0.0, -3.0, 0.0, 1.0];
+        let sqrt2 = 2f64.sqrt();
+        assert_roots(&solve_quartic(coeffs, -10.0, 10.0), &[-sqrt2, -1.0, 1.0, sqrt2], 1e-10);
+        assert!(solve_quartic([1.0, 0.0, 2.0, 0.0, 1.0], -10.0, 10.0).is_empty());
+    }
+
+    #[test]
+    fn test_close_roots() {
+        // Roots 1e-6 apart, and coefficients of very different magnitudes.
+        let roots = [1.0, 1.000001, 1000.0, 1000.5];
+        let coeffs = with_roots(&roots);
+        assert_roots(&real_roots(&coeffs, -1e4, 1e4), &roots, 1e-9);
+        // An exact double root is found once.
+        let coeffs = with_roots(&[2.0, 2.0, 3.0, 4.0]);
+        assert_roots(&real_roots(&coeffs, -10.0, 10.0), &[2.0, 3.0, 4.0], 1e-9);
+    }
+}
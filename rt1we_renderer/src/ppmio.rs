@@ -1,40 +1,148 @@
 //! Read and Write functions for raw PPM images.
 //!
-//! We only support the legacy format with 'P3' magic number.
+//! We support the plain format with 'P3' magic number, and the binary one with 'P6', a
+//! lot smaller and faster to write.
 //! Details for this format can be read on the [netpbm documentation](https://netpbm.sourceforge.net/doc/ppm.html)
+use crate::error::RtError;
 use crate::image::ImageRGBA;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufWriter, Write};
 use std::str::FromStr;
 
+/// Encoding of the pixels of a PPM file.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum PpmFormat {
+    /// 'P3': decimal values in text.
+    Ascii,
+    /// 'P6': one byte per value.
+    #[default]
+    Binary,
+}
+
+impl PpmFormat {
+    fn magic(self) -> &'static str {
+        match self {
+            PpmFormat::Ascii => "P3",
+            PpmFormat::Binary => "P6",
+        }
+    }
+}
+
 /// Write an image as PPM file.
 ///
 /// # Arguments
 /// - `fpath` - The file path to write to.
 /// - `im` - The image data to write.
+/// - `format` - Encoding of the pixels.
 ///
 /// # Notes
 /// The alpha channel is dropped.
 ///
-pub fn ppmwrite(fpath: &str, im: &ImageRGBA) {
-    let f = File::create(fpath).expect("Unable to create file");
+pub fn ppmwrite(fpath: &str, im: &ImageRGBA, format: PpmFormat) -> Result<(), RtError> {
+    write_pixels(fpath, im, format).map_err(|e| RtError::io(fpath, e))
+}
+
+fn write_pixels(fpath: &str, im: &ImageRGBA, format: PpmFormat) -> std::io::Result<()> {
+    let f = File::create(fpath)?;
     let mut f = BufWriter::new(f);
     let w = im.width;
     let h = im.height;
-    let header = format!("P3\n{w} {h}\n255\n");
+    let header = format!("{}\n{w} {h}\n255\n", format.magic());
 
-    f.write_all(header.as_bytes()).expect("unable to write data");
+    f.write_all(header.as_bytes())?;
     let count = w * h;
     for i in 0..count {
         let r = im.pixels[i * 4];
         let g = im.pixels[i * 4 + 1];
         let b = im.pixels[i * 4 + 2];
 
-        f.write_fmt(format_args!("{r} {g} {b}\n")).expect("unable to write data");
+        match format {
+            PpmFormat::Ascii => f.write_fmt(format_args!("{r} {g} {b}\n")),
+            PpmFormat::Binary => f.write_all(&[r, g, b]),
+        }?;
+    }
+    f.flush()
+}
+
+/// Error of a PPM file which cannot be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PpmError {
+    /// The file does not start with a PPM magic number.
+    BadMagic(String),
+    /// A header field or a value is not a number.
+    InvalidNumber { field: &'static str, token: String },
+    /// The maximum value is out of 1 to 65535.
+    BadMaxval(u32),
+    /// A value is larger than the maximum value.
+    ValueOutOfRange { value: u32, maxval: u32 },
+    /// The file ends before the last pixel.
+    Truncated { expected: usize, found: usize },
+}
+
+impl fmt::Display for PpmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PpmError::BadMagic(magic) => write!(f, "not a PPM file, magic number '{magic}'"),
+            PpmError::InvalidNumber { field, token } => write!(f, "invalid {field} '{token}'"),
+            PpmError::BadMaxval(maxval) => write!(f, "maximum value {maxval} out of 1 to 65535"),
+            PpmError::ValueOutOfRange { value, maxval } => {
+                write!(f, "value {value} larger than the maximum value {maxval}")
+            }
+            PpmError::Truncated { expected, found } => {
+                write!(f, "truncated file, {found} of {expected} values")
+            }
+        }
     }
 }
 
-/// Read a PPM image.
+impl std::error::Error for PpmError {}
+
+/// Whitespace separated tokens of the text parts of a PPM file, skipping `#` comments.
+struct Tokens<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn next_token(&mut self) -> Option<&'a [u8]> {
+        loop {
+            match self.data.get(self.pos)? {
+                b'#' => {
+                    while self.data.get(self.pos).is_some_and(|c| *c != b'\n' && *c != b'\r') {
+                        self.pos += 1;
+                    }
+                }
+                c if c.is_ascii_whitespace() => self.pos += 1,
+                _ => break,
+            }
+        }
+        let start = self.pos;
+        while self.data.get(self.pos).is_some_and(|c| !c.is_ascii_whitespace() && *c != b'#') {
+            self.pos += 1;
+        }
+        Some(&self.data[start..self.pos])
+    }
+
+    /// The next token as a number, `None` at the end of the data.
+    fn next_number(&mut self, field: &'static str) -> Result<Option<u32>, PpmError> {
+        let Some(token) = self.next_token() else {
+            return Ok(None);
+        };
+        let token = String::from_utf8_lossy(token);
+        let number = u32::from_str(&token)
+            .map_err(|_| PpmError::InvalidNumber { field, token: token.into_owned() })?;
+        Ok(Some(number))
+    }
+
+    /// The next header field, which must be there.
+    fn header_field(&mut self, field: &'static str) -> Result<u32, PpmError> {
+        self.next_number(field)?
+            .ok_or_else(|| PpmError::InvalidNumber { field, token: String::new() })
+    }
+}
+
+/// Read a PPM image, in either format.
 ///
 /// # Arguments
 /// - `fpath` - File path of the file to read.
@@ -50,46 +158,75 @@ pub fn ppmwrite(fpath: &str, im: &ImageRGBA) {
 /// r g b
 /// EOF
 /// ```
-pub fn ppmread(fpath: &str) -> ImageRGBA {
-    let f = File::open(fpath).expect("Unable to open file");
-    let mut f = BufReader::new(f);
+/// Fields and values are separated by any whitespace, and `#` starts a comment up to the end
+/// of the line. Binary files start with `P6` instead, and the header is followed by a single
+/// whitespace and the pixels, one byte per value, or two big-endian bytes when `$maxval`
+/// is above 255. Values are scaled from `$maxval` to 255.
+pub fn ppmread(fpath: &str) -> Result<ImageRGBA, RtError> {
+    let data = std::fs::read(fpath).map_err(|e| RtError::io(fpath, e))?;
+    ppmdecode(&data).map_err(|source| RtError::Ppm { path: fpath.to_string(), source })
+}
 
-    let mut magic_bytes = String::new();
-    let _ = f.read_line(&mut magic_bytes);
-    let mut dim = String::new();
-    let _ = f.read_line(&mut dim);
-    let mut maxval = String::new();
-    let _ = f.read_line(&mut maxval);
+/// Decode a PPM image from the content of a file, as [`ppmread`].
+pub fn ppmdecode(data: &[u8]) -> Result<ImageRGBA, PpmError> {
+    let mut tokens = Tokens { data, pos: 0 };
+    let magic = tokens.next_token().unwrap_or_default();
+    let format = match magic {
+        b"P3" => PpmFormat::Ascii,
+        b"P6" => PpmFormat::Binary,
+        _ => return Err(PpmError::BadMagic(String::from_utf8_lossy(magic).into_owned())),
+    };
+    let w = tokens.header_field("width")? as usize;
+    let h = tokens.header_field("height")? as usize;
+    let maxval = tokens.header_field("maximum value")?;
+    if !(1..=65535).contains(&maxval) {
+        return Err(PpmError::BadMaxval(maxval));
+    }
 
-    let w_h: Vec<&str> = dim.split_whitespace().collect();
-    let w = usize::from_str(w_h[0]).unwrap();
-    let h = usize::from_str(w_h[1]).unwrap();
+    // Headers of absurd sizes end up truncated, instead of overflowing.
+    let expected = w.saturating_mul(h).saturating_mul(3);
+    let mut values = Vec::with_capacity(expected.min(data.len()));
+    match format {
+        PpmFormat::Ascii => {
+            while values.len() < expected {
+                match tokens.next_number("value")? {
+                    Some(value) => values.push(value),
+                    None => break,
+                }
+            }
+        }
+        PpmFormat::Binary => {
+            // A single whitespace byte ends the header.
+            let raster = data.get(tokens.pos + 1..).unwrap_or_default();
+            let size = if maxval > 255 { 2 } else { 1 };
+            values.extend(raster.chunks_exact(size).take(expected).map(|bytes| match bytes {
+                [v] => *v as u32,
+                [hi, lo] => u16::from_be_bytes([*hi, *lo]) as u32,
+                _ => unreachable!(),
+            }));
+        }
+    }
+    if values.len() < expected {
+        return Err(PpmError::Truncated { expected, found: values.len() });
+    }
+    if let Some(&value) = values.iter().find(|v| **v > maxval) {
+        return Err(PpmError::ValueOutOfRange { value, maxval });
+    }
 
+    let scale = |v: u32| ((v * 255 + maxval / 2) / maxval) as u8;
     let mut im = ImageRGBA::new(w, h);
-
-    let count = w * h;
-
-    for i in 0..count {
-        let mut px_str = String::new();
-        let _ = f.read_line(&mut px_str);
-        let rgb: Vec<&str> = px_str.split_whitespace().collect();
-        let r = u8::from_str(rgb[0]).unwrap();
-        let g = u8::from_str(rgb[1]).unwrap();
-        let b = u8::from_str(rgb[2]).unwrap();
-
-        im.pixels[i * 4] = r;
-        im.pixels[i * 4 + 1] = g;
-        im.pixels[i * 4 + 2] = b;
-        im.pixels[i * 4 + 3] = 255;
+    for (px, rgb) in im.pixels.chunks_mut(4).zip(values.chunks(3)) {
+        px.copy_from_slice(&[scale(rgb[0]), scale(rgb[1]), scale(rgb[2]), 255]);
     }
-
-    im
+    Ok(im)
 }
 
 #[cfg(test)]
+#[allow(noop_method_call)]
 pub(crate) mod test {
+    use crate::error::RtError;
     use crate::image::ImageRGBA;
-    use crate::ppmio::{ppmread, ppmwrite};
+    use crate::ppmio::{ppmdecode, ppmread, ppmwrite, PpmError, PpmFormat};
     use std::env;
 
     #[test]
@@ -101,12 +238,84 @@ pub(crate) mod test {
         let temp_path = temp_dir.join("rt1wk-rs_im.ppm");
         let fpath = temp_path.as_path().to_str().expect("invalid path").clone();
 
-        ppmwrite(fpath, &im);
+        ppmwrite(fpath, &im, PpmFormat::Ascii).unwrap();
 
-        let im_r = ppmread(fpath);
+        let im_r = ppmread(fpath).unwrap();
         let count = im.height * im.width * 4;
         for i in 0..count {
             assert_eq!(im.pixels[i], im_r.pixels[i]);
         }
     }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let mut im = ImageRGBA::new(5, 3);
+        im.put_u32(2, 2, 0x0F0A0AFF);
+        // Bytes which would be whitespace in a text file.
+        im.put(4, 0, b'\n', b' ', b'\r', 255);
+
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("binary.ppm");
+        let binary = binary.to_str().unwrap();
+        ppmwrite(binary, &im, PpmFormat::Binary).unwrap();
+        let ascii = dir.path().join("ascii.ppm");
+        let ascii = ascii.to_str().unwrap();
+        ppmwrite(ascii, &im, PpmFormat::Ascii).unwrap();
+
+        let data = std::fs::read(binary).unwrap();
+        assert!(data.starts_with(b"P6\n5 3\n255\n"));
+        assert_eq!(data.len(), 11 + 5 * 3 * 3);
+        assert!(data.len() < std::fs::read(ascii).unwrap().len());
+        assert_eq!(ppmread(binary).unwrap().pixels, im.pixels);
+    }
+
+    #[test]
+    fn test_free_layout() {
+        let data = b"P3 # comment\n#another\n2\t1 15\n15 0 7\n1 2 3 # last\n";
+        let im = ppmdecode(data).unwrap();
+        assert_eq!((im.width, im.height), (2, 1));
+        assert_eq!(im.at(0, 0), (255, 0, 119, 255));
+        assert_eq!(im.at(1, 0), (17, 34, 51, 255));
+
+        // Two bytes per value above 255.
+        let mut data = b"P6 1 1 #c\n65535\n".to_vec();
+        data.extend([0xff, 0xff, 0x80, 0x00, 0x00, 0x00]);
+        assert_eq!(ppmdecode(&data).unwrap().at(0, 0), (255, 128, 0, 255));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |data: &[u8]| ppmdecode(data).err().unwrap();
+        assert!(matches!(err(b"P5 1 1 255 0"), PpmError::BadMagic(m) if m == "P5"));
+        assert!(matches!(err(b""), PpmError::BadMagic(_)));
+        assert!(matches!(
+            err(b"P3 2 x 255"),
+            PpmError::InvalidNumber { field: "height", token } if token == "x"
+        ));
+        assert!(matches!(err(b"P3 2 1"), PpmError::InvalidNumber { .. }));
+        assert!(matches!(err(b"P3 1 1 0 0 0 0"), PpmError::BadMaxval(0)));
+        assert!(matches!(err(b"P3 1 1 70000 0 0 0"), PpmError::BadMaxval(70000)));
+        assert!(matches!(
+            err(b"P3 1 1 15 0 16 0"),
+            PpmError::ValueOutOfRange { value: 16, maxval: 15 }
+        ));
+        assert!(matches!(
+            err(b"P3 2 1 255 1 2 3 4"),
+            PpmError::Truncated { expected: 6, found: 4 }
+        ));
+        assert!(matches!(err(b"P6 2 1 255 \x01\x02"), PpmError::Truncated { found: 2, .. }));
+        assert!(matches!(err(b"P6 99999999999 99999999 255 "), PpmError::InvalidNumber { .. }));
+        assert!(matches!(err(b"P6 4000000000 4000000000 255 "), PpmError::Truncated { .. }));
+        assert_eq!(err(b"P3 1 1 255 1 2").to_string(), "truncated file, 2 of 3 values");
+        assert!(matches!(ppmread("missing.ppm"), Err(RtError::Io { .. })));
+        let im = ImageRGBA::new(1, 1);
+        let err = ppmwrite("missing/im.ppm", &im, PpmFormat::Binary).err().unwrap();
+        assert!(err.to_string().starts_with("missing/im.ppm: "), "{err}");
+
+        let dir = tempfile::tempdir().unwrap();
+        let fpath = dir.path().join("bad.ppm");
+        std::fs::write(&fpath, b"P3 1 1 255 1").unwrap();
+        let err = ppmread(fpath.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, RtError::Ppm { source: PpmError::Truncated { .. }, .. }));
+    }
 }
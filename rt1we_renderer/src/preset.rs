@@ -0,0 +1,118 @@
+//! Named quality presets, trading render time for image quality.
+use std::fmt;
+use std::str::FromStr;
+
+/// Built-in quality levels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QualityPreset {
+    /// Fast iteration on the scene: small, noisy, denoised images.
+    Preview,
+    /// Good enough to judge lighting and materials.
+    Medium,
+    /// Full resolution, converged images.
+    Final,
+}
+
+/// Render settings selected by a [`QualityPreset`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QualitySettings {
+    /// Factor applied to the base output resolution.
+    pub resolution_scale: f32,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    /// Whether to run [`crate::denoise::denoise_hdr`] on the rendered radiance.
+    pub denoise: bool,
+}
+
+impl QualityPreset {
+    /// All presets, from the fastest to the slowest.
+    pub const ALL: [QualityPreset; 3] =
+        [QualityPreset::Preview, QualityPreset::Medium, QualityPreset::Final];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QualityPreset::Preview => "preview",
+            QualityPreset::Medium => "medium",
+            QualityPreset::Final => "final",
+        }
+    }
+
+    pub fn settings(&self) -> QualitySettings {
+        match self {
+            QualityPreset::Preview => QualitySettings {
+                resolution_scale: 0.5,
+                samples_per_pixel: 8,
+                max_depth: 8,
+                denoise: true,
+            },
+            QualityPreset::Medium => QualitySettings {
+                resolution_scale: 1.0,
+                samples_per_pixel: 64,
+                max_depth: 25,
+                denoise: true,
+            },
+            QualityPreset::Final => QualitySettings {
+                resolution_scale: 2.0,
+                samples_per_pixel: 500,
+                max_depth: 50,
+                denoise: false,
+            },
+        }
+    }
+}
+
+impl QualitySettings {
+    /// Scale a base resolution, keeping at least one pixel along each axis.
+    pub fn scale_resolution(&self, width: usize, height: usize) -> (usize, usize) {
+        let scale = |v: usize| ((v as f32 * self.resolution_scale).round() as usize).max(1);
+        (scale(width), scale(height))
+    }
+}
+
+impl fmt::Display for QualityPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for QualityPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        QualityPreset::ALL
+            .into_iter()
+            .find(|p| p.name() == s)
+            .ok_or_else(|| format!("unknown preset '{s}', expected preview, medium or final"))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::preset::QualityPreset;
+
+    #[test]
+    fn test_names_roundtrip() {
+        for preset in QualityPreset::ALL {
+            assert_eq!(preset.to_string().parse::<QualityPreset>(), Ok(preset));
+        }
+        assert!("ultra".parse::<QualityPreset>().is_err());
+    }
+
+    #[test]
+    fn test_presets_are_ordered_by_cost() {
+        let cost = |p: QualityPreset| {
+            let s = p.settings();
+            let (w, h) = s.scale_resolution(160, 90);
+            w * h * s.samples_per_pixel * s.max_depth
+        };
+        assert!(cost(QualityPreset::Preview) < cost(QualityPreset::Medium));
+        assert!(cost(QualityPreset::Medium) < cost(QualityPreset::Final));
+    }
+
+    #[test]
+    fn test_scale_resolution() {
+        let settings = QualityPreset::Preview.settings();
+        assert_eq!(settings.scale_resolution(160, 90), (80, 45));
+        assert_eq!(settings.scale_resolution(1, 1), (1, 1));
+    }
+}
@@ -0,0 +1,328 @@
+//! The principled material, after Disney's: a single material whose few parameters blend the
+//! lobes of diffuse, metallic, glossy, varnished and transparent surfaces.
+use crate::geometry::{lerp, refract, Color, Vec3};
+use crate::microfacet::{fresnel, Ggx};
+use crate::onb::Onb;
+use crate::pdf::random_cosine_direction;
+use crate::ray::Ray;
+use crate::render::{HitRecord, Material, MaterialParams, ScatterResult};
+use crate::sampler::Sampler;
+use rand::Rng;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Index of refraction of the transparent part of principled materials.
+pub const PRINCIPLED_IOR: f32 = 1.5;
+/// Roughness of the clear coat, a smooth varnish.
+const CLEARCOAT_ROUGHNESS: f32 = 0.25;
+/// Reflectance of the clear coat at normal incidence, that of a polyurethane varnish.
+const CLEARCOAT_F0: f32 = 0.04;
+
+/// Editable parameters of a principled material, all of them but the color from 0 to 1.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PrincipledParams {
+    /// Color of the diffuse base, of the reflections of metals and of the transparent part.
+    pub base_color: Color,
+    /// From a dielectric, as plastic, to a metal.
+    pub metallic: f32,
+    /// Perceptual roughness of the reflections, see [`Ggx::from_roughness`].
+    pub roughness: f32,
+    /// Reflectance of dielectrics at normal incidence, 0.5 being the 4% of most of them.
+    pub specular: f32,
+    /// Strength of a clear varnish over the surface.
+    pub clearcoat: f32,
+    /// From an opaque dielectric to a clear one, as colored glass.
+    pub transmission: f32,
+}
+
+impl Default for PrincipledParams {
+    /// A grey plastic.
+    fn default() -> Self {
+        PrincipledParams {
+            base_color: Color::new(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            clearcoat: 0.0,
+            transmission: 0.0,
+        }
+    }
+}
+
+/// Probabilities to sample each lobe, seen from a direction.
+struct Lobes {
+    clearcoat: f32,
+    specular: f32,
+    diffuse: f32,
+    transmission: f32,
+}
+
+/// Glossy reflection over a diffuse base, the transparent part going through it, the whole under
+/// a clear coat.
+///
+/// Metals only have the glossy reflection, of their base color. Rays going through the
+/// transparent part are refracted without being blurred, and leave the object as through glass.
+pub(crate) struct Principled {
+    params: PrincipledParams,
+    /// Reflectance of the glossy reflection at normal incidence.
+    f0: Color,
+    specular: Ggx,
+    clearcoat: Ggx,
+    /// Scatters the rays inside transparent objects.
+    glass: Arc<dyn Material>,
+}
+
+impl Principled {
+    pub fn new(params: PrincipledParams) -> Self {
+        let dielectric = Color::WHITE * (0.08 * params.specular);
+        Principled {
+            params,
+            f0: lerp(&dielectric, &params.base_color, params.metallic),
+            specular: Ggx::from_roughness(params.roughness),
+            clearcoat: Ggx::from_roughness(CLEARCOAT_ROUGHNESS),
+            glass: MaterialParams::Dielectric {
+                refraction_index: PRINCIPLED_IOR,
+                dispersion: 0.0,
+                film: None,
+            }
+            .build(),
+        }
+    }
+
+    /// Share of the light reflected by the clear coat, seen at `cosine` from the normal.
+    fn coat_reflectance(&self, cosine: f32) -> f32 {
+        self.params.clearcoat * fresnel(&(Color::WHITE * CLEARCOAT_F0), cosine).x
+    }
+
+    /// Share of the light coming at `cosine` from the normal which goes through the transparent
+    /// part, tinted by it.
+    fn transmittance(&self, cosine: f32) -> Color {
+        let PrincipledParams { base_color, metallic, transmission, .. } = self.params;
+        let through = (1.0 - self.coat_reflectance(cosine)) * (1.0 - metallic) * transmission;
+        through * (Color::WHITE - fresnel(&self.f0, cosine)) * base_color
+    }
+
+    /// Probabilities to sample each lobe, in proportion to the light they reflect seen from `wo`,
+    /// `None` when the material is black.
+    fn lobes(&self, wo: &Vec3) -> Option<Lobes> {
+        let average = |c: Color| (c.x + c.y + c.z) / 3.0;
+        let PrincipledParams { base_color, metallic, transmission, .. } = self.params;
+        let clearcoat = self.coat_reflectance(wo.z);
+        let under = 1.0 - clearcoat;
+        let fresnel = fresnel(&self.f0, wo.z);
+        let specular = under * average(fresnel);
+        let base = under * (1.0 - metallic) * average((Color::WHITE - fresnel) * base_color);
+        let (diffuse, transmission) = ((1.0 - transmission) * base, transmission * base);
+        let total = clearcoat + specular + diffuse + transmission;
+        (total > 0.0).then(|| Lobes {
+            clearcoat: clearcoat / total,
+            specular: specular / total,
+            diffuse: diffuse / total,
+            transmission: transmission / total,
+        })
+    }
+
+    /// Reflected light, from `wi` to `wo`, per unit of incoming light and solid angle.
+    fn brdf(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let PrincipledParams { base_color, metallic, transmission, .. } = self.params;
+        let h = (*wo + *wi).normed();
+        let glossy = |ggx: &Ggx| ggx.d(&h) * ggx.g2(wo, wi) / (4.0 * wo.z * wi.z);
+        let clearcoat = self.coat_reflectance(wi.dot(&h)) * glossy(&self.clearcoat);
+        let specular = fresnel(&self.f0, wi.dot(&h)) * glossy(&self.specular);
+        // The base only gets the light the glossy reflection lets through, both ways.
+        let through =
+            (Color::WHITE - fresnel(&self.f0, wo.z)) * (Color::WHITE - fresnel(&self.f0, wi.z));
+        let diffuse = through * base_color * ((1.0 - metallic) * (1.0 - transmission) / PI);
+        let under = (1.0 - self.coat_reflectance(wo.z)) * (1.0 - self.coat_reflectance(wi.z));
+        Color::WHITE * clearcoat + under * (specular + diffuse)
+    }
+
+    /// Density of the reflected directions sampled by [`Material::scatter`], per unit of solid
+    /// angle.
+    fn pdf(&self, lobes: &Lobes, wo: &Vec3, wi: &Vec3) -> f32 {
+        lobes.clearcoat * self.clearcoat.pdf(wo, wi)
+            + lobes.specular * self.specular.pdf(wo, wi)
+            + lobes.diffuse * wi.z / PI
+    }
+}
+
+impl Material for Principled {
+    fn params(&self) -> MaterialParams {
+        MaterialParams::Principled(self.params)
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Sampler) -> Option<ScatterResult> {
+        if !rec.front_face && self.params.transmission > 0.0 {
+            return self.glass.scatter(r_in, rec, rng);
+        }
+        let uvw = Onb::from_w(&rec.normal);
+        let wo = uvw.coordinates(&-r_in.dir.normed());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let lobes = self.lobes(&wo)?;
+        let u = rng.gen::<f32>();
+        if u < lobes.transmission {
+            let refracted = refract(&r_in.dir.normed(), &rec.normal, 1.0 / PRINCIPLED_IOR)?;
+            return Some(ScatterResult {
+                attenuation: self.transmittance(wo.z) / lobes.transmission,
+                scattered: Ray {
+                    orig: rec.p,
+                    dir: refracted,
+                    time: r_in.time,
+                    wavelength: r_in.wavelength,
+                },
+                pdf: None,
+            });
+        }
+        let wi = if u < lobes.transmission + lobes.clearcoat {
+            let h = self.clearcoat.sample_visible_normal(&wo, rng.gen(), rng.gen());
+            2.0 * wo.dot(&h) * h - wo
+        } else if u < lobes.transmission + lobes.clearcoat + lobes.specular {
+            let h = self.specular.sample_visible_normal(&wo, rng.gen(), rng.gen());
+            2.0 * wo.dot(&h) * h - wo
+        } else {
+            random_cosine_direction(rng)
+        };
+        // Reflected below the surface, the ray is absorbed.
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some(ScatterResult {
+            attenuation: self.brdf(&wo, &wi) * (wi.z / self.pdf(&lobes, &wo, &wi)),
+            scattered: Ray {
+                orig: rec.p,
+                dir: uvw.local(&wi),
+                time: r_in.time,
+                wavelength: r_in.wavelength,
+            },
+            pdf: None,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{random_unit_vector, Color, Point, Vec3};
+    use crate::principled::{Principled, PrincipledParams};
+    use crate::ray::Ray;
+    use crate::render::{HitRecord, Material};
+    use crate::sampler::Sampler;
+    use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+    const SAMPLES: usize = 100_000;
+
+    /// A ray coming down on the z = 0 surface at `degrees` from the normal, and its hit.
+    fn hit(degrees: f32) -> (Ray, HitRecord) {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let wo = Vec3::new(sin, 0.0, cos);
+        let r_in = Ray::new(Point::ZERO + wo, -wo);
+        let mut rec = HitRecord::new();
+        rec.set_face_normal(&r_in, &Vec3::UNIT_Z);
+        (r_in, rec)
+    }
+
+    /// Average attenuation of the rays scattered by `material`, reflected and transmitted.
+    fn albedo(material: &Principled, degrees: f32, rng: &mut Sampler) -> (Color, Color) {
+        let (r_in, rec) = hit(degrees);
+        let (mut reflected, mut transmitted) = (Color::BLACK, Color::BLACK);
+        for _ in 0..SAMPLES {
+            if let Some(s) = material.scatter(&r_in, &rec, rng) {
+                let sum = if s.scattered.dir.z > 0.0 { &mut reflected } else { &mut transmitted };
+                *sum += s.attenuation / SAMPLES as f32;
+            }
+        }
+        (reflected, transmitted)
+    }
+
+    #[test]
+    fn test_scattering_follows_the_brdf() {
+        let mut rng = Sampler::new(0);
+        let red = Color::new(0.8, 0.1, 0.1);
+        for params in [
+            PrincipledParams { base_color: red, ..Default::default() },
+            PrincipledParams {
+                base_color: red,
+                metallic: 0.5,
+                roughness: 0.3,
+                ..Default::default()
+            },
+            PrincipledParams {
+                base_color: red,
+                clearcoat: 1.0,
+                roughness: 0.8,
+                ..Default::default()
+            },
+            PrincipledParams { base_color: Color::WHITE, specular: 1.0, ..Default::default() },
+        ] {
+            let material = Principled::new(params);
+            for degrees in [0.0, 45.0, 75.0] {
+                let (reflected, _) = albedo(&material, degrees, &mut rng);
+                // The integral of the BRDF by uniform samples.
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                let wo = Vec3::new(sin, 0.0, cos);
+                let mut sum = 0.0;
+                for _ in 0..SAMPLES {
+                    let w = random_unit_vector(&mut rng);
+                    let wi = Vec3::new(w.x, w.y, w.z.abs());
+                    sum += material.brdf(&wo, &wi).y * wi.z;
+                }
+                let expected = 2.0 * PI * sum / SAMPLES as f32;
+                assert_float_absolute_eq!(reflected.y, expected, 0.02);
+                assert!(reflected.x <= 1.0 && reflected.y <= 1.0, "{params:?} {reflected:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_metallic_reflects_the_base_color() {
+        let mut rng = Sampler::new(1);
+        let gold = Color::new(1.0, 0.78, 0.34);
+        let params = PrincipledParams {
+            base_color: gold,
+            metallic: 1.0,
+            roughness: 0.2,
+            ..Default::default()
+        };
+        let (reflected, transmitted) = albedo(&Principled::new(params), 0.0, &mut rng);
+        // Nearly all of it, up to the facets shadowing each other.
+        assert!((reflected - gold).len() < 0.05, "{reflected:?}");
+        assert_eq!(transmitted, Color::BLACK);
+    }
+
+    #[test]
+    fn test_transmission() {
+        let mut rng = Sampler::new(2);
+        let tint = Color::new(0.5, 1.0, 1.0);
+        let glass = Principled::new(PrincipledParams {
+            base_color: tint,
+            roughness: 0.0,
+            transmission: 1.0,
+            ..Default::default()
+        });
+        // At normal incidence, 4% is reflected, the rest goes through tinted.
+        let (reflected, transmitted) = albedo(&glass, 0.0, &mut rng);
+        assert_float_absolute_eq!(reflected.y, 0.04, 0.005);
+        assert!((transmitted - 0.96 * tint).len() < 0.01, "{transmitted:?}");
+
+        // Refracted as through glass, into the object and out of it.
+        let (r_in, rec) = hit(45.0);
+        let into = (0..100).find_map(|_| {
+            let s = glass.scatter(&r_in, &rec, &mut rng).unwrap();
+            (s.scattered.dir.z < 0.0).then_some(s.scattered)
+        });
+        let into = into.unwrap();
+        assert_float_absolute_eq!(into.dir.normed().x, -FRAC_1_SQRT_2 / 1.5, 1e-5);
+        let mut rec = HitRecord::new();
+        let r_out = Ray::new(Point::ZERO - into.dir, into.dir);
+        rec.set_face_normal(&r_out, &-Vec3::UNIT_Z);
+        assert!(!rec.front_face);
+        let out = (0..100).find_map(|_| {
+            let s = glass.scatter(&r_out, &rec, &mut rng).unwrap();
+            (s.scattered.dir.z < 0.0).then_some(s)
+        });
+        let out = out.unwrap();
+        assert_eq!(out.attenuation, Color::WHITE);
+        assert_float_absolute_eq!(out.scattered.dir.normed().x, -FRAC_1_SQRT_2, 1e-5);
+    }
+}
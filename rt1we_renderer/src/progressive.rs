@@ -0,0 +1,392 @@
+//! Progressive rendering: the image refines as samples accumulate.
+use crate::bvh::Bvh;
+use crate::cancel::CancellationToken;
+use crate::error::RtError;
+use crate::geometry::{Color, Point};
+use crate::image::{ImageRGBA, ImageRGBF32};
+use crate::render::{demo_camera, sample_pixel, Camera, PathDepth, SampleStats, Scene};
+use crate::sampler::{Sampler, SamplerKind};
+use crate::settings::RenderMode;
+use crate::tonemap::{tonemap, ToneMapSettings};
+use crate::view::OrbitView;
+use crate::wavefront::{trace_row, Integrator};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::thread;
+
+/// Renders the demo scene a few samples per pixel at a time, keeping the sum of all samples.
+///
+/// A cancelled step leaves the rows it did not reach with fewer samples, the image stays
+/// correct and the next steps keep refining it.
+pub struct ProgressiveRenderer {
+    width: usize,
+    height: usize,
+    max_depth: usize,
+    /// Bounces after which the paths are stopped at random.
+    russian_roulette: Option<usize>,
+    scene: Scene,
+    world: Bvh,
+    camera: Camera,
+    /// Sum of the linear colors of every sample, for each pixel.
+    accumulator: Vec<Color>,
+    /// Number of samples accumulated in each row.
+    row_samples: Vec<usize>,
+    /// Threads tracing the rows of each step.
+    pool: ThreadPool,
+    /// Seed of the random numbers of the samples.
+    seed: u64,
+    integrator: Integrator,
+    /// How the samples of each step are spread over the pixels.
+    sampler: SamplerKind,
+    /// Largest component of the samples, see [`SampleStats::filter`].
+    clamp_radiance: Option<f32>,
+    /// Conversion of the average radiance to the 8-bit images.
+    tone_map: ToneMapSettings,
+}
+
+/// Number of threads the machine can run at once, at least 1.
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn thread_pool(threads: usize) -> Result<ThreadPool, RtError> {
+    Ok(ThreadPoolBuilder::new().num_threads(threads).build()?)
+}
+
+impl ProgressiveRenderer {
+    /// Set up the demo scene, without tracing anything yet.
+    ///
+    /// # Arguments
+    /// - `width` - Output image width
+    /// - `height` - Output image height
+    /// - `max_depth` - Maximum number of ray bounces after a hit.
+    /// - `position` - Camera position.
+    ///
+    /// # Returns
+    /// The renderer, or an error if its thread cannot be started.
+    pub fn new(
+        width: usize, height: usize, max_depth: usize, position: &Point,
+    ) -> Result<Self, RtError> {
+        let camera = demo_camera(position, width, height);
+        Self::with_camera(Scene::demo(), width, height, max_depth, camera)
+    }
+
+    /// Same as [`ProgressiveRenderer::new`], rendering `scene` from an orbit view, through the
+    /// lens and shutter of the scene.
+    pub fn with_view(
+        scene: Scene, width: usize, height: usize, max_depth: usize, view: &OrbitView,
+    ) -> Result<Self, RtError> {
+        let camera = scene.camera(view, width, height);
+        Self::with_camera(scene, width, height, max_depth, camera)
+    }
+
+    fn with_camera(
+        scene: Scene, width: usize, height: usize, max_depth: usize, camera: Camera,
+    ) -> Result<Self, RtError> {
+        let world = scene.bvh();
+        Ok(ProgressiveRenderer {
+            width,
+            height,
+            max_depth,
+            russian_roulette: None,
+            scene,
+            world,
+            camera,
+            accumulator: vec![Color::BLACK; width * height],
+            row_samples: vec![0; height],
+            pool: thread_pool(1)?,
+            seed: rand::random(),
+            integrator: Integrator::default(),
+            sampler: SamplerKind::default(),
+            clamp_radiance: None,
+            tone_map: ToneMapSettings::default(),
+        })
+    }
+
+    /// Trace the rows of each step on `threads` threads, 1 by default.
+    ///
+    /// On error, the renderer keeps its threads.
+    pub fn set_threads(&mut self, threads: usize) -> Result<(), RtError> {
+        if threads.max(1) != self.threads() {
+            self.pool = thread_pool(threads.max(1))?;
+        }
+        Ok(())
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Seed the random numbers of the next steps, a random seed by default.
+    ///
+    /// Renderers with the same seed render the same images, whatever their number of
+    /// threads.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Schedule the rays of the next steps with `integrator`, the megakernel by default.
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    /// Spread the samples of the next steps with `sampler`, uniform random numbers by default.
+    pub fn set_sampler(&mut self, sampler: SamplerKind) {
+        self.sampler = sampler;
+    }
+
+    pub fn sampler(&self) -> SamplerKind {
+        self.sampler
+    }
+
+    /// Scale the samples of the next steps down to a largest component of `clamp`, unclamped
+    /// by default, see [`crate::settings::RenderSettings::clamp_radiance`].
+    pub fn set_clamp_radiance(&mut self, clamp: Option<f32>) {
+        self.clamp_radiance = clamp;
+    }
+
+    pub fn clamp_radiance(&self) -> Option<f32> {
+        self.clamp_radiance
+    }
+
+    /// Stop the paths of the next steps at random after `bounces` bounces, never by default,
+    /// see [`crate::settings::RenderSettings::russian_roulette`].
+    pub fn set_russian_roulette(&mut self, bounces: Option<usize>) {
+        self.russian_roulette = bounces;
+    }
+
+    pub fn russian_roulette(&self) -> Option<usize> {
+        self.russian_roulette
+    }
+
+    /// Tone map the next images with `settings`, clipped with a gamma of 2 by default.
+    pub fn set_tone_map(&mut self, settings: ToneMapSettings) {
+        self.tone_map = settings;
+    }
+
+    pub fn tone_map(&self) -> ToneMapSettings {
+        self.tone_map
+    }
+
+    /// Number of samples per pixel accumulated so far, in all the pixels.
+    pub fn samples(&self) -> usize {
+        self.row_samples.iter().copied().min().unwrap_or(0)
+    }
+
+    /// Add `samples` samples to every pixel.
+    ///
+    /// The rows are shared between the threads, an idle thread takes over rows from the busy
+    /// ones.
+    ///
+    /// # Arguments
+    /// - `samples` - Number of samples per pixel to add.
+    /// - `token` - Checked between rows, the step stops early when it is cancelled.
+    ///
+    /// # Returns
+    /// `false` when the step was cancelled.
+    pub fn step(&mut self, samples: usize, token: &CancellationToken) -> bool {
+        if samples == 0 {
+            return true;
+        }
+        let (width, height, seed) = (self.width, self.height, self.seed);
+        let depth = PathDepth { max: self.max_depth, roulette: self.russian_roulette };
+        let (camera, world, scene, integrator, sampler) =
+            (&self.camera, &self.world, &self.scene, self.integrator, self.sampler);
+        let (row_samples, clamp) = (&self.row_samples, self.clamp_radiance);
+        let trace = |j: usize| {
+            if token.is_cancelled() {
+                return None;
+            }
+            let mut rng = Sampler::for_row(seed, row_samples[j], j);
+            // Successive steps add samples to the pixels, their patterns are independent.
+            let pattern = sampler.build(seed ^ row_samples[j] as u64);
+            let row: Vec<Color> = match integrator {
+                Integrator::Megakernel => (0..width)
+                    .map(|i| {
+                        sample_pixel(
+                            i,
+                            j,
+                            width,
+                            height,
+                            camera,
+                            world,
+                            scene,
+                            depth,
+                            samples,
+                            RenderMode::PathTraced,
+                            pattern.as_ref(),
+                            clamp,
+                            &mut SampleStats::default(),
+                            &mut rng,
+                        )
+                    })
+                    .collect(),
+                Integrator::Wavefront => trace_row(
+                    j,
+                    width,
+                    height,
+                    camera,
+                    world,
+                    scene,
+                    depth,
+                    samples,
+                    pattern.as_ref(),
+                    clamp,
+                    &mut SampleStats::default(),
+                    &mut rng,
+                ),
+            };
+            Some((j, row))
+        };
+        let traced: Vec<Option<(usize, Vec<Color>)>> =
+            self.pool.install(|| (0..height).into_par_iter().map(trace).collect());
+
+        let mut count = 0;
+        for (j, row) in traced.into_iter().flatten() {
+            for (i, c) in row.into_iter().enumerate() {
+                self.accumulator[j * width + i] += c * samples as f32;
+            }
+            self.row_samples[j] += samples;
+            count += 1;
+        }
+        count == height
+    }
+
+    /// Same as [`ProgressiveRenderer::step`], returning the refined image.
+    ///
+    /// # Arguments
+    /// - `samples` - Number of samples per pixel to add.
+    /// - `token` - Checked between rows, the step stops early when it is cancelled.
+    ///
+    /// # Returns
+    /// The image after the step, and `false` when the step was cancelled.
+    pub fn refine(&mut self, samples: usize, token: &CancellationToken) -> (ImageRGBA, bool) {
+        let complete = self.step(samples, token);
+        (self.image(), complete)
+    }
+
+    /// The image averaging all the samples so far, tone mapped with the settings of the
+    /// renderer. Black before the first step.
+    pub fn image(&self) -> ImageRGBA {
+        tonemap(&self.hdr_image(), &self.tone_map)
+    }
+
+    /// The average linear color of the samples so far, without gamma or clamping.
+    pub fn hdr_image(&self) -> ImageRGBF32 {
+        let mut im = ImageRGBF32::new(self.width, self.height);
+        for (k, sum) in self.accumulator.iter().enumerate() {
+            let n = self.row_samples[k / self.width].max(1) as f32;
+            let c = *sum / n;
+            im.put(k % self.width, k / self.width, c.x, c.y, c.z);
+        }
+        im
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::cancel::CancellationToken;
+    use crate::geometry::Point;
+    use crate::progressive::ProgressiveRenderer;
+    use crate::tonemap::{tonemap, ToneMapOperator, ToneMapSettings};
+
+    #[test]
+    fn test_samples_accumulate() {
+        let mut renderer = ProgressiveRenderer::new(8, 6, 4, &Point::new(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(renderer.samples(), 0);
+        assert_eq!(renderer.image().at(3, 3), (0, 0, 0, 255));
+
+        let token = CancellationToken::new();
+        assert!(renderer.step(2, &token));
+        assert!(renderer.step(0, &token));
+        assert!(renderer.step(1, &token));
+        assert_eq!(renderer.samples(), 3);
+
+        let im = renderer.image();
+        assert_eq!((im.width, im.height), (8, 6));
+        // The top of the image looks at the sky, which is never black.
+        let (r, g, b, _) = im.at(4, 5);
+        assert!(r > 0 && g > 0 && b > 0);
+
+        let hdr = renderer.hdr_image();
+        assert_eq!((hdr.width, hdr.height), (8, 6));
+        let (r, g, b) = hdr.at(4, 5);
+        assert!(r > 0.0 && g > 0.0 && b > 0.0);
+    }
+
+    #[test]
+    fn test_refine() {
+        let mut renderer = ProgressiveRenderer::new(8, 6, 4, &Point::new(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(renderer.tone_map(), ToneMapSettings::default());
+        let settings =
+            ToneMapSettings { operator: ToneMapOperator::Reinhard, exposure: 1.0, gamma: 2.2 };
+        renderer.set_tone_map(settings);
+
+        let token = CancellationToken::new();
+        let (im, complete) = renderer.refine(2, &token);
+        assert!(complete);
+        assert_eq!(renderer.samples(), 2);
+        assert_eq!(im, tonemap(&renderer.hdr_image(), &settings));
+        assert_eq!(im, renderer.image());
+
+        token.cancel();
+        let (im, complete) = renderer.refine(2, &token);
+        assert!(!complete);
+        assert_eq!((renderer.samples(), im.width, im.height), (2, 8, 6));
+    }
+
+    #[test]
+    fn test_threads_trace_every_row() {
+        let mut renderer = ProgressiveRenderer::new(8, 7, 4, &Point::new(0.0, 0.0, 0.0)).unwrap();
+        renderer.set_threads(0).unwrap();
+        assert_eq!(renderer.threads(), 1);
+        // More threads than rows.
+        renderer.set_threads(3).unwrap();
+        assert!(renderer.step(1, &CancellationToken::new()));
+        renderer.set_threads(16).unwrap();
+        assert!(renderer.step(2, &CancellationToken::new()));
+        assert_eq!(renderer.samples(), 3);
+
+        let hdr = renderer.hdr_image();
+        for j in 0..7 {
+            let (r, g, b) = hdr.at(4, j);
+            assert!(r > 0.0 || g > 0.0 || b > 0.0, "row {j} is black");
+        }
+    }
+
+    #[test]
+    fn test_seeded_steps_do_not_depend_on_threads() {
+        let render_on = |threads: usize| {
+            let mut renderer =
+                ProgressiveRenderer::new(8, 6, 8, &Point::new(0.0, 0.0, 0.0)).unwrap();
+            renderer.set_threads(threads).unwrap();
+            renderer.set_seed(42);
+            assert_eq!(renderer.seed(), 42);
+            renderer.step(2, &CancellationToken::new());
+            renderer.step(1, &CancellationToken::new());
+            renderer.hdr_image().pixels
+        };
+        assert_eq!(render_on(1), render_on(3));
+    }
+
+    #[test]
+    fn test_cancelled_step_keeps_samples() {
+        let mut renderer = ProgressiveRenderer::new(8, 6, 4, &Point::new(0.0, 0.0, 0.0)).unwrap();
+        let token = CancellationToken::new();
+        renderer.step(2, &token);
+
+        token.cancel();
+        assert!(!renderer.step(2, &token));
+        assert_eq!(renderer.samples(), 2);
+        assert!(renderer.step(1, &CancellationToken::new()));
+        assert_eq!(renderer.samples(), 3);
+    }
+}
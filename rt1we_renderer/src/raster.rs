@@ -0,0 +1,243 @@
+//! Rasterized previews: the outlines of the objects seen from a camera, fast enough to follow
+//! every edit while laying out a scene.
+use crate::geometry::{Point, Vec3};
+use crate::render::{HittableList, Sphere};
+use crate::trig::deg2rad;
+use crate::view::{Lens, OrbitView};
+
+/// Points closer to the camera plane than this are clipped.
+const NEAR: f32 = 1e-3;
+
+/// Projects points seen by the camera of an orbit view in the image.
+pub struct Projection {
+    position: Point,
+    /// Right, up and backwards directions of the camera.
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    /// Size of the image plane, at a unit distance from the camera.
+    plane_width: f32,
+    plane_height: f32,
+}
+
+/// The visible outline of an object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Silhouette {
+    /// Index of the object in the scene.
+    pub object_id: usize,
+    /// Distance from the camera to the object center.
+    pub distance: f32,
+    /// Outline, in image coordinates, see [`Projection::project`].
+    pub outline: Vec<(f32, f32)>,
+}
+
+impl Silhouette {
+    /// Whether a point, in image coordinates, is inside the outline.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let n = self.outline.len();
+        let sides = (0..n).map(|k| {
+            let ((ax, ay), (bx, by)) = (self.outline[k], self.outline[(k + 1) % n]);
+            (bx - ax) * (y - ay) - (by - ay) * (x - ax)
+        });
+        // Inside a convex outline, the point is on the same side of every edge.
+        let (mut left, mut right) = (false, false);
+        for side in sides {
+            left |= side > 0.0;
+            right |= side < 0.0;
+        }
+        n >= 3 && !(left && right)
+    }
+}
+
+impl Projection {
+    /// The projection of the camera rendering `view` through `lens` in an image of the given
+    /// aspect ratio, in perspective whatever the projection of the lens. The aperture and focus
+    /// of the lens blur the image but do not move it.
+    pub fn new(view: &OrbitView, lens: &Lens, aspect_ratio: f32) -> Self {
+        let position = view.position();
+        let w = (position - view.target).normed();
+        let u = Vec3::new(0.0, 1.0, 0.0).cross(&w).normed();
+        let v = w.cross(&u);
+        let plane_height = 2.0 * (deg2rad(lens.vfov) / 2.0).tan();
+        Projection { position, u, v, w, plane_width: aspect_ratio * plane_height, plane_height }
+    }
+
+    /// Distance of a point in front of the camera plane, negative behind it.
+    fn depth(&self, p: &Point) -> f32 {
+        -(*p - self.position).dot(&self.w)
+    }
+
+    /// Image coordinates of a point.
+    ///
+    /// # Returns
+    /// The coordinates, `(0, 0)` at the lower left corner of the image and `(1, 1)` at the
+    /// upper right one as for the rendered images, and the depth of the point. `None` for the
+    /// points behind the camera.
+    pub fn project(&self, p: &Point) -> Option<(f32, f32, f32)> {
+        let depth = self.depth(p);
+        if depth < NEAR {
+            return None;
+        }
+        let d = *p - self.position;
+        let x = 0.5 + d.dot(&self.u) / (depth * self.plane_width);
+        let y = 0.5 + d.dot(&self.v) / (depth * self.plane_height);
+        Some((x, y, depth))
+    }
+
+    /// Outline of a sphere, as seen from the camera.
+    ///
+    /// The sphere covers the cone of the rays tangent to it, which crosses the disk bounded by
+    /// the circle of the tangent points. The disk is clipped to the part in front of the
+    /// camera, and its outline is projected.
+    ///
+    /// # Arguments
+    /// - `sphere` - The sphere, negative radiuses count as positive.
+    /// - `segments` - Number of segments of the outline before clipping.
+    ///
+    /// # Returns
+    /// The outline in image coordinates, `None` when the sphere is behind the camera, or
+    /// around it.
+    pub fn sphere_outline(&self, sphere: &Sphere, segments: usize) -> Option<Vec<(f32, f32)>> {
+        let radius = sphere.radius.abs();
+        let to_center = sphere.center - self.position;
+        let distance = to_center.len();
+        if distance <= radius {
+            return None;
+        }
+        let axis = to_center / distance;
+        let circle_center = self.position + (distance - radius * radius / distance) * axis;
+        let circle_radius = radius * (distance * distance - radius * radius).sqrt() / distance;
+
+        // Any direction not along the axis gives a basis of the disk plane.
+        let helper =
+            if axis.x.abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+        let p = axis.cross(&helper).normed();
+        let q = axis.cross(&p);
+        let circle: Vec<Point> = (0..segments.max(3))
+            .map(|k| {
+                let angle = k as f32 / segments.max(3) as f32 * std::f32::consts::TAU;
+                circle_center + circle_radius * (angle.cos() * p + angle.sin() * q)
+            })
+            .collect();
+
+        let clipped = self.clip(&circle);
+        if clipped.len() < 3 {
+            return None;
+        }
+        clipped.iter().map(|p| self.project(p).map(|(x, y, _)| (x, y))).collect()
+    }
+
+    /// The part of a convex polygon in front of the near plane.
+    fn clip(&self, polygon: &[Point]) -> Vec<Point> {
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for (k, a) in polygon.iter().enumerate() {
+            let b = &polygon[(k + 1) % polygon.len()];
+            let (depth_a, depth_b) = (self.depth(a), self.depth(b));
+            if depth_a >= NEAR {
+                clipped.push(*a);
+            }
+            if (depth_a >= NEAR) != (depth_b >= NEAR) {
+                let t = (NEAR - depth_a) / (depth_b - depth_a);
+                clipped.push(*a + t * (*b - *a));
+            }
+        }
+        clipped
+    }
+
+    /// Outlines of the visible spheres of a scene, from the farthest to the closest, so that
+    /// drawing them in order hides the far objects behind the close ones. The other objects
+    /// have no outline.
+    pub fn silhouettes(&self, world: &HittableList, segments: usize) -> Vec<Silhouette> {
+        let mut silhouettes: Vec<Silhouette> = world
+            .objects()
+            .iter()
+            .enumerate()
+            .filter_map(|(object_id, object)| {
+                let sphere = object.as_sphere()?;
+                let outline = self.sphere_outline(sphere, segments)?;
+                let distance = (sphere.center - self.position).len();
+                Some(Silhouette { object_id, distance, outline })
+            })
+            .collect();
+        silhouettes.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+        silhouettes
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::Point;
+    use crate::raster::{Projection, Silhouette};
+    use crate::registry::MaterialId;
+    use crate::render::{Scene, Sphere};
+    use crate::view::{Lens, OrbitView};
+
+    #[test]
+    fn test_project_matches_the_rendered_image() {
+        // The default view looks down -z from the origin, with a 90° vertical field of view.
+        let projection = Projection::new(&OrbitView::default(), &Lens::default(), 2.0);
+        let (x, y, depth) = projection.project(&Point::new(0.0, 0.0, -1.0)).unwrap();
+        assert_f32_near!(x, 0.5);
+        assert_f32_near!(y, 0.5);
+        assert_f32_near!(depth, 1.0);
+
+        // Top right corner of the image plane.
+        let (x, y, _) = projection.project(&Point::new(2.0, 1.0, -1.0)).unwrap();
+        assert_f32_near!(x, 1.0);
+        assert_f32_near!(y, 1.0);
+        assert!(projection.project(&Point::new(0.0, 0.0, 1.0)).is_none());
+
+        // A 60° field of view frames a plane smaller by tan(30°).
+        let lens = Lens { vfov: 60.0, ..Default::default() };
+        let projection = Projection::new(&OrbitView::default(), &lens, 2.0);
+        let (x, y, _) = projection.project(&Point::new(2.0, 1.0, -1.0)).unwrap();
+        assert_f32_near!(x - 0.5, 0.5 / 30f32.to_radians().tan());
+        assert_f32_near!(y - 0.5, 0.5 / 30f32.to_radians().tan());
+    }
+
+    #[test]
+    fn test_sphere_outline() {
+        let projection = Projection::new(&OrbitView::default(), &Lens::default(), 1.0);
+        let sphere = Sphere::new(Point::new(0.0, 0.0, -4.0), 1.0, MaterialId(0));
+        let outline = projection.sphere_outline(&sphere, 32).unwrap();
+        assert_eq!(outline.len(), 32);
+        // Seen from straight ahead, the outline is a circle of angular radius asin(1/4).
+        let expected = (0.25f32.asin()).tan() / 2.0;
+        for (x, y) in outline {
+            assert!((((x - 0.5).powi(2) + (y - 0.5).powi(2)).sqrt() - expected).abs() < 1e-4);
+        }
+
+        let behind = Sphere::new(Point::new(0.0, 0.0, 4.0), 1.0, MaterialId(0));
+        assert!(projection.sphere_outline(&behind, 32).is_none());
+        let around = Sphere::new(Point::new(0.0, 0.0, 0.0), -1.0, MaterialId(0));
+        assert!(projection.sphere_outline(&around, 32).is_none());
+    }
+
+    #[test]
+    fn test_silhouette_contains() {
+        let projection = Projection::new(&OrbitView::default(), &Lens::default(), 1.0);
+        let sphere = Sphere::new(Point::new(2.0, 0.0, -4.0), 1.0, MaterialId(0));
+        let outline = projection.sphere_outline(&sphere, 16).unwrap();
+        let silhouette = Silhouette { object_id: 0, distance: 4.0, outline };
+
+        let (x, y, _) = projection.project(&sphere.center).unwrap();
+        assert!(silhouette.contains(x, y));
+        assert!(!silhouette.contains(0.5, 0.5));
+        assert!(!silhouette.contains(x, 1.0));
+    }
+
+    #[test]
+    fn test_silhouettes_of_the_demo_scene() {
+        let scene = Scene::demo();
+        let projection = Projection::new(&OrbitView::default(), &Lens::default(), 4.0 / 3.0);
+        let silhouettes = projection.silhouettes(scene.world(), 32);
+
+        assert_eq!(silhouettes.len(), scene.world().len());
+        assert!(silhouettes.windows(2).all(|s| s[0].distance >= s[1].distance));
+        // The ground sphere wraps around the camera, its outline is clipped but still covers
+        // the bottom of the image.
+        let ground = &silhouettes[0];
+        assert_eq!(scene.world().objects()[ground.object_id].as_sphere().unwrap().radius, 100.0);
+        assert!(ground.outline.iter().any(|&(_, y)| y < 0.0));
+    }
+}
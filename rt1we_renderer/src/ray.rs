@@ -6,15 +6,25 @@ use crate::geometry::{dot, Point, Vec3};
 pub struct Ray {
     pub orig: Point,
     pub dir: Vec3,
+    /// Instant at which the ray is cast, where moving objects are hit.
+    pub time: f32,
+    /// Wavelength of the light the ray carries in nanometers, in spectral renders only, see
+    /// [`crate::spectrum`].
+    pub wavelength: Option<f32>,
 }
 
 impl Ray {
+    /// A ray cast at time 0, of all wavelengths.
+    pub fn new(orig: Point, dir: Vec3) -> Self {
+        Ray { orig, dir, time: 0.0, wavelength: None }
+    }
+
     /// Get point at a distance along the ray
     ///
     /// # Parameters
     /// - `t` - Distance
     pub fn at(&self, t: f32) -> Point {
-        &self.orig + &(&self.dir * t)
+        self.orig + (self.dir * t)
     }
 }
 
@@ -32,14 +42,14 @@ pub fn hit_sphere(center: &Point, radius: f32, r: &Ray) -> f32 {
     //      A = r.orig
     //      C = center
     //      r = radius
-    let oc = &r.orig - &center;
+    let oc = &r.orig - center;
     let a = dot(&r.dir, &r.dir);
     let b = 2.0 * dot(&oc, &r.dir);
     let c = dot(&oc, &oc) - radius * radius;
     let disc = b * b - 4.0 * a * c;
 
     if disc < 0.0 {
-        return -1.0;
+        -1.0
     } else {
         (-b - disc.sqrt()) / (2.0 * a)
     }
@@ -47,14 +57,14 @@ pub fn hit_sphere(center: &Point, radius: f32, r: &Ray) -> f32 {
 
 // Same as hit_sphere(), but simplified formulas, mostly removes sqrt's
 pub fn hit_sphere2(center: &Point, radius: f32, r: &Ray) -> f32 {
-    let oc = &r.orig - &center;
+    let oc = &r.orig - center;
     let a = r.dir.len_squared();
     let half_b = dot(&oc, &r.dir);
     let c = oc.len_squared() - radius * radius;
     let disc = (half_b * half_b) - (a * c);
 
     if disc < 0.0 {
-        return -1.0;
+        -1.0
     } else {
         (-half_b - disc.sqrt()) / a
     }
@@ -68,8 +78,7 @@ pub(crate) mod test {
 
     #[test]
     fn test_projection() {
-        let r =
-            Ray { orig: Point { x: 0.0, y: 0.0, z: 0.0 }, dir: Vec3 { x: 1.0, y: 1.0, z: 1.0 } };
+        let r = Ray::new(Point { x: 0.0, y: 0.0, z: 0.0 }, Vec3 { x: 1.0, y: 1.0, z: 1.0 });
 
         let projected = r.at(5.0);
         let expected = Vec3 { x: 5.0, y: 5.0, z: 5.0 };
@@ -80,7 +89,7 @@ pub(crate) mod test {
     fn test_hit_sphere_returns_correct_distance_when_hitting_a_sphere_just_in_front() {
         let center = Vec3 { x: 0.0, y: 0.0, z: -1.0 };
         let radius = 0.5;
-        let ray = Ray { orig: Vec3::ZERO, dir: -Vec3::UNIT_Z };
+        let ray = Ray::new(Vec3::ZERO, -Vec3::UNIT_Z);
 
         let hit_distance = hit_sphere(&center, radius, &ray);
         assert_eq!(hit_distance, 0.5);
@@ -92,7 +101,7 @@ pub(crate) mod test {
     fn test_hit_sphere_returns_minus_1_when_ray_does_not_hit_the_sphere() {
         let center = Vec3 { x: 0.0, y: 10.0, z: -1.0 };
         let radius = 0.5;
-        let ray = Ray { orig: Vec3::ZERO, dir: -Vec3::UNIT_Z };
+        let ray = Ray::new(Vec3::ZERO, -Vec3::UNIT_Z);
 
         let hit_distance = hit_sphere(&center, radius, &ray);
         assert_eq!(hit_distance, -1.0);
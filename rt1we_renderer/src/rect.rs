@@ -0,0 +1,371 @@
+//! Axis-aligned rectangles, and boxes made of six of them: the walls and the area lights of
+//! Cornell box scenes.
+use crate::aabb::{axis_of, Aabb};
+use crate::geometry::{dot, Point, Vec3};
+use crate::interval::Interval;
+use crate::mesh::BOX_PADDING;
+use crate::ray::Ray;
+use crate::registry::MaterialId;
+use crate::render::{HitRecord, Hittable};
+use crate::sampler::Sampler;
+use rand::Rng;
+
+/// Rectangle in the `z = k` plane, from (`x0`, `y0`) to (`x1`, `y1`), seen from both sides,
+/// facing +z.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct XyRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub y0: f32,
+    pub y1: f32,
+    pub k: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+/// Rectangle in the `y = k` plane, from (`x0`, `z0`) to (`x1`, `z1`), seen from both sides,
+/// facing +y.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct XzRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+/// Rectangle in the `x = k` plane, from (`y0`, `z0`) to (`y1`, `z1`), seen from both sides,
+/// facing +x.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct YzRect {
+    pub y0: f32,
+    pub y1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+impl XyRect {
+    pub fn new(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material_id: MaterialId) -> Self {
+        XyRect { x0, x1, y0, y1, k, material_id }
+    }
+
+    fn axis_rect(&self) -> AxisRect {
+        let (x0, x1, y0, y1, k) = (self.x0, self.x1, self.y0, self.y1, self.k);
+        AxisRect { axes: [0, 1, 2], min: (x0, y0), max: (x1, y1), k, facing: 1.0 }
+    }
+}
+
+impl XzRect {
+    pub fn new(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material_id: MaterialId) -> Self {
+        XzRect { x0, x1, z0, z1, k, material_id }
+    }
+
+    fn axis_rect(&self) -> AxisRect {
+        let (x0, x1, z0, z1, k) = (self.x0, self.x1, self.z0, self.z1, self.k);
+        AxisRect { axes: [0, 2, 1], min: (x0, z0), max: (x1, z1), k, facing: 1.0 }
+    }
+}
+
+impl YzRect {
+    pub fn new(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material_id: MaterialId) -> Self {
+        YzRect { y0, y1, z0, z1, k, material_id }
+    }
+
+    fn axis_rect(&self) -> AxisRect {
+        let (y0, y1, z0, z1, k) = (self.y0, self.y1, self.z0, self.z1, self.k);
+        AxisRect { axes: [1, 2, 0], min: (y0, z0), max: (y1, z1), k, facing: 1.0 }
+    }
+}
+
+/// The geometry shared by the three kinds of rectangles.
+struct AxisRect {
+    /// The two axes along the sides of the rectangle, then the one of its normal.
+    axes: [usize; 3],
+    /// Corners, along the two side axes.
+    min: (f32, f32),
+    max: (f32, f32),
+    /// Coordinate of the rectangle along the normal axis.
+    k: f32,
+    /// Side of the front face along the normal axis, 1 or -1.
+    facing: f32,
+}
+
+impl AxisRect {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let [a, b, c] = self.axes;
+        let dir = axis_of(&r.dir, c);
+        if dir == 0.0 {
+            return false;
+        }
+        let t = (self.k - axis_of(&r.orig, c)) / dir;
+        if !ray_t.contains(t) {
+            return false;
+        }
+        let p = r.at(t);
+        let (pa, pb) = (axis_of(&p, a), axis_of(&p, b));
+        if !(self.min.0..=self.max.0).contains(&pa) || !(self.min.1..=self.max.1).contains(&pb) {
+            return false;
+        }
+        rec.t = t;
+        rec.p = p;
+        rec.u = (pa - self.min.0) / (self.max.0 - self.min.0);
+        rec.v = (pb - self.min.1) / (self.max.1 - self.min.1);
+        rec.set_face_normal(r, &(self.facing * unit(c)));
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let min = self.point(self.min.0, self.min.1, self.k - BOX_PADDING);
+        let max = self.point(self.max.0, self.max.1, self.k + BOX_PADDING);
+        Aabb::new(min, max)
+    }
+
+    /// The point at `a` and `b` along the side axes, and `c` along the normal axis.
+    fn point(&self, a: f32, b: f32, c: f32) -> Point {
+        unit(self.axes[0]) * a + unit(self.axes[1]) * b + unit(self.axes[2]) * c
+    }
+
+    /// Uniform on the area of the rectangle, so the density of a direction grows with the
+    /// square of the distance, and as the rectangle is seen at a grazing angle.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f32 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction), Interval::new(0.001, f32::INFINITY), &mut rec)
+        {
+            return 0.0;
+        }
+        let area = (self.max.0 - self.min.0) * (self.max.1 - self.min.1);
+        let distance_squared = rec.t * rec.t * direction.len_squared();
+        let cosine = (dot(direction, &rec.normal) / direction.len()).abs();
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point, rng: &mut Sampler) -> Vec3 {
+        let a = self.min.0 + rng.gen::<f32>() * (self.max.0 - self.min.0);
+        let b = self.min.1 + rng.gen::<f32>() * (self.max.1 - self.min.1);
+        self.point(a, b, self.k) - *origin
+    }
+}
+
+/// Unit vector along an axis.
+fn unit(axis: usize) -> Vec3 {
+    match axis {
+        0 => Vec3::UNIT_X,
+        1 => Vec3::UNIT_Y,
+        _ => Vec3::UNIT_Z,
+    }
+}
+
+impl Hittable for XyRect {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let hit = self.axis_rect().hit(r, ray_t, rec);
+        if hit {
+            rec.material_id = self.material_id;
+        }
+        hit
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.axis_rect().bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f32 {
+        self.axis_rect().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point, rng: &mut Sampler) -> Vec3 {
+        self.axis_rect().random(origin, rng)
+    }
+}
+
+impl Hittable for XzRect {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let hit = self.axis_rect().hit(r, ray_t, rec);
+        if hit {
+            rec.material_id = self.material_id;
+        }
+        hit
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.axis_rect().bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f32 {
+        self.axis_rect().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point, rng: &mut Sampler) -> Vec3 {
+        self.axis_rect().random(origin, rng)
+    }
+}
+
+impl Hittable for YzRect {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let hit = self.axis_rect().hit(r, ray_t, rec);
+        if hit {
+            rec.material_id = self.material_id;
+        }
+        hit
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.axis_rect().bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f32 {
+        self.axis_rect().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point, rng: &mut Sampler) -> Vec3 {
+        self.axis_rect().random(origin, rng)
+    }
+}
+
+/// Box aligned with the axes, made of six rectangles facing outwards.
+///
+/// Named so as not to shadow [`std::boxed::Box`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cuboid {
+    /// Corner with the smallest coordinates.
+    pub min: Point,
+    /// Corner with the largest coordinates.
+    pub max: Point,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+impl Cuboid {
+    /// A box between two opposite corners, in any order.
+    pub fn new(a: Point, b: Point, material_id: MaterialId) -> Self {
+        let min = Point::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+        Cuboid { min, max, material_id }
+    }
+
+    /// The six sides of the box.
+    fn sides(&self) -> [AxisRect; 6] {
+        let (min, max) = (self.min, self.max);
+        let side = |axes: [usize; 3], k: f32, facing: f32| AxisRect {
+            axes,
+            min: (axis_of(&min, axes[0]), axis_of(&min, axes[1])),
+            max: (axis_of(&max, axes[0]), axis_of(&max, axes[1])),
+            k,
+            facing,
+        };
+        [
+            side([0, 1, 2], min.z, -1.0),
+            side([0, 1, 2], max.z, 1.0),
+            side([0, 2, 1], min.y, -1.0),
+            side([0, 2, 1], max.y, 1.0),
+            side([1, 2, 0], min.x, -1.0),
+            side([1, 2, 0], max.x, 1.0),
+        ]
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut closest = ray_t.max;
+        let mut hit = false;
+        for side in self.sides() {
+            if side.hit(r, Interval::new(ray_t.min, closest), rec) {
+                closest = rec.t;
+                hit = true;
+            }
+        }
+        if hit {
+            rec.material_id = self.material_id;
+        }
+        hit
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let padding = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        Aabb::new(self.min - padding, self.max + padding)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Point, Vec3};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::rect::{Cuboid, XyRect, XzRect, YzRect};
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, Hittable};
+
+    #[test]
+    fn test_xy_rect() {
+        let rect = XyRect::new(-1.0, 3.0, 0.0, 2.0, -2.0, MaterialId(4));
+        let mut rec = HitRecord::new();
+        let r = Ray::new(Point::new(0.0, 0.5, 0.0), -Vec3::UNIT_Z);
+        assert!(rect.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.p, rec.normal), (2.0, Point::new(0.0, 0.5, -2.0), Vec3::UNIT_Z));
+        assert_eq!((rec.u, rec.v, rec.material_id), (0.25, 0.25, MaterialId(4)));
+        assert!(rec.front_face);
+
+        // From behind, beside, out of range and parallel.
+        let r = Ray::new(Point::new(0.0, 0.5, -4.0), Vec3::UNIT_Z);
+        assert!(rect.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert!(!rec.front_face);
+        let r = Ray::new(Point::new(0.0, 2.5, 0.0), -Vec3::UNIT_Z);
+        assert!(!rect.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        let r = Ray::new(Point::new(0.0, 0.5, 0.0), -Vec3::UNIT_Z);
+        assert!(!rect.hit(&r, Interval::new(0.001, 1.0), &mut rec));
+        let r = Ray::new(Point::new(0.0, 0.5, -2.0), Vec3::UNIT_X);
+        assert!(!rect.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+
+        let bbox = rect.bounding_box();
+        assert_eq!((bbox.min.x, bbox.max.y), (-1.0, 2.0));
+        assert!(bbox.extent().z > 0.0);
+    }
+
+    #[test]
+    fn test_xz_and_yz_rects() {
+        let mut rec = HitRecord::new();
+        let floor = XzRect::new(0.0, 1.0, 0.0, 1.0, 0.0, MaterialId(0));
+        let r = Ray::new(Point::new(0.5, 3.0, 0.5), -Vec3::UNIT_Y);
+        assert!(floor.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.normal), (3.0, Vec3::UNIT_Y));
+
+        let wall = YzRect::new(0.0, 1.0, 0.0, 1.0, 2.0, MaterialId(0));
+        let r = Ray::new(Point::new(0.0, 0.5, 0.5), Vec3::UNIT_X);
+        assert!(wall.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.normal), (2.0, -Vec3::UNIT_X));
+        assert!(!rec.front_face);
+        assert!(wall.bounding_box().extent().x > 0.0);
+    }
+
+    #[test]
+    fn test_cuboid() {
+        let cuboid =
+            Cuboid::new(Point::new(1.0, 1.0, -3.0), Point::new(-1.0, -1.0, -1.0), MaterialId(2));
+        assert_eq!(cuboid.min, Point::new(-1.0, -1.0, -3.0));
+        let mut rec = HitRecord::new();
+
+        // The closest side, facing outwards.
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+        assert!(cuboid.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.normal, rec.material_id), (1.0, Vec3::UNIT_Z, MaterialId(2)));
+        assert!(rec.front_face);
+        let r = Ray::new(Point::new(5.0, 0.0, -2.0), -Vec3::UNIT_X);
+        assert!(cuboid.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.normal), (4.0, Vec3::UNIT_X));
+
+        // From the inside, the back of the far side.
+        let r = Ray::new(Point::new(0.0, 0.0, -2.0), -Vec3::UNIT_Y);
+        assert!(cuboid.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.normal), (1.0, Vec3::UNIT_Y));
+        assert!(!rec.front_face);
+
+        let r = Ray::new(Point::new(2.0, 0.0, 0.0), -Vec3::UNIT_Z);
+        assert!(!cuboid.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        let bbox = cuboid.bounding_box();
+        assert!(bbox.min.z < -3.0 && bbox.max.x > 1.0);
+    }
+}
@@ -0,0 +1,178 @@
+//! The materials of a scene, named, and the handles the objects refer to them with.
+use crate::render::{Material, MaterialParams};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Handle of a material in a [`MaterialRegistry`].
+///
+/// Handles are only made by the registry, so the material of an object always exists, as
+/// long as the object stays in the scene which made its material.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MaterialId(pub(crate) usize);
+
+impl MaterialId {
+    /// Position of the material in the registry, from 0 in the order they were added.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for MaterialId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Error of a material looked up or added by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaterialNameError {
+    /// No material has this name.
+    Unknown(String),
+    /// Another material already has this name.
+    Duplicate(String),
+}
+
+impl fmt::Display for MaterialNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaterialNameError::Unknown(name) => write!(f, "unknown material '{name}'"),
+            MaterialNameError::Duplicate(name) => write!(f, "duplicate material name '{name}'"),
+        }
+    }
+}
+
+impl std::error::Error for MaterialNameError {}
+
+/// Materials, each with a unique name.
+///
+/// Copies are cheap: the materials are shared.
+#[derive(Clone, Default)]
+pub struct MaterialRegistry {
+    materials: Vec<Arc<dyn Material>>,
+    names: Vec<String>,
+    ids: HashMap<String, MaterialId>,
+}
+
+impl MaterialRegistry {
+    pub fn new() -> Self {
+        MaterialRegistry::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    /// Add a material named `name`.
+    ///
+    /// # Returns
+    /// The handle of the material, or an error if the name is taken.
+    pub fn add_named(
+        &mut self, name: &str, params: &MaterialParams,
+    ) -> Result<MaterialId, MaterialNameError> {
+        if self.ids.contains_key(name) {
+            return Err(MaterialNameError::Duplicate(name.to_string()));
+        }
+        let id = MaterialId(self.materials.len());
+        self.materials.push(params.build());
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    /// Add a material, named `material<index>`, or `material<index>_<k>` if that is taken.
+    pub fn add(&mut self, params: &MaterialParams) -> MaterialId {
+        let index = self.materials.len();
+        let name = std::iter::once(format!("material{index}"))
+            .chain((1..).map(|k| format!("material{index}_{k}")))
+            .find(|name| !self.ids.contains_key(name))
+            .expect("some name is free");
+        self.add_named(&name, params).expect("the name is free")
+    }
+
+    /// The material named `name`.
+    pub fn id(&self, name: &str) -> Result<MaterialId, MaterialNameError> {
+        self.ids.get(name).copied().ok_or_else(|| MaterialNameError::Unknown(name.to_string()))
+    }
+
+    /// Name of a material, `None` for a handle of another registry.
+    pub fn name(&self, id: MaterialId) -> Option<&str> {
+        self.names.get(id.0).map(|name| name.as_str())
+    }
+
+    /// Whether `id` is a handle of this registry.
+    pub fn contains(&self, id: MaterialId) -> bool {
+        id.0 < self.materials.len()
+    }
+
+    /// Parameters of a material, `None` for a handle of another registry.
+    pub fn params(&self, id: MaterialId) -> Option<MaterialParams> {
+        self.materials.get(id.0).map(|m| m.params())
+    }
+
+    /// Replace a material, keeping its name. Copies of the registry keep the previous one.
+    ///
+    /// # Panics
+    /// If `id` is a handle of another, smaller, registry.
+    pub fn set(&mut self, id: MaterialId, params: &MaterialParams) {
+        self.materials[id.0] = params.build();
+    }
+
+    /// Handles of all the materials, in the order they were added.
+    pub fn ids(&self) -> impl Iterator<Item = MaterialId> {
+        (0..self.materials.len()).map(MaterialId)
+    }
+
+    /// The material of a handle.
+    ///
+    /// # Panics
+    /// If `id` is a handle of another, smaller, registry.
+    pub(crate) fn get(&self, id: MaterialId) -> &dyn Material {
+        &*self.materials[id.0]
+    }
+
+    /// Approximate memory used by the materials, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.materials.iter().map(|m| std::mem::size_of_val(m) + std::mem::size_of_val(&**m)).sum()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::Color;
+    use crate::registry::{MaterialId, MaterialNameError, MaterialRegistry};
+    use crate::render::MaterialParams;
+
+    #[test]
+    fn test_material_registry() {
+        let mut registry = MaterialRegistry::new();
+        let glass =
+            MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.0, film: None };
+        let steel = MaterialParams::Metal { albedo: Color::WHITE, fuzz: 0.1, film: None };
+        let id = registry.add_named("glass", &glass).unwrap();
+        assert_eq!(registry.id("glass"), Ok(id));
+        assert_eq!((registry.name(id), registry.params(id)), (Some("glass"), Some(glass.clone())));
+        assert_eq!(registry.get(id).params(), glass);
+
+        let err = registry.add_named("glass", &steel).err().unwrap();
+        assert_eq!(err, MaterialNameError::Duplicate("glass".to_string()));
+        let err = registry.id("steel").err().unwrap();
+        assert_eq!(err.to_string(), "unknown material 'steel'");
+        assert_eq!(registry.len(), 1);
+
+        // Unnamed materials get a free name.
+        registry.add_named("material2", &steel).unwrap();
+        let id = registry.add(&steel);
+        assert_eq!(registry.name(id), Some("material2_1"));
+        assert_eq!(registry.ids().collect::<Vec<_>>(), [MaterialId(0), MaterialId(1), id]);
+
+        registry.set(id, &glass);
+        assert_eq!(registry.params(id), Some(glass));
+        assert_eq!(registry.params(MaterialId(3)), None);
+        assert!(registry.contains(id) && !registry.contains(MaterialId(3)));
+    }
+}
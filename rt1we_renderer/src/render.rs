@@ -1,78 +1,304 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::cancel::CancellationToken;
+use crate::cylinder::{Capsule, Cone, Cylinder};
+use crate::environment::EnvironmentMap;
+use crate::error::RtError;
 use crate::geometry::{
-    dot, lerp, random_in_unit_sphere, random_unit_vector, reflect, refract, Color, Point, Vec3,
+    dot, lerp, random_in_unit_disk, random_unit_vector, reflect, refract, Color, Point, Vec3,
 };
-use crate::image::{flipv, ImageRGBA};
-use crate::ray::{hit_sphere2, Ray};
+use crate::heightfield::Heightfield;
+use crate::image::{ImageRGBA, ImageRGBF32};
+use crate::instance::Instance;
+use crate::interval::Interval;
+use crate::light::{DirectionalLight, PunctualLight};
+use crate::lod::{averaged_material, ProxyGroup, DEFAULT_LOD_THRESHOLD};
+use crate::medium::ConstantMedium;
+use crate::mesh::{Mesh, Triangle};
+use crate::microfacet::Microfacet;
+use crate::onb::Onb;
+use crate::pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf};
+use crate::principled::{Principled, PrincipledParams};
+use crate::ray::Ray;
+use crate::rect::{Cuboid, XyRect, XzRect, YzRect};
+use crate::registry::{MaterialId, MaterialNameError, MaterialRegistry};
+use crate::sampler::{PixelSampler, Sampler};
+use crate::sdf::SdfObject;
+use crate::settings::{RenderMode, RenderSettings};
+use crate::sky::PhysicalSky;
+use crate::spectrum::{wavelength_at, wavelength_rgb, D_LINE};
+use crate::stereo::Cameras;
+use crate::subsurface::Subsurface;
+use crate::texture::{Texture, TextureParams};
+use crate::thin_film::{Substrate, ThinFilm};
+use crate::tiles::{render_tiles_cancellable, render_tiles_hdr};
+use crate::tonemap::tonemap;
+use crate::torus::Torus;
 use crate::trig::deg2rad;
+use crate::view::{CameraProjection, Lens, OrbitView};
 use rand::Rng;
-use std::time::Instant;
+use rayon::prelude::*;
+use std::f32::consts::PI;
+use std::fmt;
+use std::ops::{AddAssign, ControlFlow};
+use std::sync::Arc;
 
 /// Define a single ray-to-object hit.
 #[derive(Copy, Clone)]
 pub struct HitRecord {
-    p: Point,
-    normal: Vec3,
-    material_id: usize,
-    t: f32,
-    front_face: bool,
+    pub(crate) p: Point,
+    pub(crate) normal: Vec3,
+    pub(crate) material_id: MaterialId,
+    /// Index of the object in the scene.
+    pub(crate) object_id: usize,
+    pub(crate) t: f32,
+    /// Surface coordinates of the hit point, for the textures.
+    pub(crate) u: f32,
+    pub(crate) v: f32,
+    pub(crate) front_face: bool,
+}
+
+impl Default for HitRecord {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HitRecord {
     pub fn new() -> Self {
         HitRecord {
             p: Point { x: 0.0, y: 0.0, z: 0.0 },
-            material_id: 0,
+            material_id: MaterialId(0),
+            object_id: 0,
             normal: Vec3::ZERO,
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
         }
     }
+
+    /// The hit point.
+    pub fn p(&self) -> Point {
+        self.p
+    }
+
+    /// Unit normal at the hit point, facing the incoming ray.
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    /// Whether the ray hit the outside of the surface.
+    pub fn front_face(&self) -> bool {
+        self.front_face
+    }
+
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
-        self.front_face = dot(&r.dir, &outward_normal) < 0.0;
+        self.front_face = dot(&r.dir, outward_normal) < 0.0;
         if self.front_face {
-            self.normal = outward_normal.clone();
+            self.normal = *outward_normal;
         } else {
-            self.normal = -outward_normal.clone();
+            self.normal = -*outward_normal;
+        }
+    }
+}
+
+/// Editable description of a material.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaterialParams {
+    /// Diffuse, scattering the rays around the normal.
+    Lambertian { albedo: TextureParams },
+    /// Reflective, blurred by a `fuzz` from 0 to 1, the square of the roughness, see
+    /// [`MaterialParams::rough_metal`]. An optional `film` coats it with interference colors.
+    Metal { albedo: Color, fuzz: f32, film: Option<ThinFilm> },
+    /// Glossy, with a `diffuse` base under facets reflecting `f0` at normal incidence, their
+    /// normals spread by a `roughness` from 0 to 1, see [`crate::microfacet`]. Metals have a
+    /// black base and reflect their color, plastics reflect about 4% over a colored base.
+    Microfacet { diffuse: Color, f0: Color, roughness: f32 },
+    /// Any of the above but lights, blended by a few parameters, see [`crate::principled`].
+    Principled(PrincipledParams),
+    /// Translucent, as skin or wax, reflecting `albedo` of the light once it has walked
+    /// inside, about `mean_free_path` between two scattering events, per channel. See
+    /// [`crate::subsurface`].
+    Subsurface { albedo: Color, mean_free_path: Color },
+    /// Transparent, as glass or water, of `refraction_index` for yellow light. Its index rises
+    /// towards blue by a `dispersion`, Cauchy's coefficient in square micrometers: about
+    /// 0.004 for crown glass, 0.013 for dense flint glass, 0.0 without dispersion. The
+    /// dispersion only shows in spectral renders, see [`crate::spectrum`]. An optional `film`
+    /// coats it with interference colors, as a soap bubble, see [`crate::thin_film`].
+    Dielectric { refraction_index: f32, dispersion: f32, film: Option<ThinFilm> },
+    /// A light source of `color`, scaled by `intensity`, which does not reflect light.
+    DiffuseLight { color: Color, intensity: f32 },
+    /// Scatters the rays evenly in all directions, for the volumes of smoke and fog.
+    Isotropic { albedo: TextureParams },
+}
+
+impl MaterialParams {
+    /// A metal of perceptual `roughness` from 0 to 1: its fuzz is the square of the roughness,
+    /// so that evenly spaced roughnesses look evenly blurred.
+    pub fn rough_metal(albedo: Color, roughness: f32) -> Self {
+        MaterialParams::Metal { albedo, fuzz: roughness * roughness, film: None }
+    }
+
+    /// Kind of material, for display.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaterialParams::Lambertian { .. } => "lambertian",
+            MaterialParams::Metal { .. } => "metal",
+            MaterialParams::Microfacet { .. } => "microfacet",
+            MaterialParams::Principled(_) => "principled",
+            MaterialParams::Subsurface { .. } => "subsurface",
+            MaterialParams::Dielectric { .. } => "dielectric",
+            MaterialParams::DiffuseLight { .. } => "diffuse light",
+            MaterialParams::Isotropic { .. } => "isotropic",
+        }
+    }
+
+    pub(crate) fn build(&self) -> Arc<dyn Material> {
+        match self {
+            MaterialParams::Lambertian { albedo } => {
+                Arc::new(Lambertian { albedo: albedo.build() })
+            }
+            &MaterialParams::Metal { albedo, fuzz, film } => Arc::new(Metal { albedo, fuzz, film }),
+            &MaterialParams::Microfacet { diffuse, f0, roughness } => {
+                Arc::new(Microfacet::new(diffuse, f0, roughness))
+            }
+            &MaterialParams::Principled(params) => Arc::new(Principled::new(params)),
+            &MaterialParams::Subsurface { albedo, mean_free_path } => {
+                Arc::new(Subsurface::new(albedo, mean_free_path))
+            }
+            &MaterialParams::Dielectric { refraction_index, dispersion, film } => {
+                Arc::new(Dielectric { refraction_index, dispersion, film })
+            }
+            &MaterialParams::DiffuseLight { color, intensity } => {
+                Arc::new(DiffuseLight { color, intensity })
+            }
+            MaterialParams::Isotropic { albedo } => Arc::new(Isotropic { albedo: albedo.build() }),
         }
     }
 }
 
 /// Material scattering behaviour.
-trait Material {
+///
+/// Materials are shared between the copies of a scene, and between render threads.
+///
+/// ```
+/// use rt1we_renderer::geometry::{Color, Vec3};
+/// use rt1we_renderer::ray::Ray;
+/// use rt1we_renderer::render::{HitRecord, Material, MaterialParams, ScatterResult};
+/// use rt1we_renderer::sampler::Sampler;
+///
+/// /// A perfect mirror.
+/// struct Mirror;
+///
+/// impl Material for Mirror {
+///     fn params(&self) -> MaterialParams {
+///         MaterialParams::rough_metal(Color::WHITE, 0.0)
+///     }
+///
+///     fn scatter(
+///         &self, r_in: &Ray, rec: &HitRecord, _rng: &mut Sampler,
+///     ) -> Option<ScatterResult> {
+///         let n = rec.normal();
+///         let reflected = r_in.dir - n * 2.0 * r_in.dir.dot(&n);
+///         let scattered = Ray::new(rec.p(), reflected);
+///         Some(ScatterResult { attenuation: Color::WHITE, scattered, pdf: None })
+///     }
+/// }
+///
+/// let mut rec = HitRecord::new();
+/// let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+/// rec.set_face_normal(&ray, &Vec3::UNIT_Y);
+/// let reflected = Mirror.scatter(&ray, &rec, &mut Sampler::new(1)).unwrap().scattered;
+/// assert_eq!(reflected.dir, Vec3::new(1.0, 1.0, 0.0));
+/// assert!(!Mirror.is_emissive());
+/// ```
+pub trait Material: Send + Sync {
+    /// The parameters this material was built from.
+    fn params(&self) -> MaterialParams;
+
     /// Scatter or absorb a ray.
     ///
     /// # Arguments
     /// - `r_in` - Ray coming in the hit point
     /// - `rec` - The hit record
-    /// - `attenuation` - How much the
-    /// - `scattered` - The output scattered ray
-    fn scatter(
-        &self, r_in: &Ray, rec: &mut HitRecord, attenuation: &mut Color, scattered: &mut Ray,
-    ) -> bool;
+    /// - `rng` - Random numbers of the scattering.
+    ///
+    /// # Returns
+    /// How the ray scatters, or `None` when it is absorbed.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Sampler) -> Option<ScatterResult>;
+
+    /// Scatter or absorb a ray, which may travel under the surface before leaving the object,
+    /// the walk being traced in `world`. Most materials scatter the rays at the hit point, see
+    /// [`Material::scatter`].
+    fn scatter_through(
+        &self, r_in: &Ray, rec: &HitRecord, _world: &Bvh, rng: &mut Sampler,
+    ) -> Option<ScatterResult> {
+        self.scatter(r_in, rec, rng)
+    }
+
+    /// Density of the directions the material scatters `scattered` to, per unit of solid
+    /// angle. Only used for the materials scattering with a [`ScatterResult::pdf`].
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f32 {
+        0.0
+    }
+
+    /// Light emitted at a hit point, black for the materials which only reflect light.
+    ///
+    /// # Arguments
+    /// - `u` - First surface coordinate, in `[0;1]`.
+    /// - `v` - Second surface coordinate, in `[0;1]`.
+    /// - `p` - The hit point.
+    fn emitted(&self, _u: f32, _v: f32, _p: &Point) -> Color {
+        Color::BLACK
+    }
+
+    /// Whether the material emits light.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+}
+
+/// How a material scatters a ray, see [`Material::scatter`].
+pub struct ScatterResult {
+    /// Color factor of the scattered light.
+    pub attenuation: Color,
+    /// The scattered ray, traced as is when there is no `pdf`, as off a mirror or through
+    /// glass.
+    pub scattered: Ray,
+    /// Distribution of the directions of the light scattered around the normal, `scattered`
+    /// being one of them. The tracer samples it along with the lights, and weights the
+    /// directions with [`Material::scattering_pdf`].
+    pub pdf: Option<CosinePdf>,
 }
 
 /// Lambertian (diffuse) material.
-#[derive(Copy, Clone, Debug)]
 struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Material for Lambertian {
-    fn scatter(
-        &self, r_in: &Ray, rec: &mut HitRecord, attenuation: &mut Color, scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = rec.normal + random_unit_vector();
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
+    fn params(&self) -> MaterialParams {
+        MaterialParams::Lambertian { albedo: self.albedo.params() }
+    }
 
-        *scattered = Ray { orig: rec.p, dir: scatter_direction };
-        *attenuation = self.albedo;
-        // println!(
-        //     "[mat=lambertian] IN: {0:?}  OUT: {1:?}  ATT: {2:?}",
-        //     &rec.normal, scatter_direction, attenuation
-        // );
-        true
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Sampler) -> Option<ScatterResult> {
+        let pdf = CosinePdf::new(&rec.normal);
+        Some(ScatterResult {
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+            scattered: Ray {
+                orig: rec.p,
+                dir: pdf.generate(rng),
+                time: r_in.time,
+                wavelength: r_in.wavelength,
+            },
+            pdf: Some(pdf),
+        })
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
+        let cosine = dot(&rec.normal, &scattered.dir.normed());
+        (cosine / PI).max(0.0)
     }
 }
 
@@ -81,27 +307,65 @@ impl Material for Lambertian {
 struct Metal {
     albedo: Color,
     fuzz: f32,
+    film: Option<ThinFilm>,
 }
 
 impl Material for Metal {
-    fn scatter(
-        &self, r_in: &Ray, rec: &mut HitRecord, attenuation: &mut Color, scattered: &mut Ray,
-    ) -> bool {
+    fn params(&self) -> MaterialParams {
+        MaterialParams::Metal { albedo: self.albedo, fuzz: self.fuzz, film: self.film }
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Sampler) -> Option<ScatterResult> {
+        // The unit reflected direction, moved to a random point of the sphere of radius `fuzz`
+        // around its tip: the directions spread up to asin(fuzz) around the mirror reflection.
         let reflected = reflect(&r_in.dir.normed(), &rec.normal);
-        *scattered = Ray { orig: rec.p, dir: reflected + self.fuzz * random_in_unit_sphere() };
-        *attenuation = self.albedo;
-        let res = dot(&scattered.dir, &rec.normal) > 0.0;
-        res
+        let dir = reflected + self.fuzz * random_unit_vector(rng);
+        let scattered = Ray { orig: rec.p, dir, time: r_in.time, wavelength: r_in.wavelength };
+        // Fuzzed below the surface, or along it, the ray is absorbed.
+        if dot(&scattered.dir, &rec.normal) <= 0.0 {
+            return None;
+        }
+        let attenuation = match &self.film {
+            Some(film) => {
+                let albedo = self.albedo;
+                let substrates = [albedo.x, albedo.y, albedo.z].map(Substrate::Conductor);
+                let cosine = dot(&-r_in.dir.normed(), &rec.normal);
+                film.reflectance_rgb(1.0, substrates, cosine, r_in.wavelength)
+            }
+            None => self.albedo,
+        };
+        Some(ScatterResult { attenuation, scattered, pdf: None })
     }
 }
 
-/// Refractive material.
+/// Refractive material, as glass or water, in the air.
 #[derive(Copy, Clone, Debug)]
-struct Dieletric {
+struct Dielectric {
     refraction_index: f32,
+    dispersion: f32,
+    film: Option<ThinFilm>,
 }
 
-impl Dieletric {
+impl Dielectric {
+    /// Refraction index at `wavelength` nanometers, after Cauchy's equation `n = A + B / λ²`,
+    /// the index of the material being that of the yellow [`D_LINE`]. Rays of all wavelengths
+    /// see the index of the material.
+    fn refraction_index_at(&self, wavelength: Option<f32>) -> f32 {
+        let Some(wavelength) = wavelength else {
+            return self.refraction_index;
+        };
+        // Cauchy's coefficient is in square micrometers.
+        let inverse_square = |nm: f32| 1e6 / (nm * nm);
+        self.refraction_index
+            + self.dispersion * (inverse_square(wavelength) - inverse_square(D_LINE))
+    }
+
+    /// Share of the light reflected off the surface, Schlick's approximation of the Fresnel
+    /// equations.
+    ///
+    /// # Arguments
+    /// - `cosine` - Cosine of the incidence angle.
+    /// - `ref_idx` - Ratio of the refractive indices, incoming side over outgoing side.
     fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
         let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
         let r0 = r0 * r0;
@@ -109,59 +373,153 @@ impl Dieletric {
     }
 }
 
-impl Material for Dieletric {
-    fn scatter(
-        &self, r_in: &Ray, rec: &mut HitRecord, attenuation: &mut Color, scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = Color::WHITE;
+impl Material for Dielectric {
+    fn params(&self) -> MaterialParams {
+        let (refraction_index, dispersion) = (self.refraction_index, self.dispersion);
+        MaterialParams::Dielectric { refraction_index, dispersion, film: self.film }
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Sampler) -> Option<ScatterResult> {
+        // Entering the material from the air, or leaving it.
+        let refraction_index = self.refraction_index_at(r_in.wavelength);
         let refraction_ratio =
-            if rec.front_face { 1.0 / self.refraction_index } else { self.refraction_index };
+            if rec.front_face { 1.0 / refraction_index } else { refraction_index };
         let unit_dir = r_in.dir.normed();
 
-        let mut rng = rand::thread_rng();
-
         let cos_theta = dot(&-unit_dir, &rec.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract
-            || Dieletric::reflectance(cos_theta, refraction_ratio) > rng.gen::<f32>()
-        {
-            reflect(&unit_dir, &rec.normal)
-        } else {
-            refract(&unit_dir, &rec.normal, self.refraction_index)
-        };
-        *scattered = Ray { orig: rec.p, dir: -direction };
-        // println!("[mat=dielectric] IN: {unit_dir:?} OUT: {direction:?}");
+        // The light is either reflected or refracted, picked at random with the share of the
+        // reflected light. Past the critical angle, it is all reflected.
+        let reflected = reflect(&unit_dir, &rec.normal);
+        let (direction, attenuation) =
+            match (refract(&unit_dir, &rec.normal, refraction_ratio), &self.film) {
+                (None, _) => (reflected, Color::WHITE),
+                (Some(refracted), None) => {
+                    let reflectance = Dielectric::reflectance(cos_theta, refraction_ratio);
+                    let direction =
+                        if reflectance > rng.gen::<f32>() { reflected } else { refracted };
+                    (direction, Color::WHITE)
+                }
+                (Some(refracted), Some(film)) => {
+                    // The film reflects each channel its own share, the direction is picked with
+                    // their average.
+                    let (outside, inside) = if rec.front_face {
+                        (1.0, refraction_index)
+                    } else {
+                        (refraction_index, 1.0)
+                    };
+                    let substrates = [Substrate::Dielectric(inside); 3];
+                    let reflectance =
+                        film.reflectance_rgb(outside, substrates, cos_theta, r_in.wavelength);
+                    let p = (reflectance.x + reflectance.y + reflectance.z) / 3.0;
+                    if p > rng.gen::<f32>() {
+                        (reflected, reflectance / p)
+                    } else {
+                        (refracted, (Color::WHITE - reflectance) / (1.0 - p))
+                    }
+                }
+            };
+        let scattered =
+            Ray { orig: rec.p, dir: direction, time: r_in.time, wavelength: r_in.wavelength };
+        Some(ScatterResult { attenuation, scattered, pdf: None })
+    }
+}
+
+/// Light source material, emitting the same light everywhere and absorbing the rays.
+#[derive(Copy, Clone, Debug)]
+struct DiffuseLight {
+    color: Color,
+    intensity: f32,
+}
+
+impl Material for DiffuseLight {
+    fn params(&self) -> MaterialParams {
+        MaterialParams::DiffuseLight { color: self.color, intensity: self.intensity }
+    }
+
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _rng: &mut Sampler) -> Option<ScatterResult> {
+        None
+    }
 
-        // let refracted = refract(&unit_dir, &rec.normal, refraction_ratio);
-        // *scattered = Ray { orig: rec.p, dir: refracted };
-        // println!("[mat=dielectric] IN: {unit_dir:?} OUT: {refracted:?}");
+    fn emitted(&self, _u: f32, _v: f32, _p: &Point) -> Color {
+        self.intensity * self.color
+    }
 
+    fn is_emissive(&self) -> bool {
         true
     }
 }
 
+/// Phase function of the participating media: the rays leave in a uniformly random direction.
+struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Material for Isotropic {
+    fn params(&self) -> MaterialParams {
+        MaterialParams::Isotropic { albedo: self.albedo.params() }
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Sampler) -> Option<ScatterResult> {
+        Some(ScatterResult {
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+            scattered: Ray {
+                orig: rec.p,
+                dir: random_unit_vector(rng),
+                time: r_in.time,
+                wavelength: r_in.wavelength,
+            },
+            pdf: None,
+        })
+    }
+}
+
 /// Trait for objects we can hit with a ray.
-trait Hittable {
+pub(crate) trait Hittable {
+    /// Check if the ray hits the object within the distances `ray_t`, and fill `rec` if so.
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+
+    /// Box enclosing the whole object.
+    fn bounding_box(&self) -> Aabb;
+
+    /// Density of the directions of [`Hittable::random`], per unit of solid angle, zero for
+    /// the directions missing the object.
     ///
-    fn hit(self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool;
+    /// Objects which cannot be sampled, as most of them, always have a zero density.
+    fn pdf_value(&self, _origin: &Point, _direction: &Vec3) -> f32 {
+        0.0
+    }
+
+    /// Random direction from `origin` towards the object, to sample the light it emits.
+    fn random(&self, _origin: &Point, _rng: &mut Sampler) -> Vec3 {
+        Vec3::UNIT_X
+    }
 }
 
 /// Sphere object description.
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Sphere {
-    center: Point,
-    radius: f32,
-    material_id: usize,
+    pub center: Point,
+    /// A negative radius flips the normals, making a hollow sphere with a dielectric material.
+    pub radius: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+impl Sphere {
+    pub fn new(center: Point, radius: f32, material_id: MaterialId) -> Self {
+        Sphere { center, radius, material_id }
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
         let oc = r.orig - self.center;
         let a = r.dir.len_squared();
         let half_b = dot(&oc, &r.dir);
-        let c = oc.len_squared() - self.radius * self.radius;
-        let disc = (half_b * half_b) - (a * c);
+        // b² - ac computed from the closest point of the ray to the center, which does not
+        // cancel out for small spheres seen from far away.
+        let closest = oc - (half_b / a) * r.dir;
+        let disc = a * (self.radius * self.radius - closest.len_squared());
 
         if disc < 0.0 {
             return false;
@@ -169,10 +527,12 @@ impl Hittable for Sphere {
 
         let sqrt_disc = disc.sqrt();
 
-        let root = (-half_b - sqrt_disc) / a;
-        if root < t_min || t_max < root {
-            let root = (-half_b + sqrt_disc) / a;
-            if root < t_min || t_max < root {
+        // The closest root in range. NaN roots, from rays without a direction, are never in
+        // range.
+        let mut root = (-half_b - sqrt_disc) / a;
+        if !ray_t.contains(root) {
+            root = (-half_b + sqrt_disc) / a;
+            if !ray_t.contains(root) {
                 return false;
             }
         }
@@ -180,358 +540,1719 @@ impl Hittable for Sphere {
         rec.t = root;
         rec.p = r.at(root);
         let outward_normal = (rec.p - self.center) / self.radius;
+        (rec.u, rec.v) = sphere_uv(&((rec.p - self.center) / self.radius.abs()));
         rec.material_id = self.material_id;
         rec.set_face_normal(r, &outward_normal);
         true
     }
-}
 
-pub struct Plane {
-    center: Point,
-    normal: Vec3,
-    material_id: usize,
-}
+    fn bounding_box(&self) -> Aabb {
+        let radius = self.radius.abs();
+        let r = Vec3::new(radius, radius, radius);
+        Aabb::new(self.center - r, self.center + r)
+    }
 
-impl Hittable for Plane {
-    fn hit(self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
-        let denom = dot(&self.normal, &r.dir);
-        if denom > 1e-6 {
-            let v = self.center - r.orig;
-            let t = dot(&v, &self.normal) / denom;
-            if t >= 0.0 {
-                rec.t = t;
-                rec.p = r.at(t);
-                rec.material_id = self.material_id;
-                rec.set_face_normal(r, &self.normal);
-                return true;
-            }
+    /// Directions are uniform in the cone of the sphere seen from `origin`, or in all
+    /// directions from the inside.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f32 {
+        let (distance_squared, radius_squared) =
+            ((self.center - *origin).len_squared(), self.radius * self.radius);
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
         }
-        false
+        let r = Ray::new(*origin, *direction);
+        if !self.hit(&r, Interval::new(0.001, f32::INFINITY), &mut HitRecord::new()) {
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: &Point, rng: &mut Sampler) -> Vec3 {
+        let direction = self.center - *origin;
+        let (distance_squared, radius_squared) =
+            (direction.len_squared(), self.radius * self.radius);
+        if distance_squared <= radius_squared {
+            return random_unit_vector(rng);
+        }
+        // Uniform in the cone: the cosine to its axis is uniform, down to the cone edge.
+        let (r1, r2) = (rng.gen::<f32>(), rng.gen::<f32>());
+        let z = 1.0 + r2 * ((1.0 - radius_squared / distance_squared).sqrt() - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).sqrt();
+        let local = Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z);
+        Onb::from_w(&direction).local(&local)
     }
 }
 
-/// Collection of object that can be hit by a ray.
-pub struct HittableList {
-    objects: Vec<Sphere>,
+/// Surface coordinates of a point of the unit sphere centered on the origin.
+///
+/// `u` is the angle around the Y axis, from X = -1, and `v` the angle from Y = -1, both
+/// scaled to `[0;1]`.
+pub(crate) fn sphere_uv(p: &Point) -> (f32, f32) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
 }
 
-impl HittableList {
-    pub fn new() -> Self {
-        HittableList { objects: Vec::new() }
-    }
+/// Sphere moving in a straight line between two keyframes, blurred by the camera shutter.
+///
+/// Before `time0` and after `time1` the sphere stays at its keyframe.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MovingSphere {
+    /// Center at `time0`.
+    pub center0: Point,
+    /// Center at `time1`.
+    pub center1: Point,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
 
-    pub fn clear(&mut self) {
-        self.objects.clear()
+impl MovingSphere {
+    pub fn new(
+        center0: Point, center1: Point, time0: f32, time1: f32, radius: f32,
+        material_id: MaterialId,
+    ) -> Self {
+        MovingSphere { center0, center1, time0, time1, radius, material_id }
     }
 
-    /// Add an object to the list.
-    ///
-    /// # Arguments
-    /// - `object` - The object to add.
-    pub fn add(&mut self, object: &Sphere) {
-        self.objects.push((*object).into());
+    /// Center of the sphere at `time`.
+    pub fn center(&self, time: f32) -> Point {
+        if self.time1 <= self.time0 {
+            return self.center0;
+        }
+        let t = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        lerp(&self.center0, &self.center1, t)
     }
 
-    /// Process a single ray cast.
-    ///
-    /// # Arguments
-    /// - `r` - The ray.
-    /// - `t_min` - Minimum distance for which the ray cast is considered a valid hit.
-    /// - `t_max` - Maximum distance for which the ray cast is considered a valid hit.
-    /// - `rec` - Keep track of the hit properties.
-    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
-        let mut temp_rec = HitRecord::new();
-        let mut hit_anything = false;
-        let mut closest_so_far = t_max;
+    /// The sphere as it is at `time`.
+    pub fn at(&self, time: f32) -> Sphere {
+        Sphere::new(self.center(time), self.radius, self.material_id)
+    }
+}
 
-        for each in &self.objects {
-            if each.hit(r, t_min, closest_so_far, &mut temp_rec) {
-                hit_anything = true;
-                closest_so_far = temp_rec.t;
-                *rec = temp_rec;
-            }
-        }
+impl Hittable for MovingSphere {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        self.at(r.time).hit(r, ray_t, rec)
+    }
 
-        hit_anything
+    /// Box around the whole motion of the sphere.
+    fn bounding_box(&self) -> Aabb {
+        let start = self.at(self.time0).bounding_box();
+        start.surrounding(&self.at(self.time1).bounding_box())
     }
 }
 
-/// Using single sphere as input
-fn ray_color(r: &Ray) -> Color {
-    let t = hit_sphere2(&Point { x: 0.0, y: 0.0, z: -1.0 }, 0.5, r);
+/// Infinite plane object description, seen from both sides.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Plane {
+    /// Any point of the plane.
+    pub center: Point,
+    /// Unit normal, the front side of the plane.
+    pub normal: Vec3,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+impl Plane {
+    /// A plane through `center`, `normal` does not need to be a unit vector.
+    pub fn new(center: Point, normal: Vec3, material_id: MaterialId) -> Self {
+        Plane { center, normal: normal.normed(), material_id }
+    }
 
-    if t > 0.0 {
-        // println!("ray hit sphere at {t}");
+    /// Two unit vectors of the plane, orthogonal to each other.
+    fn axes(&self) -> (Vec3, Vec3) {
+        let other = if self.normal.x.abs() > 0.9 { Vec3::UNIT_Y } else { Vec3::UNIT_X };
+        let u = self.normal.cross(&other).normed();
+        (u, self.normal.cross(&u))
+    }
+}
 
-        let n = (r.at(t) - Vec3 { x: 0.0, y: 0.0, z: -1.0 }).normed();
-        return 0.5 * Color { x: n.x + 1.0, y: n.y + 1.0, z: n.z + 1.0 };
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let denom = dot(&self.normal, &r.dir);
+        if denom.abs() <= 1e-6 {
+            return false;
+        }
+        let t = dot(&(self.center - r.orig), &self.normal) / denom;
+        if !ray_t.contains(t) {
+            return false;
+        }
+        rec.t = t;
+        rec.p = r.at(t);
+        // Coordinates in the plane, repeating every unit.
+        let (u, v) = self.axes();
+        let offset = rec.p - self.center;
+        (rec.u, rec.v) = (dot(&offset, &u).rem_euclid(1.0), dot(&offset, &v).rem_euclid(1.0));
+        rec.material_id = self.material_id;
+        rec.set_face_normal(r, &self.normal);
+        true
     }
 
-    let unit_direction = &r.dir.normed();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    lerp(&Color::WHITE, &Color { x: 0.5, y: 0.7, z: 1.0 }, t)
+    fn bounding_box(&self) -> Aabb {
+        let inf = Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        Aabb::new(-inf, inf)
+    }
 }
 
-/// Cast a single ray in the scene and return the computed pixel color.
+/// An object of a scene, any of the primitive shapes.
 ///
-/// This is a recursive function. As long as a hit produces a scattered ray, the function
-/// will be called again with that new ray, until we reach `depth=0` or we have no more
-/// scattering ray.
-///
-/// If no object is hit, we just return a *sky* color, which is a gradient modulated by the
-/// ray direction.
-///
-/// # Arguments
-/// - `r` - The ray.
-/// - `world` - The list of object we can hit.
-/// - `depth` - Remaining amount of ray bounces.
-/// - `materials` - The collection of materials used in the scene.
-fn ray_color_2(
-    r: &Ray, world: &HittableList, depth: usize, materials: &Vec<Box<dyn Material>>,
-) -> Color {
-    let mut rec = HitRecord::new();
+/// Meshes and heightfields are shared, cloning the object does not copy their buffers, and
+/// instances share the mesh they were made of.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    Sphere(Sphere),
+    MovingSphere(MovingSphere),
+    Plane(Plane),
+    Triangle(Triangle),
+    Mesh(Arc<Mesh>),
+    XyRect(XyRect),
+    XzRect(XzRect),
+    YzRect(YzRect),
+    Cuboid(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Capsule(Capsule),
+    Torus(Torus),
+    Sdf(SdfObject),
+    Heightfield(Arc<Heightfield>),
+    Instance(Instance),
+    Medium(ConstantMedium),
+}
 
-    if depth == 0 {
-        //println!("[depth=0]!!! depth limit reached");
-        return Color { x: 0.0, y: 0.0, z: 0.0 };
+impl From<Sphere> for Object {
+    fn from(sphere: Sphere) -> Self {
+        Object::Sphere(sphere)
     }
+}
 
-    if world.hit(r, 0.001, f32::INFINITY, &mut rec) {
-        // --- using materials
-        let mut scattered = Ray { orig: Vec3::ZERO, dir: Vec3::UNIT_Y };
-        let mut attenuation = Color::BLACK;
+impl From<MovingSphere> for Object {
+    fn from(sphere: MovingSphere) -> Self {
+        Object::MovingSphere(sphere)
+    }
+}
 
-        let was_scattered =
-            materials[rec.material_id].scatter(r, &mut rec, &mut attenuation, &mut scattered);
+impl From<Plane> for Object {
+    fn from(plane: Plane) -> Self {
+        Object::Plane(plane)
+    }
+}
 
-        // println!("[depth={depth}]was scattered?  {was_scattered}");
-        // println!("[depth={depth}]attenuation?  {attenuation:?}");
-        return if was_scattered {
-            let px_color = ray_color_2(&scattered, world, depth - 1, &materials);
-            // println!("[depth={depth}]px_color {px_color:?}");
-            attenuation * px_color
-            // attenuation * ray_color_2(&scattered, world, depth - 1, &materials)
-        } else {
-            Color::BLACK
-        };
-        // --- simple lambertian
-        // let target = rec.p + rec.normal + random_unit_vector();
-        // //let target = rec.p + random_in_hemisphere(&rec.normal);
-        // let new_ray = Ray {
-        //     orig: rec.p,
-        //     dir: target - rec.p,
-        // };
-        // return 0.5 * ray_color_2(&new_ray, &world, depth - 1);
+impl From<Triangle> for Object {
+    fn from(triangle: Triangle) -> Self {
+        Object::Triangle(triangle)
+    }
+}
 
-        // --- return normal as color
-        //return 0.5 * (&rec.normal + &WHITE);
+impl From<Mesh> for Object {
+    fn from(mesh: Mesh) -> Self {
+        Object::Mesh(Arc::new(mesh))
     }
+}
 
-    // background sky
-    // println!("[depth={depth}] Hit the sky");
-    let unit_direction = &r.dir.normed();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    lerp(&Color::WHITE, &Color { x: 0.5, y: 0.7, z: 1.0 }, t)
+impl From<XyRect> for Object {
+    fn from(rect: XyRect) -> Self {
+        Object::XyRect(rect)
+    }
 }
 
-fn clamp(v: f32, lo: f32, hi: f32) -> f32 {
-    if v < lo {
-        return lo;
+impl From<XzRect> for Object {
+    fn from(rect: XzRect) -> Self {
+        Object::XzRect(rect)
     }
-    if v > hi {
-        return hi;
+}
+
+impl From<YzRect> for Object {
+    fn from(rect: YzRect) -> Self {
+        Object::YzRect(rect)
     }
-    v
 }
 
-/// Represent a camera.
-struct Camera {
-    origin: Point,
-    lower_left_corner: Point,
-    horizontal: Vec3,
-    vertical: Vec3,
+impl From<Cuboid> for Object {
+    fn from(cuboid: Cuboid) -> Self {
+        Object::Cuboid(cuboid)
+    }
 }
 
-impl Camera {
-    pub fn new(lookfrom: Point, lookat: Point, vup: Vec3, vfov: f32, aspect_ratio: f32) -> Self {
-        let theta = deg2rad(vfov);
-        let h = (theta / 2.0).tan();
+impl From<Cylinder> for Object {
+    fn from(cylinder: Cylinder) -> Self {
+        Object::Cylinder(cylinder)
+    }
+}
 
-        let vp_height = 2.0 * h;
-        let vp_width = aspect_ratio * vp_height;
+impl From<Cone> for Object {
+    fn from(cone: Cone) -> Self {
+        Object::Cone(cone)
+    }
+}
 
-        let w = (lookfrom - lookat).normed();
-        let u = (vup.cross(&w)).normed();
-        let v = w.cross(&u);
+impl From<Capsule> for Object {
+    fn from(capsule: Capsule) -> Self {
+        Object::Capsule(capsule)
+    }
+}
 
-        let origin = lookfrom;
-        let horizontal = vp_width * u;
-        let vertical = vp_height * v;
-        let lower_left_corner = origin - (horizontal / 2.0) - (vertical / 2.0) - w;
+impl From<Torus> for Object {
+    fn from(torus: Torus) -> Self {
+        Object::Torus(torus)
+    }
+}
 
-        Camera { origin, lower_left_corner, horizontal, vertical }
+impl From<SdfObject> for Object {
+    fn from(object: SdfObject) -> Self {
+        Object::Sdf(object)
     }
+}
 
-    /// Generate a ray from the camera origin to the given pixel coordinates.
-    /// The coordinates are normalized between 0 and 1.
-    /// (0, 0) is the lower left corner, (1, 1) is the upper right corner.
-    /// # Arguments
-    /// - `u` - Horizontal coordinate
-    /// - `v` - Vertical coordinate
-    /// # Returns
-    /// A ray from the camera origin to the given pixel coordinates.
-    /// The coordinates are normalized between 0 and 1.
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let dir =
-            self.lower_left_corner + (u * self.horizontal) + (v * self.vertical) - self.origin;
+impl From<Heightfield> for Object {
+    fn from(heightfield: Heightfield) -> Self {
+        Object::Heightfield(Arc::new(heightfield))
+    }
+}
 
-        Ray { orig: self.origin, dir }
+impl From<Instance> for Object {
+    fn from(instance: Instance) -> Self {
+        Object::Instance(instance)
     }
 }
 
-/// Set up a scene a render an image.
-///
-/// # Arguments
-/// - `width` - Output image width
-/// - `height` - Output image height
-/// - `max_depth` - Maximum number of ray bounces after a hit.
-/// - `samples_per_pixel` - How many random rays to generate and average to compute final pixel color.
-pub fn render(
-    width: usize, height: usize, max_depth: usize, samples_per_pixel: usize, position: &Point,
-) -> ImageRGBA {
-    let aspect_ratio = width as f32 / height as f32;
+impl From<ConstantMedium> for Object {
+    fn from(medium: ConstantMedium) -> Self {
+        Object::Medium(medium)
+    }
+}
 
-    let mut im = ImageRGBA::new(width, height);
-    let materials: Vec<Box<dyn Material>> = vec![
-        Box::new(Lambertian { albedo: Color { x: 0.8, y: 0.8, z: 0.0 } }),
-        Box::new(Lambertian { albedo: Color { x: 0.7, y: 0.3, z: 0.3 } }),
-        Box::new(Metal { albedo: Color { x: 0.8, y: 0.8, z: 0.8 }, fuzz: 0.3 }),
-        Box::new(Metal { albedo: Color { x: 0.8, y: 0.6, z: 0.2 }, fuzz: 1.0 }),
-        Box::new(Dieletric { refraction_index: 1.5 }),
-        Box::new(Dieletric { refraction_index: 1.5 }),
-    ];
-
-    let lambertian_green_index = 0;
-    let lambertian_pink_index = 1;
-    let metal_shiny_index = 2;
-    let metal_fuzzy_index = 3;
-    let dielectric_index = 4;
-    let dielectric2_index = 5;
-
-    // world
-    let mut world = HittableList::new();
-    // center sphere
-    world.add(&Sphere {
-        center: Point { x: 0.0, y: 0.0, z: -1.0 },
-        radius: 0.5,
-        material_id: dielectric_index,
-    });
-    // left sphere
-    world.add(&Sphere {
-        center: Point { x: -1.0, y: 0.0, z: -1.0 },
-        radius: 0.5,
-        material_id: metal_shiny_index,
-    });
-    // right sphere
-    world.add(&Sphere {
-        center: Point { x: 1.0, y: 0.0, z: -1.0 },
-        radius: 0.5,
-        material_id: lambertian_pink_index,
-    });
-    // ground sphere
-    world.add(&Sphere {
-        center: Point { x: 0.0, y: -100.5, z: -1.0 },
-        radius: 100.0,
-        material_id: lambertian_green_index,
-    });
-
-    let cam = Camera::new(
-        *position,
-        Vec3::new(0.0, 0.0, -1.0),
-        Vec3::new(0.0, 1.0, 0.0),
-        90.0,
-        aspect_ratio,
-    );
-    let mut rng = rand::thread_rng();
-    println!("--- Starting render");
-
-    for j in (0..im.height).rev() {
-        print!("\rScanlines remaining {j}");
-
-        for i in 0..im.width {
-            // println!("=========== BEGIN rendering pixel at [{i}, {j}]");
-            let mut pixel_color = Color::BLACK;
-
-            for _ in 0..samples_per_pixel {
-                let u = (i as f32 + rng.gen::<f32>()) / (im.width as f32 - 1.0);
-                let v = (j as f32 + rng.gen::<f32>()) / (im.height as f32 - 1.0);
-
-                let ray = cam.get_ray(u, v);
-                pixel_color += ray_color_2(&ray, &world, max_depth, &materials);
-            }
-            pixel_color /= samples_per_pixel as f32;
+impl Object {
+    /// Kind of object, for display.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Object::Sphere(_) => "sphere",
+            Object::MovingSphere(_) => "moving sphere",
+            Object::Plane(_) => "plane",
+            Object::Triangle(_) => "triangle",
+            Object::Mesh(_) => "mesh",
+            Object::XyRect(_) => "xy rect",
+            Object::XzRect(_) => "xz rect",
+            Object::YzRect(_) => "yz rect",
+            Object::Cuboid(_) => "box",
+            Object::Cylinder(_) => "cylinder",
+            Object::Cone(_) => "cone",
+            Object::Capsule(_) => "capsule",
+            Object::Torus(_) => "torus",
+            Object::Sdf(_) => "sdf",
+            Object::Heightfield(_) => "heightfield",
+            Object::Instance(_) => "instance",
+            Object::Medium(_) => "constant medium",
+        }
+    }
 
-            // color correcrt for gamma=2.0
-            let pixel_color_corrected =
-                Vec3 { x: pixel_color.x.sqrt(), y: pixel_color.y.sqrt(), z: pixel_color.z.sqrt() };
+    /// Handle of the material of the object in the scene.
+    pub fn material_id(&self) -> MaterialId {
+        match self {
+            Object::Sphere(sphere) => sphere.material_id,
+            Object::MovingSphere(sphere) => sphere.material_id,
+            Object::Plane(plane) => plane.material_id,
+            Object::Triangle(triangle) => triangle.material_id,
+            Object::Mesh(mesh) => mesh.material_id,
+            Object::XyRect(rect) => rect.material_id,
+            Object::XzRect(rect) => rect.material_id,
+            Object::YzRect(rect) => rect.material_id,
+            Object::Cuboid(cuboid) => cuboid.material_id,
+            Object::Cylinder(cylinder) => cylinder.material_id,
+            Object::Cone(cone) => cone.material_id,
+            Object::Capsule(capsule) => capsule.material_id,
+            Object::Torus(torus) => torus.material_id,
+            Object::Sdf(object) => object.material_id,
+            Object::Heightfield(heightfield) => heightfield.material_id,
+            Object::Instance(instance) => instance.material_id,
+            Object::Medium(medium) => medium.material_id,
+        }
+    }
 
-            let ir = (clamp(pixel_color_corrected.x, 0.0, 0.999) * 256.0) as u8;
-            let ig = (clamp(pixel_color_corrected.y, 0.0, 0.999) * 256.0) as u8;
-            let ib = (clamp(pixel_color_corrected.z, 0.0, 0.999) * 256.0) as u8;
+    pub fn material_id_mut(&mut self) -> &mut MaterialId {
+        match self {
+            Object::Sphere(sphere) => &mut sphere.material_id,
+            Object::MovingSphere(sphere) => &mut sphere.material_id,
+            Object::Plane(plane) => &mut plane.material_id,
+            Object::Triangle(triangle) => &mut triangle.material_id,
+            // The mesh is copied first if shared.
+            Object::Mesh(mesh) => &mut Arc::make_mut(mesh).material_id,
+            Object::XyRect(rect) => &mut rect.material_id,
+            Object::XzRect(rect) => &mut rect.material_id,
+            Object::YzRect(rect) => &mut rect.material_id,
+            Object::Cuboid(cuboid) => &mut cuboid.material_id,
+            Object::Cylinder(cylinder) => &mut cylinder.material_id,
+            Object::Cone(cone) => &mut cone.material_id,
+            Object::Capsule(capsule) => &mut capsule.material_id,
+            Object::Torus(torus) => &mut torus.material_id,
+            Object::Sdf(object) => &mut object.material_id,
+            Object::Heightfield(heightfield) => &mut Arc::make_mut(heightfield).material_id,
+            Object::Instance(instance) => &mut instance.material_id,
+            Object::Medium(medium) => &mut medium.material_id,
+        }
+    }
 
-            // println!("=========== DONE  rendering pixel at [{i}, {j}]");
+    pub fn as_sphere(&self) -> Option<&Sphere> {
+        match self {
+            Object::Sphere(sphere) => Some(sphere),
+            _ => None,
+        }
+    }
 
-            im.put(i, j, ir, ig, ib, 255);
+    pub fn as_sphere_mut(&mut self) -> Option<&mut Sphere> {
+        match self {
+            Object::Sphere(sphere) => Some(sphere),
+            _ => None,
         }
     }
-    im
+
+    /// Whether directions towards the object can be sampled, to sample the light it emits.
+    pub(crate) fn can_be_sampled(&self) -> bool {
+        matches!(
+            self,
+            Object::Sphere(_) | Object::XyRect(_) | Object::XzRect(_) | Object::YzRect(_)
+        )
+    }
 }
 
-/// Interpolate positions to make a trajectory.
-fn interpolate(points: &Vec<Point>, factor: u32) -> Vec<Point> {
-    let mut out: Vec<Point> = Vec::new();
+impl Hittable for Object {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        match self {
+            Object::Sphere(sphere) => sphere.hit(r, ray_t, rec),
+            Object::MovingSphere(sphere) => sphere.hit(r, ray_t, rec),
+            Object::Plane(plane) => plane.hit(r, ray_t, rec),
+            Object::Triangle(triangle) => triangle.hit(r, ray_t, rec),
+            Object::Mesh(mesh) => mesh.hit(r, ray_t, rec),
+            Object::XyRect(rect) => rect.hit(r, ray_t, rec),
+            Object::XzRect(rect) => rect.hit(r, ray_t, rec),
+            Object::YzRect(rect) => rect.hit(r, ray_t, rec),
+            Object::Cuboid(cuboid) => cuboid.hit(r, ray_t, rec),
+            Object::Cylinder(cylinder) => cylinder.hit(r, ray_t, rec),
+            Object::Cone(cone) => cone.hit(r, ray_t, rec),
+            Object::Capsule(capsule) => capsule.hit(r, ray_t, rec),
+            Object::Torus(torus) => torus.hit(r, ray_t, rec),
+            Object::Sdf(object) => object.hit(r, ray_t, rec),
+            Object::Heightfield(heightfield) => heightfield.hit(r, ray_t, rec),
+            Object::Instance(instance) => instance.hit(r, ray_t, rec),
+            Object::Medium(medium) => medium.hit(r, ray_t, rec),
+        }
+    }
 
-    let count = points.len();
+    fn bounding_box(&self) -> Aabb {
+        match self {
+            Object::Sphere(sphere) => sphere.bounding_box(),
+            Object::MovingSphere(sphere) => sphere.bounding_box(),
+            Object::Plane(plane) => plane.bounding_box(),
+            Object::Triangle(triangle) => triangle.bounding_box(),
+            Object::Mesh(mesh) => mesh.bounding_box(),
+            Object::XyRect(rect) => rect.bounding_box(),
+            Object::XzRect(rect) => rect.bounding_box(),
+            Object::YzRect(rect) => rect.bounding_box(),
+            Object::Cuboid(cuboid) => cuboid.bounding_box(),
+            Object::Cylinder(cylinder) => cylinder.bounding_box(),
+            Object::Cone(cone) => cone.bounding_box(),
+            Object::Capsule(capsule) => capsule.bounding_box(),
+            Object::Torus(torus) => torus.bounding_box(),
+            Object::Sdf(object) => object.bounding_box(),
+            Object::Heightfield(heightfield) => heightfield.bounding_box(),
+            Object::Instance(instance) => instance.bounding_box(),
+            Object::Medium(medium) => medium.bounding_box(),
+        }
+    }
 
-    for i in 1..count {
-        let v = points[i - 1];
-        let w = points[i];
-        out.push(v);
-        let step = 1.0 / factor as f32;
-        for i in 1..factor {
-            let p = lerp(&v, &w, step * (i as f32));
-            out.push(p);
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f32 {
+        match self {
+            Object::Sphere(sphere) => sphere.pdf_value(origin, direction),
+            Object::XyRect(rect) => rect.pdf_value(origin, direction),
+            Object::XzRect(rect) => rect.pdf_value(origin, direction),
+            Object::YzRect(rect) => rect.pdf_value(origin, direction),
+            _ => 0.0,
         }
-        out.push(w);
     }
 
-    out
+    fn random(&self, origin: &Point, rng: &mut Sampler) -> Vec3 {
+        match self {
+            Object::Sphere(sphere) => sphere.random(origin, rng),
+            Object::XyRect(rect) => rect.random(origin, rng),
+            Object::XzRect(rect) => rect.random(origin, rng),
+            Object::YzRect(rect) => rect.random(origin, rng),
+            _ => Vec3::UNIT_X,
+        }
+    }
 }
 
-#[cfg(test)]
-pub(crate) mod test {
-    use crate::geometry::{Point, Vec3};
-    use crate::image::ImageRGBA;
-    use crate::ray::Ray;
-    use crate::render::{interpolate, render, HitRecord};
+/// Collection of object that can be hit by a ray.
+#[derive(Clone)]
+pub struct HittableList {
+    objects: Vec<Object>,
+}
 
-    #[test]
-    fn test_hitrecord() {
-        let mut rec = HitRecord::new();
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        let r = Ray { orig: Point::ZERO, dir: -Vec3::UNIT_Z };
+impl HittableList {
+    pub fn new() -> Self {
+        HittableList { objects: Vec::new() }
+    }
 
-        rec.set_face_normal(&r, &Vec3::UNIT_X);
+    pub fn clear(&mut self) {
+        self.objects.clear()
     }
 
-    #[test]
-    fn test_nominal_render() {
-        let pos = Point::new(-2.0, 2.0, 1.0);
-        let im = render(16, 9, 5, 1, &pos);
-        let default_img = ImageRGBA::new(16, 9);
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
 
-        assert_eq!(im.width, 16);
-        assert_eq!(im.height, 9);
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// The objects in the list.
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    pub fn objects_mut(&mut self) -> &mut [Object] {
+        &mut self.objects
+    }
+
+    /// Add an object to the list. Objects are added to a scene with [`Scene::add`].
+    ///
+    /// # Arguments
+    /// - `object` - The object to add, a [`Sphere`], a [`Plane`] or an [`Object`].
+    pub(crate) fn add(&mut self, object: impl Into<Object>) {
+        self.objects.push(object.into());
+    }
+
+    /// Remove and return the object at `index`.
+    ///
+    /// # Panics
+    /// If `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Object {
+        self.objects.remove(index)
+    }
+
+    /// Process a single ray cast.
+    ///
+    /// # Arguments
+    /// - `r` - The ray.
+    /// - `ray_t` - Distances along the ray for which the ray cast is considered a valid hit.
+    /// - `rec` - Keep track of the hit properties.
+    pub fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut temp_rec = HitRecord::new();
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+
+        for (id, each) in self.objects.iter().enumerate() {
+            if each.hit(r, Interval::new(ray_t.min, closest_so_far), &mut temp_rec) {
+                temp_rec.object_id = id;
+                hit_anything = true;
+                closest_so_far = temp_rec.t;
+                *rec = temp_rec;
+            }
+        }
+
+        hit_anything
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        HittableList::hit(self, r, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let boxes = self.objects.iter().map(|o| o.bounding_box());
+        boxes.fold(Aabb::EMPTY, |bbox, b| bbox.surrounding(&b))
+    }
+
+    /// Average density of the objects, which are picked evenly by [`Hittable::random`].
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f32 {
+        let sum: f32 = self.objects.iter().map(|o| o.pdf_value(origin, direction)).sum();
+        sum / self.objects.len().max(1) as f32
+    }
+
+    fn random(&self, origin: &Point, rng: &mut Sampler) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::UNIT_X;
+        }
+        let index = rng.gen_range(0..self.objects.len());
+        self.objects[index].random(origin, rng)
+    }
+}
+
+/// How far the paths bounce.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct PathDepth {
+    /// Maximum number of ray bounces after a hit.
+    pub(crate) max: usize,
+    /// Number of bounces after which the paths are stopped at random, never when `None`, see
+    /// [`RenderSettings::russian_roulette`].
+    pub(crate) roulette: Option<usize>,
+}
+
+impl PathDepth {
+    /// At most `max` bounces, without Russian roulette.
+    pub(crate) fn new(max: usize) -> Self {
+        PathDepth { max, roulette: None }
+    }
+
+    /// Whether a path goes on after `bounces` bounces, with the product of the attenuations
+    /// along it so far.
+    ///
+    /// Once the roulette starts, a path goes on with a probability of its brightest
+    /// attenuation, and the light it then brings back is divided by that probability, which
+    /// keeps the average unchanged.
+    pub(crate) fn survives(
+        &self, bounces: usize, throughput: &mut Color, rng: &mut Sampler,
+    ) -> bool {
+        if self.roulette.is_none_or(|start| bounces < start) {
+            return true;
+        }
+        let p = throughput.x.max(throughput.y).max(throughput.z).clamp(0.05, 1.0);
+        if rng.gen::<f32>() >= p {
+            return false;
+        }
+        *throughput /= p;
+        true
+    }
+}
+
+/// Cast a single ray in the scene and return the computed pixel color.
+///
+/// The ray is followed from hit to hit, as long as each hit produces a scattered ray, until
+/// `depth.max` bounces. The light emitted by the materials hit, and the light of the point and
+/// spot lights they reflect, is added on the way, through the attenuations of the hits before.
+///
+/// If no object is hit, we just return the background color of the scene in the ray
+/// direction.
+///
+/// # Arguments
+/// - `r` - The ray.
+/// - `world` - The list of object we can hit.
+/// - `depth` - How far the ray bounces.
+/// - `scene` - The scene, for its materials and background.
+/// - `rng` - Random numbers of the scattering.
+fn ray_color_2(r: &Ray, world: &Bvh, depth: PathDepth, scene: &Scene, rng: &mut Sampler) -> Color {
+    trace_ray(r, world, depth, scene, rng, &mut |_| {})
+}
+
+/// What happened to a ray at one step of its path.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bounce {
+    /// The ray hit an object.
+    Hit {
+        /// Remaining amount of ray bounces.
+        depth: usize,
+        point: Point,
+        /// Normal facing the incoming ray.
+        normal: Vec3,
+        /// Distance from the ray origin.
+        distance: f32,
+        object_id: usize,
+        material_id: MaterialId,
+        front_face: bool,
+        /// Light emitted by the material.
+        emitted: Color,
+        /// Light of the point and spot lights reflected by the material.
+        direct: Color,
+        /// Color factor applied by the material, divided by the density of the scattered
+        /// direction when it was importance sampled.
+        attenuation: Color,
+        /// Direction of the scattered ray, `None` when the material absorbed the ray.
+        scattered: Option<Vec3>,
+    },
+    /// The ray left the scene.
+    Sky { depth: usize, color: Color },
+    /// The bounce limit was reached.
+    DepthLimit,
+    /// The path was stopped at random by the Russian roulette.
+    Roulette,
+}
+
+/// Same as [`ray_color_2`], calling `on_bounce` at each step of the path.
+fn trace_ray<F: FnMut(Bounce)>(
+    r: &Ray, world: &Bvh, depth: PathDepth, scene: &Scene, rng: &mut Sampler, on_bounce: &mut F,
+) -> Color {
+    let mut color = Color::BLACK;
+    // Product of the attenuations along the path so far.
+    let mut throughput = Color::WHITE;
+    let mut ray = Ray { orig: r.orig, dir: r.dir, time: r.time, wavelength: r.wavelength };
+
+    for bounces in 0..depth.max {
+        let remaining = depth.max - bounces;
+        let mut rec = HitRecord::new();
+        if !world.hit(&ray, Interval::new(0.001, f32::INFINITY), &mut rec) {
+            let sky = scene.sky(&ray.dir);
+            on_bounce(Bounce::Sky { depth: remaining, color: sky });
+            return color + throughput * sky;
+        }
+
+        let material = scene.materials.get(rec.material_id);
+        let emitted = material.emitted(rec.u, rec.v, &rec.p);
+        let scatter = scatter_towards_lights(material, &ray, &rec, world, rng);
+        on_bounce(Bounce::Hit {
+            depth: remaining,
+            point: rec.p,
+            normal: rec.normal,
+            distance: rec.t * ray.dir.len(),
+            object_id: rec.object_id,
+            material_id: rec.material_id,
+            front_face: rec.front_face,
+            emitted,
+            direct: scatter.direct,
+            attenuation: scatter
+                .next
+                .as_ref()
+                .map_or(Color::BLACK, |(attenuation, _)| *attenuation),
+            scattered: scatter.next.as_ref().map(|(_, scattered)| scattered.dir),
+        });
+
+        color += throughput * (emitted + scatter.direct);
+        let Some((attenuation, scattered)) = scatter.next else {
+            return color;
+        };
+        throughput = throughput * attenuation;
+        ray = scattered;
+        if !depth.survives(bounces + 1, &mut throughput, rng) {
+            on_bounce(Bounce::Roulette);
+            return color;
+        }
+    }
+
+    on_bounce(Bounce::DepthLimit);
+    color
+}
+
+/// A ray scattered off a hit, see [`scatter_towards_lights`].
+pub(crate) struct Scattered {
+    /// Light of the point and spot lights reflected along the incoming ray.
+    pub(crate) direct: Color,
+    /// The color factor of the scattered ray, with the density of its direction divided out,
+    /// and the ray. `None` when the material absorbed the incoming ray.
+    pub(crate) next: Option<(Color, Ray)>,
+}
+
+/// Scatter a ray off a hit. Diffuse materials send half of their rays towards the lights, the
+/// sun and the environment map, and reflect the light of the point and spot lights, see
+/// [`direct_lighting`].
+///
+/// # Arguments
+/// - `material` - The material hit.
+/// - `r` - The incoming ray.
+/// - `rec` - The hit.
+/// - `world` - The scene, with the lights to sample, see [`Scene::bvh`].
+/// - `rng` - Random numbers of the scattering.
+pub(crate) fn scatter_towards_lights(
+    material: &dyn Material, r: &Ray, rec: &HitRecord, world: &Bvh, rng: &mut Sampler,
+) -> Scattered {
+    let Some(ScatterResult { attenuation, scattered, pdf }) =
+        material.scatter_through(r, rec, world, rng)
+    else {
+        return Scattered { direct: Color::BLACK, next: None };
+    };
+    let Some(pdf) = pdf else {
+        return Scattered { direct: Color::BLACK, next: Some((attenuation, scattered)) };
+    };
+    let direct = direct_lighting(material, r, rec, attenuation, world);
+    let lights = world.lights();
+    let to_lights = HittablePdf::new(lights, rec.p);
+    let to_sun = world.sun().map(DirectionalLight::pdf);
+    let mut emitters: Vec<&dyn Pdf> = Vec::new();
+    if !lights.is_empty() {
+        emitters.push(&to_lights);
+    }
+    if let Some(to_sun) = &to_sun {
+        emitters.push(to_sun);
+    }
+    if let Some(environment) = world.environment() {
+        emitters.push(environment);
+    }
+    let to_emitters = MixturePdf::even(emitters);
+    let mixture;
+    // The scattered ray of the material is the sample of its own distribution.
+    let (dir, pdf): (Vec3, &dyn Pdf) = match &to_emitters {
+        None => (scattered.dir, &pdf),
+        Some(to_emitters) => {
+            mixture = MixturePdf::new(to_emitters, &pdf);
+            if rng.gen_range(0..2) == 0 {
+                (to_emitters.generate(rng), &mixture)
+            } else {
+                (scattered.dir, &mixture)
+            }
+        }
+    };
+    let density = pdf.value(&dir);
+    // A direction the distribution cannot produce, as grazing the lights.
+    if density.is_nan() || density <= 0.0 {
+        return Scattered { direct, next: None };
+    }
+    let scattered = Ray { orig: rec.p, dir, time: r.time, wavelength: r.wavelength };
+    let weight = material.scattering_pdf(r, rec, &scattered) / density;
+    Scattered { direct, next: Some((weight * attenuation, scattered)) }
+}
+
+/// Light of the point and spot lights of `world` reflected by a diffuse hit along the incoming
+/// ray, through shadow rays: the lights hidden behind objects add nothing.
+///
+/// # Arguments
+/// - `attenuation` - Color factor of the material at the hit.
+fn direct_lighting(
+    material: &dyn Material, r: &Ray, rec: &HitRecord, attenuation: Color, world: &Bvh,
+) -> Color {
+    let mut direct = Color::BLACK;
+    for light in world.punctual_lights() {
+        let position = light.position();
+        let dir = position - rec.p;
+        let towards = Ray { orig: rec.p, dir, time: r.time, wavelength: r.wavelength };
+        // The share of the light reflected along the incoming ray, the cosine included.
+        let reflected = material.scattering_pdf(r, rec, &towards);
+        if reflected <= 0.0 || world.occluded(&rec.p, &position, r.time) {
+            continue;
+        }
+        direct += attenuation * light.irradiance(&rec.p) * reflected;
+    }
+    direct
+}
+
+/// Represent a camera.
+pub(crate) struct Camera {
+    origin: Point,
+    lower_left_corner: Point,
+    horizontal: Vec3,
+    vertical: Vec3,
+    /// Image plane axes, to offset the ray origins on the lens, and backwards direction.
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    /// Up direction of the image, the axis of the panoramas.
+    up: Vec3,
+    lens_radius: f32,
+    focus_dist: f32,
+    /// Vertical field of view, in radians, and image width over height, for the fisheye.
+    fov: f32,
+    aspect_ratio: f32,
+    projection: CameraProjection,
+    /// Shutter open and close times, the rays are cast in between.
+    time0: f32,
+    time1: f32,
+}
+
+impl Camera {
+    /// # Arguments
+    /// - `lookfrom` - Camera position.
+    /// - `lookat` - Point the camera looks at.
+    /// - `vup` - Up direction of the image.
+    /// - `vfov` - Vertical field of view, in degrees.
+    /// - `aspect_ratio` - Image width over height.
+    /// - `aperture` - Lens diameter, 0 for a pinhole camera with everything in focus.
+    /// - `focus_dist` - Distance from the camera to the plane in focus.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        lookfrom: Point, lookat: Point, vup: Vec3, vfov: f32, aspect_ratio: f32, aperture: f32,
+        focus_dist: f32,
+    ) -> Self {
+        let theta = deg2rad(vfov);
+        let h = (theta / 2.0).tan();
+
+        let vp_height = 2.0 * h;
+        let vp_width = aspect_ratio * vp_height;
+
+        let w = (lookfrom - lookat).normed();
+        let u = (vup.cross(&w)).normed();
+        let v = w.cross(&u);
+
+        // The viewport sits in the plane in focus, so rays from anywhere on the lens meet there.
+        let origin = lookfrom;
+        let horizontal = focus_dist * vp_width * u;
+        let vertical = focus_dist * vp_height * v;
+        let lower_left_corner = origin - (horizontal / 2.0) - (vertical / 2.0) - focus_dist * w;
+
+        Camera {
+            origin,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u,
+            v,
+            w,
+            up: vup.normed(),
+            lens_radius: aperture / 2.0,
+            focus_dist,
+            fov: theta,
+            aspect_ratio,
+            projection: CameraProjection::Perspective,
+            time0: 0.0,
+            time1: 0.0,
+        }
+    }
+
+    /// The same camera, casting its rays at random times between `time0` and `time1`.
+    pub fn with_shutter(self, time0: f32, time1: f32) -> Self {
+        Camera { time0, time1, ..self }
+    }
+
+    /// The same camera, mapping the rays to the image with `projection`.
+    pub fn with_projection(self, projection: CameraProjection) -> Self {
+        Camera { projection, ..self }
+    }
+
+    /// Generate a ray from the camera lens to the given pixel coordinates.
+    /// The coordinates are normalized between 0 and 1.
+    /// (0, 0) is the lower left corner, (1, 1) is the upper right corner.
+    /// # Arguments
+    /// - `s` - Horizontal coordinate
+    /// - `t` - Vertical coordinate
+    /// - `rng` - Random numbers of the lens and the shutter.
+    /// # Returns
+    /// A ray from a random point of the lens to the point in focus seen at the given pixel
+    /// coordinates, from the center of the lens for a pinhole camera, at a random time while
+    /// the shutter is open. Panoramas are always seen through a pinhole.
+    pub fn get_ray(&self, s: f32, t: f32, rng: &mut Sampler) -> Ray {
+        let panorama = self.projection == CameraProjection::Equirectangular;
+        let offset = if self.lens_radius > 0.0 && !panorama {
+            let rd = self.lens_radius * random_in_unit_disk(rng);
+            rd.x * self.u + rd.y * self.v
+        } else {
+            Vec3::ZERO
+        };
+        let (center, focus) = match self.projection {
+            CameraProjection::Perspective => {
+                (self.origin, self.lower_left_corner + (s * self.horizontal) + (t * self.vertical))
+            }
+            CameraProjection::Orthographic => {
+                // Each ray starts in the plane of the camera, straight behind the point it sees.
+                let focus = self.lower_left_corner + (s * self.horizontal) + (t * self.vertical);
+                (focus + self.focus_dist * self.w, focus)
+            }
+            CameraProjection::Fisheye => {
+                // Distance to the image center in image heights, the top edge seeing half the
+                // field of view away from the axis.
+                let (x, y) = ((s - 0.5) * self.aspect_ratio, t - 0.5);
+                let angle = (x * x + y * y).sqrt() * self.fov;
+                let around = y.atan2(x);
+                let dir = angle.sin() * (around.cos() * self.u + around.sin() * self.v)
+                    - angle.cos() * self.w;
+                (self.origin, self.origin + self.focus_dist * dir)
+            }
+            CameraProjection::Equirectangular => {
+                // Longitude from the horizontal view direction, turning right, and latitude.
+                let (longitude, latitude) = (2.0 * PI * (s - 0.5), PI * (t - 0.5));
+                let forward = self.up.cross(&self.u);
+                let around = longitude.cos() * forward + longitude.sin() * self.u;
+                let dir = latitude.cos() * around + latitude.sin() * self.up;
+                (self.origin, self.origin + self.focus_dist * dir)
+            }
+        };
+        let orig = center + offset;
+        let dir = focus - orig;
+        let time = if self.time1 > self.time0 {
+            rng.gen_range(self.time0..self.time1)
+        } else {
+            self.time0
+        };
+
+        Ray { orig, dir, time, wavelength: None }
+    }
+}
+
+/// What the rays leaving the scene bring back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    /// A sky blending from `horizon`, looking down, to `zenith`, looking up.
+    Gradient {
+        horizon: Color,
+        zenith: Color,
+    },
+    Solid(Color),
+    /// A clear daylight sky, its colors depending on the position of the sun.
+    Physical(PhysicalSky),
+    /// A panorama around the scene, also sampled as a light.
+    Environment(EnvironmentMap),
+}
+
+impl Default for Background {
+    /// The sky of the demo scene, white to light blue.
+    fn default() -> Self {
+        Background::Gradient { horizon: Color::WHITE, zenith: Color::new(0.5, 0.7, 1.0) }
+    }
+}
+
+impl Background {
+    /// Color of the background in a direction.
+    pub fn color(&self, dir: &Vec3) -> Color {
+        match self {
+            Background::Gradient { horizon, zenith } => {
+                let t = 0.5 * (dir.normed().y + 1.0);
+                lerp(horizon, zenith, t)
+            }
+            Background::Solid(color) => *color,
+            Background::Physical(sky) => sky.radiance(dir),
+            Background::Environment(map) => map.radiance(dir),
+        }
+    }
+}
+
+/// Objects, materials, camera, background and lights making up the world to render.
+///
+/// Copies are cheap: the materials are shared.
+#[derive(Clone)]
+pub struct Scene {
+    world: HittableList,
+    materials: MaterialRegistry,
+    /// The camera, see [`render`].
+    view: OrbitView,
+    lens: Lens,
+    /// Shutter open and close times of the camera.
+    shutter: (f32, f32),
+    background: Background,
+    /// Distant light, as the sun, in front of the background.
+    sun: Option<DirectionalLight>,
+    /// Point and spot lights, lit through shadow rays.
+    punctual_lights: Vec<PunctualLight>,
+    /// Groups of objects replaced by a proxy when seen from far away.
+    proxies: Vec<ProxyGroup>,
+    /// Angular size below which the groups are replaced by their proxy, in radians.
+    lod_threshold: f32,
+}
+
+impl Scene {
+    /// The demo scene: a glass, a metal and a diffuse sphere standing on a huge ground sphere.
+    pub fn demo() -> Self {
+        let mut materials = MaterialRegistry::new();
+        let mut add = |name: &str, params: MaterialParams| {
+            materials.add_named(name, &params).expect("the demo materials have unique names")
+        };
+        let green = Color { x: 0.8, y: 0.8, z: 0.0 };
+        let lambertian_green_index =
+            add("ground", MaterialParams::Lambertian { albedo: green.into() });
+        let pink = Color { x: 0.7, y: 0.3, z: 0.3 };
+        let lambertian_pink_index = add("pink", MaterialParams::Lambertian { albedo: pink.into() });
+        let shiny = MaterialParams::Metal {
+            albedo: Color { x: 0.8, y: 0.8, z: 0.8 },
+            fuzz: 0.3,
+            film: None,
+        };
+        let metal_shiny_index = add("shiny metal", shiny);
+        let fuzzy = MaterialParams::Metal {
+            albedo: Color { x: 0.8, y: 0.6, z: 0.2 },
+            fuzz: 1.0,
+            film: None,
+        };
+        let _metal_fuzzy_index = add("fuzzy metal", fuzzy);
+        let dielectric_index = add(
+            "glass",
+            MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.0, film: None },
+        );
+        let _dielectric2_index = add(
+            "glass 2",
+            MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.0, film: None },
+        );
+
+        let mut world = HittableList::new();
+        // center sphere
+        world.add(Sphere {
+            center: Point { x: 0.0, y: 0.0, z: -1.0 },
+            radius: 0.5,
+            material_id: dielectric_index,
+        });
+        // left sphere
+        world.add(Sphere {
+            center: Point { x: -1.0, y: 0.0, z: -1.0 },
+            radius: 0.5,
+            material_id: metal_shiny_index,
+        });
+        // right sphere
+        world.add(Sphere {
+            center: Point { x: 1.0, y: 0.0, z: -1.0 },
+            radius: 0.5,
+            material_id: lambertian_pink_index,
+        });
+        // ground sphere
+        world.add(Sphere {
+            center: Point { x: 0.0, y: -100.5, z: -1.0 },
+            radius: 100.0,
+            material_id: lambertian_green_index,
+        });
+
+        Scene {
+            world,
+            materials,
+            view: OrbitView::default(),
+            lens: Lens::default(),
+            shutter: (0.0, 0.0),
+            background: Background::default(),
+            sun: None,
+            punctual_lights: Vec::new(),
+            proxies: Vec::new(),
+            lod_threshold: DEFAULT_LOD_THRESHOLD,
+        }
+    }
+
+    /// A scene without objects nor materials, to fill with [`Scene::add_material`] and
+    /// [`Scene::world_mut`]. It has the camera and background of the demo scene.
+    pub fn empty() -> Self {
+        Scene {
+            world: HittableList::new(),
+            materials: MaterialRegistry::new(),
+            view: OrbitView::default(),
+            lens: Lens::default(),
+            shutter: (0.0, 0.0),
+            background: Background::default(),
+            sun: None,
+            punctual_lights: Vec::new(),
+            proxies: Vec::new(),
+            lod_threshold: DEFAULT_LOD_THRESHOLD,
+        }
+    }
+
+    pub fn world(&self) -> &HittableList {
+        &self.world
+    }
+
+    /// The objects, to edit the scene. Their materials must be materials of this scene.
+    pub fn world_mut(&mut self) -> &mut HittableList {
+        &mut self.world
+    }
+
+    /// Add an object to the scene.
+    ///
+    /// # Arguments
+    /// - `object` - The object to add, a [`Sphere`], a [`Plane`] or an [`Object`].
+    ///
+    /// # Returns
+    /// An error if the material of the object is not a material of this scene.
+    pub fn add(&mut self, object: impl Into<Object>) -> Result<(), RtError> {
+        let object = object.into();
+        let id = object.material_id();
+        if !self.materials.contains(id) {
+            return Err(RtError::Scene(format!(
+                "the {} has material {id}, the scene has {} materials",
+                object.name(),
+                self.materials.len()
+            )));
+        }
+        self.world.add(object);
+        Ok(())
+    }
+
+    pub fn material_count(&self) -> usize {
+        self.materials.len()
+    }
+
+    /// The materials, and their names.
+    pub fn materials(&self) -> &MaterialRegistry {
+        &self.materials
+    }
+
+    /// Kind of the material at `material_id`, for display.
+    pub fn material_name(&self, material_id: MaterialId) -> Option<&'static str> {
+        self.material(material_id).map(|p| p.name())
+    }
+
+    /// Parameters of the material at `material_id`.
+    pub fn material(&self, material_id: MaterialId) -> Option<MaterialParams> {
+        self.materials.params(material_id)
+    }
+
+    /// Replace the material at `material_id`. Copies of the scene keep the previous one.
+    ///
+    /// # Panics
+    /// If `material_id` is a material of another scene.
+    pub fn set_material(&mut self, material_id: MaterialId, params: &MaterialParams) {
+        self.materials.set(material_id, params);
+    }
+
+    /// Add a material with a generated name, see [`MaterialRegistry::add`].
+    pub fn add_material(&mut self, params: &MaterialParams) -> MaterialId {
+        self.materials.add(params)
+    }
+
+    /// Add a material named `name`, or return an error if the name is taken.
+    pub fn add_named_material(
+        &mut self, name: &str, params: &MaterialParams,
+    ) -> Result<MaterialId, MaterialNameError> {
+        self.materials.add_named(name, params)
+    }
+
+    /// Group objects, so that they are replaced by a single sphere when they look tiny.
+    ///
+    /// The proxy sphere encloses the members, and gets a new diffuse material averaging
+    /// theirs. Objects belong to a single group: the first one they were added to.
+    ///
+    /// # Arguments
+    /// - `members` - Indices of the objects to group.
+    ///
+    /// # Returns
+    /// The index of the group, or an error when a member does not exist or is not a sphere.
+    pub fn add_proxy(&mut self, members: &[usize]) -> Result<usize, RtError> {
+        if members.is_empty() {
+            return Err(RtError::Scene("a proxy needs at least one member".to_string()));
+        }
+        let objects = self.world.objects();
+        if let Some(missing) = members.iter().find(|&&i| i >= objects.len()) {
+            return Err(RtError::Scene(format!("no object {missing} to add to a proxy")));
+        }
+        if let Some(other) = members.iter().find(|&&i| objects[i].as_sphere().is_none()) {
+            let msg = format!("object {other} is not a sphere, only spheres have proxies");
+            return Err(RtError::Scene(msg));
+        }
+        let materials: Vec<MaterialParams> = members
+            .iter()
+            .map(|&i| self.materials.get(objects[i].material_id()).params())
+            .collect();
+        let material_id = self.add_material(&averaged_material(&materials));
+        self.proxies.push(ProxyGroup { members: members.to_vec(), material_id });
+        Ok(self.proxies.len() - 1)
+    }
+
+    pub fn proxies(&self) -> &[ProxyGroup] {
+        &self.proxies
+    }
+
+    /// Change the angular size below which the proxies replace their members, in radians,
+    /// see [`crate::lod::DEFAULT_LOD_THRESHOLD`]. 0 always renders the members.
+    pub fn set_lod_threshold(&mut self, threshold: f32) {
+        self.lod_threshold = threshold.max(0.0);
+    }
+
+    /// The camera rendering the scene.
+    pub fn view(&self) -> &OrbitView {
+        &self.view
+    }
+
+    pub fn set_view(&mut self, view: &OrbitView) {
+        self.view = *view;
+    }
+
+    /// The lens of the camera, blurring what is out of focus.
+    pub fn lens(&self) -> &Lens {
+        &self.lens
+    }
+
+    pub fn set_lens(&mut self, lens: &Lens) {
+        self.lens = *lens;
+    }
+
+    /// When the camera shutter opens and closes: the rays are cast at random times in between,
+    /// blurring the moving objects.
+    pub fn shutter(&self) -> (f32, f32) {
+        self.shutter
+    }
+
+    pub fn set_shutter(&mut self, time0: f32, time1: f32) {
+        self.shutter = (time0, time1);
+    }
+
+    /// Camera rendering the scene from `view`, with the lens and shutter of the scene.
+    pub(crate) fn camera(&self, view: &OrbitView, width: usize, height: usize) -> Camera {
+        let (time0, time1) = self.shutter;
+        view.camera(width, height, &self.lens).with_shutter(time0, time1)
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn set_background(&mut self, background: &Background) {
+        self.background = background.clone();
+    }
+
+    pub fn sun(&self) -> Option<&DirectionalLight> {
+        self.sun.as_ref()
+    }
+
+    pub fn set_sun(&mut self, sun: Option<DirectionalLight>) {
+        self.sun = sun;
+    }
+
+    /// Point and spot lights, lighting the diffuse surfaces through shadow rays.
+    pub fn punctual_lights(&self) -> &[PunctualLight] {
+        &self.punctual_lights
+    }
+
+    /// The point and spot lights, to edit the scene.
+    pub fn punctual_lights_mut(&mut self) -> &mut Vec<PunctualLight> {
+        &mut self.punctual_lights
+    }
+
+    /// Light the rays leaving the scene along `dir` bring back: the background, and the sun.
+    pub fn sky(&self, dir: &Vec3) -> Color {
+        let sun = self.sun.map_or(Color::BLACK, |sun| sun.radiance(dir));
+        self.background.color(dir) + sun
+    }
+
+    /// Number of objects made of an emissive material.
+    pub fn light_count(&self) -> usize {
+        self.world
+            .objects()
+            .iter()
+            .filter(|o| self.materials.get(o.material_id()).is_emissive())
+            .count()
+    }
+
+    /// Approximate memory used by the materials, in bytes.
+    pub fn materials_memory_size(&self) -> usize {
+        self.materials.memory_size()
+    }
+
+    /// Follow a ray through the scene, calling `on_bounce` at each step of its path.
+    ///
+    /// # Arguments
+    /// - `world` - The acceleration structure of the scene, see [`Scene::bvh`].
+    /// - `r` - The ray.
+    /// - `max_depth` - Maximum number of ray bounces after a hit.
+    /// - `rng` - Random numbers of the scattering.
+    ///
+    /// # Returns
+    /// The color the ray brings back.
+    pub(crate) fn trace<F: FnMut(Bounce)>(
+        &self, world: &Bvh, r: &Ray, max_depth: usize, rng: &mut Sampler, on_bounce: &mut F,
+    ) -> Color {
+        trace_ray(r, world, PathDepth::new(max_depth), self, rng, on_bounce)
+    }
+
+    /// Build the acceleration structure used to render the scene, with the lights it samples.
+    pub fn bvh(&self) -> Bvh {
+        let bvh = Bvh::with_proxies(self.world.objects(), &self.proxies, self.lod_threshold);
+        let mut lights = HittableList::new();
+        for object in self.world.objects() {
+            if self.materials.get(object.material_id()).is_emissive() && object.can_be_sampled() {
+                lights.add(object.clone());
+            }
+        }
+        bvh.with_lights(lights)
+            .with_sun(self.sun)
+            .with_punctual_lights(self.punctual_lights.clone())
+            .with_environment(match &self.background {
+                Background::Environment(map) => Some(map.clone()),
+                _ => None,
+            })
+    }
+}
+
+/// Render an image of a scene, seen from its camera.
+///
+/// Nothing is printed, [`crate::tiles::render_tiles_hdr`] reports the progress of the tiles.
+///
+/// # Arguments
+/// - `scene` - The scene, see [`Scene::demo`] for an example.
+/// - `settings` - Resolution, samples and the other settings of the render.
+///
+/// # Returns
+/// The image, tone mapped as the settings say, or an error if the threads of the render
+/// cannot be started.
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Result<ImageRGBA, RtError> {
+    Ok(tonemap(&render_hdr(scene, settings)?, &settings.tonemap()))
+}
+
+/// Same as [`render`], returning the linear radiance of each pixel, before tone mapping.
+pub fn render_hdr(scene: &Scene, settings: &RenderSettings) -> Result<ImageRGBF32, RtError> {
+    let (_, radiance) = render_tiles_hdr(scene, settings, |_| ControlFlow::Continue(()))?;
+    Ok(radiance)
+}
+
+/// Same as [`render`], stopping between tiles when `token` is cancelled.
+///
+/// # Returns
+/// The image, black where the render did not reach, and `false` when it was cancelled, or an
+/// error if the threads of the render cannot be started.
+pub fn render_cancellable(
+    scene: &Scene, settings: &RenderSettings, token: &CancellationToken,
+) -> Result<(ImageRGBA, bool), RtError> {
+    let (im, _, complete) =
+        render_tiles_cancellable(scene, settings, token, |_| ControlFlow::Continue(()))?;
+    Ok((im, complete))
+}
+
+/// Same as [`render`], but calls `on_scanline` after each completed scanline.
+///
+/// Scanlines are traced in parallel, and reported in order from the top of the image
+/// (`j = height - 1`) down to `j = 0`.
+///
+/// # Arguments
+/// - `on_scanline` - Called with the partially rendered image and the index of the
+///   scanline that was just completed. Returning `ControlFlow::Break` stops the render,
+///   and the partial image is returned.
+pub fn render_with_progress<F>(
+    scene: &Scene, settings: &RenderSettings, mut on_scanline: F,
+) -> Result<ImageRGBA, RtError>
+where
+    F: FnMut(&ImageRGBA, usize) -> ControlFlow<()>,
+{
+    let (width, height) = (settings.width, settings.height);
+    let mut im = ImageRGBA::new(width, height);
+    let scene = settings.scene(scene);
+    let world = scene.bvh();
+    let cams = Cameras::new(&scene, scene.view(), settings);
+    let seed = settings.seed.unwrap_or_else(rand::random);
+    let pattern = settings.sampler.build(seed);
+    let pool = settings.thread_pool()?;
+
+    let trace = |j: usize| -> Vec<Color> {
+        let mut rng = Sampler::for_row(seed, 0, j);
+        (0..width)
+            .map(|i| {
+                let (cam, i) = cams.at(i);
+                sample_pixel(
+                    i,
+                    j,
+                    cams.width(),
+                    height,
+                    cam,
+                    &world,
+                    &scene,
+                    settings.path_depth(),
+                    settings.samples(),
+                    settings.mode,
+                    pattern.as_ref(),
+                    settings.clamp_radiance,
+                    &mut SampleStats::default(),
+                    &mut rng,
+                )
+            })
+            .collect()
+    };
+
+    // Scanlines are traced a band at a time, one scanline per thread, so that progress is
+    // reported while the render goes.
+    let rows: Vec<usize> = (0..height).rev().collect();
+    for band in rows.chunks(settings.thread_count()) {
+        let trace_band = || band.par_iter().copied().map(trace).collect::<Vec<_>>();
+        let traced = match &pool {
+            Some(pool) => pool.install(trace_band),
+            None => trace_band(),
+        };
+
+        for (&j, row) in band.iter().zip(traced) {
+            for (i, pixel_color) in row.iter().enumerate() {
+                let (ir, ig, ib) = settings.encode(pixel_color);
+                im.put(i, j, ir, ig, ib, 255);
+            }
+            if on_scanline(&im, j).is_break() {
+                return Ok(im);
+            }
+        }
+    }
+    Ok(im)
+}
+
+/// Camera of the demo scene, looking down the -z axis from `position`.
+pub(crate) fn demo_camera(position: &Point, width: usize, height: usize) -> Camera {
+    let aspect_ratio = width as f32 / height as f32;
+    let (target, up) = (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+    Camera::new(*position, target, up, 90.0, aspect_ratio, 0.0, 1.0)
+}
+
+/// Number of samples of a render, and of those [`SampleStats::filter`] changed.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SampleStats {
+    pub samples: u64,
+    /// Samples brighter than the clamp of the settings, dimmed down to it.
+    pub clamped: u64,
+    /// Samples with a NaN or infinite component, left out of their pixel.
+    pub rejected: u64,
+}
+
+impl SampleStats {
+    /// Count a sample, and bring it within the radiance the pixels accumulate.
+    ///
+    /// The rare paths finding a small bright light after a diffuse bounce leave bright
+    /// speckles, the fireflies, that take many samples to average out. Clamping their radiance
+    /// removes them, at the cost of some light.
+    ///
+    /// # Arguments
+    /// - `c` - Linear color of the sample.
+    /// - `clamp` - Largest component of the samples, the brighter ones are scaled down to it,
+    ///   keeping their hue. Unclamped when `None`.
+    ///
+    /// # Returns
+    /// The sample to accumulate, `None` when it is not a number or infinite.
+    pub fn filter(&mut self, c: Color, clamp: Option<f32>) -> Option<Color> {
+        self.samples += 1;
+        if !(c.x.is_finite() && c.y.is_finite() && c.z.is_finite()) {
+            self.rejected += 1;
+            return None;
+        }
+        let brightest = c.x.max(c.y).max(c.z);
+        match clamp {
+            Some(clamp) if brightest > clamp => {
+                self.clamped += 1;
+                Some(c * (clamp / brightest))
+            }
+            _ => Some(c),
+        }
+    }
+}
+
+impl AddAssign for SampleStats {
+    fn add_assign(&mut self, other: SampleStats) {
+        self.samples += other.samples;
+        self.clamped += other.clamped;
+        self.rejected += other.rejected;
+    }
+}
+
+impl fmt::Display for SampleStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (clamped, rejected) = (self.clamped, self.rejected);
+        write!(f, "{clamped} clamped and {rejected} rejected of {} samples", self.samples)
+    }
+}
+
+/// Average linear color of `samples_per_pixel` random rays through pixel `(i, j)`.
+///
+/// # Arguments
+/// - `mode` - What the pixel shows, the light of the scene or a diagnostic pass.
+/// - `pattern` - How the samples are spread over the pixel, the lens, the shutter time and the
+///   first bounces.
+/// - `clamp` - Largest component of the samples, see [`SampleStats::filter`].
+/// - `stats` - Counts the samples of the pixel.
+///
+/// # Returns
+/// The average of the samples kept by [`SampleStats::filter`], black if there is none.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sample_pixel(
+    i: usize, j: usize, width: usize, height: usize, cam: &Camera, world: &Bvh, scene: &Scene,
+    depth: PathDepth, samples_per_pixel: usize, mode: RenderMode, pattern: &dyn PixelSampler,
+    clamp: Option<f32>, stats: &mut SampleStats, rng: &mut Sampler,
+) -> Color {
+    let (mut pixel_color, mut kept) = (Color::BLACK, 0);
+
+    for index in 0..samples_per_pixel {
+        rng.start_sample(pattern, (i, j), index, samples_per_pixel);
+        let u = (i as f32 + rng.gen::<f32>()) / (width as f32 - 1.0);
+        let v = (j as f32 + rng.gen::<f32>()) / (height as f32 - 1.0);
+
+        let mut ray = cam.get_ray(u, v, rng);
+        let c = match mode {
+            RenderMode::PathTraced => ray_color_2(&ray, world, depth, scene, rng),
+            RenderMode::Spectral => {
+                // The wavelengths are stratified over the samples of the pixel.
+                let wavelength =
+                    wavelength_at((index as f32 + rng.gen::<f32>()) / samples_per_pixel as f32);
+                ray.wavelength = Some(wavelength);
+                ray_color_2(&ray, world, depth, scene, rng) * wavelength_rgb(wavelength)
+            }
+            _ => diagnostic_color(mode, &ray, world, scene, rng),
+        };
+        if let Some(c) = stats.filter(c, clamp) {
+            pixel_color += c;
+            kept += 1;
+        }
+    }
+    rng.end_sample();
+    pixel_color / kept.max(1) as f32
+}
+
+/// Color of a camera ray in a diagnostic mode, from the first surface it hits.
+///
+/// # Arguments
+/// - `mode` - The diagnostic pass, see [`RenderMode`].
+/// - `r` - The camera ray.
+/// - `world` - The list of object we can hit.
+/// - `scene` - The scene, for its materials, background and view.
+/// - `rng` - Random numbers of the occlusion rays and of the materials.
+fn diagnostic_color(
+    mode: RenderMode, r: &Ray, world: &Bvh, scene: &Scene, rng: &mut Sampler,
+) -> Color {
+    let mut rec = HitRecord::new();
+    if !world.hit(r, Interval::new(0.001, f32::INFINITY), &mut rec) {
+        return match mode {
+            RenderMode::AmbientOcclusion => Color::WHITE,
+            RenderMode::Albedo => scene.background.color(&r.dir),
+            _ => Color::BLACK,
+        };
+    }
+
+    match mode {
+        RenderMode::PathTraced | RenderMode::Spectral => {
+            unreachable!("the path traced modes are not diagnostic passes")
+        }
+        RenderMode::Normals => {
+            let outward = if rec.front_face { rec.normal } else { -rec.normal };
+            (outward + Color::WHITE) * 0.5
+        }
+        RenderMode::Depth => {
+            let target = scene.view.distance;
+            let gray = target / (target + rec.t * r.dir.len());
+            Color::new(gray, gray, gray)
+        }
+        RenderMode::AmbientOcclusion => {
+            // One cosine weighted ray towards the sky per sample.
+            let dir = CosinePdf::new(&rec.normal).generate(rng);
+            let ray = Ray { orig: rec.p, dir, time: r.time, wavelength: r.wavelength };
+            if world.hit(&ray, Interval::new(0.001, f32::INFINITY), &mut HitRecord::new()) {
+                Color::BLACK
+            } else {
+                Color::WHITE
+            }
+        }
+        RenderMode::Albedo => surface_albedo(scene, r, &rec, rng),
+    }
+}
+
+/// Color factor of the material of a hit, without lighting: the attenuation of the scattered
+/// ray, or the emitted light when the material absorbs the ray, as the lamps do.
+///
+/// # Arguments
+/// - `scene` - The scene, for its materials.
+/// - `r` - The incoming ray.
+/// - `rec` - The hit.
+/// - `rng` - Random numbers of the scattering.
+pub(crate) fn surface_albedo(scene: &Scene, r: &Ray, rec: &HitRecord, rng: &mut Sampler) -> Color {
+    let material = scene.materials.get(rec.material_id);
+    match material.scatter(r, rec, rng) {
+        Some(scatter) => scatter.attenuation,
+        None => material.emitted(rec.u, rec.v, &rec.p),
+    }
+}
+
+/// Trace some pixels of a scene with full render settings, discarding the result.
+///
+/// Used to measure how long a full render takes without running it.
+///
+/// # Arguments
+/// - `views` - Cameras.
+/// - `pixels` - Pixel coordinates to trace, with the index of the camera to use.
+pub(crate) fn trace_pixels(
+    scene: &Scene, settings: &RenderSettings, views: &[OrbitView], pixels: &[(usize, usize, usize)],
+) {
+    let (width, height) = (settings.width, settings.height);
+    let (depth, samples_per_pixel) = (settings.path_depth(), settings.samples());
+    let scene = &settings.scene(scene);
+    let world = scene.bvh();
+    let cameras: Vec<Camera> = views.iter().map(|v| scene.camera(v, width, height)).collect();
+    let mut rng = Sampler::from_entropy();
+    let pattern = settings.sampler.build(rand::random());
+
+    for &(i, j, k) in pixels {
+        let c = sample_pixel(
+            i,
+            j,
+            width,
+            height,
+            &cameras[k],
+            &world,
+            scene,
+            depth,
+            samples_per_pixel,
+            settings.mode,
+            pattern.as_ref(),
+            settings.clamp_radiance,
+            &mut SampleStats::default(),
+            &mut rng,
+        );
+        std::hint::black_box(c);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::bvh::Bvh;
+    use crate::cancel::CancellationToken;
+    use crate::compare::psnr;
+    use crate::environment::EnvironmentMap;
+    use crate::geometry::{Color, Point, Vec3};
+    use crate::image::{ImageRGBA, ImageRGBF32};
+    use crate::interval::Interval;
+    use crate::light::{DirectionalLight, PointLight, SpotLight};
+    use crate::pdf::Pdf;
+    use crate::ray::Ray;
+    use crate::rect::XzRect;
+    use crate::registry::MaterialId;
+    use crate::render::{
+        render, render_cancellable, render_hdr, render_with_progress, scatter_towards_lights,
+        trace_ray, Background, Bounce, Camera, HitRecord, Hittable, HittableList, MaterialParams,
+        MovingSphere, Object, PathDepth, Plane, SampleStats, ScatterResult, Scene, Sphere,
+    };
+    use crate::sampler::Sampler;
+    use crate::settings::{RenderMode, RenderSettings};
+    use crate::spectrum::D_LINE;
+    use crate::thin_film::ThinFilm;
+    use crate::tiles::render_tiles_hdr;
+    use crate::tonemap::{tonemap, ToneMapOperator};
+    use crate::view::{CameraProjection, Lens, OrbitView};
+    use std::f32::consts::PI;
+    use std::ops::ControlFlow;
+
+    #[test]
+    fn test_hitrecord() {
+        let mut rec = HitRecord::new();
+
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+
+        rec.set_face_normal(&r, &Vec3::UNIT_X);
+    }
+
+    #[test]
+    fn test_edit_scene_copy() {
+        let demo = Scene::demo();
+        let mut scene = demo.clone();
+        let removed = scene.world_mut().remove(0);
+        scene.world_mut().objects_mut()[0].as_sphere_mut().unwrap().radius = 2.0;
+        scene.add(Sphere::new(Point::new(0.0, 1.0, -1.0), 0.25, MaterialId(1))).unwrap();
+
+        // A handle of a larger scene is refused, rather than failing when rendering.
+        let err = scene.add(Sphere::new(Point::ZERO, 1.0, MaterialId(6))).err().unwrap();
+        assert_eq!(err.to_string(), "the sphere has material 6, the scene has 6 materials");
+
+        assert_eq!(removed, demo.world().objects()[0]);
+        assert_eq!(scene.world().len(), 4);
+        assert_eq!(scene.world().objects()[0].as_sphere().unwrap().radius, 2.0);
+        assert_eq!(demo.world().objects()[1].as_sphere().unwrap().radius, 0.5);
+        assert_eq!(scene.material_count(), demo.material_count());
+        assert_eq!(scene.material_name(MaterialId(0)), Some("lambertian"));
+        assert_eq!(scene.material_name(MaterialId(4)), Some("dielectric"));
+        assert_eq!(scene.material_name(MaterialId(6)), None);
+    }
+
+    #[test]
+    fn test_edit_materials() {
+        let demo = Scene::demo();
+        let mut scene = demo.clone();
+        let metal =
+            MaterialParams::Metal { albedo: Color::new(0.8, 0.8, 0.8), fuzz: 0.3, film: None };
+        assert_eq!(scene.material(MaterialId(2)), Some(metal.clone()));
+
+        let glass =
+            MaterialParams::Dielectric { refraction_index: 2.4, dispersion: 0.0, film: None };
+        scene.set_material(MaterialId(2), &glass);
+        assert_eq!(scene.material(MaterialId(2)), Some(glass.clone()));
+        assert_eq!(demo.material(MaterialId(2)), Some(metal));
+
+        let id = scene.add_material(&MaterialParams::Lambertian { albedo: Color::WHITE.into() });
+        assert_eq!(id, MaterialId(6));
+        assert_eq!(scene.material_name(id), Some("lambertian"));
+        assert_eq!(scene.material(MaterialId(7)), None);
+    }
+
+    /// The demo scene, seen from `position`.
+    fn demo_from(position: &Point) -> Scene {
+        let mut scene = Scene::demo();
+        scene.set_view(&OrbitView::looking_at(position, &Point::new(0.0, 0.0, -1.0)));
+        scene
+    }
+
+    /// Settings of a test render, seeded so that it always renders the same image.
+    fn settings(width: usize, height: usize, max_depth: usize, spp: usize) -> RenderSettings {
+        let builder = RenderSettings::builder().resolution(width, height).max_depth(max_depth);
+        builder.samples_per_pixel(spp).seed(Some(1)).build().unwrap()
+    }
+
+    #[test]
+    fn test_nominal_render() {
+        let im = render(&demo_from(&Point::new(-2.0, 2.0, 1.0)), &settings(16, 9, 5, 1)).unwrap();
+        let default_img = ImageRGBA::new(16, 9);
+
+        assert_eq!(im.width, 16);
+        assert_eq!(im.height, 9);
 
         let mut diff_count = 0usize;
         for (p1, p2) in im.pixels.iter().zip(default_img.pixels.iter()) {
@@ -543,36 +2264,957 @@ pub(crate) mod test {
     }
 
     #[test]
-    fn test_linear_trajectory_interpolation() {
-        let start = Point::new(0.0, 0.0, 0.0);
-        let mid = Point::new(0.0, 0.0, 1.0);
-        let end = Point::new(0.0, 1.0, 1.0);
-
-        let points = vec![start, mid, end];
-        let trajectory = interpolate(&points, 10);
-
-        assert_eq!(trajectory.len(), 22);
-        assert_eq!(trajectory[0], start);
-        assert_eq!(trajectory[1], Point::new(0.0, 0.0, 0.1));
-        assert_eq!(trajectory[2], Point::new(0.0, 0.0, 0.2));
-        assert_eq!(trajectory[3], Point::new(0.0, 0.0, 0.3));
-        assert_eq!(trajectory[4], Point::new(0.0, 0.0, 0.4));
-        assert_eq!(trajectory[5], Point::new(0.0, 0.0, 0.5));
-        assert_eq!(trajectory[6], Point::new(0.0, 0.0, 0.6));
-        assert_eq!(trajectory[7], Point::new(0.0, 0.0, 0.7));
-        assert_eq!(trajectory[8], Point::new(0.0, 0.0, 0.8));
-        assert_eq!(trajectory[9], Point::new(0.0, 0.0, 0.9));
-        assert_eq!(trajectory[10], mid);
-        assert_eq!(trajectory[11], Point::new(0.0, 0.0, 1.0));
-        assert_eq!(trajectory[12], Point::new(0.0, 0.1, 1.0));
-        assert_eq!(trajectory[13], Point::new(0.0, 0.2, 1.0));
-        assert_eq!(trajectory[14], Point::new(0.0, 0.3, 1.0));
-        assert_eq!(trajectory[15], Point::new(0.0, 0.4, 1.0));
-        assert_eq!(trajectory[16], Point::new(0.0, 0.5, 1.0));
-        assert_eq!(trajectory[17], Point::new(0.0, 0.6, 1.0));
-        assert_eq!(trajectory[18], Point::new(0.0, 0.7, 1.0));
-        assert_eq!(trajectory[19], Point::new(0.0, 0.8, 1.0));
-        assert_eq!(trajectory[20], Point::new(0.0, 0.9, 1.0));
-        assert_eq!(trajectory[21], end);
+    fn test_render_hdr_is_tone_mapped() {
+        let scene = demo_from(&Point::new(-2.0, 2.0, 1.0));
+        let settings = RenderSettings {
+            tone_map: ToneMapOperator::AcesFilmic,
+            exposure: 1.5,
+            ..settings(16, 9, 5, 2)
+        };
+        let hdr = render_hdr(&scene, &settings).unwrap();
+        assert_eq!((hdr.width, hdr.height), (16, 9));
+        // The sky is brighter than the display white with this exposure.
+        assert!(hdr.pixels.iter().all(|v| *v >= 0.0));
+        assert!(hdr.pixels.iter().any(|v| *v * 1.5f32.exp2() > 1.0));
+
+        let (im, hdr) = render_tiles_hdr(&scene, &settings, |_| ControlFlow::Continue(())).unwrap();
+        assert_eq!(im.pixels, tonemap(&hdr, &settings.tonemap()).pixels);
+    }
+
+    #[test]
+    fn test_plane_hit_from_both_sides() {
+        let plane = Plane::new(Point::new(0.0, -1.0, 0.0), Vec3::new(0.0, 2.0, 0.0), MaterialId(3));
+        assert_eq!(plane.normal, Vec3::UNIT_Y);
+
+        let down = Ray::new(Point::ZERO, -Vec3::UNIT_Y);
+        let mut rec = HitRecord::new();
+        assert!(plane.hit(&down, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!(
+            (rec.t, rec.p, rec.material_id),
+            (1.0, Point::new(0.0, -1.0, 0.0), MaterialId(3))
+        );
+        assert!(rec.front_face);
+
+        let up = Ray::new(Point::new(0.0, -3.0, 0.0), Vec3::UNIT_Y);
+        assert!(plane.hit(&up, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, -Vec3::UNIT_Y);
+
+        // Out of range, and parallel to the plane.
+        assert!(!plane.hit(&down, Interval::new(0.001, 0.5), &mut rec));
+        let along = Ray::new(Point::ZERO, Vec3::UNIT_X);
+        assert!(!plane.hit(&along, Interval::new(0.001, f32::INFINITY), &mut rec));
+    }
+
+    #[test]
+    fn test_sphere_hits_from_the_inside() {
+        let solid = Sphere::new(Point::ZERO, 1.0, MaterialId(0));
+        let hollow = Sphere::new(Point::ZERO, -1.0, MaterialId(0));
+        assert_eq!(solid.bounding_box(), hollow.bounding_box());
+
+        // Normals always face the ray, and negative radiuses swap the faces of the sphere.
+        let mut rec = HitRecord::new();
+        let outwards = Ray::new(Point::ZERO, Vec3::UNIT_X);
+        assert!(solid.hit(&outwards, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.p, rec.normal), (1.0, Point::new(1.0, 0.0, 0.0), -Vec3::UNIT_X));
+        assert!(!rec.front_face);
+        let uv = (rec.u, rec.v);
+        assert!(hollow.hit(&outwards, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.p, rec.normal), (1.0, Point::new(1.0, 0.0, 0.0), -Vec3::UNIT_X));
+        assert!(rec.front_face);
+        assert_eq!((rec.u, rec.v), uv);
+
+        let inwards = Ray::new(Point::new(-3.0, 0.0, 0.0), Vec3::UNIT_X);
+        assert!(solid.hit(&inwards, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.normal), (2.0, -Vec3::UNIT_X));
+        assert!(rec.front_face);
+        assert!(hollow.hit(&inwards, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.normal), (2.0, -Vec3::UNIT_X));
+        assert!(!rec.front_face);
+
+        // Past the near side, the far side is hit from the inside.
+        assert!(hollow.hit(&inwards, Interval::new(2.5, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.normal), (4.0, -Vec3::UNIT_X));
+        assert!(rec.front_face);
+    }
+
+    #[test]
+    fn test_surface_coordinates() {
+        let sphere = Sphere::new(Point::new(0.0, 0.0, -2.0), 1.0, MaterialId(0));
+        let mut rec = HitRecord::new();
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+        assert!(sphere.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        // The front of the sphere is a quarter turn from -X, half way between the poles.
+        assert_f32_near!(rec.u, 0.25);
+        assert_f32_near!(rec.v, 0.5);
+        let r = Ray::new(Point::new(0.0, 2.0, -2.0), -Vec3::UNIT_Y);
+        assert!(sphere.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_f32_near!(rec.v, 1.0);
+
+        // Plane coordinates repeat every unit.
+        let plane = Plane::new(Point::new(0.0, -1.0, 0.0), Vec3::UNIT_Y, MaterialId(0));
+        let mut uv = |x, z| {
+            let r = Ray::new(Point::new(x, 0.0, z), -Vec3::UNIT_Y);
+            assert!(plane.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+            (rec.u, rec.v)
+        };
+        let (u, v) = uv(0.25, 0.5);
+        assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+        let (u1, v1) = uv(1.25, 1.5);
+        assert_f32_near!(u, u1);
+        assert_f32_near!(v, v1);
+    }
+
+    #[test]
+    fn test_heterogeneous_world() {
+        let mut world = HittableList::new();
+        world.add(Plane::new(Point::new(0.0, 0.0, -10.0), Vec3::UNIT_Z, MaterialId(0)));
+        world.add(Object::Sphere(Sphere::new(Point::new(0.0, 0.0, -5.0), 1.0, MaterialId(1))));
+        assert_eq!(world.objects()[0].name(), "plane");
+        assert_eq!(world.objects()[1].material_id(), MaterialId(1));
+        assert!(world.objects()[0].as_sphere().is_none());
+
+        let r = Ray::new(Point::ZERO, -Vec3::UNIT_Z);
+        let mut rec = HitRecord::new();
+        assert!(world.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.object_id, rec.t), (1, 4.0));
+        let r = Ray::new(Point::new(3.0, 0.0, 0.0), -Vec3::UNIT_Z);
+        assert!(world.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.object_id, rec.t), (0, 10.0));
+    }
+
+    #[test]
+    fn test_background() {
+        // Without objects, the whole image is the background.
+        let mut scene = Scene::empty();
+        scene.set_background(&Background::Solid(Color::new(0.25, 1.0, 0.0)));
+        let im = render(&scene, &settings(4, 3, 5, 1)).unwrap();
+        assert!(im.pixels.chunks(4).all(|p| p == [128, 255, 0, 255]));
+
+        let sky = Background::default();
+        assert_eq!(sky.color(&Vec3::new(0.0, -2.0, 0.0)), Color::WHITE);
+        assert_eq!(sky.color(&Vec3::UNIT_Y), Color::new(0.5, 0.7, 1.0));
+    }
+
+    #[test]
+    fn test_sample_stats() {
+        let mut stats = SampleStats::default();
+        let c = Color::new(0.5, 2.0, 1.0);
+        assert_eq!(stats.filter(c, None), Some(c));
+        assert_eq!(stats.filter(c, Some(4.0)), Some(c));
+        assert_eq!(stats.filter(c, Some(1.0)), Some(Color::new(0.25, 1.0, 0.5)));
+        assert_eq!(stats.filter(Color::new(f32::NAN, 0.0, 0.0), Some(1.0)), None);
+        assert_eq!(stats.filter(Color::new(0.0, f32::INFINITY, 0.0), None), None);
+        assert_eq!(stats, SampleStats { samples: 5, clamped: 1, rejected: 2 });
+
+        stats += SampleStats { samples: 5, clamped: 1, rejected: 0 };
+        assert_eq!(stats.to_string(), "2 clamped and 2 rejected of 10 samples");
+    }
+
+    #[test]
+    fn test_render_modes() {
+        // Close on the front of the pink sphere, two units away.
+        let mut scene = Scene::demo();
+        scene.set_view(&OrbitView::looking_at(
+            &Point::new(1.0, 0.0, 1.0),
+            &Point::new(1.0, 0.0, -1.0),
+        ));
+        scene.set_lens(&Lens { vfov: 10.0, ..Default::default() });
+        let center = |scene: &Scene, mode: RenderMode| {
+            let settings = RenderSettings { mode, ..settings(33, 33, 5, 8) };
+            let (x, y, z) = render_hdr(scene, &settings).unwrap().at(16, 16);
+            Color::new(x, y, z)
+        };
+
+        assert_float_absolute_eq!(
+            (center(&scene, RenderMode::Normals) - Color::new(0.5, 0.5, 1.0)).len(),
+            0.0,
+            0.01
+        );
+        // The sphere is 1.5 away, the target 2.
+        let gray = 2.0 / 3.5;
+        assert_float_absolute_eq!(
+            (center(&scene, RenderMode::Depth) - Color::new(gray, gray, gray)).len(),
+            0.0,
+            0.01
+        );
+        assert_eq!(center(&scene, RenderMode::Albedo), Color::new(0.7, 0.3, 0.3));
+        // The ground hides the lower half of the sky from the front of the sphere.
+        let ao = center(&scene, RenderMode::AmbientOcclusion);
+        assert!(ao.x > 0.0 && ao.x < 1.0 && ao.x == ao.y, "{ao:?}");
+
+        // Without the ground, nothing covers it.
+        scene.world_mut().remove(3);
+        assert_eq!(center(&scene, RenderMode::AmbientOcclusion), Color::WHITE);
+
+        let settings = RenderSettings { mode: RenderMode::Depth, ..Default::default() };
+        assert_eq!(settings.samples(), 1);
+        assert_eq!(RenderSettings::default().samples(), 100);
+    }
+
+    #[test]
+    fn test_diffuse_light() {
+        // Inside a hollow lamp, under a black sky.
+        let mut scene = Scene::empty();
+        scene.set_background(&Background::Solid(Color::BLACK));
+        let lamp =
+            MaterialParams::DiffuseLight { color: Color::new(0.125, 0.5, 0.0), intensity: 2.0 };
+        let id = scene.add_material(&lamp);
+        scene.world_mut().add(Sphere::new(Point::ZERO, 1000.0, id));
+        assert_eq!(scene.light_count(), 1);
+        let im = render(&scene, &settings(4, 3, 5, 1)).unwrap();
+        assert!(im.pixels.chunks(4).all(|p| p == [128, 255, 0, 255]));
+
+        // Without a light, nothing lights the walls.
+        scene.set_material(id, &MaterialParams::Lambertian { albedo: Color::WHITE.into() });
+        assert_eq!(scene.light_count(), 0);
+        let im = render(&scene, &settings(4, 3, 5, 1)).unwrap();
+        assert!(im.pixels.chunks(4).all(|p| p == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_light_sampling() {
+        // A small lamp above a white floor.
+        let mut scene = Scene::empty();
+        let white = scene.add_material(&MaterialParams::Lambertian { albedo: Color::WHITE.into() });
+        let lamp = MaterialParams::DiffuseLight { color: Color::WHITE, intensity: 10.0 };
+        let lamp = scene.add_material(&lamp);
+        scene.world_mut().add(XzRect::new(-10.0, 10.0, -10.0, 10.0, 0.0, white));
+        scene.world_mut().add(XzRect::new(-0.1, 0.1, -0.1, 0.1, 2.0, lamp));
+        let bvh = scene.bvh();
+        assert_eq!(bvh.lights().objects().len(), 1);
+
+        let r = Ray::new(Point::new(0.5, 1.0, 0.0), -Vec3::UNIT_Y);
+        let mut rec = HitRecord::new();
+        assert!(bvh.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec));
+        let (material, mut rng) = (scene.materials.get(white), Sampler::new(0));
+        let towards_lamp = (0..1000)
+            .filter(|_| {
+                let (weight, scattered) =
+                    scatter_towards_lights(material, &r, &rec, &bvh, &mut rng).next.unwrap();
+                assert!(
+                    weight.x.is_finite() && weight.x >= 0.0 && weight == weight.x * Color::WHITE
+                );
+                let mut light_rec = HitRecord::new();
+                bvh.hit(&scattered, Interval::new(0.001, f32::INFINITY), &mut light_rec)
+                    && light_rec.material_id == lamp
+            })
+            .count();
+        // Half of the rays are sent towards the lamp, which cosine sampling alone rarely hits.
+        assert!(towards_lamp > 400, "{towards_lamp}");
+
+        // Without lights, the diffuse bounces stay cosine sampled, weighted by the albedo.
+        let (weight, _) =
+            scatter_towards_lights(material, &r, &rec, &Bvh::new(&[]), &mut rng).next.unwrap();
+        assert_float_absolute_eq!((weight - Color::WHITE).len(), 0.0, 1e-4);
+    }
+
+    #[test]
+    fn test_sun_light() {
+        // A grey floor under a ball, lit by the sun straight above, in a black sky.
+        let mut scene = Scene::empty();
+        scene.set_background(&Background::Solid(Color::BLACK));
+        let grey = Color::new(0.5, 0.5, 0.5);
+        let grey = scene.add_material(&MaterialParams::Lambertian { albedo: grey.into() });
+        scene.world_mut().add(XzRect::new(-10.0, 10.0, -10.0, 10.0, 0.0, grey));
+        scene.world_mut().add(Sphere::new(Point::new(3.0, 1.0, 0.0), 0.5, grey));
+        let sun = DirectionalLight { direction: Vec3::UNIT_Y, intensity: PI, ..Default::default() };
+        // Radiance of the floor at `x`, seen from the side.
+        let floor = |scene: &Scene, x: f32| {
+            let (world, mut rng) = (scene.bvh(), Sampler::new(0));
+            let r = Ray::new(Point::new(x - 1.0, 0.2, 0.0), Vec3::new(1.0, -0.2, 0.0));
+            let n = 4000;
+            let sum = (0..n).fold(Color::BLACK, |sum, _| {
+                sum + trace_ray(&r, &world, PathDepth::new(10), scene, &mut rng, &mut |_| {})
+            });
+            (sum / n as f32).x
+        };
+
+        scene.set_sun(Some(sun));
+        // The floor reflects half of the light of the sun, although its rays would rarely hit
+        // its tiny disk at random.
+        assert_float_absolute_eq!(floor(&scene, 0.0), 0.5, 0.05);
+        // While the ball casts a crisp shadow.
+        assert!(floor(&scene, 3.0) < 0.02);
+        assert!(floor(&scene, 3.45) < 0.02);
+        assert_float_absolute_eq!(floor(&scene, 3.55), 0.5, 0.05);
+
+        // A wider sun softens the edge of the shadow.
+        scene.set_sun(Some(DirectionalLight { angular_radius: 15.0, ..sun }));
+        let penumbra = floor(&scene, 3.5);
+        assert!(0.1 < penumbra && penumbra < 0.4, "{penumbra}");
+    }
+
+    #[test]
+    fn test_punctual_lights() {
+        // A grey floor under a ball, in a black sky.
+        let mut scene = Scene::empty();
+        scene.set_background(&Background::Solid(Color::BLACK));
+        let grey = Color::new(0.5, 0.5, 0.5);
+        let grey = scene.add_material(&MaterialParams::Lambertian { albedo: grey.into() });
+        scene.world_mut().add(XzRect::new(-10.0, 10.0, -10.0, 10.0, 0.0, grey));
+        scene.world_mut().add(Sphere::new(Point::new(3.0, 1.0, 0.0), 0.5, grey));
+        // Light reflected by the floor at `x` straight up, without bounces: a single sample is
+        // exact.
+        let floor = |scene: &Scene, x: f32| {
+            let (world, mut rng) = (scene.bvh(), Sampler::new(0));
+            let r = Ray::new(Point::new(x, 0.1, 0.0), -Vec3::UNIT_Y);
+            trace_ray(&r, &world, PathDepth::new(1), scene, &mut rng, &mut |_| {}).x
+        };
+        assert_eq!(floor(&scene, 0.0), 0.0);
+
+        // A bulb 2 above the floor: half of its light is reflected, spread over a half sphere.
+        let bulb = PointLight { intensity: 8.0 * PI, ..Default::default() };
+        scene.punctual_lights_mut().push(bulb.into());
+        assert_float_absolute_eq!(floor(&scene, 0.0), 1.0, 1e-4);
+        // Further away and at a grazing angle, the light is weaker.
+        assert_float_absolute_eq!(floor(&scene, 2.0), 1.0 / 2f32.powf(1.5), 1e-4);
+        // And the ball hides the bulb from behind it.
+        assert_eq!(floor(&scene, 4.0), 0.0);
+
+        // A spot pointing down only lights the floor under it.
+        let spot = SpotLight {
+            position: bulb.position,
+            angle: 20.0,
+            blend: 0.0,
+            intensity: bulb.intensity,
+            ..Default::default()
+        };
+        scene.punctual_lights_mut()[0] = spot.into();
+        assert_float_absolute_eq!(floor(&scene, 0.0), 1.0, 1e-4);
+        assert_eq!(floor(&scene, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_environment_light() {
+        // A grey floor under a black sky, but for a bright spot.
+        let mut scene = Scene::empty();
+        let map = crate::environment::test::bright_spot();
+        scene.set_background(&Background::Environment(map));
+        let grey = Color::new(0.5, 0.5, 0.5);
+        let grey = scene.add_material(&MaterialParams::Lambertian { albedo: grey.into() });
+        scene.world_mut().add(Plane::new(Point::ZERO, Vec3::UNIT_Y, grey));
+        let (world, mut rng) = (scene.bvh(), Sampler::new(0));
+        let r = Ray::new(Point::new(0.0, 1.0, 0.0), -Vec3::UNIT_Y);
+        let n = 4000;
+        let sum = (0..n).fold(Color::BLACK, |sum, _| {
+            sum + trace_ray(&r, &world, PathDepth::new(2), &scene, &mut rng, &mut |_| {})
+        });
+        // The spot, 1/16 of the turn wide, from 0.38 to 0.71 high, shines on the floor as
+        // much as it is high above the horizon.
+        let (low, high) = ((PI * 0.625).cos().abs(), (PI * 0.75).cos().abs());
+        let irradiance = 2.0 * PI / 16.0 * (high * high - low * low) / 2.0;
+        let expected = Color::new(100.0, 50.0, 25.0) * (irradiance * 0.5 / PI);
+        let floor = sum / n as f32;
+        assert!((floor - expected).len() < 0.05 * expected.len(), "{floor:?} != {expected:?}");
+    }
+
+    /// A ray bouncing between the walls of a mirror sphere, from its center.
+    fn hall_of_mirrors(albedo: Color) -> (Scene, Ray) {
+        let mut scene = Scene::empty();
+        let mirror = scene.add_material(&MaterialParams::Metal { albedo, fuzz: 0.0, film: None });
+        scene.world_mut().add(Sphere::new(Point::ZERO, 1.0, mirror));
+        (scene, Ray::new(Point::ZERO, Vec3::new(0.3, 0.2, 1.0)))
+    }
+
+    #[test]
+    fn test_deep_paths() {
+        // Far deeper than the stack would allow a recursive tracer.
+        let (scene, r) = hall_of_mirrors(Color::WHITE);
+        let (world, mut rng) = (scene.bvh(), Sampler::new(0));
+        let (mut hits, mut last) = (0, None);
+        let color = trace_ray(&r, &world, PathDepth::new(100_000), &scene, &mut rng, &mut |b| {
+            hits += matches!(b, Bounce::Hit { .. }) as usize;
+            last = Some(b);
+        });
+        assert_eq!((hits, last, color), (100_000, Some(Bounce::DepthLimit), Color::BLACK));
+    }
+
+    #[test]
+    fn test_russian_roulette() {
+        // Each bounce halves the light, the roulette soon stops the path.
+        let (scene, r) = hall_of_mirrors(Color::new(0.5, 0.5, 0.5));
+        let (world, mut rng) = (scene.bvh(), Sampler::new(0));
+        let depth = PathDepth { max: 100_000, roulette: Some(3) };
+        for _ in 0..20 {
+            let (mut hits, mut last) = (0, None);
+            trace_ray(&r, &world, depth, &scene, &mut rng, &mut |b| {
+                hits += matches!(b, Bounce::Hit { .. }) as usize;
+                last = Some(b);
+            });
+            assert!((3..1000).contains(&hits), "{hits}");
+            assert_eq!(last, Some(Bounce::Roulette));
+        }
+
+        // The paths going on make up for those stopped.
+        let mut throughput = Color::new(0.25, 0.1, 0.0);
+        let survivors = (0..10_000)
+            .filter(|_| {
+                let mut t = throughput;
+                depth.survives(3, &mut t, &mut rng) && t == Color::new(1.0, 0.4, 0.0)
+            })
+            .count();
+        assert!((2300..2700).contains(&survivors), "{survivors}");
+        assert!(depth.survives(2, &mut throughput, &mut rng));
+        assert_eq!(throughput, Color::new(0.25, 0.1, 0.0));
+    }
+
+    #[test]
+    fn test_scatter_results() {
+        // Down onto a floor.
+        let r_in = Ray::new(Point::new(-1.0, 2.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let mut rec = HitRecord::new();
+        rec.p = Point::new(0.0, 1.0, 0.0);
+        rec.set_face_normal(&r_in, &Vec3::UNIT_Y);
+        let mut rng = Sampler::new(0);
+        let mut scatter = |params: MaterialParams| params.build().scatter(&r_in, &rec, &mut rng);
+
+        // Diffuse: a ray of the cosine distribution, which the tracer may sample instead.
+        let white = MaterialParams::Lambertian { albedo: Color::WHITE.into() };
+        let ScatterResult { attenuation, scattered, pdf } = scatter(white).unwrap();
+        assert_eq!((attenuation, scattered.orig), (Color::WHITE, rec.p));
+        assert!(scattered.dir.y > 0.0);
+        assert!(pdf.unwrap().value(&scattered.dir) > 0.0);
+
+        // Mirror: the reflected ray alone.
+        let mirror =
+            MaterialParams::Metal { albedo: Color::new(0.5, 0.5, 0.5), fuzz: 0.0, film: None };
+        let ScatterResult { attenuation, scattered, pdf } = scatter(mirror).unwrap();
+        assert_eq!(attenuation, Color::new(0.5, 0.5, 0.5));
+        assert_float_absolute_eq!((scattered.dir - Vec3::new(1.0, 1.0, 0.0).normed()).len(), 0.0);
+        assert!(pdf.is_none());
+
+        let light = MaterialParams::DiffuseLight { color: Color::WHITE, intensity: 4.0 };
+        assert!(scatter(light).is_none());
+    }
+
+    #[test]
+    fn test_metal_scattering() {
+        let mut rng = Sampler::new(0);
+        // Rays hitting the y = 0 surface from `dir`, and the directions they scatter to.
+        let mut scatter = |params: MaterialParams, dir: Vec3| {
+            let metal = params.build();
+            let r_in = Ray::new(Point::ZERO - dir, dir);
+            let mut rec = HitRecord::new();
+            rec.set_face_normal(&r_in, &Vec3::UNIT_Y);
+            let scatter = |_| metal.scatter(&r_in, &rec, &mut rng).map(|s| s.scattered.dir);
+            (0..10_000).map(scatter).collect::<Vec<Option<Vec3>>>()
+        };
+        let albedo = Color::new(0.8, 0.8, 0.8);
+        assert_eq!(
+            MaterialParams::rough_metal(albedo, 0.5),
+            MaterialParams::Metal { albedo, fuzz: 0.25, film: None }
+        );
+
+        // The fuzz moves the mirror reflection to the surface of a sphere around its tip.
+        let reflected = Vec3::new(1.0, 1.0, 0.0).normed();
+        let dirs = scatter(MaterialParams::rough_metal(albedo, 0.5), Vec3::new(1.0, -1.0, 0.0));
+        assert!(dirs.iter().all(|d| ((d.unwrap() - reflected).len() - 0.25).abs() < 1e-5));
+        // Spread on the whole sphere, not only around its center.
+        let offsets = dirs.iter().map(|d| (d.unwrap() - reflected).normed());
+        let mean = offsets.fold(Vec3::ZERO, |sum, v| sum + v) / dirs.len() as f32;
+        assert!(mean.len() < 0.05, "{mean:?}");
+
+        // At grazing angles, the rays fuzzed below the surface are absorbed.
+        let (sin, cos) = 80f32.to_radians().sin_cos();
+        let dirs = scatter(MaterialParams::rough_metal(albedo, 1.0), Vec3::new(sin, -cos, 0.0));
+        let absorbed = dirs.iter().filter(|d| d.is_none()).count();
+        assert!((3000..5000).contains(&absorbed), "{absorbed}");
+        assert!(dirs.iter().flatten().all(|d| d.y > 0.0));
+    }
+
+    #[test]
+    fn test_metal_furnace() {
+        // A white mirror reflects all the light of a white sky, a rough one loses some.
+        let mut scene = Scene::empty();
+        scene.set_background(&Background::Solid(Color::WHITE));
+        let metal = scene.add_material(&MaterialParams::rough_metal(Color::WHITE, 0.0));
+        let center = Point::new(0.0, 0.0, -1.0);
+        scene.world_mut().add(Sphere { center, radius: 0.5, material_id: metal });
+        scene.set_view(&OrbitView::looking_at(&Point::new(0.0, 0.0, 1.0), &center));
+
+        let settings = RenderSettings { seed: Some(1), ..settings(16, 16, 50, 4) };
+        let radiance = |scene: &Scene| {
+            let rendered = render_tiles_hdr(scene, &settings, |_| ControlFlow::Continue(()));
+            rendered.unwrap().1.pixels
+        };
+        assert!(radiance(&scene).iter().all(|&v| (v - 1.0).abs() < 1e-4));
+
+        scene.set_material(metal, &MaterialParams::rough_metal(Color::WHITE, 1.0));
+        let rough = radiance(&scene);
+        assert!(rough.iter().all(|&v| v < 1.0 + 1e-4));
+        assert!(rough.iter().any(|&v| v < 0.9));
+    }
+
+    #[test]
+    fn test_metal_roughness_blurs_reflections() {
+        // Against the reference render of a mirror, rougher metals differ more.
+        let render = |roughness| {
+            let mut scene = demo_from(&Point::new(-1.0, 0.5, 1.0));
+            let metal = MaterialParams::rough_metal(Color::new(0.8, 0.8, 0.8), roughness);
+            scene.set_material(MaterialId(2), &metal);
+            let settings = RenderSettings { seed: Some(1), ..settings(32, 18, 10, 64) };
+            render_tiles_hdr(&scene, &settings, |_| ControlFlow::Continue(())).unwrap().0
+        };
+        let reference = render(0.0);
+        let psnrs: Vec<f64> =
+            [0.1, 0.25, 0.5, 1.0].iter().map(|&r| psnr(&render(r), &reference).unwrap()).collect();
+        assert!(psnrs.windows(2).all(|w| w[0] > w[1]), "{psnrs:?}");
+    }
+
+    #[test]
+    fn test_dielectric_scattering() {
+        let glass =
+            MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.0, film: None }
+                .build();
+        let mut rng = Sampler::new(0);
+        // Rays hitting the y = 0 surface of the glass from `dir`, from the air above or from the
+        // glass below, and the directions they scatter to.
+        let mut scatter = |dir: Vec3| {
+            let r_in = Ray::new(Point::ZERO - dir, dir);
+            let mut rec = HitRecord::new();
+            rec.set_face_normal(&r_in, &Vec3::UNIT_Y);
+            let scatter = |_| glass.scatter(&r_in, &rec, &mut rng).unwrap().scattered.dir.normed();
+            (0..10_000).map(scatter).collect::<Vec<Vec3>>()
+        };
+
+        // At normal incidence, 4% of the light is reflected, the rest goes straight through.
+        let dirs = scatter(-Vec3::UNIT_Y);
+        let reflected = dirs.iter().filter(|d| d.y > 0.0).count();
+        assert!((300..500).contains(&reflected), "{reflected}");
+        assert!(dirs.iter().filter(|d| d.y < 0.0).all(|d| (*d + Vec3::UNIT_Y).len() < 1e-5));
+
+        // Snell's law: sin(45°) = 1.5 sin(theta) into the glass, 1.5 sin(20°) = sin(theta) out.
+        let refracted = |dirs: &[Vec3], down| *dirs.iter().find(|d| (d.y < 0.0) == down).unwrap();
+        let into = refracted(&scatter(Vec3::new(1.0, -1.0, 0.0)), true);
+        assert_float_absolute_eq!(into.x, std::f32::consts::FRAC_1_SQRT_2 / 1.5, 1e-5);
+        let (sin, cos) = 20f32.to_radians().sin_cos();
+        let out = refracted(&scatter(Vec3::new(sin, cos, 0.0)), false);
+        assert_float_absolute_eq!(out.x, 1.5 * sin, 1e-5);
+
+        // Past the critical angle of 41.8°, the light stays in the glass.
+        let (sin, cos) = 60f32.to_radians().sin_cos();
+        let dirs = scatter(Vec3::new(sin, cos, 0.0));
+        assert!(dirs.iter().all(|d| (*d - Vec3::new(sin, -cos, 0.0)).len() < 1e-5));
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let prism =
+            MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.013, film: None };
+        let prism = prism.build();
+        let mut rng = Sampler::new(0);
+        // Sine of the angle into the glass of a ray at 45° in the air, by Snell's law.
+        let mut refracted_sine = |wavelength| {
+            let dir = Vec3::new(1.0, -1.0, 0.0);
+            let r_in = Ray { wavelength, ..Ray::new(Point::ZERO - dir, dir) };
+            let mut rec = HitRecord::new();
+            rec.set_face_normal(&r_in, &Vec3::UNIT_Y);
+            loop {
+                let scattered = prism.scatter(&r_in, &rec, &mut rng).unwrap().scattered;
+                assert_eq!(scattered.wavelength, wavelength);
+                if scattered.dir.y < 0.0 {
+                    return scattered.dir.normed().x;
+                }
+            }
+        };
+        let sine = std::f32::consts::FRAC_1_SQRT_2 / 1.5;
+        assert_float_absolute_eq!(refracted_sine(None), sine, 1e-5);
+        assert_float_absolute_eq!(refracted_sine(Some(D_LINE)), sine, 1e-5);
+        // Blue light bends more than red.
+        let (blue, red) = (refracted_sine(Some(450.0)), refracted_sine(Some(650.0)));
+        assert!(blue < sine && sine < red, "{blue} {red}");
+        let index = |sine: f32| std::f32::consts::FRAC_1_SQRT_2 / sine;
+        assert_float_absolute_eq!(
+            index(blue) - index(red),
+            0.013 * (1.0 / 0.2025 - 1.0 / 0.4225),
+            1e-3
+        );
+    }
+
+    #[test]
+    fn test_spectral_render() {
+        // Dispersive glass in a white furnace only splits white light into white light.
+        let mut scene = Scene::empty();
+        scene.set_background(&Background::Solid(Color::WHITE));
+        let prism =
+            MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.013, film: None };
+        let glass = scene.add_material(&prism);
+        let center = Point::new(0.0, 0.0, -1.0);
+        scene.world_mut().add(Sphere { center, radius: 0.5, material_id: glass });
+        scene.set_view(&OrbitView::looking_at(&Point::new(0.0, 0.0, 1.0), &center));
+
+        let settings = RenderSettings { mode: RenderMode::Spectral, ..settings(16, 16, 50, 64) };
+        let radiance = render_hdr(&scene, &settings).unwrap();
+        let n = radiance.pixels.len() as f32;
+        assert_float_absolute_eq!(radiance.pixels.iter().sum::<f32>() / n, 1.0, 0.01);
+        // Each pixel averages its stratified wavelengths.
+        assert!(radiance.pixels.iter().all(|&v| (v - 1.0).abs() < 0.2));
+    }
+
+    #[test]
+    fn test_glass_furnace() {
+        // Glass neither absorbs nor emits light: under a white sky, it is white.
+        let mut scene = Scene::empty();
+        scene.set_background(&Background::Solid(Color::WHITE));
+        let glass = scene.add_material(&MaterialParams::Dielectric {
+            refraction_index: 1.5,
+            dispersion: 0.0,
+            film: None,
+        });
+        let center = Point::new(0.0, 0.0, -1.0);
+        scene.world_mut().add(Sphere { center, radius: 0.5, material_id: glass });
+        scene.set_view(&OrbitView::looking_at(&Point::new(0.0, 0.0, 1.0), &center));
+
+        let settings = RenderSettings { seed: Some(1), ..settings(16, 16, 50, 4) };
+        let furnace = |scene: &Scene| {
+            let rendered = render_tiles_hdr(scene, &settings, |_| ControlFlow::Continue(()));
+            let (_, radiance) = rendered.unwrap();
+            radiance.pixels.iter().all(|&v| (v - 1.0).abs() < 1e-4)
+        };
+        assert!(furnace(&scene));
+
+        // Nor does a hollow glass bubble.
+        scene.world_mut().add(Sphere { center, radius: -0.4, material_id: glass });
+        assert!(furnace(&scene));
+    }
+
+    #[test]
+    fn test_thin_film_coatings() {
+        let mut rng = Sampler::new(0);
+        let r_in = Ray::new(Point::new(0.0, 1.0, 0.0), -Vec3::UNIT_Y);
+        let mut rec = HitRecord::new();
+        rec.set_face_normal(&r_in, &Vec3::UNIT_Y);
+        // A film colors the reflections of a grey metal.
+        let film = Some(ThinFilm { thickness: 250.0, refraction_index: 2.5 });
+        let grey = Color::new(0.6, 0.6, 0.6);
+        let tempered = MaterialParams::Metal { albedo: grey, fuzz: 0.0, film }.build();
+        let c = tempered.scatter(&r_in, &rec, &mut rng).unwrap().attenuation;
+        assert!((c - grey).len() > 0.05 && c.x.max(c.y).max(c.z) - c.x.min(c.y).min(c.z) > 0.05);
+
+        // A soap bubble neither absorbs nor emits light: under a white sky, it is white.
+        let mut scene = Scene::empty();
+        scene.set_background(&Background::Solid(Color::WHITE));
+        let film = Some(ThinFilm::default());
+        let bubble = MaterialParams::Dielectric { refraction_index: 1.0, dispersion: 0.0, film };
+        let bubble = scene.add_material(&bubble);
+        let center = Point::new(0.0, 0.0, -1.0);
+        scene.world_mut().add(Sphere { center, radius: 0.5, material_id: bubble });
+        scene.set_view(&OrbitView::looking_at(&Point::new(0.0, 0.0, 1.0), &center));
+        let settings = RenderSettings { seed: Some(1), ..settings(16, 16, 50, 16) };
+        let radiance = render_hdr(&scene, &settings).unwrap();
+        let n = radiance.pixels.len() as f32;
+        assert_float_absolute_eq!(radiance.pixels.iter().sum::<f32>() / n, 1.0, 0.01);
+    }
+
+    #[test]
+    fn test_glass_of_index_1_is_invisible() {
+        // Compared to the demo scene without its glass sphere, the reference, a sphere of the
+        // index of the air differs about as much as a render with another seed.
+        let render = |scene: &Scene, seed| {
+            let settings = RenderSettings { seed: Some(seed), ..settings(32, 18, 10, 64) };
+            render_tiles_hdr(scene, &settings, |_| ControlFlow::Continue(())).unwrap().0
+        };
+        let position = Point::new(0.0, 0.5, 1.0);
+        let mut reference = demo_from(&position);
+        reference.world_mut().remove(0);
+        let mut air = demo_from(&position);
+        air.set_material(
+            MaterialId(4),
+            &MaterialParams::Dielectric { refraction_index: 1.0, dispersion: 0.0, film: None },
+        );
+        let noise = psnr(&render(&reference, 1), &render(&reference, 2)).unwrap();
+        let air = psnr(&render(&air, 1), &render(&reference, 2)).unwrap();
+        let glass = psnr(&render(&demo_from(&position), 1), &render(&reference, 2)).unwrap();
+        // Schlick's approximation still reflects a little at grazing angles.
+        assert!(air > noise - 4.0, "{air} dB, {noise} dB between seeds");
+        // While the actual glass sphere shows.
+        assert!(glass < noise - 10.0, "{glass} dB");
+    }
+
+    #[test]
+    fn test_isotropic_scattering() {
+        let albedo = Color::new(0.5, 0.25, 1.0);
+        let params = MaterialParams::Isotropic { albedo: albedo.into() };
+        let material = params.build();
+        assert_eq!(material.params(), params);
+
+        let mut rec = HitRecord::new();
+        rec.p = Point::new(1.0, 2.0, 3.0);
+        let r_in = Ray::new(Point::ZERO, Vec3::UNIT_X);
+        let mut rng = Sampler::new(0);
+        // Any direction, even backwards.
+        let backwards = (0..100).any(|_| {
+            let ScatterResult { attenuation, scattered, pdf } =
+                material.scatter(&r_in, &rec, &mut rng).unwrap();
+            assert_eq!((attenuation, scattered.orig, pdf), (albedo, rec.p, None));
+            assert_float_absolute_eq!(scattered.dir.len(), 1.0, 1e-5);
+            scattered.dir.x < 0.0
+        });
+        assert!(backwards);
+    }
+
+    #[test]
+    fn test_render_with_progress_reports_every_scanline() {
+        let scene = demo_from(&Point::new(-2.0, 2.0, 1.0));
+        let mut scanlines = Vec::new();
+        let im = render_with_progress(&scene, &settings(8, 4, 2, 1), |partial, j| {
+            assert_eq!(partial.height, 4);
+            scanlines.push(j);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+
+        assert_eq!(im.height, 4);
+        assert_eq!(scanlines, vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_seeded_render_does_not_depend_on_threads() {
+        let scene = demo_from(&Point::new(-2.0, 2.0, 1.0));
+        let render_on = |threads: usize, seed: u64| {
+            let settings = RenderSettings {
+                threads: Some(threads),
+                seed: Some(seed),
+                ..settings(16, 9, 8, 4)
+            };
+            render_with_progress(&scene, &settings, |_, _| ControlFlow::Continue(())).unwrap()
+        };
+        let reference = render_on(1, 7);
+        assert_eq!(render_on(4, 7).pixels, reference.pixels);
+        assert_ne!(render_on(4, 8).pixels, reference.pixels);
+    }
+
+    #[test]
+    fn test_render_with_progress_can_be_stopped() {
+        let scene = demo_from(&Point::new(-2.0, 2.0, 1.0));
+        let mut count = 0;
+        let im = render_with_progress(&scene, &settings(8, 4, 2, 1), |_, j| {
+            count += 1;
+            if j == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+
+        assert_eq!(count, 2);
+        // untouched scanlines keep the default background
+        assert_eq!(im.at_u32(0, 0), ImageRGBA::new(1, 1).at_u32(0, 0));
+    }
+
+    #[test]
+    fn test_cancelled_render() {
+        let scene = demo_from(&Point::new(-2.0, 2.0, 1.0));
+        let token = CancellationToken::new();
+        let (im, complete) = render_cancellable(&scene, &settings(8, 4, 2, 1), &token).unwrap();
+        assert!(complete);
+        assert_ne!(im, ImageRGBA::new(8, 4));
+
+        token.cancel();
+        let (im, complete) = render_cancellable(&scene, &settings(8, 4, 2, 1), &token).unwrap();
+        assert!(!complete);
+        assert_eq!(im, ImageRGBA::new(8, 4));
+    }
+
+    #[test]
+    fn test_defocus_rays_meet_in_the_focus_plane() {
+        let (position, target) = (Point::new(0.0, 0.0, 2.0), Point::new(0.0, 0.0, -1.0));
+        let up = Vec3::UNIT_Y;
+        let cam = Camera::new(position, target, up, 90.0, 2.0, 0.5, 4.0);
+        let pinhole = Camera::new(position, target, up, 90.0, 2.0, 0.0, 4.0);
+        let mut rng = Sampler::new(0);
+        let origins: Vec<Point> = (0..20).map(|_| cam.get_ray(0.25, 0.75, &mut rng).orig).collect();
+        // Rays start on the lens, in the image plane around the camera position.
+        assert!(origins.iter().all(|o| (*o - position).len() <= 0.25 && o.z == position.z));
+        assert!(origins.iter().any(|o| *o != position));
+        assert_eq!(pinhole.get_ray(0.25, 0.75, &mut rng).orig, position);
+
+        // And all reach the same point at the focus distance.
+        let focus = pinhole.get_ray(0.25, 0.75, &mut rng).at(1.0);
+        assert_f32_near!(focus.z, -2.0);
+        for _ in 0..20 {
+            let r = cam.get_ray(0.25, 0.75, &mut rng);
+            assert!((r.at(1.0) - focus).len() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_moving_sphere() {
+        let (start, end) = (Point::new(0.0, 0.0, -2.0), Point::new(2.0, 0.0, -2.0));
+        let sphere = MovingSphere::new(start, end, 1.0, 2.0, 0.5, MaterialId(3));
+        assert_eq!(sphere.center(1.5), Point::new(1.0, 0.0, -2.0));
+        // Outside of the keyframes, the sphere stays put.
+        assert_eq!(sphere.center(0.0), start);
+        assert_eq!(sphere.center(5.0), end);
+
+        let mut rec = HitRecord::new();
+        let r = |time| Ray { orig: Point::ZERO, dir: -Vec3::UNIT_Z, time, wavelength: None };
+        assert!(sphere.hit(&r(1.0), Interval::new(0.001, f32::INFINITY), &mut rec));
+        assert_eq!((rec.t, rec.material_id), (1.5, MaterialId(3)));
+        assert!(!sphere.hit(&r(2.0), Interval::new(0.001, f32::INFINITY), &mut rec));
+
+        let bbox = sphere.bounding_box();
+        assert_eq!(
+            (bbox.min, bbox.max),
+            (Point::new(-0.5, -0.5, -2.5), Point::new(2.5, 0.5, -1.5))
+        );
+    }
+
+    #[test]
+    fn test_shutter_times() {
+        let up = Vec3::UNIT_Y;
+        let cam = Camera::new(Point::ZERO, -Vec3::UNIT_Z, up, 90.0, 1.0, 0.0, 1.0);
+        let mut rng = Sampler::new(0);
+        assert_eq!(cam.get_ray(0.5, 0.5, &mut rng).time, 0.0);
+        let cam = cam.with_shutter(0.25, 0.5);
+        let times: Vec<f32> = (0..20).map(|_| cam.get_ray(0.5, 0.5, &mut rng).time).collect();
+        assert!(times.iter().all(|t| (0.25..0.5).contains(t)));
+        assert!(times.iter().any(|&t| t != times[0]));
+    }
+
+    #[test]
+    fn test_camera_projections() {
+        let (position, target) = (Point::new(0.0, 0.0, 2.0), Point::ZERO);
+        let camera = |aperture, projection| {
+            Camera::new(position, target, Vec3::UNIT_Y, 90.0, 2.0, aperture, 2.0)
+                .with_projection(projection)
+        };
+        let cam = camera(0.0, CameraProjection::Perspective);
+        let mut rng = Sampler::new(0);
+
+        // Parallel rays from the plane of the camera, framing the plane in focus as the
+        // perspective does.
+        let orthographic = camera(0.0, CameraProjection::Orthographic);
+        for (s, t) in [(0.0, 0.0), (0.5, 0.5), (0.25, 0.75)] {
+            let r = orthographic.get_ray(s, t, &mut rng);
+            assert_eq!(r.dir, Vec3::new(0.0, 0.0, -2.0));
+            assert_f32_near!(r.orig.z, 2.0);
+            assert!((r.at(1.0) - cam.get_ray(s, t, &mut rng).at(1.0)).len() < 1e-5);
+        }
+        // Out of focus, the rays still meet in the plane in focus.
+        let blurred = camera(0.5, CameraProjection::Orthographic);
+        let r = blurred.get_ray(0.25, 0.75, &mut rng);
+        assert!((r.at(1.0) - orthographic.get_ray(0.25, 0.75, &mut rng).at(1.0)).len() < 1e-5);
+
+        // The angle from the axis grows evenly towards the edges, the top edge at half the
+        // field of view.
+        let fisheye = camera(0.0, CameraProjection::Fisheye);
+        let angle = |s, t, rng: &mut Sampler| {
+            let r = fisheye.get_ray(s, t, rng);
+            assert_eq!(r.orig, position);
+            r.dir.normed().dot(&-Vec3::UNIT_Z).clamp(-1.0, 1.0).acos().to_degrees()
+        };
+        assert_float_absolute_eq!(angle(0.5, 0.5, &mut rng), 0.0, 1e-2);
+        assert_float_absolute_eq!(angle(0.5, 1.0, &mut rng), 45.0, 1e-2);
+        assert_float_absolute_eq!(angle(0.75, 0.5, &mut rng), 45.0, 1e-2);
+        assert_float_absolute_eq!(angle(1.0, 0.5, &mut rng), 90.0, 1e-2);
+        let up = fisheye.get_ray(0.5, 1.0, &mut rng).dir;
+        assert!(up.y > 0.0 && up.x.abs() < 1e-5, "{up:?}");
+    }
+
+    #[test]
+    fn test_panorama_as_environment_map() {
+        // A panorama, each pixel seeing its own color.
+        let (width, height) = (16, 8);
+        let mut image = ImageRGBF32::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                image.put(i, j, i as f32, j as f32, 0.0);
+            }
+        }
+        let view = OrbitView { yaw: 30.0, pitch: 20.0, ..Default::default() };
+        let map = EnvironmentMap::new(image).unwrap().with_rotation(view.yaw + 90.0);
+        let lens = Lens { projection: CameraProjection::Equirectangular, ..Default::default() };
+        let camera = view.camera(width, height, &lens);
+        let mut rng = Sampler::new(0);
+        // The rays of the pixels, from the bottom, see their colors in the environment map.
+        for j in 0..height {
+            for i in 0..width {
+                let (s, t) = ((i as f32 + 0.5) / width as f32, (j as f32 + 0.5) / height as f32);
+                let r = camera.get_ray(s, t, &mut rng);
+                let expected = Color::new(i as f32, (height - 1 - j) as f32, 0.0);
+                assert_eq!(map.radiance(&r.dir), expected, "{i} {j}");
+            }
+        }
+        // The view direction is in the center, and the right of the view on the right.
+        let forward = camera.get_ray(0.5, 0.5, &mut rng).dir.normed();
+        let position = view.position() - view.target;
+        let level = -Vec3::new(position.x, 0.0, position.z).normed();
+        assert!((forward - level).len() < 1e-5, "{forward:?} {level:?}");
+        let right = camera.get_ray(0.75, 0.5, &mut rng).dir.normed();
+        assert!((right - level.cross(&Vec3::UNIT_Y)).len() < 1e-5, "{right:?}");
+    }
+
+    mod properties {
+        use crate::geometry::{Point, Vec3};
+        use crate::interval::Interval;
+        use crate::ray::Ray;
+        use crate::registry::MaterialId;
+        use crate::render::{HitRecord, Hittable, Sphere};
+        use proptest::prelude::*;
+
+        fn point() -> impl Strategy<Value = Point> {
+            (-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0)
+                .prop_map(|(x, y, z)| Point::new(x, y, z))
+        }
+
+        fn direction() -> impl Strategy<Value = Vec3> {
+            point()
+                .prop_filter("too short to normalize", |v| v.len() > 0.01)
+                .prop_map(|v| v.normed())
+        }
+
+        /// Radiuses from tiny to large, negative ones for hollow spheres.
+        fn radius() -> impl Strategy<Value = f32> {
+            (1e-3f32..10.0, any::<bool>()).prop_map(|(r, hollow)| if hollow { -r } else { r })
+        }
+
+        proptest! {
+            #[test]
+            fn test_sphere_hits_are_in_range_and_on_the_surface(
+                center in point(), radius in radius(), orig in point(), dir in direction(),
+                t_min in 0.0f32..5.0, t_range in 0.0f32..50.0,
+            ) {
+                let sphere = Sphere::new(center, radius, MaterialId(0));
+                let r = Ray::new(orig, dir);
+                let ray_t = Interval::new(t_min, t_min + t_range);
+                let mut rec = HitRecord::new();
+                if sphere.hit(&r, ray_t, &mut rec) {
+                    prop_assert!(ray_t.contains(rec.t));
+                    let distance = (rec.p - center).len();
+                    prop_assert!((distance - radius.abs()).abs() <= 1e-3 * (1.0 + radius.abs()) + 1e-3);
+                    prop_assert!((rec.normal.len() - 1.0).abs() <= 1e-2);
+                }
+            }
+
+            #[test]
+            fn test_grazing_rays_hit_only_within_the_radius(
+                center in point(), radius in 0.01f32..10.0, dir in direction(),
+                offset in -2.0f32..2.0, side in direction(),
+            ) {
+                // A ray passing at `|offset| * radius` from the center, starting far before it.
+                let across = side - side.dot(&dir) * dir;
+                prop_assume!(across.len() > 0.1);
+                let closest = center + (offset * radius) * across.normed();
+                let r = Ray::new(closest - 20.0 * dir, dir);
+                let sphere = Sphere::new(center, radius, MaterialId(0));
+                let hit = sphere.hit(&r, Interval::new(0.0, f32::INFINITY), &mut HitRecord::new());
+                if offset.abs() < 0.99 {
+                    prop_assert!(hit);
+                } else if offset.abs() > 1.01 {
+                    prop_assert!(!hit);
+                }
+            }
+
+            #[test]
+            fn test_rays_without_direction_never_hit(center in point(), radius in radius(), orig in point()) {
+                let r = Ray::new(orig, Vec3::ZERO);
+                let mut rec = HitRecord::new();
+                let sphere = Sphere::new(center, radius, MaterialId(0));
+                prop_assert!(!sphere.hit(&r, Interval::new(0.0, f32::INFINITY), &mut rec));
+            }
+        }
     }
 }
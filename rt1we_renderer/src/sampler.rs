@@ -0,0 +1,562 @@
+//! Random numbers of the renders.
+//!
+//! Every random choice of a render, from where a ray goes through its pixel to how it
+//! scatters, draws from a [`Sampler`] handed down by the row being traced. Renders with the
+//! same seed give the same image.
+//!
+//! The renders draw several random numbers per bounce, the generator is a PCG32: a few
+//! instructions per number, and a state small enough to make one per row.
+//!
+//! Independent random numbers leave clumps and holes among the samples of a pixel. A
+//! [`PixelSampler`] spreads the first numbers each sample draws, its pixel position, lens
+//! position, shutter time and first bounces, evenly over the samples of the pixel, for less
+//! noise at the same sample count. The numbers after those come from the generator.
+use crate::ray::Ray;
+use rand::RngCore;
+use std::fmt;
+use std::str::FromStr;
+
+/// Multiplier of the linear congruential step of PCG32.
+const PCG_MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+/// Stream of the samplers made with [`Sampler::new`].
+const DEFAULT_STREAM: u64 = 1_442_695_040_888_963_407;
+
+/// Permuted congruential generator, 32-bit output, see <https://www.pcg-random.org>.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pcg32 {
+    state: u64,
+    /// Odd increment, selecting one of 2^63 streams.
+    inc: u64,
+}
+
+impl Pcg32 {
+    fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Pcg32 { state: 0, inc: (stream << 1) | 1 };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    fn step(&mut self) {
+        self.state = self.state.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.inc);
+    }
+
+    fn next(&mut self) -> u32 {
+        let old = self.state;
+        self.step();
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+}
+
+/// Scramble a seed, so that close seeds give unrelated generators.
+fn mix(seed: u64) -> u64 {
+    let z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Source of the random numbers of a render.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sampler {
+    rng: Pcg32,
+    /// Numbers of the current pixel sample, drawn before those of the generator, last first.
+    pending: Vec<u32>,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Sampler { rng: Pcg32::new(mix(seed), DEFAULT_STREAM), pending: Vec::new() }
+    }
+
+    /// A sampler seeded by the operating system, for random numbers nobody needs to
+    /// reproduce.
+    pub fn from_entropy() -> Self {
+        Sampler::new(rand::random())
+    }
+
+    /// Random numbers of row `j` of a block of work of a render: a progressive pass or a tile.
+    ///
+    /// Each row gets its own sampler, so the samples do not depend on which thread traces
+    /// which row.
+    pub(crate) fn for_row(seed: u64, block: usize, j: usize) -> Self {
+        let block = (block as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let row = (j as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+        Sampler::new(seed ^ block ^ row)
+    }
+
+    /// Random numbers drawn from a ray and a distance along it, for the objects which need
+    /// some to be hit: the same ray always hits them the same way.
+    pub(crate) fn for_ray(r: &Ray, t: f32) -> Self {
+        let values = [r.orig.x, r.orig.y, r.orig.z, r.dir.x, r.dir.y, r.dir.z, r.time, t];
+        let seed = values.iter().fold(0u64, |hash, v| {
+            (hash ^ u64::from(v.to_bits())).wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(31)
+        });
+        Sampler::new(seed)
+    }
+
+    /// Start sample `index` of the `count` samples of pixel `(i, j)`: the next numbers drawn
+    /// are the dimensions of the sample in `pattern`, then those of the generator.
+    pub(crate) fn start_sample(
+        &mut self, pattern: &dyn PixelSampler, pixel: (usize, usize), index: usize, count: usize,
+    ) {
+        self.pending.clear();
+        let dims = (0..pattern.dimensions()).rev();
+        // Kept as the 24 bits of the floats drawn from them.
+        let to_u32 = |v: f32| ((v * 16_777_216.0) as u32).min(0xff_ffff) << 8;
+        self.pending.extend(dims.map(|dim| to_u32(pattern.sample(pixel, index, count, dim))));
+    }
+
+    /// End the samples of a pixel: drop the numbers of the last sample it did not draw.
+    pub(crate) fn end_sample(&mut self) {
+        self.pending.clear();
+    }
+}
+
+impl RngCore for Sampler {
+    fn next_u32(&mut self) -> u32 {
+        self.pending.pop().unwrap_or_else(|| self.rng.next())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = u64::from(self.rng.next());
+        (u64::from(self.rng.next()) << 32) | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.rng.next().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Number of dimensions of each pixel sample spread by the low-discrepancy samplers.
+const SAMPLE_DIMENSIONS: usize = 16;
+
+/// Bases of the dimensions of [`HaltonSampler`], the first primes.
+const HALTON_BASES: [u32; SAMPLE_DIMENSIONS] =
+    [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+
+/// Where the samples of a pixel fall: the numbers of each dimension of each sample.
+///
+/// The dimensions are drawn in order by the tracing of a sample, the first two are the
+/// position in the pixel. Two consecutive dimensions, starting from an even one, are spread
+/// together over the square.
+pub trait PixelSampler: fmt::Debug + Send + Sync {
+    /// Dimension `dim` of sample `index` of the `count` samples of pixel `(i, j)`, in `[0;1)`.
+    fn sample(&self, pixel: (usize, usize), index: usize, count: usize, dim: usize) -> f32;
+
+    /// Number of dimensions given by [`PixelSampler::sample`] before the random generator
+    /// takes over.
+    fn dimensions(&self) -> usize {
+        SAMPLE_DIMENSIONS
+    }
+}
+
+/// Independent random numbers. They are drawn from the random generator of the render,
+/// except when asked directly.
+#[derive(Debug, Copy, Clone)]
+pub struct UniformSampler {
+    seed: u32,
+}
+
+impl PixelSampler for UniformSampler {
+    fn sample(&self, pixel: (usize, usize), index: usize, _count: usize, dim: usize) -> f32 {
+        hash_float(index as u32, pattern_hash(self.seed, pixel, dim))
+    }
+
+    fn dimensions(&self) -> usize {
+        0
+    }
+}
+
+/// Correlated multi-jittered samples, see Kensler, "Correlated Multi-Jittered Sampling": each
+/// pair of dimensions is stratified on a grid of about `count` cells, and on each axis in
+/// `count` intervals.
+#[derive(Debug, Copy, Clone)]
+pub struct StratifiedSampler {
+    seed: u32,
+}
+
+impl PixelSampler for StratifiedSampler {
+    fn sample(&self, pixel: (usize, usize), index: usize, count: usize, dim: usize) -> f32 {
+        let (count, p) = (count as u32, pattern_hash(self.seed, pixel, dim / 2));
+        let m = ((count as f32).sqrt() as u32).max(1);
+        let n = count.div_ceil(m);
+        let s = permute(index as u32, count, p.wrapping_mul(0x5163_3e2d));
+        let v = if dim.is_multiple_of(2) {
+            let sx = permute(s % m, m, p.wrapping_mul(0x68bc_21eb));
+            let sy = permute(s / m, n, p.wrapping_mul(0x02e5_be93));
+            let jx = hash_float(s, p.wrapping_mul(0x967a_889b));
+            (sx as f32 + (sy as f32 + jx) / n as f32) / m as f32
+        } else {
+            (s as f32 + hash_float(s, p.wrapping_mul(0x368c_c8b7))) / count as f32
+        };
+        v.min(ONE_MINUS_EPSILON)
+    }
+}
+
+/// The Halton sequence: dimension `k` is the radical inverse of the sample index in the `k`-th
+/// prime base, shifted by a random offset per pixel so that the pixels do not all get the
+/// same samples.
+#[derive(Debug, Copy, Clone)]
+pub struct HaltonSampler {
+    seed: u32,
+}
+
+impl PixelSampler for HaltonSampler {
+    fn sample(&self, pixel: (usize, usize), index: usize, _count: usize, dim: usize) -> f32 {
+        let offset = hash_float(0, pattern_hash(self.seed, pixel, dim));
+        let v = radical_inverse(HALTON_BASES[dim], index as u32) + offset;
+        (v - v.floor()).min(ONE_MINUS_EPSILON)
+    }
+}
+
+/// Sobol (0,2)-sequences: each pair of dimensions is the first two dimensions of the Sobol
+/// sequence, with its bits scrambled and its samples shuffled per pixel and per pair. The first
+/// `2^k` samples have one sample in each cell of any `2^k` cell grid of power of two sides.
+#[derive(Debug, Copy, Clone)]
+pub struct SobolSampler {
+    seed: u32,
+}
+
+impl PixelSampler for SobolSampler {
+    fn sample(&self, pixel: (usize, usize), index: usize, count: usize, dim: usize) -> f32 {
+        let p = pattern_hash(self.seed, pixel, dim / 2);
+        let index = permute(index as u32, count as u32, p);
+        let bits = if dim.is_multiple_of(2) {
+            index.reverse_bits()
+        } else {
+            sobol_second_dimension(index)
+        };
+        // Flipping the same bits of all the samples keeps them stratified.
+        let scramble = pattern_hash(self.seed, pixel, SAMPLE_DIMENSIONS + dim);
+        ((bits ^ scramble) >> 8) as f32 / 16_777_216.0
+    }
+}
+
+/// How the samples of the pixels are spread, see [`PixelSampler`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    #[default]
+    Uniform,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] =
+        [SamplerKind::Uniform, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Uniform => "uniform",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    /// The sampler, its randomization depending on `seed`.
+    pub fn build(&self, seed: u64) -> Box<dyn PixelSampler> {
+        let seed = mix(seed) as u32;
+        match self {
+            SamplerKind::Uniform => Box::new(UniformSampler { seed }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler { seed }),
+            SamplerKind::Halton => Box::new(HaltonSampler { seed }),
+            SamplerKind::Sobol => Box::new(SobolSampler { seed }),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SamplerKind::ALL.into_iter().find(|k| k.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = SamplerKind::ALL.iter().map(|k| k.name()).collect();
+            format!("unknown sampler '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+/// Largest float below 1.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Randomization of a pair of dimensions, or a dimension, of a pixel.
+fn pattern_hash(seed: u32, (i, j): (usize, usize), dim: usize) -> u32 {
+    let pixel = ((i as u64) << 32) | j as u64;
+    mix(u64::from(seed) ^ mix(pixel) ^ (dim as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)) as u32
+}
+
+/// Position of `i` in a random permutation of `0..l` picked by `p`, see Kensler.
+fn permute(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l.saturating_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            return i.wrapping_add(p) % l;
+        }
+    }
+}
+
+/// Random float in `[0;1)` hashed from `i` and `p`, see Kensler.
+fn hash_float(mut i: u32, p: u32) -> f32 {
+    i ^= p;
+    i ^= i >> 17;
+    i ^= i >> 10;
+    i = i.wrapping_mul(0xb365_34e5);
+    i ^= i >> 12;
+    i ^= i >> 21;
+    i = i.wrapping_mul(0x93fc_4795);
+    i ^= 0xdf6e_307f;
+    i ^= i >> 17;
+    i = i.wrapping_mul(1 | p >> 18);
+    (i >> 8) as f32 / 16_777_216.0
+}
+
+/// Digits of `index` in `base`, mirrored around the radix point.
+fn radical_inverse(base: u32, mut index: u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let (mut reversed, mut inv_base_n) = (0u64, 1.0);
+    while index > 0 {
+        reversed = reversed * u64::from(base) + u64::from(index % base);
+        index /= base;
+        inv_base_n *= inv_base;
+    }
+    (reversed as f64 * inv_base_n) as f32
+}
+
+/// Second dimension of the Sobol sequence, as the bits of a fraction.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let (mut v, mut bits) = (1u32 << 31, 0);
+    while index != 0 {
+        if index & 1 != 0 {
+            bits ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    bits
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Point, Vec3};
+    use crate::ray::Ray;
+    use crate::sampler::{
+        radical_inverse, Pcg32, PixelSampler, Sampler, SamplerKind, SAMPLE_DIMENSIONS,
+    };
+    use rand::{Rng, RngCore};
+
+    /// The `count` points of dimensions `dim` and `dim + 1` of a pixel.
+    fn points(
+        sampler: &dyn PixelSampler, pixel: (usize, usize), count: usize, dim: usize,
+    ) -> Vec<(f32, f32)> {
+        let point =
+            |k| (sampler.sample(pixel, k, count, dim), sampler.sample(pixel, k, count, dim + 1));
+        (0..count).map(point).collect()
+    }
+
+    /// Whether each of the `nx` by `ny` cells of the square holds exactly one of the points.
+    fn one_per_cell(points: &[(f32, f32)], nx: usize, ny: usize) -> bool {
+        let mut cells = vec![0; nx * ny];
+        for &(x, y) in points {
+            cells[(y * ny as f32) as usize * nx + (x * nx as f32) as usize] += 1;
+        }
+        cells.iter().all(|&c| c == 1)
+    }
+
+    #[test]
+    fn test_pcg32_reference_output() {
+        // First outputs of the reference implementation, pcg32-demo seeded with (42, 54).
+        let mut rng = Pcg32::new(42, 54);
+        let expected = [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e];
+        assert_eq!(expected.map(|_| rng.next()), expected);
+    }
+
+    #[test]
+    fn test_uniform_numbers() {
+        let mut sampler = Sampler::new(0);
+        let values: Vec<f32> = (0..10_000).map(|_| sampler.gen()).collect();
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert!((mean - 0.5).abs() < 0.01, "{mean}");
+
+        let mut bytes = [0u8; 7];
+        sampler.fill_bytes(&mut bytes);
+        assert!(bytes.iter().any(|&b| b != 0));
+    }
+
+    #[test]
+    fn test_same_seed_same_numbers() {
+        let draw = |mut sampler: Sampler| (0..8).map(|_| sampler.gen()).collect::<Vec<f32>>();
+        assert_eq!(draw(Sampler::new(5)), draw(Sampler::new(5)));
+        assert_ne!(draw(Sampler::new(5)), draw(Sampler::new(6)));
+        assert_eq!(draw(Sampler::for_row(5, 1, 2)), draw(Sampler::for_row(5, 1, 2)));
+        assert_ne!(draw(Sampler::for_row(5, 1, 2)), draw(Sampler::for_row(5, 2, 1)));
+
+        let r = Ray::new(Point::ZERO, Vec3::UNIT_Z);
+        assert_eq!(draw(Sampler::for_ray(&r, 1.0)), draw(Sampler::for_ray(&r, 1.0)));
+        assert_ne!(draw(Sampler::for_ray(&r, 1.0)), draw(Sampler::for_ray(&r, 2.0)));
+        let later = Ray { time: 0.5, ..r };
+        assert_ne!(draw(Sampler::for_ray(&r, 1.0)), draw(Sampler::for_ray(&later, 1.0)));
+    }
+
+    #[test]
+    fn test_radical_inverse() {
+        assert_eq!([1, 2, 3, 4].map(|i| radical_inverse(2, i)), [0.5, 0.25, 0.75, 0.125]);
+        let thirds = [1, 2, 3, 4].map(|i| radical_inverse(3, i));
+        let expected = [1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0, 4.0 / 9.0];
+        for (v, e) in thirds.into_iter().zip(expected) {
+            assert_float_absolute_eq!(v, e, 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_samples_in_unit_square() {
+        for kind in SamplerKind::ALL {
+            let sampler = kind.build(3);
+            for dim in 0..SAMPLE_DIMENSIONS {
+                for count in [1, 7, 16, 100] {
+                    for k in 0..count {
+                        let v = sampler.sample((5, 8), k, count, dim);
+                        assert!((0.0..1.0).contains(&v), "{kind} {dim} {count} {k}: {v}");
+                        assert_eq!(v, kind.build(3).sample((5, 8), k, count, dim));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stratified_samples() {
+        let sampler = SamplerKind::Stratified.build(1);
+        for dim in [0, 2, 14] {
+            let points = points(sampler.as_ref(), (3, 4), 16, dim);
+            // One sample per cell of the 4x4 grid, and per row and column of the 16x16 grid.
+            assert!(one_per_cell(&points, 4, 4), "{points:?}");
+            assert!(one_per_cell(&points, 16, 1) && one_per_cell(&points, 1, 16), "{points:?}");
+        }
+        // Other pixels have other samples.
+        assert_ne!(
+            points(sampler.as_ref(), (3, 4), 16, 0),
+            points(sampler.as_ref(), (4, 3), 16, 0)
+        );
+    }
+
+    #[test]
+    fn test_halton_samples() {
+        let sampler = SamplerKind::Halton.build(1);
+        // Bases 2 and 3: the first 6 samples are in distinct cells of a 2x3 grid.
+        let points = points(sampler.as_ref(), (0, 0), 6, 0);
+        let offset = points[0];
+        let shifted: Vec<(f32, f32)> = points
+            .iter()
+            .map(|&(x, y)| ((x - offset.0).rem_euclid(1.0), (y - offset.1).rem_euclid(1.0)))
+            .collect();
+        assert!(one_per_cell(&shifted, 2, 3), "{shifted:?}");
+    }
+
+    #[test]
+    fn test_sobol_samples() {
+        let sampler = SamplerKind::Sobol.build(1);
+        for dim in [0, 6] {
+            let points = points(sampler.as_ref(), (2, 9), 16, dim);
+            // A (0,4,2)-net: one sample in each elementary interval of area 1/16.
+            for (nx, ny) in [(16, 1), (8, 2), (4, 4), (2, 8), (1, 16)] {
+                assert!(one_per_cell(&points, nx, ny), "{nx}x{ny}: {points:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_low_discrepancy_integration() {
+        // Area of the quarter disk, pi / 4, from 16 samples in each of 64 pixels.
+        let error = |kind: SamplerKind| {
+            let sampler = kind.build(7);
+            let mut squared = 0.0;
+            for pixel in (0..8).flat_map(|i| (0..8).map(move |j| (i, j))) {
+                let inside = points(sampler.as_ref(), pixel, 16, 2)
+                    .iter()
+                    .filter(|(x, y)| x * x + y * y < 1.0)
+                    .count();
+                squared += (inside as f32 / 16.0 - std::f32::consts::FRAC_PI_4).powi(2);
+            }
+            squared
+        };
+        let uniform = error(SamplerKind::Uniform);
+        for kind in [SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            assert!(error(kind) < 0.5 * uniform, "{kind}: {} vs {uniform}", error(kind));
+        }
+    }
+
+    #[test]
+    fn test_sample_numbers() {
+        let pattern = SamplerKind::Sobol.build(2);
+        let mut sampler = Sampler::new(0);
+        sampler.start_sample(pattern.as_ref(), (1, 2), 3, 16);
+        for dim in 0..SAMPLE_DIMENSIONS {
+            let expected = pattern.sample((1, 2), 3, 16, dim);
+            assert_float_absolute_eq!(sampler.gen::<f32>(), expected, 1e-6);
+        }
+        // Then the numbers of the generator.
+        let mut rng = Sampler::new(0);
+        assert_eq!(sampler.gen::<f32>(), rng.gen::<f32>());
+
+        // The uniform sampler leaves the generator alone.
+        let mut sampler = Sampler::new(0);
+        sampler.start_sample(SamplerKind::Uniform.build(2).as_ref(), (1, 2), 3, 16);
+        sampler.end_sample();
+        assert_eq!(sampler, Sampler::new(0));
+    }
+
+    #[test]
+    fn test_sampler_names() {
+        for kind in SamplerKind::ALL {
+            assert_eq!(kind.name().parse::<SamplerKind>(), Ok(kind));
+        }
+        let err = "random".parse::<SamplerKind>().unwrap_err();
+        assert_eq!(
+            err,
+            "unknown sampler 'random', expected one of: uniform, stratified, halton, sobol"
+        );
+    }
+}
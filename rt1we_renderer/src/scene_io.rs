@@ -0,0 +1,2586 @@
+//! Scene files: scenes described in JSON, loaded into a [`Scene`].
+//!
+//! # Format
+//! Materials are named, and objects refer to them by name:
+//! ```json
+//! {
+//!   "materials": [
+//!     { "name": "ground", "type": "lambertian", "albedo": [0.8, 0.8, 0.0] },
+//!     { "name": "steel", "type": "metal", "albedo": [0.8, 0.8, 0.8], "fuzz": 0.3 },
+//!     { "name": "glass", "type": "dielectric", "refraction_index": 1.5 }
+//!   ],
+//!   "objects": [
+//!     { "type": "sphere", "center": [0.0, -100.5, -1.0], "radius": 100.0, "material": "ground" },
+//!     { "type": "sphere", "center": [0.0, 0.0, -1.0], "radius": 0.5, "material": "glass" },
+//!     { "type": "plane", "point": [0.0, 0.0, -5.0], "normal": [0.0, 0.0, 1.0], "material": "steel" }
+//!   ]
+//! }
+//! ```
+//!
+//! A negative radius turns a sphere inside out. Inside a glass sphere, a slightly smaller one of
+//! negative radius and the same glass makes a hollow glass bubble:
+//! ```json
+//! [
+//!   { "type": "sphere", "center": [-1.0, 0.0, -1.0], "radius": 0.5, "material": "glass" },
+//!   { "type": "sphere", "center": [-1.0, 0.0, -1.0], "radius": -0.4, "material": "glass" }
+//! ]
+//! ```
+//!
+//! Instead of a `fuzz`, metals may have a `"roughness"` from 0 to 1, perceptually even: a
+//! roughness of 0.5 is a fuzz of 0.25, see [`MaterialParams::rough_metal`].
+//!
+//! A `microfacet` material is glossy, more realistic than a fuzzed metal: a `diffuse` base under
+//! facets reflecting `f0` at normal incidence, spread by a `roughness` from 0 to 1. Metals have
+//! a black base, plastics reflect about 4%:
+//! ```json
+//! [
+//!   { "name": "gold", "type": "microfacet", "diffuse": [0, 0, 0], "f0": [1.0, 0.78, 0.34],
+//!     "roughness": 0.3 },
+//!   { "name": "red plastic", "type": "microfacet", "diffuse": [0.8, 0.1, 0.1],
+//!     "f0": [0.04, 0.04, 0.04], "roughness": 0.5 }
+//! ]
+//! ```
+//!
+//! A `principled` material blends all of these but lights, from its `base_color` and factors
+//! from 0 to 1, see [`crate::principled`]. Those left out default to those of a plastic: no
+//! `metallic`, `"roughness": 0.5`, `"specular": 0.5`, no `clearcoat` nor `transmission`.
+//! ```json
+//! [
+//!   { "name": "car paint", "type": "principled", "base_color": [0.6, 0.0, 0.0],
+//!     "metallic": 0.3, "roughness": 0.4, "clearcoat": 1.0 },
+//!   { "name": "green glass", "type": "principled", "base_color": [0.8, 1.0, 0.8],
+//!     "roughness": 0.0, "transmission": 1.0 }
+//! ]
+//! ```
+//!
+//! A `subsurface` material is translucent, as skin or wax: the light walks inside the object
+//! before leaving it, about `mean_free_path` between two scattering events, per channel, and
+//! the object reflects `albedo` of it. Such objects must be closed, and not overlap others:
+//! `{ "name": "wax", "type": "subsurface", "albedo": [0.9, 0.8, 0.6],
+//! "mean_free_path": [0.1, 0.05, 0.02] }`.
+//!
+//! A `dielectric` may split white light into a rainbow in spectral renders, its index rising
+//! towards blue by a `dispersion`, see [`MaterialParams::Dielectric`]. For a diamond:
+//! `{ "name": "diamond", "type": "dielectric", "refraction_index": 2.42, "dispersion": 0.018 }`.
+//!
+//! Metals and dielectrics may be coated with a thin `film`, of a `thickness` in nanometers and
+//! a `refraction_index`, which colors their reflections as a soap bubble or an oil slick does,
+//! see [`crate::thin_film`]:
+//! ```json
+//! [
+//!   { "name": "bubble", "type": "dielectric", "refraction_index": 1.0,
+//!     "film": { "thickness": 400, "refraction_index": 1.33 } },
+//!   { "name": "tempered steel", "type": "metal", "albedo": [0.6, 0.6, 0.6], "fuzz": 0.05,
+//!     "film": { "thickness": 250, "refraction_index": 2.5 } }
+//! ]
+//! ```
+//!
+//! Triangles list their three vertices, and meshes index a list of vertices:
+//! ```json
+//! [
+//!   { "type": "triangle", "vertices": [[0, 0, -2], [1, 0, -2], [0, 1, -2]], "material": "steel" },
+//!   {
+//!     "type": "mesh",
+//!     "vertices": [[0, 0, -3], [1, 0, -3], [1, 1, -3], [0, 1, -3]],
+//!     "triangles": [[0, 1, 2], [0, 2, 3]],
+//!     "material": "ground"
+//!   }
+//! ]
+//! ```
+//!
+//! Meshes repeated many times, as the trees of a forest, are better described once in the
+//! optional `meshes`, by name, then placed by `instance` objects, see [`crate::instance`]: the
+//! mesh is scaled along each axis by a `scale`, `[1, 1, 1]` by default, turned by a `rotation`
+//! in degrees around the X, then the Y, then the Z axis, none by default, then moved by a
+//! `translation`. Instances share the triangles of their mesh, not its material:
+//! ```json
+//! {
+//!   "meshes": [
+//!     { "name": "tree", "vertices": [[0, 0, 0], [1, 0, 0], [0.5, 2, 0]],
+//!       "triangles": [[0, 1, 2]] }
+//!   ],
+//!   "objects": [
+//!     { "type": "instance", "mesh": "tree", "translation": [-2, 0, -3], "material": "ground" },
+//!     { "type": "instance", "mesh": "tree", "translation": [2, 0, -4], "rotation": [0, 45, 0],
+//!       "scale": [1, 1.5, 1], "material": "ground" }
+//!   ]
+//! }
+//! ```
+//!
+//! Optional `proxies` group objects by index, see [`crate::lod`]:
+//! `"proxies": [{ "members": [2, 3, 4] }]`.
+//!
+//! The optional `camera` and `background` default to those of the demo scene:
+//! ```json
+//! {
+//!   "camera": { "position": [-2.0, 2.0, 1.0], "target": [0.0, 0.0, -1.0] },
+//!   "background": { "type": "gradient", "horizon": [1.0, 1.0, 1.0], "zenith": [0.5, 0.7, 1.0] }
+//! }
+//! ```
+//! A `{ "type": "solid", "color": [r, g, b] }` background has a single color. A
+//! `physical_sky` is a clear daylight sky, see [`crate::sky`], its colors depending on the
+//! `sun_direction` and on the haze of the air, a `turbidity` from 2 to 10, 3 by default, its
+//! radiance scaled by an `intensity`, 1 by default. A `sun` in the same direction adds the disk
+//! of the sun: `{ "type": "physical_sky", "sun_direction": [1, 2, 1], "turbidity": 2.5 }`. An
+//! `environment` background surrounds the scene with a panorama, an equirectangular image
+//! preferably in high dynamic range, lighting it from all directions, see
+//! [`crate::environment`]. Its radiance is scaled by an `intensity`, 1 by default, and it
+//! turns around the vertical axis by a `rotation` in degrees:
+//! `{ "type": "environment", "path": "skies/park.hdr", "intensity": 1.5, "rotation": 90 }`.
+//!
+//! An optional `sun` lights outdoor scenes from a disk of the sky in a `direction`, an
+//! `angular_radius` in degrees wide: the wider, the softer its shadows. A surface facing it
+//! receives its `color` times its `intensity`:
+//! `"sun": { "direction": [1, 2, 1], "angular_radius": 0.27, "color": [1, 0.95, 0.9],
+//! "intensity": 3.0 }`.
+//!
+//! Optional point and spot `lights`, tiny bulbs lighting the diffuse surfaces through shadow
+//! rays, converge much faster than small emissive objects. A surface facing them, 1 away,
+//! receives their `color` times their `intensity`, less further away. Spots shine in a cone of
+//! half `angle` in degrees around their `direction`, fading out over a `blend` share of it,
+//! 0 by default:
+//! ```json
+//! "lights": [
+//!   { "type": "point", "position": [0, 3, 0], "color": [1, 1, 1], "intensity": 10 },
+//!   { "type": "spot", "position": [2, 3, -1], "direction": [0, -1, 0], "angle": 30,
+//!     "blend": 0.2, "color": [1, 0.8, 0.6], "intensity": 20 }
+//! ]
+//! ```
+//!
+//! The albedo of a lambertian material may also be a texture: a checkerboard of cubes `scale`
+//! wide, an image file mapped on the surface coordinates, as a map of the earth wrapped
+//! around a sphere, or marble with veins about `1 / scale` apart. Relative image paths start
+//! from the working directory.
+//! ```json
+//! [
+//!   { "type": "checker", "odd": [0, 0, 0], "even": [1, 1, 1], "scale": 0.5 },
+//!   { "type": "image", "path": "textures/earth.jpg" },
+//!   { "type": "noise", "color": [1, 1, 1], "scale": 4.0 }
+//! ]
+//! ```
+//!
+//! A `diffuse_light` material emits light instead of reflecting it, as the lamps of a room:
+//! `{ "name": "lamp", "type": "diffuse_light", "color": [1, 0.9, 0.8], "intensity": 4.0 }`.
+//! Such scenes usually have a black `solid` background, so that the lights alone light them.
+//!
+//! The camera may also have a lens, blurring what is out of focus, see [`Lens`]:
+//! `"aperture": 0.1` is the lens diameter, and `"focus_distance": 3.0` the distance to the
+//! plane in focus, the distance to the target by default. `"vfov": 40` narrows the vertical
+//! field of view, 90° by default.
+//!
+//! `"projection": "orthographic"` casts parallel rays, for technical renders: the image frames
+//! the plane in focus as the default `"perspective"` projection does. `"projection": "fisheye"`
+//! is an equidistant fisheye, its `vfov` spanning the height of the image.
+//! `"projection": "equirectangular"` renders a 360° panorama, for VR viewers or to light other
+//! scenes as an environment map, in images twice as wide as high.
+//!
+//! Moving spheres go in a straight line from `center0` at `time0` to `center1` at `time1`:
+//! ```json
+//! { "type": "moving_sphere", "center0": [0, 0, -1], "center1": [0, 0.5, -1],
+//!   "time0": 0.0, "time1": 1.0, "radius": 0.5, "material": "steel" }
+//! ```
+//! They are blurred when the camera shutter stays open a while, as with
+//! `"shutter": [0.0, 1.0]` on the camera. By default the shutter opens and closes at time 0.
+//!
+//! Rectangles aligned with the axes span two ranges, at a coordinate along the third axis,
+//! and boxes lie between two opposite corners, as the walls and the blocks of a Cornell box:
+//! ```json
+//! [
+//!   { "type": "xy_rect", "x": [0, 555], "y": [0, 555], "z": 555, "material": "white" },
+//!   { "type": "xz_rect", "x": [213, 343], "z": [227, 332], "y": 554, "material": "lamp" },
+//!   { "type": "yz_rect", "y": [0, 555], "z": [0, 555], "x": 0, "material": "red" },
+//!   { "type": "box", "min": [130, 0, 65], "max": [295, 165, 230], "material": "white" }
+//! ]
+//! ```
+//!
+//! Cylinders, cones and capsules stand on their `base`, around the axis going up to their
+//! `top`. Cylinders and cones are closed by flat caps, capsules by half spheres centered on
+//! `base` and `top`. Cones narrow from a `base_radius` to a `top_radius`, 0 by default for a
+//! pointed cone:
+//! ```json
+//! [
+//!   { "type": "cylinder", "base": [0, 0, -1], "top": [0, 1, -1], "radius": 0.3,
+//!     "material": "steel" },
+//!   { "type": "cone", "base": [1, 0, -1], "top": [1, 1, -1], "base_radius": 0.4,
+//!     "material": "red" },
+//!   { "type": "capsule", "base": [-1, 0.3, -1], "top": [-1, 0.7, -1], "radius": 0.3,
+//!     "material": "glass" }
+//! ]
+//! ```
+//!
+//! Tori are tubes of a `minor_radius` around a circle of a `major_radius`, at a `center` and
+//! across an `axis`, as a ring lying on the ground:
+//! `{ "type": "torus", "center": [0, 0.25, -1], "axis": [0, 1, 0], "major_radius": 1,
+//! "minor_radius": 0.25, "material": "gold" }`.
+//!
+//! An `sdf` object takes the `shape` of a signed distance field, see [`crate::sdf`]: spheres,
+//! boxes of a `half_size` along each axis, and the `smooth_union` of two shapes `a` and `b` or
+//! the `smooth_subtract` of `b` from `a`, blended over a `smoothness` distance, 0 by default.
+//! A box with rounded edges, its top carved by a sphere:
+//! ```json
+//! { "type": "sdf", "material": "red", "shape": {
+//!   "type": "smooth_subtract", "smoothness": 0.1,
+//!   "a": { "type": "box", "center": [0, 0, -2], "half_size": [0.5, 0.5, 0.5] },
+//!   "b": { "type": "sphere", "center": [0, 0.5, -2], "radius": 0.4 } } }
+//! ```
+//!
+//! A `heightfield` is a terrain, its heights the gray levels of the image at `path`, white
+//! the highest, see [`crate::heightfield`]. It spans `size` along X and Z from its `min`
+//! corner, where the top left pixel of the image lies, and rises by up to `size` along Y:
+//! ```json
+//! { "type": "heightfield", "path": "terrain/valley.png", "min": [-50, -2, -50],
+//!   "size": [100, 8, 100], "material": "ground" }
+//! ```
+//!
+//! A `constant_medium` fills a sphere or a box with smoke or fog, usually of an `isotropic`
+//! material, with an `albedo` as the lambertian ones. The denser the medium, the shorter the
+//! rays go through it:
+//! ```json
+//! { "type": "constant_medium", "boundary": { "type": "sphere", "center": [0, 0, -1],
+//!   "radius": 0.5 }, "density": 2.0, "material": "smoke" }
+//! ```
+//!
+//! [`save_scene`] writes a scene back in this format.
+use crate::cylinder::{Capsule, Cone, Cylinder};
+use crate::environment::EnvironmentMap;
+use crate::error::RtError;
+use crate::geometry::{Color, Vec3};
+use crate::heightfield::Heightfield;
+use crate::instance::{Instance, Transform};
+use crate::light::{DirectionalLight, PointLight, PunctualLight, SpotLight};
+use crate::medium::ConstantMedium;
+use crate::mesh::{Mesh, Triangle};
+use crate::principled::PrincipledParams;
+use crate::rect::{Cuboid, XyRect, XzRect, YzRect};
+use crate::registry::MaterialId;
+use crate::render::{Background, MaterialParams, MovingSphere, Object, Plane, Scene, Sphere};
+use crate::sdf::{Sdf, SdfObject};
+use crate::sky::PhysicalSky;
+use crate::texture::{ImageTexture, TextureParams};
+use crate::thin_film::ThinFilm;
+use crate::torus::Torus;
+use crate::view::{CameraProjection, Lens, OrbitView, VFOV};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize)]
+struct SceneFile {
+    materials: Vec<MaterialEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    meshes: Vec<MeshEntry>,
+    #[serde(default)]
+    objects: Vec<ObjectEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    proxies: Vec<ProxyEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    camera: Option<CameraEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    background: Option<BackgroundEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sun: Option<SunEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    lights: Vec<LightEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CameraEntry {
+    position: [f32; 3],
+    target: [f32; 3],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vfov: Option<f32>,
+    #[serde(default)]
+    aperture: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    focus_distance: Option<f32>,
+    #[serde(default)]
+    shutter: [f32; 2],
+    #[serde(default, skip_serializing_if = "ProjectionEntry::is_perspective")]
+    projection: ProjectionEntry,
+}
+
+#[derive(Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ProjectionEntry {
+    #[default]
+    Perspective,
+    Orthographic,
+    Fisheye,
+    Equirectangular,
+}
+
+impl ProjectionEntry {
+    fn is_perspective(&self) -> bool {
+        *self == ProjectionEntry::Perspective
+    }
+}
+
+impl From<&ProjectionEntry> for CameraProjection {
+    fn from(entry: &ProjectionEntry) -> Self {
+        match entry {
+            ProjectionEntry::Perspective => CameraProjection::Perspective,
+            ProjectionEntry::Orthographic => CameraProjection::Orthographic,
+            ProjectionEntry::Fisheye => CameraProjection::Fisheye,
+            ProjectionEntry::Equirectangular => CameraProjection::Equirectangular,
+        }
+    }
+}
+
+impl From<CameraProjection> for ProjectionEntry {
+    fn from(projection: CameraProjection) -> Self {
+        match projection {
+            CameraProjection::Perspective => ProjectionEntry::Perspective,
+            CameraProjection::Orthographic => ProjectionEntry::Orthographic,
+            CameraProjection::Fisheye => ProjectionEntry::Fisheye,
+            CameraProjection::Equirectangular => ProjectionEntry::Equirectangular,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BackgroundEntry {
+    Gradient {
+        horizon: [f32; 3],
+        zenith: [f32; 3],
+    },
+    Solid {
+        color: [f32; 3],
+    },
+    PhysicalSky {
+        sun_direction: [f32; 3],
+        #[serde(default = "default_turbidity")]
+        turbidity: f32,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+    Environment {
+        path: String,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        #[serde(default)]
+        rotation: f32,
+    },
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+fn default_turbidity() -> f32 {
+    PhysicalSky::default().turbidity
+}
+
+#[derive(Serialize, Deserialize)]
+struct SunEntry {
+    direction: [f32; 3],
+    angular_radius: f32,
+    color: [f32; 3],
+    intensity: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum LightEntry {
+    Point {
+        position: [f32; 3],
+        color: [f32; 3],
+        intensity: f32,
+    },
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        angle: f32,
+        #[serde(default)]
+        blend: f32,
+        color: [f32; 3],
+        intensity: f32,
+    },
+}
+
+impl LightEntry {
+    /// The light, or the reasons why it is not valid.
+    fn light(&self) -> Result<PunctualLight, Vec<String>> {
+        let mut errors = Vec::new();
+        let (position, color, intensity) = match self {
+            LightEntry::Point { position, color, intensity } => (position, color, intensity),
+            LightEntry::Spot { position, color, intensity, .. } => (position, color, intensity),
+        };
+        if !position.iter().all(|v| v.is_finite()) {
+            errors.push("position must be finite".to_string());
+        }
+        if !color.iter().all(|v| v.is_finite() && *v >= 0.0) {
+            errors.push("color channels must be positive".to_string());
+        }
+        if !(intensity.is_finite() && *intensity >= 0.0) {
+            errors.push("intensity must be positive or zero".to_string());
+        }
+        let (position, color, intensity) = (vec3(position), vec3(color), *intensity);
+        let light = match self {
+            LightEntry::Point { .. } => PointLight { position, color, intensity }.into(),
+            LightEntry::Spot { direction, angle, blend, .. } => {
+                let direction = vec3(direction);
+                if !(direction.len().is_finite() && direction.len() > 0.0) {
+                    errors.push("direction must be a non-zero vector".to_string());
+                }
+                if !(*angle > 0.0 && *angle <= 90.0) {
+                    errors.push("angle must be between 0 and 90 degrees".to_string());
+                }
+                if !(0.0..=1.0).contains(blend) {
+                    errors.push("blend must be between 0 and 1".to_string());
+                }
+                let (angle, blend) = (*angle, *blend);
+                SpotLight { position, direction, angle, blend, color, intensity }.into()
+            }
+        };
+        if errors.is_empty() {
+            Ok(light)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn from_light(light: &PunctualLight) -> Self {
+        match light {
+            PunctualLight::Point(light) => LightEntry::Point {
+                position: array(&light.position),
+                color: array(&light.color),
+                intensity: light.intensity,
+            },
+            PunctualLight::Spot(light) => LightEntry::Spot {
+                position: array(&light.position),
+                direction: array(&light.direction),
+                angle: light.angle,
+                blend: light.blend,
+                color: array(&light.color),
+                intensity: light.intensity,
+            },
+        }
+    }
+}
+
+/// A mesh shared by instances.
+#[derive(Serialize, Deserialize)]
+struct MeshEntry {
+    name: String,
+    vertices: Vec<[f32; 3]>,
+    triangles: Vec<[usize; 3]>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProxyEntry {
+    members: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MaterialEntry {
+    name: String,
+    #[serde(flatten)]
+    material: MaterialDesc,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum MaterialDesc {
+    Lambertian {
+        albedo: AlbedoEntry,
+    },
+    Metal {
+        albedo: [f32; 3],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        fuzz: Option<f32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        roughness: Option<f32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        film: Option<FilmEntry>,
+    },
+    Microfacet {
+        diffuse: [f32; 3],
+        f0: [f32; 3],
+        roughness: f32,
+    },
+    Principled {
+        base_color: [f32; 3],
+        #[serde(default)]
+        metallic: f32,
+        #[serde(default = "default_roughness")]
+        roughness: f32,
+        #[serde(default = "default_specular")]
+        specular: f32,
+        #[serde(default)]
+        clearcoat: f32,
+        #[serde(default)]
+        transmission: f32,
+    },
+    Subsurface {
+        albedo: [f32; 3],
+        mean_free_path: [f32; 3],
+    },
+    Dielectric {
+        refraction_index: f32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        dispersion: Option<f32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        film: Option<FilmEntry>,
+    },
+    DiffuseLight {
+        color: [f32; 3],
+        intensity: f32,
+    },
+    Isotropic {
+        albedo: AlbedoEntry,
+    },
+}
+
+fn default_roughness() -> f32 {
+    PrincipledParams::default().roughness
+}
+
+fn default_specular() -> f32 {
+    PrincipledParams::default().specular
+}
+
+/// A thin film coating a material.
+#[derive(Serialize, Deserialize)]
+struct FilmEntry {
+    thickness: f32,
+    refraction_index: f32,
+}
+
+/// A color, or a texture.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum AlbedoEntry {
+    Color([f32; 3]),
+    Texture(TextureEntry),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TextureEntry {
+    Checker { odd: [f32; 3], even: [f32; 3], scale: f32 },
+    Image { path: String },
+    Noise { color: [f32; 3], scale: f32 },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ObjectEntry {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+        material: String,
+    },
+    MovingSphere {
+        center0: [f32; 3],
+        center1: [f32; 3],
+        time0: f32,
+        time1: f32,
+        radius: f32,
+        material: String,
+    },
+    Plane {
+        point: [f32; 3],
+        normal: [f32; 3],
+        material: String,
+    },
+    Triangle {
+        vertices: [[f32; 3]; 3],
+        material: String,
+    },
+    Mesh {
+        vertices: Vec<[f32; 3]>,
+        triangles: Vec<[usize; 3]>,
+        material: String,
+    },
+    XyRect {
+        x: [f32; 2],
+        y: [f32; 2],
+        z: f32,
+        material: String,
+    },
+    XzRect {
+        x: [f32; 2],
+        z: [f32; 2],
+        y: f32,
+        material: String,
+    },
+    YzRect {
+        y: [f32; 2],
+        z: [f32; 2],
+        x: f32,
+        material: String,
+    },
+    Box {
+        min: [f32; 3],
+        max: [f32; 3],
+        material: String,
+    },
+    Cylinder {
+        base: [f32; 3],
+        top: [f32; 3],
+        radius: f32,
+        material: String,
+    },
+    Cone {
+        base: [f32; 3],
+        top: [f32; 3],
+        base_radius: f32,
+        #[serde(default)]
+        top_radius: f32,
+        material: String,
+    },
+    Capsule {
+        base: [f32; 3],
+        top: [f32; 3],
+        radius: f32,
+        material: String,
+    },
+    Torus {
+        center: [f32; 3],
+        axis: [f32; 3],
+        major_radius: f32,
+        minor_radius: f32,
+        material: String,
+    },
+    Sdf {
+        shape: SdfEntry,
+        material: String,
+    },
+    Heightfield {
+        path: String,
+        min: [f32; 3],
+        size: [f32; 3],
+        material: String,
+    },
+    Instance {
+        mesh: String,
+        translation: [f32; 3],
+        #[serde(default)]
+        rotation: [f32; 3],
+        #[serde(default = "default_scale")]
+        scale: [f32; 3],
+        material: String,
+    },
+    ConstantMedium {
+        boundary: BoundaryEntry,
+        density: f32,
+        material: String,
+    },
+}
+
+fn default_scale() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+/// A signed distance field.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SdfEntry {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+    Box {
+        center: [f32; 3],
+        half_size: [f32; 3],
+    },
+    SmoothUnion {
+        a: Box<SdfEntry>,
+        b: Box<SdfEntry>,
+        #[serde(default)]
+        smoothness: f32,
+    },
+    SmoothSubtract {
+        a: Box<SdfEntry>,
+        b: Box<SdfEntry>,
+        #[serde(default)]
+        smoothness: f32,
+    },
+}
+
+/// The shape of a constant medium.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BoundaryEntry {
+    Sphere { center: [f32; 3], radius: f32 },
+    Box { min: [f32; 3], max: [f32; 3] },
+}
+
+fn check_albedo(albedo: &[f32; 3], errors: &mut Vec<String>, at: &str) {
+    if !albedo.iter().all(|c| (0.0..=1.0).contains(c)) {
+        errors.push(format!("{at}: albedo channels must be between 0 and 1"));
+    }
+}
+
+impl MaterialDesc {
+    /// The material parameters, or the reasons they are invalid added to `errors`.
+    fn params(&self, errors: &mut Vec<String>, at: &str) -> MaterialParams {
+        let color = |c: &[f32; 3]| Color::new(c[0], c[1], c[2]);
+        match self {
+            MaterialDesc::Lambertian { albedo } => {
+                MaterialParams::Lambertian { albedo: albedo.params(errors, at) }
+            }
+            MaterialDesc::Metal { albedo, fuzz, roughness, film } => {
+                check_albedo(albedo, errors, at);
+                let (key, value) = match (fuzz, roughness) {
+                    (Some(fuzz), None) => ("fuzz", *fuzz),
+                    (None, Some(roughness)) => ("roughness", *roughness),
+                    _ => {
+                        errors.push(format!("{at}: metal needs either a fuzz or a roughness"));
+                        ("fuzz", 0.0)
+                    }
+                };
+                if !(0.0..=1.0).contains(&value) {
+                    errors.push(format!("{at}: {key} must be between 0 and 1"));
+                }
+                let mut metal = if roughness.is_some() {
+                    MaterialParams::rough_metal(color(albedo), value)
+                } else {
+                    MaterialParams::Metal { albedo: color(albedo), fuzz: value, film: None }
+                };
+                if let MaterialParams::Metal { film: coating, .. } = &mut metal {
+                    *coating = film.as_ref().map(|film| film.params(errors, at));
+                }
+                metal
+            }
+            MaterialDesc::Microfacet { diffuse, f0, roughness } => {
+                for (key, c) in [("diffuse", diffuse), ("f0", f0)] {
+                    if !c.iter().all(|v| (0.0..=1.0).contains(v)) {
+                        errors.push(format!("{at}: {key} channels must be between 0 and 1"));
+                    }
+                }
+                if !(0.0..=1.0).contains(roughness) {
+                    errors.push(format!("{at}: roughness must be between 0 and 1"));
+                }
+                let (diffuse, f0) = (color(diffuse), color(f0));
+                MaterialParams::Microfacet { diffuse, f0, roughness: *roughness }
+            }
+            &MaterialDesc::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                transmission,
+            } => {
+                if !base_color.iter().all(|v| (0.0..=1.0).contains(v)) {
+                    errors.push(format!("{at}: base_color channels must be between 0 and 1"));
+                }
+                let factors = [
+                    ("metallic", metallic),
+                    ("roughness", roughness),
+                    ("specular", specular),
+                    ("clearcoat", clearcoat),
+                    ("transmission", transmission),
+                ];
+                for (key, value) in factors {
+                    if !(0.0..=1.0).contains(&value) {
+                        errors.push(format!("{at}: {key} must be between 0 and 1"));
+                    }
+                }
+                let base_color = color(&base_color);
+                MaterialParams::Principled(PrincipledParams {
+                    base_color,
+                    metallic,
+                    roughness,
+                    specular,
+                    clearcoat,
+                    transmission,
+                })
+            }
+            MaterialDesc::Subsurface { albedo, mean_free_path } => {
+                check_albedo(albedo, errors, at);
+                if !mean_free_path.iter().all(|v| v.is_finite() && *v > 0.0) {
+                    errors.push(format!("{at}: mean_free_path channels must be positive"));
+                }
+                let mean_free_path = color(mean_free_path);
+                MaterialParams::Subsurface { albedo: color(albedo), mean_free_path }
+            }
+            MaterialDesc::Dielectric { refraction_index, dispersion, film } => {
+                let (refraction_index, dispersion) = (*refraction_index, *dispersion);
+                if !(refraction_index.is_finite() && refraction_index > 0.0) {
+                    errors.push(format!("{at}: refraction_index must be positive"));
+                }
+                let dispersion = dispersion.unwrap_or(0.0);
+                if !(dispersion.is_finite() && dispersion >= 0.0) {
+                    errors.push(format!("{at}: dispersion must be positive or zero"));
+                }
+                let film = film.as_ref().map(|film| film.params(errors, at));
+                MaterialParams::Dielectric { refraction_index, dispersion, film }
+            }
+            MaterialDesc::DiffuseLight { color: c, intensity } => {
+                if !c.iter().all(|v| v.is_finite() && *v >= 0.0) {
+                    errors.push(format!("{at}: light color channels must be positive"));
+                }
+                if !(intensity.is_finite() && *intensity >= 0.0) {
+                    errors.push(format!("{at}: light intensity must be positive or zero"));
+                }
+                MaterialParams::DiffuseLight { color: color(c), intensity: *intensity }
+            }
+            MaterialDesc::Isotropic { albedo } => {
+                MaterialParams::Isotropic { albedo: albedo.params(errors, at) }
+            }
+        }
+    }
+}
+
+impl FilmEntry {
+    /// The film parameters, or the reasons they are invalid added to `errors`.
+    fn params(&self, errors: &mut Vec<String>, at: &str) -> ThinFilm {
+        let FilmEntry { thickness, refraction_index } = *self;
+        if !(thickness.is_finite() && thickness >= 0.0) {
+            errors.push(format!("{at}: film thickness must be positive or zero"));
+        }
+        if !(refraction_index.is_finite() && refraction_index > 0.0) {
+            errors.push(format!("{at}: film refraction_index must be positive"));
+        }
+        ThinFilm { thickness, refraction_index }
+    }
+
+    fn from_params(film: &ThinFilm) -> Self {
+        FilmEntry { thickness: film.thickness, refraction_index: film.refraction_index }
+    }
+}
+
+impl SdfEntry {
+    /// The field, or the reasons it is invalid added to `errors`.
+    fn sdf(&self, errors: &mut Vec<String>, at: &str) -> Sdf {
+        match self {
+            SdfEntry::Sphere { center, radius } => {
+                if !center.iter().all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: center must be finite"));
+                }
+                if !(radius.is_finite() && *radius > 0.0) {
+                    errors.push(format!("{at}: radius must be positive"));
+                }
+                Sdf::Sphere { center: vec3(center), radius: *radius }
+            }
+            SdfEntry::Box { center, half_size } => {
+                if !center.iter().all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: center must be finite"));
+                }
+                if !half_size.iter().all(|c| c.is_finite() && *c > 0.0) {
+                    errors.push(format!("{at}: half_size must be positive"));
+                }
+                Sdf::Cuboid { center: vec3(center), half_size: vec3(half_size) }
+            }
+            SdfEntry::SmoothUnion { a, b, smoothness }
+            | SdfEntry::SmoothSubtract { a, b, smoothness } => {
+                if !(smoothness.is_finite() && *smoothness >= 0.0) {
+                    errors.push(format!("{at}: smoothness must be positive or zero"));
+                }
+                let a = a.sdf(errors, &format!("{at}.a"));
+                let b = b.sdf(errors, &format!("{at}.b"));
+                if matches!(self, SdfEntry::SmoothUnion { .. }) {
+                    a.smooth_union(b, *smoothness)
+                } else {
+                    a.smooth_subtract(b, *smoothness)
+                }
+            }
+        }
+    }
+
+    fn from_sdf(sdf: &Sdf) -> Self {
+        match sdf {
+            Sdf::Sphere { center, radius } => {
+                SdfEntry::Sphere { center: array(center), radius: *radius }
+            }
+            Sdf::Cuboid { center, half_size } => {
+                SdfEntry::Box { center: array(center), half_size: array(half_size) }
+            }
+            Sdf::SmoothUnion { a, b, smoothness } => SdfEntry::SmoothUnion {
+                a: Box::new(SdfEntry::from_sdf(a)),
+                b: Box::new(SdfEntry::from_sdf(b)),
+                smoothness: *smoothness,
+            },
+            Sdf::SmoothSubtract { a, b, smoothness } => SdfEntry::SmoothSubtract {
+                a: Box::new(SdfEntry::from_sdf(a)),
+                b: Box::new(SdfEntry::from_sdf(b)),
+                smoothness: *smoothness,
+            },
+        }
+    }
+}
+
+impl AlbedoEntry {
+    /// The texture parameters, or the reasons they are invalid added to `errors`.
+    fn params(&self, errors: &mut Vec<String>, at: &str) -> TextureParams {
+        match self {
+            AlbedoEntry::Color(albedo) => {
+                check_albedo(albedo, errors, at);
+                TextureParams::Solid(vec3(albedo))
+            }
+            AlbedoEntry::Texture(TextureEntry::Checker { odd, even, scale }) => {
+                check_albedo(odd, errors, at);
+                check_albedo(even, errors, at);
+                if !(scale.is_finite() && *scale > 0.0) {
+                    errors.push(format!("{at}: checker scale must be positive"));
+                }
+                TextureParams::Checker { odd: vec3(odd), even: vec3(even), scale: *scale }
+            }
+            AlbedoEntry::Texture(TextureEntry::Noise { color, scale }) => {
+                check_albedo(color, errors, at);
+                if !(scale.is_finite() && *scale > 0.0) {
+                    errors.push(format!("{at}: noise scale must be positive"));
+                }
+                TextureParams::Noise { color: vec3(color), scale: *scale }
+            }
+            AlbedoEntry::Texture(TextureEntry::Image { path }) => match ImageTexture::load(path) {
+                Ok(texture) => TextureParams::Image(texture),
+                Err(msg) => {
+                    errors.push(format!("{at}: {msg}"));
+                    TextureParams::Solid(Color::BLACK)
+                }
+            },
+        }
+    }
+}
+
+/// Check the ranges of a rectangle, and the coordinate along its normal.
+fn check_rect(ranges: [&[f32; 2]; 2], k: f32, errors: &mut Vec<String>, at: &str) {
+    if !(ranges.iter().all(|r| r.iter().all(|c| c.is_finite())) && k.is_finite()) {
+        errors.push(format!("{at}: coordinates must be finite"));
+    } else if !ranges.iter().all(|r| r[0] < r[1]) {
+        errors.push(format!("{at}: ranges must go from the lowest to the highest coordinate"));
+    }
+}
+
+/// Check the ends of the axis of a cylinder, a cone or a capsule.
+fn check_axis(base: &[f32; 3], top: &[f32; 3], errors: &mut Vec<String>, at: &str) {
+    if !base.iter().chain(top).all(|c| c.is_finite()) {
+        errors.push(format!("{at}: base and top must be finite"));
+    } else if base == top {
+        errors.push(format!("{at}: base and top must differ"));
+    }
+}
+
+/// The material named `name` in `scene`, or `None` with the error added to `errors`.
+fn material_id(
+    scene: &Scene, name: &str, errors: &mut Vec<String>, at: &str,
+) -> Option<MaterialId> {
+    match scene.materials().id(name) {
+        Ok(id) => Some(id),
+        Err(err) => {
+            errors.push(format!("{at}: {err}"));
+            None
+        }
+    }
+}
+
+fn vec3(c: &[f32; 3]) -> Vec3 {
+    Vec3::new(c[0], c[1], c[2])
+}
+
+fn array(v: &Vec3) -> [f32; 3] {
+    [v.x, v.y, v.z]
+}
+
+impl MaterialDesc {
+    /// Description of a material, or an error if a file cannot describe it.
+    fn from_params(params: &MaterialParams) -> Result<Self, String> {
+        Ok(match params {
+            MaterialParams::Lambertian { albedo } => {
+                MaterialDesc::Lambertian { albedo: AlbedoEntry::from_params(albedo)? }
+            }
+            MaterialParams::Metal { albedo, fuzz, film } => MaterialDesc::Metal {
+                albedo: array(albedo),
+                fuzz: Some(*fuzz),
+                roughness: None,
+                film: film.as_ref().map(FilmEntry::from_params),
+            },
+            &MaterialParams::Microfacet { diffuse, f0, roughness } => {
+                MaterialDesc::Microfacet { diffuse: array(&diffuse), f0: array(&f0), roughness }
+            }
+            MaterialParams::Principled(p) => MaterialDesc::Principled {
+                base_color: array(&p.base_color),
+                metallic: p.metallic,
+                roughness: p.roughness,
+                specular: p.specular,
+                clearcoat: p.clearcoat,
+                transmission: p.transmission,
+            },
+            MaterialParams::Subsurface { albedo, mean_free_path } => MaterialDesc::Subsurface {
+                albedo: array(albedo),
+                mean_free_path: array(mean_free_path),
+            },
+            &MaterialParams::Dielectric { refraction_index, dispersion, film } => {
+                let dispersion = (dispersion != 0.0).then_some(dispersion);
+                let film = film.as_ref().map(FilmEntry::from_params);
+                MaterialDesc::Dielectric { refraction_index, dispersion, film }
+            }
+            MaterialParams::DiffuseLight { color, intensity } => {
+                MaterialDesc::DiffuseLight { color: array(color), intensity: *intensity }
+            }
+            MaterialParams::Isotropic { albedo } => {
+                MaterialDesc::Isotropic { albedo: AlbedoEntry::from_params(albedo)? }
+            }
+        })
+    }
+}
+
+impl AlbedoEntry {
+    /// Description of a texture, or an error for the images which were not loaded from a
+    /// file.
+    fn from_params(params: &TextureParams) -> Result<Self, String> {
+        Ok(match params {
+            TextureParams::Solid(color) => AlbedoEntry::Color(array(color)),
+            TextureParams::Checker { odd, even, scale } => {
+                AlbedoEntry::Texture(TextureEntry::Checker {
+                    odd: array(odd),
+                    even: array(even),
+                    scale: *scale,
+                })
+            }
+            TextureParams::Image(texture) => {
+                let Some(path) = texture.path() else {
+                    return Err("an image texture built in memory has no file to refer to".into());
+                };
+                AlbedoEntry::Texture(TextureEntry::Image { path: path.to_string() })
+            }
+            TextureParams::Noise { color, scale } => {
+                AlbedoEntry::Texture(TextureEntry::Noise { color: array(color), scale: *scale })
+            }
+        })
+    }
+}
+
+impl ObjectEntry {
+    /// Description of an object made of the material named `material`, or an error if a
+    /// file cannot describe it.
+    ///
+    /// # Arguments
+    /// - `meshes` - The meshes shared by the instances described so far, named after their
+    ///   index, where the mesh of an instance is added if it is not there yet.
+    fn from_object(
+        object: &Object, material: String, meshes: &mut Vec<Arc<Mesh>>,
+    ) -> Result<Self, String> {
+        Ok(match object {
+            Object::Sphere(s) => {
+                ObjectEntry::Sphere { center: array(&s.center), radius: s.radius, material }
+            }
+            Object::MovingSphere(s) => ObjectEntry::MovingSphere {
+                center0: array(&s.center0),
+                center1: array(&s.center1),
+                time0: s.time0,
+                time1: s.time1,
+                radius: s.radius,
+                material,
+            },
+            Object::Plane(p) => {
+                ObjectEntry::Plane { point: array(&p.center), normal: array(&p.normal), material }
+            }
+            Object::Triangle(t) => {
+                ObjectEntry::Triangle { vertices: t.vertices.map(|v| array(&v)), material }
+            }
+            Object::Mesh(mesh) => ObjectEntry::Mesh {
+                vertices: mesh.vertices().iter().map(array).collect(),
+                triangles: mesh.indices().to_vec(),
+                material,
+            },
+            Object::XyRect(r) => {
+                ObjectEntry::XyRect { x: [r.x0, r.x1], y: [r.y0, r.y1], z: r.k, material }
+            }
+            Object::XzRect(r) => {
+                ObjectEntry::XzRect { x: [r.x0, r.x1], z: [r.z0, r.z1], y: r.k, material }
+            }
+            Object::YzRect(r) => {
+                ObjectEntry::YzRect { y: [r.y0, r.y1], z: [r.z0, r.z1], x: r.k, material }
+            }
+            Object::Cuboid(c) => {
+                ObjectEntry::Box { min: array(&c.min), max: array(&c.max), material }
+            }
+            Object::Cylinder(c) => ObjectEntry::Cylinder {
+                base: array(&c.base),
+                top: array(&c.top),
+                radius: c.radius,
+                material,
+            },
+            Object::Cone(c) => ObjectEntry::Cone {
+                base: array(&c.base),
+                top: array(&c.top),
+                base_radius: c.base_radius,
+                top_radius: c.top_radius,
+                material,
+            },
+            Object::Capsule(c) => ObjectEntry::Capsule {
+                base: array(&c.base),
+                top: array(&c.top),
+                radius: c.radius,
+                material,
+            },
+            Object::Torus(t) => ObjectEntry::Torus {
+                center: array(&t.center),
+                axis: array(&t.axis),
+                major_radius: t.major_radius,
+                minor_radius: t.minor_radius,
+                material,
+            },
+            Object::Sdf(object) => {
+                ObjectEntry::Sdf { shape: SdfEntry::from_sdf(&object.sdf), material }
+            }
+            Object::Heightfield(heightfield) => {
+                let Some(path) = heightfield.path() else {
+                    return Err("a heightfield built in memory has no file to refer to".into());
+                };
+                ObjectEntry::Heightfield {
+                    path: path.to_string(),
+                    min: array(&heightfield.min),
+                    size: array(&heightfield.size),
+                    material,
+                }
+            }
+            Object::Instance(instance) => {
+                let index = match meshes.iter().position(|m| Arc::ptr_eq(m, instance.mesh())) {
+                    Some(index) => index,
+                    None => {
+                        meshes.push(instance.mesh().clone());
+                        meshes.len() - 1
+                    }
+                };
+                let transform = instance.transform();
+                ObjectEntry::Instance {
+                    mesh: format!("mesh {index}"),
+                    translation: array(&transform.translation()),
+                    rotation: array(&transform.rotation()),
+                    scale: array(&transform.scale()),
+                    material,
+                }
+            }
+            Object::Medium(medium) => {
+                let boundary = match &*medium.boundary {
+                    Object::Sphere(s) => {
+                        BoundaryEntry::Sphere { center: array(&s.center), radius: s.radius }
+                    }
+                    Object::Cuboid(c) => {
+                        BoundaryEntry::Box { min: array(&c.min), max: array(&c.max) }
+                    }
+                    other => {
+                        return Err(format!(
+                            "a constant medium bounded by a {} cannot be saved, only spheres \
+                             and boxes can",
+                            other.name()
+                        ))
+                    }
+                };
+                ObjectEntry::ConstantMedium { boundary, density: medium.density, material }
+            }
+        })
+    }
+}
+
+/// Build a scene from its description.
+///
+/// # Returns
+/// The scene, or an error with one line per problem found in the description.
+pub fn parse_scene(text: &str) -> Result<Scene, RtError> {
+    let file: SceneFile = serde_json::from_str(text)?;
+    let mut errors = Vec::new();
+    let mut scene = Scene::empty();
+
+    if file.materials.is_empty() {
+        errors.push("a scene needs at least one material".to_string());
+    }
+    for (k, entry) in file.materials.iter().enumerate() {
+        let at = format!("materials[{k}] ({})", entry.name);
+        let params = entry.material.params(&mut errors, &at);
+        if let Err(err) = scene.add_named_material(&entry.name, &params) {
+            errors.push(format!("{at}: {err}"));
+        }
+    }
+
+    // Meshes which could not be built are kept as `None`, their instances being errors already.
+    let mut meshes = HashMap::new();
+    for (k, entry) in file.meshes.iter().enumerate() {
+        let at = format!("meshes[{k}] ({})", entry.name);
+        if !entry.vertices.iter().flatten().all(|c| c.is_finite()) {
+            errors.push(format!("{at}: vertices must be finite"));
+        }
+        // Instances replace the material of their mesh.
+        let vertices = entry.vertices.iter().map(vec3).collect();
+        let mesh = match Mesh::new(vertices, entry.triangles.clone(), MaterialId(0)) {
+            Ok(mesh) => Some(Arc::new(mesh)),
+            Err(msg) => {
+                errors.push(format!("{at}: {msg}"));
+                None
+            }
+        };
+        if meshes.insert(entry.name.as_str(), mesh).is_some() {
+            errors.push(format!("{at}: duplicate mesh name '{}'", entry.name));
+        }
+    }
+
+    for (k, object) in file.objects.iter().enumerate() {
+        let at = format!("objects[{k}]");
+        match object {
+            ObjectEntry::Sphere { center, radius, material } => {
+                if !center.iter().all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: center must be finite"));
+                }
+                if !(radius.is_finite() && *radius != 0.0) {
+                    errors.push(format!("{at}: radius must be finite and not zero"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(Sphere::new(vec3(center), *radius, id));
+            }
+            ObjectEntry::MovingSphere { center0, center1, time0, time1, radius, material } => {
+                if !center0.iter().chain(center1).all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: centers must be finite"));
+                }
+                if !(time0.is_finite() && time1.is_finite() && time0 <= time1) {
+                    errors.push(format!("{at}: time0 must not be after time1"));
+                }
+                if !(radius.is_finite() && *radius != 0.0) {
+                    errors.push(format!("{at}: radius must be finite and not zero"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                let (center0, center1) = (vec3(center0), vec3(center1));
+                scene
+                    .world_mut()
+                    .add(MovingSphere::new(center0, center1, *time0, *time1, *radius, id));
+            }
+            ObjectEntry::Plane { point, normal, material } => {
+                if !point.iter().all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: point must be finite"));
+                }
+                let normal = vec3(normal);
+                if !(normal.len().is_finite() && normal.len() > 0.0) {
+                    errors.push(format!("{at}: normal must be a non-zero vector"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(Plane::new(vec3(point), normal, id));
+            }
+            ObjectEntry::Triangle { vertices, material } => {
+                if !vertices.iter().flatten().all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: vertices must be finite"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                let [a, b, c] = vertices.map(|v| vec3(&v));
+                scene.world_mut().add(Triangle::new(a, b, c, id));
+            }
+            ObjectEntry::Mesh { vertices, triangles, material } => {
+                if !vertices.iter().flatten().all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: vertices must be finite"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                let vertices = vertices.iter().map(vec3).collect();
+                match Mesh::new(vertices, triangles.clone(), id) {
+                    Ok(mesh) => scene.world_mut().add(mesh),
+                    Err(msg) => errors.push(format!("{at}: {msg}")),
+                }
+            }
+            ObjectEntry::XyRect { x, y, z, material } => {
+                check_rect([x, y], *z, &mut errors, &at);
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(XyRect::new(x[0], x[1], y[0], y[1], *z, id));
+            }
+            ObjectEntry::XzRect { x, z, y, material } => {
+                check_rect([x, z], *y, &mut errors, &at);
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(XzRect::new(x[0], x[1], z[0], z[1], *y, id));
+            }
+            ObjectEntry::YzRect { y, z, x, material } => {
+                check_rect([y, z], *x, &mut errors, &at);
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(YzRect::new(y[0], y[1], z[0], z[1], *x, id));
+            }
+            ObjectEntry::Box { min, max, material } => {
+                if !min.iter().chain(max).all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: corners must be finite"));
+                } else if !(0..3).all(|k| min[k] < max[k]) {
+                    errors.push(format!("{at}: min must be below max on every axis"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(Cuboid::new(vec3(min), vec3(max), id));
+            }
+            ObjectEntry::Cylinder { base, top, radius, material } => {
+                check_axis(base, top, &mut errors, &at);
+                if !(radius.is_finite() && *radius > 0.0) {
+                    errors.push(format!("{at}: radius must be positive"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(Cylinder::new(vec3(base), vec3(top), *radius, id));
+            }
+            ObjectEntry::Cone { base, top, base_radius, top_radius, material } => {
+                check_axis(base, top, &mut errors, &at);
+                let radii = [base_radius, top_radius];
+                if !radii.iter().all(|r| r.is_finite() && **r >= 0.0) {
+                    errors.push(format!("{at}: radii must be positive or zero"));
+                } else if *base_radius == 0.0 && *top_radius == 0.0 {
+                    errors.push(format!("{at}: base_radius and top_radius cannot both be zero"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                let (base, top) = (vec3(base), vec3(top));
+                scene.world_mut().add(Cone::new(base, top, *base_radius, *top_radius, id));
+            }
+            ObjectEntry::Capsule { base, top, radius, material } => {
+                check_axis(base, top, &mut errors, &at);
+                if !(radius.is_finite() && *radius > 0.0) {
+                    errors.push(format!("{at}: radius must be positive"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(Capsule::new(vec3(base), vec3(top), *radius, id));
+            }
+            ObjectEntry::Torus { center, axis, major_radius, minor_radius, material } => {
+                if !center.iter().all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: center must be finite"));
+                }
+                let axis = vec3(axis);
+                if !(axis.len().is_finite() && axis.len() > 0.0) {
+                    errors.push(format!("{at}: axis must be a non-zero vector"));
+                }
+                let radii = [major_radius, minor_radius];
+                if !radii.iter().all(|r| r.is_finite() && **r > 0.0) {
+                    errors.push(format!("{at}: radii must be positive"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                let center = vec3(center);
+                scene.world_mut().add(Torus::new(center, axis, *major_radius, *minor_radius, id));
+            }
+            ObjectEntry::Sdf { shape, material } => {
+                let sdf = shape.sdf(&mut errors, &format!("{at}.shape"));
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(SdfObject::new(sdf, id));
+            }
+            ObjectEntry::Heightfield { path, min, size, material } => {
+                if !min.iter().all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: min must be finite"));
+                }
+                if !size.iter().all(|c| c.is_finite() && *c > 0.0) {
+                    errors.push(format!("{at}: size must be positive"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                match Heightfield::load(path, vec3(min), vec3(size), id) {
+                    Ok(heightfield) => scene.world_mut().add(heightfield),
+                    Err(msg) => errors.push(format!("{at}: {msg}")),
+                }
+            }
+            ObjectEntry::Instance { mesh, translation, rotation, scale, material } => {
+                if !translation.iter().chain(rotation).all(|c| c.is_finite()) {
+                    errors.push(format!("{at}: translation and rotation must be finite"));
+                }
+                if !scale.iter().all(|c| c.is_finite() && *c != 0.0) {
+                    errors.push(format!("{at}: scale must be finite and not zero"));
+                }
+                let shared = match meshes.get(mesh.as_str()) {
+                    Some(Some(shared)) => shared,
+                    Some(None) => continue,
+                    None => {
+                        errors.push(format!("{at}: unknown mesh '{mesh}'"));
+                        continue;
+                    }
+                };
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                let transform = Transform::new(vec3(translation), vec3(rotation), vec3(scale));
+                scene.world_mut().add(Instance::new(shared.clone(), transform, id));
+            }
+            ObjectEntry::ConstantMedium { boundary, density, material } => {
+                let boundary: Object = match boundary {
+                    BoundaryEntry::Sphere { center, radius } => {
+                        if !(center.iter().all(|c| c.is_finite()) && radius.is_finite()) {
+                            errors.push(format!("{at}: boundary must be finite"));
+                        }
+                        Sphere::new(vec3(center), *radius, MaterialId(0)).into()
+                    }
+                    BoundaryEntry::Box { min, max } => {
+                        if !min.iter().chain(max).all(|c| c.is_finite()) {
+                            errors.push(format!("{at}: boundary must be finite"));
+                        }
+                        Cuboid::new(vec3(min), vec3(max), MaterialId(0)).into()
+                    }
+                };
+                if !(density.is_finite() && *density > 0.0) {
+                    errors.push(format!("{at}: density must be positive"));
+                }
+                let Some(id) = material_id(&scene, material, &mut errors, &at) else {
+                    continue;
+                };
+                scene.world_mut().add(ConstantMedium::new(boundary, *density, id));
+            }
+        }
+    }
+
+    for (k, proxy) in file.proxies.iter().enumerate() {
+        if let Err(msg) = scene.add_proxy(&proxy.members) {
+            errors.push(format!("proxies[{k}]: {msg}"));
+        }
+    }
+
+    if let Some(camera) = &file.camera {
+        let (position, target) = (vec3(&camera.position), vec3(&camera.target));
+        if !camera.position.iter().chain(&camera.target).all(|c| c.is_finite()) {
+            errors.push("camera: position and target must be finite".to_string());
+        } else if position == target {
+            errors.push("camera: position and target must differ".to_string());
+        }
+        scene.set_view(&OrbitView::looking_at(&position, &target));
+        let vfov = camera.vfov.unwrap_or(VFOV);
+        if !(vfov > 0.0 && vfov < 180.0) {
+            errors.push("camera: vfov must be between 0 and 180 degrees".to_string());
+        }
+        if !(camera.aperture.is_finite() && camera.aperture >= 0.0) {
+            errors.push("camera: aperture must be positive or zero".to_string());
+        }
+        if camera.focus_distance.is_some_and(|d| !(d.is_finite() && d > 0.0)) {
+            errors.push("camera: focus_distance must be positive".to_string());
+        }
+        let lens = Lens {
+            vfov,
+            aperture: camera.aperture,
+            focus_distance: camera.focus_distance,
+            projection: (&camera.projection).into(),
+        };
+        scene.set_lens(&lens);
+        let [time0, time1] = camera.shutter;
+        if !(time0.is_finite() && time1.is_finite() && time0 <= time1) {
+            errors.push("camera: the shutter must open before it closes".to_string());
+        }
+        scene.set_shutter(time0, time1);
+    }
+
+    if let Some(background) = &file.background {
+        let background = match background {
+            BackgroundEntry::Gradient { horizon, zenith } => {
+                Background::Gradient { horizon: vec3(horizon), zenith: vec3(zenith) }
+            }
+            BackgroundEntry::Solid { color } => Background::Solid(vec3(color)),
+            BackgroundEntry::PhysicalSky { sun_direction, turbidity, intensity } => {
+                let sun_direction = vec3(sun_direction);
+                if !(sun_direction.len().is_finite() && sun_direction.len() > 0.0) {
+                    errors.push("background: sun_direction must be a non-zero vector".to_string());
+                }
+                if !(2.0..=10.0).contains(turbidity) {
+                    errors.push("background: turbidity must be between 2 and 10".to_string());
+                }
+                if !(intensity.is_finite() && *intensity >= 0.0) {
+                    errors.push("background: intensity must be positive or zero".to_string());
+                }
+                let (turbidity, intensity) = (*turbidity, *intensity);
+                Background::Physical(PhysicalSky { sun_direction, turbidity, intensity })
+            }
+            BackgroundEntry::Environment { path, intensity, rotation } => {
+                if !(intensity.is_finite() && *intensity >= 0.0) {
+                    errors.push("background: intensity must be positive or zero".to_string());
+                }
+                if !rotation.is_finite() {
+                    errors.push("background: rotation must be finite".to_string());
+                }
+                match EnvironmentMap::load(path) {
+                    Ok(map) => Background::Environment(
+                        map.with_intensity(*intensity).with_rotation(*rotation),
+                    ),
+                    Err(msg) => {
+                        errors.push(format!("background: {msg}"));
+                        Background::default()
+                    }
+                }
+            }
+        };
+        let colors = match &background {
+            Background::Gradient { horizon, zenith } => vec![*horizon, *zenith],
+            Background::Solid(color) => vec![*color],
+            Background::Physical(_) | Background::Environment(_) => vec![],
+        };
+        if !colors.iter().all(|c| [c.x, c.y, c.z].iter().all(|v| v.is_finite() && *v >= 0.0)) {
+            errors.push("background: colors must be positive".to_string());
+        }
+        scene.set_background(&background);
+    }
+
+    if let Some(sun) = &file.sun {
+        let direction = vec3(&sun.direction);
+        if !(direction.len().is_finite() && direction.len() > 0.0) {
+            errors.push("sun: direction must be a non-zero vector".to_string());
+        }
+        if !(sun.angular_radius > 0.0 && sun.angular_radius <= 90.0) {
+            errors.push("sun: angular_radius must be between 0 and 90 degrees".to_string());
+        }
+        if !sun.color.iter().all(|v| v.is_finite() && *v >= 0.0) {
+            errors.push("sun: color channels must be positive".to_string());
+        }
+        if !(sun.intensity.is_finite() && sun.intensity >= 0.0) {
+            errors.push("sun: intensity must be positive or zero".to_string());
+        }
+        scene.set_sun(Some(DirectionalLight {
+            direction,
+            angular_radius: sun.angular_radius,
+            color: vec3(&sun.color),
+            intensity: sun.intensity,
+        }));
+    }
+
+    for (k, entry) in file.lights.iter().enumerate() {
+        match entry.light() {
+            Ok(light) => scene.punctual_lights_mut().push(light),
+            Err(reasons) => errors.extend(reasons.iter().map(|r| format!("lights[{k}]: {r}"))),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(scene)
+    } else {
+        Err(RtError::Scene(errors.join("\n")))
+    }
+}
+
+/// Describe a scene, the inverse of [`parse_scene`].
+///
+/// # Returns
+/// The description, or an error if the scene holds something a file cannot describe, as an
+/// image texture which was not loaded from a file.
+pub fn scene_to_json(scene: &Scene) -> Result<String, RtError> {
+    let registry = scene.materials();
+    let name = |id: MaterialId| registry.name(id).expect("the material exists").to_string();
+    let mut materials = Vec::new();
+    // The materials of the proxies are averaged again when the proxies are loaded.
+    let proxy_materials: Vec<MaterialId> = scene.proxies().iter().map(|p| p.material_id).collect();
+    for id in registry.ids().filter(|id| !proxy_materials.contains(id)) {
+        let params = registry.params(id).expect("the material exists");
+        let material = MaterialDesc::from_params(&params)
+            .map_err(|msg| RtError::Scene(format!("materials[{id}]: {msg}")))?;
+        materials.push(MaterialEntry { name: name(id), material });
+    }
+    let mut objects = Vec::new();
+    let mut shared = Vec::new();
+    for (k, object) in scene.world().objects().iter().enumerate() {
+        let entry = ObjectEntry::from_object(object, name(object.material_id()), &mut shared)
+            .map_err(|msg| RtError::Scene(format!("objects[{k}]: {msg}")))?;
+        objects.push(entry);
+    }
+    let meshes = shared
+        .iter()
+        .enumerate()
+        .map(|(k, mesh)| MeshEntry {
+            name: format!("mesh {k}"),
+            vertices: mesh.vertices().iter().map(array).collect(),
+            triangles: mesh.indices().to_vec(),
+        })
+        .collect();
+    let proxies =
+        scene.proxies().iter().map(|p| ProxyEntry { members: p.members.clone() }).collect();
+
+    let lens = scene.lens();
+    let (time0, time1) = scene.shutter();
+    let camera = CameraEntry {
+        position: array(&scene.view().position()),
+        target: array(&scene.view().target),
+        vfov: Some(lens.vfov),
+        aperture: lens.aperture,
+        focus_distance: lens.focus_distance,
+        shutter: [time0, time1],
+        projection: lens.projection.into(),
+    };
+    let background = match scene.background() {
+        Background::Gradient { horizon, zenith } => {
+            BackgroundEntry::Gradient { horizon: array(horizon), zenith: array(zenith) }
+        }
+        Background::Solid(color) => BackgroundEntry::Solid { color: array(color) },
+        Background::Physical(sky) => BackgroundEntry::PhysicalSky {
+            sun_direction: array(&sky.sun_direction),
+            turbidity: sky.turbidity,
+            intensity: sky.intensity,
+        },
+        Background::Environment(map) => {
+            let Some(path) = map.path() else {
+                return Err(RtError::Scene(
+                    "background: an environment map built in memory has no file to refer to"
+                        .to_string(),
+                ));
+            };
+            BackgroundEntry::Environment {
+                path: path.to_string(),
+                intensity: map.intensity(),
+                rotation: map.rotation(),
+            }
+        }
+    };
+    let sun = scene.sun().map(|sun| SunEntry {
+        direction: array(&sun.direction),
+        angular_radius: sun.angular_radius,
+        color: array(&sun.color),
+        intensity: sun.intensity,
+    });
+    let lights = scene.punctual_lights().iter().map(LightEntry::from_light).collect();
+
+    let file = SceneFile {
+        materials,
+        meshes,
+        objects,
+        proxies,
+        camera: Some(camera),
+        background: Some(background),
+        sun,
+        lights,
+    };
+    Ok(serde_json::to_string_pretty(&file)?)
+}
+
+/// Write a scene file, see [`scene_to_json`].
+pub fn save_scene(scene: &Scene, fpath: &str) -> Result<(), RtError> {
+    let text = scene_to_json(scene)?;
+    fs::write(fpath, text).map_err(|e| RtError::io(fpath, e))
+}
+
+/// Read a scene file, see [`parse_scene`].
+pub fn load_scene(fpath: &str) -> Result<Scene, RtError> {
+    let text = fs::read_to_string(fpath).map_err(|e| RtError::io(fpath, e))?;
+    parse_scene(&text)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::cylinder::{Capsule, Cone, Cylinder};
+    use crate::environment::EnvironmentMap;
+    use crate::error::RtError;
+    use crate::geometry::{Color, Point, Vec3};
+    use crate::heightfield::Heightfield;
+    use crate::image::{ImageRGBA, ImageRGBF32};
+    use crate::imageio::{imwrite, imwrite_hdr};
+    use crate::instance::{Instance, Transform};
+    use crate::light::{DirectionalLight, PointLight, PunctualLight, SpotLight};
+    use crate::medium::ConstantMedium;
+    use crate::mesh::{Mesh, Triangle};
+    use crate::principled::PrincipledParams;
+    use crate::rect::{Cuboid, XyRect, XzRect, YzRect};
+    use crate::registry::MaterialId;
+    use crate::render::{Background, MaterialParams, MovingSphere, Object, Plane, Scene, Sphere};
+    use crate::scene_io::{load_scene, parse_scene, save_scene, scene_to_json};
+    use crate::sdf::{Sdf, SdfObject};
+    use crate::sky::PhysicalSky;
+    use crate::texture::{ImageTexture, TextureParams};
+    use crate::thin_film::ThinFilm;
+    use crate::torus::Torus;
+    use crate::view::{CameraProjection, Lens, OrbitView};
+    use std::sync::Arc;
+
+    const SCENE: &str = r#"{
+        "materials": [
+            { "name": "ground", "type": "lambertian", "albedo": [0.8, 0.8, 0.0] },
+            { "name": "steel", "type": "metal", "albedo": [0.8, 0.8, 0.8], "fuzz": 0.3 },
+            { "name": "glass", "type": "dielectric", "refraction_index": 1.5 }
+        ],
+        "objects": [
+            { "type": "sphere", "center": [0, -100.5, -1], "radius": 100, "material": "ground" },
+            { "type": "sphere", "center": [0, 0, -1], "radius": 0.5, "material": "glass" }
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_scene() {
+        let scene = parse_scene(SCENE).unwrap();
+
+        assert_eq!(scene.material_count(), 3);
+        assert_eq!(
+            scene.material(MaterialId(2)),
+            Some(MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.0, film: None })
+        );
+        let objects = scene.world().objects();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[1].material_id(), MaterialId(2));
+        assert_eq!(objects[1].as_sphere().unwrap().radius, 0.5);
+    }
+
+    #[test]
+    fn test_demo_scene_file_matches_the_demo() {
+        let scene = parse_scene(include_str!("../../scenes/demo.json")).unwrap();
+        let demo = Scene::demo();
+
+        assert_eq!(scene.world().objects(), demo.world().objects());
+        for object in scene.world().objects() {
+            assert_eq!(scene.material(object.material_id()), demo.material(object.material_id()));
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors_have_a_location() {
+        let err = parse_scene("{ \"materials\": [ }").err().unwrap().to_string();
+        assert!(err.contains("line 1"), "{err}");
+
+        let err = parse_scene(r#"{ "materials": [{ "name": "a", "type": "plastic" }] }"#);
+        assert!(err.err().unwrap().to_string().contains("plastic"));
+    }
+
+    #[test]
+    fn test_all_validation_errors_are_reported() {
+        let text = SCENE
+            .replace("\"fuzz\": 0.3", "\"fuzz\": 2.0")
+            .replace("\"radius\": 0.5", "\"radius\": 0")
+            .replace("\"material\": \"ground\"", "\"material\": \"grass\"");
+
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "materials[1] (steel): fuzz must be between 0 and 1",
+                "objects[0]: unknown material 'grass'",
+                "objects[1]: radius must be finite and not zero",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metal_roughness() {
+        let text = SCENE.replace("\"fuzz\": 0.3", "\"roughness\": 0.5");
+        let scene = parse_scene(&text).unwrap();
+        let steel =
+            MaterialParams::Metal { albedo: Color::new(0.8, 0.8, 0.8), fuzz: 0.25, film: None };
+        assert_eq!(scene.material(MaterialId(1)), Some(steel));
+        // Saved with its fuzz.
+        assert!(scene_to_json(&scene).unwrap().contains("\"fuzz\": 0.25"));
+
+        let err = |text: &str| parse_scene(text).err().unwrap().to_string();
+        let both = SCENE.replace("\"fuzz\": 0.3", "\"fuzz\": 0.3, \"roughness\": 0.5");
+        assert_eq!(err(&both), "materials[1] (steel): metal needs either a fuzz or a roughness");
+        let neither = SCENE.replace(", \"fuzz\": 0.3", "");
+        assert_eq!(err(&neither), "materials[1] (steel): metal needs either a fuzz or a roughness");
+        let rough = SCENE.replace("\"fuzz\": 0.3", "\"roughness\": 1.5");
+        assert_eq!(err(&rough), "materials[1] (steel): roughness must be between 0 and 1");
+    }
+
+    #[test]
+    fn test_microfacet() {
+        let gold =
+            r#""type": "microfacet", "diffuse": [0, 0, 0], "f0": [1, 0.5, 0], "roughness": 0.3"#;
+        let text =
+            SCENE.replace(r#""type": "metal", "albedo": [0.8, 0.8, 0.8], "fuzz": 0.3"#, gold);
+        let scene = parse_scene(&text).unwrap();
+        let (diffuse, f0) = (Color::BLACK, Color::new(1.0, 0.5, 0.0));
+        let gold = MaterialParams::Microfacet { diffuse, f0, roughness: 0.3 };
+        assert_eq!(scene.material(MaterialId(1)), Some(gold));
+        let saved = parse_scene(&scene_to_json(&scene).unwrap()).unwrap();
+        assert_eq!(saved.material(MaterialId(1)), scene.material(MaterialId(1)));
+
+        let text = text.replace("[1, 0.5, 0]", "[2, 0.5, 0]").replace("0.3", "-1");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "materials[1] (steel): f0 channels must be between 0 and 1",
+                "materials[1] (steel): roughness must be between 0 and 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_principled() {
+        let paint = r#""type": "principled", "base_color": [0.6, 0, 0], "clearcoat": 1"#;
+        let text =
+            SCENE.replace(r#""type": "metal", "albedo": [0.8, 0.8, 0.8], "fuzz": 0.3"#, paint);
+        let scene = parse_scene(&text).unwrap();
+        let paint = PrincipledParams {
+            base_color: Color::new(0.6, 0.0, 0.0),
+            clearcoat: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(scene.material(MaterialId(1)), Some(MaterialParams::Principled(paint)));
+        let saved = parse_scene(&scene_to_json(&scene).unwrap()).unwrap();
+        assert_eq!(saved.material(MaterialId(1)), scene.material(MaterialId(1)));
+
+        let text = text.replace("\"clearcoat\": 1", "\"metallic\": 2, \"transmission\": -1");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "materials[1] (steel): metallic must be between 0 and 1",
+                "materials[1] (steel): transmission must be between 0 and 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subsurface() {
+        let wax =
+            r#""type": "subsurface", "albedo": [0.9, 0.8, 0.6], "mean_free_path": [1, 0.5, 0.2]"#;
+        let text = SCENE.replace(r#""type": "metal", "albedo": [0.8, 0.8, 0.8], "fuzz": 0.3"#, wax);
+        let scene = parse_scene(&text).unwrap();
+        let wax = MaterialParams::Subsurface {
+            albedo: Color::new(0.9, 0.8, 0.6),
+            mean_free_path: Color::new(1.0, 0.5, 0.2),
+        };
+        assert_eq!(scene.material(MaterialId(1)), Some(wax));
+        let saved = parse_scene(&scene_to_json(&scene).unwrap()).unwrap();
+        assert_eq!(saved.material(MaterialId(1)), scene.material(MaterialId(1)));
+
+        let text = text.replace("0.2]", "0]");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(err, "materials[1] (steel): mean_free_path channels must be positive");
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let prism = r#""type": "dielectric", "refraction_index": 1.7, "dispersion": 0.013"#;
+        let text =
+            SCENE.replace(r#""type": "metal", "albedo": [0.8, 0.8, 0.8], "fuzz": 0.3"#, prism);
+        let scene = parse_scene(&text).unwrap();
+        let prism =
+            MaterialParams::Dielectric { refraction_index: 1.7, dispersion: 0.013, film: None };
+        assert_eq!(scene.material(MaterialId(1)), Some(prism.clone()));
+        let json = scene_to_json(&scene).unwrap();
+        assert_eq!(parse_scene(&json).unwrap().material(MaterialId(1)), Some(prism));
+        // Glass without dispersion is saved as before.
+        assert_eq!(json.matches("dispersion").count(), 1);
+
+        let text = text.replace("0.013", "-0.01");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(err, "materials[1] (steel): dispersion must be positive or zero");
+    }
+
+    #[test]
+    fn test_thin_film() {
+        let film = r#""film": { "thickness": 250, "refraction_index": 2.5 }"#;
+        let text = SCENE.replace(r#""fuzz": 0.3"#, &format!(r#""fuzz": 0.3, {film}"#));
+        let scene = parse_scene(&text).unwrap();
+        let film = Some(ThinFilm { thickness: 250.0, refraction_index: 2.5 });
+        let steel = MaterialParams::Metal { albedo: Color::new(0.8, 0.8, 0.8), fuzz: 0.3, film };
+        assert_eq!(scene.material(MaterialId(1)), Some(steel.clone()));
+        let saved = parse_scene(&scene_to_json(&scene).unwrap()).unwrap();
+        assert_eq!(saved.material(MaterialId(1)), Some(steel));
+
+        let bubble =
+            r#""refraction_index": 1.0, "film": { "thickness": -1, "refraction_index": 0 }"#;
+        let text = text.replace(r#""refraction_index": 1.5"#, bubble);
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "materials[2] (glass): film thickness must be positive or zero",
+                "materials[2] (glass): film refraction_index must be positive",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_checker_albedo() {
+        let checker = r#"{ "type": "checker", "odd": [0, 0, 0], "even": [1, 1, 1], "scale": 0.5 }"#;
+        let text = SCENE.replace("[0.8, 0.8, 0.0]", checker);
+        let scene = parse_scene(&text).unwrap();
+        let albedo = TextureParams::Checker { odd: Color::BLACK, even: Color::WHITE, scale: 0.5 };
+        assert_eq!(scene.material(MaterialId(0)), Some(MaterialParams::Lambertian { albedo }));
+
+        let text =
+            SCENE.replace("[0.8, 0.8, 0.0]", &checker.replace("0.5", "0").replace("[1,", "[2,"));
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "materials[0] (ground): albedo channels must be between 0 and 1",
+                "materials[0] (ground): checker scale must be positive",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diffuse_light() {
+        let lamp = r#""type": "diffuse_light", "color": [1, 0.5, 0], "intensity": 4"#;
+        let text = SCENE.replace(r#""type": "dielectric", "refraction_index": 1.5"#, lamp);
+        let scene = parse_scene(&text).unwrap();
+        let light =
+            MaterialParams::DiffuseLight { color: Color::new(1.0, 0.5, 0.0), intensity: 4.0 };
+        assert_eq!(scene.material(MaterialId(2)), Some(light));
+        assert_eq!(scene.light_count(), 1);
+
+        let err = parse_scene(&text.replace("[1, 0.5, 0]", "[1, -1, 0]").replace("4", "-4"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "materials[2] (glass): light color channels must be positive",
+                "materials[2] (glass): light intensity must be positive or zero",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_image_albedo() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("earth.png").to_string_lossy().to_string();
+        let mut image = ImageRGBA::new(4, 2);
+        image.put(0, 0, 255, 0, 0, 255);
+        imwrite(&path, &image).unwrap();
+
+        let albedo = format!(r#"{{ "type": "image", "path": "{path}" }}"#);
+        let scene = parse_scene(&SCENE.replace("[0.8, 0.8, 0.0]", &albedo)).unwrap();
+        let Some(MaterialParams::Lambertian { albedo: TextureParams::Image(texture) }) =
+            scene.material(MaterialId(0))
+        else {
+            panic!("the albedo is an image");
+        };
+        assert_eq!((texture.width(), texture.height()), (4, 2));
+
+        let missing = r#"{ "type": "image", "path": "does/not/exist.png" }"#;
+        let err =
+            parse_scene(&SCENE.replace("[0.8, 0.8, 0.0]", missing)).err().unwrap().to_string();
+        assert_eq!(err, "materials[0] (ground): does/not/exist.png: no such file");
+    }
+
+    #[test]
+    fn test_proxies() {
+        let text =
+            SCENE.replace("\"objects\"", "\"proxies\": [{ \"members\": [0, 1] }], \"objects\"");
+        let scene = parse_scene(&text).unwrap();
+        assert_eq!(scene.proxies()[0].members, vec![0, 1]);
+        assert_eq!(scene.material_count(), 4);
+
+        let text = SCENE.replace("\"objects\"", "\"proxies\": [{ \"members\": [5] }], \"objects\"");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(err, "proxies[0]: no object 5 to add to a proxy");
+    }
+
+    #[test]
+    fn test_hollow_spheres() {
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""objects": [
+            { "type": "sphere", "center": [0, 0, -1], "radius": -0.4, "material": "glass" },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        let bubble = Sphere::new(Point::new(0.0, 0.0, -1.0), -0.4, MaterialId(2));
+        assert_eq!(scene.world().objects()[0], Object::Sphere(bubble));
+        let saved = parse_scene(&scene_to_json(&scene).unwrap()).unwrap();
+        assert_eq!(saved.world().objects(), scene.world().objects());
+    }
+
+    #[test]
+    fn test_planes() {
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""objects": [
+            { "type": "plane", "point": [0, -1, 0], "normal": [0, 3, 0], "material": "steel" },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        let plane = Plane::new(Point::new(0.0, -1.0, 0.0), Vec3::UNIT_Y, MaterialId(1));
+        assert_eq!(scene.world().objects()[0], Object::Plane(plane));
+
+        let text = text.replace("\"normal\": [0, 3, 0]", "\"normal\": [0, 0, 0]");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(err, "objects[0]: normal must be a non-zero vector");
+    }
+
+    #[test]
+    fn test_rects_and_boxes() {
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""objects": [
+            { "type": "xy_rect", "x": [0, 2], "y": [1, 3], "z": -4, "material": "steel" },
+            { "type": "xz_rect", "x": [0, 2], "z": [1, 3], "y": 5, "material": "steel" },
+            { "type": "yz_rect", "y": [0, 2], "z": [1, 3], "x": 6, "material": "steel" },
+            { "type": "box", "min": [0, 0, 0], "max": [1, 2, 3], "material": "ground" },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        let objects = scene.world().objects();
+        assert_eq!(objects[0], XyRect::new(0.0, 2.0, 1.0, 3.0, -4.0, MaterialId(1)).into());
+        assert_eq!(objects[1], XzRect::new(0.0, 2.0, 1.0, 3.0, 5.0, MaterialId(1)).into());
+        assert_eq!(objects[2], YzRect::new(0.0, 2.0, 1.0, 3.0, 6.0, MaterialId(1)).into());
+        assert_eq!(
+            objects[3],
+            Cuboid::new(Point::ZERO, Point::new(1.0, 2.0, 3.0), MaterialId(0)).into()
+        );
+
+        let text =
+            text.replace("[1, 3], \"y\"", "[3, 1], \"y\"").replace("[1, 2, 3]", "[1, 2, -3]");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "objects[1]: ranges must go from the lowest to the highest coordinate",
+                "objects[3]: min must be below max on every axis",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cylinders_cones_and_capsules() {
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""objects": [
+            { "type": "cylinder", "base": [0, 0, 0], "top": [0, 1, 0], "radius": 0.5,
+              "material": "steel" },
+            { "type": "cone", "base": [0, 0, 0], "top": [0, 2, 0], "base_radius": 1,
+              "material": "steel" },
+            { "type": "capsule", "base": [0, 0, 0], "top": [1, 0, 0], "radius": 0.25,
+              "material": "ground" },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        let objects = scene.world().objects();
+        let (up, right) = (Point::new(0.0, 1.0, 0.0), Point::new(1.0, 0.0, 0.0));
+        assert_eq!(objects[0], Cylinder::new(Point::ZERO, up, 0.5, MaterialId(1)).into());
+        assert_eq!(objects[1], Cone::new(Point::ZERO, 2.0 * up, 1.0, 0.0, MaterialId(1)).into());
+        assert_eq!(objects[2], Capsule::new(Point::ZERO, right, 0.25, MaterialId(0)).into());
+
+        let text = text
+            .replace("[0, 1, 0], \"radius\": 0.5", "[0, 0, 0], \"radius\": 0.5")
+            .replace("\"base_radius\": 1", "\"base_radius\": 0")
+            .replace("\"radius\": 0.25", "\"radius\": -1");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "objects[0]: base and top must differ",
+                "objects[1]: base_radius and top_radius cannot both be zero",
+                "objects[2]: radius must be positive",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tori() {
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""objects": [
+            { "type": "torus", "center": [0, 1, 0], "axis": [0, 2, 0], "major_radius": 1,
+              "minor_radius": 0.25, "material": "steel" },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        let torus = Torus::new(Point::new(0.0, 1.0, 0.0), Vec3::UNIT_Y, 1.0, 0.25, MaterialId(1));
+        assert_eq!(scene.world().objects()[0], torus.into());
+
+        let text = text.replace("[0, 2, 0]", "[0, 0, 0]").replace("0.25", "-0.25");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "objects[0]: axis must be a non-zero vector",
+                "objects[0]: radii must be positive"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instances() {
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""meshes": [
+              { "name": "tree", "vertices": [[0, 0, 0], [1, 0, 0], [0, 1, 0]],
+                "triangles": [[0, 1, 2]] }
+            ],
+            "objects": [
+            { "type": "instance", "mesh": "tree", "translation": [1, 2, 3], "material": "steel" },
+            { "type": "instance", "mesh": "tree", "translation": [0, 0, 0],
+              "rotation": [0, 90, 0], "scale": [2, 2, 2], "material": "ground" },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        let (Object::Instance(a), Object::Instance(b)) =
+            (&scene.world().objects()[0], &scene.world().objects()[1])
+        else {
+            panic!("{:?}", scene.world().objects());
+        };
+        assert!(Arc::ptr_eq(a.mesh(), b.mesh()));
+        assert_eq!(
+            a.transform(),
+            &Transform::new(Vec3::new(1.0, 2.0, 3.0), Vec3::ZERO, Vec3::new(1.0, 1.0, 1.0))
+        );
+        assert_eq!((a.material_id, b.material_id), (MaterialId(1), MaterialId(0)));
+        assert_eq!(b.transform().rotation(), Vec3::new(0.0, 90.0, 0.0));
+
+        let text = text
+            .replace("[2, 2, 2]", "[2, 0, 2]")
+            .replace(
+                "\"mesh\": \"tree\", \"translation\": [1, 2, 3]",
+                "\"mesh\": \"bush\", \"translation\": [1, 2, 3]",
+            )
+            .replace("[[0, 1, 2]]", "[[0, 1, 3]]");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        let lines: Vec<_> = err.lines().collect();
+        assert_eq!(lines.len(), 3, "{err}");
+        assert!(lines[0].starts_with("meshes[0] (tree): "), "{err}");
+        assert_eq!(lines[1], "objects[0]: unknown mesh 'bush'");
+        assert_eq!(lines[2], "objects[1]: scale must be finite and not zero");
+
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""meshes": [
+              { "name": "tree", "vertices": [[0, 0, 0], [1, 0, 0], [0, 1, 0]],
+                "triangles": [[0, 1, 2]] },
+              { "name": "tree", "vertices": [[0, 0, 0], [1, 0, 0], [0, 1, 0]],
+                "triangles": [[0, 1, 2]] }
+            ],
+            "objects": ["#,
+        );
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(err, "meshes[1] (tree): duplicate mesh name 'tree'");
+    }
+
+    #[test]
+    fn test_sdf() {
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""objects": [
+            { "type": "sdf", "material": "steel", "shape": {
+              "type": "smooth_union", "smoothness": 0.2,
+              "a": { "type": "box", "center": [0, 0, 0], "half_size": [1, 1, 1] },
+              "b": { "type": "smooth_subtract",
+                "a": { "type": "sphere", "center": [0, 1, 0], "radius": 0.5 },
+                "b": { "type": "sphere", "center": [0, 2, 0], "radius": 0.5 } } } },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        let cuboid = Sdf::Cuboid { center: Point::ZERO, half_size: Vec3::new(1.0, 1.0, 1.0) };
+        let ball = |y: f32| Sdf::Sphere { center: Point::new(0.0, y, 0.0), radius: 0.5 };
+        let sdf = cuboid.smooth_union(ball(1.0).smooth_subtract(ball(2.0), 0.0), 0.2);
+        assert_eq!(scene.world().objects()[0], SdfObject::new(sdf, MaterialId(1)).into());
+
+        let text = text.replace("0.2,", "-0.2,").replace("[1, 1, 1]", "[1, 0, 1]");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "objects[0].shape: smoothness must be positive or zero",
+                "objects[0].shape.a: half_size must be positive",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heightfield() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("terrain.png").to_string_lossy().to_string();
+        let mut image = ImageRGBA::new(3, 2);
+        image.put(1, 0, 255, 255, 255, 255);
+        imwrite(&path, &image).unwrap();
+
+        let entry = format!(
+            r#""objects": [
+            {{ "type": "heightfield", "path": "{path}", "min": [-1, 0, -1], "size": [2, 0.5, 2],
+              "material": "ground" }},"#
+        );
+        let scene = parse_scene(&SCENE.replace("\"objects\": [", &entry)).unwrap();
+        let (min, size) = (Point::new(-1.0, 0.0, -1.0), Vec3::new(2.0, 0.5, 2.0));
+        let heightfield = Heightfield::load(&path, min, size, MaterialId(0)).unwrap();
+        assert_eq!(scene.world().objects()[0], heightfield.into());
+
+        let entry = entry.replace(&path, "does/not/exist.png").replace("0.5", "0");
+        let err = parse_scene(&SCENE.replace("\"objects\": [", &entry)).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "objects[0]: size must be positive",
+                "objects[0]: does/not/exist.png: no such file"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constant_medium() {
+        let text = SCENE.replace(
+            r#""type": "dielectric", "refraction_index": 1.5"#,
+            r#""type": "isotropic", "albedo": [0.2, 0.2, 0.2]"#,
+        );
+        let text = text.replace(
+            "\"objects\": [",
+            r#""objects": [
+            { "type": "constant_medium", "boundary": { "type": "box", "min": [0, 0, 0],
+              "max": [1, 1, 1] }, "density": 0.5, "material": "glass" },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        let albedo = Color::new(0.2, 0.2, 0.2).into();
+        assert_eq!(scene.material(MaterialId(2)), Some(MaterialParams::Isotropic { albedo }));
+        let boundary = Cuboid::new(Point::ZERO, Point::new(1.0, 1.0, 1.0), MaterialId(0));
+        assert_eq!(
+            scene.world().objects()[0],
+            ConstantMedium::new(boundary, 0.5, MaterialId(2)).into()
+        );
+
+        let err = parse_scene(&text.replace("\"density\": 0.5", "\"density\": 0"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(err, "objects[0]: density must be positive");
+    }
+
+    #[test]
+    fn test_meshes() {
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""objects": [
+            { "type": "triangle", "vertices": [[0, 0, 0], [1, 0, 0], [0, 1, 0]], "material": "steel" },
+            {
+                "type": "mesh",
+                "vertices": [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]],
+                "triangles": [[0, 1, 2], [0, 2, 3]],
+                "material": "steel"
+            },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        let objects = scene.world().objects();
+        assert_eq!(
+            objects[0],
+            Object::Triangle(Triangle::new(Point::ZERO, Vec3::UNIT_X, Vec3::UNIT_Y, MaterialId(1)))
+        );
+        let Object::Mesh(mesh) = &objects[1] else { panic!("{:?} is not a mesh", objects[1]) };
+        assert_eq!((mesh.len(), mesh.vertices().len(), mesh.material_id), (2, 4, MaterialId(1)));
+
+        let text = text.replace("[0, 2, 3]", "[0, 2, 4]");
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "objects[1]: triangle 1 refers to a missing vertex, the mesh has 4 vertices"
+        );
+    }
+
+    #[test]
+    fn test_camera_and_background() {
+        let scene = parse_scene(SCENE).unwrap();
+        assert_eq!(scene.view(), Scene::demo().view());
+        assert_eq!(scene.background(), &Background::default());
+
+        let text = SCENE.replace(
+            "\"objects\"",
+            r#""camera": { "position": [0, 3, 3], "target": [0, 0, -1] },
+            "background": { "type": "solid", "color": [0.1, 0.1, 0.2] },
+            "objects""#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        assert!((scene.view().position() - Point::new(0.0, 3.0, 3.0)).len() < 1e-5);
+        assert_eq!(scene.view().target, Point::new(0.0, 0.0, -1.0));
+        assert_eq!(scene.background(), &Background::Solid(Color::new(0.1, 0.1, 0.2)));
+        assert_eq!(scene.lens(), &Lens::default());
+
+        let text = SCENE.replace(
+            "\"objects\"",
+            r#""camera": { "position": [0, 0, 1], "target": [0, 0, 1] },
+            "background": { "type": "gradient", "horizon": [1, 1, 1], "zenith": [0, -1, 0] },
+            "objects""#,
+        );
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec!["camera: position and target must differ", "background: colors must be positive"]
+        );
+    }
+
+    #[test]
+    fn test_physical_sky_background() {
+        let background = r#""background": { "type": "physical_sky", "sun_direction": [1, 2, 1] },
+            "objects""#;
+        let scene = parse_scene(&SCENE.replace("\"objects\"", background)).unwrap();
+        let sky = PhysicalSky { sun_direction: Vec3::new(1.0, 2.0, 1.0), ..Default::default() };
+        assert_eq!(scene.background(), &Background::Physical(sky));
+        let saved = parse_scene(&scene_to_json(&scene).unwrap()).unwrap();
+        assert_eq!(saved.background(), scene.background());
+
+        let background = background.replace("[1, 2, 1]", "[0, 0, 0], \"turbidity\": 1");
+        let err = parse_scene(&SCENE.replace("\"objects\"", &background)).err().unwrap();
+        assert_eq!(
+            err.to_string().lines().collect::<Vec<_>>(),
+            vec![
+                "background: sun_direction must be a non-zero vector",
+                "background: turbidity must be between 2 and 10",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_environment_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sky.hdr").to_string_lossy().to_string();
+        let mut image = ImageRGBF32::new(8, 4);
+        image.put(2, 1, 5.0, 4.0, 3.0);
+        imwrite_hdr(&path, &image).unwrap();
+
+        let background = format!(
+            r#""background": {{ "type": "environment", "path": "{path}", "rotation": 45 }},
+            "objects""#
+        );
+        let scene = parse_scene(&SCENE.replace("\"objects\"", &background)).unwrap();
+        let Background::Environment(map) = scene.background() else {
+            panic!("{:?}", scene.background());
+        };
+        assert_eq!((map.path(), map.intensity(), map.rotation()), (Some(path.as_str()), 1.0, 45.0));
+        assert_eq!((map.width(), map.height()), (8, 4));
+        let saved = parse_scene(&scene_to_json(&scene).unwrap()).unwrap();
+        assert_eq!(saved.background(), scene.background());
+
+        let background = background.replace("45", "45, \"intensity\": -1").replace("sky", "none");
+        let err = parse_scene(&SCENE.replace("\"objects\"", &background)).err().unwrap();
+        let err = err.to_string();
+        let lines: Vec<&str> = err.lines().collect();
+        assert_eq!(lines[0], "background: intensity must be positive or zero");
+        assert!(lines[1].starts_with("background: ") && lines[1].contains("none.hdr"), "{err}");
+
+        // Panoramas built in memory cannot be saved.
+        let mut scene = scene;
+        scene.set_background(&Background::Environment(EnvironmentMap::new(image).unwrap()));
+        assert!(scene_to_json(&scene).is_err());
+    }
+
+    #[test]
+    fn test_sun() {
+        assert_eq!(parse_scene(SCENE).unwrap().sun(), None);
+        let sun = r#""sun": {
+                "direction": [1, 2, 1], "angular_radius": 2.5,
+                "color": [1, 0.9, 0.8], "intensity": 3
+            },
+            "objects""#;
+        let scene = parse_scene(&SCENE.replace("\"objects\"", sun)).unwrap();
+        let expected = DirectionalLight {
+            direction: Vec3::new(1.0, 2.0, 1.0),
+            angular_radius: 2.5,
+            color: Color::new(1.0, 0.9, 0.8),
+            intensity: 3.0,
+        };
+        assert_eq!(scene.sun(), Some(&expected));
+        let saved = parse_scene(&scene_to_json(&scene).unwrap()).unwrap();
+        assert_eq!(saved.sun(), Some(&expected));
+
+        let sun = sun.replace("[1, 2, 1]", "[0, 0, 0]").replace("2.5", "0");
+        let err = parse_scene(&SCENE.replace("\"objects\"", &sun)).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "sun: direction must be a non-zero vector",
+                "sun: angular_radius must be between 0 and 90 degrees",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_punctual_lights() {
+        assert!(parse_scene(SCENE).unwrap().punctual_lights().is_empty());
+        let lights = r#""lights": [
+                { "type": "point", "position": [0, 3, 0], "color": [1, 1, 1], "intensity": 10 },
+                { "type": "spot", "position": [2, 3, -1], "direction": [0, -1, 0],
+                  "angle": 30, "color": [1, 0.8, 0.6], "intensity": 20 }
+            ],
+            "objects""#;
+        let scene = parse_scene(&SCENE.replace("\"objects\"", lights)).unwrap();
+        let expected: Vec<PunctualLight> = vec![
+            PointLight {
+                position: Point::new(0.0, 3.0, 0.0),
+                color: Color::WHITE,
+                intensity: 10.0,
+            }
+            .into(),
+            SpotLight {
+                position: Point::new(2.0, 3.0, -1.0),
+                direction: -Vec3::UNIT_Y,
+                angle: 30.0,
+                blend: 0.0,
+                color: Color::new(1.0, 0.8, 0.6),
+                intensity: 20.0,
+            }
+            .into(),
+        ];
+        assert_eq!(scene.punctual_lights(), expected);
+        let saved = parse_scene(&scene_to_json(&scene).unwrap()).unwrap();
+        assert_eq!(saved.punctual_lights(), expected);
+
+        let lights = lights.replace("10 }", "-1 }").replace("30", "120");
+        let err = parse_scene(&SCENE.replace("\"objects\"", &lights)).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "lights[0]: intensity must be positive or zero",
+                "lights[1]: angle must be between 0 and 90 degrees",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_camera_lens() {
+        let camera = r#""camera": {
+                "position": [0, 0, 1], "target": [0, 0, -1], "aperture": 0.2, "focus_distance": 2.5
+            },
+            "objects""#;
+        let scene = parse_scene(&SCENE.replace("\"objects\"", camera)).unwrap();
+        let lens =
+            Lens { vfov: 90.0, aperture: 0.2, focus_distance: Some(2.5), ..Default::default() };
+        assert_eq!(scene.lens(), &lens);
+
+        let camera = r#""camera": { "position": [0, 0, 1], "target": [0, 0, -1], "vfov": 40 },
+            "objects""#;
+        let scene = parse_scene(&SCENE.replace("\"objects\"", camera)).unwrap();
+        assert_eq!(scene.lens(), &Lens { vfov: 40.0, ..Default::default() });
+
+        let camera = r#""camera": {
+                "position": [0, 0, 1], "target": [0, 0, -1], "projection": "orthographic"
+            },
+            "objects""#;
+        let scene = parse_scene(&SCENE.replace("\"objects\"", camera)).unwrap();
+        assert_eq!(scene.lens().projection, CameraProjection::Orthographic);
+        let json = scene_to_json(&scene).unwrap();
+        assert!(json.contains("\"projection\": \"orthographic\""), "{json}");
+        let camera = r#""camera": {
+                "position": [0, 0, 1], "target": [0, 0, -1], "projection": "cylindrical"
+            },
+            "objects""#;
+        assert!(parse_scene(&SCENE.replace("\"objects\"", camera)).is_err());
+
+        let camera = r#""camera": {
+                "position": [0, 0, 1], "target": [0, 0, -1], "vfov": 180, "aperture": -1,
+                "focus_distance": 0
+            },
+            "objects""#;
+        let err = parse_scene(&SCENE.replace("\"objects\"", camera)).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "camera: vfov must be between 0 and 180 degrees",
+                "camera: aperture must be positive or zero",
+                "camera: focus_distance must be positive"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_motion_blur() {
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""camera": { "position": [0, 0, 1], "target": [0, 0, -1], "shutter": [0, 1] },
+            "objects": [
+                { "type": "moving_sphere", "center0": [0, 0, -1], "center1": [1, 0, -1],
+                  "time0": 0, "time1": 1, "radius": 0.5, "material": "steel" },"#,
+        );
+        let scene = parse_scene(&text).unwrap();
+        assert_eq!(scene.shutter(), (0.0, 1.0));
+        let moving = Point::new(1.0, 0.0, -1.0);
+        let sphere =
+            MovingSphere::new(Point::new(0.0, 0.0, -1.0), moving, 0.0, 1.0, 0.5, MaterialId(1));
+        assert_eq!(scene.world().objects()[0], Object::MovingSphere(sphere));
+        assert_eq!(parse_scene(SCENE).unwrap().shutter(), (0.0, 0.0));
+
+        let text = SCENE.replace(
+            "\"objects\": [",
+            r#""camera": { "position": [0, 0, 1], "target": [0, 0, -1], "shutter": [1, 0] },
+            "objects": [
+                { "type": "moving_sphere", "center0": [0, 0, -1], "center1": [1, 0, -1],
+                  "time0": 1, "time1": 0, "radius": 0.5, "material": "steel" },"#,
+        );
+        let err = parse_scene(&text).err().unwrap().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            vec![
+                "objects[0]: time0 must not be after time1",
+                "camera: the shutter must open before it closes"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_scene() {
+        let dir = tempfile::tempdir().unwrap();
+        let image_path = dir.path().join("earth.png").to_string_lossy().to_string();
+        imwrite(&image_path, &ImageRGBA::new(4, 2)).unwrap();
+
+        let mut scene = parse_scene(SCENE).unwrap();
+        let earth = TextureParams::Image(ImageTexture::load(&image_path).unwrap());
+        let earth = scene.add_material(&MaterialParams::Lambertian { albedo: earth });
+        let smoke = MaterialParams::Isotropic { albedo: Color::new(0.2, 0.2, 0.2).into() };
+        let smoke = scene.add_material(&smoke);
+        let lamp = MaterialParams::DiffuseLight { color: Color::WHITE, intensity: 4.0 };
+        let lamp = scene.add_material(&lamp);
+        let (a, b, c) = (Point::ZERO, Point::new(1.0, 0.0, 0.0), Point::new(0.0, 1.0, 0.0));
+        let world = scene.world_mut();
+        world.add(Sphere::new(Point::new(2.0, 0.0, -1.0), 0.5, earth));
+        world.add(MovingSphere::new(a, b, 0.0, 1.0, 0.25, MaterialId(1)));
+        world.add(Plane::new(Point::new(0.0, 0.0, -5.0), Vec3::UNIT_Z, MaterialId(1)));
+        world.add(Triangle::new(a, b, c, MaterialId(1)));
+        world.add(Mesh::new(vec![a, b, c], vec![[0, 1, 2]], MaterialId(0)).unwrap());
+        world.add(XyRect::new(0.0, 1.0, 0.0, 2.0, -3.0, MaterialId(0)));
+        world.add(XzRect::new(-1.0, 1.0, -2.0, -1.0, 2.0, lamp));
+        world.add(YzRect::new(0.0, 1.0, -1.0, 0.0, 3.0, MaterialId(0)));
+        world.add(Cuboid::new(a, Point::new(1.0, 1.0, -1.0), MaterialId(0)));
+        world.add(Cylinder::new(a, b, 0.5, MaterialId(1)));
+        world.add(Cone::new(a, c, 0.5, 0.25, MaterialId(1)));
+        world.add(Capsule::new(b, c, 0.25, MaterialId(0)));
+        world.add(Torus::new(c, Vec3::new(1.0, 1.0, 0.0), 1.0, 0.25, MaterialId(1)));
+        let ball = Sdf::Sphere { center: c, radius: 0.5 };
+        let cuboid = Sdf::Cuboid { center: a, half_size: Vec3::new(0.5, 0.25, 0.5) };
+        let sdf = cuboid.smooth_union(ball.clone(), 0.1).smooth_subtract(ball, 0.0);
+        world.add(SdfObject::new(sdf, MaterialId(0)));
+        let size = Vec3::new(2.0, 0.5, 2.0);
+        world.add(Heightfield::load(&image_path, c, size, MaterialId(0)).unwrap());
+        let tree = Arc::new(Mesh::new(vec![a, b, c], vec![[0, 1, 2]], MaterialId(0)).unwrap());
+        for k in 0..2 {
+            let transform = Transform::new(c * k as f32, Vec3::new(0.0, 30.0, 0.0), size);
+            world.add(Instance::new(tree.clone(), transform, MaterialId(1)));
+        }
+        world.add(ConstantMedium::new(Sphere::new(c, 0.5, MaterialId(0)), 0.5, smoke));
+        scene.add_proxy(&[1, 2]).unwrap();
+        scene.set_view(&OrbitView::looking_at(&Point::new(1.0, 2.0, 3.0), &c));
+        scene.set_lens(&Lens {
+            vfov: 50.0,
+            aperture: 0.1,
+            focus_distance: Some(2.0),
+            projection: CameraProjection::Fisheye,
+        });
+        scene.set_shutter(0.0, 0.5);
+        scene.set_background(&Background::Solid(Color::BLACK));
+
+        let path = dir.path().join("scene.json").to_string_lossy().to_string();
+        save_scene(&scene, &path).unwrap();
+        let loaded = load_scene(&path).unwrap();
+        assert_eq!(loaded.world().objects(), scene.world().objects());
+        // The mesh of the instances is saved once, and still shared.
+        let instances: Vec<_> = loaded
+            .world()
+            .objects()
+            .iter()
+            .filter_map(|object| match object {
+                Object::Instance(instance) => Some(instance.mesh()),
+                _ => None,
+            })
+            .collect();
+        assert!(Arc::ptr_eq(instances[0], instances[1]));
+        assert_eq!(loaded.material_count(), scene.material_count());
+        for id in scene.materials().ids() {
+            assert_eq!(loaded.material(id), scene.material(id));
+            assert_eq!(loaded.materials().name(id), scene.materials().name(id));
+        }
+        assert_eq!(loaded.proxies(), scene.proxies());
+        assert!((loaded.view().position() - scene.view().position()).len() < 1e-5);
+        assert_eq!(loaded.view().target, scene.view().target);
+        assert_eq!((loaded.lens(), loaded.shutter()), (scene.lens(), scene.shutter()));
+        assert_eq!(loaded.background(), scene.background());
+
+        // Images built in memory have no file to refer to.
+        let texture = ImageTexture::new(ImageRGBA::new(1, 1)).unwrap();
+        scene.set_material(
+            earth,
+            &MaterialParams::Lambertian { albedo: TextureParams::Image(texture) },
+        );
+        let err = scene_to_json(&scene).err().unwrap().to_string();
+        assert_eq!(err, "materials[3]: an image texture built in memory has no file to refer to");
+        assert!(save_scene(&Scene::demo(), "does/not/exist.json").is_err());
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let err = load_scene("does/not/exist.json").err().unwrap();
+        assert!(matches!(err, RtError::Io { ref path, .. } if path == "does/not/exist.json"));
+        assert!(err.to_string().starts_with("does/not/exist.json: "), "{err}");
+    }
+}
@@ -0,0 +1,204 @@
+//! Ready-made scenes, among which those of the reference images of the book series.
+use crate::geometry::{Color, Point, Vec3};
+use crate::rect::{Cuboid, XyRect, XzRect, YzRect};
+use crate::render::{Background, MaterialParams, Scene, Sphere};
+use crate::sampler::Sampler;
+use crate::view::{Lens, OrbitView};
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+
+/// Seed of the spheres of [`ScenePreset::RandomSpheres`].
+const RANDOM_SPHERES_SEED: u64 = 0;
+
+/// Built-in scenes, selectable by name.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScenePreset {
+    /// Three spheres on a large one, see [`Scene::demo`].
+    Demo,
+    /// See [`cornell_box`].
+    CornellBox,
+    /// See [`random_spheres_final_scene`].
+    RandomSpheres,
+}
+
+impl ScenePreset {
+    pub const ALL: [ScenePreset; 3] =
+        [ScenePreset::Demo, ScenePreset::CornellBox, ScenePreset::RandomSpheres];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScenePreset::Demo => "demo",
+            ScenePreset::CornellBox => "cornell-box",
+            ScenePreset::RandomSpheres => "random-spheres",
+        }
+    }
+
+    /// The scene, always the same for a preset.
+    pub fn build(&self) -> Scene {
+        match self {
+            ScenePreset::Demo => Scene::demo(),
+            ScenePreset::CornellBox => cornell_box(),
+            ScenePreset::RandomSpheres => random_spheres_final_scene(RANDOM_SPHERES_SEED),
+        }
+    }
+}
+
+impl fmt::Display for ScenePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ScenePreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ScenePreset::ALL.into_iter().find(|p| p.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = ScenePreset::ALL.iter().map(|p| p.name()).collect();
+            format!("unknown scene preset '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+/// The Cornell box of "Ray Tracing: The Next Week": a 555 units wide room with a red and a
+/// green wall, lit by a lamp in the ceiling, and two white boxes.
+///
+/// The boxes of the book are turned, they are axis-aligned here.
+pub fn cornell_box() -> Scene {
+    let mut scene = Scene::empty();
+    let mut add = |name: &str, params: MaterialParams| {
+        scene.add_named_material(name, &params).expect("the materials have unique names")
+    };
+    let red =
+        add("red", MaterialParams::Lambertian { albedo: Color::new(0.65, 0.05, 0.05).into() });
+    let white =
+        add("white", MaterialParams::Lambertian { albedo: Color::new(0.73, 0.73, 0.73).into() });
+    let green =
+        add("green", MaterialParams::Lambertian { albedo: Color::new(0.12, 0.45, 0.15).into() });
+    let light = add("light", MaterialParams::DiffuseLight { color: Color::WHITE, intensity: 15.0 });
+
+    let world = scene.world_mut();
+    world.add(YzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green));
+    world.add(YzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red));
+    world.add(XzRect::new(213.0, 343.0, 227.0, 332.0, 554.0, light));
+    world.add(XzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white));
+    world.add(XzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white));
+    world.add(XyRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white));
+    world.add(Cuboid::new(Point::new(130.0, 0.0, 65.0), Point::new(295.0, 165.0, 230.0), white));
+    world.add(Cuboid::new(Point::new(265.0, 0.0, 295.0), Point::new(430.0, 330.0, 460.0), white));
+
+    let target = Point::new(278.0, 278.0, 0.0);
+    scene.set_view(&OrbitView::looking_at(&Point::new(278.0, 278.0, -800.0), &target));
+    scene.set_lens(&Lens { vfov: 40.0, ..Default::default() });
+    scene.set_background(&Background::Solid(Color::BLACK));
+    scene
+}
+
+/// The cover of "Ray Tracing in One Weekend": hundreds of small random spheres around three
+/// large ones, glass, diffuse and metal, seen through a lens focused on the glass one.
+///
+/// # Arguments
+/// - `seed` - Seed of the positions and materials of the small spheres.
+pub fn random_spheres_final_scene(seed: u64) -> Scene {
+    let mut rng = Sampler::new(seed);
+    let mut scene = Scene::empty();
+    let ground = MaterialParams::Lambertian { albedo: Color::new(0.5, 0.5, 0.5).into() };
+    let ground = scene.add_named_material("ground", &ground).expect("the scene is empty");
+    scene.world_mut().add(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, ground));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Point::new(
+                a as f32 + 0.9 * rng.gen::<f32>(),
+                0.2,
+                b as f32 + 0.9 * rng.gen::<f32>(),
+            );
+            if (center - Point::new(4.0, 0.2, 0.0)).len() <= 0.9 {
+                continue;
+            }
+            let choice: f32 = rng.gen();
+            let params = if choice < 0.8 {
+                let albedo = Vec3::random(&mut rng) * Vec3::random(&mut rng);
+                MaterialParams::Lambertian { albedo: albedo.into() }
+            } else if choice < 0.95 {
+                let albedo = Vec3::random_range(0.5, 1.0, &mut rng);
+                MaterialParams::Metal { albedo, fuzz: rng.gen_range(0.0..0.5), film: None }
+            } else {
+                MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.0, film: None }
+            };
+            let material = scene.add_material(&params);
+            scene.world_mut().add(Sphere::new(center, 0.2, material));
+        }
+    }
+
+    let mut add = |name: &str, params: MaterialParams| {
+        scene.add_named_material(name, &params).expect("the large spheres have unique names")
+    };
+    let glass = add(
+        "glass",
+        MaterialParams::Dielectric { refraction_index: 1.5, dispersion: 0.0, film: None },
+    );
+    let brown = Color::new(0.4, 0.2, 0.1);
+    let diffuse = add("diffuse", MaterialParams::Lambertian { albedo: brown.into() });
+    let metal = add(
+        "metal",
+        MaterialParams::Metal { albedo: Color::new(0.7, 0.6, 0.5), fuzz: 0.0, film: None },
+    );
+    let world = scene.world_mut();
+    world.add(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, glass));
+    world.add(Sphere::new(Point::new(-4.0, 1.0, 0.0), 1.0, diffuse));
+    world.add(Sphere::new(Point::new(4.0, 1.0, 0.0), 1.0, metal));
+
+    scene.set_view(&OrbitView::looking_at(&Point::new(13.0, 2.0, 3.0), &Point::ZERO));
+    scene.set_lens(&Lens {
+        vfov: 20.0,
+        aperture: 0.1,
+        focus_distance: Some(10.0),
+        ..Default::default()
+    });
+    scene
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::render::{render, Background};
+    use crate::scenes::{cornell_box, random_spheres_final_scene, ScenePreset};
+    use crate::settings::RenderSettings;
+
+    #[test]
+    fn test_scene_presets() {
+        for preset in ScenePreset::ALL {
+            assert_eq!(preset.name().parse(), Ok(preset));
+            assert!(!preset.build().world().is_empty(), "{preset}");
+        }
+        let err = "teapot".parse::<ScenePreset>().err().unwrap();
+        assert_eq!(
+            err,
+            "unknown scene preset 'teapot', expected one of: demo, cornell-box, random-spheres"
+        );
+    }
+
+    #[test]
+    fn test_cornell_box() {
+        let scene = cornell_box();
+        assert_eq!(scene.world().len(), 8);
+        assert_eq!(scene.light_count(), 1);
+        assert!(matches!(scene.background(), Background::Solid(_)));
+
+        // The room is closed: only the lamp lights it.
+        let settings = RenderSettings::builder().resolution(16, 16).samples_per_pixel(4).build();
+        let im = render(&scene, &settings.unwrap()).unwrap();
+        assert!(im.pixels.chunks(4).any(|p| p[..3] != [0, 0, 0]));
+    }
+
+    #[test]
+    fn test_random_spheres_final_scene() {
+        let scene = random_spheres_final_scene(1);
+        // The ground, at most 22 × 22 small spheres, and the three large ones.
+        assert!(scene.world().len() > 400 && scene.world().len() <= 4 + 22 * 22);
+        assert_eq!(scene.world().objects(), random_spheres_final_scene(1).world().objects());
+        assert_ne!(scene.world().objects(), random_spheres_final_scene(2).world().objects());
+        assert_eq!(scene.lens().aperture, 0.1);
+    }
+}
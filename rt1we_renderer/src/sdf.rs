@@ -0,0 +1,287 @@
+//! Shapes described by signed distance fields, rendered by sphere tracing.
+//!
+//! A signed distance field gives, at each point, the distance to the closest point of a shape,
+//! negative inside of it. Fields of spheres and boxes combine into new shapes, their union or
+//! difference blended smoothly over a `smoothness` distance, as a sphere melting into a box.
+//!
+//! Rays march towards the shape by the distance to it, which they cannot overshoot, until they
+//! reach its surface. The normal there is the gradient of the field.
+use crate::aabb::Aabb;
+use crate::geometry::{dot, Point, Vec3};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::registry::MaterialId;
+use crate::render::{sphere_uv, HitRecord, Hittable};
+
+/// Distance to a surface under which a ray hits it.
+const HIT_DISTANCE: f32 = 1e-4;
+/// Steps after which a ray marching along a surface, without reaching it, misses it.
+const MAX_STEPS: usize = 256;
+/// Distance between the points the gradient of a field is estimated from.
+const GRADIENT_STEP: f32 = 1e-4;
+
+/// A signed distance field, see the [module](self) documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sdf {
+    Sphere {
+        center: Point,
+        radius: f32,
+    },
+    /// Box aligned with the axes.
+    Cuboid {
+        center: Point,
+        /// Distances from the center to the faces, along each axis.
+        half_size: Vec3,
+    },
+    /// Both shapes, blended where closer than `smoothness`.
+    SmoothUnion {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        smoothness: f32,
+    },
+    /// Shape `a` with shape `b` carved out of it, blended where closer than `smoothness`.
+    SmoothSubtract {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        smoothness: f32,
+    },
+}
+
+impl Sdf {
+    /// This shape and `other`, see [`Sdf::SmoothUnion`].
+    pub fn smooth_union(self, other: Sdf, smoothness: f32) -> Sdf {
+        Sdf::SmoothUnion { a: Box::new(self), b: Box::new(other), smoothness }
+    }
+
+    /// This shape with `other` carved out, see [`Sdf::SmoothSubtract`].
+    pub fn smooth_subtract(self, other: Sdf, smoothness: f32) -> Sdf {
+        Sdf::SmoothSubtract { a: Box::new(self), b: Box::new(other), smoothness }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sdf::Sphere { .. } => "sphere",
+            Sdf::Cuboid { .. } => "box",
+            Sdf::SmoothUnion { .. } => "smooth union",
+            Sdf::SmoothSubtract { .. } => "smooth subtract",
+        }
+    }
+
+    /// Signed distance from `p` to the shape, negative inside.
+    ///
+    /// Blended shapes only bound the distance, close to the exact one.
+    pub fn distance(&self, p: &Point) -> f32 {
+        match self {
+            Sdf::Sphere { center, radius } => (*p - *center).len() - radius,
+            Sdf::Cuboid { center, half_size } => {
+                let d = *p - *center;
+                let q = Vec3::new(d.x.abs(), d.y.abs(), d.z.abs()) - *half_size;
+                let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).len();
+                outside + q.x.max(q.y).max(q.z).min(0.0)
+            }
+            Sdf::SmoothUnion { a, b, smoothness } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                if *smoothness <= 0.0 {
+                    return da.min(db);
+                }
+                let h = (0.5 + 0.5 * (db - da) / smoothness).clamp(0.0, 1.0);
+                db + (da - db) * h - smoothness * h * (1.0 - h)
+            }
+            Sdf::SmoothSubtract { a, b, smoothness } => {
+                let (da, db) = (a.distance(p), -b.distance(p));
+                if *smoothness <= 0.0 {
+                    return da.max(db);
+                }
+                let h = (0.5 - 0.5 * (db - da) / smoothness).clamp(0.0, 1.0);
+                db + (da - db) * h + smoothness * h * (1.0 - h)
+            }
+        }
+    }
+
+    /// Unit normal of the surface near `p`, the gradient of the field.
+    pub fn normal(&self, p: &Point) -> Vec3 {
+        let axis = |d: Vec3| self.distance(&(*p + d)) - self.distance(&(*p - d));
+        let gradient = Vec3::new(
+            axis(Vec3::new(GRADIENT_STEP, 0.0, 0.0)),
+            axis(Vec3::new(0.0, GRADIENT_STEP, 0.0)),
+            axis(Vec3::new(0.0, 0.0, GRADIENT_STEP)),
+        );
+        gradient.normed()
+    }
+
+    /// Box around the shape.
+    pub fn bounding_box(&self) -> Aabb {
+        match self {
+            Sdf::Sphere { center, radius } => {
+                let r = Vec3::new(*radius, *radius, *radius);
+                Aabb::new(*center - r, *center + r)
+            }
+            Sdf::Cuboid { center, half_size } => {
+                Aabb::new(*center - *half_size, *center + *half_size)
+            }
+            Sdf::SmoothUnion { a, b, smoothness } => {
+                // The blend swells the union by up to a quarter of the smoothness.
+                let swell = smoothness.max(0.0) / 4.0;
+                let bbox = a.bounding_box().surrounding(&b.bounding_box());
+                let swell = Vec3::new(swell, swell, swell);
+                Aabb::new(bbox.min - swell, bbox.max + swell)
+            }
+            // Carving only shrinks the shape.
+            Sdf::SmoothSubtract { a, .. } => a.bounding_box(),
+        }
+    }
+}
+
+/// An object of the shape of a signed distance field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdfObject {
+    pub sdf: Sdf,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Sdf, material_id: MaterialId) -> Self {
+        SdfObject { sdf, material_id }
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let Some(range) = self.bounding_box().clip(r, ray_t) else {
+            return false;
+        };
+        let speed = r.dir.len();
+        // Rays starting inside march out to the surface, where the distance turns positive.
+        let mut t = range.min;
+        let start = self.sdf.distance(&r.at(t));
+        // Rays scattered by the surface start on it, on the side they leave it to.
+        let mut leaving = start.abs() < HIT_DISTANCE;
+        let side =
+            if leaving { dot(&self.sdf.normal(&r.at(t)), &r.dir).signum() } else { start.signum() };
+        let mut hit = false;
+        for _ in 0..MAX_STEPS {
+            let distance = side * self.sdf.distance(&r.at(t));
+            leaving &= distance < HIT_DISTANCE;
+            if distance < HIT_DISTANCE && !leaving {
+                hit = true;
+                break;
+            }
+            t += distance.max(HIT_DISTANCE) / speed;
+            if t > range.max {
+                break;
+            }
+        }
+        if !(hit && ray_t.surrounds(t)) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = r.at(t);
+        let outward_normal = self.sdf.normal(&rec.p);
+        (rec.u, rec.v) = sphere_uv(&outward_normal);
+        rec.material_id = self.material_id;
+        rec.set_face_normal(r, &outward_normal);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = self.sdf.bounding_box();
+        let padding = Vec3::new(HIT_DISTANCE, HIT_DISTANCE, HIT_DISTANCE);
+        Aabb::new(bbox.min - padding, bbox.max + padding)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Point, Vec3};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, Hittable, Sphere};
+    use crate::sdf::{Sdf, SdfObject};
+
+    fn hit(object: &impl Hittable, orig: Point, dir: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let r = Ray::new(orig, dir);
+        object.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec).then_some(rec)
+    }
+
+    fn near(a: &Vec3, b: &Vec3) -> bool {
+        (*a - *b).len() < 1e-3
+    }
+
+    #[test]
+    fn test_distances() {
+        let sphere = Sdf::Sphere { center: Point::ZERO, radius: 1.0 };
+        assert_float_absolute_eq!(sphere.distance(&Point::new(0.0, 3.0, 0.0)), 2.0, 1e-6);
+        assert_float_absolute_eq!(sphere.distance(&Point::ZERO), -1.0, 1e-6);
+        let cuboid = Sdf::Cuboid { center: Point::ZERO, half_size: Vec3::new(1.0, 2.0, 3.0) };
+        assert_float_absolute_eq!(cuboid.distance(&Point::new(4.0, 0.0, 0.0)), 3.0, 1e-6);
+        assert_float_absolute_eq!(cuboid.distance(&Point::new(4.0, 6.0, 0.0)), 5.0, 1e-6);
+        assert_float_absolute_eq!(cuboid.distance(&Point::new(0.5, 0.0, 0.0)), -0.5, 1e-6);
+
+        // Without smoothness, the union is the closest shape, and the difference the farthest.
+        let moved = Sdf::Sphere { center: Point::new(1.5, 0.0, 0.0), radius: 1.0 };
+        let union = sphere.clone().smooth_union(moved.clone(), 0.0);
+        assert_float_absolute_eq!(union.distance(&Point::new(3.0, 0.0, 0.0)), 0.5, 1e-6);
+        let carved = sphere.clone().smooth_subtract(moved.clone(), 0.0);
+        assert_float_absolute_eq!(carved.distance(&Point::new(0.75, 0.0, 0.0)), 0.25, 1e-6);
+        assert_float_absolute_eq!(carved.distance(&Point::new(-0.5, 0.0, 0.0)), -0.5, 1e-6);
+        // Smoothly, the union swells where both shapes are close.
+        let smooth = sphere.smooth_union(moved, 0.5);
+        let between = Point::new(0.75, 1.0, 0.0);
+        assert!(smooth.distance(&between) < union.distance(&between) - 0.1);
+    }
+
+    #[test]
+    fn test_sdf_sphere_matches_sphere() {
+        let center = Point::new(0.2, -0.1, -2.0);
+        let sdf = SdfObject::new(Sdf::Sphere { center, radius: 0.5 }, MaterialId(3));
+        let sphere = Sphere::new(center, 0.5, MaterialId(3));
+        for dir in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.2, 0.1, -1.0), Vec3::new(0.5, 0.0, -1.0)]
+        {
+            let expected = hit(&sphere, Point::ZERO, dir);
+            let rec = hit(&sdf, Point::ZERO, dir);
+            assert_eq!(rec.is_some(), expected.is_some(), "{dir:?}");
+            let (Some(rec), Some(expected)) = (rec, expected) else {
+                continue;
+            };
+            assert_float_absolute_eq!(rec.t, expected.t, 1e-3);
+            assert!(near(&rec.normal, &expected.normal), "{:?}", rec.normal);
+            assert_eq!((rec.front_face, rec.material_id), (true, MaterialId(3)));
+        }
+        // From the inside, out.
+        let rec = hit(&sdf, center, Vec3::UNIT_X).unwrap();
+        assert_float_absolute_eq!(rec.t, 0.5, 1e-3);
+        assert!(near(&rec.normal, &-Vec3::UNIT_X) && !rec.front_face);
+        // Rays leaving the surface do not hit it again where they start.
+        let on_surface = center + Vec3::new(0.0, 0.5, 0.0);
+        assert!(hit(&sdf, on_surface, Vec3::new(0.3, 1.0, 0.0)).is_none());
+        let rec = hit(&sdf, on_surface, -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.0, 1e-3);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn test_carved_box() {
+        // A unit box with a sphere carved out of its top face.
+        let cuboid = Sdf::Cuboid { center: Point::ZERO, half_size: Vec3::new(1.0, 1.0, 1.0) };
+        let hole = Sdf::Sphere { center: Point::new(0.0, 1.0, 0.0), radius: 0.5 };
+        let object = SdfObject::new(cuboid.smooth_subtract(hole, 0.0), MaterialId(0));
+
+        // Onto the top face, and into the bottom of the hole.
+        let rec = hit(&object, Point::new(0.8, 3.0, 0.0), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 2.0, 1e-3);
+        assert!(near(&rec.normal, &Vec3::UNIT_Y));
+        let rec = hit(&object, Point::new(0.0, 3.0, 0.0), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 2.5, 1e-3);
+        assert!(near(&rec.normal, &Vec3::UNIT_Y), "{:?}", rec.normal);
+        // Beside the box.
+        assert!(hit(&object, Point::new(1.2, 3.0, 0.0), -Vec3::UNIT_Y).is_none());
+
+        let bbox = object.bounding_box();
+        assert!(near(&bbox.min, &Point::new(-1.0, -1.0, -1.0)));
+        assert!(near(&bbox.max, &Point::new(1.0, 1.0, 1.0)));
+    }
+}
@@ -0,0 +1,434 @@
+//! Render settings: everything about a render that is not part of the scene.
+use crate::error::RtError;
+use crate::geometry::Color;
+use crate::render::{Background, PathDepth, Scene};
+use crate::sampler::SamplerKind;
+use crate::stereo::Stereo;
+use crate::tiles::DEFAULT_TILE_SIZE;
+use crate::tonemap::{ToneMapOperator, ToneMapSettings};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// What the pixels of a render show: the light of the scene, or a fast diagnostic pass of the
+/// surfaces the camera rays hit first.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// The light bouncing in the scene.
+    #[default]
+    PathTraced,
+    /// The light bouncing in the scene, each camera ray carrying a single wavelength, so that
+    /// dispersive dielectrics split it into rainbows. Noisier, see [`crate::spectrum`].
+    Spectral,
+    /// Outward surface normals, their components brought from `[-1;1]` to `[0;1]`.
+    Normals,
+    /// Distance to the camera: 1 at the camera, 1/2 at the distance of the target of the view
+    /// and towards 0 beyond.
+    Depth,
+    /// Fraction of the hemisphere above the surface from which the sky is seen.
+    AmbientOcclusion,
+    /// Color factor of the materials, without lighting.
+    Albedo,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 6] = [
+        RenderMode::PathTraced,
+        RenderMode::Spectral,
+        RenderMode::Normals,
+        RenderMode::Depth,
+        RenderMode::AmbientOcclusion,
+        RenderMode::Albedo,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderMode::PathTraced => "path-traced",
+            RenderMode::Spectral => "spectral",
+            RenderMode::Normals => "normals",
+            RenderMode::Depth => "depth",
+            RenderMode::AmbientOcclusion => "ambient-occlusion",
+            RenderMode::Albedo => "albedo",
+        }
+    }
+
+    /// Whether a single sample per pixel gives the final image, the pass being noiseless
+    /// but for the edges.
+    pub fn is_single_sample(&self) -> bool {
+        matches!(self, RenderMode::Normals | RenderMode::Depth)
+    }
+}
+
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RenderMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RenderMode::ALL.into_iter().find(|m| m.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = RenderMode::ALL.iter().map(|m| m.name()).collect();
+            format!("unknown render mode '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+/// How to render a scene, see [`crate::render::render`].
+///
+/// Settings are usually made with [`RenderSettings::builder`], which checks them. New options
+/// get a default value, so that existing callers keep working.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    /// Output image width
+    pub width: usize,
+    /// Output image height
+    pub height: usize,
+    /// How many random rays to generate and average to compute final pixel color.
+    pub samples_per_pixel: usize,
+    /// Maximum number of ray bounces after a hit.
+    pub max_depth: usize,
+    /// Number of bounces after which the paths are stopped at random, the more likely the
+    /// less light they can still bring back. Faster, for a little more noise. Never when
+    /// `None`.
+    pub russian_roulette: Option<usize>,
+    /// Display gamma of the 8-bit images.
+    pub gamma: f32,
+    /// How the radiance above 1 is brought into the 8-bit images.
+    pub tone_map: ToneMapOperator,
+    /// Exposure adjustment of the 8-bit images, in stops.
+    pub exposure: f32,
+    /// Seed of the random numbers of the render, random when `None`. A given seed gives the
+    /// same image whatever the number of threads.
+    pub seed: Option<u64>,
+    /// Background replacing the one of the scene.
+    pub background: Option<Background>,
+    /// Number of threads tracing the image, those of the current rayon pool when `None`.
+    pub threads: Option<usize>,
+    /// Side of the tiles of [`crate::tiles::render_tiles`], in pixels.
+    pub tile_size: usize,
+    /// What the pixels show.
+    pub mode: RenderMode,
+    /// How the samples are spread over the pixels.
+    pub sampler: SamplerKind,
+    /// Largest component of the radiance of a sample, the brighter ones are dimmed down to it,
+    /// see [`crate::render::SampleStats::filter`]. Unclamped when `None`.
+    pub clamp_radiance: Option<f32>,
+    /// Render the view of each eye side by side, each in half the width of the image.
+    pub stereo: Option<Stereo>,
+}
+
+impl Default for RenderSettings {
+    /// The settings of the sample renders.
+    fn default() -> Self {
+        RenderSettings {
+            width: 160,
+            height: 90,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            russian_roulette: None,
+            gamma: 2.0,
+            tone_map: ToneMapOperator::Clamp,
+            exposure: 0.0,
+            seed: None,
+            background: None,
+            threads: None,
+            tile_size: DEFAULT_TILE_SIZE,
+            mode: RenderMode::PathTraced,
+            sampler: SamplerKind::Uniform,
+            clamp_radiance: None,
+            stereo: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Start from the default settings.
+    pub fn builder() -> RenderSettingsBuilder {
+        RenderSettingsBuilder { settings: RenderSettings::default() }
+    }
+
+    /// Tone mapping of the 8-bit images.
+    pub fn tonemap(&self) -> ToneMapSettings {
+        ToneMapSettings { operator: self.tone_map, exposure: self.exposure, gamma: self.gamma }
+    }
+
+    /// Convert a linear color to 8-bit values, with the tone mapping of the settings.
+    pub fn encode(&self, c: &Color) -> (u8, u8, u8) {
+        let tonemap = self.tonemap();
+        (tonemap.encode(c.x), tonemap.encode(c.y), tonemap.encode(c.z))
+    }
+
+    /// How far the paths bounce.
+    pub(crate) fn path_depth(&self) -> PathDepth {
+        PathDepth { max: self.max_depth, roulette: self.russian_roulette }
+    }
+
+    /// Number of samples traced in each pixel: a single one in the modes that need no more.
+    pub fn samples(&self) -> usize {
+        if self.mode.is_single_sample() {
+            1
+        } else {
+            self.samples_per_pixel
+        }
+    }
+
+    /// The scene, with the background of the settings if any.
+    pub(crate) fn scene<'a>(&self, scene: &'a Scene) -> Cow<'a, Scene> {
+        match &self.background {
+            Some(background) => {
+                let mut scene = scene.clone();
+                scene.set_background(background);
+                Cow::Owned(scene)
+            }
+            None => Cow::Borrowed(scene),
+        }
+    }
+
+    /// Thread pool of the render, `None` to use the current one.
+    pub(crate) fn thread_pool(&self) -> Result<Option<ThreadPool>, RtError> {
+        let Some(threads) = self.threads else {
+            return Ok(None);
+        };
+        Ok(Some(ThreadPoolBuilder::new().num_threads(threads).build()?))
+    }
+
+    /// Number of threads tracing the image.
+    pub fn thread_count(&self) -> usize {
+        self.threads.unwrap_or_else(rayon::current_num_threads)
+    }
+}
+
+/// Builds checked [`RenderSettings`].
+///
+/// ```
+/// use rt1we_renderer::settings::RenderSettings;
+///
+/// let settings = RenderSettings::builder().resolution(320, 180).samples_per_pixel(16).build();
+/// assert_eq!(settings.unwrap().width, 320);
+/// assert!(RenderSettings::builder().samples_per_pixel(0).build().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RenderSettingsBuilder {
+    settings: RenderSettings,
+}
+
+impl RenderSettingsBuilder {
+    pub fn resolution(mut self, width: usize, height: usize) -> Self {
+        (self.settings.width, self.settings.height) = (width, height);
+        self
+    }
+
+    pub fn samples_per_pixel(mut self, samples_per_pixel: usize) -> Self {
+        self.settings.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.settings.max_depth = max_depth;
+        self
+    }
+
+    pub fn russian_roulette(mut self, russian_roulette: Option<usize>) -> Self {
+        self.settings.russian_roulette = russian_roulette;
+        self
+    }
+
+    pub fn gamma(mut self, gamma: f32) -> Self {
+        self.settings.gamma = gamma;
+        self
+    }
+
+    pub fn tone_map(mut self, tone_map: ToneMapOperator) -> Self {
+        self.settings.tone_map = tone_map;
+        self
+    }
+
+    pub fn exposure(mut self, exposure: f32) -> Self {
+        self.settings.exposure = exposure;
+        self
+    }
+
+    pub fn seed(mut self, seed: Option<u64>) -> Self {
+        self.settings.seed = seed;
+        self
+    }
+
+    pub fn background(mut self, background: Option<Background>) -> Self {
+        self.settings.background = background;
+        self
+    }
+
+    pub fn threads(mut self, threads: Option<usize>) -> Self {
+        self.settings.threads = threads;
+        self
+    }
+
+    pub fn tile_size(mut self, tile_size: usize) -> Self {
+        self.settings.tile_size = tile_size;
+        self
+    }
+
+    pub fn mode(mut self, mode: RenderMode) -> Self {
+        self.settings.mode = mode;
+        self
+    }
+
+    pub fn sampler(mut self, sampler: SamplerKind) -> Self {
+        self.settings.sampler = sampler;
+        self
+    }
+
+    pub fn clamp_radiance(mut self, clamp_radiance: Option<f32>) -> Self {
+        self.settings.clamp_radiance = clamp_radiance;
+        self
+    }
+
+    pub fn stereo(mut self, stereo: Option<Stereo>) -> Self {
+        self.settings.stereo = stereo;
+        self
+    }
+
+    /// # Returns
+    /// The settings, or an error with one line per invalid setting.
+    pub fn build(self) -> Result<RenderSettings, RtError> {
+        let s = self.settings;
+        let mut errors = Vec::new();
+        if s.width == 0 || s.height == 0 {
+            errors.push(format!("invalid resolution {}x{}", s.width, s.height));
+        }
+        if s.samples_per_pixel == 0 {
+            errors.push("samples_per_pixel must be at least 1".to_string());
+        }
+        if !(s.gamma.is_finite() && s.gamma > 0.0) {
+            errors.push("gamma must be positive".to_string());
+        }
+        if !s.exposure.is_finite() {
+            errors.push("exposure must be finite".to_string());
+        }
+        if s.threads == Some(0) {
+            errors.push("threads must be at least 1".to_string());
+        }
+        if s.tile_size == 0 {
+            errors.push("tile_size must be at least 1".to_string());
+        }
+        if s.clamp_radiance.is_some_and(|c| !(c.is_finite() && c > 0.0)) {
+            errors.push("clamp_radiance must be positive".to_string());
+        }
+        if let Some(stereo) = s.stereo {
+            if !s.width.is_multiple_of(2) {
+                errors.push("stereo renders need an even width".to_string());
+            }
+            if !(stereo.eye_separation.is_finite() && stereo.eye_separation >= 0.0) {
+                errors.push("eye_separation must be positive or zero".to_string());
+            }
+        }
+        if errors.is_empty() {
+            Ok(s)
+        } else {
+            Err(RtError::Settings(errors.join("\n")))
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::Color;
+    use crate::render::{Background, Scene};
+    use crate::sampler::SamplerKind;
+    use crate::settings::{RenderMode, RenderSettings};
+    use crate::stereo::Stereo;
+    use crate::tonemap::ToneMapOperator;
+
+    #[test]
+    fn test_render_modes() {
+        for mode in RenderMode::ALL {
+            assert_eq!(mode.name().parse(), Ok(mode));
+        }
+        assert_eq!(RenderMode::default(), RenderMode::PathTraced);
+        assert!("wireframe".parse::<RenderMode>().is_err());
+    }
+
+    #[test]
+    fn test_builder() {
+        let settings = RenderSettings::builder()
+            .resolution(32, 18)
+            .samples_per_pixel(4)
+            .max_depth(3)
+            .russian_roulette(Some(2))
+            .gamma(1.0)
+            .tone_map(ToneMapOperator::Reinhard)
+            .exposure(-1.0)
+            .seed(Some(5))
+            .threads(Some(2))
+            .tile_size(8)
+            .mode(RenderMode::Depth)
+            .sampler(SamplerKind::Sobol)
+            .clamp_radiance(Some(10.0))
+            .stereo(Some(Stereo::default()))
+            .build()
+            .unwrap();
+        let expected = RenderSettings {
+            width: 32,
+            height: 18,
+            samples_per_pixel: 4,
+            max_depth: 3,
+            russian_roulette: Some(2),
+            gamma: 1.0,
+            tone_map: ToneMapOperator::Reinhard,
+            exposure: -1.0,
+            seed: Some(5),
+            background: None,
+            threads: Some(2),
+            tile_size: 8,
+            mode: RenderMode::Depth,
+            sampler: SamplerKind::Sobol,
+            clamp_radiance: Some(10.0),
+            stereo: Some(Stereo::default()),
+        };
+        assert_eq!(settings, expected);
+        assert_eq!(settings.thread_count(), 2);
+        assert_eq!(RenderSettings::builder().build().unwrap(), RenderSettings::default());
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let err = RenderSettings::builder()
+            .resolution(0, 10)
+            .gamma(f32::NAN)
+            .exposure(f32::INFINITY)
+            .threads(Some(0))
+            .clamp_radiance(Some(f32::NAN))
+            .build()
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(err.lines().count(), 5, "{err}");
+        assert!(err.starts_with("invalid resolution 0x10"));
+    }
+
+    #[test]
+    fn test_gamma_and_background() {
+        let c = Color::new(0.25, 1.0, 0.0);
+        assert_eq!(RenderSettings::default().encode(&c), (128, 255, 0));
+        let linear = RenderSettings { gamma: 1.0, ..Default::default() };
+        assert_eq!(linear.encode(&c), (64, 255, 0));
+        // Reinhard keeps the highlights apart, one stop down halves the radiance.
+        let settings = RenderSettings {
+            gamma: 1.0,
+            tone_map: ToneMapOperator::Reinhard,
+            exposure: -1.0,
+            ..Default::default()
+        };
+        assert_eq!(settings.encode(&Color::new(2.0, 6.0, 0.0)), (128, 192, 0));
+
+        let scene = Scene::demo();
+        let solid = Background::Solid(Color::WHITE);
+        let settings = RenderSettings { background: Some(solid.clone()), ..Default::default() };
+        assert_eq!(*settings.scene(&scene).background(), solid);
+        assert_eq!(RenderSettings::default().scene(&scene).background(), scene.background());
+    }
+}
@@ -0,0 +1,160 @@
+//! Physical sky: the color of a clear daylight sky, after the analytic model of Preetham,
+//! Shirley and Smits, "A Practical Analytic Model for Daylight" (1999).
+//!
+//! The sky gets bluer and darker away from the sun and towards the zenith, and whiter and
+//! brighter around the sun and along the horizon, the more so in hazy air.
+use crate::geometry::{dot, Color, Vec3};
+use crate::spectrum::xyz_to_rgb;
+use std::f32::consts::PI;
+
+/// Luminance of the model, in kcd/m², per unit of radiance: a clear sky at an intensity of 1
+/// is about as bright as the default gradient.
+const LUMINANCE_SCALE: f32 = 8.0;
+
+/// Clear daylight sky, lit by a sun in a given direction.
+///
+/// The sky does not hold the disk of the sun: a [`crate::light::DirectionalLight`] in the same
+/// direction adds it, with its shadows.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhysicalSky {
+    /// Direction towards the sun. The sky of a sun below the horizon is that of a sun on it.
+    pub sun_direction: Vec3,
+    /// Haziness of the air, from 2 for a very clear sky to 10 for a hazy one.
+    pub turbidity: f32,
+    /// Factor of the radiance of the sky.
+    pub intensity: f32,
+}
+
+impl Default for PhysicalSky {
+    /// A clear sky, the sun high.
+    fn default() -> Self {
+        PhysicalSky { sun_direction: Vec3::new(1.0, 2.0, 1.0), turbidity: 3.0, intensity: 1.0 }
+    }
+}
+
+/// Coefficients of the distribution of Perez et al., for a turbidity.
+struct Perez([f32; 5]);
+
+impl Perez {
+    /// Relative brightness of the sky `theta` from the zenith, `gamma` from the sun.
+    fn at(&self, theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.0;
+        let cos = gamma.cos();
+        (1.0 + a * (b / theta.cos().max(0.01)).exp())
+            * (1.0 + c * (d * gamma).exp() + e * cos * cos)
+    }
+}
+
+/// Sum of `coefficients` of T², T and 1 applied to `theta`³, `theta`², `theta` and 1.
+fn zenith_chromaticity(t: f32, theta: f32, coefficients: [[f32; 4]; 3]) -> f32 {
+    let powers = [theta.powi(3), theta * theta, theta, 1.0];
+    let row = |k: usize| -> f32 { coefficients[k].iter().zip(powers).map(|(c, p)| c * p).sum() };
+    t * t * row(0) + t * row(1) + row(2)
+}
+
+impl PhysicalSky {
+    /// Light coming from `dir`. Below the horizon, the sky along the horizon.
+    pub fn radiance(&self, dir: &Vec3) -> Color {
+        let t = self.turbidity;
+        let sun = self.sun_direction.normed();
+        let dir = dir.normed();
+        let dir = Vec3::new(dir.x, dir.y.max(0.001), dir.z).normed();
+        // The model holds for a sun above the horizon.
+        let theta_sun = sun.y.clamp(0.0, 1.0).acos();
+        let theta = dir.y.clamp(-1.0, 1.0).acos();
+        let gamma = dot(&dir, &sun).clamp(-1.0, 1.0).acos();
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = zenith_chromaticity(
+            t,
+            theta_sun,
+            [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ],
+        );
+        let zenith_y = zenith_chromaticity(
+            t,
+            theta_sun,
+            [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ],
+        );
+        let luminance = Perez([
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ]);
+        let x = Perez([
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ]);
+        let y = Perez([
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ]);
+        // Each quantity is its value at the zenith, scaled by the distribution relative to
+        // the zenith.
+        let relative = |perez: &Perez| perez.at(theta, gamma) / perez.at(0.0, theta_sun);
+        let luminance = (zenith_luminance * relative(&luminance)).max(0.0);
+        let (x, y) = (zenith_x * relative(&x), zenith_y * relative(&y));
+        if y <= 0.0 {
+            return Color::BLACK;
+        }
+        let xyz = Vec3::new(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+        xyz_to_rgb(&xyz) * (self.intensity / LUMINANCE_SCALE)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Color, Vec3};
+    use crate::sky::PhysicalSky;
+
+    /// Blue share of a color, 1/3 for a grey.
+    fn blueness(c: Color) -> f32 {
+        c.z / (c.x + c.y + c.z)
+    }
+
+    #[test]
+    fn test_physical_sky() {
+        let sky = PhysicalSky { sun_direction: Vec3::new(1.0, 1.0, 0.0), ..Default::default() };
+        let zenith = sky.radiance(&Vec3::UNIT_Y);
+        // A blue sky, about as bright as the default gradient.
+        assert!(blueness(zenith) > 0.4, "{zenith:?}");
+        assert!(zenith.y > 0.2 && zenith.y < 2.0, "{zenith:?}");
+        // Brighter and whiter around the sun than away from it.
+        let near_sun = sky.radiance(&Vec3::new(1.0, 0.8, 0.1));
+        let away = sky.radiance(&Vec3::new(-1.0, 0.8, 0.1));
+        assert!(near_sun.y > 2.0 * away.y, "{near_sun:?} {away:?}");
+        assert!(blueness(near_sun) < blueness(away));
+        // Brighter along the horizon than at the zenith, away from the sun.
+        let horizon = sky.radiance(&Vec3::new(-1.0, 0.05, 0.0));
+        assert!(horizon.y > away.y, "{horizon:?} {away:?}");
+        // Below the horizon, as along it.
+        let below = sky.radiance(&Vec3::new(-1.0, -0.5, 0.0));
+        assert!((below - sky.radiance(&Vec3::new(-1.0, 0.0, 0.0))).len() < 1e-3, "{below:?}");
+
+        // Hazy air whitens the sky.
+        let hazy = PhysicalSky { turbidity: 8.0, ..sky };
+        assert!(blueness(hazy.radiance(&Vec3::UNIT_Y)) < blueness(zenith));
+        let bright = PhysicalSky { intensity: 2.0, ..sky };
+        assert!((bright.radiance(&Vec3::UNIT_Y) - 2.0 * zenith).len() < 1e-5);
+        // With the sun on the horizon, or below it, the sky stays defined.
+        let sunset = PhysicalSky { sun_direction: Vec3::new(1.0, -0.1, 0.0), ..sky };
+        let c = sunset.radiance(&Vec3::new(0.0, 0.5, 1.0));
+        assert!(c.x.is_finite() && c.y.is_finite() && c.z.is_finite() && c.y >= 0.0, "{c:?}");
+    }
+}
@@ -0,0 +1,97 @@
+//! Spectral rendering: rays of a single wavelength, brought back to RGB.
+//!
+//! In the spectral mode, see [`crate::settings::RenderMode::Spectral`], each camera ray carries
+//! a wavelength of the visible range, picked at random, and its radiance is weighted by the
+//! color of that wavelength. Materials still reflect RGB colors, but the refraction index of
+//! the dispersive dielectrics depends on the wavelength: white light splits into a rainbow.
+use crate::geometry::{Color, Vec3};
+
+/// Shortest visible wavelength, in nanometers.
+pub const WAVELENGTH_MIN: f32 = 380.0;
+/// Longest visible wavelength, in nanometers.
+pub const WAVELENGTH_MAX: f32 = 780.0;
+/// Wavelength of the yellow helium line, in nanometers, at which refraction indices are given.
+pub const D_LINE: f32 = 587.6;
+
+/// Average of [`linear_rgb`] over the visible range, per channel, for the white of an even
+/// spectrum.
+const WHITE: Color = Color { x: 0.440_457, y: 0.288_466, z: 0.273_240 };
+
+/// Wavelength at `u` between 0 and 1 in the visible range, evenly.
+pub fn wavelength_at(u: f32) -> f32 {
+    WAVELENGTH_MIN + u * (WAVELENGTH_MAX - WAVELENGTH_MIN)
+}
+
+/// CIE 1931 color matching functions, the multi-lobe fit of Wyman, Sloan and Shirley.
+///
+/// # Returns
+/// The X, Y and Z responses of the standard observer to light of `wavelength` nanometers.
+pub fn cie_xyz(wavelength: f32) -> Vec3 {
+    // Gaussian of different widths below and above its mean.
+    let g = |mean: f32, below: f32, above: f32| {
+        let t = (wavelength - mean) / if wavelength < mean { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// Linear sRGB color of CIE XYZ tristimulus values, the saturated colors out of the sRGB gamut
+/// being clipped.
+pub fn xyz_to_rgb(xyz: &Vec3) -> Color {
+    let Vec3 { x, y, z } = *xyz;
+    Color::new(
+        (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.0),
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.0),
+        (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.0),
+    )
+}
+
+/// Linear sRGB color of a single wavelength.
+fn linear_rgb(wavelength: f32) -> Color {
+    xyz_to_rgb(&cie_xyz(wavelength))
+}
+
+/// Weight of the RGB radiance of a ray of `wavelength` nanometers.
+///
+/// The weights of the wavelengths picked evenly with [`wavelength_at`] average to white: a
+/// scene without dispersion renders as in RGB.
+pub fn wavelength_rgb(wavelength: f32) -> Color {
+    let c = linear_rgb(wavelength);
+    Color::new(c.x / WHITE.x, c.y / WHITE.y, c.z / WHITE.z)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::Color;
+    use crate::spectrum::{cie_xyz, wavelength_at, wavelength_rgb};
+
+    #[test]
+    fn test_color_matching_functions() {
+        // The eye is most sensitive to green light.
+        let peak = (380..=780).max_by(|&a, &b| cie_xyz(a as f32).y.total_cmp(&cie_xyz(b as f32).y));
+        assert!((550..=560).contains(&peak.unwrap()), "{peak:?}");
+        assert!(cie_xyz(450.0).z > 1.5 && cie_xyz(450.0).y < 0.1);
+        assert!(cie_xyz(780.0).len() < 1e-3);
+    }
+
+    #[test]
+    fn test_wavelength_colors() {
+        let blue = wavelength_rgb(450.0);
+        assert!(blue.z > 5.0 * blue.x && blue.y == 0.0, "{blue:?}");
+        let green = wavelength_rgb(520.0);
+        assert!(green.y > 0.0 && green.x == 0.0 && green.z == 0.0, "{green:?}");
+        let red = wavelength_rgb(650.0);
+        assert!(red.x > 0.0 && red.y == 0.0 && red.z == 0.0, "{red:?}");
+
+        // An even spectrum is white.
+        let n = 10_000;
+        let sum = (0..n).fold(Color::BLACK, |sum, i| {
+            sum + wavelength_rgb(wavelength_at((i as f32 + 0.5) / n as f32))
+        });
+        assert!((sum / n as f32 - Color::WHITE).len() < 1e-3, "{:?}", sum / n as f32);
+    }
+}
@@ -0,0 +1,117 @@
+//! Scene statistics, to check what is about to be rendered.
+use crate::aabb::Aabb;
+use crate::render::{Object, Scene};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+/// Summary of a scene and of its acceleration structure.
+#[derive(Debug, Copy, Clone)]
+pub struct SceneStats {
+    pub objects: usize,
+    pub materials: usize,
+    pub lights: usize,
+    pub bounds: Aabb,
+    pub bvh_nodes: usize,
+    pub bvh_depth: usize,
+    /// Estimated memory used by the scene and its BVH, in bytes.
+    pub memory: usize,
+}
+
+impl SceneStats {
+    /// Compute the statistics of a scene, building its BVH.
+    pub fn of(scene: &Scene) -> Self {
+        let bvh = scene.bvh();
+        let world = scene.world();
+        // Meshes shared by instances count once.
+        let mut instanced = HashSet::new();
+        let buffers = world.objects().iter().map(|object| match object {
+            Object::Mesh(mesh) => mesh.memory_size(),
+            Object::Heightfield(heightfield) => heightfield.memory_size(),
+            Object::Instance(instance) if instanced.insert(Arc::as_ptr(instance.mesh())) => {
+                instance.mesh().memory_size()
+            }
+            _ => 0,
+        });
+        let memory = world.len() * std::mem::size_of::<Object>()
+            + buffers.sum::<usize>()
+            + scene.materials_memory_size()
+            + bvh.memory_size();
+
+        SceneStats {
+            objects: world.len(),
+            materials: scene.material_count(),
+            lights: scene.light_count(),
+            bounds: bvh.bounding_box(),
+            bvh_nodes: bvh.node_count(),
+            bvh_depth: bvh.depth(),
+            memory,
+        }
+    }
+}
+
+impl fmt::Display for SceneStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        let extent = self.bounds.extent();
+        writeln!(f, "Objects        : {}", self.objects)?;
+        writeln!(f, "Materials      : {}", self.materials)?;
+        writeln!(f, "Lights         : {}", self.lights)?;
+        writeln!(f, "Bounds min     : ({:.3}, {:.3}, {:.3})", min.x, min.y, min.z)?;
+        writeln!(f, "Bounds max     : ({:.3}, {:.3}, {:.3})", max.x, max.y, max.z)?;
+        writeln!(f, "Bounds extent  : {:.3} x {:.3} x {:.3}", extent.x, extent.y, extent.z)?;
+        writeln!(f, "BVH nodes      : {}", self.bvh_nodes)?;
+        writeln!(f, "BVH depth      : {}", self.bvh_depth)?;
+        write!(f, "Memory (est.)  : {:.1} KiB", self.memory as f32 / 1024.0)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::Point;
+    use crate::instance::test::square;
+    use crate::instance::{Instance, Transform};
+    use crate::registry::MaterialId;
+    use crate::render::{Object, Scene};
+    use crate::stats::SceneStats;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_demo_scene_stats() {
+        let stats = SceneStats::of(&Scene::demo());
+
+        assert_eq!(stats.objects, 4);
+        assert_eq!(stats.materials, 6);
+        assert_eq!(stats.lights, 0);
+        assert_eq!(stats.bvh_nodes, 3);
+        assert_eq!(stats.bvh_depth, 2);
+        assert_eq!(stats.bounds.min, Point::new(-100.0, -200.5, -101.0));
+        assert_eq!(stats.bounds.max, Point::new(100.0, 0.5, 99.0));
+        assert!(stats.memory > 0);
+
+        let printed = stats.to_string();
+        assert!(printed.contains("Objects        : 4"));
+        assert!(printed.contains("BVH depth      : 2"));
+    }
+
+    #[test]
+    fn test_instanced_mesh_stats() {
+        // The triangles of a forest are stored once, those of separate meshes each time.
+        let tree = Arc::new(square());
+        let forest = |instanced: bool| {
+            let mut scene = Scene::demo();
+            for _ in 0..100 {
+                if instanced {
+                    let instance = Instance::new(tree.clone(), Transform::default(), MaterialId(0));
+                    scene.world_mut().add(instance);
+                } else {
+                    scene.world_mut().add(Object::Mesh(tree.clone()));
+                }
+            }
+            SceneStats::of(&scene)
+        };
+        let (instances, meshes) = (forest(true), forest(false));
+        assert_eq!(instances.objects, 104);
+        assert_eq!(instances.memory + 99 * tree.memory_size(), meshes.memory);
+    }
+}
@@ -0,0 +1,192 @@
+//! Stereo rendering, for VR headsets: the scene seen by each eye, side by side in one image.
+//!
+//! The eyes look in the same direction as the camera, moved apart sideways by the distance
+//! between the eyes. Both render in the same pass, the left eye in the left half of the image.
+use crate::geometry::Vec3;
+use crate::render::{Camera, Scene};
+use crate::settings::RenderSettings;
+use crate::view::OrbitView;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Average distance between the pupils of adults, in meters.
+pub const DEFAULT_EYE_SEPARATION: f32 = 0.064;
+
+/// Stereo settings of a render, see the [module](self) documentation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stereo {
+    /// Distance between the eyes, in scene units.
+    pub eye_separation: f32,
+}
+
+impl Default for Stereo {
+    fn default() -> Self {
+        Stereo { eye_separation: DEFAULT_EYE_SEPARATION }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    pub const ALL: [Eye; 2] = [Eye::Left, Eye::Right];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Eye::Left => "left",
+            Eye::Right => "right",
+        }
+    }
+}
+
+/// How the images of the eyes are written.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// One image, the left eye in the left half.
+    #[default]
+    SideBySide,
+    /// One image per eye, see [`eye_path`].
+    Separate,
+}
+
+impl StereoLayout {
+    pub const ALL: [StereoLayout; 2] = [StereoLayout::SideBySide, StereoLayout::Separate];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StereoLayout::SideBySide => "side-by-side",
+            StereoLayout::Separate => "separate",
+        }
+    }
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StereoLayout::ALL.into_iter().find(|l| l.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = StereoLayout::ALL.iter().map(|l| l.name()).collect();
+            format!("unknown stereo layout '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+/// Path of the image of an eye, written next to the image at `path`: `out/image.png` gives
+/// `out/image_left.png` for the left eye.
+pub fn eye_path(path: &str, eye: Eye) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let name = match path.extension().and_then(|s| s.to_str()) {
+        Some(ext) => format!("{stem}_{}.{ext}", eye.name()),
+        None => format!("{stem}_{}", eye.name()),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// The view of an eye: the view moved sideways by `offset`, to the right when positive.
+fn eye_view(view: &OrbitView, offset: f32) -> OrbitView {
+    let right = Vec3::UNIT_Y.cross(&(view.position() - view.target)).normed();
+    OrbitView { target: view.target + offset * right, ..*view }
+}
+
+/// The cameras of a render, side by side in the image: one, or one per eye for stereo
+/// renders.
+pub(crate) struct Cameras {
+    cameras: Vec<Camera>,
+    /// Width of the part of the image each camera sees.
+    width: usize,
+}
+
+impl Cameras {
+    /// The cameras seeing `view` in an image of the resolution of `settings`.
+    pub(crate) fn new(scene: &Scene, view: &OrbitView, settings: &RenderSettings) -> Self {
+        let (width, height) = (settings.width, settings.height);
+        match settings.stereo {
+            Some(stereo) => {
+                let (width, offset) = (width / 2, stereo.eye_separation / 2.0);
+                let cameras = [-offset, offset]
+                    .iter()
+                    .map(|&o| scene.camera(&eye_view(view, o), width, height))
+                    .collect();
+                Cameras { cameras, width }
+            }
+            None => Cameras { cameras: vec![scene.camera(view, width, height)], width },
+        }
+    }
+
+    /// Width of the part of the image each camera sees.
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The camera seeing column `i` of the image, and the column in its part.
+    pub(crate) fn at(&self, i: usize) -> (&Camera, usize) {
+        let k = (i / self.width).min(self.cameras.len() - 1);
+        (&self.cameras[k], i - k * self.width)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Color, Point};
+    use crate::render::{
+        render_hdr, render_with_progress, Background, MaterialParams, Scene, Sphere,
+    };
+    use crate::settings::RenderSettings;
+    use crate::stereo::{eye_path, Eye, Stereo, StereoLayout};
+    use crate::view::OrbitView;
+    use std::ops::ControlFlow;
+
+    #[test]
+    fn test_eye_names() {
+        assert_eq!(eye_path("out/image.png", Eye::Left), "out/image_left.png");
+        assert_eq!(eye_path("image", Eye::Right), "image_right");
+        assert_eq!("separate".parse(), Ok(StereoLayout::Separate));
+        assert!("anaglyph".parse::<StereoLayout>().is_err());
+    }
+
+    #[test]
+    fn test_stereo_render() {
+        // A white sphere close to the camera, on a black background.
+        let mut scene = Scene::empty();
+        scene.set_background(&Background::Solid(Color::BLACK));
+        let white = MaterialParams::DiffuseLight { color: Color::WHITE, intensity: 1.0 };
+        let white = scene.add_material(&white);
+        scene.world_mut().add(Sphere::new(Point::new(0.0, 0.0, -1.0), 0.3, white));
+        scene.set_view(&OrbitView::looking_at(&Point::ZERO, &Point::new(0.0, 0.0, -1.0)));
+        let stereo = Stereo { eye_separation: 1.0 };
+        let builder =
+            RenderSettings::builder().resolution(32, 8).samples_per_pixel(16).seed(Some(1));
+        let settings = builder.stereo(Some(stereo)).build().unwrap();
+        let im = render_hdr(&scene, &settings).unwrap();
+        assert_eq!((im.width, im.height), (32, 8));
+
+        // Each eye sees the sphere shifted towards the other eye.
+        let center = |x0: usize, red: &dyn Fn(usize, usize) -> f32| {
+            let columns = (0..16).map(|i| (0..8).map(|j| red(x0 + i, j)).sum::<f32>());
+            let (sum, total) = columns
+                .enumerate()
+                .fold((0.0, 0.0), |(sum, total), (i, c)| (sum + i as f32 * c, total + c));
+            sum / total
+        };
+        let red = |i, j| im.at(i, j).0;
+        let (left, right) = (center(0, &red), center(16, &red));
+        assert!(left - right > 3.0, "{left} {right}");
+
+        // So do those of a render reporting its progress.
+        let im = render_with_progress(&scene, &settings, |_, _| ControlFlow::Continue(())).unwrap();
+        let red = |i, j| im.at(i, j).0 as f32;
+        let (eyes, expected) = ((center(0, &red), center(16, &red)), (left, right));
+        assert!(
+            (eyes.0 - left).abs() < 1.0 && (eyes.1 - right).abs() < 1.0,
+            "{eyes:?} {expected:?}"
+        );
+
+        assert!(RenderSettings::builder().resolution(31, 8).stereo(Some(stereo)).build().is_err());
+        let stereo = Stereo { eye_separation: -1.0 };
+        assert!(RenderSettings::builder().stereo(Some(stereo)).build().is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Sub-surface scattering: light entering translucent objects, as skin, wax or marble, walks
+//! at random inside them before leaving them, away from where it entered.
+use crate::bvh::Bvh;
+use crate::geometry::{random_unit_vector, Color, Vec3};
+use crate::interval::Interval;
+use crate::onb::Onb;
+use crate::pdf::random_cosine_direction;
+use crate::ray::Ray;
+use crate::render::{HitRecord, Material, MaterialParams, ScatterResult};
+use crate::sampler::Sampler;
+use rand::Rng;
+
+/// Longest random walk inside an object, after which the light is absorbed.
+const MAX_STEPS: usize = 256;
+
+/// Albedo of a single scattering event inside a volume which reflects `albedo` of the light
+/// once all the events are added up, after Van de Hulst, as fitted by Christensen.
+pub fn single_scattering_albedo(albedo: f32) -> f32 {
+    let a = albedo.clamp(0.0, 1.0);
+    let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+    1.0 - s * s
+}
+
+/// Translucent material: the light enters the object diffusely, scatters isotropically inside
+/// it, and leaves it diffusely.
+///
+/// The walk inside the object is traced in the scene, the first surface it meets being where
+/// it leaves: objects must be closed, and not overlap others.
+pub(crate) struct Subsurface {
+    albedo: Color,
+    mean_free_path: Color,
+    /// Extinction coefficient, per unit of distance.
+    extinction: Color,
+    /// Scattering coefficient, per unit of distance, the rest of the extinction being
+    /// absorbed.
+    scattering: Color,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, mean_free_path: Color) -> Self {
+        let extinction =
+            Color::new(1.0 / mean_free_path.x, 1.0 / mean_free_path.y, 1.0 / mean_free_path.z);
+        let single = Color::new(
+            single_scattering_albedo(albedo.x),
+            single_scattering_albedo(albedo.y),
+            single_scattering_albedo(albedo.z),
+        );
+        Subsurface { albedo, mean_free_path, extinction, scattering: single * extinction }
+    }
+
+    /// Transmittance over `distance`, per channel.
+    fn transmittance(&self, distance: f32) -> Color {
+        let t = |sigma: f32| (-sigma * distance).exp();
+        Color::new(t(self.extinction.x), t(self.extinction.y), t(self.extinction.z))
+    }
+
+    /// Random walk inside the object, from where the light entered it along `ray`.
+    ///
+    /// # Returns
+    /// The ray leaving the object, and its weight, or `None` when the light is absorbed.
+    fn walk(&self, mut ray: Ray, world: &Bvh, rng: &mut Sampler) -> Option<(Color, Ray)> {
+        let mut weight = Color::WHITE;
+        for step in 0..MAX_STEPS {
+            // The distance to the next event follows the extinction of a random channel, so
+            // that each channel is sampled well.
+            let channel = rng.gen_range(0..3);
+            let sigma = [self.extinction.x, self.extinction.y, self.extinction.z][channel];
+            let distance = -(1.0 - rng.gen::<f32>()).ln() / sigma;
+            let average = |c: Color| (c.x + c.y + c.z) / 3.0;
+
+            // Only the first step starts on the surface, the others may scatter right under it.
+            let min = if step == 0 { 0.001 } else { 0.0 };
+            let mut rec = HitRecord::new();
+            if world.hit(&ray, Interval::new(min, distance), &mut rec) {
+                // Out of the object through a rounding error, the walk goes on outside.
+                if rec.front_face {
+                    return Some((weight, ray));
+                }
+                // Leaving the object, the probability to go that far being averaged over the
+                // channels.
+                let transmittance = self.transmittance(rec.t);
+                weight = weight * transmittance / average(transmittance);
+                let outwards = Onb::from_w(&-rec.normal).local(&random_cosine_direction(rng));
+                return Some((
+                    weight,
+                    Ray { orig: rec.p, dir: outwards, time: ray.time, wavelength: ray.wavelength },
+                ));
+            }
+            // Scattered at `distance`, its density being averaged over the channels.
+            let density = self.extinction * self.transmittance(distance);
+            weight = weight * self.scattering * self.transmittance(distance) / average(density);
+            let orig = ray.at(distance);
+            ray = Ray {
+                orig,
+                dir: random_unit_vector(rng),
+                time: ray.time,
+                wavelength: ray.wavelength,
+            };
+        }
+        None
+    }
+}
+
+impl Material for Subsurface {
+    fn params(&self) -> MaterialParams {
+        MaterialParams::Subsurface { albedo: self.albedo, mean_free_path: self.mean_free_path }
+    }
+
+    /// Without the scene to walk in, diffuse as a lambertian surface of the same albedo.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Sampler) -> Option<ScatterResult> {
+        let dir = Onb::from_w(&rec.normal).local(&random_cosine_direction(rng));
+        Some(ScatterResult {
+            attenuation: self.albedo,
+            scattered: Ray { orig: rec.p, dir, time: r_in.time, wavelength: r_in.wavelength },
+            pdf: None,
+        })
+    }
+
+    fn scatter_through(
+        &self, r_in: &Ray, rec: &HitRecord, world: &Bvh, rng: &mut Sampler,
+    ) -> Option<ScatterResult> {
+        // Seen from the inside, as from a camera in the object, the surface lets light through.
+        if !rec.front_face {
+            let scattered =
+                Ray { orig: rec.p, dir: r_in.dir, time: r_in.time, wavelength: r_in.wavelength };
+            return Some(ScatterResult { attenuation: Color::WHITE, scattered, pdf: None });
+        }
+        let inwards: Vec3 = Onb::from_w(&-rec.normal).local(&random_cosine_direction(rng));
+        let entering =
+            Ray { orig: rec.p, dir: inwards, time: r_in.time, wavelength: r_in.wavelength };
+        let (attenuation, scattered) = self.walk(entering, world, rng)?;
+        Some(ScatterResult { attenuation, scattered, pdf: None })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::bvh::Bvh;
+    use crate::geometry::{dot, Color, Point, Vec3};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, Hittable, Material, Object, Sphere};
+    use crate::sampler::Sampler;
+    use crate::subsurface::{single_scattering_albedo, Subsurface};
+
+    #[test]
+    fn test_single_scattering_albedo() {
+        assert_float_absolute_eq!(single_scattering_albedo(0.0), 0.0, 1e-3);
+        assert_float_absolute_eq!(single_scattering_albedo(1.0), 1.0, 1e-3);
+        // Many events darken the light a lot: a whitish surface needs little absorption.
+        let albedos: Vec<f32> =
+            [0.2, 0.5, 0.8].iter().map(|&a| single_scattering_albedo(a)).collect();
+        assert!(albedos.windows(2).all(|w| w[0] < w[1]));
+        assert!(albedos.iter().zip([0.2, 0.5, 0.8]).all(|(single, a)| *single > a));
+    }
+
+    /// A unit sphere, a ray hitting it from above, and the hit.
+    fn sphere() -> (Bvh, Ray, HitRecord) {
+        let sphere = Sphere::new(Point::ZERO, 1.0, MaterialId(0));
+        let world = Bvh::new(&[Object::Sphere(sphere)]);
+        let r_in = Ray::new(Point::new(0.0, 3.0, 0.0), -Vec3::UNIT_Y);
+        let mut rec = HitRecord::new();
+        assert!(sphere.hit(&r_in, Interval::new(0.001, f32::INFINITY), &mut rec));
+        (world, r_in, rec)
+    }
+
+    #[test]
+    fn test_light_leaves_the_object() {
+        let (world, r_in, rec) = sphere();
+        let mut rng = Sampler::new(0);
+        // Without absorption, all the light leaves the sphere, from its surface, outwards.
+        let wax = Subsurface::new(Color::WHITE, Color::new(0.2, 0.2, 0.2));
+        let mut below = 0;
+        for _ in 0..1000 {
+            let scattered = wax.scatter_through(&r_in, &rec, &world, &mut rng).unwrap();
+            assert!((scattered.attenuation - Color::WHITE).len() < 1e-4);
+            let ray = scattered.scattered;
+            assert_float_absolute_eq!(ray.orig.len(), 1.0, 1e-3);
+            assert!(dot(&ray.dir, &ray.orig) > 0.0);
+            below += usize::from(ray.orig.y < 0.5);
+        }
+        // Many away from where it entered.
+        assert!(below > 100, "{below}");
+    }
+
+    #[test]
+    fn test_colored_mean_free_paths() {
+        let (world, r_in, rec) = sphere();
+        let mut rng = Sampler::new(1);
+        // Each channel walks its own way, the light still all leaves on average.
+        let skin = Subsurface::new(Color::WHITE, Color::new(0.5, 0.35, 0.25));
+        let n = 20_000;
+        let mut sum = Color::BLACK;
+        for _ in 0..n {
+            sum += skin.scatter_through(&r_in, &rec, &world, &mut rng).unwrap().attenuation;
+        }
+        assert!((sum / n as f32 - Color::WHITE).len() < 0.05, "{:?}", sum / n as f32);
+
+        // With absorption, the reflected light gets the color of the albedo.
+        let albedo = Color::new(0.8, 0.5, 0.2);
+        let skin = Subsurface::new(albedo, Color::new(0.05, 0.05, 0.05));
+        let mut sum = Color::BLACK;
+        for _ in 0..n {
+            if let Some(scattered) = skin.scatter_through(&r_in, &rec, &world, &mut rng) {
+                sum += scattered.attenuation;
+            }
+        }
+        let reflected = sum / n as f32;
+        assert!(reflected.x > reflected.y && reflected.y > reflected.z, "{reflected:?}");
+        assert!((reflected - albedo).len() < 0.1, "{reflected:?}");
+    }
+}
@@ -0,0 +1,293 @@
+//! Textures: colors varying over the surface of the objects.
+use crate::error::RtError;
+use crate::geometry::{Color, Point};
+use crate::image::ImageRGBA;
+use crate::imageio::imread;
+use crate::perlin::Perlin;
+use std::sync::Arc;
+
+/// Seed of the noise textures, so that renders of a scene look the same.
+const NOISE_SEED: u64 = 0x5eed;
+/// Octaves of noise in the marble veins.
+const TURBULENCE_DEPTH: usize = 7;
+
+/// Editable description of a texture.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureParams {
+    /// The same color everywhere.
+    Solid(Color),
+    /// A 3D checkerboard of cubes `scale` wide, alternating two colors.
+    Checker { odd: Color, even: Color, scale: f32 },
+    /// An image mapped on the surface coordinates.
+    Image(ImageTexture),
+    /// Marble of `color`, with veins about `1 / scale` apart.
+    Noise { color: Color, scale: f32 },
+}
+
+impl From<Color> for TextureParams {
+    fn from(color: Color) -> Self {
+        TextureParams::Solid(color)
+    }
+}
+
+impl TextureParams {
+    /// Kind of texture, for display.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextureParams::Solid(_) => "solid",
+            TextureParams::Checker { .. } => "checker",
+            TextureParams::Image(_) => "image",
+            TextureParams::Noise { .. } => "noise",
+        }
+    }
+
+    /// Average color of the texture, seen from far away.
+    pub fn average(&self) -> Color {
+        match self {
+            TextureParams::Solid(color) => *color,
+            TextureParams::Checker { odd, even, .. } => (*odd + *even) / 2.0,
+            TextureParams::Image(texture) => texture.average,
+            TextureParams::Noise { color, .. } => 0.5 * *color,
+        }
+    }
+
+    pub(crate) fn build(&self) -> Arc<dyn Texture> {
+        match self {
+            TextureParams::Solid(color) => Arc::new(SolidColor { color: *color }),
+            TextureParams::Checker { odd, even, scale } => {
+                Arc::new(CheckerTexture { odd: *odd, even: *even, scale: *scale })
+            }
+            TextureParams::Image(texture) => Arc::new(texture.clone()),
+            TextureParams::Noise { color, scale } => Arc::new(NoiseTexture {
+                color: *color,
+                scale: *scale,
+                perlin: Perlin::new(NOISE_SEED),
+            }),
+        }
+    }
+}
+
+/// Color of a surface, depending on where it is hit.
+///
+/// Textures are shared between materials, and between render threads.
+pub(crate) trait Texture: Send + Sync {
+    /// The parameters this texture was built from.
+    fn params(&self) -> TextureParams;
+
+    /// Color at a hit point.
+    ///
+    /// # Arguments
+    /// - `u` - First surface coordinate, in `[0;1]`.
+    /// - `v` - Second surface coordinate, in `[0;1]`.
+    /// - `p` - The hit point.
+    fn value(&self, u: f32, v: f32, p: &Point) -> Color;
+}
+
+/// A texture of a single color.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SolidColor {
+    color: Color,
+}
+
+impl Texture for SolidColor {
+    fn params(&self) -> TextureParams {
+        TextureParams::Solid(self.color)
+    }
+
+    fn value(&self, _u: f32, _v: f32, _p: &Point) -> Color {
+        self.color
+    }
+}
+
+/// Checkerboard of cubes in space, alternating between two colors.
+///
+/// The cubes do not depend on the surface coordinates, so the pattern fits any shape.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct CheckerTexture {
+    odd: Color,
+    even: Color,
+    /// Side of the cubes.
+    scale: f32,
+}
+
+impl Texture for CheckerTexture {
+    fn params(&self) -> TextureParams {
+        TextureParams::Checker { odd: self.odd, even: self.even, scale: self.scale }
+    }
+
+    fn value(&self, _u: f32, _v: f32, p: &Point) -> Color {
+        let cell = |c: f32| (c / self.scale).floor() as i64;
+        if (cell(p.x) + cell(p.y) + cell(p.z)) % 2 == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// Marble: stripes along the z axis, bent by Perlin turbulence.
+pub(crate) struct NoiseTexture {
+    color: Color,
+    /// Frequency of the stripes.
+    scale: f32,
+    perlin: Perlin,
+}
+
+impl Texture for NoiseTexture {
+    fn params(&self) -> TextureParams {
+        TextureParams::Noise { color: self.color, scale: self.scale }
+    }
+
+    fn value(&self, _u: f32, _v: f32, p: &Point) -> Color {
+        let phase = self.scale * p.z + 10.0 * self.perlin.turbulence(p, TURBULENCE_DEPTH);
+        0.5 * (1.0 + phase.sin()) * self.color
+    }
+}
+
+/// An image wrapped around the surface coordinates, as a map of the earth on a sphere.
+///
+/// Copies share the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageTexture {
+    /// 8-bit texels, with gamma 2.0, from the top left corner as in image files.
+    image: Arc<ImageRGBA>,
+    average: Color,
+    /// File the image was loaded from, so that scene files can refer to it.
+    path: Option<String>,
+}
+
+/// Linear color of an 8-bit gamma 2.0 texel.
+fn decode_texel(image: &ImageRGBA, i: usize, j: usize) -> Color {
+    let (r, g, b, _) = image.at(i, j);
+    let f = |c: u8| (c as f32 / 255.0).powi(2);
+    Color::new(f(r), f(g), f(b))
+}
+
+impl ImageTexture {
+    /// # Returns
+    /// The texture, or an error if the image has no pixels.
+    pub fn new(image: ImageRGBA) -> Result<Self, RtError> {
+        if image.width == 0 || image.height == 0 {
+            return Err(RtError::Texture("an image texture needs at least one pixel".to_string()));
+        }
+        let texels = (0..image.height).flat_map(|j| (0..image.width).map(move |i| (i, j)));
+        let sum = texels.fold(Color::BLACK, |sum, (i, j)| sum + decode_texel(&image, i, j));
+        let average = sum / (image.width * image.height) as f32;
+        Ok(ImageTexture { image: Arc::new(image), average, path: None })
+    }
+
+    /// Load the texture from an image file, see [`imread`] for the formats.
+    pub fn load(fpath: &str) -> Result<Self, RtError> {
+        let texture = ImageTexture::new(imread(fpath)?)?;
+        Ok(ImageTexture { path: Some(fpath.to_string()), ..texture })
+    }
+
+    /// The file the image was loaded from, `None` for images built in memory.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height
+    }
+}
+
+impl Texture for ImageTexture {
+    fn params(&self) -> TextureParams {
+        TextureParams::Image(self.clone())
+    }
+
+    /// Bilinear sample of the image, with `(0, 0)` at its bottom left corner. The image
+    /// repeats horizontally, and its top and bottom rows stretch beyond `v` = 1 and 0.
+    fn value(&self, u: f32, v: f32, _p: &Point) -> Color {
+        let (width, height) = (self.image.width, self.image.height);
+        // Texel centers sit at half integer coordinates, rows go down in the image.
+        let x = u.rem_euclid(1.0) * width as f32 - 0.5;
+        let y = (1.0 - v.clamp(0.0, 1.0)) * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let texel = |i: f32, j: f32| {
+            let i = (i as isize).rem_euclid(width as isize) as usize;
+            let j = (j as isize).clamp(0, height as isize - 1) as usize;
+            decode_texel(&self.image, i, j)
+        };
+        let top = (1.0 - tx) * texel(x0, y0) + tx * texel(x0 + 1.0, y0);
+        let bottom = (1.0 - tx) * texel(x0, y0 + 1.0) + tx * texel(x0 + 1.0, y0 + 1.0);
+        (1.0 - ty) * top + ty * bottom
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Color, Point};
+    use crate::image::ImageRGBA;
+    use crate::texture::{ImageTexture, TextureParams};
+
+    #[test]
+    fn test_solid_color() {
+        let texture = TextureParams::from(Color::new(0.1, 0.2, 0.3)).build();
+        assert_eq!(texture.value(0.5, 0.5, &Point::ZERO), Color::new(0.1, 0.2, 0.3));
+        assert_eq!(texture.params(), TextureParams::Solid(Color::new(0.1, 0.2, 0.3)));
+    }
+
+    #[test]
+    fn test_checker() {
+        let params = TextureParams::Checker { odd: Color::BLACK, even: Color::WHITE, scale: 0.5 };
+        let texture = params.build();
+        assert_eq!(texture.params(), params);
+        assert_eq!(texture.value(0.0, 0.0, &Point::new(0.25, 0.25, 0.25)), Color::WHITE);
+        assert_eq!(texture.value(0.0, 0.0, &Point::new(0.75, 0.25, 0.25)), Color::BLACK);
+        // Negative coordinates continue the pattern.
+        assert_eq!(texture.value(0.0, 0.0, &Point::new(-0.25, 0.25, 0.25)), Color::BLACK);
+        assert_eq!(texture.value(0.0, 0.0, &Point::new(-0.25, -0.25, 0.25)), Color::WHITE);
+        assert_eq!(params.average(), Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn test_noise() {
+        let params = TextureParams::Noise { color: Color::new(1.0, 0.5, 0.0), scale: 4.0 };
+        let texture = params.build();
+        assert_eq!(texture.params(), params);
+        let p = Point::new(0.3, 1.2, -0.7);
+        // The same noise in every build.
+        assert_eq!(texture.value(0.0, 0.0, &p), params.build().value(0.0, 0.0, &p));
+
+        let values: Vec<Color> = (0..100)
+            .map(|i| texture.value(0.0, 0.0, &Point::new(0.0, 0.0, i as f32 * 0.1)))
+            .collect();
+        assert!(values
+            .iter()
+            .all(|c| (0.0..=1.0).contains(&c.x) && c.y == c.x / 2.0 && c.z == 0.0));
+        assert!(values.iter().any(|c| c.x < 0.2) && values.iter().any(|c| c.x > 0.8));
+    }
+
+    #[test]
+    fn test_image_texture() {
+        // White on the top row, black on the bottom one.
+        let mut image = ImageRGBA::new(2, 2);
+        image.put(0, 0, 255, 255, 255, 255);
+        image.put(1, 0, 255, 255, 255, 255);
+        image.put(0, 1, 0, 0, 0, 255);
+        image.put(1, 1, 0, 0, 0, 255);
+        let texture = ImageTexture::new(image).unwrap();
+        assert_eq!(TextureParams::Image(texture.clone()).average(), Color::new(0.5, 0.5, 0.5));
+
+        let texture = TextureParams::Image(texture).build();
+        let p = Point::ZERO;
+        assert_eq!(texture.value(0.25, 0.75, &p), Color::WHITE);
+        assert_eq!(texture.value(0.25, 0.25, &p), Color::BLACK);
+        assert_eq!(texture.value(0.75, 1.0, &p), Color::WHITE);
+        // Bilinear filtering between the rows.
+        assert_eq!(texture.value(0.0, 0.5, &p), Color::new(0.5, 0.5, 0.5));
+        // Repeated horizontally, stretched vertically.
+        assert_eq!(texture.value(1.75, -1.0, &p), Color::BLACK);
+
+        let err = ImageTexture::new(ImageRGBA::new(0, 3)).err().unwrap();
+        assert_eq!(err.to_string(), "an image texture needs at least one pixel");
+        assert!(ImageTexture::load("does/not/exist.png").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Texture cache: image textures loaded on first use, shared between the materials using them,
+//! and evicted when they take more memory than allowed.
+use crate::error::RtError;
+use crate::imageio::imread_hdr;
+use crate::mipmap::MipChain;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Default memory budget of a cache, in bytes.
+pub const DEFAULT_BUDGET: usize = 1 << 30;
+
+struct CachedTexture {
+    texture: Arc<MipChain>,
+    /// Value of the access counter when the texture was last used.
+    last_used: u64,
+}
+
+/// Textures loaded from files, by file path.
+///
+/// The cache hands out shared textures: an evicted texture stays alive for the materials
+/// still using it, and is loaded again the next time it is asked for.
+pub struct TextureCache {
+    /// Most memory the textures may take, in bytes.
+    budget: usize,
+    textures: HashMap<String, CachedTexture>,
+    /// Incremented at each access, to find the least recently used textures.
+    accesses: u64,
+}
+
+impl Default for TextureCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUDGET)
+    }
+}
+
+impl TextureCache {
+    /// An empty cache.
+    ///
+    /// # Arguments
+    /// - `budget` - Most memory the textures may take, in bytes. A texture larger than the
+    ///   budget is still loaded, and evicted as soon as another one is.
+    pub fn new(budget: usize) -> Self {
+        TextureCache { budget, textures: HashMap::new(), accesses: 0 }
+    }
+
+    /// The texture of an image file, loaded and prefiltered on first use.
+    ///
+    /// # Arguments
+    /// - `fpath` - Path of the image, see [`imread_hdr`] for the formats.
+    pub fn get(&mut self, fpath: &str) -> Result<Arc<MipChain>, RtError> {
+        self.accesses += 1;
+        if let Some(cached) = self.textures.get_mut(fpath) {
+            cached.last_used = self.accesses;
+            return Ok(cached.texture.clone());
+        }
+
+        let texture = Arc::new(MipChain::new(imread_hdr(fpath)?));
+        self.textures.insert(
+            fpath.to_string(),
+            CachedTexture { texture: texture.clone(), last_used: self.accesses },
+        );
+        self.evict(fpath);
+        Ok(texture)
+    }
+
+    /// Drop the least recently used textures until the others fit in the budget.
+    ///
+    /// # Arguments
+    /// - `keep` - Path of the texture just used, which stays.
+    fn evict(&mut self, keep: &str) {
+        while self.memory_size() > self.budget {
+            let oldest = self
+                .textures
+                .iter()
+                .filter(|(fpath, _)| fpath.as_str() != keep)
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(fpath, _)| fpath.clone());
+            match oldest {
+                Some(fpath) => self.textures.remove(&fpath),
+                None => return,
+            };
+        }
+    }
+
+    /// Change the memory budget, evicting textures if needed.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict("");
+    }
+
+    /// Memory taken by the cached textures, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.textures.values().map(|cached| cached.texture.memory_size()).sum()
+    }
+
+    /// Number of cached textures.
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::image::ImageRGBA;
+    use crate::imageio::imwrite;
+    use crate::texture_cache::TextureCache;
+    use std::sync::Arc;
+
+    /// Write `count` 4x4 textures, returning their paths.
+    fn write_textures(dir: &tempfile::TempDir, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|k| {
+                let fpath = dir.path().join(format!("texture{k}.png"));
+                let fpath = fpath.to_str().unwrap().to_string();
+                imwrite(&fpath, &ImageRGBA::new(4, 4)).unwrap();
+                fpath
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_textures_are_loaded_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpaths = write_textures(&dir, 1);
+        let mut cache = TextureCache::default();
+        assert!(cache.is_empty());
+
+        let a = cache.get(&fpaths[0]).unwrap();
+        let b = cache.get(&fpaths[0]).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.memory_size(), a.memory_size());
+    }
+
+    #[test]
+    fn test_least_recently_used_textures_are_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let fpaths = write_textures(&dir, 3);
+        let mut cache = TextureCache::default();
+        let size = cache.get(&fpaths[0]).unwrap().memory_size();
+        cache.set_budget(2 * size);
+
+        let first = cache.get(&fpaths[0]).unwrap();
+        cache.get(&fpaths[1]).unwrap();
+        cache.get(&fpaths[0]).unwrap();
+        cache.get(&fpaths[2]).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(Arc::ptr_eq(&first, &cache.get(&fpaths[0]).unwrap()));
+
+        // Evicted textures stay usable, and are loaded again when asked for.
+        let second = cache.get(&fpaths[1]).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(second.levels().len(), 3);
+
+        cache.set_budget(0);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_missing_texture() {
+        let mut cache = TextureCache::default();
+        assert!(cache.get("does/not/exist.png").is_err());
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+//! Thin-film interference: a transparent coating, as of a soap bubble or an oil slick, a few
+//! hundred nanometers thick, whose reflections off its two sides add up or cancel out depending
+//! on the wavelength, coloring the surface under it.
+use crate::geometry::Color;
+use crate::spectrum::{wavelength_at, wavelength_rgb};
+use std::f32::consts::PI;
+
+/// Number of wavelengths over which the reflectance of a film is averaged in RGB renders, close
+/// enough for the fringes of films up to about a micrometer.
+const WAVELENGTHS: usize = 40;
+
+/// A transparent film coating a surface.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ThinFilm {
+    /// Thickness, in nanometers.
+    pub thickness: f32,
+    pub refraction_index: f32,
+}
+
+impl Default for ThinFilm {
+    /// A soap film.
+    fn default() -> Self {
+        ThinFilm { thickness: 400.0, refraction_index: 1.33 }
+    }
+}
+
+/// What is under a film.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Substrate {
+    /// A transparent material of this refraction index.
+    Dielectric(f32),
+    /// A metal reflecting this share of the light, whatever the angle.
+    Conductor(f32),
+}
+
+/// Fresnel amplitude reflection coefficients for the s and p polarizations, from a medium of
+/// index `n1` to one of index `n2`, for the cosines of the angles on each side.
+fn fresnel_amplitudes(n1: f32, cos1: f32, n2: f32, cos2: f32) -> [f32; 2] {
+    [
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+    ]
+}
+
+/// Cosine of the refracted angle from a medium of index `n1` into one of index `n2`, `None`
+/// past the critical angle.
+fn refracted_cosine(n1: f32, cosine: f32, n2: f32) -> Option<f32> {
+    let sin2 = (n1 / n2).powi(2) * (1.0 - cosine * cosine);
+    (sin2 < 1.0).then(|| (1.0 - sin2).sqrt())
+}
+
+impl ThinFilm {
+    /// Share of the light of `wavelength` nanometers reflected by the film and the surface
+    /// under it, Airy's sum of the reflections inside the film, unpolarized.
+    ///
+    /// # Arguments
+    /// - `outside` - Refraction index of the medium the light comes from.
+    /// - `substrate` - What the film coats.
+    /// - `cosine` - Cosine of the incidence angle.
+    pub(crate) fn reflectance(
+        &self, outside: f32, substrate: Substrate, cosine: f32, wavelength: f32,
+    ) -> f32 {
+        let n = self.refraction_index;
+        let Some(cos_film) = refracted_cosine(outside, cosine, n) else {
+            return 1.0;
+        };
+        let outer = fresnel_amplitudes(outside, cosine, n, cos_film);
+        let inner = match substrate {
+            Substrate::Dielectric(index) => {
+                // Light that cannot leave the film is all reflected, the film absorbing none.
+                let Some(cos_substrate) = refracted_cosine(n, cos_film, index) else {
+                    return 1.0;
+                };
+                fresnel_amplitudes(n, cos_film, index, cos_substrate)
+            }
+            // Reflected with the phase shift of a perfect conductor.
+            Substrate::Conductor(reflectance) => [-reflectance.sqrt(); 2],
+        };
+        // Phase difference between two reflections, one more round trip in the film apart.
+        let phase = 4.0 * PI * n * self.thickness * cos_film / wavelength;
+        let airy = |r1: f32, r2: f32| {
+            let interference = 2.0 * r1 * r2 * phase.cos();
+            (r1 * r1 + r2 * r2 + interference) / (1.0 + r1 * r1 * r2 * r2 + interference)
+        };
+        (airy(outer[0], inner[0]) + airy(outer[1], inner[1])) / 2.0
+    }
+
+    /// [`ThinFilm::reflectance`] of each channel, at the `wavelength` of a spectral ray, or
+    /// averaged over the visible spectrum weighted by the color of each wavelength.
+    ///
+    /// # Arguments
+    /// - `substrates` - What the film coats, for each channel.
+    pub(crate) fn reflectance_rgb(
+        &self, outside: f32, substrates: [Substrate; 3], cosine: f32, wavelength: Option<f32>,
+    ) -> Color {
+        let at = |wavelength| {
+            let [r, g, b] = substrates.map(|s| self.reflectance(outside, s, cosine, wavelength));
+            Color::new(r, g, b)
+        };
+        if let Some(wavelength) = wavelength {
+            return at(wavelength);
+        }
+        let mut sum = Color::BLACK;
+        for i in 0..WAVELENGTHS {
+            let wavelength = wavelength_at((i as f32 + 0.5) / WAVELENGTHS as f32);
+            sum += at(wavelength) * wavelength_rgb(wavelength);
+        }
+        sum / WAVELENGTHS as f32
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::Color;
+    use crate::sampler::Sampler;
+    use crate::thin_film::{Substrate, ThinFilm};
+    use rand::Rng;
+
+    #[test]
+    fn test_film_of_no_thickness() {
+        // As bare glass.
+        let film = ThinFilm { thickness: 0.0, refraction_index: 1.33 };
+        let glass = Substrate::Dielectric(1.5);
+        assert_float_absolute_eq!(film.reflectance(1.0, glass, 1.0, 550.0), 0.04, 1e-5);
+        assert_float_absolute_eq!(
+            film.reflectance(1.5, Substrate::Dielectric(1.0), 1.0, 550.0),
+            0.04,
+            1e-5
+        );
+        // Total internal reflection out of the glass.
+        assert_eq!(film.reflectance(1.5, Substrate::Dielectric(1.0), 0.5, 550.0), 1.0);
+    }
+
+    #[test]
+    fn test_antireflection_coating() {
+        // A quarter wave thick film, of the geometric mean of the indices around it, cancels
+        // the reflection of that wavelength at normal incidence.
+        let n = 1.5f32.sqrt();
+        let film = ThinFilm { thickness: 550.0 / (4.0 * n), refraction_index: n };
+        let glass = Substrate::Dielectric(1.5);
+        assert_float_absolute_eq!(film.reflectance(1.0, glass, 1.0, 550.0), 0.0, 1e-6);
+        assert!(film.reflectance(1.0, glass, 1.0, 400.0) > 0.01);
+        // Half a wave thick, the film is as absent.
+        let film = ThinFilm { thickness: 550.0 / (2.0 * n), refraction_index: n };
+        assert_float_absolute_eq!(film.reflectance(1.0, glass, 1.0, 550.0), 0.04, 1e-5);
+    }
+
+    #[test]
+    fn test_film_colors() {
+        // A soap bubble takes colors changing with its thickness and the angle.
+        let film = ThinFilm::default();
+        let air = [Substrate::Dielectric(1.0); 3];
+        let color = |film: &ThinFilm, cosine| film.reflectance_rgb(1.0, air, cosine, None);
+        let spread = |c: Color| c.x.max(c.y).max(c.z) - c.x.min(c.y).min(c.z);
+        assert!(spread(color(&film, 1.0)) > 0.02, "{:?}", color(&film, 1.0));
+        assert!((color(&film, 1.0) - color(&film, 0.5)).len() > 0.02);
+        let thinner = ThinFilm { thickness: 300.0, ..film };
+        assert!((color(&film, 1.0) - color(&thinner, 1.0)).len() > 0.02);
+
+        // The energy is conserved, a perfect mirror under a film stays perfect.
+        let mut rng = Sampler::new(0);
+        for _ in 0..1000 {
+            let film = ThinFilm {
+                thickness: rng.gen_range(0.0..2000.0),
+                refraction_index: rng.gen_range(1.0..2.5),
+            };
+            let (outside, index) = (rng.gen_range(1.0..2.0), rng.gen_range(1.0..2.5));
+            let (cosine, wavelength) = (rng.gen_range(0.0..=1.0), rng.gen_range(380.0..780.0));
+            let r = film.reflectance(outside, Substrate::Dielectric(index), cosine, wavelength);
+            assert!((0.0..=1.0 + 1e-5).contains(&r), "{film:?} {r}");
+            let mirror = film.reflectance(outside, Substrate::Conductor(1.0), cosine, wavelength);
+            assert_float_absolute_eq!(mirror, 1.0, 1e-5);
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Tiled rendering: the image is split into square tiles, traced in parallel.
+//!
+//! Tiles keep the rays of a thread close together in the scene, and give a steady progress
+//! report whatever the shape of the image.
+use crate::cancel::CancellationToken;
+use crate::error::RtError;
+use crate::geometry::Color;
+use crate::image::{ImageRGBA, ImageRGBF32};
+use crate::render::{sample_pixel, SampleStats, Scene};
+use crate::sampler::Sampler;
+use crate::settings::RenderSettings;
+use crate::stereo::Cameras;
+use rayon::prelude::*;
+use std::ops::ControlFlow;
+
+/// Default tile side, in pixels.
+pub const DEFAULT_TILE_SIZE: usize = 16;
+
+/// A rectangle of pixels of an image, rendered as a unit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tile {
+    /// Column of the bottom left pixel.
+    pub x: usize,
+    /// Row of the bottom left pixel.
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// State of a tiled render, after a tile completed.
+pub struct TileProgress<'a> {
+    /// Index of the tile in [`tile_grid`].
+    pub index: usize,
+    pub tile: Tile,
+    /// Pixels of the tile alone.
+    pub pixels: &'a ImageRGBA,
+    /// The whole image, with the tiles completed so far.
+    pub image: &'a ImageRGBA,
+    /// Linear radiance of the whole image, before gamma and clipping.
+    pub radiance: &'a ImageRGBF32,
+    /// Number of tiles completed, this one included.
+    pub tiles_done: usize,
+    pub tiles_total: usize,
+    /// Samples of the tiles completed, and how many were clamped or rejected.
+    pub stats: SampleStats,
+}
+
+impl TileProgress<'_> {
+    /// Share of the image completed, from 0 to 100.
+    pub fn percent(&self) -> f32 {
+        100.0 * self.tiles_done as f32 / self.tiles_total.max(1) as f32
+    }
+}
+
+/// Split an image into tiles of `tile_size` pixels, smaller on the right and top edges.
+///
+/// Tiles are ordered row by row from the top of the image, where the renders usually start.
+pub fn tile_grid(width: usize, height: usize, tile_size: usize) -> Vec<Tile> {
+    let size = tile_size.max(1);
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(size).rev() {
+        for x in (0..width).step_by(size) {
+            tiles.push(Tile { x, y, width: size.min(width - x), height: size.min(height - y) });
+        }
+    }
+    tiles
+}
+
+/// Render an image of a scene tile by tile, in parallel.
+///
+/// # Arguments
+/// - `scene` - The scene, seen from its camera.
+/// - `settings` - Settings of the render, the tiles are `settings.tile_size` wide.
+/// - `on_tile` - Called on the calling thread after each completed tile, in any order.
+///   Returning `ControlFlow::Break` stops the render, and the partial image is returned.
+///
+/// # Returns
+/// The image, or an error if the threads of the render cannot be started.
+pub fn render_tiles<F>(
+    scene: &Scene, settings: &RenderSettings, on_tile: F,
+) -> Result<ImageRGBA, RtError>
+where
+    F: FnMut(&TileProgress) -> ControlFlow<()>,
+{
+    Ok(render_tiles_hdr(scene, settings, on_tile)?.0)
+}
+
+/// Render an image of a scene tile by tile, in parallel, keeping its linear radiance.
+///
+/// Same as [`render_tiles`], for outputs in high dynamic range.
+///
+/// # Returns
+/// The image tone mapped with the settings, and the radiance it was encoded from.
+pub fn render_tiles_hdr<F>(
+    scene: &Scene, settings: &RenderSettings, on_tile: F,
+) -> Result<(ImageRGBA, ImageRGBF32), RtError>
+where
+    F: FnMut(&TileProgress) -> ControlFlow<()>,
+{
+    let (im, radiance, _) =
+        render_tiles_cancellable(scene, settings, &CancellationToken::new(), on_tile)?;
+    Ok((im, radiance))
+}
+
+/// Same as [`render_tiles_hdr`], stopping early when `token` is cancelled, from any thread.
+///
+/// The token is checked before each tile: the tiles being traced are completed and reported,
+/// the others are left black.
+///
+/// # Returns
+/// The image, its radiance, and `false` when the render stopped before the last tile, or an
+/// error if the threads of the render cannot be started.
+pub fn render_tiles_cancellable<F>(
+    scene: &Scene, settings: &RenderSettings, token: &CancellationToken, mut on_tile: F,
+) -> Result<(ImageRGBA, ImageRGBF32, bool), RtError>
+where
+    F: FnMut(&TileProgress) -> ControlFlow<()>,
+{
+    let (width, height) = (settings.width, settings.height);
+    let (depth, samples_per_pixel) = (settings.path_depth(), settings.samples());
+    let mut im = ImageRGBA::new(width, height);
+    let mut radiance = ImageRGBF32::new(width, height);
+    let scene = &settings.scene(scene);
+    let world = scene.bvh();
+    let cams = Cameras::new(scene, scene.view(), settings);
+    let seed = settings.seed.unwrap_or_else(rand::random);
+    let pattern = settings.sampler.build(seed);
+    let tiles = tile_grid(width, height, settings.tile_size);
+    let pool = settings.thread_pool()?;
+
+    let trace = |(index, tile): (usize, &Tile)| {
+        if token.is_cancelled() {
+            return None;
+        }
+        let mut pixels = ImageRGBA::new(tile.width, tile.height);
+        let mut colors = ImageRGBF32::new(tile.width, tile.height);
+        let mut stats = SampleStats::default();
+        for y in 0..tile.height {
+            // Each row of each tile has its own random numbers.
+            let mut rng = Sampler::for_row(seed, index, y);
+            for x in 0..tile.width {
+                let (cam, i) = cams.at(tile.x + x);
+                let c: Color = sample_pixel(
+                    i,
+                    tile.y + y,
+                    cams.width(),
+                    height,
+                    cam,
+                    &world,
+                    scene,
+                    depth,
+                    samples_per_pixel,
+                    settings.mode,
+                    pattern.as_ref(),
+                    settings.clamp_radiance,
+                    &mut stats,
+                    &mut rng,
+                );
+                let (r, g, b) = settings.encode(&c);
+                pixels.put(x, y, r, g, b, 255);
+                colors.put(x, y, c.x, c.y, c.z);
+            }
+        }
+        Some((index, pixels, colors, stats))
+    };
+
+    // Tiles are traced a batch at a time, one per thread, and reported from this thread.
+    let (mut tiles_done, mut stats) = (0, SampleStats::default());
+    let indexed: Vec<(usize, &Tile)> = tiles.iter().enumerate().collect();
+    for batch in indexed.chunks(settings.thread_count()) {
+        let trace_batch = || batch.par_iter().copied().map(trace).collect::<Vec<_>>();
+        let traced = match &pool {
+            Some(pool) => pool.install(trace_batch),
+            None => trace_batch(),
+        };
+        for (index, pixels, colors, tile_stats) in traced.into_iter().flatten() {
+            let tile = tiles[index];
+            for y in 0..tile.height {
+                for x in 0..tile.width {
+                    im.put_u32(tile.x + x, tile.y + y, pixels.at_u32(x, y));
+                    let (r, g, b) = colors.at(x, y);
+                    radiance.put(tile.x + x, tile.y + y, r, g, b);
+                }
+            }
+            tiles_done += 1;
+            stats += tile_stats;
+            let progress = TileProgress {
+                index,
+                tile,
+                pixels: &pixels,
+                image: &im,
+                radiance: &radiance,
+                tiles_done,
+                tiles_total: tiles.len(),
+                stats,
+            };
+            if on_tile(&progress).is_break() {
+                return Ok((im, radiance, false));
+            }
+        }
+        if token.is_cancelled() {
+            break;
+        }
+    }
+    let complete = tiles_done == tiles.len();
+    Ok((im, radiance, complete))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::cancel::CancellationToken;
+    use crate::geometry::{Color, Point};
+    use crate::image::ImageRGBA;
+    use crate::render::{Background, SampleStats, Scene};
+    use crate::settings::RenderSettings;
+    use crate::tiles::{render_tiles, render_tiles_cancellable, render_tiles_hdr, tile_grid, Tile};
+    use crate::view::OrbitView;
+    use std::ops::ControlFlow;
+
+    fn settings(width: usize, height: usize, max_depth: usize) -> RenderSettings {
+        let builder = RenderSettings::builder().resolution(width, height).max_depth(max_depth);
+        builder.samples_per_pixel(1).tile_size(4).build().unwrap()
+    }
+
+    fn scene() -> Scene {
+        let mut scene = Scene::demo();
+        scene.set_view(&OrbitView::looking_at(
+            &Point::new(-2.0, 2.0, 1.0),
+            &Point::new(0.0, 0.0, -1.0),
+        ));
+        scene
+    }
+
+    #[test]
+    fn test_tile_grid() {
+        let tiles = tile_grid(10, 7, 4);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[0], Tile { x: 0, y: 4, width: 4, height: 3 });
+        assert_eq!(tiles[2], Tile { x: 8, y: 4, width: 2, height: 3 });
+        assert_eq!(tiles[5], Tile { x: 8, y: 0, width: 2, height: 4 });
+        let area: usize = tiles.iter().map(|t| t.width * t.height).sum();
+        assert_eq!(area, 70);
+
+        assert!(tile_grid(0, 7, 4).is_empty());
+        assert_eq!(tile_grid(3, 2, 0).len(), 6);
+    }
+
+    #[test]
+    fn test_render_tiles_reports_every_tile() {
+        let mut reported = Vec::new();
+        let mut stitched = ImageRGBA::new(10, 7);
+        let im = render_tiles(&scene(), &settings(10, 7, 2), |progress| {
+            let t = progress.tile;
+            assert_eq!((progress.pixels.width, progress.pixels.height), (t.width, t.height));
+            assert_eq!(progress.tiles_total, 6);
+            // The tile is copied in the image before the report.
+            assert_eq!(progress.image.at(t.x, t.y), progress.pixels.at(0, 0));
+            for y in 0..t.height {
+                for x in 0..t.width {
+                    stitched.put_u32(t.x + x, t.y + y, progress.pixels.at_u32(x, y));
+                }
+            }
+            reported.push((progress.index, progress.percent()));
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+
+        assert_eq!((im.width, im.height), (10, 7));
+        assert_eq!(reported.last().unwrap().1, 100.0);
+        let mut indices: Vec<usize> = reported.iter().map(|r| r.0).collect();
+        indices.sort();
+        assert_eq!(indices, (0..6).collect::<Vec<_>>());
+        // The tiles cover the whole image.
+        assert_eq!(stitched.pixels, im.pixels);
+    }
+
+    #[test]
+    fn test_render_tiles_hdr() {
+        let mut scene = scene();
+        scene.set_background(&Background::Solid(Color::new(3.0, 0.5, 0.0)));
+        let settings = settings(10, 7, 1);
+        let (im, radiance) = render_tiles_hdr(&scene, &settings, |progress| {
+            let t = progress.tile;
+            let (r, g, b) = progress.radiance.at(t.x, t.y);
+            let (r8, g8, b8, _) = progress.image.at(t.x, t.y);
+            assert_eq!(settings.encode(&Color::new(r, g, b)), (r8, g8, b8));
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!((radiance.width, radiance.height), (10, 7));
+        // The background is seen at the top of the image, brighter than the 8-bit white.
+        assert_eq!(radiance.at(0, 6), (3.0, 0.5, 0.0));
+        assert_eq!(im.at(0, 6), (255, 181, 0, 255));
+    }
+
+    #[test]
+    fn test_clamped_tiles() {
+        let mut scene = scene();
+        scene.set_background(&Background::Solid(Color::new(3.0, 0.5, 0.0)));
+        let settings = RenderSettings { clamp_radiance: Some(1.5), ..settings(10, 7, 1) };
+        let mut stats = SampleStats::default();
+        let (_, radiance) = render_tiles_hdr(&scene, &settings, |progress| {
+            assert!(progress.stats.samples >= stats.samples);
+            stats = progress.stats;
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        // The background keeps its hue, at most as bright as the clamp.
+        assert_eq!(radiance.at(0, 6), (1.5, 0.25, 0.0));
+        assert_eq!(stats.samples, 70);
+        assert!(stats.clamped > 0 && stats.clamped < 70, "{stats}");
+        assert_eq!(stats.rejected, 0);
+    }
+
+    #[test]
+    fn test_render_tiles_can_be_stopped() {
+        let mut count = 0;
+        let im = render_tiles(&scene(), &settings(10, 7, 2), |_| {
+            count += 1;
+            ControlFlow::Break(())
+        })
+        .unwrap();
+        assert_eq!(count, 1);
+        // Untouched tiles keep the default background.
+        let default = ImageRGBA::new(1, 1).at_u32(0, 0);
+        assert!((0..10).any(|i| im.at_u32(i, 0) == default));
+    }
+
+    #[test]
+    fn test_render_tiles_can_be_cancelled() {
+        let settings = RenderSettings { threads: Some(2), ..settings(10, 7, 2) };
+        let token = CancellationToken::new();
+        let mut count = 0;
+        let (im, _, complete) = render_tiles_cancellable(&scene(), &settings, &token, |_| {
+            count += 1;
+            token.cancel();
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert!(!complete);
+        // The tiles of the first batch were already being traced.
+        assert_eq!(count, 2);
+        let default = ImageRGBA::new(1, 1).at_u32(0, 0);
+        assert!((0..10).any(|i| im.at_u32(i, 0) == default));
+
+        let (_, _, complete) = render_tiles_cancellable(&scene(), &settings, &token, |_| {
+            panic!("the token is already cancelled")
+        })
+        .unwrap();
+        assert!(!complete);
+        let (_, _, complete) =
+            render_tiles_cancellable(&scene(), &settings, &CancellationToken::new(), |_| {
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert!(complete);
+    }
+
+    #[test]
+    fn test_seeded_tiles_do_not_depend_on_threads() {
+        let render_on = |threads: usize| {
+            let settings = RenderSettings {
+                samples_per_pixel: 2,
+                seed: Some(3),
+                threads: Some(threads),
+                ..settings(12, 8, 8)
+            };
+            render_tiles(&scene(), &settings, |_| ControlFlow::Continue(())).unwrap().pixels
+        };
+        assert_eq!(render_on(1), render_on(4));
+    }
+}
@@ -0,0 +1,144 @@
+//! Tone mapping: turn linear radiance into displayable 8-bit pixels.
+use crate::image::{ImageRGBA, ImageRGBF32};
+use std::fmt;
+use std::str::FromStr;
+
+/// How values above 1 are brought into the displayable range.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// Values above 1 are clipped.
+    Clamp,
+    /// `x / (1 + x)`: never clips, flattens highlights.
+    Reinhard,
+    /// Filmic curve approximating the ACES reference rendering transform.
+    AcesFilmic,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [ToneMapOperator; 3] =
+        [ToneMapOperator::Clamp, ToneMapOperator::Reinhard, ToneMapOperator::AcesFilmic];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapOperator::Clamp => "clamp",
+            ToneMapOperator::Reinhard => "reinhard",
+            ToneMapOperator::AcesFilmic => "aces",
+        }
+    }
+
+    /// Map a linear value to `[0;1]`.
+    pub fn apply(&self, v: f32) -> f32 {
+        let v = v.max(0.0);
+        let mapped = match self {
+            ToneMapOperator::Clamp => v,
+            ToneMapOperator::Reinhard => v / (1.0 + v),
+            ToneMapOperator::AcesFilmic => (v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14),
+        };
+        mapped.min(1.0)
+    }
+}
+
+impl fmt::Display for ToneMapOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ToneMapOperator::ALL.into_iter().find(|o| o.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = ToneMapOperator::ALL.iter().map(|o| o.name()).collect();
+            format!("unknown tone mapping operator '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+/// Settings of [`tonemap`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ToneMapSettings {
+    pub operator: ToneMapOperator,
+    /// Exposure adjustment, in stops: each stop doubles the brightness.
+    pub exposure: f32,
+    /// Display gamma.
+    pub gamma: f32,
+}
+
+impl Default for ToneMapSettings {
+    /// Clipping with a gamma of 2, as the renderer does by default.
+    fn default() -> Self {
+        ToneMapSettings { operator: ToneMapOperator::Clamp, exposure: 0.0, gamma: 2.0 }
+    }
+}
+
+impl ToneMapSettings {
+    /// Map a linear value to 8 bits.
+    pub fn encode(&self, v: f32) -> u8 {
+        let scale = self.exposure.exp2();
+        let mapped = self.operator.apply(v * scale).powf(1.0 / self.gamma);
+        (mapped.clamp(0.0, 0.999) * 256.0) as u8
+    }
+}
+
+/// Convert a linear image to 8 bits, with an opaque alpha channel.
+pub fn tonemap(im: &ImageRGBF32, settings: &ToneMapSettings) -> ImageRGBA {
+    let pixels = im
+        .pixels
+        .chunks(3)
+        .flat_map(|px| {
+            [settings.encode(px[0]), settings.encode(px[1]), settings.encode(px[2]), 255]
+        })
+        .collect();
+    ImageRGBA { width: im.width, height: im.height, pixels }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::image::ImageRGBF32;
+    use crate::tonemap::{tonemap, ToneMapOperator, ToneMapSettings};
+
+    #[test]
+    fn test_default_is_clipped_gamma_2() {
+        let settings = ToneMapSettings::default();
+        for v in [0.0f32, 0.01, 0.2, 0.5, 0.99, 1.0, 3.0] {
+            let expected = (v.sqrt().clamp(0.0, 0.999) * 256.0) as u8;
+            assert_eq!(settings.encode(v), expected, "{v}");
+        }
+    }
+
+    #[test]
+    fn test_operators() {
+        for operator in ToneMapOperator::ALL {
+            assert_eq!(operator.apply(0.0), 0.0);
+            assert_eq!(operator.apply(-1.0), 0.0);
+            assert!(operator.apply(1e6) <= 1.0);
+            assert!(operator.apply(0.5) < operator.apply(0.6), "{operator}");
+            assert_eq!(operator.name().parse::<ToneMapOperator>(), Ok(operator));
+        }
+        assert_eq!(ToneMapOperator::Reinhard.apply(1.0), 0.5);
+        assert!("filmic".parse::<ToneMapOperator>().is_err());
+    }
+
+    #[test]
+    fn test_exposure_and_gamma() {
+        let mut settings = ToneMapSettings { gamma: 1.0, ..Default::default() };
+        assert_eq!(settings.encode(0.25), 64);
+
+        settings.exposure = 1.0;
+        assert_eq!(settings.encode(0.25), 128);
+        settings.exposure = -2.0;
+        assert_eq!(settings.encode(1.0), 64);
+    }
+
+    #[test]
+    fn test_tonemap_image() {
+        let mut im = ImageRGBF32::new(2, 1);
+        im.put(1, 0, 4.0, 0.25, 0.0);
+
+        let out = tonemap(&im, &ToneMapSettings::default());
+        assert_eq!((out.width, out.height), (2, 1));
+        assert_eq!(out.at(0, 0), (0, 0, 0, 255));
+        assert_eq!(out.at(1, 0), (255, 128, 0, 255));
+    }
+}
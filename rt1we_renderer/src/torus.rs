@@ -0,0 +1,180 @@
+//! Tori, rings around an axis: the quartic equation of their hits tests the precision of the
+//! intersection code.
+//!
+//! The quartic is solved in double precision, see [`crate::poly`], from an origin moved along
+//! the ray next to the torus, so that rays from far away keep the precision of close ones.
+use crate::aabb::Aabb;
+use crate::geometry::{dot, Point, Vec3};
+use crate::interval::Interval;
+use crate::mesh::BOX_PADDING;
+use crate::onb::Onb;
+use crate::poly::solve_quartic;
+use crate::ray::Ray;
+use crate::registry::MaterialId;
+use crate::render::{HitRecord, Hittable};
+use std::f32::consts::PI;
+
+/// Torus, a tube of `minor_radius` around a circle of `major_radius`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Torus {
+    /// Center of the circle.
+    pub center: Point,
+    /// Axis of the circle, a unit vector.
+    pub axis: Vec3,
+    /// Radius of the circle.
+    pub major_radius: f32,
+    /// Radius of the tube.
+    pub minor_radius: f32,
+    /// Handle of the material in the scene.
+    pub material_id: MaterialId,
+}
+
+impl Torus {
+    /// # Arguments
+    /// - `axis` - Axis of the circle, which does not need to be a unit vector.
+    pub fn new(
+        center: Point, axis: Vec3, major_radius: f32, minor_radius: f32, material_id: MaterialId,
+    ) -> Self {
+        Torus { center, axis: axis.normed(), major_radius, minor_radius, material_id }
+    }
+}
+
+/// Coordinates of `v` in double precision.
+fn f64s(v: &Vec3) -> [f64; 3] {
+    [v.x as f64, v.y as f64, v.z as f64]
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let frame = Onb::from_w(&self.axis);
+        let (major, minor) = (self.major_radius as f64, self.minor_radius as f64);
+        let outer = major + minor;
+        let length = r.dir.len();
+        let d = f64s(&frame.coordinates(&(r.dir / length)));
+        // Origin moved to the point of the ray closest to the center, in units along the ray.
+        let shift = dot(&(self.center - r.orig), &r.dir) / length;
+        let o = f64s(&frame.coordinates(&(r.at(shift / length) - self.center)));
+        let m = o[0] * o[0] + o[1] * o[1] + o[2] * o[2];
+        if m > outer * outer {
+            return false;
+        }
+
+        // |p|² + R² - r² squared is 4R²(x² + y²) on the torus, with p = o + t d.
+        let n = o[0] * d[0] + o[1] * d[1] + o[2] * d[2];
+        let k = m + major * major - minor * minor;
+        let four_r2 = 4.0 * major * major;
+        let coeffs = [
+            k * k - four_r2 * (o[0] * o[0] + o[1] * o[1]),
+            4.0 * n * k - 2.0 * four_r2 * (o[0] * d[0] + o[1] * d[1]),
+            4.0 * n * n + 2.0 * k - four_r2 * (d[0] * d[0] + d[1] * d[1]),
+            4.0 * n,
+            1.0,
+        ];
+        // Hits are inside the sphere around the torus.
+        let (shift, length) = (shift as f64, length as f64);
+        let lo = (ray_t.min as f64 * length - shift).max(-outer);
+        let hi = (ray_t.max as f64 * length - shift).min(outer);
+        let to_ray = |t: f64| ((t + shift) / length) as f32;
+        let Some(t) = solve_quartic(coeffs, lo, hi).into_iter().find(|t| {
+            // Roots close to the bounds may round out of the ray interval.
+            ray_t.surrounds(to_ray(*t))
+        }) else {
+            return false;
+        };
+
+        let p = [o[0] + t * d[0], o[1] + t * d[1], o[2] + t * d[2]];
+        // From the closest point of the circle.
+        let around = p[1].atan2(p[0]);
+        let ring = p[0].hypot(p[1]);
+        let normal = Vec3::new(
+            ((ring - major) * around.cos()) as f32,
+            ((ring - major) * around.sin()) as f32,
+            p[2] as f32,
+        );
+        rec.t = to_ray(t);
+        rec.p = r.at(rec.t);
+        rec.u = (around as f32 + PI) / (2.0 * PI);
+        rec.v = ((p[2].atan2(ring - major)) as f32 + PI) / (2.0 * PI);
+        rec.material_id = self.material_id;
+        rec.set_face_normal(r, &frame.local(&normal).normed());
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = |a: f32| {
+            self.major_radius * (1.0 - a * a).max(0.0).sqrt() + self.minor_radius + BOX_PADDING
+        };
+        let half = Vec3::new(extent(self.axis.x), extent(self.axis.y), extent(self.axis.z));
+        Aabb::new(self.center - half, self.center + half)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::{Point, Vec3};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::registry::MaterialId;
+    use crate::render::{HitRecord, Hittable};
+    use crate::torus::Torus;
+
+    fn hit(torus: &Torus, orig: Point, dir: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let r = Ray::new(orig, dir);
+        torus.hit(&r, Interval::new(0.001, f32::INFINITY), &mut rec).then_some(rec)
+    }
+
+    fn near(a: &Vec3, b: &Vec3) -> bool {
+        (*a - *b).len() < 1e-3
+    }
+
+    #[test]
+    fn test_torus() {
+        // Lying flat, a tube of radius 0.25 around a circle of radius 1.
+        let torus = Torus::new(Point::ZERO, Vec3::UNIT_Y, 1.0, 0.25, MaterialId(4));
+
+        // From above, onto the top of the tube.
+        let rec = hit(&torus, Point::new(1.0, 2.0, 0.0), -Vec3::UNIT_Y).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.75, 1e-5);
+        assert!(near(&rec.normal, &Vec3::UNIT_Y) && rec.front_face, "{:?}", rec.normal);
+        assert_eq!(rec.material_id, MaterialId(4));
+        // Through the hole, and beside the torus.
+        assert!(hit(&torus, Point::new(0.0, 2.0, 0.0), -Vec3::UNIT_Y).is_none());
+        assert!(hit(&torus, Point::new(1.3, 2.0, 0.0), -Vec3::UNIT_Y).is_none());
+        // Across, hitting the outside of the tube, then the inside of the hole.
+        let rec = hit(&torus, Point::new(-3.0, 0.0, 0.0), Vec3::UNIT_X).unwrap();
+        assert_float_absolute_eq!(rec.t, 1.75, 1e-5);
+        assert!(near(&rec.normal, &-Vec3::UNIT_X));
+        let rec = hit(&torus, Point::ZERO, Vec3::UNIT_X).unwrap();
+        assert_float_absolute_eq!(rec.t, 0.75, 1e-5);
+        assert!(near(&rec.normal, &-Vec3::UNIT_X) && rec.front_face);
+        // From inside the tube, with a direction which is not a unit vector.
+        let rec = hit(&torus, Point::new(1.0, 0.0, 0.0), Vec3::new(4.0, 0.0, 0.0)).unwrap();
+        assert_float_absolute_eq!(rec.t, 0.0625, 1e-5);
+        assert!(!rec.front_face);
+        assert!((0.0..=1.0).contains(&rec.u) && (0.0..=1.0).contains(&rec.v));
+
+        let bbox = torus.bounding_box();
+        assert!(near(&bbox.min, &Point::new(-1.25, -0.25, -1.25)));
+        assert!(near(&bbox.max, &Point::new(1.25, 0.25, 1.25)));
+    }
+
+    #[test]
+    fn test_distant_torus() {
+        // Seen from far away, along a diagonal, the hits stay precise.
+        let axis = Vec3::new(1.0, 1.0, 0.0);
+        let center = Point::new(3.0, -2.0, 1.0);
+        let torus = Torus::new(center, axis, 1.0, 0.25, MaterialId(0));
+        let top = center + axis.normed() * 0.25;
+        // A point of the torus, the top of the tube above the circle.
+        let onto = top + Vec3::new(0.0, 0.0, 1.0);
+        for distance in [2.0, 1e3, 1e5] {
+            let orig = onto + axis.normed() * distance;
+            let rec = hit(&torus, orig, -axis).unwrap();
+            // As precise as the single precision coordinates of the hit.
+            let error = (rec.p - onto).len();
+            assert!(error < 1e-5 + 1e-7 * distance, "{distance} {error}");
+            assert!((rec.normal - axis.normed()).len() < 1e-5, "{distance} {:?}", rec.normal);
+        }
+    }
+}
@@ -1,6 +1,5 @@
 //! Trigonometry functions and datastructures.
 const PI: f32 = std::f32::consts::PI;
-const INF: f32 = f32::INFINITY;
 
 pub fn rad2deg(rad: f32) -> f32 {
     (rad * 180.0) / PI
@@ -0,0 +1,238 @@
+//! Orbit camera, for interactive navigation around a point of interest.
+use crate::geometry::{lerp, Point, Vec3};
+use crate::render::Camera;
+use crate::trig::{deg2rad, rad2deg};
+
+/// Pitch is kept away from the poles, where the camera up vector is undefined.
+const MAX_PITCH: f32 = 89.0;
+const MIN_DISTANCE: f32 = 0.05;
+/// Vertical field of view of the default lens, in degrees, the same as the demo camera.
+pub(crate) const VFOV: f32 = 90.0;
+
+/// A camera orbiting around a target point.
+///
+/// The camera sits at `distance` from `target`, in the direction given by `yaw` (around the
+/// vertical axis, from +z) and `pitch` (above the horizontal plane), both in degrees.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OrbitView {
+    pub target: Point,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// How the camera maps the directions of its rays to the image.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum CameraProjection {
+    /// Rays fan out from the camera position, as through a photographic lens.
+    #[default]
+    Perspective,
+    /// Parallel rays, as for technical drawings: sizes do not shrink with the distance. The
+    /// image frames the part of the plane in focus the perspective projection would.
+    Orthographic,
+    /// Equidistant fisheye: the angle of a ray from the view axis grows with its distance to
+    /// the image center, the field of view spanning the height of the image.
+    Fisheye,
+    /// Full 360° by 180° panorama around the camera, for VR viewers, the longitude across and
+    /// the latitude up, in images twice as wide as high. The view direction is in the center,
+    /// level whatever the pitch. Loaded as an [`crate::environment::EnvironmentMap`] turned by
+    /// the yaw of the view plus 90°, it surrounds the scene as seen from the camera.
+    Equirectangular,
+}
+
+impl CameraProjection {
+    pub const ALL: [CameraProjection; 4] = [
+        CameraProjection::Perspective,
+        CameraProjection::Orthographic,
+        CameraProjection::Fisheye,
+        CameraProjection::Equirectangular,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraProjection::Perspective => "perspective",
+            CameraProjection::Orthographic => "orthographic",
+            CameraProjection::Fisheye => "fisheye",
+            CameraProjection::Equirectangular => "equirectangular",
+        }
+    }
+}
+
+/// Lens of a camera, framing the scene and blurring what is out of focus, see
+/// [`crate::render::Scene::lens`].
+///
+/// The default lens is a pinhole with the field of view of the demo camera: everything is in
+/// focus.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lens {
+    /// Vertical field of view, in degrees.
+    pub vfov: f32,
+    /// Diameter of the lens. The wider, the blurrier what is out of focus.
+    pub aperture: f32,
+    /// Distance from the camera to the plane in focus, the distance to the target when `None`.
+    pub focus_distance: Option<f32>,
+    /// How the rays map to the image.
+    pub projection: CameraProjection,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Lens {
+            vfov: VFOV,
+            aperture: 0.0,
+            focus_distance: None,
+            projection: CameraProjection::Perspective,
+        }
+    }
+}
+
+impl Default for OrbitView {
+    /// The view of the demo camera at the origin.
+    fn default() -> Self {
+        OrbitView { target: Point::new(0.0, 0.0, -1.0), distance: 1.0, yaw: 0.0, pitch: 0.0 }
+    }
+}
+
+impl OrbitView {
+    /// The view from `position` towards `target`.
+    ///
+    /// Positions straight above or below the target are moved off the poles, as by
+    /// [`OrbitView::orbit`].
+    pub fn looking_at(position: &Point, target: &Point) -> Self {
+        let offset = *position - *target;
+        let distance = offset.len().max(MIN_DISTANCE);
+        let pitch = rad2deg((offset.y / distance).clamp(-1.0, 1.0).asin());
+        OrbitView {
+            target: *target,
+            distance,
+            yaw: rad2deg(offset.x.atan2(offset.z)),
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+        }
+    }
+
+    /// Camera position.
+    pub fn position(&self) -> Point {
+        let (yaw, pitch) = (deg2rad(self.yaw), deg2rad(self.pitch));
+        let direction = Vec3::new(yaw.sin() * pitch.cos(), pitch.sin(), yaw.cos() * pitch.cos());
+        self.target + self.distance * direction
+    }
+
+    /// Turn around the target.
+    ///
+    /// # Arguments
+    /// - `yaw` - Angle added around the vertical axis, in degrees.
+    /// - `pitch` - Angle added upwards, in degrees. The result is clamped below the poles.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = (self.yaw + yaw) % 360.0;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Move the camera and the target in the image plane.
+    ///
+    /// # Arguments
+    /// - `right` - Distance to the right, relative to the distance to the target.
+    /// - `up` - Distance upwards, relative to the distance to the target.
+    pub fn pan(&mut self, right: f32, up: f32) {
+        let w = (self.position() - self.target).normed();
+        let u = Vec3::new(0.0, 1.0, 0.0).cross(&w).normed();
+        let v = w.cross(&u);
+        self.target += self.distance * (right * u + up * v);
+    }
+
+    /// Move towards the target, multiplying the distance by `factor`.
+    pub fn dolly(&mut self, factor: f32) {
+        self.distance = (self.distance * factor).max(MIN_DISTANCE);
+    }
+
+    /// Interpolate between two views, turning around the target the shortest way.
+    ///
+    /// # Arguments
+    /// - `other` - The view at `t = 1`.
+    /// - `t` - Interpolation factor, in `[0;1]`.
+    pub fn lerp(&self, other: &OrbitView, t: f32) -> OrbitView {
+        let turn = (other.yaw - self.yaw + 180.0).rem_euclid(360.0) - 180.0;
+        OrbitView {
+            target: lerp(&self.target, &other.target, t),
+            distance: self.distance + (other.distance - self.distance) * t,
+            yaw: self.yaw + turn * t,
+            pitch: self.pitch + (other.pitch - self.pitch) * t,
+        }
+    }
+
+    /// Camera rendering this view through `lens`.
+    pub(crate) fn camera(&self, width: usize, height: usize, lens: &Lens) -> Camera {
+        let aspect_ratio = width as f32 / height as f32;
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let focus_distance = lens.focus_distance.unwrap_or(self.distance);
+        let (vfov, aperture) = (lens.vfov, lens.aperture);
+        Camera::new(self.position(), self.target, up, vfov, aspect_ratio, aperture, focus_distance)
+            .with_projection(lens.projection)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::geometry::Point;
+    use crate::view::OrbitView;
+
+    fn assert_point_near(a: Point, b: Point) {
+        assert!((a - b).len() < 1e-5, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn test_default_matches_the_demo_camera() {
+        assert_point_near(OrbitView::default().position(), Point::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_looking_at() {
+        let position = Point::new(-2.0, 2.0, 1.0);
+        let view = OrbitView::looking_at(&position, &Point::new(0.0, 0.0, -1.0));
+        assert_point_near(view.position(), position);
+        assert_f32_near!(view.yaw, -45.0);
+
+        let above = OrbitView::looking_at(&Point::new(0.0, 5.0, 0.0), &Point::ZERO);
+        assert_eq!(above.pitch, 89.0);
+        assert_f32_near!(above.distance, 5.0);
+    }
+
+    #[test]
+    fn test_orbit_keeps_the_distance() {
+        let mut view = OrbitView::default();
+        view.orbit(90.0, 0.0);
+        assert_point_near(view.position(), Point::new(1.0, 0.0, -1.0));
+
+        view.orbit(0.0, 120.0);
+        assert_eq!(view.pitch, 89.0);
+        assert_f32_near!((view.position() - view.target).len(), 1.0);
+    }
+
+    #[test]
+    fn test_pan_moves_the_target() {
+        let mut view = OrbitView { distance: 2.0, ..Default::default() };
+        view.pan(1.0, 0.5);
+        assert_point_near(view.target, Point::new(2.0, 1.0, -1.0));
+        assert_point_near(view.position(), Point::new(2.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_lerp_turns_the_shortest_way() {
+        let a = OrbitView { yaw: 170.0, ..Default::default() };
+        let b = OrbitView { yaw: -170.0, distance: 3.0, pitch: 40.0, ..Default::default() };
+
+        let mid = a.lerp(&b, 0.5);
+        assert_f32_near!(mid.yaw, 180.0);
+        assert_f32_near!(mid.distance, 2.0);
+        assert_f32_near!(mid.pitch, 20.0);
+        assert_point_near(a.lerp(&b, 1.0).position(), b.position());
+    }
+
+    #[test]
+    fn test_dolly_stops_before_the_target() {
+        let mut view = OrbitView::default();
+        view.dolly(0.5);
+        assert_f32_near!(view.distance, 0.5);
+        view.dolly(0.0);
+        assert!(view.distance > 0.0);
+    }
+}
@@ -0,0 +1,242 @@
+//! Wavefront rendering: the rays are traced breadth first, one bounce of a whole batch at a
+//! time.
+//!
+//! The classic integrator follows each camera ray through all its bounces before starting the
+//! next one, jumping between the code of the objects and of the materials at every step. A
+//! wavefront generates all the camera rays of a batch and intersects them together, then
+//! scatters the hits material by material and queues the scattered rays for the next bounce.
+//! Each stage runs the same code over a flat array, the layout SIMD and GPU backends need.
+use crate::bvh::Bvh;
+use crate::geometry::Color;
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::render::{scatter_towards_lights, Camera, HitRecord, PathDepth, SampleStats, Scene};
+use crate::sampler::{PixelSampler, Sampler};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// How the rays of a render are scheduled.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Integrator {
+    /// Each path is followed through all its bounces, one path after the other.
+    #[default]
+    Megakernel,
+    /// The paths of a row advance together, one bounce at a time.
+    Wavefront,
+}
+
+impl Integrator {
+    pub const ALL: [Integrator; 2] = [Integrator::Megakernel, Integrator::Wavefront];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Integrator::Megakernel => "megakernel",
+            Integrator::Wavefront => "wavefront",
+        }
+    }
+}
+
+/// A path still bouncing in the scene.
+struct Path {
+    /// Index of the pixel in the row.
+    pixel: usize,
+    ray: Ray,
+    /// Product of the attenuations along the path so far.
+    throughput: Color,
+    /// Light brought back along the path so far.
+    radiance: Color,
+    /// Random numbers of the path, starting with the dimensions of its sample in the pattern.
+    rng: Sampler,
+}
+
+/// The samples of the pixels of a row, added as their paths end.
+struct RowSamples {
+    sums: Vec<Color>,
+    /// Number of samples kept in each pixel.
+    kept: Vec<usize>,
+}
+
+impl RowSamples {
+    /// Add the light of a path which ended, filtered like the samples of
+    /// [`crate::render::sample_pixel`].
+    fn finish(&mut self, path: &Path, clamp: Option<f32>, stats: &mut SampleStats) {
+        if let Some(c) = stats.filter(path.radiance, clamp) {
+            self.sums[path.pixel] += c;
+            self.kept[path.pixel] += 1;
+        }
+    }
+}
+
+/// Trace `samples` random rays through every pixel of row `j`, breadth first.
+///
+/// The result matches [`crate::render::sample_pixel`] on each pixel of the row, up to the
+/// random numbers.
+///
+/// # Arguments
+/// - `depth` - How far the paths bounce.
+/// - `pattern` - How the samples are spread over the pixels, the lens, the shutter time and
+///   the first bounces.
+/// - `clamp` - Largest component of the samples, see [`SampleStats::filter`].
+/// - `stats` - Counts the samples of the row.
+/// - `rng` - Seeds the random numbers of each path.
+///
+/// # Returns
+/// The average linear color of the samples kept in each pixel of the row, black if there is
+/// none.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn trace_row(
+    j: usize, width: usize, height: usize, camera: &Camera, world: &Bvh, scene: &Scene,
+    depth: PathDepth, samples: usize, pattern: &dyn PixelSampler, clamp: Option<f32>,
+    stats: &mut SampleStats, rng: &mut Sampler,
+) -> Vec<Color> {
+    let mut row = RowSamples { sums: vec![Color::BLACK; width], kept: vec![0; width] };
+    let mut paths = Vec::with_capacity(width * samples);
+    for pixel in 0..width {
+        for index in 0..samples {
+            // The paths are interleaved, each draws from its own sampler.
+            let mut rng = Sampler::new(rng.next_u64());
+            rng.start_sample(pattern, (pixel, j), index, samples);
+            let u = (pixel as f32 + rng.gen::<f32>()) / (width as f32 - 1.0);
+            let v = (j as f32 + rng.gen::<f32>()) / (height as f32 - 1.0);
+            let ray = camera.get_ray(u, v, &mut rng);
+            let (throughput, radiance) = (Color::WHITE, Color::BLACK);
+            paths.push(Path { pixel, ray, throughput, radiance, rng });
+        }
+    }
+
+    let materials = scene.materials();
+    let mut hits: Vec<(Path, HitRecord)> = Vec::with_capacity(paths.len());
+    for bounces in 0..depth.max {
+        if paths.is_empty() {
+            break;
+        }
+        // Intersect the whole wavefront, the rays leaving the scene bring back the
+        // background.
+        for mut path in paths.drain(..) {
+            let mut rec = HitRecord::new();
+            if world.hit(&path.ray, Interval::new(0.001, f32::INFINITY), &mut rec) {
+                hits.push((path, rec));
+            } else {
+                path.radiance += path.throughput * scene.sky(&path.ray.dir);
+                row.finish(&path, clamp, stats);
+            }
+        }
+
+        // Shade the hits one material after the other, queueing the scattered rays.
+        hits.sort_unstable_by_key(|(_, rec)| rec.material_id);
+        for (mut path, rec) in hits.drain(..) {
+            let material = materials.get(rec.material_id);
+            let scattered = scatter_towards_lights(material, &path.ray, &rec, world, &mut path.rng);
+            let emitted = material.emitted(rec.u, rec.v, &rec.p);
+            path.radiance += path.throughput * (emitted + scattered.direct);
+            if let Some((attenuation, ray)) = scattered.next {
+                let mut throughput = path.throughput * attenuation;
+                if depth.survives(bounces + 1, &mut throughput, &mut path.rng) {
+                    paths.push(Path { ray, throughput, ..path });
+                    continue;
+                }
+            }
+            row.finish(&path, clamp, stats);
+        }
+    }
+    // The paths left reached the bounce limit, and bring back no more light.
+    for path in &paths {
+        row.finish(path, clamp, stats);
+    }
+
+    row.sums.iter().zip(&row.kept).map(|(sum, &kept)| *sum / kept.max(1) as f32).collect()
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::cancel::CancellationToken;
+    use crate::geometry::{Color, Point};
+    use crate::image::ImageRGBF32;
+    use crate::progressive::ProgressiveRenderer;
+    use crate::sampler::SamplerKind;
+    use crate::wavefront::Integrator;
+
+    /// Average linear color of a render of the demo scene.
+    fn mean_color(
+        integrator: Integrator, max_depth: usize, samples: usize, roulette: Option<usize>,
+    ) -> Color {
+        let mut renderer = ProgressiveRenderer::new(16, 12, max_depth, &Point::ZERO).unwrap();
+        renderer.set_integrator(integrator);
+        renderer.set_russian_roulette(roulette);
+        renderer.set_seed(1);
+        assert_eq!(renderer.russian_roulette(), roulette);
+        assert!(renderer.step(samples, &CancellationToken::new()));
+        let hdr = renderer.hdr_image();
+        let sum =
+            hdr.pixels.chunks(3).fold(Color::BLACK, |sum, p| sum + Color::new(p[0], p[1], p[2]));
+        sum / (hdr.width * hdr.height) as f32
+    }
+
+    #[test]
+    fn test_wavefront_matches_megakernel() {
+        let megakernel = mean_color(Integrator::Megakernel, 8, 64, None);
+        let wavefront = mean_color(Integrator::Wavefront, 8, 64, None);
+        assert!((megakernel - wavefront).len() < 0.02, "{megakernel:?} != {wavefront:?}");
+    }
+
+    #[test]
+    fn test_russian_roulette_keeps_the_average() {
+        let reference = mean_color(Integrator::Megakernel, 8, 64, None);
+        for integrator in Integrator::ALL {
+            let mean = mean_color(integrator, 8, 64, Some(2));
+            assert!((reference - mean).len() < 0.03, "{integrator:?}: {reference:?} != {mean:?}");
+        }
+    }
+
+    /// Render of the demo scene with the wavefront.
+    fn wavefront_image(sampler: SamplerKind, samples: usize, seed: u64) -> ImageRGBF32 {
+        let mut renderer = ProgressiveRenderer::new(16, 12, 4, &Point::ZERO).unwrap();
+        renderer.set_integrator(Integrator::Wavefront);
+        renderer.set_sampler(sampler);
+        renderer.set_seed(seed);
+        assert_eq!(renderer.sampler(), sampler);
+        assert!(renderer.step(samples, &CancellationToken::new()));
+        renderer.hdr_image()
+    }
+
+    #[test]
+    fn test_wavefront_sampler() {
+        // Spread samples converge faster than uniform ones.
+        let reference = wavefront_image(SamplerKind::Uniform, 1024, 1);
+        let error = |sampler| {
+            let im = wavefront_image(sampler, 16, 2);
+            let squares = im.pixels.iter().zip(&reference.pixels).map(|(a, b)| (a - b).powi(2));
+            squares.sum::<f32>()
+        };
+        let (uniform, sobol) = (error(SamplerKind::Uniform), error(SamplerKind::Sobol));
+        assert!(sobol < 0.8 * uniform, "{sobol} >= 0.8 * {uniform}");
+    }
+
+    #[test]
+    fn test_clamp_radiance() {
+        for integrator in Integrator::ALL {
+            let mut renderer = ProgressiveRenderer::new(16, 12, 4, &Point::ZERO).unwrap();
+            renderer.set_integrator(integrator);
+            renderer.set_clamp_radiance(Some(0.25));
+            assert_eq!(renderer.clamp_radiance(), Some(0.25));
+            assert!(renderer.step(8, &CancellationToken::new()));
+            // Every sample is dimmed down to the clamp, and so are their averages.
+            let hdr = renderer.hdr_image();
+            assert!(hdr.pixels.iter().all(|&c| c <= 0.25 + 1e-5), "{integrator:?}");
+            assert!(hdr.pixels.iter().any(|&c| c > 0.2), "{integrator:?}");
+        }
+    }
+
+    #[test]
+    fn test_wavefront_depth_limit() {
+        // Without bounces, only the sky shows: the objects in the bottom half are black.
+        let mut renderer = ProgressiveRenderer::new(16, 12, 1, &Point::ZERO).unwrap();
+        renderer.set_integrator(Integrator::Wavefront);
+        assert_eq!(renderer.integrator(), Integrator::Wavefront);
+        renderer.step(2, &CancellationToken::new());
+        let hdr = renderer.hdr_image();
+        assert_eq!(hdr.at(8, 0), (0.0, 0.0, 0.0));
+        let (r, g, b) = hdr.at(8, 11);
+        assert!(r > 0.0 && g > 0.0 && b > 0.0);
+    }
+}
@@ -0,0 +1,709 @@
+//! Command line arguments parsing.
+use crate::progress::ProgressFormat;
+use crate::terminal_preview::TerminalProtocol;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use rt1we_renderer::animation::{Easing, Interpolation};
+use rt1we_renderer::environment::EnvironmentMap;
+use rt1we_renderer::geometry::Color;
+use rt1we_renderer::imageio::ImageFormat;
+use rt1we_renderer::output::OverwritePolicy;
+use rt1we_renderer::preset::QualityPreset;
+use rt1we_renderer::render::Background;
+use rt1we_renderer::sampler::SamplerKind;
+use rt1we_renderer::scenes::ScenePreset;
+use rt1we_renderer::settings::RenderMode;
+use rt1we_renderer::sky::PhysicalSky;
+use rt1we_renderer::stereo::{Stereo, StereoLayout, DEFAULT_EYE_SEPARATION};
+use rt1we_renderer::tonemap::{ToneMapOperator, ToneMapSettings};
+use std::fmt::Display;
+
+/// How to show the image while it renders.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PreviewMode {
+    /// Draw a downscaled image directly in the terminal.
+    Terminal(TerminalProtocol),
+    /// Show the image in a native window.
+    Window,
+}
+
+/// Arguments of the `animate` command.
+#[derive(Debug, Clone, PartialEq, clap::Args)]
+pub struct AnimateArgs {
+    /// Output frames per second.
+    #[arg(long, default_value_t = 24.0)]
+    pub fps: f32,
+    /// Output duration in seconds [default: the whole trajectory].
+    #[arg(long)]
+    pub duration: Option<f32>,
+    /// Camera path: linear, catmull-rom.
+    #[arg(long, default_value = "catmull-rom")]
+    pub interpolation: Interpolation,
+    /// Camera speed between keyframes: linear, ease-in, ease-out, ease-in-out.
+    #[arg(long, default_value = "linear")]
+    pub easing: Easing,
+    /// Where to write the manifest [default: manifest.json next to the frames].
+    #[arg(long, value_name = "FILE")]
+    pub manifest: Option<String>,
+}
+
+/// Arguments of the `convert` command.
+#[derive(Debug, Clone, PartialEq, clap::Args)]
+pub struct ConvertArgs {
+    /// Image to convert.
+    pub input: String,
+    /// Converted image, its format given by its extension.
+    pub output: String,
+    /// Flip the image vertically.
+    #[arg(long)]
+    pub flip: bool,
+    /// Output width and optional height. The aspect ratio is kept when the height is missing.
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    pub resize: Option<(usize, Option<usize>)>,
+}
+
+/// Arguments of the `denoise` command.
+#[derive(Debug, Clone, PartialEq, clap::Args)]
+pub struct DenoiseArgs {
+    /// Image to denoise.
+    pub input: String,
+    /// Denoised image.
+    pub output: String,
+    /// Optional normal image, guiding the filter.
+    #[arg(long, value_name = "FILE")]
+    pub normal: Option<String>,
+    /// Optional albedo image, guiding the filter. The .exr and .hdr inputs take the raw
+    /// buffers of `render --aovs`.
+    #[arg(long, value_name = "FILE")]
+    pub albedo: Option<String>,
+    /// Filter half-size, in pixels, of the 8-bit inputs [default: 3].
+    #[arg(long, value_name = "N")]
+    pub radius: Option<usize>,
+}
+
+/// Arguments of the `diff` command.
+#[derive(Debug, Clone, PartialEq, clap::Args)]
+pub struct DiffArgs {
+    /// Image the other is compared to.
+    pub reference: String,
+    /// Image compared to the reference.
+    pub test: String,
+    /// Where to write the difference heatmap.
+    #[arg(long, value_name = "FILE", default_value = "out/diff.ppm")]
+    pub heatmap: String,
+}
+
+/// What the binary should do.
+#[derive(Debug, Clone, PartialEq, Default, Subcommand)]
+pub enum Command {
+    /// Render a still, seen from the camera of the scene (default).
+    #[default]
+    Render,
+    /// Render the frames of the camera trajectory, and their manifest.
+    Animate(AnimateArgs),
+    /// Print scene statistics without rendering: objects, bounds, BVH, memory.
+    Stats,
+    /// Convert an image between ppm, png, jpeg, exr and hdr.
+    Convert(ConvertArgs),
+    /// Denoise a rendered image.
+    Denoise(DenoiseArgs),
+    /// Compare two images: print PSNR and SSIM, and write a difference heatmap.
+    Diff(DiffArgs),
+}
+
+/// Parsed command line arguments.
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    pub preview: Option<PreviewMode>,
+    pub progress: ProgressFormat,
+    /// Number of animation frames rendered at the same time.
+    pub jobs: usize,
+    /// Number of threads tracing the frames, all the cores by default.
+    pub threads: Option<usize>,
+    /// Seed of the random numbers of the renders, random by default.
+    pub seed: Option<u64>,
+    /// Quality preset overriding the default render settings.
+    pub preset: Option<QualityPreset>,
+    /// Image width, overriding the preset. The aspect ratio is kept when the height is missing.
+    pub width: Option<usize>,
+    /// Image height, overriding the preset. The aspect ratio is kept when the width is missing.
+    pub height: Option<usize>,
+    /// Samples per pixel, overriding the preset.
+    pub samples_per_pixel: Option<usize>,
+    /// Maximum number of bounces of the rays, overriding the preset.
+    pub max_depth: Option<usize>,
+    /// Filename template of the rendered frames.
+    pub output: String,
+    /// What to do with existing output files.
+    pub overwrite: OverwritePolicy,
+    /// Only estimate the render time and memory.
+    pub estimate: bool,
+    /// Scene file to render instead of the demo scene.
+    pub scene: Option<String>,
+    /// Built-in scene to render instead of the demo scene.
+    pub scene_preset: Option<ScenePreset>,
+    /// Background replacing the one of the scene.
+    pub background: Option<Background>,
+    /// Tone mapping of the 8-bit frames.
+    pub tonemap: ToneMapSettings,
+    /// What the pixels show: the light of the scene or a diagnostic pass.
+    pub render_mode: RenderMode,
+    /// How the samples are spread over the pixels.
+    pub sampler: SamplerKind,
+    /// Largest component of the radiance of a sample, unclamped when `None`.
+    pub clamp: Option<f32>,
+    /// Also write the albedo, normal, depth and object of the first hits next to each frame.
+    pub aovs: bool,
+    /// Render the views of both eyes side by side, and how to write them.
+    pub stereo: Option<(Stereo, StereoLayout)>,
+}
+
+const EXIT_STATUS: &str = "\
+Exit status:
+  0 on success, 1 when rendering or writing failed, 2 for invalid arguments,
+  3 when the render was aborted";
+
+/// Where to show the render while it progresses.
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+enum PreviewArg {
+    Terminal,
+    Window,
+}
+
+/// Graphics protocol of the terminal preview.
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+enum ProtocolArg {
+    /// Guessed from the terminal.
+    Auto,
+    /// Unicode half blocks, in any true color terminal.
+    Blocks,
+    /// Kitty graphics protocol.
+    Kitty,
+    /// DEC sixel graphics.
+    Sixel,
+}
+
+/// The command line, as clap parses it.
+#[derive(Debug, Parser)]
+#[command(name = "rt1we_sample", about = "Render the demo scene or a scene file")]
+#[command(after_help = EXIT_STATUS)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    options: Options,
+}
+
+/// Options shared by the commands, accepted before or after the command name.
+#[derive(Debug, clap::Args)]
+struct Options {
+    /// JSON scene file [default: the demo scene].
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "scene_preset")]
+    scene: Option<String>,
+    /// Built-in scene: demo, cornell-box, random-spheres.
+    #[arg(long, global = true, value_name = "NAME")]
+    scene_preset: Option<ScenePreset>,
+    /// Replace the scene background: sky, black, physical-sky, or an environment map, a
+    /// panorama image lighting the scene.
+    #[arg(long, global = true, value_name = "NAME|FILE")]
+    background: Option<String>,
+    /// What the pixels show: path-traced, spectral, normals, depth, ambient-occlusion, albedo
+    /// [default: path-traced].
+    #[arg(long, global = true, value_name = "MODE")]
+    render_mode: Option<RenderMode>,
+    /// Spread of the samples of the pixels: uniform, stratified, halton, sobol
+    /// [default: uniform].
+    #[arg(long, global = true, value_name = "NAME")]
+    sampler: Option<SamplerKind>,
+    /// Dim the samples brighter than RADIANCE, removing fireflies [default: unclamped].
+    #[arg(long, global = true, value_name = "RADIANCE")]
+    clamp: Option<f32>,
+    /// Also write the albedo, normal, depth and object ID of the first hits next to each
+    /// frame, as <FRAME>_albedo...
+    #[arg(long, global = true)]
+    aovs: bool,
+    /// The views of both eyes, each in half the width: side-by-side, or separate images
+    /// <FRAME>_left...
+    #[arg(long, global = true, value_name = "LAYOUT")]
+    stereo: Option<StereoLayout>,
+    /// Distance between the eyes of --stereo, in scene units [default: 0.064].
+    #[arg(long, global = true, value_name = "DIST", requires = "stereo")]
+    eye_separation: Option<f32>,
+    /// Tone mapping of 8-bit frames: clamp, reinhard, aces [default: clamp].
+    #[arg(long, global = true, value_name = "OP")]
+    tonemap: Option<ToneMapOperator>,
+    /// Exposure of 8-bit frames [default: 0].
+    #[arg(long, global = true, value_name = "STOPS", allow_negative_numbers = true)]
+    exposure: Option<f32>,
+    /// Display gamma of 8-bit frames [default: 2].
+    #[arg(long, global = true, value_name = "G")]
+    gamma: Option<f32>,
+    /// Show the render while it progresses.
+    #[arg(long, global = true, value_name = "MODE")]
+    preview: Option<PreviewArg>,
+    /// Graphics protocol of the terminal preview.
+    #[arg(long, global = true, value_name = "PROTO", default_value = "auto")]
+    preview_protocol: ProtocolArg,
+    /// Progress output.
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "text")]
+    progress: ProgressFormat,
+    /// Rendered frames path, with {frame}, {date}, {time}, {seed} placeholders. The .exr
+    /// and .hdr frames keep the radiance unclipped.
+    #[arg(short, long = "out", visible_alias = "output", global = true)]
+    #[arg(value_name = "TEMPLATE", default_value = "out/anim_image_{frame}.ppm")]
+    out: String,
+    /// When an output file exists: error, overwrite, increment [default: increment].
+    #[arg(long, global = true, value_name = "POLICY")]
+    overwrite: Option<OverwritePolicy>,
+    /// Quality preset: preview, medium, final.
+    #[arg(long, global = true, value_name = "NAME")]
+    preset: Option<QualityPreset>,
+    /// Image width, overriding the preset.
+    #[arg(long, global = true, value_name = "N")]
+    width: Option<usize>,
+    /// Image height, overriding the preset.
+    #[arg(long, global = true, value_name = "N")]
+    height: Option<usize>,
+    /// Samples per pixel, overriding the preset.
+    #[arg(long, global = true, value_name = "N")]
+    spp: Option<usize>,
+    /// Maximum ray bounces, overriding the preset.
+    #[arg(long, global = true, value_name = "N")]
+    max_depth: Option<usize>,
+    /// Estimate render time and memory from a few pixels, then exit.
+    #[arg(long, global = true)]
+    estimate: bool,
+    /// Number of frames rendered concurrently.
+    #[arg(short, long, global = true, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+    /// Number of threads shared by the frames [default: all cores].
+    #[arg(short, long, global = true, value_name = "N")]
+    threads: Option<usize>,
+    /// Seed of the random numbers, for reproducible renders.
+    #[arg(long, global = true, value_name = "N")]
+    seed: Option<u64>,
+}
+
+/// An error of the arguments, reported like those clap finds.
+fn invalid(msg: impl Display) -> clap::Error {
+    Cli::command().error(ErrorKind::ValueValidation, msg)
+}
+
+/// Parse command line arguments, without the program name.
+///
+/// # Arguments
+/// - `args` - The arguments to parse.
+///
+/// # Returns
+/// The arguments, or the error to exit with, which is the help when it was asked for.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Args, clap::Error> {
+    let cli = Cli::try_parse_from(std::iter::once("rt1we_sample".to_string()).chain(args))?;
+    let (command, o) = (cli.command.unwrap_or_default(), cli.options);
+
+    if let Command::Animate(animate) = &command {
+        if !(animate.fps.is_finite() && animate.fps > 0.0) {
+            return Err(invalid("--fps must be positive"));
+        }
+        if animate.duration.is_some_and(|d| !(d.is_finite() && d >= 0.0)) {
+            return Err(invalid("--duration must be positive or zero"));
+        }
+    }
+
+    let protocol = match o.preview_protocol {
+        ProtocolArg::Auto => TerminalProtocol::detect(),
+        ProtocolArg::Blocks => TerminalProtocol::HalfBlocks,
+        ProtocolArg::Kitty => TerminalProtocol::Kitty,
+        ProtocolArg::Sixel => TerminalProtocol::Sixel,
+    };
+    let preview = o.preview.map(|preview| match preview {
+        PreviewArg::Terminal => PreviewMode::Terminal(protocol),
+        PreviewArg::Window => PreviewMode::Window,
+    });
+
+    let background = match o.background.as_deref() {
+        None => None,
+        Some("sky") => Some(Background::default()),
+        Some("black") => Some(Background::Solid(Color::BLACK)),
+        Some("physical-sky") => Some(Background::Physical(PhysicalSky::default())),
+        Some(path) if ImageFormat::from_path(path).is_some() => {
+            let map =
+                EnvironmentMap::load(path).map_err(|e| invalid(format!("--background: {e}")))?;
+            Some(Background::Environment(map))
+        }
+        Some(other) => return Err(invalid(format!("unknown background '{other}'"))),
+    };
+
+    if o.progress == ProgressFormat::Json && matches!(preview, Some(PreviewMode::Terminal(_))) {
+        return Err(invalid("--progress json can not be combined with the terminal preview"));
+    }
+
+    if o.eye_separation.is_some_and(|d| !(d.is_finite() && d >= 0.0)) {
+        return Err(invalid("--eye-separation must be positive or zero"));
+    }
+    let stereo = o.stereo.map(|layout| {
+        let eye_separation = o.eye_separation.unwrap_or(DEFAULT_EYE_SEPARATION);
+        (Stereo { eye_separation }, layout)
+    });
+
+    if o.jobs == 0 {
+        return Err(invalid("--jobs must be at least 1"));
+    }
+    if o.threads == Some(0) {
+        return Err(invalid("--threads must be at least 1"));
+    }
+    if o.jobs > 1 && preview.is_some() {
+        return Err(invalid("the preview can only show one frame at a time, use --jobs 1"));
+    }
+
+    let defaults = ToneMapSettings::default();
+    let tonemap = ToneMapSettings {
+        operator: o.tonemap.unwrap_or(defaults.operator),
+        exposure: o.exposure.unwrap_or(defaults.exposure),
+        gamma: o.gamma.unwrap_or(defaults.gamma),
+    };
+    Ok(Args {
+        command,
+        preview,
+        progress: o.progress,
+        jobs: o.jobs,
+        threads: o.threads,
+        seed: o.seed,
+        preset: o.preset,
+        width: o.width,
+        height: o.height,
+        samples_per_pixel: o.spp,
+        max_depth: o.max_depth,
+        output: o.out,
+        overwrite: o.overwrite.unwrap_or_default(),
+        estimate: o.estimate,
+        scene: o.scene,
+        scene_preset: o.scene_preset,
+        background,
+        tonemap,
+        render_mode: o.render_mode.unwrap_or_default(),
+        sampler: o.sampler.unwrap_or_default(),
+        clamp: o.clamp,
+        aovs: o.aovs,
+        stereo,
+    })
+}
+
+/// Parse `<W>x<H>`, or `<W>x` / `<W>` to keep the aspect ratio.
+fn parse_size(s: &str) -> Result<(usize, Option<usize>), String> {
+    let invalid = || format!("invalid size '{s}', expected <W>x<H>");
+    let (w, h) = s.split_once('x').unwrap_or((s, ""));
+    let w: usize = w.parse().map_err(|_| invalid())?;
+    let h: Option<usize> =
+        if h.is_empty() { None } else { Some(h.parse().map_err(|_| invalid())?) };
+    if w == 0 || h == Some(0) {
+        return Err(invalid());
+    }
+    Ok((w, h))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::cli::{
+        parse_args, AnimateArgs, Cli, Command, ConvertArgs, DenoiseArgs, DiffArgs, PreviewMode,
+    };
+    use crate::progress::ProgressFormat;
+    use crate::terminal_preview::TerminalProtocol;
+    use clap::error::ErrorKind;
+    use clap::CommandFactory;
+    use rt1we_renderer::animation::{Easing, Interpolation};
+    use rt1we_renderer::geometry::Color;
+    use rt1we_renderer::output::OverwritePolicy;
+    use rt1we_renderer::preset::QualityPreset;
+    use rt1we_renderer::render::Background;
+    use rt1we_renderer::sampler::SamplerKind;
+    use rt1we_renderer::scenes::ScenePreset;
+    use rt1we_renderer::settings::RenderMode;
+    use rt1we_renderer::sky::PhysicalSky;
+    use rt1we_renderer::stereo::{Stereo, StereoLayout};
+    use rt1we_renderer::tonemap::{ToneMapOperator, ToneMapSettings};
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_no_arguments_means_no_preview() {
+        let parsed = parse_args(args("")).unwrap();
+        assert_eq!(parsed.command, Command::Render);
+        assert_eq!(parsed.preview, None);
+    }
+
+    #[test]
+    fn test_subcommands() {
+        assert_eq!(parse_args(args("render")).unwrap().command, Command::Render);
+        assert_eq!(parse_args(args("stats")).unwrap().command, Command::Stats);
+        assert!(parse_args(args("explode")).is_err());
+        assert!(parse_args(args("stats extra")).is_err());
+    }
+
+    #[test]
+    fn test_animate_arguments() {
+        let expected = AnimateArgs {
+            fps: 24.0,
+            duration: None,
+            interpolation: Interpolation::CatmullRom,
+            easing: Easing::Linear,
+            manifest: None,
+        };
+        assert_eq!(parse_args(args("animate")).unwrap().command, Command::Animate(expected));
+
+        let parsed = parse_args(args(
+            "animate --fps 30 --duration 1.5 --interpolation linear --easing ease-in-out \
+             --manifest out/anim.json",
+        ))
+        .unwrap();
+        let expected = AnimateArgs {
+            fps: 30.0,
+            duration: Some(1.5),
+            interpolation: Interpolation::Linear,
+            easing: Easing::EaseInOut,
+            manifest: Some("out/anim.json".to_string()),
+        };
+        assert_eq!(parsed.command, Command::Animate(expected));
+
+        assert!(parse_args(args("animate --fps 0")).is_err());
+        assert!(parse_args(args("animate --duration -1")).is_err());
+        assert!(parse_args(args("animate --easing bounce")).is_err());
+        assert!(parse_args(args("animate --interpolation bezier")).is_err());
+    }
+
+    #[test]
+    fn test_convert_arguments() {
+        let parsed = parse_args(args("convert in.ppm out.png --flip --resize 320x")).unwrap();
+        let expected = ConvertArgs {
+            input: "in.ppm".to_string(),
+            output: "out.png".to_string(),
+            flip: true,
+            resize: Some((320, None)),
+        };
+        assert_eq!(parsed.command, Command::Convert(expected));
+
+        let parsed = parse_args(args("convert in.ppm out.png --resize 32x18")).unwrap();
+        assert!(matches!(parsed.command, Command::Convert(c) if c.resize == Some((32, Some(18)))));
+
+        assert!(parse_args(args("convert in.ppm")).is_err());
+        assert!(parse_args(args("convert in.ppm out.png --resize 0x10")).is_err());
+        assert!(parse_args(args("convert in.ppm out.png --resize big")).is_err());
+    }
+
+    #[test]
+    fn test_denoise_arguments() {
+        let parsed = parse_args(args("denoise in.ppm out.ppm --albedo a.ppm --radius 5")).unwrap();
+        let expected = DenoiseArgs {
+            input: "in.ppm".to_string(),
+            output: "out.ppm".to_string(),
+            normal: None,
+            albedo: Some("a.ppm".to_string()),
+            radius: Some(5),
+        };
+        assert_eq!(parsed.command, Command::Denoise(expected));
+
+        assert!(parse_args(args("denoise in.ppm out.ppm --radius -1")).is_err());
+        assert!(parse_args(args("denoise in.ppm")).is_err());
+    }
+
+    #[test]
+    fn test_diff_arguments() {
+        let parsed = parse_args(args("diff out/latest.ppm new.ppm")).unwrap();
+        let expected = DiffArgs {
+            reference: "out/latest.ppm".to_string(),
+            test: "new.ppm".to_string(),
+            heatmap: "out/diff.ppm".to_string(),
+        };
+        assert_eq!(parsed.command, Command::Diff(expected));
+
+        let parsed = parse_args(args("diff a.png b.png --heatmap heat.png")).unwrap();
+        assert!(matches!(parsed.command, Command::Diff(d) if d.heatmap == "heat.png"));
+
+        assert!(parse_args(args("diff a.png")).is_err());
+    }
+
+    #[test]
+    fn test_terminal_preview_with_explicit_protocol() {
+        let parsed = parse_args(args("--preview terminal --preview-protocol kitty")).unwrap();
+        assert_eq!(parsed.preview, Some(PreviewMode::Terminal(TerminalProtocol::Kitty)));
+    }
+
+    #[test]
+    fn test_window_preview() {
+        let parsed = parse_args(args("--preview window")).unwrap();
+        assert_eq!(parsed.preview, Some(PreviewMode::Window));
+    }
+
+    #[test]
+    fn test_progress_format() {
+        assert_eq!(parse_args(args("")).unwrap().progress, ProgressFormat::Text);
+        assert_eq!(parse_args(args("--progress json")).unwrap().progress, ProgressFormat::Json);
+        assert!(parse_args(args("--progress xml")).is_err());
+        assert!(parse_args(args("--progress json --preview terminal")).is_err());
+    }
+
+    #[test]
+    fn test_jobs() {
+        assert_eq!(parse_args(args("")).unwrap().jobs, 1);
+        assert_eq!(parse_args(args("-j 4")).unwrap().jobs, 4);
+        assert_eq!(parse_args(args("--jobs 2 --progress json")).unwrap().jobs, 2);
+        assert!(parse_args(args("--jobs 0")).is_err());
+        assert!(parse_args(args("--jobs 2 --preview window")).is_err());
+    }
+
+    #[test]
+    fn test_threads_and_seed() {
+        let parsed = parse_args(args("")).unwrap();
+        assert_eq!((parsed.threads, parsed.seed), (None, None));
+        let parsed = parse_args(args("-t 3 --seed 18446744073709551615")).unwrap();
+        assert_eq!((parsed.threads, parsed.seed), (Some(3), Some(u64::MAX)));
+        assert!(parse_args(args("--threads 0")).is_err());
+        assert!(parse_args(args("--seed -1")).is_err());
+    }
+
+    #[test]
+    fn test_preset() {
+        assert_eq!(parse_args(args("")).unwrap().preset, None);
+        let parsed = parse_args(args("--preset final")).unwrap();
+        assert_eq!(parsed.preset, Some(QualityPreset::Final));
+        assert!(parse_args(args("--preset ultra")).is_err());
+    }
+
+    #[test]
+    fn test_render_settings() {
+        let parsed = parse_args(args("")).unwrap();
+        assert_eq!((parsed.width, parsed.height), (None, None));
+        assert_eq!((parsed.samples_per_pixel, parsed.max_depth), (None, None));
+
+        let parsed =
+            parse_args(args("render --preset final --width 1920 --spp 256 --max-depth 8")).unwrap();
+        assert_eq!((parsed.width, parsed.height), (Some(1920), None));
+        assert_eq!((parsed.samples_per_pixel, parsed.max_depth), (Some(256), Some(8)));
+        assert_eq!(parse_args(args("--height 720")).unwrap().height, Some(720));
+        assert!(parse_args(args("--width wide")).is_err());
+        assert!(parse_args(args("--spp")).is_err());
+    }
+
+    #[test]
+    fn test_estimate() {
+        assert!(!parse_args(args("")).unwrap().estimate);
+        assert!(parse_args(args("--estimate --preset final -j 2")).unwrap().estimate);
+    }
+
+    #[test]
+    fn test_scene() {
+        assert_eq!(parse_args(args("")).unwrap().scene, None);
+        let parsed = parse_args(args("stats --scene scenes/demo.json")).unwrap();
+        assert_eq!(parsed.scene.as_deref(), Some("scenes/demo.json"));
+        assert!(parse_args(args("--scene")).is_err());
+
+        assert_eq!(parse_args(args("")).unwrap().scene_preset, None);
+        let parsed = parse_args(args("--scene-preset cornell-box")).unwrap();
+        assert_eq!(parsed.scene_preset, Some(ScenePreset::CornellBox));
+        assert!(parse_args(args("--scene-preset teapot")).is_err());
+        assert!(parse_args(args("--scene demo.json --scene-preset demo")).is_err());
+    }
+
+    #[test]
+    fn test_background() {
+        assert_eq!(parse_args(args("")).unwrap().background, None);
+        let parsed = parse_args(args("--background black")).unwrap();
+        assert_eq!(parsed.background, Some(Background::Solid(Color::BLACK)));
+        let parsed = parse_args(args("--background sky")).unwrap();
+        assert_eq!(parsed.background, Some(Background::default()));
+        let parsed = parse_args(args("--background physical-sky")).unwrap();
+        assert_eq!(parsed.background, Some(Background::Physical(PhysicalSky::default())));
+        assert!(parse_args(args("--background night")).is_err());
+        let err = parse_args(args("--background missing.hdr")).err().unwrap().to_string();
+        assert!(err.contains("--background: ") && err.contains("missing.hdr"), "{err}");
+    }
+
+    #[test]
+    fn test_tonemap() {
+        assert_eq!(parse_args(args("")).unwrap().tonemap, ToneMapSettings::default());
+        let parsed = parse_args(args("--tonemap aces --exposure -1.5 --gamma 2.2")).unwrap();
+        let expected =
+            ToneMapSettings { operator: ToneMapOperator::AcesFilmic, exposure: -1.5, gamma: 2.2 };
+        assert_eq!(parsed.tonemap, expected);
+        assert!(parse_args(args("--tonemap filmic")).is_err());
+        assert!(parse_args(args("--exposure bright")).is_err());
+    }
+
+    #[test]
+    fn test_render_mode() {
+        assert_eq!(parse_args(args("")).unwrap().render_mode, RenderMode::PathTraced);
+        let parsed = parse_args(args("--render-mode ambient-occlusion")).unwrap();
+        assert_eq!(parsed.render_mode, RenderMode::AmbientOcclusion);
+        assert!(parse_args(args("--render-mode wireframe")).is_err());
+    }
+
+    #[test]
+    fn test_sampler() {
+        assert_eq!(parse_args(args("")).unwrap().sampler, SamplerKind::Uniform);
+        assert_eq!(parse_args(args("--sampler sobol")).unwrap().sampler, SamplerKind::Sobol);
+        assert!(parse_args(args("--sampler random")).is_err());
+    }
+
+    #[test]
+    fn test_clamp() {
+        assert_eq!(parse_args(args("")).unwrap().clamp, None);
+        assert_eq!(parse_args(args("--clamp 20")).unwrap().clamp, Some(20.0));
+        assert!(parse_args(args("--clamp bright")).is_err());
+    }
+
+    #[test]
+    fn test_aovs() {
+        assert!(!parse_args(args("")).unwrap().aovs);
+        assert!(parse_args(args("render --aovs -o out/image.exr")).unwrap().aovs);
+    }
+
+    #[test]
+    fn test_stereo() {
+        assert_eq!(parse_args(args("")).unwrap().stereo, None);
+        let parsed = parse_args(args("--stereo side-by-side")).unwrap();
+        assert_eq!(parsed.stereo, Some((Stereo::default(), StereoLayout::SideBySide)));
+        let parsed = parse_args(args("--stereo separate --eye-separation 0.1")).unwrap();
+        let stereo = Stereo { eye_separation: 0.1 };
+        assert_eq!(parsed.stereo, Some((stereo, StereoLayout::Separate)));
+        assert!(parse_args(args("--stereo anaglyph")).is_err());
+        assert!(parse_args(args("--stereo separate --eye-separation -1")).is_err());
+        assert!(parse_args(args("--eye-separation 0.1")).is_err());
+    }
+
+    #[test]
+    fn test_output_options() {
+        let parsed = parse_args(args("")).unwrap();
+        assert_eq!(parsed.output, "out/anim_image_{frame}.ppm");
+        assert_eq!(parsed.overwrite, OverwritePolicy::Increment);
+
+        let parsed = parse_args(args("-o renders/{date}_{frame}.png --overwrite error")).unwrap();
+        assert_eq!(parsed.output, "renders/{date}_{frame}.png");
+        assert_eq!(parsed.overwrite, OverwritePolicy::Error);
+        let parsed = parse_args(args("render --scene scene.json --width 1920 --out img.png"));
+        assert_eq!(parsed.unwrap().output, "img.png");
+        assert_eq!(parse_args(args("--output img.exr")).unwrap().output, "img.exr");
+
+        assert!(parse_args(args("--overwrite maybe")).is_err());
+    }
+
+    #[test]
+    fn test_command_line() {
+        Cli::command().debug_assert();
+        let help = parse_args(args("--help")).err().unwrap();
+        assert_eq!(help.kind(), ErrorKind::DisplayHelp);
+        assert!(help.to_string().contains("--out <TEMPLATE>"));
+        // The options of the renders are accepted before or after the command.
+        assert_eq!(parse_args(args("--spp 4 animate")).unwrap().samples_per_pixel, Some(4));
+        assert_eq!(parse_args(args("animate --spp 4")).unwrap().samples_per_pixel, Some(4));
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_rejected() {
+        assert!(parse_args(args("--preview")).is_err());
+        assert!(parse_args(args("--preview hologram")).is_err());
+        assert!(parse_args(args("--preview terminal --preview-protocol vt100")).is_err());
+        assert!(parse_args(args("--frobnicate")).is_err());
+    }
+}
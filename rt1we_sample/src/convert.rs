@@ -0,0 +1,25 @@
+//! The `convert` command: turn rendered frames into shareable files.
+use crate::cli::ConvertArgs;
+use rt1we_renderer::image::{flipv, resize};
+use rt1we_renderer::imageio::{imread, imwrite};
+use rt1we_renderer::output::{prepare_output, OverwritePolicy};
+use std::error::Error;
+
+/// Read an image, optionally flip and resize it, and write it in the output format.
+pub fn convert(args: &ConvertArgs, overwrite: OverwritePolicy) -> Result<(), Box<dyn Error>> {
+    let mut im = imread(&args.input)?;
+
+    if args.flip {
+        im = flipv(&im);
+    }
+    if let Some((width, height)) = args.resize {
+        let height = height.unwrap_or_else(|| (im.height * width / im.width).max(1));
+        im = resize(&im, width, height);
+    }
+
+    let output = prepare_output(&args.output, overwrite)?;
+    let output = output.to_string_lossy();
+    imwrite(&output, &im)?;
+    println!("{} -> {} ({}x{})", args.input, output, im.width, im.height);
+    Ok(())
+}
@@ -0,0 +1,43 @@
+//! The `denoise` command: clean up existing low sample count renders.
+use crate::cli::DenoiseArgs;
+use rt1we_renderer::denoise::{self, DenoiseSettings, Guides, HdrGuides, WaveletSettings};
+use rt1we_renderer::imageio::{imread, imread_hdr, imwrite, imwrite_hdr, ImageFormat};
+use rt1we_renderer::output::{prepare_output, OverwritePolicy};
+use rt1we_renderer::tonemap::{tonemap, ToneMapSettings};
+use std::error::Error;
+
+/// Read a beauty image and its optional guides, and write the denoised image.
+///
+/// High dynamic range images are denoised before tone mapping, with their guides read as
+/// linear values, such as the raw buffers written by `render --aovs`.
+pub fn denoise(args: &DenoiseArgs, overwrite: OverwritePolicy) -> Result<(), Box<dyn Error>> {
+    let is_hdr = |path: &str| ImageFormat::from_path(path).is_some_and(|f| f.is_hdr());
+    let output = prepare_output(&args.output, overwrite)?;
+    let output = output.to_string_lossy();
+
+    if is_hdr(&args.input) {
+        let beauty = imread_hdr(&args.input)?;
+        let normal = args.normal.as_deref().map(imread_hdr).transpose()?;
+        let albedo = args.albedo.as_deref().map(imread_hdr).transpose()?;
+        let guides = HdrGuides { normal: normal.as_ref(), albedo: albedo.as_ref() };
+        let radiance = denoise::denoise_hdr(&beauty, &guides, &WaveletSettings::default())?;
+        if is_hdr(&output) {
+            imwrite_hdr(&output, &radiance)?;
+        } else {
+            imwrite(&output, &tonemap(&radiance, &ToneMapSettings::default()))?;
+        }
+    } else {
+        let beauty = imread(&args.input)?;
+        let normal = args.normal.as_deref().map(imread).transpose()?;
+        let albedo = args.albedo.as_deref().map(imread).transpose()?;
+
+        let mut settings = DenoiseSettings::default();
+        if let Some(radius) = args.radius {
+            settings.radius = radius;
+        }
+        let guides = Guides { normal: normal.as_ref(), albedo: albedo.as_ref() };
+        imwrite(&output, &denoise::denoise(&beauty, &guides, &settings)?)?;
+    }
+    println!("{} -> {}", args.input, output);
+    Ok(())
+}
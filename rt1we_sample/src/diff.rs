@@ -0,0 +1,26 @@
+//! The `diff` command: compare a render against a baseline.
+use crate::cli::DiffArgs;
+use rt1we_renderer::compare::{heatmap, psnr, ssim};
+use rt1we_renderer::imageio::{imread, imwrite};
+use rt1we_renderer::output::{prepare_output, OverwritePolicy};
+use std::error::Error;
+
+/// Print the PSNR and SSIM of two images, and write their difference heatmap.
+pub fn diff(args: &DiffArgs, overwrite: OverwritePolicy) -> Result<(), Box<dyn Error>> {
+    let reference = imread(&args.reference)?;
+    let test = imread(&args.test)?;
+
+    let psnr = psnr(&reference, &test)?;
+    let ssim = ssim(&reference, &test)?;
+    let (hm, max_error) = heatmap(&reference, &test)?;
+
+    let heatmap_path = prepare_output(&args.heatmap, overwrite)?;
+    let heatmap_path = heatmap_path.to_string_lossy();
+    imwrite(&heatmap_path, &hm)?;
+
+    println!("PSNR           : {psnr:.2} dB");
+    println!("SSIM           : {ssim:.4}");
+    println!("Max difference : {max_error}");
+    println!("Heatmap        : {heatmap_path} (white = {max_error})");
+    Ok(())
+}
@@ -1,48 +1,437 @@
 //! Toy raytracer, following the [Raytracer in One Weekend](https://github.com/RayTracing/raytracing.github.io/) Series.
 
 extern crate rt1we_renderer;
+mod cli;
+mod convert;
+mod denoise;
+mod diff;
+mod manifest;
+mod progress;
+mod terminal_preview;
+mod window_preview;
+
+use std::env;
+use std::error::Error;
+use std::fmt::Display;
+use std::io;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 use std::time::Instant;
 
+use rt1we_renderer::animation::{AnimationSettings, Keyframe, Trajectory};
+use rt1we_renderer::aov::{aov_path, render_aovs, AovKind};
+use rt1we_renderer::denoise::{denoise_hdr, HdrGuides, WaveletSettings};
+use rt1we_renderer::error::RtError;
+use rt1we_renderer::estimate::estimate;
 use rt1we_renderer::geometry::Vec3;
-use rt1we_renderer::image::flipv;
-use rt1we_renderer::ppmio::ppmwrite;
-use rt1we_renderer::render::render;
+use rt1we_renderer::image::{flipv, ImageRGBA};
+use rt1we_renderer::imageio::{imwrite, imwrite_hdr, ImageFormat};
+use rt1we_renderer::output::{expand_template, prepare_output, OverwritePolicy, TemplateContext};
+use rt1we_renderer::render::{SampleStats, Scene};
+use rt1we_renderer::scene_io::load_scene;
+use rt1we_renderer::settings::RenderSettings;
+use rt1we_renderer::stats::SceneStats;
+use rt1we_renderer::stereo::{eye_path, Eye, StereoLayout};
+use rt1we_renderer::tiles::{render_tiles_hdr, tile_grid};
+use rt1we_renderer::tonemap::tonemap;
+use rt1we_renderer::view::OrbitView;
+
+use crate::cli::{parse_args, Args, Command, PreviewMode};
+use crate::convert::convert;
+use crate::denoise::denoise;
+use crate::diff::diff;
+use crate::manifest::{Manifest, ManifestFrame};
+use crate::progress::{
+    ProgressReporter, RenderInfo, EXIT_ABORTED, EXIT_FAILURE, EXIT_SUCCESS, EXIT_USAGE,
+};
+use crate::terminal_preview::TerminalPreview;
+use crate::window_preview::WindowPreview;
+
+/// Exit with the status matching the outcome of a subcommand.
+fn exit_with(result: Result<(), Box<dyn Error>>) -> ! {
+    if let Err(e) = result {
+        eprintln!("{e}");
+        exit(EXIT_FAILURE);
+    }
+    exit(EXIT_SUCCESS);
+}
+
+/// Number of pixels traced by `--estimate`.
+const ESTIMATE_PROBES: usize = 256;
+
+/// Progress reporter shared by the threads rendering frames.
+type Reporter = Mutex<ProgressReporter<io::Stdout>>;
+
+/// Report a render failure and exit.
+fn fail(reporter: &Reporter, msg: impl Display) -> ! {
+    reporter.lock().unwrap().error(&msg.to_string(), EXIT_FAILURE);
+    exit(EXIT_FAILURE);
+}
+
+/// Files of a frame at `fpath` and the columns of the image they hold: the whole image, or
+/// each eye in its own file for separate stereo images.
+fn image_parts(fpath: &str, width: usize, args: &Args) -> Vec<(String, usize, usize)> {
+    match args.stereo {
+        Some((_, StereoLayout::Separate)) => Eye::ALL
+            .iter()
+            .enumerate()
+            .map(|(k, &eye)| (eye_path(fpath, eye), k * width / 2, width / 2))
+            .collect(),
+        _ => vec![(fpath.to_string(), 0, width)],
+    }
+}
+
+/// Where the window preview saves the image of the frame written to `output`: next to it,
+/// named after it, as `out/frame_003_preview.png` for `out/frame_003.png`. The previews of
+/// high dynamic range frames are 8-bit PPM images.
+fn preview_path(output: &str) -> String {
+    let path = Path::new(output);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let ext = match ImageFormat::from_path(output) {
+        Some(format) if !format.is_hdr() => path.extension().and_then(|s| s.to_str()),
+        _ => None,
+    };
+    path.with_file_name(format!("{stem}_preview.{}", ext.unwrap_or("ppm")))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Copy of the last frame rendered, overwritten by each frame.
+const LATEST_PATH: &str = "out/latest.ppm";
+
+/// Write `im` to `path`, the right way up like the frames.
+fn write_latest(path: &str, im: &ImageRGBA) -> Result<(), RtError> {
+    let path = prepare_output(path, OverwritePolicy::Overwrite)?;
+    imwrite(&path.to_string_lossy(), &flipv(im))
+}
+
+/// Render frame `i` of a scene, seen from its camera, and write it to `output`.
+///
+/// # Returns
+/// The path written, which differs from `output` when the overwrite policy picked a new name.
+#[cfg(not(tarpaulin_include))]
+fn render_frame(
+    i: usize, scene: &Scene, output: &str, args: &Args, settings: &RenderSettings,
+    reporter: &Reporter,
+) -> String {
+    let (width, height) = (settings.width, settings.height);
+    reporter.lock().unwrap().frame_started(i);
+    let mut terminal = match args.preview {
+        Some(PreviewMode::Terminal(protocol)) => {
+            println!();
+            Some(TerminalPreview::new(protocol, width, height))
+        }
+        _ => None,
+    };
+    let mut window = match args.preview {
+        Some(PreviewMode::Window) => match WindowPreview::new(width, height, &preview_path(output))
+        {
+            Ok(window) => Some(window),
+            Err(msg) => fail(reporter, msg),
+        },
+        _ => None,
+    };
+
+    let mut stats = SampleStats::default();
+    let rendered = render_tiles_hdr(scene, settings, |progress| {
+        stats = progress.stats;
+        if let Some(preview) = terminal.as_mut() {
+            preview.update(progress.image);
+        } else {
+            reporter.lock().unwrap().tiles_done(i, progress.tiles_done);
+        }
+        match window.as_mut() {
+            Some(preview) => preview.update(progress.image),
+            None => ControlFlow::Continue(()),
+        }
+    });
+    let (mut im, mut radiance) = rendered.unwrap_or_else(|e| fail(reporter, e));
+    let aovs =
+        args.aovs.then(|| render_aovs(scene, settings).unwrap_or_else(|e| fail(reporter, e)));
+    // The radiance is denoised before tone mapping, the auxiliary buffers, when rendered,
+    // keep the edges of the objects sharp.
+    if args.preset.is_some_and(|p| p.settings().denoise) {
+        let guides = aovs.as_ref().map_or_else(HdrGuides::default, HdrGuides::of);
+        radiance = denoise_hdr(&radiance, &guides, &WaveletSettings::default())
+            .unwrap_or_else(|msg| fail(reporter, msg));
+        im = tonemap(&radiance, &settings.tonemap());
+    }
+
+    if let Some(preview) = terminal.as_mut() {
+        preview.finish(&im);
+    }
+    if let Some(preview) = window.as_mut() {
+        if preview.aborted() {
+            reporter.lock().unwrap().aborted(i);
+            exit(EXIT_ABORTED);
+        }
+        preview.wait(&im);
+    }
+
+    let fpath = match prepare_output(output, args.overwrite) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(msg) => fail(reporter, msg),
+    };
+    // High dynamic range outputs get the radiance, for tonemapping later.
+    let hdr = ImageFormat::from_path(&fpath).is_some_and(|f| f.is_hdr());
+    for (path, x, part_width) in image_parts(&fpath, width, args) {
+        let written = if hdr {
+            imwrite_hdr(&path, &radiance.columns(x, part_width).flipv())
+        } else {
+            imwrite(&path, &flipv(&im.columns(x, part_width)))
+        };
+        if let Err(msg) = written {
+            fail(reporter, msg);
+        }
+        // The buffers are named after the frame, in its format.
+        if let Some(aovs) = &aovs {
+            for kind in AovKind::ALL {
+                let path = aov_path(&path, kind);
+                let written = if hdr {
+                    imwrite_hdr(&path, &aovs.image_hdr(kind).columns(x, part_width).flipv())
+                } else {
+                    imwrite(&path, &flipv(&aovs.image(kind).columns(x, part_width)))
+                };
+                if let Err(msg) = written {
+                    fail(reporter, msg);
+                }
+            }
+        }
+    }
+    // Frames finish in any order, hold the lock so only one thread writes the shared file.
+    let mut reporter = reporter.lock().unwrap();
+    if let Err(e) = write_latest(LATEST_PATH, &im) {
+        reporter.error(&e.to_string(), EXIT_FAILURE);
+        exit(EXIT_FAILURE);
+    }
+    reporter.frame_done(i, &fpath, &stats);
+    fpath
+}
 
 #[cfg(not(tarpaulin_include))]
 fn main() {
+    // The help exits with 0, the invalid arguments with EXIT_USAGE.
+    let args = parse_args(env::args().skip(1)).unwrap_or_else(|e| e.exit());
+
+    let scene = match &args.scene {
+        Some(fpath) => load_scene(fpath).unwrap_or_else(|e| {
+            // I/O errors already name the file.
+            match e {
+                RtError::Io { .. } => eprintln!("{e}"),
+                _ => eprintln!("{fpath}: {e}"),
+            }
+            exit(EXIT_FAILURE);
+        }),
+        None => args.scene_preset.map_or_else(Scene::demo, |preset| preset.build()),
+    };
+
+    match &args.command {
+        Command::Render | Command::Animate(_) => {}
+        Command::Stats => {
+            println!("{}", SceneStats::of(&scene));
+            return;
+        }
+        Command::Convert(convert_args) => exit_with(convert(convert_args, args.overwrite)),
+        Command::Denoise(denoise_args) => exit_with(denoise(denoise_args, args.overwrite)),
+        Command::Diff(diff_args) => exit_with(diff(diff_args, args.overwrite)),
+    }
+
+    // The frames rendered concurrently share the threads of the global pool.
+    if let Some(threads) = args.threads {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
+        if let Err(e) = pool {
+            eprintln!("cannot start {threads} threads: {e}");
+            exit(EXIT_FAILURE);
+        }
+    }
+
     let aspect_ratio = 16.0 / 9.0;
-    let width = 160;
-    let height = (width as f32 / aspect_ratio) as usize;
-    let max_depth = 50;
-
-    let samples_per_pixel = 100;
-
-    let trajectory_points = vec![
-        Vec3::new(-2.0, 2.0, 1.0),
-        Vec3::new(2.0, 2.0, 1.0),
-        Vec3::new(2.0, 0.1, 0.3),
-        Vec3::new(-2.0, 0.1, 0.5),
-    ];
-
-    //let trajectory = interpolate(&trajectory_points, 2);
-    let trajectory = vec![trajectory_points[0]];
-    let count = trajectory.len();
-    for (i, p) in trajectory.iter().enumerate() {
-        print!("\n\n--- Rendering frame #{}/{}", i, count);
+    let mut width = 160;
+    let mut height = (width as f32 / aspect_ratio) as usize;
+    let mut max_depth = 50;
+
+    let mut samples_per_pixel = 100;
+
+    if let Some(preset) = args.preset {
+        let quality = preset.settings();
+        (width, height) = quality.scale_resolution(width, height);
+        max_depth = quality.max_depth;
+        samples_per_pixel = quality.samples_per_pixel;
+    }
+    (width, height) = match (args.width, args.height) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, ((w as f32 / aspect_ratio).round() as usize).max(1)),
+        (None, Some(h)) => (((h as f32 * aspect_ratio).round() as usize).max(1), h),
+        (None, None) => (width, height),
+    };
+    max_depth = args.max_depth.unwrap_or(max_depth);
+    samples_per_pixel = args.samples_per_pixel.unwrap_or(samples_per_pixel);
+
+    // The threads are those of the global pool, shared by the concurrent frames.
+    let settings = RenderSettings::builder()
+        .resolution(width, height)
+        .max_depth(max_depth)
+        .samples_per_pixel(samples_per_pixel)
+        .seed(args.seed)
+        .background(args.background.clone())
+        .tone_map(args.tonemap.operator)
+        .exposure(args.tonemap.exposure)
+        .gamma(args.tonemap.gamma)
+        .mode(args.render_mode)
+        .sampler(args.sampler)
+        .clamp_radiance(args.clamp)
+        .stereo(args.stereo.map(|(stereo, _)| stereo))
+        .build()
+        .unwrap_or_else(|msg| {
+            eprintln!("{msg}");
+            exit(EXIT_USAGE);
+        });
+
+    let trajectory = Trajectory::new(&[
+        Keyframe { time: 0.0, position: Vec3::new(-2.0, 2.0, 1.0) },
+        Keyframe { time: 1.0, position: Vec3::new(2.0, 2.0, 1.0) },
+        Keyframe { time: 2.0, position: Vec3::new(2.0, 0.1, 0.3) },
+        Keyframe { time: 3.0, position: Vec3::new(-2.0, 0.1, 0.5) },
+    ]);
+
+    // A zero duration renders a single still frame, seen from the camera of the scene.
+    let mut animation = AnimationSettings::new(24.0, 0.0);
+    let views: Vec<OrbitView> = match &args.command {
+        Command::Animate(animate) => {
+            let trajectory =
+                trajectory.with_interpolation(animate.interpolation).with_easing(animate.easing);
+            let duration = animate.duration.unwrap_or_else(|| trajectory.duration());
+            animation = AnimationSettings::new(animate.fps, duration);
+            // The camera follows the trajectory, looking at the target of the scene camera.
+            let target = scene.view().target;
+            trajectory
+                .sample(&animation)
+                .iter()
+                .map(|p| OrbitView::looking_at(p, &target))
+                .collect()
+        }
+        _ => vec![*scene.view()],
+    };
+    let count = views.len();
+
+    if args.estimate {
+        let est = estimate(&scene, &settings, &views, args.jobs, ESTIMATE_PROBES);
+        println!("{est}");
+        return;
+    }
+
+    // Check the output paths before spending time rendering.
+    let ctx = TemplateContext::now(0, args.seed);
+    let outputs: Result<Vec<String>, _> = (0..count)
+        .map(|frame| expand_template(&args.output, &TemplateContext { frame, ..ctx }))
+        .collect();
+    let outputs = outputs.unwrap_or_else(|msg| {
+        eprintln!("{msg}");
+        exit(EXIT_USAGE);
+    });
+    if count > 1 && outputs[0] == outputs[1] {
+        eprintln!("the output template must contain {{frame}} to render an animation");
+        exit(EXIT_USAGE);
+    }
+    if args.overwrite == OverwritePolicy::Error {
+        if let Some(existing) = outputs.iter().find(|p| Path::new(p).exists()) {
+            eprintln!("{existing} already exists");
+            exit(EXIT_FAILURE);
+        }
+    }
+
+    let tiles = tile_grid(width, height, settings.tile_size).len();
+    let info = RenderInfo { frames: count, width, height, samples_per_pixel, max_depth, tiles };
+    let reporter = Mutex::new(ProgressReporter::start(args.progress, io::stdout(), info));
+
+    // Frames are handed out one at a time to `args.jobs` workers, each rendering a whole frame.
+    let next_frame = AtomicUsize::new(0);
+    let rendered = Mutex::new(Vec::with_capacity(count));
+    let worker = || loop {
+        let i = next_frame.fetch_add(1, Ordering::Relaxed);
+        if i >= count {
+            break;
+        }
+        let mut frame = scene.clone();
+        frame.set_view(&views[i]);
         let start = Instant::now();
-        let im = render(width, height, max_depth, samples_per_pixel, p);
-        let elapsed = start.elapsed();
+        let output = render_frame(i, &frame, &outputs[i], &args, &settings, &reporter);
+        let position = views[i].position();
+        rendered.lock().unwrap().push(ManifestFrame {
+            frame: i,
+            time: animation.frame_time(i),
+            output,
+            camera: [position.x, position.y, position.z],
+            render_secs: start.elapsed().as_secs_f64(),
+        });
+    };
+    if args.jobs == 1 {
+        // Keep the previews on the main thread, some platforms require it for windows.
+        worker();
+    } else {
+        thread::scope(|s| {
+            for _ in 0..args.jobs.min(count) {
+                s.spawn(worker);
+            }
+        });
+    }
+
+    if let Command::Animate(animate) = &args.command {
+        let mut frames = rendered.into_inner().unwrap();
+        frames.sort_by_key(|f| f.frame);
+        let manifest = Manifest {
+            fps: animation.fps,
+            duration: animation.duration,
+            interpolation: animate.interpolation.to_string(),
+            easing: animate.easing.to_string(),
+            width,
+            height,
+            samples_per_pixel,
+            max_depth,
+            frames,
+        };
+        let path = animate.manifest.clone().unwrap_or_else(|| {
+            let dir = Path::new(&outputs[0]).parent().map_or_else(PathBuf::new, Path::to_path_buf);
+            dir.join("manifest.json").to_string_lossy().into_owned()
+        });
+        if let Err(e) = manifest.write(&path) {
+            fail(&reporter, e);
+        }
+    }
+    reporter.into_inner().unwrap().done();
+}
 
-        println!("\n--- Summary");
-        println!("Time elapsed   : {elapsed:?}");
-        println!("Image size     : {width}x{height}");
-        println!("Max ray depth  : {max_depth}");
-        println!("#Samples/px    : {samples_per_pixel}");
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::{preview_path, write_latest};
+    use rt1we_renderer::image::{flipv, ImageRGBA};
+    use rt1we_renderer::imageio::{imread, imwrite};
 
-        let im = flipv(&im);
+    #[test]
+    fn test_preview_path() {
+        assert_eq!(preview_path("out/frame_003.png"), "out/frame_003_preview.png");
+        assert_eq!(preview_path("renders/shot.ppm"), "renders/shot_preview.ppm");
+        // 8-bit previews of high dynamic range frames.
+        assert_eq!(preview_path("out/frame.exr"), "out/frame_preview.ppm");
+        assert_eq!(preview_path("frame"), "frame_preview.ppm");
+    }
 
-        let fpath = format!("out/anim_image_{:0>5}.ppm", i);
-        ppmwrite(&fpath, &im);
-        ppmwrite("out/latest.ppm", &im);
+    #[test]
+    fn test_latest_matches_the_frame() {
+        // A white pixel in the bottom row of the render.
+        let mut im = ImageRGBA::new(3, 2);
+        im.put(0, 0, 255, 255, 255, 255);
+        let dir = tempfile::tempdir().unwrap();
+        let frame = dir.path().join("frame.ppm").to_string_lossy().into_owned();
+        let latest = dir.path().join("latest.ppm").to_string_lossy().into_owned();
+        imwrite(&frame, &flipv(&im)).unwrap();
+        write_latest(&latest, &im).unwrap();
+        let (frame, latest) = (imread(&frame).unwrap(), imread(&latest).unwrap());
+        assert_eq!(latest.pixels, frame.pixels);
+        assert_eq!(latest.at(0, 1), (255, 255, 255, 255));
     }
 }
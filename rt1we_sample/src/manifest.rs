@@ -0,0 +1,82 @@
+//! Manifest of an animation: written next to its frames by the `animate` command, so that the
+//! tools assembling them know the frame rate and when and from where each frame was seen.
+use rt1we_renderer::error::RtError;
+use rt1we_renderer::output::{prepare_output, OverwritePolicy};
+use serde::Serialize;
+use std::fs;
+
+/// A rendered frame of an animation.
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestFrame {
+    pub frame: usize,
+    /// Scene time of the frame, in seconds.
+    pub time: f32,
+    pub output: String,
+    /// Camera position.
+    pub camera: [f32; 3],
+    /// Time spent rendering the frame, in seconds.
+    pub render_secs: f64,
+}
+
+/// The frames of an animation, and the settings they were rendered with.
+#[derive(Debug, Clone, Serialize)]
+pub struct Manifest {
+    pub fps: f32,
+    /// Duration of the animation, in seconds.
+    pub duration: f32,
+    pub interpolation: String,
+    pub easing: String,
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    /// The frames, in order.
+    pub frames: Vec<ManifestFrame>,
+}
+
+impl Manifest {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a manifest serializes to JSON")
+    }
+
+    /// Write the manifest, replacing any previous one.
+    pub fn write(&self, path: &str) -> Result<(), RtError> {
+        let path = prepare_output(path, OverwritePolicy::Overwrite)?;
+        fs::write(&path, self.to_json())
+            .map_err(|source| RtError::Io { path: path.to_string_lossy().into_owned(), source })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::manifest::{Manifest, ManifestFrame};
+
+    #[test]
+    fn test_manifest_json() {
+        let frame = |frame: usize| ManifestFrame {
+            frame,
+            time: frame as f32 / 24.0,
+            output: format!("out/anim_image_{frame:0>5}.ppm"),
+            camera: [1.0, 2.0, 3.0],
+            render_secs: 0.5,
+        };
+        let manifest = Manifest {
+            fps: 24.0,
+            duration: 2.0 / 24.0,
+            interpolation: "catmull-rom".to_string(),
+            easing: "linear".to_string(),
+            width: 160,
+            height: 90,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            frames: vec![frame(0), frame(1)],
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+        assert_eq!(json["fps"], 24.0);
+        assert_eq!(json["interpolation"], "catmull-rom");
+        assert_eq!(json["frames"].as_array().unwrap().len(), 2);
+        assert_eq!(json["frames"][1]["output"], "out/anim_image_00001.ppm");
+        assert_eq!(json["frames"][1]["camera"], serde_json::json!([1.0, 2.0, 3.0]));
+    }
+}
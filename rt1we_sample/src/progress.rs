@@ -0,0 +1,325 @@
+//! Render progress reporting, for humans or for the tools wrapping the binary.
+//!
+//! In JSON mode, every event is a single line JSON object with an `event` field, and nothing
+//! else is printed on stdout:
+//! ```text
+//! {"event":"start","frames":1,"width":160,"height":90,"samples_per_pixel":100,"max_depth":50}
+//! {"event":"progress","frame":0,"tiles_done":1,"tiles_total":60,"eta_secs":12.5}
+//! {"event":"frame_done","frame":0,"output":"out/anim_image_00000.ppm","elapsed_secs":12.6,
+//!  "clamped_samples":0,"rejected_samples":0}
+//! {"event":"done","frames":1,"elapsed_secs":12.6}
+//! ```
+//! Failures emit an `error` or `aborted` event before exiting with the matching status.
+//! Tiles are the square blocks the frames are rendered in. When frames render concurrently,
+//! their events interleave.
+use rt1we_renderer::render::SampleStats;
+use serde::Serialize;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Exit status of a successful run.
+pub const EXIT_SUCCESS: i32 = 0;
+/// Exit status when rendering or writing files failed.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit status for invalid command line arguments.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit status when the user aborted the render.
+pub const EXIT_ABORTED: i32 = 3;
+
+/// How progress is reported on stdout.
+#[derive(Debug, Copy, Clone, PartialEq, Default, clap::ValueEnum)]
+pub enum ProgressFormat {
+    /// Human readable messages.
+    #[default]
+    Text,
+    /// Newline-delimited JSON events.
+    Json,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    Start {
+        frames: usize,
+        width: usize,
+        height: usize,
+        samples_per_pixel: usize,
+        max_depth: usize,
+    },
+    Progress {
+        frame: usize,
+        tiles_done: usize,
+        tiles_total: usize,
+        eta_secs: f64,
+    },
+    FrameDone {
+        frame: usize,
+        output: &'a str,
+        elapsed_secs: f64,
+        clamped_samples: u64,
+        rejected_samples: u64,
+    },
+    Done {
+        frames: usize,
+        elapsed_secs: f64,
+    },
+    Aborted {
+        frame: usize,
+    },
+    Error {
+        message: &'a str,
+        exit_code: i32,
+    },
+}
+
+/// Settings of the render, printed when it starts.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderInfo {
+    pub frames: usize,
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    /// Number of tiles of each frame.
+    pub tiles: usize,
+}
+
+/// Reports the progress of an animation render.
+///
+/// Frames may be reported in any order, and several of them may be in progress at once.
+pub struct ProgressReporter<W: Write> {
+    format: ProgressFormat,
+    out: W,
+    info: RenderInfo,
+    start: Instant,
+    /// Start time of each frame.
+    frame_starts: Vec<Instant>,
+    /// Number of completed tiles of each frame.
+    tiles: Vec<usize>,
+    frames_done: usize,
+}
+
+impl<W: Write> ProgressReporter<W> {
+    /// Report the start of the render.
+    ///
+    /// # Arguments
+    /// - `format` - How to report progress.
+    /// - `out` - Where to write the reports, usually stdout.
+    /// - `info` - Settings of the render.
+    pub fn start(format: ProgressFormat, out: W, info: RenderInfo) -> Self {
+        let now = Instant::now();
+        let mut reporter = ProgressReporter {
+            format,
+            out,
+            info,
+            start: now,
+            frame_starts: vec![now; info.frames],
+            tiles: vec![0; info.frames],
+            frames_done: 0,
+        };
+        if format == ProgressFormat::Json {
+            reporter.emit(&Event::Start {
+                frames: info.frames,
+                width: info.width,
+                height: info.height,
+                samples_per_pixel: info.samples_per_pixel,
+                max_depth: info.max_depth,
+            });
+        }
+        reporter
+    }
+
+    /// Report the start of a frame.
+    pub fn frame_started(&mut self, frame: usize) {
+        self.frame_starts[frame] = Instant::now();
+        if self.format == ProgressFormat::Text {
+            let _ = write!(self.out, "\n\n--- Rendering frame #{}/{}", frame, self.info.frames);
+            let _ = writeln!(self.out, "\n--- Starting render");
+        }
+    }
+
+    /// Report that `tiles_done` tiles of the current frame are complete.
+    pub fn tiles_done(&mut self, frame: usize, tiles_done: usize) {
+        let tiles_total = self.info.tiles;
+        self.tiles[frame] = tiles_done;
+        match self.format {
+            ProgressFormat::Text => {
+                let percent = 100.0 * tiles_done as f32 / tiles_total.max(1) as f32;
+                let _ = write!(self.out, "\rRendered {percent:.0}%");
+                let _ = self.out.flush();
+            }
+            ProgressFormat::Json => {
+                let eta = self.eta();
+                self.emit(&Event::Progress {
+                    frame,
+                    tiles_done,
+                    tiles_total,
+                    eta_secs: eta.as_secs_f64(),
+                });
+            }
+        }
+    }
+
+    /// Report that a frame was written to `output`, with the counts of its samples.
+    pub fn frame_done(&mut self, frame: usize, output: &str, stats: &SampleStats) {
+        self.frames_done += 1;
+        self.tiles[frame] = self.info.tiles;
+        let elapsed = self.frame_starts[frame].elapsed();
+        match self.format {
+            ProgressFormat::Text => {
+                let info = self.info;
+                let _ = writeln!(self.out, "\n--- Summary of frame #{frame}");
+                let _ = writeln!(self.out, "Time elapsed   : {elapsed:?}");
+                let _ = writeln!(self.out, "Image size     : {}x{}", info.width, info.height);
+                let _ = writeln!(self.out, "Max ray depth  : {}", info.max_depth);
+                let _ = writeln!(self.out, "#Samples/px    : {}", info.samples_per_pixel);
+                let _ = writeln!(self.out, "Samples        : {stats}");
+            }
+            ProgressFormat::Json => self.emit(&Event::FrameDone {
+                frame,
+                output,
+                elapsed_secs: elapsed.as_secs_f64(),
+                clamped_samples: stats.clamped,
+                rejected_samples: stats.rejected,
+            }),
+        }
+    }
+
+    /// Report the end of the render.
+    pub fn done(&mut self) {
+        if self.format == ProgressFormat::Json {
+            let elapsed_secs = self.start.elapsed().as_secs_f64();
+            self.emit(&Event::Done { frames: self.frames_done, elapsed_secs });
+        }
+    }
+
+    /// Report that the user stopped the render during `frame`.
+    pub fn aborted(&mut self, frame: usize) {
+        match self.format {
+            ProgressFormat::Text => {
+                let _ = writeln!(self.out, "\n--- Render aborted");
+            }
+            ProgressFormat::Json => self.emit(&Event::Aborted { frame }),
+        }
+    }
+
+    /// Report a failure, the process is expected to exit with `exit_code`.
+    pub fn error(&mut self, message: &str, exit_code: i32) {
+        match self.format {
+            ProgressFormat::Text => eprintln!("{message}"),
+            ProgressFormat::Json => self.emit(&Event::Error { message, exit_code }),
+        }
+    }
+
+    /// Remaining time for the whole animation, extrapolated from the elapsed time.
+    fn eta(&self) -> Duration {
+        let total = self.info.frames * self.info.tiles;
+        let done: usize = self.tiles.iter().sum();
+        if done == 0 {
+            return Duration::ZERO;
+        }
+        self.start.elapsed().mul_f64((total.saturating_sub(done)) as f64 / done as f64)
+    }
+
+    fn emit(&mut self, event: &Event) {
+        let line = serde_json::to_string(event).expect("progress events are serializable");
+        let _ = writeln!(self.out, "{line}");
+        let _ = self.out.flush();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::progress::{ProgressFormat, ProgressReporter, RenderInfo, EXIT_FAILURE};
+    use rt1we_renderer::render::SampleStats;
+    use serde_json::Value;
+
+    const INFO: RenderInfo =
+        RenderInfo { frames: 2, width: 4, height: 3, samples_per_pixel: 1, max_depth: 5, tiles: 3 };
+
+    fn lines(out: Vec<u8>) -> Vec<Value> {
+        String::from_utf8(out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_json_events() {
+        let mut out = Vec::new();
+        let mut reporter = ProgressReporter::start(ProgressFormat::Json, &mut out, INFO);
+        for frame in 0..2 {
+            reporter.frame_started(frame);
+            for tiles in 1..=3 {
+                reporter.tiles_done(frame, tiles);
+            }
+            let stats = SampleStats { samples: 12, clamped: frame as u64, rejected: 0 };
+            reporter.frame_done(frame, &format!("out/{frame}.ppm"), &stats);
+        }
+        reporter.done();
+
+        let events = lines(out);
+        let kinds: Vec<&str> = events.iter().map(|e| e["event"].as_str().unwrap()).collect();
+        assert_eq!(kinds[0], "start");
+        assert_eq!(kinds.iter().filter(|k| **k == "progress").count(), 6);
+        assert_eq!(kinds.iter().filter(|k| **k == "frame_done").count(), 2);
+        assert_eq!(kinds[kinds.len() - 1], "done");
+
+        assert_eq!(events[0]["frames"], 2);
+        assert_eq!(events[1]["tiles_done"], 1);
+        assert_eq!(events[1]["tiles_total"], 3);
+        assert!(events[1]["eta_secs"].as_f64().unwrap() >= 0.0);
+        assert_eq!(events[4]["output"], "out/0.ppm");
+        assert_eq!(events[4]["clamped_samples"], 0);
+        assert_eq!(events[8]["clamped_samples"], 1);
+        assert_eq!(events[8]["rejected_samples"], 0);
+        assert_eq!(events[events.len() - 1]["frames"], 2);
+    }
+
+    #[test]
+    fn test_interleaved_frames() {
+        let mut out = Vec::new();
+        let mut reporter = ProgressReporter::start(ProgressFormat::Json, &mut out, INFO);
+        reporter.frame_started(0);
+        reporter.frame_started(1);
+        reporter.tiles_done(1, 2);
+        reporter.tiles_done(0, 1);
+        reporter.frame_done(1, "out/1.ppm", &SampleStats::default());
+        reporter.frame_done(0, "out/0.ppm", &SampleStats::default());
+        reporter.done();
+
+        let events = lines(out);
+        assert_eq!(events[1]["frame"], 1);
+        assert_eq!(events[2]["frame"], 0);
+        assert_eq!(events[3]["output"], "out/1.ppm");
+        assert_eq!(events[5]["frames"], 2);
+    }
+
+    #[test]
+    fn test_json_failures() {
+        let mut out = Vec::new();
+        let mut reporter = ProgressReporter::start(ProgressFormat::Json, &mut out, INFO);
+        reporter.aborted(1);
+        reporter.error("disk full", EXIT_FAILURE);
+
+        let events = lines(out);
+        assert_eq!(events[1]["event"], "aborted");
+        assert_eq!(events[1]["frame"], 1);
+        assert_eq!(events[2]["event"], "error");
+        assert_eq!(events[2]["message"], "disk full");
+        assert_eq!(events[2]["exit_code"], EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_text_output() {
+        let mut out = Vec::new();
+        let mut reporter = ProgressReporter::start(ProgressFormat::Text, &mut out, INFO);
+        reporter.frame_started(0);
+        reporter.tiles_done(0, 1);
+        let stats = SampleStats { samples: 12, clamped: 2, rejected: 1 };
+        reporter.frame_done(0, "out/0.ppm", &stats);
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("--- Rendering frame #0/2"));
+        assert!(text.contains("Rendered 33%"));
+        assert!(text.contains("Image size     : 4x3"));
+        assert!(text.contains("Samples        : 2 clamped and 1 rejected of 12 samples"));
+    }
+}
@@ -0,0 +1,338 @@
+//! Live preview of a render in the terminal.
+//!
+//! The image is drawn with 24-bit colored half blocks (`▀`), which most terminals support,
+//! or with the kitty/sixel graphics protocols when the terminal is known to handle them.
+//! This works over ssh, so remote renders give visual feedback without copying files around.
+use rt1we_renderer::image::{flipv, resize, ImageRGBA};
+use std::env;
+use std::fmt::Write as _;
+use std::io::{stdout, Write};
+use std::time::{Duration, Instant};
+
+/// Approximate size of a terminal cell in pixels, for the protocols drawing real pixels.
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 16;
+
+/// Minimum delay between two redraws.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Terminal graphics protocol used to draw the preview.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TerminalProtocol {
+    /// Unicode upper half blocks with true color foreground/background.
+    HalfBlocks,
+    /// [Kitty graphics protocol](https://sw.kovidgoyal.net/kitty/graphics-protocol/).
+    Kitty,
+    /// DEC sixel graphics, with a 6x6x6 color cube palette.
+    Sixel,
+}
+
+impl TerminalProtocol {
+    /// Guess the best protocol supported by the current terminal.
+    pub fn detect() -> Self {
+        TerminalProtocol::from_env(|key| env::var(key).ok())
+    }
+
+    fn from_env<F: Fn(&str) -> Option<String>>(get: F) -> Self {
+        let term = get("TERM").unwrap_or_default();
+        let program = get("TERM_PROGRAM").unwrap_or_default();
+
+        if get("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || program == "WezTerm"
+            || program == "ghostty"
+        {
+            TerminalProtocol::Kitty
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            TerminalProtocol::Sixel
+        } else {
+            TerminalProtocol::HalfBlocks
+        }
+    }
+}
+
+/// Progressive preview drawn in place in the terminal.
+pub struct TerminalPreview {
+    protocol: TerminalProtocol,
+    columns: usize,
+    rows: usize,
+    last_draw: Option<Instant>,
+}
+
+impl TerminalPreview {
+    /// Create a preview fitting the terminal, and reserve the space it needs below the cursor.
+    ///
+    /// The terminal size is read from the `COLUMNS` and `LINES` variables, defaulting to 80x24.
+    ///
+    /// # Arguments
+    /// - `protocol` - How to draw the image.
+    /// - `width` - Width of the rendered image.
+    /// - `height` - Height of the rendered image.
+    pub fn new(protocol: TerminalProtocol, width: usize, height: usize) -> Self {
+        let max_columns = env_usize("COLUMNS").unwrap_or(80).min(width).max(1);
+        let max_rows = env_usize("LINES").unwrap_or(24).saturating_sub(2).max(1);
+        let (columns, rows) = fit_cells(width, height, max_columns, max_rows);
+
+        // Scroll now, so the image can be redrawn at a fixed position.
+        let mut out = stdout().lock();
+        let _ = write!(out, "{}\x1b[{rows}A\x1b7", "\n".repeat(rows));
+        let _ = out.flush();
+
+        TerminalPreview { protocol, columns, rows, last_draw: None }
+    }
+
+    /// Redraw the preview, unless it was redrawn very recently.
+    ///
+    /// # Arguments
+    /// - `im` - The partially rendered image.
+    pub fn update(&mut self, im: &ImageRGBA) {
+        if self.last_draw.is_some_and(|t| t.elapsed() < REFRESH_INTERVAL) {
+            return;
+        }
+        self.draw(im);
+    }
+
+    /// Draw the final image and move the cursor below it.
+    ///
+    /// # Arguments
+    /// - `im` - The rendered image.
+    pub fn finish(&mut self, im: &ImageRGBA) {
+        self.draw(im);
+        let mut out = stdout().lock();
+        let _ = writeln!(out, "\x1b8\x1b[{}B", self.rows);
+        let _ = out.flush();
+    }
+
+    fn draw(&mut self, im: &ImageRGBA) {
+        // Rendered images have their origin at the bottom left.
+        let im = flipv(im);
+        let frame = match self.protocol {
+            TerminalProtocol::HalfBlocks => {
+                encode_half_blocks(&resize(&im, self.columns, self.rows * 2))
+            }
+            TerminalProtocol::Kitty => {
+                let width = im.width.min(self.columns * CELL_WIDTH);
+                let height = (im.height * width / im.width).max(1);
+                encode_kitty(&resize(&im, width, height), self.columns, self.rows)
+            }
+            TerminalProtocol::Sixel => {
+                encode_sixel(&resize(&im, self.columns * CELL_WIDTH, self.rows * CELL_HEIGHT))
+            }
+        };
+
+        let mut out = stdout().lock();
+        let _ = write!(out, "\x1b8{frame}");
+        let _ = out.flush();
+        self.last_draw = Some(Instant::now());
+    }
+}
+
+fn env_usize(key: &str) -> Option<usize> {
+    env::var(key).ok()?.parse().ok()
+}
+
+/// Find the largest cell area with the image aspect ratio. A cell is twice as tall as wide.
+fn fit_cells(width: usize, height: usize, max_columns: usize, max_rows: usize) -> (usize, usize) {
+    let rows = (max_columns * height).div_ceil(width * 2).max(1);
+    if rows <= max_rows {
+        return (max_columns, rows);
+    }
+    let columns = (max_rows * 2 * width / height).clamp(1, max_columns);
+    (columns, max_rows)
+}
+
+/// Encode an image as lines of half blocks, two pixel rows per line.
+fn encode_half_blocks(im: &ImageRGBA) -> String {
+    let mut s = String::new();
+    for j in (0..im.height).step_by(2) {
+        for i in 0..im.width {
+            let (r, g, b, _) = im.at(i, j);
+            let _ = write!(s, "\x1b[38;2;{r};{g};{b}m");
+            if j + 1 < im.height {
+                let (r, g, b, _) = im.at(i, j + 1);
+                let _ = write!(s, "\x1b[48;2;{r};{g};{b}m");
+            }
+            s.push('▀');
+        }
+        s.push_str("\x1b[0m\r\n");
+    }
+    s
+}
+
+/// Encode an image with the kitty graphics protocol, scaled to a cell area.
+fn encode_kitty(im: &ImageRGBA, columns: usize, rows: usize) -> String {
+    let payload = base64(&im.pixels);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(4096).collect();
+
+    // Delete the previous frame, so redraws do not stack up images.
+    let mut s = String::from("\x1b_Ga=d,d=I,i=1,q=2\x1b\\");
+    for (n, chunk) in chunks.iter().enumerate() {
+        let more = if n + 1 < chunks.len() { 1 } else { 0 };
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        if n == 0 {
+            let (w, h) = (im.width, im.height);
+            let _ = write!(
+                s,
+                "\x1b_Ga=T,f=32,i=1,q=2,C=1,s={w},v={h},c={columns},r={rows},m={more};{chunk}\x1b\\"
+            );
+        } else {
+            let _ = write!(s, "\x1b_Gm={more};{chunk}\x1b\\");
+        }
+    }
+    s
+}
+
+/// Quantize a color channel to the 6 levels of the sixel palette.
+fn level6(v: u8) -> usize {
+    (v as usize * 5 + 127) / 255
+}
+
+/// Encode an image as sixels, using a 6x6x6 color cube palette.
+fn encode_sixel(im: &ImageRGBA) -> String {
+    let (w, h) = (im.width, im.height);
+    let mut s = format!("\x1bPq\"1;1;{w};{h}");
+    for idx in 0..216 {
+        let (r, g, b) = (idx / 36, (idx / 6) % 6, idx % 6);
+        let _ = write!(s, "#{idx};2;{};{};{}", r * 20, g * 20, b * 20);
+    }
+
+    let colors: Vec<usize> = (0..w * h)
+        .map(|k| {
+            let (r, g, b, _) = im.at(k % w, k / w);
+            level6(r) * 36 + level6(g) * 6 + level6(b)
+        })
+        .collect();
+
+    for band in (0..h).step_by(6) {
+        let mut used = [false; 216];
+        for y in band..(band + 6).min(h) {
+            for x in 0..w {
+                used[colors[y * w + x]] = true;
+            }
+        }
+
+        for idx in (0..216).filter(|&idx| used[idx]) {
+            let _ = write!(s, "#{idx}");
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..w {
+                let mut bits = 0u8;
+                for k in 0..6 {
+                    let y = band + k;
+                    if y < h && colors[y * w + x] == idx {
+                        bits |= 1 << k;
+                    }
+                }
+                let c = (63 + bits) as char;
+                run = match run {
+                    Some((prev, n)) if prev == c => Some((c, n + 1)),
+                    Some((prev, n)) => {
+                        push_sixel_run(&mut s, prev, n);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some((c, n)) = run {
+                push_sixel_run(&mut s, c, n);
+            }
+            s.push('$');
+        }
+        s.push('-');
+    }
+
+    s.push_str("\x1b\\");
+    s
+}
+
+fn push_sixel_run(s: &mut String, c: char, n: usize) {
+    if n > 3 {
+        let _ = write!(s, "!{n}{c}");
+    } else {
+        s.extend(std::iter::repeat_n(c, n));
+    }
+}
+
+/// Standard base64 encoding, with padding.
+fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for k in 0..4 {
+            if k <= chunk.len() {
+                s.push(TABLE[(n >> (18 - 6 * k) & 0x3F) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::terminal_preview::{
+        base64, encode_half_blocks, encode_kitty, encode_sixel, fit_cells, TerminalProtocol,
+    };
+    use rt1we_renderer::image::ImageRGBA;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_detect_protocol_from_environment() {
+        let kitty = |key: &str| (key == "TERM").then(|| "xterm-kitty".to_string());
+        let foot = |key: &str| (key == "TERM").then(|| "foot".to_string());
+        let xterm = |key: &str| (key == "TERM").then(|| "xterm-256color".to_string());
+
+        assert_eq!(TerminalProtocol::from_env(kitty), TerminalProtocol::Kitty);
+        assert_eq!(TerminalProtocol::from_env(foot), TerminalProtocol::Sixel);
+        assert_eq!(TerminalProtocol::from_env(xterm), TerminalProtocol::HalfBlocks);
+    }
+
+    #[test]
+    fn test_fit_cells_keeps_aspect_ratio() {
+        assert_eq!(fit_cells(160, 90, 80, 22), (78, 22));
+        assert_eq!(fit_cells(100, 100, 80, 60), (80, 40));
+        assert_eq!(fit_cells(100, 100, 80, 20), (40, 20));
+    }
+
+    #[test]
+    fn test_half_blocks_use_upper_pixel_as_foreground() {
+        let mut im = ImageRGBA::new(1, 2);
+        im.put(0, 0, 255, 0, 0, 255);
+        im.put(0, 1, 0, 0, 255, 255);
+
+        let s = encode_half_blocks(&im);
+        assert_eq!(s, "\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀\x1b[0m\r\n");
+    }
+
+    #[test]
+    fn test_kitty_chunks_payload() {
+        let im = ImageRGBA::new(64, 64);
+        let s = encode_kitty(&im, 10, 5);
+
+        assert!(s.contains("a=T,f=32,i=1,q=2,C=1,s=64,v=64,c=10,r=5,m=1;"));
+        assert!(s.contains("\x1b_Gm=0;"));
+    }
+
+    #[test]
+    fn test_sixel_single_color() {
+        let mut im = ImageRGBA::new(4, 1);
+        for i in 0..4 {
+            im.put(i, 0, 255, 255, 255, 255);
+        }
+
+        let s = encode_sixel(&im);
+        assert!(s.starts_with("\x1bPq\"1;1;4;1"));
+        assert!(s.ends_with("#215!4@$-\x1b\\"));
+    }
+}
@@ -0,0 +1,126 @@
+//! Live preview of a render in a native window.
+//!
+//! Much lighter than the egui application: the window only shows the image as scanlines
+//! complete, and reacts to a couple of keys.
+//!
+//! # Shortcuts
+//! - `Esc`/`Q` - Abort the render, or close the window once the render is done.
+//! - `S` - Save the image as it currently is.
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+use rt1we_renderer::image::{flipv, ImageRGBA};
+use rt1we_renderer::imageio::imwrite;
+use rt1we_renderer::output::{prepare_output, OverwritePolicy};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// Minimum delay between two window refreshes while rendering.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Window size we try not to exceed when upscaling small renders.
+const MAX_WINDOW_WIDTH: usize = 1280;
+
+/// Progressive preview in its own window.
+pub struct WindowPreview {
+    window: Window,
+    buffer: Vec<u32>,
+    width: usize,
+    height: usize,
+    save_path: String,
+    last_draw: Option<Instant>,
+    aborted: bool,
+}
+
+impl WindowPreview {
+    /// Open the preview window.
+    ///
+    /// # Arguments
+    /// - `width` - Width of the rendered image.
+    /// - `height` - Height of the rendered image.
+    /// - `save_path` - Where to write the image when `S` is pressed. Existing files are kept,
+    ///   a numbered name is picked instead.
+    pub fn new(width: usize, height: usize, save_path: &str) -> Result<Self, String> {
+        let scale = match MAX_WINDOW_WIDTH / width.max(1) {
+            0 | 1 => Scale::X1,
+            2 | 3 => Scale::X2,
+            4..=7 => Scale::X4,
+            _ => Scale::X8,
+        };
+        let options = WindowOptions { scale, ..WindowOptions::default() };
+        let window = Window::new("rt1we - Esc: abort, S: save", width, height, options)
+            .map_err(|e| format!("unable to open preview window: {e}"))?;
+
+        Ok(WindowPreview {
+            window,
+            buffer: vec![0; width * height],
+            width,
+            height,
+            save_path: save_path.to_string(),
+            last_draw: None,
+            aborted: false,
+        })
+    }
+
+    /// Whether the user asked to stop the render.
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Refresh the window with the partial image and handle key presses.
+    ///
+    /// # Arguments
+    /// - `im` - The partially rendered image.
+    ///
+    /// # Returns
+    /// `ControlFlow::Break` when the render should be aborted.
+    pub fn update(&mut self, im: &ImageRGBA) -> ControlFlow<()> {
+        if self.last_draw.is_some_and(|t| t.elapsed() < REFRESH_INTERVAL) {
+            return ControlFlow::Continue(());
+        }
+        self.draw(im);
+
+        if !self.window.is_open() || self.quit_pressed() {
+            self.aborted = true;
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Show the final image until the window is closed.
+    ///
+    /// # Arguments
+    /// - `im` - The rendered image.
+    pub fn wait(&mut self, im: &ImageRGBA) {
+        self.window.set_title("rt1we - done. Esc: close, S: save");
+        while self.window.is_open() && !self.quit_pressed() {
+            self.draw(im);
+            std::thread::sleep(REFRESH_INTERVAL);
+        }
+    }
+
+    fn quit_pressed(&self) -> bool {
+        self.window.is_key_pressed(Key::Escape, KeyRepeat::No)
+            || self.window.is_key_pressed(Key::Q, KeyRepeat::No)
+    }
+
+    fn draw(&mut self, im: &ImageRGBA) {
+        // Rendered images have their origin at the bottom left, the window at the top left.
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let rgba = im.at_u32(i, self.height - 1 - j);
+                self.buffer[j * self.width + i] = rgba >> 8;
+            }
+        }
+
+        if self.window.is_key_pressed(Key::S, KeyRepeat::No) {
+            let saved = prepare_output(&self.save_path, OverwritePolicy::Increment)
+                .and_then(|path| imwrite(&path.to_string_lossy(), &flipv(im)).map(|_| path));
+            match saved {
+                Ok(path) => eprintln!("\nSaved preview to {}", path.display()),
+                Err(msg) => eprintln!("\n{msg}"),
+            }
+        }
+
+        let _ = self.window.update_with_buffer(&self.buffer, self.width, self.height);
+        self.last_draw = Some(Instant::now());
+    }
+}